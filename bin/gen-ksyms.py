@@ -0,0 +1,138 @@
+#!/usr/bin/env python3
+
+# Fills in the symbol table of a linked kernel: the `.ksyms` section, which
+# the kernel reserves and links empty. Each function in the ELF's own symbol
+# table gets an entry, with its name demangled. See `kern/src/symbols.rs` for
+# the table's layout.
+#
+# usage: gen-ksyms.py <kernel.elf>
+
+import re
+import struct
+import sys
+
+MAGIC = b"KSYM"
+HEADER = struct.Struct("<4sIII")
+ENTRY = struct.Struct("<IIII")
+
+SHT_SYMTAB = 2
+STT_FUNC = 2
+
+ESCAPES = {
+    "$SP$": "@",
+    "$BP$": "*",
+    "$RF$": "&",
+    "$LT$": "<",
+    "$GT$": ">",
+    "$LP$": "(",
+    "$RP$": ")",
+    "$C$": ",",
+}
+
+def die(msg):
+    print("gen-ksyms: %s" % msg, file=sys.stderr)
+    sys.exit(1)
+
+def sections(elf):
+    """Returns the header of each section, with its name."""
+    shoff, = struct.unpack_from("<Q", elf, 0x28)
+    shentsize, shnum, shstrndx = struct.unpack_from("<HHH", elf, 0x3a)
+
+    headers = []
+    for i in range(shnum):
+        fields = struct.unpack_from("<IIQQQQIIQQ", elf, shoff + i * shentsize)
+        headers.append({
+            "name": fields[0],
+            "type": fields[1],
+            "addr": fields[3],
+            "offset": fields[4],
+            "size": fields[5],
+            "link": fields[6],
+        })
+
+    names = headers[shstrndx]
+    for header in headers:
+        header["name"] = cstr(elf, names["offset"] + header["name"])
+    return headers
+
+def cstr(elf, offset):
+    return elf[offset:elf.index(b"\0", offset)].decode()
+
+def symbols(elf, headers):
+    """Yields the (name, value, size, type) of each symbol."""
+    for symtab in (h for h in headers if h["type"] == SHT_SYMTAB):
+        strtab = headers[symtab["link"]]
+        for offset in range(symtab["offset"], symtab["offset"] + symtab["size"], 24):
+            name, info, _, _, value, size = struct.unpack_from("<IBBHQQ", elf, offset)
+            yield cstr(elf, strtab["offset"] + name), value, size, info & 0xf
+
+def unescape(ident):
+    if ident.startswith("_$"):
+        ident = ident[1:]
+    ident = ident.replace("..", "::")
+    for escape, char in ESCAPES.items():
+        ident = ident.replace(escape, char)
+    return re.sub(r"\$u([0-9a-f]+)\$", lambda m: chr(int(m.group(1), 16)), ident)
+
+def demangle(name):
+    """Demangles a Rust symbol of the legacy scheme, dropping its hash. Other
+    names are returned as they are."""
+    if not name.startswith("_ZN") or not name.endswith("E"):
+        return name
+
+    parts, i = [], 3
+    while i < len(name) - 1:
+        digits = re.match(r"\d+", name[i:])
+        if digits is None:
+            return name
+        i += len(digits.group())
+        n = int(digits.group())
+        parts.append(name[i:i + n])
+        i += n
+
+    if parts and re.fullmatch(r"h[0-9a-f]{16}", parts[-1]):
+        parts.pop()
+    return "::".join(unescape(part) for part in parts)
+
+def main():
+    if len(sys.argv) != 2:
+        die("usage: gen-ksyms.py <kernel.elf>")
+
+    path = sys.argv[1]
+    elf = bytearray(open(path, "rb").read())
+    if elf[:4] != b"\x7fELF" or elf[4] != 2 or elf[5] != 1:
+        die("%s is not a little-endian ELF64 file" % path)
+
+    headers = sections(elf)
+    ksyms = next((h for h in headers if h["name"] == ".ksyms"), None)
+    if ksyms is None:
+        die("%s has no .ksyms section" % path)
+
+    syms = list(symbols(elf, headers))
+    values = {name: value for name, value, _, _ in syms}
+    text, end = values["__text_beg"], values["__rodata_beg"]
+
+    functions = {}
+    for name, value, size, kind in syms:
+        if kind == STT_FUNC and size > 0 and text <= value < end:
+            functions.setdefault(value, (size, demangle(name)))
+
+    entries, names = [], bytearray()
+    for value in sorted(functions):
+        size, name = functions[value]
+        name = name.encode()
+        entries.append(ENTRY.pack(value - text, size, len(names), len(name)))
+        names += name
+
+    table = HEADER.pack(MAGIC, len(entries), len(names), 0) + b"".join(entries) + names
+    if len(table) > ksyms["size"]:
+        die("the table takes %d bytes, more than the %d of .ksyms" % (len(table), ksyms["size"]))
+
+    used = len(table)
+    table += bytes(ksyms["size"] - used)
+    elf[ksyms["offset"]:ksyms["offset"] + ksyms["size"]] = table
+    open(path, "wb").write(elf)
+    print("gen-ksyms: %d symbols, %d of %d bytes" % (len(entries), used, ksyms["size"]))
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+
+# Picks the kernel's crash reports, or oopses, out of a serial console log
+# and summarizes each, most recent last. With -o, also writes each to a JSON
+# file in a directory, for triage by other tools. See `kern/src/oops.rs` for
+# the format.
+#
+# usage: oops.py [-o <dir>] [log]   (the log defaults to stdin)
+
+import argparse
+import json
+import os
+import sys
+
+BEGIN = "-----BEGIN OOPS-----"
+END = "-----END OOPS-----"
+
+# The keys that may appear more than once, whose values are kept as lists.
+REPEATED = {"frame", "lock", "log"}
+
+def unescape(value):
+    out = []
+    chars = iter(value)
+    for c in chars:
+        if c == "\\":
+            c = {"n": "\n", "r": "\r"}.get(next(chars, ""), "\\")
+        out.append(c)
+    return "".join(out)
+
+def oopses(lines):
+    """Yields each complete oops in `lines` as a dict of its keys."""
+    oops = None
+    for line in lines:
+        # Lines that are not `key: value` pairs, like output from other cores
+        # interleaved with an oops, are skipped.
+        line = line.rstrip("\r\n")
+        if line == BEGIN:
+            oops = {key: [] for key in REPEATED}
+        elif oops is None:
+            continue
+        elif line == END:
+            yield oops
+            oops = None
+        elif ": " in line:
+            key, value = line.split(": ", 1)
+            value = unescape(value)
+            if key in REPEATED:
+                oops[key].append(value)
+            else:
+                oops[key] = value
+
+def summary(oops):
+    where = oops.get("message") or "%s at %s" % (oops.get("exception"), oops.get("pc"))
+    return "[%s] %s on core %s in process %s: %s" % (
+        oops.get("uptime", "?"), oops.get("reason", "?"), oops.get("core", "?"),
+        oops.get("process", "unknown"), where.splitlines()[0])
+
+def main():
+    parser = argparse.ArgumentParser(description="Extract kernel oopses from a serial log.")
+    parser.add_argument("log", nargs="?", help="the serial log (default: stdin)")
+    parser.add_argument("-o", "--output", help="directory to write each oops to as JSON")
+    args = parser.parse_args()
+
+    log = open(args.log, errors="replace") if args.log else sys.stdin
+    found = list(oopses(log))
+    if args.output:
+        os.makedirs(args.output, exist_ok=True)
+
+    for i, oops in enumerate(found):
+        print(summary(oops))
+        if args.output:
+            path = os.path.join(args.output, "oops-%d.json" % i)
+            with open(path, "w") as fd:
+                json.dump(oops, fd, indent=2)
+
+    if not found:
+        print("oops: no oopses found", file=sys.stderr)
+        sys.exit(1)
+
+if __name__ == "__main__":
+    main()
@@ -1,3 +1,4 @@
+use boot_protocol::{ImageHeader, BOOTLOADER_ADDR};
 use core::mem::zeroed;
 use core::ptr::write_volatile;
 
@@ -7,6 +8,12 @@ use crate::kmain;
 
 global_asm!(include_str!("init/init.s"));
 
+/// The header the bootloader starts with, which `layout.ld` puts before
+/// `_start`.
+#[used]
+#[link_section = ".text.header"]
+static HEADER: ImageHeader = ImageHeader::new(BOOTLOADER_ADDR);
+
 unsafe fn zeros_bss() {
     extern "C" {
         static mut __bss_beg: u64;
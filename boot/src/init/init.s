@@ -14,8 +14,8 @@ _start:
     b       1b
 
 2:
-    // set the stack to start before our boot code
-    adr     x1, _start
+    // set the stack to start before our boot code and its header
+    adr     x1, __text_beg
     mov     sp, x1
 
     // jump to kinit, which shouldn't return. halt if it does
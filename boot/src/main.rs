@@ -6,40 +6,49 @@
 #[cfg(not(test))]
 mod init;
 
+use boot_protocol::{ATAGS_ADDR, KERNEL_LOAD_ADDR, LOADER_MAGIC, MAX_KERNEL_SIZE};
 use core::time::Duration;
 use pi;
 use xmodem::Xmodem;
 
-/// Start address of the binary to load and of the bootloader.
-const BINARY_START_ADDR: usize = 0x80000;
-const BOOTLOADER_START_ADDR: usize = 0x4000000;
-
 /// Pointer to where the loaded binary expects to be loaded.
-const BINARY_START: *mut u8 = BINARY_START_ADDR as *mut u8;
-
-/// Free space between the bootloader and the loaded binary's start address.
-const MAX_BINARY_SIZE: usize = BOOTLOADER_START_ADDR - BINARY_START_ADDR;
+const BINARY_START: *mut u8 = KERNEL_LOAD_ADDR as *mut u8;
 
-/// Branches to the address `addr` unconditionally.
+/// Branches to the address `addr` unconditionally, with the registers set as
+/// the boot protocol says an image finds them.
 unsafe fn jump_to(addr: *mut u8) -> ! {
-    asm!("br $0" : : "r"(addr as usize));
+    asm!("br $0"
+         :: "r"(addr as usize), "{x0}"(ATAGS_ADDR), "{x1}"(LOADER_MAGIC)
+         :: "volatile");
     loop {
         asm!("wfe" :::: "volatile")
     }
 }
 
+/// How many attempts in a row ask for XMODEM-CRC, which lets the sender use
+/// 1K packets, before one asks for plain checksums in case the sender only
+/// knows the original protocol.
+const CRC_ATTEMPTS: usize = 3;
+
 /// Try to initialize an XMODEM connection to receive kernel binary.
 /// Will wait until we receive a binary, load it into memory, and then jump to execute.
 fn kmain() -> ! {
-    let mut binary_buffer =
-        unsafe { core::slice::from_raw_parts_mut(BINARY_START, MAX_BINARY_SIZE) };
+    let binary_buffer = unsafe { core::slice::from_raw_parts_mut(BINARY_START, MAX_KERNEL_SIZE) };
 
     let mut uart = pi::uart::MiniUart::new();
     uart.set_read_timeout(Duration::from_millis(750));
 
-    loop {
-        if Xmodem::receive(&mut uart, &mut binary_buffer).is_ok() {
-            break;
+    // a binary that says it cannot be loaded here is not entered, so that
+    // it can be sent again rather than crash
+    for attempt in 0.. {
+        let crc = attempt % (CRC_ATTEMPTS + 1) != CRC_ATTEMPTS;
+        let received = Xmodem::new(&mut uart)
+            .with_crc(crc)
+            .receive_all(&mut binary_buffer[..]);
+        if let Ok(len) = received {
+            if boot_protocol::check_kernel(&binary_buffer[..len]).is_ok() {
+                break;
+            }
         }
     }
 
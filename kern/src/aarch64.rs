@@ -0,0 +1,435 @@
+/// Defines a module named after a system register with functions to read,
+/// write and modify it, plus any constants given for its fields.
+macro_rules! sysreg {
+    ($(#[$doc:meta])* $name:ident = $reg:literal { $($item:item)* }) => {
+        $(#[$doc])*
+        #[allow(non_snake_case)]
+        pub mod $name {
+            $($item)*
+
+            /// Returns the value of the register.
+            #[inline(always)]
+            pub fn read() -> u64 {
+                #[cfg(target_os = "none")]
+                unsafe {
+                    let value: u64;
+                    core::arch::asm!(
+                        concat!("mrs {}, ", $reg),
+                        out(reg) value,
+                        options(nomem, nostack)
+                    );
+                    value
+                }
+
+                #[cfg(not(target_os = "none"))]
+                0
+            }
+
+            /// Writes `value` to the register and synchronizes the context,
+            /// so the change affects the next instruction.
+            ///
+            /// # Safety
+            ///
+            /// The caller must ensure that the new value does not break
+            /// assumptions the rest of the kernel makes about the processor's
+            /// configuration.
+            #[inline(always)]
+            pub unsafe fn write(value: u64) {
+                #[cfg(target_os = "none")]
+                core::arch::asm!(
+                    concat!("msr ", $reg, ", {}"),
+                    "isb",
+                    in(reg) value,
+                    options(nomem, nostack)
+                );
+
+                #[cfg(not(target_os = "none"))]
+                let _ = value;
+            }
+
+            /// Replaces the register's value `v` with `f(v)`.
+            ///
+            /// # Safety
+            ///
+            /// As for `write()`.
+            #[inline(always)]
+            pub unsafe fn modify<F: FnOnce(u64) -> u64>(f: F) {
+                write(f(read()))
+            }
+        }
+    };
+}
+
+sysreg!(
+    /// The system control register for EL1, which controls the MMU and
+    /// caches.
+    SCTLR_EL1 = "SCTLR_EL1" {
+        /// Enables the MMU.
+        pub const M: u64 = 1 << 0;
+        /// Enables alignment checking.
+        pub const A: u64 = 1 << 1;
+        /// Enables the data cache.
+        pub const C: u64 = 1 << 2;
+        /// Enables stack alignment checking.
+        pub const SA: u64 = 1 << 3;
+        /// Enables the instruction cache.
+        pub const I: u64 = 1 << 12;
+        /// Makes writable memory never executable.
+        pub const WXN: u64 = 1 << 19;
+    }
+);
+
+sysreg!(
+    /// The architectural feature access control register, which controls
+    /// access to floating point and SIMD.
+    CPACR_EL1 = "CPACR_EL1" {
+        /// Disables trapping of floating point and SIMD instructions.
+        pub const FPEN: u64 = 0b11 << 20;
+    }
+);
+
+sysreg!(
+    /// The vector base address register, holding the address of the
+    /// exception vectors.
+    VBAR_EL1 = "VBAR_EL1" {}
+);
+
+sysreg!(
+    /// The fault address register, holding the virtual address of the last
+    /// abort.
+    FAR_EL1 = "FAR_EL1" {}
+);
+
+sysreg!(
+    /// The monitor debug system control register, which controls debug
+    /// exceptions.
+    MDSCR_EL1 = "MDSCR_EL1" {
+        /// Enables software step.
+        pub const SS: u64 = 1 << 0;
+        /// Enables debug exceptions from EL1 to EL1.
+        pub const KDE: u64 = 1 << 13;
+    }
+);
+
+sysreg!(
+    /// The physical address register, holding the result of the last address
+    /// translation instruction.
+    PAR_EL1 = "PAR_EL1" {
+        /// Set if the translation faulted.
+        pub const F: u64 = 1 << 0;
+    }
+);
+
+sysreg!(
+    /// The memory attribute indirection register, holding the memory types
+    /// that translation table entries refer to by index.
+    MAIR_EL1 = "MAIR_EL1" {}
+);
+
+sysreg!(
+    /// The translation control register, which configures the translation
+    /// table walks for both halves of the address space.
+    TCR_EL1 = "TCR_EL1" {}
+);
+
+sysreg!(
+    /// The base address of the translation tables for the lower half of the
+    /// address space.
+    TTBR0_EL1 = "TTBR0_EL1" {}
+);
+
+sysreg!(
+    /// The base address of the translation tables for the upper half of the
+    /// address space.
+    TTBR1_EL1 = "TTBR1_EL1" {}
+);
+
+sysreg!(
+    /// The counter-timer frequency register, holding the frequency of the
+    /// system counter the generic timers count, in Hz.
+    CNTFRQ_EL0 = "CNTFRQ_EL0" {}
+);
+
+sysreg!(
+    /// The timer value register of this core's EL1 physical timer: writing
+    /// `n` makes the timer fire `n` counter ticks from now.
+    CNTP_TVAL_EL0 = "CNTP_TVAL_EL0" {}
+);
+
+sysreg!(
+    /// The control register of this core's EL1 physical timer.
+    CNTP_CTL_EL0 = "CNTP_CTL_EL0" {
+        /// Enables the timer.
+        pub const ENABLE: u64 = 1 << 0;
+        /// Masks the timer's interrupt.
+        pub const IMASK: u64 = 1 << 1;
+    }
+);
+
+sysreg!(
+    /// The performance monitors control register.
+    PMCR_EL0 = "PMCR_EL0" {
+        /// Enables the counters.
+        pub const E: u64 = 1 << 0;
+        /// Resets the cycle counter to zero.
+        pub const C: u64 = 1 << 2;
+        /// Makes the cycle counter overflow at 64 bits rather than 32.
+        pub const LC: u64 = 1 << 6;
+    }
+);
+
+sysreg!(
+    /// The performance monitors cycle counter, counting processor cycles.
+    PMCCNTR_EL0 = "PMCCNTR_EL0" {}
+);
+
+sysreg!(
+    /// Selects the exception levels the cycle counter counts cycles at.
+    PMCCFILTR_EL0 = "PMCCFILTR_EL0" {}
+);
+
+sysreg!(
+    /// Enables counters: writing a set bit enables that counter.
+    PMCNTENSET_EL0 = "PMCNTENSET_EL0" {
+        /// The bit of the cycle counter, here and in the other counter masks.
+        pub const C: u64 = 1 << 31;
+    }
+);
+
+sysreg!(
+    /// Disables counters: writing a set bit disables that counter.
+    PMCNTENCLR_EL0 = "PMCNTENCLR_EL0" {}
+);
+
+sysreg!(
+    /// Enables the overflow interrupts of counters.
+    PMINTENSET_EL1 = "PMINTENSET_EL1" {}
+);
+
+sysreg!(
+    /// Disables the overflow interrupts of counters.
+    PMINTENCLR_EL1 = "PMINTENCLR_EL1" {}
+);
+
+sysreg!(
+    /// Which counters have overflowed: writing a set bit clears it.
+    PMOVSCLR_EL0 = "PMOVSCLR_EL0" {}
+);
+
+/// Invalidates every EL1 translation cached in every core's TLB, after
+/// waiting for earlier writes to translation tables to complete.
+pub fn tlb_invalidate_all() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!(
+            "dsb ishst",
+            "tlbi vmalle1is",
+            "dsb ish",
+            "isb",
+            options(nostack)
+        );
+    }
+}
+
+/// Invalidates the non-global translations cached in every core's TLB for the
+/// address space identified by `asid`, after waiting for earlier writes to
+/// translation tables to complete.
+pub fn tlb_invalidate_asid(asid: u16) {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!(
+            "dsb ishst",
+            "tlbi aside1is, {}",
+            "dsb ish",
+            "isb",
+            in(reg) (asid as u64) << 48,
+            options(nostack)
+        );
+    }
+
+    #[cfg(not(target_os = "none"))]
+    let _ = asid;
+}
+
+/// Invalidates the translations of the `pages` pages starting at the virtual
+/// address `va` cached in every core's TLB, after waiting for earlier writes
+/// to translation tables to complete. Translations for `asid` are
+/// invalidated, or those for every address space if it is `None`, which
+/// global translations require.
+pub fn tlb_invalidate_pages(asid: Option<u16>, va: usize, pages: usize) {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("dsb ishst", options(nostack));
+        for i in 0..pages {
+            let page = ((va >> 12) + i) as u64 & 0xFFF_FFFF_FFFF;
+            match asid {
+                Some(asid) => core::arch::asm!(
+                    "tlbi vae1is, {}",
+                    in(reg) (asid as u64) << 48 | page,
+                    options(nostack)
+                ),
+                None => core::arch::asm!("tlbi vaae1is, {}", in(reg) page, options(nostack)),
+            }
+        }
+        core::arch::asm!("dsb ish", "isb", options(nostack));
+    }
+
+    #[cfg(not(target_os = "none"))]
+    let _ = (asid, va, pages);
+}
+
+/// Waits for earlier writes to translation tables to complete, so the table
+/// walker sees them. Enough by itself when only invalid descriptors were
+/// replaced, since the TLB never caches a translation that faulted.
+pub fn tables_sync() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("dsb ishst", "isb", options(nostack));
+    }
+}
+
+/// The size of the cache lines maintained by `sync_instruction_cache()` and
+/// `clean_data_cache()`.
+const CACHE_LINE_SIZE: usize = 64;
+
+/// Makes the `len` bytes of code just written at `addr` visible to
+/// instruction fetches on every core: cleans them from the data cache and
+/// invalidates the instruction caches.
+pub fn sync_instruction_cache(addr: usize, len: usize) {
+    #[cfg(target_os = "none")]
+    unsafe {
+        let mut line = addr & !(CACHE_LINE_SIZE - 1);
+        while line < addr + len {
+            core::arch::asm!("dc cvau, {}", in(reg) line, options(nostack));
+            line += CACHE_LINE_SIZE;
+        }
+        core::arch::asm!("dsb ish", "ic ialluis", "dsb ish", "isb", options(nostack));
+    }
+
+    #[cfg(not(target_os = "none"))]
+    let _ = (addr, len, CACHE_LINE_SIZE);
+}
+
+/// Writes the `len` bytes at `addr` back from the data cache to memory, for a
+/// core whose MMU and caches are still off to read.
+pub fn clean_data_cache(addr: usize, len: usize) {
+    #[cfg(target_os = "none")]
+    unsafe {
+        let mut line = addr & !(CACHE_LINE_SIZE - 1);
+        while line < addr + len {
+            core::arch::asm!("dc cvac, {}", in(reg) line, options(nostack));
+            line += CACHE_LINE_SIZE;
+        }
+        core::arch::asm!("dsb sy", options(nostack));
+    }
+
+    #[cfg(not(target_os = "none"))]
+    let _ = (addr, len);
+}
+
+/// Writes the `len` bytes at `addr` back from the data cache to memory and
+/// drops them from it, so that the next reads of them come from memory.
+pub fn clean_invalidate_data_cache(addr: usize, len: usize) {
+    #[cfg(target_os = "none")]
+    unsafe {
+        let mut line = addr & !(CACHE_LINE_SIZE - 1);
+        while line < addr + len {
+            core::arch::asm!("dc civac, {}", in(reg) line, options(nostack));
+            line += CACHE_LINE_SIZE;
+        }
+        core::arch::asm!("dsb sy", options(nostack));
+    }
+
+    #[cfg(not(target_os = "none"))]
+    let _ = (addr, len);
+}
+
+/// Returns `true` if EL1 can read the virtual address `va` or, if `write` is
+/// set, write it, with the translation tables installed. The MMU translates
+/// it as an access would, so nothing faults.
+pub fn can_access(va: usize, write: bool) -> bool {
+    #[cfg(target_os = "none")]
+    unsafe {
+        if write {
+            core::arch::asm!("at s1e1w, {}", "isb", in(reg) va, options(nostack));
+        } else {
+            core::arch::asm!("at s1e1r, {}", "isb", in(reg) va, options(nostack));
+        }
+        PAR_EL1::read() & PAR_EL1::F == 0
+    }
+
+    #[cfg(not(target_os = "none"))]
+    {
+        let _ = (va, write);
+        true
+    }
+}
+
+/// Waits for an interrupt: suspends the core until one is pending, even if it
+/// is masked.
+pub fn wfi() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("wfi", options(nomem, nostack));
+    }
+}
+
+/// Waits for an event: suspends the core until another core sends one with
+/// `sev()`, the timer's event stream generates one, or an unmasked interrupt
+/// is pending. Returns at once if an event arrived since the last wait.
+pub fn wfe() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("wfe", options(nomem, nostack));
+    }
+}
+
+/// Sends an event to every core, waking those waiting in `wfe`, once the
+/// stores before it are visible to them.
+pub fn sev() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("dsb ishst", "sev", options(nostack));
+    }
+}
+
+/// Returns the number of the core running this: 0 to 3 on the Pi 3.
+pub fn affinity() -> usize {
+    #[cfg(target_os = "none")]
+    unsafe {
+        let mpidr: u64;
+        core::arch::asm!("mrs {}, MPIDR_EL1", out(reg) mpidr, options(nomem, nostack));
+        (mpidr & 0b11) as usize
+    }
+
+    #[cfg(not(target_os = "none"))]
+    0
+}
+
+/// Returns the exception level the processor is running at.
+pub fn current_el() -> u8 {
+    #[cfg(target_os = "none")]
+    unsafe {
+        let el: u64;
+        core::arch::asm!("mrs {}, CurrentEL", out(reg) el, options(nomem, nostack));
+        ((el >> 2) & 0b11) as u8
+    }
+
+    #[cfg(not(target_os = "none"))]
+    1
+}
+
+/// Returns the exception level the firmware entered the kernel at, before
+/// `init` dropped to EL1. The Pi 3's firmware enters at EL2.
+pub fn entry_el() -> u8 {
+    #[cfg(target_os = "none")]
+    unsafe {
+        extern "C" {
+            static __entry_el: u64;
+        }
+        core::ptr::read_volatile(&__entry_el) as u8
+    }
+
+    #[cfg(not(target_os = "none"))]
+    2
+}
@@ -6,14 +6,16 @@ mod bump;
 
 type AllocatorImpl = bump::Allocator;
 
-#[cfg(test)]
+#[cfg(all(test, not(target_os = "none")))]
 mod tests;
 
 use core::alloc::{GlobalAlloc, Layout};
 use core::fmt;
 
 use crate::console::kprintln;
+use crate::irq::with_irqs_disabled;
 use crate::mutex::Mutex;
+use crate::vm::{phys_to_virt, virt_to_phys};
 use pi::atags::{Atag, Atags};
 
 /// `LocalAlloc` is an analogous trait to the standard library's `GlobalAlloc`,
@@ -21,6 +23,36 @@ use pi::atags::{Atag, Atags};
 pub trait LocalAlloc {
     unsafe fn alloc(&mut self, layout: Layout) -> *mut u8;
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Returns a snapshot of this allocator's usage.
+    fn stats(&self) -> Stats;
+}
+
+/// A snapshot of a memory allocator's usage. All sizes are in bytes.
+///
+/// `used` and `free` need not add up to `total`: memory lost to alignment
+/// padding, or freed to an allocator that cannot reuse it, is neither.
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    /// The size of the region the allocator manages.
+    pub total: usize,
+    /// The amount of memory currently allocated.
+    pub used: usize,
+    /// The largest amount of memory that has been allocated at once.
+    pub peak: usize,
+    /// The amount of memory available to future allocations.
+    pub free: usize,
+    /// The state of each size class, for allocators that keep them.
+    pub bins: Option<[BinStats; bin::BIN_COUNT]>,
+}
+
+/// The state of one size class of a bin allocator.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BinStats {
+    /// The size of the blocks in the bin.
+    pub size: usize,
+    /// The number of freed blocks the bin holds for reuse.
+    pub free_blocks: usize,
 }
 
 /// Thread-safe (locking) wrapper around a particular memory allocator.
@@ -35,34 +67,48 @@ impl Allocator {
         Allocator(Mutex::new(None))
     }
 
-    /// Initializes the memory allocator.
+    /// Initializes the memory allocator to hand out the memory from `start`
+    /// to `end`, as found by `memory_map()`.
     /// The caller should assure that the method is invoked only once during the
     /// kernel initialization.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the system's memory map could not be retrieved.
-    pub unsafe fn initialize(&self) {
-        let (start, end) = memory_map().expect("failed to find memory map");
+    pub unsafe fn initialize(&self, start: usize, end: usize) {
         *self.0.lock() = Some(AllocatorImpl::new(start, end));
     }
+
+    /// Returns a snapshot of the allocator's usage, or `None` if it has not
+    /// been initialized.
+    pub fn stats(&self) -> Option<Stats> {
+        self.0.lock().as_ref().map(|alloc| alloc.stats())
+    }
+
+    /// Like `stats()`, but returns `None` rather than wait for another core
+    /// using the allocator, so that crash reports can use it.
+    pub fn try_stats(&self) -> Option<Stats> {
+        self.0.try_lock()?.as_ref().map(|alloc| alloc.stats())
+    }
 }
 
+// IRQs are masked while the heap is in use, since the timer interrupt can
+// switch to another process that allocates.
 unsafe impl GlobalAlloc for Allocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.0
-            .lock()
-            .as_mut()
-            .expect("allocator uninitialized")
-            .alloc(layout)
+        with_irqs_disabled(|| {
+            self.0
+                .lock()
+                .as_mut()
+                .expect("allocator uninitialized")
+                .alloc(layout)
+        })
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.0
-            .lock()
-            .as_mut()
-            .expect("allocator uninitialized")
-            .dealloc(ptr, layout);
+        with_irqs_disabled(|| {
+            self.0
+                .lock()
+                .as_mut()
+                .expect("allocator uninitialized")
+                .dealloc(ptr, layout)
+        })
     }
 }
 
@@ -71,12 +117,13 @@ extern "C" {
 }
 
 /// Returns the (start address, end address) of the available memory on this
-/// system if it can be determined. If it cannot, `None` is returned.
+/// system if it can be determined. If it cannot, `None` is returned. The
+/// addresses are the kernel's virtual addresses for the memory.
 ///
 /// This function is expected to return `Some` under all normal cirumstances.
 pub fn memory_map() -> Option<(usize, usize)> {
     let page_size = 1 << 12;
-    let binary_end = unsafe { (&__text_end as *const u8) as usize };
+    let binary_end = virt_to_phys(unsafe { (&__text_end as *const u8) as usize });
     let mem_begin = util::align_up(binary_end, page_size);
 
     for tag in Atags::get() {
@@ -89,7 +136,7 @@ pub fn memory_map() -> Option<(usize, usize)> {
                 continue;
             }
 
-            return Some((mem_begin, mem_end));
+            return Some((phys_to_virt(mem_begin), phys_to_virt(mem_end)));
         }
     }
 
@@ -105,3 +152,45 @@ impl fmt::Debug for Allocator {
         Ok(())
     }
 }
+
+#[cfg(all(test, target_os = "none"))]
+mod ktests {
+    use alloc::vec::Vec;
+    use core::alloc::{GlobalAlloc, Layout};
+
+    use crate::ALLOCATOR;
+
+    #[test_case]
+    fn allocations_are_aligned_and_disjoint() {
+        let sizes = [(1, 1), (24, 8), (100, 64), (4096, 4096), (3, 2)];
+        let blocks: Vec<(*mut u8, Layout)> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &(size, align))| {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { ALLOCATOR.alloc(layout) };
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0);
+                unsafe { ptr.write_bytes(i as u8, size) };
+                (ptr, layout)
+            })
+            .collect();
+
+        for (i, &(ptr, layout)) in blocks.iter().enumerate() {
+            for offset in 0..layout.size() {
+                assert_eq!(unsafe { *ptr.add(offset) }, i as u8);
+            }
+            unsafe { ALLOCATOR.dealloc(ptr, layout) };
+        }
+    }
+
+    #[test_case]
+    fn stats_count_allocations() {
+        let before = ALLOCATOR.stats().unwrap();
+        let block: Vec<u8> = Vec::with_capacity(4096);
+        let after = ALLOCATOR.stats().unwrap();
+        assert!(after.used >= before.used + block.capacity());
+        assert!(after.peak >= after.used);
+        assert!(after.used + after.free <= after.total);
+    }
+}
@@ -5,7 +5,7 @@ use core::ptr;
 use crate::allocator::bump;
 use crate::allocator::linked_list::LinkedList;
 use crate::allocator::util::*;
-use crate::allocator::LocalAlloc;
+use crate::allocator::{BinStats, LocalAlloc, Stats};
 
 /// A simple allocator that allocates based on size classes.
 ///   bin 0 (2^3 bytes)    : handles allocations in (0, 2^3]
@@ -19,9 +19,17 @@ use crate::allocator::LocalAlloc;
 pub struct Allocator {
     /// Fallback allocator when there are no free slots in the requested bin
     global_pool: bump::Allocator,
-    bins: [LinkedList; SIZES.len()],
+    bins: [LinkedList; BIN_COUNT],
+    /// The number of bytes currently allocated, counting whole blocks for
+    /// allocations served by a bin.
+    used: usize,
+    /// The largest value `used` has had.
+    peak: usize,
 }
 
+/// The number of size classes a bin allocator keeps.
+pub const BIN_COUNT: usize = SIZES.len();
+
 /// The size of the memory blocks that each bin handles
 const SIZES: [usize; 14] = [
     1 << 3,
@@ -44,10 +52,15 @@ impl Allocator {
     /// Creates a new bin allocator that will allocate memory from the region
     /// starting at address `start` and ending at address `end`.
     pub fn new(start: usize, end: usize) -> Self {
-        let bins = [LinkedList::new(); SIZES.len()];
+        let bins = [LinkedList::new(); BIN_COUNT];
         let global_pool = bump::Allocator::new(start, end);
 
-        Self { global_pool, bins }
+        Self {
+            global_pool,
+            bins,
+            used: 0,
+            peak: 0,
+        }
     }
 
     /// Allocates memory that is guaranteed to fit in the `bin_num`th bin.
@@ -124,10 +137,16 @@ impl LocalAlloc for Allocator {
             return ptr::null_mut();
         }
 
-        match map_to_bin(layout) {
-            Some(n) => self.alloc_from_bin(n, layout),
-            None => self.alloc_from_fallback(layout),
+        let (ptr, size) = match map_to_bin(layout) {
+            Some(n) => (self.alloc_from_bin(n, layout), SIZES[n]),
+            None => (self.alloc_from_fallback(layout), layout.size()),
+        };
+
+        if !ptr.is_null() {
+            self.used += size;
+            self.peak = core::cmp::max(self.peak, self.used);
         }
+        ptr
     }
 
     /// Deallocates the memory referenced by `ptr`.
@@ -152,8 +171,32 @@ impl LocalAlloc for Allocator {
                 );
 
                 self.bins[n].push(ptr as *mut usize);
+                self.used -= SIZES[n];
+            }
+            None => {
+                self.global_pool.dealloc(ptr, layout);
+                self.used -= layout.size();
             }
-            None => self.global_pool.dealloc(ptr, layout),
+        }
+    }
+
+    /// Returns this allocator's usage. Freed blocks held by the bins count as
+    /// free along with the untouched remainder of the region; memory freed
+    /// from allocations too large for any bin is leaked and counts as neither.
+    fn stats(&self) -> Stats {
+        let pool = self.global_pool.stats();
+        let mut bins = [BinStats::default(); BIN_COUNT];
+        for (stats, (&size, list)) in bins.iter_mut().zip(SIZES.iter().zip(self.bins.iter())) {
+            stats.size = size;
+            stats.free_blocks = list.iter().count();
+        }
+
+        Stats {
+            total: pool.total,
+            used: self.used,
+            peak: self.peak,
+            free: pool.free + bins.iter().map(|b| b.size * b.free_blocks).sum::<usize>(),
+            bins: Some(bins),
         }
     }
 }
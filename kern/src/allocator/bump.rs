@@ -2,11 +2,12 @@ use core::alloc::Layout;
 use core::ptr;
 
 use crate::allocator::util::*;
-use crate::allocator::LocalAlloc;
+use crate::allocator::{LocalAlloc, Stats};
 
 /// A "bump" allocator: allocates memory by bumping a pointer; never frees.
 #[derive(Debug)]
 pub struct Allocator {
+    start: usize,
     current: usize,
     end: usize,
 }
@@ -17,6 +18,7 @@ impl Allocator {
     #[allow(dead_code)]
     pub fn new(start: usize, end: usize) -> Allocator {
         Allocator {
+            start,
             current: start,
             end,
         }
@@ -84,4 +86,17 @@ impl LocalAlloc for Allocator {
     unsafe fn dealloc(&mut self, _ptr: *mut u8, _layout: Layout) {
         // LEAKED
     }
+
+    /// Returns this allocator's usage. Since memory is never freed, every byte
+    /// below the bump pointer counts as used, and usage is always at its peak.
+    fn stats(&self) -> Stats {
+        let used = self.current - self.start;
+        Stats {
+            total: self.end - self.start,
+            used,
+            peak: used,
+            free: self.end - self.current,
+            bins: None,
+        }
+    }
 }
@@ -202,6 +202,27 @@ mod allocator {
         test_layouts!(layouts, start, end, a);
     });
 
+    test_allocators!(bin_stats, bump_stats, 4096, |(start, end, mut a)| {
+        let stats = a.stats();
+        assert_eq!(stats.total, end - start);
+        assert_eq!(stats.used, 0);
+        assert_eq!(stats.free, end - start);
+
+        let layout = layout!(100, 8);
+        let ptr = a.alloc(layout.clone());
+        let used = a.stats().used;
+        assert!(used >= 100);
+
+        a.dealloc(ptr, layout);
+        let stats = a.stats();
+        assert_eq!(stats.peak, used);
+        assert!(stats.used + stats.free <= stats.total);
+        if let Some(bins) = stats.bins {
+            assert_eq!(stats.used, 0);
+            assert_eq!(bins.iter().map(|b| b.free_blocks).sum::<usize>(), 1);
+        }
+    });
+
     fn scribble(ptr: *mut u8, size: usize) {
         unsafe {
             ::core::ptr::write_bytes(ptr, 0xAF, size);
@@ -0,0 +1,422 @@
+use alloc::boxed::Box;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use pi::i2c::{self, I2c};
+use pi::interrupt::Interrupt;
+use pi::timer::Timer;
+
+use crate::aarch64::{CNTFRQ_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0};
+use crate::irq::with_irqs_disabled;
+use crate::mutex::Mutex;
+use crate::profile;
+use crate::IRQ;
+
+/// The frequency of the periodic tick, in ticks per second.
+pub const HZ: u64 = 100;
+
+/// The time between ticks.
+pub const TICK: Duration = Duration::from_millis(1000 / HZ);
+
+/// The number of seconds in a day.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The I2C address of a DS1307 or DS3231 real-time clock.
+const RTC_ADDRESS: u8 = 0x68;
+
+/// The number of the RTC's registers holding the date and time, from register
+/// 0 on: seconds, minutes, hours, weekday, day, month and year, in BCD.
+const RTC_REGS: usize = 7;
+
+/// The wall-clock time the clock was last set to, in seconds since the Unix
+/// epoch, along with the uptime at which it was set. `None` until the clock is
+/// first set. It is only locked with IRQs masked, as log output from interrupt
+/// handlers reads it.
+///
+/// The wall-clock time is kept in software by counting forward from the last
+/// time it was set: at boot from a battery-backed RTC, if `probe_rtc()` found
+/// one, or by `set()`.
+static CLOCK: Mutex<Option<(u64, Duration)>> = Mutex::new(None);
+
+/// Whether `probe_rtc()` found an RTC, which `set()` then sets too.
+static RTC: AtomicBool = AtomicBool::new(false);
+
+/// The number of ticks since the tick was started.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// A calendar date and time of day, in UTC.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DateTime {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the date and time `secs` seconds after the Unix epoch.
+    pub fn from_unix(secs: u64) -> DateTime {
+        // Days to civil date, from Howard Hinnant's `civil_from_days`, with
+        // days counted from 0000-03-01 so that leap days end each era.
+        let days = secs / SECS_PER_DAY + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        let secs_of_day = secs % SECS_PER_DAY;
+        DateTime {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+        }
+    }
+
+    /// Returns the number of seconds between the Unix epoch and this time, or
+    /// `None` if this time is before the epoch.
+    pub fn to_unix(&self) -> Option<u64> {
+        // The inverse of `from_unix`: Howard Hinnant's `days_from_civil`.
+        let (month, day) = (self.month as u64, self.day as u64);
+        let year = (self.year as u64).checked_sub(if month <= 2 { 1 } else { 0 })?;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = (era * 146_097 + day_of_era).checked_sub(719_468)?;
+
+        let secs_of_day = self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64;
+        Some(days * SECS_PER_DAY + secs_of_day)
+    }
+
+    /// Parses a date and time written as `YYYY-MM-DD HH:MM:SS`. A `T` may
+    /// separate the date and time instead of a space. Returns `None` if `s`
+    /// is malformed or names a time before the Unix epoch.
+    pub fn parse(s: &str) -> Option<DateTime> {
+        let s = s.trim();
+        let split = s.find(|c| c == ' ' || c == 'T')?;
+        DateTime::from_parts(&s[..split], s[split + 1..].trim_start())
+    }
+
+    /// Parses a date written as `YYYY-MM-DD` and a time written as
+    /// `HH:MM:SS`. Returns `None` if either is malformed or they name a time
+    /// before the Unix epoch.
+    pub fn from_parts(date: &str, time: &str) -> Option<DateTime> {
+        let mut fields = [0u32; 6];
+        let parts = date.splitn(3, '-').chain(time.splitn(3, ':'));
+        let mut count = 0;
+        for (field, part) in fields.iter_mut().zip(parts) {
+            *field = part.parse().ok()?;
+            count += 1;
+        }
+
+        let [year, month, day, hour, minute, second] = fields;
+        if count != fields.len() {
+            return None;
+        }
+        DateTime::new(year, month, day, hour, minute, second)
+    }
+
+    /// Returns the given date and time, or `None` if it does not exist or is
+    /// before the Unix epoch.
+    pub fn new(
+        year: u32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> Option<DateTime> {
+        let max_day = match month {
+            2 if is_leap_year(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            1..=12 => 31,
+            _ => return None,
+        };
+
+        if day == 0 || day > max_day || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+
+        let datetime = DateTime {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hour: hour as u8,
+            minute: minute as u8,
+            second: second as u8,
+        };
+        datetime.to_unix().map(|_| datetime)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Returns the date and time the RTC registers `regs` hold, or `None` if the
+/// RTC is stopped or holds no valid date and time. Hours may be kept in 12- or
+/// 24-hour form; years count from 2000.
+fn from_rtc(regs: &[u8; RTC_REGS]) -> Option<DateTime> {
+    // A DS1307 sets the top bit of the seconds while its oscillator is
+    // stopped, as it is until it is first set.
+    if regs[0] & 0x80 != 0 {
+        return None;
+    }
+
+    let hour = if regs[2] & 0x40 != 0 {
+        let pm = if regs[2] & 0x20 != 0 { 12 } else { 0 };
+        from_bcd(regs[2] & 0x1f) % 12 + pm
+    } else {
+        from_bcd(regs[2] & 0x3f)
+    };
+    DateTime::new(
+        2000 + from_bcd(regs[6]),
+        from_bcd(regs[5] & 0x1f),
+        from_bcd(regs[4] & 0x3f),
+        hour,
+        from_bcd(regs[1] & 0x7f),
+        from_bcd(regs[0] & 0x7f),
+    )
+}
+
+/// Returns the RTC registers holding `datetime`, with 24-hour hours, or
+/// `None` if its year is outside the RTC's 2000 to 2099.
+fn to_rtc(datetime: &DateTime) -> Option<[u8; RTC_REGS]> {
+    if datetime.year < 2000 || datetime.year > 2099 {
+        return None;
+    }
+
+    // The RTC counts weekdays from 1, taken here to be Sunday; 1970-01-01 was
+    // a Thursday.
+    let days = datetime.to_unix()? / SECS_PER_DAY;
+    let weekday = ((days + 4) % 7 + 1) as u8;
+    Some([
+        to_bcd(datetime.second as u32),
+        to_bcd(datetime.minute as u32),
+        to_bcd(datetime.hour as u32),
+        weekday,
+        to_bcd(datetime.day as u32),
+        to_bcd(datetime.month as u32),
+        to_bcd(datetime.year - 2000),
+    ])
+}
+
+/// Returns the value of the two BCD digits `byte`.
+fn from_bcd(byte: u8) -> u32 {
+    (byte >> 4) as u32 * 10 + (byte & 0xf) as u32
+}
+
+/// Returns `value`, less than 100, as two BCD digits.
+fn to_bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+/// Returns `true` if `year` has a February 29th.
+fn is_leap_year(year: u32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Returns the time elapsed since the system timer started counting at boot.
+pub fn uptime() -> Duration {
+    pi::timer::current_time()
+}
+
+/// Starts the periodic tick: a timer interrupt `HZ` times a second, each of
+/// which advances `ticks()` and lets the profiler start sampling on core 0.
+/// IRQs must be initialized.
+pub fn start_tick() {
+    IRQ.register(
+        Interrupt::Timer1,
+        Box::new(|_| {
+            TICKS.fetch_add(1, Ordering::Relaxed);
+            profile::tick();
+            Timer::new().tick_again(TICK);
+        }),
+    );
+    pi::timer::tick_in(TICK);
+}
+
+/// Starts the periodic tick on a secondary core, from the core's own generic
+/// timer: an interrupt `HZ` times a second, at which the scheduler can
+/// preempt the core's processes and the profiler can start sampling on the
+/// core. Only core 0's tick advances `ticks()`.
+pub fn start_core_tick() {
+    IRQ.register_core_timer(Box::new(|_| {
+        profile::tick();
+        core_tick_in(TICK)
+    }));
+    core_tick_in(TICK);
+}
+
+/// Makes this core's EL1 physical timer interrupt `t` from now, which also
+/// clears its last interrupt.
+fn core_tick_in(t: Duration) {
+    let counts = CNTFRQ_EL0::read() * t.as_micros() as u64 / 1_000_000;
+    unsafe {
+        CNTP_TVAL_EL0::write(counts);
+        CNTP_CTL_EL0::write(CNTP_CTL_EL0::ENABLE);
+    }
+}
+
+/// Returns the number of ticks since the tick was started.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Looks for a DS1307 or DS3231 RTC on the I2C bus and, if one answers and
+/// holds a valid time, sets the wall-clock time from it. Returns `true` if an
+/// RTC answered, whether or not it had been set.
+pub fn probe_rtc() -> bool {
+    let mut regs = [0u8; RTC_REGS];
+    if I2c::new()
+        .read_registers(RTC_ADDRESS, 0, &mut regs)
+        .is_err()
+    {
+        return false;
+    }
+
+    RTC.store(true, Ordering::Relaxed);
+    if let Some(now) = from_rtc(&regs) {
+        set_software(now);
+    }
+    true
+}
+
+/// Sets the wall-clock time to `now`, and the RTC too if there is one.
+///
+/// # Errors
+///
+/// Fails if the RTC could not be set. The wall-clock time is set regardless.
+///
+/// # Panics
+///
+/// Panics if `now` is before the Unix epoch. `DateTime::parse` never returns
+/// such a time.
+pub fn set(now: DateTime) -> Result<(), i2c::Error> {
+    set_software(now);
+    if !RTC.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    // A date the RTC cannot hold is left to the software clock alone.
+    match to_rtc(&now) {
+        Some(regs) => {
+            let mut bytes = [0u8; RTC_REGS + 1];
+            bytes[1..].copy_from_slice(&regs);
+            I2c::new().write(RTC_ADDRESS, &bytes)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Sets the software clock's wall-clock time to `now`.
+fn set_software(now: DateTime) {
+    let secs = now.to_unix().expect("clock set before the Unix epoch");
+    with_irqs_disabled(|| *CLOCK.lock() = Some((secs, uptime())));
+}
+
+/// Returns the current wall-clock time, or `None` if the clock has not been
+/// set since boot.
+pub fn now() -> Option<DateTime> {
+    unix_time().map(DateTime::from_unix)
+}
+
+/// Returns the current wall-clock time in seconds since the Unix epoch, or
+/// `None` if the clock has not been set since boot.
+pub fn unix_time() -> Option<u64> {
+    let (secs, set_at) = with_irqs_disabled(|| *CLOCK.lock())?;
+    let elapsed = uptime().checked_sub(set_at).unwrap_or_default();
+    Some(secs + elapsed.as_secs())
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{from_rtc, to_rtc, DateTime};
+
+    fn datetime(year: u32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn unix_conversions() {
+        let cases = [
+            (0, datetime(1970, 1, 1, 0, 0, 0)),
+            (951_782_400, datetime(2000, 2, 29, 0, 0, 0)),
+            (1_234_567_890, datetime(2009, 2, 13, 23, 31, 30)),
+            (4_107_542_399, datetime(2100, 2, 28, 23, 59, 59)),
+            (4_107_542_400, datetime(2100, 3, 1, 0, 0, 0)),
+        ];
+
+        for &(secs, expected) in cases.iter() {
+            assert_eq!(DateTime::from_unix(secs), expected);
+            assert_eq!(expected.to_unix(), Some(secs));
+        }
+
+        assert_eq!(datetime(1969, 12, 31, 23, 59, 59).to_unix(), None);
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let parsed = DateTime::parse("2020-02-29 13:05:09").unwrap();
+        assert_eq!(parsed, datetime(2020, 2, 29, 13, 5, 9));
+        assert_eq!(parsed.to_string(), "2020-02-29 13:05:09");
+        assert_eq!(DateTime::parse("2020-02-29T13:05:09"), Some(parsed));
+
+        assert_eq!(DateTime::parse("2021-02-29 00:00:00"), None);
+        assert_eq!(DateTime::parse("2021-13-01 00:00:00"), None);
+        assert_eq!(DateTime::parse("2021-01-01 24:00:00"), None);
+        assert_eq!(DateTime::parse("1969-12-31 23:59:59"), None);
+        assert_eq!(DateTime::parse("2021-01-01"), None);
+        assert_eq!(DateTime::parse("yesterday"), None);
+    }
+
+    #[test]
+    fn rtc_registers() {
+        let now = datetime(2024, 2, 29, 13, 5, 9);
+        let regs = [0x09, 0x05, 0x13, 0x05, 0x29, 0x02, 0x24];
+        assert_eq!(to_rtc(&now), Some(regs));
+        assert_eq!(from_rtc(&regs), Some(now));
+
+        // 1 PM in 12-hour form, and 12 AM.
+        assert_eq!(
+            from_rtc(&[0x09, 0x05, 0x61, 0x05, 0x29, 0x02, 0x24]),
+            Some(now)
+        );
+        let midnight = from_rtc(&[0x00, 0x00, 0x52, 0x05, 0x29, 0x02, 0x24]);
+        assert_eq!(midnight, Some(datetime(2024, 2, 29, 0, 0, 0)));
+
+        assert_eq!(from_rtc(&[0x80, 0, 0, 1, 1, 1, 0]), None);
+        assert_eq!(from_rtc(&[0, 0, 0, 1, 0x30, 0x02, 0x24]), None);
+        assert_eq!(to_rtc(&datetime(2100, 1, 1, 0, 0, 0)), None);
+    }
+}
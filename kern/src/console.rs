@@ -1,23 +1,113 @@
+mod bounded;
+mod file;
+mod history;
+mod line;
+pub mod log;
+mod rx;
+
+use alloc::boxed::Box;
 use core::fmt;
+use core::time::Duration;
+use kernel_api::SIGINT;
+use pi::interrupt::Interrupt;
 use pi::uart::MiniUart;
 use shim::io;
+use shim::ioerr;
 
+use crate::irq::with_irqs_disabled;
 use crate::mutex::Mutex;
+use crate::process::{self, signal, Event};
+use crate::traps::gdb;
+use crate::{IRQ, SCHEDULER};
+
+use self::rx::RxBuffer;
+
+pub use self::bounded::BoundedWriter;
+pub use self::file::ConsoleFile;
+pub use self::history::History;
+pub use self::line::{Completer, LineEditor, Status};
+
+/// The size of the stack buffer used to format `kprint_nolock!` output.
+const NOLOCK_BUF_SIZE: usize = 512;
+
+/// The byte `Ctrl-C` sends.
+const CTRL_C: u8 = 0x03;
+
+/// The most sinks console output can be copied to.
+const MAX_SINKS: usize = 4;
+
+/// Bytes received by the UART's interrupt handler, or from other sources of
+/// input, and not yet read.
+static RX: RxBuffer = RxBuffer::new();
+
+/// Held by whoever pushes to `RX`.
+static RX_PRODUCER: Mutex<()> = Mutex::new(());
+
+/// The event readers of `RX` block until, woken whenever a byte is pushed.
+fn rx_event() -> Event {
+    &RX as *const RxBuffer as Event
+}
+
+/// Somewhere console output is copied to besides the UART, such as the
+/// framebuffer's terminal. A sink is given the bytes as the UART is, except
+/// that a `\n` may not be preceded by a `\r`.
+#[derive(Copy, Clone)]
+pub struct Sink {
+    /// Writes bytes to the sink, waiting for it if another core is using it.
+    pub write: fn(&[u8]),
+    /// Writes bytes to the sink without waiting or allocating, as
+    /// `kprint_nolock!` must. The bytes may be dropped instead.
+    pub write_nolock: fn(&[u8]),
+}
+
+/// The sinks console output is copied to.
+static SINKS: Mutex<[Option<Sink>; MAX_SINKS]> = Mutex::new([None; MAX_SINKS]);
+
+/// Has console output copied to `sink` from now on. Returns `false` if there
+/// are `MAX_SINKS` sinks already.
+pub fn add_sink(sink: Sink) -> bool {
+    let mut sinks = SINKS.lock();
+    match sinks.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(sink);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Copies `bytes`, just written to the UART, to the log and every sink.
+fn copy_to_sinks(bytes: &[u8]) {
+    log::record(bytes);
+
+    // The lock is not held while the sinks write, which may take a while.
+    let sinks = *SINKS.lock();
+    for sink in sinks.iter().flatten() {
+        (sink.write)(bytes);
+    }
+}
 
 /// A global singleton allowing read/write access to the console.
 pub struct Console {
     inner: Option<MiniUart>,
+    /// Whether input arrives through `RX` rather than by polling the UART.
+    buffered: bool,
 }
 
 impl Console {
     /// Creates a new instance of `Console`.
     const fn new() -> Console {
-        Console { inner: None }
+        Console {
+            inner: None,
+            buffered: false,
+        }
     }
 
-    /// Initializes the console if it's not already initialized.
+    /// Initializes the console if it's not already initialized. Writing to
+    /// the console initializes it too; booting does it early so that the
+    /// UART is set up before anything is written.
     #[inline]
-    fn initialize(&mut self) {
+    pub fn initialize(&mut self) {
         if self.inner.is_none() {
             self.inner = Some(MiniUart::new());
         }
@@ -31,26 +121,79 @@ impl Console {
         self.inner.as_mut().unwrap()
     }
 
-    /// Reads a byte from the UART device, blocking until a byte is available.
-    pub fn read_byte(&mut self) -> u8 {
-        self.inner().read_byte()
+    /// Reads a byte from the UART device if one has arrived, without waiting.
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        if self.buffered {
+            RX.pop()
+        } else if self.inner().has_byte() {
+            Some(self.inner().read_byte())
+        } else {
+            None
+        }
     }
 
-    /// Writes the byte `byte` to the UART device.
+    /// Writes the byte `byte` to the UART device, and to every sink.
     pub fn write_byte(&mut self, byte: u8) {
-        self.inner().write_byte(byte)
+        self.inner().write_byte(byte);
+        copy_to_sinks(&[byte]);
     }
-}
 
-impl io::Read for Console {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner().read(buf)
+    /// Switches the console to interrupt-driven input: from now on, the
+    /// UART's receive interrupt moves each byte into a buffer as soon as it
+    /// arrives, so input is not lost while the console is busy writing.
+    /// `Ctrl-C` is not buffered while a process is in the foreground: it
+    /// sends the process `SIGINT` instead. What GDB sends to break in stops
+    /// the kernel in `traps::gdb`. IRQs must be initialized.
+    pub fn enable_rx_interrupt(&mut self) {
+        // The handler has a UART handle of its own so that it never waits
+        // for the console lock, which the interrupted code may hold.
+        let mut uart = MiniUart::new();
+        let mut watcher = gdb::Watcher::new();
+        IRQ.register(
+            Interrupt::Aux,
+            Box::new(move |tf| {
+                while uart.has_byte() {
+                    let byte = uart.read_byte();
+                    if watcher.watch(byte) {
+                        gdb::interrupt(tf);
+                        continue;
+                    }
+                    receive(byte);
+                }
+            }),
+        );
+
+        self.inner().set_rx_interrupt(true);
+        self.buffered = true;
     }
 }
 
+/// Takes `byte` as console input, from the UART or another source of input
+/// such as a USB keyboard. `Ctrl-C` is not buffered while a process is in
+/// the foreground: it sends the process `SIGINT` instead. The console must
+/// be using interrupt-driven input.
+pub fn receive(byte: u8) {
+    // `RX` takes one producer at a time: the sources take turns, and one
+    // is never interrupted by another on its core.
+    with_irqs_disabled(|| {
+        let _producer = RX_PRODUCER.lock();
+        match signal::foreground() {
+            Some(id) if byte == CTRL_C => {
+                let _ = SCHEDULER.kill(id, SIGINT);
+            }
+            _ => {
+                RX.push(byte);
+                SCHEDULER.wake(rx_event());
+            }
+        }
+    })
+}
+
 impl io::Write for Console {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner().write(buf)
+        let n = self.inner().write(buf)?;
+        copy_to_sinks(&buf[..n]);
+        Ok(n)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -60,29 +203,188 @@ impl io::Write for Console {
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.inner().write_str(s)
+        self.inner().write_str(s)?;
+        copy_to_sinks(s.as_bytes());
+        Ok(())
     }
 }
 
 /// Global `Console` singleton.
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
 
+/// An `io::Write` handle to `CONSOLE`.
+///
+/// The console is locked for the duration of each write. As with `kprint!`,
+/// each `\n` written is sent as `\r\n`.
+#[derive(Debug, Default)]
+pub struct ConsoleWriter;
+
+impl io::Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut console = CONSOLE.lock();
+        for &byte in buf {
+            if byte == b'\n' {
+                console.write_byte(b'\r');
+            }
+            console.write_byte(byte);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Waits for console input to arrive, or for `deadline` to pass if there is
+/// one, without holding `CONSOLE`, so that other cores can write to the
+/// console meanwhile. Returns `false` if the deadline has passed.
+fn wait_for_input(deadline: Option<Duration>) -> bool {
+    let now = pi::timer::current_time();
+    let timeout = match deadline {
+        Some(d) if now >= d => return false,
+        Some(d) => Some(d - now),
+        None => None,
+    };
+
+    if CONSOLE.lock().buffered {
+        process::block_until(rx_event(), timeout, || !RX.is_empty());
+    } else {
+        core::hint::spin_loop();
+    }
+    true
+}
+
+/// Reads a byte of console input, blocking until one is available. Once
+/// input is interrupt-driven, other processes run while it waits, or the
+/// core idles if none can. The console is locked only to take the byte.
+pub fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = CONSOLE.lock().try_read_byte() {
+            return byte;
+        }
+        wait_for_input(None);
+    }
+}
+
+/// An `io::Read` and `io::Write` handle to `CONSOLE` for binary transfers.
+///
+/// Bytes are written as they are, with `CONSOLE` locked for the duration of
+/// each write. A read waits for input without holding the console lock, then
+/// takes whatever has arrived.
+#[derive(Debug, Default)]
+pub struct RawConsole {
+    /// How long reads wait for a first byte.
+    timeout: Option<Duration>,
+}
+
+impl RawConsole {
+    /// Returns a new handle, whose reads block until a byte arrives.
+    pub fn new() -> RawConsole {
+        RawConsole::default()
+    }
+
+    /// Sets how long reads wait for the first byte before failing with
+    /// `TimedOut`. With `None`, reads block until a byte arrives.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+impl io::Read for RawConsole {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Wait at most the timeout for the first byte, then take whatever
+        // else has arrived.
+        let deadline = self.timeout.map(|t| pi::timer::current_time() + t);
+        loop {
+            let mut console = CONSOLE.lock();
+            let mut n = 0;
+            while n < buf.len() {
+                match console.try_read_byte() {
+                    Some(byte) => buf[n] = byte,
+                    None => break,
+                }
+                n += 1;
+            }
+            drop(console);
+
+            if n > 0 {
+                return Ok(n);
+            } else if !wait_for_input(deadline) {
+                return ioerr!(TimedOut, "Timed out waiting for first byte");
+            }
+        }
+    }
+}
+
+impl io::Write for RawConsole {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(&mut *CONSOLE.lock(), buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Internal function called by the `kprint[ln]!` macros.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    #[cfg(not(test))]
+    #[cfg(target_os = "none")]
     {
         use core::fmt::Write;
         let mut console = CONSOLE.lock();
         console.write_fmt(args).unwrap();
     }
 
-    #[cfg(test)]
+    #[cfg(not(target_os = "none"))]
     {
         print!("{}", args);
     }
 }
 
+/// Internal function called by the `kprint[ln]_nolock!` macros.
+///
+/// The message is formatted into a fixed-size stack buffer and written to a
+/// fresh handle to the UART, bypassing `CONSOLE` entirely, then to the log
+/// unless another core is writing to it, and to the sinks unless another
+/// core is adding one. Output longer than `NOLOCK_BUF_SIZE` bytes is
+/// truncated and marked as such.
+#[doc(hidden)]
+pub fn _print_nolock(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    let mut buf = [0u8; NOLOCK_BUF_SIZE];
+    let mut writer = BoundedWriter::new(&mut buf);
+    let _ = writer.write_fmt(args);
+    let marker = if writer.is_truncated() { "[...]\n" } else { "" };
+
+    #[cfg(target_os = "none")]
+    {
+        let mut uart = MiniUart::new();
+        let _ = uart.write_str(writer.as_str());
+        let _ = uart.write_str(marker);
+        log::record_nolock(writer.as_bytes());
+        log::record_nolock(marker.as_bytes());
+
+        if let Some(sinks) = SINKS.try_lock().map(|sinks| *sinks) {
+            for sink in sinks.iter().flatten() {
+                (sink.write_nolock)(writer.as_bytes());
+                (sink.write_nolock)(marker.as_bytes());
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "none"))]
+    {
+        print!("{}{}", writer.as_str(), marker);
+    }
+}
+
 /// Like `println!`, but for kernel-space.
 pub macro kprintln {
     () => (kprint!("\n")),
@@ -94,3 +396,18 @@ pub macro kprintln {
 pub macro kprint($($arg:tt)*) {
     _print(format_args!($($arg)*))
 }
+
+/// Like `kprintln!`, but never acquires the `CONSOLE` lock and never allocates.
+///
+/// Use this only where the lock may already be held, such as in the panic or
+/// exception handlers: output may interleave with a concurrent `kprint!`.
+pub macro kprintln_nolock {
+    () => (kprint_nolock!("\n")),
+    ($fmt:expr) => (kprint_nolock!(concat!($fmt, "\n"))),
+    ($fmt:expr, $($arg:tt)*) => (kprint_nolock!(concat!($fmt, "\n"), $($arg)*))
+}
+
+/// Like `kprint!`, but never acquires the `CONSOLE` lock and never allocates.
+pub macro kprint_nolock($($arg:tt)*) {
+    _print_nolock(format_args!($($arg)*))
+}
@@ -0,0 +1,69 @@
+use core::fmt;
+
+use stack_vec::StackVec;
+
+/// A `fmt::Write` sink backed by a caller-supplied, fixed-size buffer.
+///
+/// Writing to a `BoundedWriter` never allocates. Once the buffer is full, any
+/// further output is silently dropped and the writer is marked as truncated.
+/// This makes it suitable for formatting messages in contexts where the heap
+/// may be unusable, such as the panic handler or an exception handler.
+pub struct BoundedWriter<'a> {
+    buf: StackVec<'a, u8>,
+    truncated: bool,
+}
+
+impl<'a> BoundedWriter<'a> {
+    /// Returns a new, empty `BoundedWriter` using `storage` as the backing
+    /// store.
+    pub fn new(storage: &'a mut [u8]) -> BoundedWriter<'a> {
+        BoundedWriter {
+            buf: StackVec::new(storage),
+            truncated: false,
+        }
+    }
+
+    /// Returns the bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.as_slice()
+    }
+
+    /// Returns the text written so far.
+    ///
+    /// If output was truncated in the middle of a multi-byte character, the
+    /// incomplete character is omitted.
+    pub fn as_str(&self) -> &str {
+        match core::str::from_utf8(self.as_bytes()) {
+            Ok(s) => s,
+            Err(e) => {
+                let valid = &self.as_bytes()[..e.valid_up_to()];
+                // `valid_up_to()` guarantees this prefix is valid UTF-8
+                unsafe { core::str::from_utf8_unchecked(valid) }
+            }
+        }
+    }
+
+    /// Returns `true` if some output was dropped because the buffer was full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Discards everything written so far, allowing the buffer to be reused.
+    pub fn clear(&mut self) {
+        self.buf.truncate(0);
+        self.truncated = false;
+    }
+}
+
+impl<'a> fmt::Write for BoundedWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let written = self.buf.extend_from_slice(s.as_bytes()).unwrap_or(0);
+        if written < s.len() {
+            self.truncated = true;
+        }
+
+        // Never report an error: callers would rather see a truncated message
+        // than have formatting abort halfway through.
+        Ok(())
+    }
+}
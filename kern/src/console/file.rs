@@ -0,0 +1,192 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use shim::io;
+use shim::ioerr;
+
+use crate::process;
+use crate::SCHEDULER;
+
+use super::{ConsoleWriter, CONSOLE};
+
+/// The longest line that can be typed at a `ConsoleFile`, including its
+/// `\n`.
+pub const MAX_LINE_LEN: usize = 1024;
+
+/// ASCII control codes understood by `ConsoleFile`.
+mod ctrl {
+    pub const D: u8 = 0x04;
+    pub const BELL: u8 = 0x07;
+    pub const BACKSPACE: u8 = 0x08;
+    pub const TAB: u8 = 0x09;
+    pub const DEL: u8 = 0x7f;
+}
+
+/// What a byte typed at the console did to the line being edited.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Edit {
+    /// The line is still being edited.
+    Editing,
+    /// The line was entered, and can be read.
+    Entered,
+    /// `Ctrl-D` was typed on an empty line: the end of the input.
+    EndOfFile,
+}
+
+/// The console as a file: what a user program's standard input, output and
+/// error refer to.
+///
+/// Input is line buffered. What is typed is echoed, and `Backspace` erases
+/// the last byte; a read waits for a whole line to be entered and returns it,
+/// `\n` included, over as many reads as it takes. `Ctrl-D` enters the line
+/// without a `\n`, so on an empty line it reads as the end of the file. A
+/// read waiting for input fails with `Interrupted` once a signal is sent to
+/// the reader. Output is written straight to the console, each `\n` as `\r\n`.
+#[derive(Debug, Default)]
+pub struct ConsoleFile {
+    /// The line being edited.
+    line: Vec<u8>,
+    /// What has been entered and not yet read.
+    entered: VecDeque<u8>,
+}
+
+impl ConsoleFile {
+    /// Returns the console, with nothing typed yet.
+    pub fn new() -> ConsoleFile {
+        ConsoleFile::default()
+    }
+
+    /// Processes the byte `byte` typed at the console, writing its echo to
+    /// `out`.
+    fn edit<W: Write>(&mut self, byte: u8, out: &mut W) -> Edit {
+        match byte {
+            b'\r' | b'\n' => {
+                let _ = out.write_str("\n");
+                self.line.push(b'\n');
+                self.enter()
+            }
+            ctrl::D if self.line.is_empty() => Edit::EndOfFile,
+            ctrl::D => self.enter(),
+            ctrl::BACKSPACE | ctrl::DEL if !self.line.is_empty() => {
+                self.line.pop();
+                let _ = out.write_str("\x08 \x08");
+                Edit::Editing
+            }
+            b if (b.is_ascii_graphic() || b == b' ' || b == ctrl::TAB)
+                && self.line.len() < MAX_LINE_LEN - 1 =>
+            {
+                self.line.push(b);
+                let _ = out.write_char(b as char);
+                Edit::Editing
+            }
+            _ => {
+                let _ = out.write_char(ctrl::BELL as char);
+                Edit::Editing
+            }
+        }
+    }
+
+    /// Makes the line being edited available to read.
+    fn enter(&mut self) -> Edit {
+        self.entered.extend(self.line.drain(..));
+        Edit::Entered
+    }
+}
+
+impl io::Read for ConsoleFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while self.entered.is_empty() {
+            let byte = match CONSOLE.lock().try_read_byte() {
+                Some(byte) => byte,
+                None if SCHEDULER.signal_pending() => {
+                    return ioerr!(Interrupted, "interrupted by a signal");
+                }
+                None => {
+                    process::yield_now();
+                    continue;
+                }
+            };
+            let mut console = CONSOLE.lock();
+            if self.edit(byte, &mut *console) == Edit::EndOfFile {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.entered.len());
+        for (dst, src) in buf.iter_mut().zip(self.entered.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for ConsoleFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(&mut ConsoleWriter, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use super::{ConsoleFile, Edit, MAX_LINE_LEN};
+
+    fn feed(file: &mut ConsoleFile, input: &[u8]) -> (String, Edit) {
+        let mut out = String::new();
+        let mut edit = Edit::Editing;
+        for &byte in input {
+            edit = file.edit(byte, &mut out);
+        }
+        (out, edit)
+    }
+
+    fn entered(file: &mut ConsoleFile) -> Vec<u8> {
+        file.entered.drain(..).collect()
+    }
+
+    #[test]
+    fn lines_are_echoed_and_edited() {
+        let mut file = ConsoleFile::new();
+        let (out, edit) = feed(&mut file, b"cat\x7f\x7fd");
+        assert_eq!(out, "cat\x08 \x08\x08 \x08d");
+        assert_eq!(edit, Edit::Editing);
+        assert!(file.entered.is_empty());
+
+        assert_eq!(feed(&mut file, b"\r"), (String::from("\n"), Edit::Entered));
+        assert_eq!(entered(&mut file), b"cd\n");
+
+        // Backspace on an empty line and unknown control codes ring the bell.
+        assert_eq!(feed(&mut file, b"\x08\x01").0, "\x07\x07");
+    }
+
+    #[test]
+    fn end_of_file() {
+        let mut file = ConsoleFile::new();
+        assert_eq!(feed(&mut file, b"\x04"), (String::new(), Edit::EndOfFile));
+
+        assert_eq!(feed(&mut file, b"ab\x04").1, Edit::Entered);
+        assert_eq!(entered(&mut file), b"ab");
+    }
+
+    #[test]
+    fn long_lines() {
+        let mut file = ConsoleFile::new();
+        let long = [b'x'; MAX_LINE_LEN + 1];
+        let (out, _) = feed(&mut file, &long);
+        assert!(out.ends_with("x\x07\x07"));
+
+        feed(&mut file, b"\n");
+        assert_eq!(entered(&mut file).len(), MAX_LINE_LEN);
+    }
+}
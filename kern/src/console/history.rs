@@ -0,0 +1,75 @@
+/// The number of lines remembered by a `History`.
+pub const HISTORY_DEPTH: usize = 16;
+
+/// The longest line a `History` can remember. Longer lines are not recorded.
+pub const HISTORY_LINE_LEN: usize = 512;
+
+/// A fixed-depth record of previously entered lines.
+///
+/// Once `HISTORY_DEPTH` lines have been recorded, recording a new line
+/// forgets the oldest one. `History` never allocates.
+pub struct History {
+    lines: [[u8; HISTORY_LINE_LEN]; HISTORY_DEPTH],
+    lens: [usize; HISTORY_DEPTH],
+    /// The slot the next recorded line will be written to.
+    next: usize,
+    /// The number of slots holding a line.
+    count: usize,
+}
+
+impl History {
+    /// Returns an empty history.
+    pub const fn new() -> History {
+        History {
+            lines: [[0; HISTORY_LINE_LEN]; HISTORY_DEPTH],
+            lens: [0; HISTORY_DEPTH],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    /// Returns the number of lines currently remembered.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns `true` if no lines are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Records `line` as the most recent entry.
+    ///
+    /// Lines that are empty, too long to store, or identical to the most
+    /// recent entry are not recorded.
+    pub fn push(&mut self, line: &[u8]) {
+        if line.is_empty() || line.len() > HISTORY_LINE_LEN || self.get(0) == Some(line) {
+            return;
+        }
+
+        self.lines[self.next][..line.len()].copy_from_slice(line);
+        self.lens[self.next] = line.len();
+        self.next = (self.next + 1) % HISTORY_DEPTH;
+        self.count = (self.count + 1).min(HISTORY_DEPTH);
+    }
+
+    /// Returns the line recorded `age` entries ago, where `0` is the most
+    /// recent entry, or `None` if there is no such line.
+    pub fn get(&self, age: usize) -> Option<&[u8]> {
+        if age >= self.count {
+            return None;
+        }
+
+        let slot = (self.next + HISTORY_DEPTH - 1 - age) % HISTORY_DEPTH;
+        Some(&self.lines[slot][..self.lens[slot]])
+    }
+
+    /// Returns the age of the most recent entry at least `from` entries old
+    /// that contains `query`, or `None` if no such entry exists.
+    pub fn search(&self, query: &[u8], from: usize) -> Option<usize> {
+        (from..self.count).find(|&age| {
+            let line = self.get(age).unwrap_or(&[]);
+            query.is_empty() || line.windows(query.len()).any(|w| w == query)
+        })
+    }
+}
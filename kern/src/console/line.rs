@@ -0,0 +1,825 @@
+use core::fmt::{self, Write};
+use core::str::Utf8Error;
+
+use stack_vec::StackVec;
+
+use crate::console::history::{History, HISTORY_LINE_LEN};
+use crate::console::{self, CONSOLE};
+
+/// The longest completion candidate that `LineEditor` will consider.
+const MAX_COMPLETION_LEN: usize = 128;
+
+/// The terminal width assumed when listing completion candidates.
+const LIST_WIDTH: usize = 80;
+
+/// The longest query accepted by reverse history search.
+const MAX_SEARCH_LEN: usize = 64;
+
+/// The prompt shown while searching history.
+const SEARCH_PROMPT: &str = "(reverse-i-search)'";
+
+/// ASCII control codes understood by `LineEditor`.
+mod ctrl {
+    pub const A: u8 = 0x01;
+    pub const B: u8 = 0x02;
+    pub const D: u8 = 0x04;
+    pub const E: u8 = 0x05;
+    pub const F: u8 = 0x06;
+    pub const G: u8 = 0x07;
+    pub const N: u8 = 0x0e;
+    pub const P: u8 = 0x10;
+    pub const R: u8 = 0x12;
+    pub const U: u8 = 0x15;
+    pub const W: u8 = 0x17;
+    pub const BELL: u8 = 0x07;
+    pub const BACKSPACE: u8 = 0x08;
+    pub const TAB: u8 = 0x09;
+    pub const ESC: u8 = 0x1b;
+    pub const DEL: u8 = 0x7f;
+}
+
+/// Progress through an ANSI escape sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Escape {
+    /// Not in an escape sequence.
+    None,
+    /// Received `ESC`.
+    Esc,
+    /// Received `ESC [`, the control sequence introducer, followed by digits
+    /// forming the numeric parameter `.0`.
+    Csi(u8),
+    /// Received `ESC O`, which some terminals send before `H` and `F`.
+    Ss3,
+}
+
+/// The state of an in-progress reverse history search.
+struct Search {
+    query: [u8; MAX_SEARCH_LEN],
+    len: usize,
+    /// The age of the history entry currently matched, if any.
+    found: Option<usize>,
+    /// The number of characters drawn on the terminal for the search.
+    drawn: usize,
+}
+
+/// A source of completions for the word being typed.
+pub trait Completer {
+    /// Calls `f` once with every possible completion of `word`, the partial
+    /// word immediately before the cursor. `line` is the entire line up to the
+    /// cursor, so implementations can tell which argument is being completed.
+    ///
+    /// Candidates must be full replacements for `word`; candidates that do not
+    /// start with `word` are ignored.
+    fn complete(&mut self, line: &str, word: &str, f: &mut dyn FnMut(&str));
+}
+
+/// The state of a line after `LineEditor::handle_byte()` processes a byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// The line is still being edited.
+    Editing,
+    /// The user submitted the line by pressing enter.
+    Submitted,
+}
+
+/// An interactive, allocation-free line editor.
+///
+/// `LineEditor` turns raw console bytes into a line of text, echoing and
+/// redrawing as needed. In addition to appending and backspacing, it supports:
+///
+///   * `Home` / `End` (or `Ctrl-A` / `Ctrl-E`): move to the start / end of
+///     the line
+///   * `Left` / `Right` (or `Ctrl-B` / `Ctrl-F`): move one character left /
+///     right
+///   * `Delete` (or `Ctrl-D`): delete the character under the cursor
+///   * `Insert`: toggle between insert and overwrite mode
+///   * `Ctrl-U`: delete everything before the cursor
+///   * `Ctrl-W`: delete the word before the cursor
+///   * `Tab`: complete the word before the cursor using a `Completer`
+///   * `Up` / `Down` (or `Ctrl-P` / `Ctrl-N`): recall older / newer lines
+///     from a `History`
+///   * `Ctrl-R`: search the `History` backwards for a line containing the
+///     typed text; `Ctrl-R` again finds the next older match, `Enter` runs the
+///     match, `Ctrl-G` cancels, and any other key accepts the match for editing
+///
+/// Characters typed with the cursor mid-line are inserted at the cursor unless
+/// overwrite mode is on, in which case they replace the character under it.
+pub struct LineEditor<'a> {
+    buf: StackVec<'a, u8>,
+    cursor: usize,
+    completer: Option<&'a mut dyn Completer>,
+    prompt: &'a str,
+    history: Option<&'a mut History>,
+    /// The age of the history entry being shown, or `None` for a new line.
+    recalled: Option<usize>,
+    escape: Escape,
+    search: Option<Search>,
+    /// Whether the previous byte handled was a `Tab`.
+    tabbed: bool,
+    /// Whether typed characters replace, rather than push aside, the
+    /// characters under the cursor.
+    overwrite: bool,
+}
+
+impl<'a> LineEditor<'a> {
+    /// Returns a new line editor which stores input in `storage`. Lines can be
+    /// at most `storage.len()` bytes long.
+    pub fn new(storage: &'a mut [u8]) -> LineEditor<'a> {
+        LineEditor {
+            buf: StackVec::new(storage),
+            cursor: 0,
+            completer: None,
+            prompt: "",
+            history: None,
+            recalled: None,
+            escape: Escape::None,
+            search: None,
+            tabbed: false,
+            overwrite: false,
+        }
+    }
+
+    /// Sets the completer consulted when the user presses `Tab`.
+    pub fn set_completer(&mut self, completer: &'a mut dyn Completer) {
+        self.completer = Some(completer);
+    }
+
+    /// Sets the prompt printed by `read_line()` before each line. The prompt
+    /// is redrawn whenever the line has to be redrawn from scratch.
+    pub fn set_prompt(&mut self, prompt: &'a str) {
+        self.prompt = prompt;
+    }
+
+    /// Sets the history that submitted lines are recorded in and recalled
+    /// from.
+    pub fn set_history(&mut self, history: &'a mut History) {
+        self.history = Some(history);
+    }
+
+    /// Returns the line entered so far.
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        core::str::from_utf8(self.buf.as_slice())
+    }
+
+    /// Returns the cursor's position as a byte offset into the line.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Discards the current line and resets the cursor.
+    pub fn clear(&mut self) {
+        self.buf.truncate(0);
+        self.cursor = 0;
+        self.recalled = None;
+        self.escape = Escape::None;
+        self.search = None;
+    }
+
+    /// Prints the prompt, then reads a full line from `CONSOLE`, echoing and
+    /// editing as the user types, and returns it once the user presses enter.
+    pub fn read_line(&mut self) -> Result<&str, Utf8Error> {
+        self.clear();
+        let _ = CONSOLE.lock().write_str(self.prompt);
+
+        loop {
+            let byte = console::read_byte();
+            let mut console = CONSOLE.lock();
+            if self.handle_byte(byte, &mut *console) == Status::Submitted {
+                break;
+            }
+        }
+
+        self.as_str()
+    }
+
+    /// Processes the input byte `byte`, writing any required echo or redraw
+    /// sequences to `out`.
+    pub fn handle_byte<W: Write>(&mut self, byte: u8, out: &mut W) -> Status {
+        let tabbed = core::mem::replace(&mut self.tabbed, byte == ctrl::TAB);
+        let result = match (self.escape, self.search.is_some()) {
+            (Escape::None, true) => return self.handle_search_byte(byte, out),
+            (Escape::None, false) => return self.handle_plain_byte(byte, out, tabbed),
+            (Escape::Esc, _) if byte == b'[' || byte == b'O' => {
+                self.escape = if byte == b'[' { Escape::Csi(0) } else { Escape::Ss3 };
+                return Status::Editing;
+            }
+            (Escape::Csi(param), _) if byte.is_ascii_digit() => {
+                let param = param.saturating_mul(10).saturating_add(byte - b'0');
+                self.escape = Escape::Csi(param);
+                return Status::Editing;
+            }
+            (Escape::Esc, _) => Err(fmt::Error),
+            (Escape::Csi(param), _) => self.handle_csi(param, byte, out),
+            (Escape::Ss3, _) => self.handle_csi(0, byte, out),
+        };
+
+        self.escape = Escape::None;
+        if result.is_err() {
+            bell(out);
+        }
+
+        Status::Editing
+    }
+
+    /// Processes a byte that is not part of an escape sequence while editing.
+    fn handle_plain_byte<W: Write>(&mut self, byte: u8, out: &mut W, tabbed: bool) -> Status {
+        let result = match byte {
+            b'\r' | b'\n' => {
+                let _ = out.write_str("\n");
+                if let Some(history) = self.history.as_mut() {
+                    history.push(self.buf.as_slice());
+                }
+                return Status::Submitted;
+            }
+            b if b.is_ascii_graphic() || b == b' ' => self.type_byte(b, out),
+            ctrl::BACKSPACE | ctrl::DEL if self.cursor > 0 => {
+                self.remove(self.cursor - 1, self.cursor, out)
+            }
+            ctrl::D if self.cursor < self.buf.len() => self.remove(self.cursor, self.cursor + 1, out),
+            ctrl::A => self.move_to(0, out),
+            ctrl::E => self.move_to(self.buf.len(), out),
+            ctrl::B if self.cursor > 0 => self.move_to(self.cursor - 1, out),
+            ctrl::F if self.cursor < self.buf.len() => self.move_to(self.cursor + 1, out),
+            ctrl::U if self.cursor > 0 => self.remove(0, self.cursor, out),
+            ctrl::W if self.cursor > 0 => self.remove(self.prev_word_start(), self.cursor, out),
+            ctrl::P => self.recall_older(out),
+            ctrl::N => self.recall_newer(out),
+            ctrl::R => self.start_search(out),
+            ctrl::TAB => self.complete(out, tabbed),
+            ctrl::ESC => {
+                self.escape = Escape::Esc;
+                Ok(())
+            }
+            _ => Err(fmt::Error),
+        };
+
+        if result.is_err() {
+            bell(out);
+        }
+
+        Status::Editing
+    }
+
+    /// Processes the final byte, `byte`, of the control sequence with numeric
+    /// parameter `param`. Both the VT100 (`ESC [ H`) and VT220 (`ESC [ 1 ~`)
+    /// forms of the editing keys are understood.
+    fn handle_csi<W: Write>(&mut self, param: u8, byte: u8, out: &mut W) -> fmt::Result {
+        let len = self.buf.len();
+        match (byte, param) {
+            (b'A', _) => self.recall_older(out),
+            (b'B', _) => self.recall_newer(out),
+            (b'C', _) if self.cursor < len => self.move_to(self.cursor + 1, out),
+            (b'D', _) if self.cursor > 0 => self.move_to(self.cursor - 1, out),
+            (b'H', _) | (b'~', 1) | (b'~', 7) => self.move_to(0, out),
+            (b'F', _) | (b'~', 4) | (b'~', 8) => self.move_to(len, out),
+            (b'~', 3) if self.cursor < len => self.remove(self.cursor, self.cursor + 1, out),
+            (b'~', 2) => {
+                self.overwrite = !self.overwrite;
+                Ok(())
+            }
+            _ => Err(fmt::Error),
+        }
+    }
+
+    /// Replaces the line with the next older history entry.
+    fn recall_older<W: Write>(&mut self, out: &mut W) -> fmt::Result {
+        let age = self.recalled.map_or(0, |age| age + 1);
+        self.recall(Some(age), out)
+    }
+
+    /// Replaces the line with the next newer history entry, or with an empty
+    /// line when moving past the newest entry.
+    fn recall_newer<W: Write>(&mut self, out: &mut W) -> fmt::Result {
+        match self.recalled {
+            Some(0) => self.recall(None, out),
+            Some(age) => self.recall(Some(age - 1), out),
+            None => Err(fmt::Error),
+        }
+    }
+
+    /// Replaces the line with the history entry `age` entries old, or with an
+    /// empty line if `age` is `None`.
+    fn recall<W: Write>(&mut self, age: Option<usize>, out: &mut W) -> fmt::Result {
+        let mut line = [0u8; HISTORY_LINE_LEN];
+        let len = match age {
+            Some(age) => {
+                let history = self.history.as_ref().ok_or(fmt::Error)?;
+                let entry = history.get(age).ok_or(fmt::Error)?;
+                let len = entry.len().min(line.len());
+                line[..len].copy_from_slice(&entry[..len]);
+                len
+            }
+            None => 0,
+        };
+
+        self.replace(&line[..len], out)?;
+        self.recalled = age;
+        Ok(())
+    }
+
+    /// Replaces the entire line with `line`, leaving the cursor at its end.
+    /// If `line` is longer than the editor's capacity, it is truncated.
+    fn replace<W: Write>(&mut self, line: &[u8], out: &mut W) -> fmt::Result {
+        let old_len = self.buf.len();
+        erase(out, self.cursor, old_len)?;
+
+        self.buf.truncate(0);
+        self.buf.extend(line);
+
+        self.cursor = self.buf.len();
+        write_bytes(out, &self.buf)
+    }
+
+    /// Enters reverse history search mode.
+    fn start_search<W: Write>(&mut self, out: &mut W) -> fmt::Result {
+        if self.history.as_ref().map_or(true, |h| h.is_empty()) {
+            return Err(fmt::Error);
+        }
+
+        erase(out, self.cursor, self.buf.len())?;
+        self.search = Some(Search {
+            query: [0; MAX_SEARCH_LEN],
+            len: 0,
+            found: None,
+            drawn: 0,
+        });
+
+        self.draw_search(out)
+    }
+
+    /// Processes a byte while in reverse history search mode.
+    fn handle_search_byte<W: Write>(&mut self, byte: u8, out: &mut W) -> Status {
+        let result = match byte {
+            b if b.is_ascii_graphic() || b == b' ' => self.push_search_byte(b, out),
+            ctrl::BACKSPACE | ctrl::DEL => self.pop_search_byte(out),
+            ctrl::R => self.search_older(out),
+            ctrl::G => self.end_search(false, out),
+            _ => {
+                // Any other key accepts the match and is then handled as if
+                // it had been pressed while editing.
+                let _ = self.end_search(true, out);
+                return self.handle_byte(byte, out);
+            }
+        };
+
+        if result.is_err() {
+            bell(out);
+        }
+
+        Status::Editing
+    }
+
+    /// Appends `byte` to the search query and searches again from the
+    /// current match.
+    fn push_search_byte<W: Write>(&mut self, byte: u8, out: &mut W) -> fmt::Result {
+        let search = self.search.as_mut().ok_or(fmt::Error)?;
+        if search.len == MAX_SEARCH_LEN {
+            return Err(fmt::Error);
+        }
+
+        search.query[search.len] = byte;
+        search.len += 1;
+        let from = search.found.unwrap_or(0);
+        self.search_from(from, out)
+    }
+
+    /// Removes the last byte of the search query and searches again from the
+    /// most recent entry.
+    fn pop_search_byte<W: Write>(&mut self, out: &mut W) -> fmt::Result {
+        let search = self.search.as_mut().ok_or(fmt::Error)?;
+        if search.len == 0 {
+            return Err(fmt::Error);
+        }
+
+        search.len -= 1;
+        self.search_from(0, out)
+    }
+
+    /// Searches for the next older entry matching the current query.
+    fn search_older<W: Write>(&mut self, out: &mut W) -> fmt::Result {
+        let from = self.search.as_ref().and_then(|s| s.found).map_or(0, |age| age + 1);
+        self.search_from(from, out)
+    }
+
+    /// Searches history for the current query starting `from` entries ago and
+    /// redraws the search. Returns an error, keeping the previous match, if
+    /// nothing matches.
+    fn search_from<W: Write>(&mut self, from: usize, out: &mut W) -> fmt::Result {
+        let search = self.search.as_mut().ok_or(fmt::Error)?;
+        let history = self.history.as_ref().ok_or(fmt::Error)?;
+        let found = history.search(&search.query[..search.len], from);
+        if found.is_some() {
+            search.found = found;
+        }
+
+        self.draw_search(out)?;
+        found.map(|_| ()).ok_or(fmt::Error)
+    }
+
+    /// Redraws the search prompt, query, and current match.
+    fn draw_search<W: Write>(&mut self, out: &mut W) -> fmt::Result {
+        let search = self.search.as_mut().ok_or(fmt::Error)?;
+        let history = self.history.as_ref().ok_or(fmt::Error)?;
+        let found = search.found.and_then(|age| history.get(age)).unwrap_or(&[]);
+
+        erase(out, search.drawn, search.drawn)?;
+        out.write_str(SEARCH_PROMPT)?;
+        write_bytes(out, &search.query[..search.len])?;
+        out.write_str("': ")?;
+        write_bytes(out, found)?;
+
+        search.drawn = SEARCH_PROMPT.len() + search.len + 3 + found.len();
+        Ok(())
+    }
+
+    /// Leaves search mode. If `accept` is `true`, the line is replaced with
+    /// the current match. Otherwise, the line is restored as it was.
+    fn end_search<W: Write>(&mut self, accept: bool, out: &mut W) -> fmt::Result {
+        let search = self.search.take().ok_or(fmt::Error)?;
+        erase(out, search.drawn, search.drawn)?;
+
+        // Draw the line as it was before the search began, then swap in the
+        // match if there is one.
+        write_bytes(out, &self.buf)?;
+        back(out, self.buf.len() - self.cursor)?;
+        match search.found {
+            Some(age) if accept => self.recall(Some(age), out),
+            _ => Ok(()),
+        }
+    }
+
+    /// Enters the typed character `byte`, either inserting it at the cursor or,
+    /// in overwrite mode, replacing the character under the cursor.
+    fn type_byte<W: Write>(&mut self, byte: u8, out: &mut W) -> fmt::Result {
+        if !self.overwrite || self.cursor == self.buf.len() {
+            return self.insert(byte, out);
+        }
+
+        self.buf[self.cursor] = byte;
+        self.cursor += 1;
+        out.write_char(byte as char)
+    }
+
+    /// Inserts `byte` at the cursor and redraws the rest of the line.
+    fn insert<W: Write>(&mut self, byte: u8, out: &mut W) -> fmt::Result {
+        self.buf.insert(self.cursor, byte).map_err(|_| fmt::Error)?;
+
+        write_bytes(out, &self.buf[self.cursor..])?;
+        self.cursor += 1;
+        back(out, self.buf.len() - self.cursor)
+    }
+
+    /// Removes the bytes in `[start, end)`, where `start <= cursor <= end`,
+    /// leaving the cursor at `start`, and redraws the rest of the line.
+    fn remove<W: Write>(&mut self, start: usize, end: usize, out: &mut W) -> fmt::Result {
+        let removed = end - start;
+
+        back(out, self.cursor - start)?;
+        self.buf.drain(start..end);
+        self.cursor = start;
+
+        // Redraw the tail, blank out the now-stale characters after it, and
+        // return the terminal's cursor to where ours is.
+        write_bytes(out, &self.buf[start..])?;
+        for _ in 0..removed {
+            out.write_char(' ')?;
+        }
+        back(out, self.buf.len() - start + removed)
+    }
+
+    /// Moves the cursor to `pos`, which must be within the line.
+    fn move_to<W: Write>(&mut self, pos: usize, out: &mut W) -> fmt::Result {
+        if pos < self.cursor {
+            back(out, self.cursor - pos)?;
+        } else {
+            write_bytes(out, &self.buf[self.cursor..pos])?;
+        }
+
+        self.cursor = pos;
+        Ok(())
+    }
+
+    /// Returns the index of the start of the word immediately before the
+    /// cursor, skipping any spaces between it and the cursor.
+    fn prev_word_start(&self) -> usize {
+        let before = &self.buf[..self.cursor];
+        let end = before.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+        before[..end].iter().rposition(|&b| b == b' ').map_or(0, |i| i + 1)
+    }
+
+    /// Completes the word before the cursor as far as is unambiguous. If there
+    /// is exactly one candidate, a space is added after it unless it ends in
+    /// `/`. If no progress can be made, rings the bell, or, if the previous key
+    /// was also a `Tab`, lists every candidate. Returns an error if there is no
+    /// completer or there are no candidates.
+    fn complete<W: Write>(&mut self, out: &mut W, tabbed: bool) -> fmt::Result {
+        // The completer is taken out while it is consulted, so that the line
+        // can be edited meanwhile.
+        let completer = self.completer.take().ok_or(fmt::Error)?;
+        let result = self.complete_with(out, &mut *completer, tabbed);
+        self.completer = Some(completer);
+        result
+    }
+
+    /// Does the work of `complete()`, consulting `completer`.
+    fn complete_with<W: Write>(
+        &mut self,
+        out: &mut W,
+        completer: &mut dyn Completer,
+        tabbed: bool,
+    ) -> fmt::Result {
+        let word_start = self.current_word_start();
+        let word_len = self.cursor - word_start;
+        let line = core::str::from_utf8(&self.buf[..self.cursor]).map_err(|_| fmt::Error)?;
+        let word = &line[word_start..];
+
+        let mut common = [0u8; MAX_COMPLETION_LEN];
+        let mut common_len = 0;
+        let mut count = 0;
+        completer.complete(line, word, &mut |candidate| {
+            let candidate = candidate.as_bytes();
+            if !candidate.starts_with(word.as_bytes()) || candidate.len() > common.len() {
+                return;
+            }
+
+            if count == 0 {
+                common[..candidate.len()].copy_from_slice(candidate);
+                common_len = candidate.len();
+            } else {
+                common_len = common[..common_len]
+                    .iter()
+                    .zip(candidate)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+            }
+
+            count += 1;
+        });
+
+        if count == 0 {
+            return Err(fmt::Error);
+        }
+
+        if count > 1 && common_len == word_len {
+            if !tabbed {
+                return Err(fmt::Error);
+            }
+
+            return self.list_completions(out, completer);
+        }
+
+        for &byte in &common[word_len..common_len] {
+            self.insert(byte, out)?;
+        }
+
+        if count == 1 && common_len > 0 && common[common_len - 1] != b'/' {
+            self.insert(b' ', out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints every completion candidate for the word before the cursor on the
+    /// lines below the current one, then redraws the prompt and line.
+    fn list_completions<W: Write>(
+        &mut self,
+        out: &mut W,
+        completer: &mut dyn Completer,
+    ) -> fmt::Result {
+        let line = core::str::from_utf8(&self.buf[..self.cursor]).map_err(|_| fmt::Error)?;
+        let word = &line[self.current_word_start()..];
+
+        let mut column = 0;
+        let mut result = out.write_str("\n");
+        completer.complete(line, word, &mut |candidate| {
+            if !candidate.starts_with(word) {
+                return;
+            }
+
+            if column > 0 && column + candidate.len() > LIST_WIDTH {
+                result = result.and_then(|_| out.write_str("\n"));
+                column = 0;
+            }
+
+            result = result.and_then(|_| write!(out, "{}  ", candidate));
+            column += candidate.len() + 2;
+        });
+        result?;
+
+        out.write_str("\n")?;
+        out.write_str(self.prompt)?;
+        write_bytes(out, &self.buf)?;
+        back(out, self.buf.len() - self.cursor)
+    }
+
+    /// Returns the index of the start of the word the cursor is in or
+    /// immediately after.
+    fn current_word_start(&self) -> usize {
+        self.buf[..self.cursor]
+            .iter()
+            .rposition(|&b| b == b' ')
+            .map_or(0, |i| i + 1)
+    }
+}
+
+/// Writes `bytes`, which are known to be ASCII, to `out`.
+fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> fmt::Result {
+    for &b in bytes {
+        out.write_char(b as char)?;
+    }
+
+    Ok(())
+}
+
+/// Blanks out `width` characters drawn from the start of the line, where the
+/// terminal's cursor is `cursor` characters from the start, and leaves the
+/// terminal's cursor at the start of the line.
+fn erase<W: Write>(out: &mut W, cursor: usize, width: usize) -> fmt::Result {
+    back(out, cursor)?;
+    for _ in 0..width {
+        out.write_char(' ')?;
+    }
+    back(out, width)
+}
+
+/// Moves the terminal's cursor `n` characters to the left.
+fn back<W: Write>(out: &mut W, n: usize) -> fmt::Result {
+    for _ in 0..n {
+        out.write_char(ctrl::BACKSPACE as char)?;
+    }
+
+    Ok(())
+}
+
+/// Rings the terminal's bell.
+fn bell<W: Write>(out: &mut W) {
+    let _ = out.write_char(ctrl::BELL as char);
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{Completer, LineEditor, Status};
+    use crate::console::History;
+
+    fn feed(editor: &mut LineEditor, input: &[u8]) -> String {
+        let mut out = String::new();
+        for &byte in input {
+            editor.handle_byte(byte, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn append_and_submit() {
+        let mut storage = [0u8; 16];
+        let mut editor = LineEditor::new(&mut storage);
+        assert_eq!(feed(&mut editor, b"echo hi"), "echo hi");
+        assert_eq!(editor.handle_byte(b'\r', &mut String::new()), Status::Submitted);
+        assert_eq!(editor.as_str(), Ok("echo hi"));
+    }
+
+    #[test]
+    fn insert_and_delete_mid_line() {
+        let mut storage = [0u8; 16];
+        let mut editor = LineEditor::new(&mut storage);
+        feed(&mut editor, b"ac\x02b");
+        assert_eq!(editor.as_str(), Ok("abc"));
+        assert_eq!(editor.cursor(), 2);
+
+        feed(&mut editor, b"\x01\x04");
+        assert_eq!(editor.as_str(), Ok("bc"));
+        assert_eq!(editor.cursor(), 0);
+
+        feed(&mut editor, b"\x05\x7f");
+        assert_eq!(editor.as_str(), Ok("b"));
+    }
+
+    #[test]
+    fn kill_word_and_line() {
+        let mut storage = [0u8; 32];
+        let mut editor = LineEditor::new(&mut storage);
+        feed(&mut editor, b"cat /a/b  \x17");
+        assert_eq!(editor.as_str(), Ok("cat "));
+
+        feed(&mut editor, b"x\x02\x15");
+        assert_eq!(editor.as_str(), Ok("x"));
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn full_line_rings_bell() {
+        let mut storage = [0u8; 2];
+        let mut editor = LineEditor::new(&mut storage);
+        assert_eq!(feed(&mut editor, b"abc"), "ab\u{7}");
+        assert_eq!(editor.as_str(), Ok("ab"));
+    }
+
+    struct Words(&'static [&'static str]);
+
+    impl Completer for Words {
+        fn complete(&mut self, _line: &str, _word: &str, f: &mut dyn FnMut(&str)) {
+            self.0.iter().for_each(|w| f(w));
+        }
+    }
+
+    #[test]
+    fn completion() {
+        let mut completer = Words(&["echo", "exit", "ls", "lsatag"]);
+        let mut storage = [0u8; 32];
+        let mut editor = LineEditor::new(&mut storage);
+        editor.set_completer(&mut completer);
+        editor.set_prompt("> ");
+
+        feed(&mut editor, b"ec\t");
+        assert_eq!(editor.as_str(), Ok("echo "));
+
+        feed(&mut editor, b"\x15l\t");
+        assert_eq!(editor.as_str(), Ok("ls"));
+
+        assert!(feed(&mut editor, b"\x15e\t").ends_with("e\u{7}"));
+        assert_eq!(editor.as_str(), Ok("e"));
+
+        let listing = feed(&mut editor, b"\t");
+        assert_eq!(listing, "\necho  exit  \n> e");
+        assert_eq!(editor.as_str(), Ok("e"));
+    }
+
+    fn submit(editor: &mut LineEditor, line: &[u8]) {
+        editor.clear();
+        feed(editor, line);
+        assert_eq!(editor.handle_byte(b'\r', &mut String::new()), Status::Submitted);
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let mut storage = [0u8; 16];
+        let mut editor = LineEditor::new(&mut storage);
+        feed(&mut editor, b"bd\x1b[Dc\x1b[Ha\x1b[F!");
+        assert_eq!(editor.as_str(), Ok("abcd!"));
+
+        feed(&mut editor, b"\x1b[1~\x1b[C\x1b[3~");
+        assert_eq!(editor.as_str(), Ok("acd!"));
+        assert_eq!(editor.cursor(), 1);
+
+        feed(&mut editor, b"\x1bOF\x1b[D\x1b[D\x1b[2~xyz");
+        assert_eq!(editor.as_str(), Ok("acxyz"));
+
+        assert!(feed(&mut editor, b"\x1b[C").ends_with('\u{7}'));
+        assert!(feed(&mut editor, b"\x1b[9Z").ends_with('\u{7}'));
+    }
+
+    #[test]
+    fn history_recall() {
+        let mut history = History::new();
+        let mut storage = [0u8; 32];
+        let mut editor = LineEditor::new(&mut storage);
+        editor.set_history(&mut history);
+
+        submit(&mut editor, b"ls");
+        submit(&mut editor, b"cat /a/b/c");
+        submit(&mut editor, b"");
+        editor.clear();
+
+        feed(&mut editor, b"x\x1b[A");
+        assert_eq!(editor.as_str(), Ok("cat /a/b/c"));
+        feed(&mut editor, b"\x1b[A");
+        assert_eq!(editor.as_str(), Ok("ls"));
+        assert!(feed(&mut editor, b"\x1b[A").ends_with('\u{7}'));
+        assert_eq!(editor.as_str(), Ok("ls"));
+
+        feed(&mut editor, b"\x1b[B");
+        assert_eq!(editor.as_str(), Ok("cat /a/b/c"));
+        feed(&mut editor, b"\x1b[B");
+        assert_eq!(editor.as_str(), Ok(""));
+    }
+
+    #[test]
+    fn history_search() {
+        let mut history = History::new();
+        let mut storage = [0u8; 32];
+        let mut editor = LineEditor::new(&mut storage);
+        editor.set_history(&mut history);
+
+        submit(&mut editor, b"cat /a");
+        submit(&mut editor, b"ls /b");
+        submit(&mut editor, b"cat /c");
+        editor.clear();
+
+        feed(&mut editor, b"\x12cat\x05");
+        assert_eq!(editor.as_str(), Ok("cat /c"));
+
+        editor.clear();
+        feed(&mut editor, b"\x12cat\x12\x05");
+        assert_eq!(editor.as_str(), Ok("cat /a"));
+
+        editor.clear();
+        feed(&mut editor, b"ed\x12ls\x07");
+        assert_eq!(editor.as_str(), Ok("ed"));
+        assert_eq!(editor.cursor(), 2);
+
+        editor.clear();
+        feed(&mut editor, b"\x12ls");
+        assert_eq!(editor.handle_byte(b'\r', &mut String::new()), Status::Submitted);
+        assert_eq!(editor.as_str(), Ok("ls /b"));
+    }
+}
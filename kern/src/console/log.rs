@@ -0,0 +1,231 @@
+use alloc::format;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::clock::{self, DateTime};
+use crate::irq::with_irqs_disabled;
+use crate::mutex::Mutex;
+
+/// The number of lines a `Log` keeps.
+pub const LOG_LINES: usize = 128;
+
+/// The longest line a `Log` keeps. Longer lines are split.
+pub const LOG_LINE_LEN: usize = 128;
+
+/// The latest lines of console output, for `dmesg` and crash reports.
+///
+/// It holds `LOG_LINES` lines, counting the one being written. Once it is
+/// full, each new line forgets the oldest. `\r`s are left out. `Log` never
+/// allocates.
+///
+/// Each line is dated with the wall-clock time its first byte was written
+/// at, if the clock was set then.
+pub struct Log {
+    lines: [[u8; LOG_LINE_LEN]; LOG_LINES],
+    lens: [usize; LOG_LINES],
+    /// The time each line was started, in seconds since the Unix epoch.
+    times: [Option<u64>; LOG_LINES],
+    /// The slot of the line being written, which no `\n` has ended yet.
+    next: usize,
+    /// The number of slots holding an ended line.
+    count: usize,
+}
+
+impl Log {
+    /// Returns an empty log.
+    pub const fn new() -> Log {
+        Log {
+            lines: [[0; LOG_LINE_LEN]; LOG_LINES],
+            lens: [0; LOG_LINES],
+            times: [None; LOG_LINES],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    /// Adds `bytes`, written at `time` in seconds since the Unix epoch if it
+    /// is known, to the log.
+    pub fn write(&mut self, bytes: &[u8], time: Option<u64>) {
+        for &byte in bytes {
+            match byte {
+                b'\r' => continue,
+                b'\n' => self.end_line(),
+                _ => {
+                    if self.lens[self.next] == LOG_LINE_LEN {
+                        self.end_line();
+                    }
+                    if self.lens[self.next] == 0 {
+                        self.times[self.next] = time;
+                    }
+                    self.lines[self.next][self.lens[self.next]] = byte;
+                    self.lens[self.next] += 1;
+                }
+            }
+        }
+    }
+
+    /// Ends the line being written and starts the next, forgetting the
+    /// oldest line if the log is full.
+    fn end_line(&mut self) {
+        self.next = (self.next + 1) % LOG_LINES;
+        self.lens[self.next] = 0;
+        self.count = (self.count + 1).min(LOG_LINES - 1);
+    }
+
+    /// Returns the number of lines kept, counting a line not yet ended if it
+    /// is not empty.
+    pub fn len(&self) -> usize {
+        self.count + (self.lens[self.next] != 0) as usize
+    }
+
+    /// Returns `true` if the log is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the lines kept, oldest first, without their `\n`s.
+    pub fn lines(&self) -> impl Iterator<Item = &[u8]> {
+        self.dated_lines().map(|(_, line)| line)
+    }
+
+    /// Returns the lines kept, oldest first, without their `\n`s, each with
+    /// the time it was started at, if it is known.
+    pub fn dated_lines(&self) -> impl Iterator<Item = (Option<u64>, &[u8])> {
+        let first = (self.next + LOG_LINES - self.count) % LOG_LINES;
+        (0..self.len()).map(move |i| {
+            let slot = (first + i) % LOG_LINES;
+            (self.times[slot], &self.lines[slot][..self.lens[slot]])
+        })
+    }
+}
+
+/// The length of the date `contents()` starts a dated line with.
+const DATE_LEN: usize = "[YYYY-MM-DD HH:MM:SS] ".len();
+
+/// The kernel's log: everything written to the console.
+static LOG: Mutex<Log> = Mutex::new(Log::new());
+
+/// Whether the log has stopped taking output.
+static FROZEN: AtomicBool = AtomicBool::new(false);
+
+/// Adds `bytes`, just written to the console, to the log.
+pub fn record(bytes: &[u8]) {
+    if !FROZEN.load(Ordering::Relaxed) {
+        let time = clock::unix_time();
+        with_irqs_disabled(|| LOG.lock().write(bytes, time));
+    }
+}
+
+/// Like `record()`, but drops `bytes` rather than wait for another core
+/// using the log. The lines `bytes` starts are not dated, as reading the
+/// clock could wait too.
+pub fn record_nolock(bytes: &[u8]) {
+    if !FROZEN.load(Ordering::Relaxed) {
+        with_irqs_disabled(|| {
+            if let Some(mut log) = LOG.try_lock() {
+                log.write(bytes, None);
+            }
+        });
+    }
+}
+
+/// Stops the log from taking any more output, so that a crash report can
+/// include it as it was when the crash happened.
+pub fn freeze() {
+    FROZEN.store(true, Ordering::Relaxed);
+}
+
+/// Returns a copy of the log's lines, each ended by a `\n` and, if it is
+/// dated, started by the time it was written at.
+pub fn contents() -> Vec<u8> {
+    // The copy is made before anything is written, since writing to the
+    // console adds to the log.
+    with_irqs_disabled(|| {
+        let log = LOG.lock();
+        let mut contents = Vec::with_capacity(log.len() * (LOG_LINE_LEN + DATE_LEN));
+        for (time, line) in log.dated_lines() {
+            if let Some(time) = time {
+                let date = format!("[{}] ", DateTime::from_unix(time));
+                contents.extend_from_slice(date.as_bytes());
+            }
+            contents.extend_from_slice(line);
+            contents.push(b'\n');
+        }
+        contents
+    })
+}
+
+/// Calls `f` with the log, or returns `None` without calling it if another
+/// core is using it. For crash reports, once the log is frozen.
+pub fn try_with<R>(f: impl FnOnce(&Log) -> R) -> Option<R> {
+    LOG.try_lock().map(|log| f(&log))
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{Log, LOG_LINES, LOG_LINE_LEN};
+
+    fn lines(log: &Log) -> Vec<Vec<u8>> {
+        log.lines().map(|line| line.to_vec()).collect()
+    }
+
+    #[test]
+    fn lines_and_partial_line() {
+        let mut log = Log::new();
+        assert!(log.is_empty());
+
+        log.write(b"one\r\ntwo\n\nthr", None);
+        assert_eq!(log.len(), 4);
+        assert_eq!(
+            lines(&log),
+            vec![b"one".to_vec(), b"two".to_vec(), vec![], b"thr".to_vec()]
+        );
+
+        log.write(b"ee\n", None);
+        assert_eq!(log.len(), 4);
+        assert_eq!(log.lines().last(), Some(&b"three"[..]));
+    }
+
+    #[test]
+    fn forgets_oldest() {
+        let mut log = Log::new();
+        for i in 0..2 * LOG_LINES {
+            log.write(format!("line {}\n", i).as_bytes(), None);
+        }
+
+        let kept = lines(&log);
+        assert_eq!(kept.len(), LOG_LINES - 1);
+        assert_eq!(kept[0], format!("line {}", LOG_LINES + 1).into_bytes());
+        assert_eq!(
+            kept[LOG_LINES - 2],
+            format!("line {}", 2 * LOG_LINES - 1).into_bytes()
+        );
+
+        log.write(b"partial", None);
+        assert_eq!(log.len(), LOG_LINES);
+        assert_eq!(log.lines().last(), Some(&b"partial"[..]));
+    }
+
+    #[test]
+    fn splits_long_lines() {
+        let mut log = Log::new();
+        log.write(&[b'x'; LOG_LINE_LEN + 1], None);
+        assert_eq!(log.len(), 2);
+        assert_eq!(
+            log.lines().next().map(|line| line.len()),
+            Some(LOG_LINE_LEN)
+        );
+        assert_eq!(log.lines().last(), Some(&b"x"[..]));
+    }
+
+    #[test]
+    fn dated_lines() {
+        let mut log = Log::new();
+        log.write(b"one\ntw", Some(10));
+        log.write(b"o\nthree\n", Some(20));
+        log.write(b"four", None);
+
+        let times: Vec<_> = log.dated_lines().map(|(time, _)| time).collect();
+        assert_eq!(times, [Some(10), Some(10), Some(20), None]);
+    }
+}
@@ -0,0 +1,92 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of bytes an `RxBuffer` holds.
+pub const RX_BUF_SIZE: usize = 256;
+
+/// A ring buffer of bytes received but not yet read.
+///
+/// The buffer has a single producer, the UART's interrupt handler, and a
+/// single consumer, the console, so it needs no lock: each side only moves
+/// its own index. Bytes received while the buffer is full are dropped.
+pub struct RxBuffer {
+    buf: UnsafeCell<[u8; RX_BUF_SIZE]>,
+    /// The total number of bytes ever pushed.
+    head: AtomicUsize,
+    /// The total number of bytes ever popped.
+    tail: AtomicUsize,
+}
+
+unsafe impl Sync for RxBuffer {}
+
+impl RxBuffer {
+    /// Returns a new, empty buffer.
+    pub const fn new() -> RxBuffer {
+        RxBuffer {
+            buf: UnsafeCell::new([0; RX_BUF_SIZE]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `byte` to the buffer. Returns `false`, dropping the byte, if the
+    /// buffer is full. Must only be called by the producer.
+    pub fn push(&self, byte: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == RX_BUF_SIZE {
+            return false;
+        }
+
+        unsafe { (*self.buf.get())[head % RX_BUF_SIZE] = byte };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Returns `true` if there are no bytes to pop.
+    pub fn is_empty(&self) -> bool {
+        self.tail.load(Ordering::Acquire) == self.head.load(Ordering::Acquire)
+    }
+
+    /// Removes and returns the oldest byte in the buffer, if there is one.
+    /// Must only be called by the consumer.
+    pub fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let byte = unsafe { (*self.buf.get())[tail % RX_BUF_SIZE] };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{RxBuffer, RX_BUF_SIZE};
+
+    #[test]
+    fn ring() {
+        let rx = RxBuffer::new();
+        assert!(rx.is_empty());
+        assert_eq!(rx.pop(), None);
+
+        // Go around the ring a few times.
+        for i in 0..3 * RX_BUF_SIZE {
+            assert!(rx.push(i as u8));
+            assert_eq!(rx.pop(), Some(i as u8));
+        }
+
+        for i in 0..RX_BUF_SIZE {
+            assert!(rx.push(i as u8));
+        }
+        assert!(!rx.push(0xff));
+        assert!(!rx.is_empty());
+
+        for i in 0..RX_BUF_SIZE {
+            assert_eq!(rx.pop(), Some(i as u8));
+        }
+        assert_eq!(rx.pop(), None);
+        assert!(rx.is_empty());
+    }
+}
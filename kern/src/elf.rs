@@ -0,0 +1,327 @@
+use core::convert::TryInto;
+use core::fmt;
+
+/// The bytes every ELF file starts with.
+pub const MAGIC: [u8; 4] = *b"\x7fELF";
+
+/// `e_type` of an executable linked to run at fixed addresses.
+pub const ET_EXEC: u16 = 2;
+/// `e_type` of a position-independent executable or shared object.
+pub const ET_DYN: u16 = 3;
+
+/// `p_type` of a segment to be loaded into memory.
+pub const PT_LOAD: u32 = 1;
+/// `p_type` of the segment holding the dynamic section.
+pub const PT_DYNAMIC: u32 = 2;
+
+/// `p_flags` bit of a segment that is executable.
+pub const PF_X: u32 = 1 << 0;
+/// `p_flags` bit of a segment that is writable.
+pub const PF_W: u32 = 1 << 1;
+
+/// `e_machine` for AArch64.
+const EM_AARCH64: u16 = 183;
+/// `EI_CLASS` for 64-bit objects.
+const ELFCLASS64: u8 = 2;
+/// `EI_DATA` for little-endian objects.
+const ELFDATA2LSB: u8 = 1;
+
+/// The size of the ELF64 file header.
+const HEADER_SIZE: usize = 64;
+/// The size of an ELF64 program header.
+const PROGRAM_HEADER_SIZE: usize = 56;
+/// The size of an ELF64 dynamic section entry.
+const DYN_SIZE: usize = 16;
+/// The size of an ELF64 relocation with addend.
+const RELA_SIZE: usize = 24;
+
+/// Dynamic section tags.
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+
+/// The relocation type which adds the load address to an addend.
+const R_AARCH64_RELATIVE: u32 = 1027;
+
+/// Errors from reading an ELF file.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The file does not start with the ELF magic bytes.
+    BadMagic,
+    /// The file ends before a structure it describes.
+    Truncated,
+    /// The file is valid ELF but cannot be run by this kernel.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::BadMagic => write!(f, "not an ELF file"),
+            Error::Truncated => write!(f, "truncated ELF file"),
+            Error::Unsupported(what) => write!(f, "unsupported ELF file: {}", what),
+        }
+    }
+}
+
+/// The fields of an ELF file header the loader needs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Header {
+    /// The object file type: `ET_EXEC` or `ET_DYN`.
+    pub kind: u16,
+    /// The virtual address of the entry point.
+    pub entry: u64,
+    /// The file offset of the program header table.
+    pub phoff: u64,
+    /// The number of program headers.
+    pub phnum: u16,
+}
+
+/// An ELF program header, describing one segment.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+/// Returns the little-endian `u16` at `offset` in `bytes`.
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, Error> {
+    let field = bytes.get(offset..offset + 2).ok_or(Error::Truncated)?;
+    Ok(u16::from_le_bytes(field.try_into().unwrap()))
+}
+
+/// Returns the little-endian `u32` at `offset` in `bytes`.
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, Error> {
+    let field = bytes.get(offset..offset + 4).ok_or(Error::Truncated)?;
+    Ok(u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+/// Returns the little-endian `u64` at `offset` in `bytes`.
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, Error> {
+    let field = bytes.get(offset..offset + 8).ok_or(Error::Truncated)?;
+    Ok(u64::from_le_bytes(field.try_into().unwrap()))
+}
+
+/// Returns `true` if `bytes` starts with the ELF magic bytes.
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Parses and validates the header of the ELF file `bytes`.
+///
+/// # Errors
+///
+/// Returns `Error::BadMagic` if `bytes` is not an ELF file and
+/// `Error::Unsupported` if it is not a little-endian, 64-bit AArch64
+/// executable.
+pub fn parse_header(bytes: &[u8]) -> Result<Header, Error> {
+    if !is_elf(bytes) {
+        return Err(Error::BadMagic);
+    } else if bytes.len() < HEADER_SIZE {
+        return Err(Error::Truncated);
+    } else if bytes[4] != ELFCLASS64 {
+        return Err(Error::Unsupported("not 64-bit"));
+    } else if bytes[5] != ELFDATA2LSB {
+        return Err(Error::Unsupported("not little-endian"));
+    } else if u16_at(bytes, 18)? != EM_AARCH64 {
+        return Err(Error::Unsupported("not AArch64"));
+    }
+
+    let header = Header {
+        kind: u16_at(bytes, 16)?,
+        entry: u64_at(bytes, 24)?,
+        phoff: u64_at(bytes, 32)?,
+        phnum: u16_at(bytes, 56)?,
+    };
+
+    if header.kind != ET_EXEC && header.kind != ET_DYN {
+        return Err(Error::Unsupported("not an executable"));
+    } else if u16_at(bytes, 54)? as usize != PROGRAM_HEADER_SIZE {
+        return Err(Error::Unsupported("unexpected program header size"));
+    }
+
+    Ok(header)
+}
+
+/// Returns the `index`th program header of the ELF file `bytes`.
+pub fn program_header(bytes: &[u8], header: &Header, index: u16) -> Result<ProgramHeader, Error> {
+    let start = (header.phoff as usize)
+        .checked_add(index as usize * PROGRAM_HEADER_SIZE)
+        .ok_or(Error::Truncated)?;
+    let ph = bytes
+        .get(start..start + PROGRAM_HEADER_SIZE)
+        .ok_or(Error::Truncated)?;
+
+    Ok(ProgramHeader {
+        kind: u32_at(ph, 0)?,
+        flags: u32_at(ph, 4)?,
+        offset: u64_at(ph, 8)?,
+        vaddr: u64_at(ph, 16)?,
+        filesz: u64_at(ph, 32)?,
+        memsz: u64_at(ph, 40)?,
+        align: u64_at(ph, 48)?,
+    })
+}
+
+/// Applies the relocations of a position-independent executable that has
+/// been loaded at address `base`. `image` holds the loaded segments, with the
+/// segment at virtual address `0` at its start; `dynamic` describes the
+/// dynamic segment.
+///
+/// # Errors
+///
+/// Returns `Error::Unsupported` if the executable needs any relocation other
+/// than `R_AARCH64_RELATIVE`, which is all a statically linked executable
+/// needs, and `Error::Truncated` if a relocation lies outside of `image`.
+pub fn relocate(image: &mut [u8], dynamic: &ProgramHeader, base: u64) -> Result<(), Error> {
+    let (mut rela, mut relasz, mut relaent) = (None, 0, RELA_SIZE as u64);
+    let start = dynamic.vaddr as usize;
+    let end = start
+        .checked_add(dynamic.memsz as usize)
+        .ok_or(Error::Truncated)?;
+    let entries = image.get(start..end).ok_or(Error::Truncated)?;
+
+    for entry in entries.chunks_exact(DYN_SIZE) {
+        let (tag, value) = (u64_at(entry, 0)?, u64_at(entry, 8)?);
+        match tag {
+            DT_NULL => break,
+            DT_RELA => rela = Some(value as usize),
+            DT_RELASZ => relasz = value as usize,
+            DT_RELAENT => relaent = value,
+            _ => (),
+        }
+    }
+
+    let rela = match rela {
+        Some(rela) => rela,
+        None => return Ok(()),
+    };
+
+    if relaent as usize != RELA_SIZE {
+        return Err(Error::Unsupported("unexpected relocation size"));
+    }
+
+    for i in 0..relasz / RELA_SIZE {
+        let at = rela + i * RELA_SIZE;
+        let offset = u64_at(image, at)? as usize;
+        let info = u64_at(image, at + 8)?;
+        let addend = u64_at(image, at + 16)?;
+
+        if info as u32 != R_AARCH64_RELATIVE {
+            return Err(Error::Unsupported(
+                "relocation other than R_AARCH64_RELATIVE",
+            ));
+        }
+
+        let target = image.get_mut(offset..offset + 8).ok_or(Error::Truncated)?;
+        target.copy_from_slice(&base.wrapping_add(addend).to_le_bytes());
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// Returns a minimal ELF header of type `kind` with one program header
+    /// immediately following it.
+    fn header(kind: u16) -> Vec<u8> {
+        let mut bytes = vec![0u8; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = ELFCLASS64;
+        bytes[5] = ELFDATA2LSB;
+        bytes[16..18].copy_from_slice(&kind.to_le_bytes());
+        bytes[18..20].copy_from_slice(&EM_AARCH64.to_le_bytes());
+        bytes[24..32].copy_from_slice(&0x1234u64.to_le_bytes());
+        bytes[32..40].copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        bytes[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        bytes[56..58].copy_from_slice(&1u16.to_le_bytes());
+        bytes
+    }
+
+    fn push_u32s(bytes: &mut Vec<u8>, values: &[u32]) {
+        values
+            .iter()
+            .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+    }
+
+    fn push_u64s(bytes: &mut Vec<u8>, values: &[u64]) {
+        values
+            .iter()
+            .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+    }
+
+    #[test]
+    fn headers() {
+        let mut bytes = header(ET_DYN);
+        push_u32s(&mut bytes, &[PT_LOAD, 5]);
+        push_u64s(&mut bytes, &[0x1000, 0, 0, 0x200, 0x300, 0x10000]);
+
+        let header = parse_header(&bytes).unwrap();
+        assert_eq!(
+            header,
+            Header {
+                kind: ET_DYN,
+                entry: 0x1234,
+                phoff: HEADER_SIZE as u64,
+                phnum: 1,
+            }
+        );
+
+        let ph = program_header(&bytes, &header, 0).unwrap();
+        assert_eq!((ph.kind, ph.flags, ph.offset), (PT_LOAD, 5, 0x1000));
+        assert_eq!((ph.filesz, ph.memsz, ph.align), (0x200, 0x300, 0x10000));
+        assert_eq!(program_header(&bytes, &header, 1), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert_eq!(parse_header(b"\x7fELX"), Err(Error::BadMagic));
+        assert_eq!(parse_header(b"\x7fELF"), Err(Error::Truncated));
+
+        let mut bytes = header(ET_DYN);
+        bytes[18] = 62;
+        assert_eq!(parse_header(&bytes), Err(Error::Unsupported("not AArch64")));
+        assert_eq!(
+            parse_header(&header(1)),
+            Err(Error::Unsupported("not an executable"))
+        );
+    }
+
+    #[test]
+    fn relative_relocations() {
+        // The dynamic section is at 0x00, the relocation table at 0x40 and
+        // the pointer being relocated at 0x58.
+        let mut image = Vec::new();
+        push_u64s(
+            &mut image,
+            &[DT_RELA, 0x40, DT_RELASZ, 0x18, DT_RELAENT, 0x18],
+        );
+        push_u64s(&mut image, &[DT_NULL, 0]);
+        push_u64s(&mut image, &[0x58, R_AARCH64_RELATIVE as u64, 0x20]);
+        push_u64s(&mut image, &[0]);
+
+        let dynamic = ProgramHeader {
+            kind: PT_DYNAMIC,
+            flags: 0,
+            offset: 0,
+            vaddr: 0,
+            filesz: 0x40,
+            memsz: 0x40,
+            align: 8,
+        };
+
+        relocate(&mut image, &dynamic, 0x8000_0000).unwrap();
+        assert_eq!(u64_at(&image, 0x58), Ok(0x8000_0020));
+
+        image[0x48] = 0x01;
+        assert!(relocate(&mut image, &dynamic, 0).is_err());
+    }
+}
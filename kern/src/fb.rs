@@ -0,0 +1,190 @@
+//! The framebuffer the firmware scans out to the HDMI port, and a terminal
+//! drawn on it that console output is copied to, so that the kernel can be
+//! used with a monitor alone.
+//!
+//! The firmware sets the framebuffer up when asked through its property
+//! interface. The display's own size is used, or `DEFAULT_WIDTH` by
+//! `DEFAULT_HEIGHT` if none is attached.
+
+mod font;
+mod term;
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::console::{self, Sink};
+use crate::firmware::{self, property, tag};
+use crate::mutex::Mutex;
+use crate::vm::PAGE_SIZE;
+use crate::VMM;
+
+pub use self::term::{Cell, Screen, Terminal, SCROLLBACK};
+
+/// The size the framebuffer is made if no display reports its own.
+pub const DEFAULT_WIDTH: u32 = 1024;
+pub const DEFAULT_HEIGHT: u32 = 768;
+
+/// The bits a pixel takes.
+const DEPTH: u32 = 32;
+
+/// The value of `SET_PIXEL_ORDER` that makes pixels `0xRRGGBB`.
+const PIXEL_ORDER_RGB: u32 = 1;
+
+/// The colors of the 16-color ANSI palette, as `0xRRGGBB`.
+const PALETTE: [u32; 16] = [
+    0x000000, 0xaa0000, 0x00aa00, 0xaa5500, 0x0000aa, 0xaa00aa, 0x00aaaa, 0xaaaaaa, 0x555555,
+    0xff5555, 0x55ff55, 0xffff55, 0x5555ff, 0xff55ff, 0x55ffff, 0xffffff,
+];
+
+/// The framebuffer, as a screen of `font::WIDTH` by `font::HEIGHT` cells.
+pub struct Framebuffer {
+    /// Where the framebuffer is mapped.
+    base: usize,
+    width: usize,
+    height: usize,
+    /// The distance between the starts of rows, in bytes.
+    pitch: usize,
+    /// Whether the firmware kept pixels `0xBBGGRR` instead.
+    bgr: bool,
+}
+
+impl Framebuffer {
+    /// Has the firmware set up the framebuffer, and maps it.
+    pub fn new() -> Result<Framebuffer, &'static str> {
+        let mut size = [0; 2];
+        property(&[(tag::GET_PHYSICAL_SIZE, &[], 2)], &mut size)?;
+        let (width, height) = match size {
+            [0, _] | [_, 0] => (DEFAULT_WIDTH, DEFAULT_HEIGHT),
+            [width, height] => (width, height),
+        };
+
+        let mut reply = [0; 9];
+        property(
+            &[
+                (tag::SET_PHYSICAL_SIZE, &[width, height], 2),
+                (tag::SET_VIRTUAL_SIZE, &[width, height], 2),
+                (tag::SET_DEPTH, &[DEPTH], 1),
+                (tag::SET_PIXEL_ORDER, &[PIXEL_ORDER_RGB], 1),
+                (tag::ALLOCATE_BUFFER, &[PAGE_SIZE as u32], 2),
+                (tag::GET_PITCH, &[], 1),
+            ],
+            &mut reply,
+        )?;
+
+        let [width, height, _, _, depth, order, bus, len, pitch] = reply;
+        if depth != DEPTH {
+            return Err("the firmware would not make 32-bit pixels");
+        } else if bus == 0 || len == 0 {
+            return Err("the firmware allocated no framebuffer");
+        }
+
+        let len = len as usize;
+        let base = VMM.map_device(firmware::bus_to_phys(bus), len);
+        let (width, height, pitch) = (width as usize, height as usize, pitch as usize);
+        if pitch < width * 4 || pitch * height > len {
+            return Err("the firmware's framebuffer is too small");
+        }
+
+        Ok(Framebuffer {
+            base,
+            width,
+            height,
+            pitch,
+            bgr: order != PIXEL_ORDER_RGB,
+        })
+    }
+
+    /// Returns the width of the framebuffer, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the framebuffer, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the pixel of the palette's color `color`.
+    fn pixel(&self, color: u8) -> u32 {
+        let rgb = PALETTE[usize::from(color) % PALETTE.len()];
+        if self.bgr {
+            (rgb & 0x00ff00) | (rgb >> 16) | ((rgb & 0xff) << 16)
+        } else {
+            rgb
+        }
+    }
+}
+
+impl Screen for Framebuffer {
+    fn draw(&mut self, col: usize, row: usize, cell: Cell) {
+        let (fg, bg) = (self.pixel(cell.fg), self.pixel(cell.bg));
+        let glyph = font::glyph(cell.byte as char);
+        let (x, y) = (col * font::WIDTH, row * font::HEIGHT);
+        for (dy, &bits) in glyph.iter().enumerate() {
+            let line = self.base + (y + dy) * self.pitch + x * 4;
+            for dx in 0..font::WIDTH {
+                let pixel = if bits & (0x80 >> dx) != 0 { fg } else { bg };
+                // The framebuffer is device memory: each pixel is one
+                // aligned store.
+                unsafe { ptr::write_volatile((line + dx * 4) as *mut u32, pixel) };
+            }
+        }
+    }
+}
+
+/// The terminal on the framebuffer, once it is set up.
+static TERMINAL: Mutex<Option<Terminal<Framebuffer>>> = Mutex::new(None);
+
+/// Whether a core is writing to `TERMINAL`.
+static WRITING: AtomicBool = AtomicBool::new(false);
+
+/// Sets up the framebuffer and its terminal, and has the console copy its
+/// output to the terminal.
+pub fn initialize() -> Result<(), &'static str> {
+    let framebuffer = Framebuffer::new()?;
+    let cols = framebuffer.width() / font::WIDTH;
+    let rows = framebuffer.height() / font::HEIGHT;
+    *TERMINAL.lock() = Some(Terminal::new(framebuffer, cols, rows));
+
+    let sink = Sink {
+        write,
+        write_nolock,
+    };
+    if !console::add_sink(sink) {
+        return Err("no room for another console sink");
+    }
+    Ok(())
+}
+
+/// Scrolls the terminal `lines` lines back into its scrollback, or forward if
+/// `lines` is negative. See `Terminal::scroll_view()`.
+pub fn scroll(lines: isize) {
+    if let Some(ref mut terminal) = *TERMINAL.lock() {
+        terminal.scroll_view(lines);
+    }
+}
+
+/// Writes `bytes` to the terminal, as the console's sink. Bytes written by
+/// code that interrupted a write on this core are dropped, since the
+/// terminal is half way through the write.
+fn write(bytes: &[u8]) {
+    write_to(&mut TERMINAL.lock(), bytes);
+}
+
+/// Like `write()`, but drops the bytes rather than wait for another core to
+/// finish writing.
+fn write_nolock(bytes: &[u8]) {
+    if let Some(mut terminal) = TERMINAL.try_lock() {
+        write_to(&mut terminal, bytes);
+    }
+}
+
+/// Writes `bytes` to `terminal` unless this core is writing to it already.
+fn write_to(terminal: &mut Option<Terminal<Framebuffer>>, bytes: &[u8]) {
+    if let Some(ref mut terminal) = *terminal {
+        if !WRITING.swap(true, Ordering::Relaxed) {
+            terminal.write(bytes);
+            WRITING.store(false, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The number of lines that scrolled off the top the terminal keeps.
+pub const SCROLLBACK: usize = 500;
+
+/// The most parameters a control sequence keeps. Later ones are ignored.
+const MAX_PARAMS: usize = 8;
+
+/// The columns tab stops are a multiple of.
+const TAB_WIDTH: usize = 8;
+
+/// The colors characters are drawn in until a sequence changes them: light
+/// gray on black.
+const DEFAULT_FG: u8 = 7;
+const DEFAULT_BG: u8 = 0;
+
+/// Added to a color to brighten it.
+const BRIGHT: u8 = 8;
+
+/// A character on the terminal, with its colors. Colors are indices in the
+/// 16-color ANSI palette: black, red, green, yellow, blue, magenta, cyan and
+/// white, then their bright versions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub byte: u8,
+    pub fg: u8,
+    pub bg: u8,
+}
+
+impl Cell {
+    /// A space in the default colors.
+    pub const BLANK: Cell = Cell {
+        byte: b' ',
+        fg: DEFAULT_FG,
+        bg: DEFAULT_BG,
+    };
+
+    /// Returns the cell with its colors swapped, as the cursor is drawn.
+    fn inverted(self) -> Cell {
+        Cell {
+            fg: self.bg,
+            bg: self.fg,
+            ..self
+        }
+    }
+}
+
+/// What a `Terminal` draws on: a grid of character cells.
+pub trait Screen {
+    /// Draws `cell` at column `col` of row `row`.
+    fn draw(&mut self, col: usize, row: usize, cell: Cell);
+}
+
+/// Progress through an escape sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    /// Not in an escape sequence.
+    Ground,
+    /// Received `ESC`.
+    Escape,
+    /// Received `ESC [`, the control sequence introducer, and possibly some
+    /// parameters.
+    Csi,
+}
+
+/// A terminal that draws the text written to it on a `Screen`, as a VT100
+/// would: text wraps at the last column, and scrolls up off the top into
+/// the scrollback once the cursor passes the last row. Control characters
+/// are `\n`, which also returns to the first column, `\r`, backspace, tab
+/// and bell. Escape sequences are the subset of ECMA-48 the kernel and its
+/// programs write:
+///
+///   * `ESC [ n A` / `B` / `C` / `D`: move the cursor up, down, right or
+///     left `n` cells
+///   * `ESC [ n G`: move the cursor to column `n`
+///   * `ESC [ row ; col H` (or `f`): move the cursor to `row` and `col`
+///   * `ESC [ n J`: erase from the cursor to the end of the screen, from the
+///     start to the cursor, the whole screen, or with `3` the scrollback too
+///   * `ESC [ n K`: erase from the cursor to the end of its line, from the
+///     start of the line to the cursor, or the whole line
+///   * `ESC [ s` / `u`: save and restore the cursor's position
+///   * `ESC [ ... m`: set the colors: reset, bold, reverse, and the 16 ANSI
+///     foreground and background colors
+///
+/// Rows, columns and counts are 1-based and default to 1. Other sequences
+/// are ignored.
+///
+/// Nothing is allocated once the terminal is made, so it can be written to
+/// from anywhere the kernel prints.
+pub struct Terminal<S: Screen> {
+    screen: S,
+    cols: usize,
+    rows: usize,
+    /// The cells on the screen, row after row.
+    cells: Vec<Cell>,
+    /// The lines that scrolled off the top, a ring of `SCROLLBACK` lines of
+    /// which the `history_len` starting at line `history_start` are used.
+    history: Vec<Cell>,
+    history_start: usize,
+    history_len: usize,
+    /// How many lines of the scrollback the screen shows, scrolled back.
+    view: usize,
+    /// The cursor's position. `col` is `cols` after a character is written
+    /// in the last column, and wraps with the next one.
+    col: usize,
+    row: usize,
+    saved: (usize, usize),
+    fg: u8,
+    bg: u8,
+    bold: bool,
+    reverse: bool,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    /// The number of parameters of the control sequence so far, including
+    /// the one being read.
+    nparams: usize,
+}
+
+impl<S: Screen> Terminal<S> {
+    /// Returns a terminal of `cols` by `rows` cells drawn on `screen`, which
+    /// it clears.
+    pub fn new(screen: S, cols: usize, rows: usize) -> Terminal<S> {
+        let mut terminal = Terminal {
+            screen,
+            cols,
+            rows,
+            cells: vec![Cell::BLANK; cols * rows],
+            history: vec![Cell::BLANK; cols * SCROLLBACK],
+            history_start: 0,
+            history_len: 0,
+            view: 0,
+            col: 0,
+            row: 0,
+            saved: (0, 0),
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+            bold: false,
+            reverse: false,
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            nparams: 0,
+        };
+        terminal.redraw();
+        terminal.draw_cursor(true);
+        terminal
+    }
+
+    /// Returns the number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the screen the terminal draws on.
+    pub fn screen(&self) -> &S {
+        &self.screen
+    }
+
+    /// Writes `bytes` to the terminal. A view scrolled back returns to the
+    /// bottom first.
+    pub fn write(&mut self, bytes: &[u8]) {
+        if self.view != 0 {
+            self.view = 0;
+            self.redraw();
+        }
+
+        self.draw_cursor(false);
+        for &byte in bytes {
+            self.feed(byte);
+        }
+        self.draw_cursor(true);
+    }
+
+    /// Scrolls the view `lines` lines back into the scrollback, or forward
+    /// if `lines` is negative, as far as there are lines. Returns how many
+    /// lines back the view is.
+    pub fn scroll_view(&mut self, lines: isize) -> usize {
+        let view = if lines < 0 {
+            self.view.saturating_sub(lines.wrapping_neg() as usize)
+        } else {
+            self.view.saturating_add(lines as usize)
+        };
+
+        let view = view.min(self.history_len);
+        if view != self.view {
+            self.view = view;
+            self.redraw();
+            self.draw_cursor(true);
+        }
+        self.view
+    }
+
+    /// Handles one byte written to the terminal.
+    fn feed(&mut self, byte: u8) {
+        match self.state {
+            State::Ground => self.control_or_print(byte),
+            State::Escape if byte == b'[' => {
+                self.params = [0; MAX_PARAMS];
+                self.nparams = 0;
+                self.state = State::Csi;
+            }
+            State::Escape => self.state = State::Ground,
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    self.nparams = self.nparams.max(1);
+                    if let Some(param) = self.params.get_mut(self.nparams - 1) {
+                        let digit = u16::from(byte - b'0');
+                        *param = param.saturating_mul(10).saturating_add(digit);
+                    }
+                }
+                b';' => self.nparams = self.nparams.max(1) + 1,
+                // Parameters and intermediates this terminal has no use for.
+                0x20..=0x3f => {}
+                _ => {
+                    self.state = State::Ground;
+                    self.control_sequence(byte);
+                }
+            },
+        }
+    }
+
+    /// Handles a byte outside an escape sequence.
+    fn control_or_print(&mut self, byte: u8) {
+        match byte {
+            b'\n' => {
+                self.col = 0;
+                self.line_feed();
+            }
+            b'\r' => self.col = 0,
+            0x08 => self.col = self.col.min(self.cols - 1).saturating_sub(1),
+            b'\t' => {
+                let stop = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.col = stop.min(self.cols - 1);
+            }
+            0x1b => self.state = State::Escape,
+            // The rest of a character encoded in several bytes, which is
+            // drawn once, at its first byte.
+            0x80..=0xbf => {}
+            0x20..=0x7e | 0xc0..=0xff => self.print(byte),
+            _ => {}
+        }
+    }
+
+    /// Writes the character `byte` at the cursor, and moves the cursor on.
+    fn print(&mut self, byte: u8) {
+        if self.col == self.cols {
+            self.col = 0;
+            self.line_feed();
+        }
+
+        let (mut fg, mut bg) = (self.fg, self.bg);
+        if self.bold && fg < BRIGHT {
+            fg += BRIGHT;
+        }
+        if self.reverse {
+            core::mem::swap(&mut fg, &mut bg);
+        }
+        self.set(self.col, self.row, Cell { byte, fg, bg });
+        self.col += 1;
+    }
+
+    /// Moves the cursor down a row, scrolling if it is on the last.
+    fn line_feed(&mut self) {
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+
+        let slot = (self.history_start + self.history_len) % SCROLLBACK;
+        let line = slot * self.cols;
+        self.history[line..line + self.cols].copy_from_slice(&self.cells[..self.cols]);
+        if self.history_len < SCROLLBACK {
+            self.history_len += 1;
+        } else {
+            self.history_start = (self.history_start + 1) % SCROLLBACK;
+        }
+
+        self.cells.copy_within(self.cols.., 0);
+        let last = (self.rows - 1) * self.cols;
+        for cell in &mut self.cells[last..] {
+            *cell = Cell::BLANK;
+        }
+        self.redraw();
+    }
+
+    /// Returns the `i`th parameter of the control sequence, or `default` if
+    /// it is missing or 0.
+    fn param(&self, i: usize, default: usize) -> usize {
+        match self.params.get(i) {
+            Some(&param) if i < self.nparams && param != 0 => usize::from(param),
+            _ => default,
+        }
+    }
+
+    /// Carries out the control sequence that `last` ends.
+    fn control_sequence(&mut self, last: u8) {
+        let n = self.param(0, 1);
+        let col = self.col.min(self.cols - 1);
+        match last {
+            b'A' => self.row = self.row.saturating_sub(n),
+            b'B' => self.row = (self.row + n).min(self.rows - 1),
+            b'C' => self.col = (col + n).min(self.cols - 1),
+            b'D' => self.col = col.saturating_sub(n),
+            b'G' => self.col = (n - 1).min(self.cols - 1),
+            b'H' | b'f' => {
+                self.row = (n - 1).min(self.rows - 1);
+                self.col = (self.param(1, 1) - 1).min(self.cols - 1);
+            }
+            b'J' => {
+                let cursor = self.row * self.cols + col;
+                match self.param(0, 0) {
+                    0 => self.erase(cursor, self.cells.len()),
+                    1 => self.erase(0, cursor + 1),
+                    2 => self.erase(0, self.cells.len()),
+                    3 => {
+                        self.history_len = 0;
+                        self.erase(0, self.cells.len());
+                    }
+                    _ => {}
+                }
+            }
+            b'K' => {
+                let start = self.row * self.cols;
+                match self.param(0, 0) {
+                    0 => self.erase(start + col, start + self.cols),
+                    1 => self.erase(start, start + col + 1),
+                    2 => self.erase(start, start + self.cols),
+                    _ => {}
+                }
+            }
+            b's' => self.saved = (self.col, self.row),
+            b'u' => {
+                self.col = self.saved.0.min(self.cols);
+                self.row = self.saved.1.min(self.rows - 1);
+            }
+            b'm' => self.select_graphic_rendition(),
+            _ => {}
+        }
+    }
+
+    /// Carries out `ESC [ ... m`, which sets the colors.
+    fn select_graphic_rendition(&mut self) {
+        // `ESC [ m`, with no parameters, resets.
+        let count = if self.nparams == 0 {
+            1
+        } else {
+            self.nparams.min(MAX_PARAMS)
+        };
+        for i in 0..count {
+            match self.param(i, 0) {
+                0 => {
+                    self.fg = DEFAULT_FG;
+                    self.bg = DEFAULT_BG;
+                    self.bold = false;
+                    self.reverse = false;
+                }
+                1 => self.bold = true,
+                7 => self.reverse = true,
+                22 => self.bold = false,
+                27 => self.reverse = false,
+                n @ 30..=37 => self.fg = (n - 30) as u8,
+                39 => self.fg = DEFAULT_FG,
+                n @ 40..=47 => self.bg = (n - 40) as u8,
+                49 => self.bg = DEFAULT_BG,
+                n @ 90..=97 => self.fg = (n - 90) as u8 + BRIGHT,
+                n @ 100..=107 => self.bg = (n - 100) as u8 + BRIGHT,
+                _ => {}
+            }
+        }
+    }
+
+    /// Blanks the cells from `start` to `end`, counted row after row.
+    fn erase(&mut self, start: usize, end: usize) {
+        let blank = Cell {
+            bg: self.bg,
+            ..Cell::BLANK
+        };
+        for i in start..end.min(self.cells.len()) {
+            self.set(i % self.cols, i / self.cols, blank);
+        }
+    }
+
+    /// Sets the cell at `col` and `row`, drawing it unless the view is
+    /// scrolled back.
+    fn set(&mut self, col: usize, row: usize, cell: Cell) {
+        self.cells[row * self.cols + col] = cell;
+        if self.view == 0 {
+            self.screen.draw(col, row, cell);
+        }
+    }
+
+    /// Draws the cell under the cursor, in inverted colors if `shown`. The
+    /// cursor is only shown at the bottom of the scrollback.
+    fn draw_cursor(&mut self, shown: bool) {
+        if self.view != 0 {
+            return;
+        }
+
+        let col = self.col.min(self.cols - 1);
+        let cell = self.cells[self.row * self.cols + col];
+        let cell = if shown { cell.inverted() } else { cell };
+        self.screen.draw(col, self.row, cell);
+    }
+
+    /// Draws every row of the view, but not the cursor.
+    fn redraw(&mut self) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let cell = if row < self.view {
+                    let line = self.history_len - self.view + row;
+                    let slot = (self.history_start + line) % SCROLLBACK;
+                    self.history[slot * self.cols + col]
+                } else {
+                    self.cells[(row - self.view) * self.cols + col]
+                };
+                self.screen.draw(col, row, cell);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// A screen that remembers what was drawn on it.
+    struct Grid {
+        cols: usize,
+        cells: Vec<Cell>,
+    }
+
+    impl Screen for Grid {
+        fn draw(&mut self, col: usize, row: usize, cell: Cell) {
+            self.cells[row * self.cols + col] = cell;
+        }
+    }
+
+    fn terminal(cols: usize, rows: usize) -> Terminal<Grid> {
+        let grid = Grid {
+            cols,
+            cells: vec![Cell::BLANK; cols * rows],
+        };
+        Terminal::new(grid, cols, rows)
+    }
+
+    /// Returns what is drawn on row `row`, without trailing spaces.
+    fn row(terminal: &Terminal<Grid>, row: usize) -> String {
+        let cols = terminal.cols();
+        let cells = &terminal.screen().cells[row * cols..(row + 1) * cols];
+        let text: String = cells.iter().map(|cell| cell.byte as char).collect();
+        text.trim_end().to_string()
+    }
+
+    #[test]
+    fn wraps_and_scrolls() {
+        let mut t = terminal(4, 2);
+        t.write(b"abcdef");
+        assert_eq!((row(&t, 0), row(&t, 1)), ("abcd".into(), "ef".into()));
+
+        t.write(b"\nxy\r!");
+        assert_eq!((row(&t, 0), row(&t, 1)), ("ef".into(), "!y".into()));
+        t.write(b"\x08\x08z\tq");
+        assert_eq!(row(&t, 1), "zy q");
+    }
+
+    #[test]
+    fn cursor_is_inverted() {
+        let mut t = terminal(4, 2);
+        t.write(b"ab");
+        let cells = &t.screen().cells;
+        assert_eq!(
+            cells[1],
+            Cell {
+                byte: b'b',
+                ..Cell::BLANK
+            }
+        );
+        assert_eq!(cells[2], Cell::BLANK.inverted());
+
+        t.write(b"\x1b[D");
+        let cells = &t.screen().cells;
+        assert_eq!(cells[1].fg, DEFAULT_BG);
+        assert_eq!(cells[2], Cell::BLANK);
+    }
+
+    #[test]
+    fn control_sequences() {
+        let mut t = terminal(8, 3);
+        t.write(b"one\ntwo\nthree");
+        t.write(b"\x1b[2;2Ho\x1b[A\x1b[2D\x1b[KO\x1b[1;4H\x1b[1K");
+        assert_eq!(row(&t, 0), "");
+        assert_eq!(row(&t, 1), "too");
+
+        t.write(b"\x1b[3;5H\x1b[0J\x1b[10CX\x1b[2GY");
+        assert_eq!(row(&t, 2), "tYre   X");
+        t.write(b"\x1b[s\x1b[H\x1b[2J\x1b[uZ");
+        assert_eq!((row(&t, 1), row(&t, 2)), ("".into(), "  Z".into()));
+
+        // Unknown sequences are swallowed whole.
+        t.write(b"\x1b[?25l\x1bc!");
+        assert_eq!(row(&t, 2), "  Z!");
+    }
+
+    #[test]
+    fn colors() {
+        let mut t = terminal(8, 1);
+        t.write(b"\x1b[31ma\x1b[1;44mb\x1b[7mc\x1b[0;97;100md\x1b[me");
+        let cells = &t.screen().cells;
+        let colors: Vec<(u8, u8)> = cells[..5].iter().map(|c| (c.fg, c.bg)).collect();
+        assert_eq!(colors, [(1, 0), (9, 4), (4, 9), (15, 8), (7, 0)]);
+
+        t.write(b"\x1b[42m\x1b[2K");
+        assert!(t.cells.iter().all(|cell| cell.bg == 2));
+    }
+
+    #[test]
+    fn scrollback() {
+        let mut t = terminal(4, 2);
+        for line in &[&b"1\n"[..], b"2\n", b"3\n", b"4"] {
+            t.write(line);
+        }
+        assert_eq!((row(&t, 0), row(&t, 1)), ("3".into(), "4".into()));
+
+        assert_eq!(t.scroll_view(1), 1);
+        assert_eq!((row(&t, 0), row(&t, 1)), ("2".into(), "3".into()));
+        assert_eq!(t.scroll_view(10), 2);
+        assert_eq!((row(&t, 0), row(&t, 1)), ("1".into(), "2".into()));
+        assert_eq!(t.scroll_view(-1), 1);
+
+        t.write(b"5");
+        assert_eq!((row(&t, 0), row(&t, 1)), ("3".into(), "45".into()));
+        t.write(b"\x1b[3J");
+        assert_eq!(t.scroll_view(1), 0);
+    }
+}
@@ -0,0 +1,148 @@
+//! The VideoCore firmware's property interface, through which the kernel has
+//! the firmware set up the framebuffer, power devices on and tell it the
+//! board's MAC address, and the bus addresses the VideoCore and the devices it
+//! serves see memory at.
+
+use pi::mailbox::{Mailbox, PROPERTY_CHANNEL};
+
+use crate::aarch64;
+use crate::mutex::Mutex;
+use crate::vm::virt_to_phys;
+
+/// The size of the buffer property calls are made in, in words.
+const MESSAGE_WORDS: usize = 40;
+
+/// The code of a property call's buffer as it is sent, and as the firmware
+/// returns it if it answered every tag.
+const REQUEST: u32 = 0;
+const RESPONSE_OK: u32 = 0x8000_0000;
+
+/// Added to a physical address to have the VideoCore access it without its
+/// L2 cache, as it must for memory the cores write.
+const BUS_UNCACHED: u32 = 0xC000_0000;
+
+/// The bits of a bus address that hold the physical address.
+const BUS_ADDR_MASK: u32 = 0x3FFF_FFFF;
+
+/// The property tags the kernel uses.
+pub mod tag {
+    pub const GET_BOARD_MAC_ADDRESS: u32 = 0x0001_0003;
+    pub const SET_POWER_STATE: u32 = 0x0002_8001;
+    pub const ALLOCATE_BUFFER: u32 = 0x0004_0001;
+    pub const GET_PHYSICAL_SIZE: u32 = 0x0004_0003;
+    pub const GET_PITCH: u32 = 0x0004_0008;
+    pub const SET_PHYSICAL_SIZE: u32 = 0x0004_8003;
+    pub const SET_VIRTUAL_SIZE: u32 = 0x0004_8004;
+    pub const SET_DEPTH: u32 = 0x0004_8005;
+    pub const SET_PIXEL_ORDER: u32 = 0x0004_8006;
+}
+
+/// The devices whose power `set_power()` switches.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Device {
+    Usb = 3,
+}
+
+/// The `SET_POWER_STATE` bits: the power is on, and the firmware waits for
+/// the device to be ready before it answers.
+const POWER_ON: u32 = 1 << 0;
+const POWER_WAIT: u32 = 1 << 1;
+
+/// The `SET_POWER_STATE` answer bit set if there is no such device.
+const POWER_NO_DEVICE: u32 = 1 << 1;
+
+/// The buffer of a property call. The firmware needs it 16-byte aligned.
+#[repr(C, align(16))]
+struct Message([u32; MESSAGE_WORDS]);
+
+/// The one buffer property calls are made in. It is in the kernel's image,
+/// so its physical address is known.
+static MESSAGE: Mutex<Message> = Mutex::new(Message([0; MESSAGE_WORDS]));
+
+/// Returns the bus address of the kernel's memory at `va`, for the VideoCore
+/// and the devices that access memory themselves to use.
+pub fn bus_addr(va: usize) -> u32 {
+    virt_to_phys(va) as u32 | BUS_UNCACHED
+}
+
+/// Returns the physical address of the bus address `bus`.
+pub fn bus_to_phys(bus: u32) -> usize {
+    (bus & BUS_ADDR_MASK) as usize
+}
+
+/// Asks the firmware for the properties `tags`: each a tag, the values it is
+/// sent with and the number of values it answers with. The answers fill
+/// `reply`, one tag's after another.
+pub fn property(tags: &[(u32, &[u32], usize)], reply: &mut [u32]) -> Result<(), &'static str> {
+    let mut message = MESSAGE.lock();
+    let words = &mut message.0;
+
+    // The buffer's size and code, each tag, then the end tag, 0.
+    let mut len = 2;
+    for &(id, values, answers) in tags {
+        let size = values.len().max(answers);
+        let end = len + 3 + size;
+        if end >= MESSAGE_WORDS {
+            return Err("property call too long");
+        }
+
+        words[len..len + 3].copy_from_slice(&[id, (size * 4) as u32, REQUEST]);
+        words[len + 3..end].iter_mut().for_each(|word| *word = 0);
+        words[len + 3..len + 3 + values.len()].copy_from_slice(values);
+        len = end;
+    }
+    words[len] = 0;
+    words[0] = ((len + 1) * 4) as u32;
+    words[1] = REQUEST;
+
+    let va = words.as_ptr() as usize;
+    aarch64::clean_data_cache(va, MESSAGE_WORDS * 4);
+    unsafe {
+        Mailbox::new().call(PROPERTY_CHANNEL, bus_addr(va));
+    }
+    aarch64::clean_invalidate_data_cache(va, MESSAGE_WORDS * 4);
+    if words[1] != RESPONSE_OK {
+        return Err("the firmware refused a property call");
+    }
+
+    let (mut i, mut out) = (2, 0);
+    for &(_, values, answers) in tags {
+        let size = values.len().max(answers);
+        let answered = &words[i + 3..i + 3 + answers];
+        reply[out..out + answers].copy_from_slice(answered);
+        i += 3 + size;
+        out += answers;
+    }
+    Ok(())
+}
+
+/// Switches `device`'s power on, or off, waiting for it to be ready.
+pub fn set_power(device: Device, on: bool) -> Result<(), &'static str> {
+    let state = if on {
+        POWER_ON | POWER_WAIT
+    } else {
+        POWER_WAIT
+    };
+    let mut reply = [0; 2];
+    property(
+        &[(tag::SET_POWER_STATE, &[device as u32, state], 2)],
+        &mut reply,
+    )?;
+
+    if reply[1] & POWER_NO_DEVICE != 0 {
+        Err("the firmware knows no such device")
+    } else if (reply[1] & POWER_ON != 0) != on {
+        Err("the firmware did not switch the power")
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the MAC address the board's Ethernet interface is assigned.
+pub fn mac_address() -> Result<[u8; 6], &'static str> {
+    let mut reply = [0; 2];
+    property(&[(tag::GET_BOARD_MAC_ADDRESS, &[], 2)], &mut reply)?;
+    let [a, b, c, d] = reply[0].to_le_bytes();
+    let [e, f, _, _] = reply[1].to_le_bytes();
+    Ok([a, b, c, d, e, f])
+}
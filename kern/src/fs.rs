@@ -1,25 +1,31 @@
+pub mod devfs;
 pub mod sd;
+pub mod tmpfs;
+pub mod vfs;
 
-use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::fmt::{self, Debug};
 use shim::io;
-use shim::ioerr;
-use shim::path::Path;
+use shim::path::{Path, PathBuf};
 
 pub use fat32::traits;
 use fat32::vfat::{Dir, Entry, File, VFat, VFatHandle};
 
+use self::devfs::DevFs;
 use self::sd::Sd;
+use self::tmpfs::TmpFs;
+use self::vfs::{FileSystemOps, MountTable};
 use crate::mutex::Mutex;
 
 #[derive(Clone)]
-pub struct PiVFatHandle(Rc<Mutex<VFat<Self>>>);
+pub struct PiVFatHandle(Arc<Mutex<VFat<Self>>>);
 
-// These impls are *unsound*. We should use `Arc` instead of `Rc` to implement
-// `Sync` and `Send` trait for `PiVFatHandle`. However, `Arc` uses atomic memory
-// access, which requires MMU to be initialized on ARM architecture. Since we
-// have enabled only one core of the board, these unsound impls will not cause
-// any immediate harm for now. We will fix this in the future.
+// The file system's block device is a `Box<dyn BlockDevice>`, which is neither
+// `Send` nor `Sync`. The kernel's only device is the SD card, which any core
+// can drive, and the `Mutex` lets one core at a time use it, so sharing the
+// handle between cores is sound.
 unsafe impl Send for PiVFatHandle {}
 unsafe impl Sync for PiVFatHandle {}
 
@@ -31,14 +37,54 @@ impl Debug for PiVFatHandle {
 
 impl VFatHandle for PiVFatHandle {
     fn new(val: VFat<PiVFatHandle>) -> Self {
-        PiVFatHandle(Rc::new(Mutex::new(val)))
+        PiVFatHandle(Arc::new(Mutex::new(val)))
     }
 
     fn lock<R>(&self, f: impl FnOnce(&mut VFat<PiVFatHandle>) -> R) -> R {
         f(&mut self.0.lock())
     }
 }
-pub struct FileSystem(Mutex<Option<PiVFatHandle>>);
+impl vfs::FileSystemOps for PiVFatHandle {
+    fn kind(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn open(&self, path: &Path) -> io::Result<vfs::Entry> {
+        traits::FileSystem::open(self, path).map(fat_entry)
+    }
+}
+
+impl vfs::FileOps for File<PiVFatHandle> {
+    fn size(&self) -> u64 {
+        traits::File::size(self)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
+
+impl vfs::DirOps for Dir<PiVFatHandle> {
+    fn entries(&self) -> io::Result<Vec<vfs::Entry>> {
+        Ok(traits::Dir::entries(self)?.map(fat_entry).collect())
+    }
+}
+
+/// Returns the entry of the virtual file system that stands for `entry`.
+fn fat_entry(entry: Entry<PiVFatHandle>) -> vfs::Entry {
+    use fat32::traits::Entry as _;
+
+    let name = String::from(entry.name());
+    let metadata = vfs::Metadata::of(entry.metadata());
+    match entry {
+        fat32::vfat::Entry::File(file) => vfs::Entry::file(name, metadata, file),
+        fat32::vfat::Entry::Dir(dir) => vfs::Entry::dir(name, metadata, dir),
+    }
+}
+
+/// The kernel's file system: the virtual file system, over every file system
+/// mounted. See `vfs`.
+pub struct FileSystem(Mutex<MountTable>);
 
 impl FileSystem {
     /// Returns an uninitialized `FileSystem`.
@@ -46,20 +92,84 @@ impl FileSystem {
     /// The file system must be initialized by calling `initialize()` before the
     /// first memory allocation. Failure to do will result in panics.
     pub const fn uninitialized() -> Self {
-        FileSystem(Mutex::new(None))
+        FileSystem(Mutex::new(MountTable::new()))
     }
 
-    /// Initializes the file system.
+    /// Initializes the file system, mounting the device file system at
+    /// `/dev`, an empty temporary file system at `/tmp` and the SD card's
+    /// FAT32 file system at `/`.
     /// The caller should assure that the method is invoked only once during the
     /// kernel initialization.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the underlying disk or file sytem failed to initialize.
-    pub unsafe fn initialize(&self) {
-        unimplemented!("FileSystem::initialize()")
+    /// Returns an error if the SD card or its file system failed to
+    /// initialize. The device and temporary file systems are mounted even so.
+    pub unsafe fn initialize(&self) -> io::Result<()> {
+        self.mount(Path::new("/dev"), Arc::new(DevFs))?;
+        self.mount(Path::new("/tmp"), Arc::new(TmpFs::new()))?;
+
+        let vfat = VFat::<PiVFatHandle>::from(Sd::new()?).map_err(|e| match e {
+            fat32::vfat::Error::Io(e) | fat32::vfat::Error::Mbr(fat32::Error::Io(e)) => e,
+            _ => io::Error::new(io::ErrorKind::InvalidData, "no FAT32 file system found"),
+        })?;
+        self.mount(Path::new("/"), Arc::new(vfat))
+    }
+
+    /// Mounts `fs` at the absolute path `point`. See `MountTable::mount()`.
+    pub fn mount(&self, point: &Path, fs: Arc<dyn FileSystemOps>) -> io::Result<()> {
+        self.0.lock().mount(point, fs)
+    }
+
+    /// Unmounts the file system mounted at `point`, returning it. See
+    /// `MountTable::unmount()`.
+    pub fn unmount(&self, point: &Path) -> io::Result<Arc<dyn FileSystemOps>> {
+        self.0.lock().unmount(point)
+    }
+
+    /// Returns the mount point and kind of each file system mounted.
+    pub fn mounts(&self) -> Vec<(PathBuf, &'static str)> {
+        self.0.lock().mounts()
+    }
+
+    /// Creates an empty directory at the absolute path `path`. See
+    /// `vfs::Resolved::create_dir()`.
+    pub fn create_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let resolved = self.0.lock().resolve(path.as_ref())?;
+        resolved.create_dir()
+    }
+
+    /// Moves the entry at the absolute path `from` to `to`, which must be in
+    /// the same file system. See `vfs::Resolved::rename()`.
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> io::Result<()> {
+        let (from, to) = {
+            let table = self.0.lock();
+            (table.resolve(from.as_ref())?, table.resolve(to.as_ref())?)
+        };
+        from.rename(to)
+    }
+
+    /// Writes any changes cached in memory back to the disk. Call this before
+    /// resetting or halting the board so the volume is left consistent.
+    pub fn sync(&self) -> io::Result<()> {
+        // Each file system syncs without the table locked, as opening does.
+        let file_systems = self.0.lock().file_systems();
+        file_systems.iter().try_for_each(|fs| fs.sync())
     }
 }
 
-// FIXME: Implement `fat32::traits::FileSystem` for `&FileSystem`
-impl fat32::traits::FileSystem for &FileSystem {}
+impl fat32::traits::FileSystem for &FileSystem {
+    type File = vfs::File;
+    type Dir = vfs::Dir;
+    type Entry = vfs::Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let resolved = self.0.lock().resolve(path.as_ref())?;
+        resolved.open()
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let resolved = self.0.lock().resolve(path.as_ref())?;
+        resolved.create_file()
+    }
+}
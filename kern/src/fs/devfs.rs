@@ -0,0 +1,169 @@
+//! The device file system, mounted at `/dev`: devices that can be opened as
+//! files.
+//!
+//! * `console`: the console, as a program's standard input and output are.
+//! * `null`: reads as empty, and discards what is written.
+//! * `zero`: reads as endless zero bytes, and discards what is written.
+//! * `random`: reads as endless random bytes from the hardware generator, and
+//!   discards what is written.
+//!
+//! Devices have no size, and seeking one does nothing.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::Path;
+
+use crate::console::ConsoleFile;
+
+use super::vfs::{DirOps, Entry, FileOps, FileSystemOps, Metadata};
+
+/// The names of the devices, as listed.
+const DEVICES: [&str; 4] = ["console", "null", "random", "zero"];
+
+/// A device open as a file.
+#[derive(Debug)]
+enum Device {
+    Console(ConsoleFile),
+    Null,
+    Zero,
+    Random,
+}
+
+impl Device {
+    /// Opens the device named `name`, if there is one.
+    fn open(name: &str) -> Option<Device> {
+        match name {
+            "console" => Some(Device::Console(ConsoleFile::new())),
+            "null" => Some(Device::Null),
+            "zero" => Some(Device::Zero),
+            "random" => Some(Device::Random),
+            _ => None,
+        }
+    }
+}
+
+impl io::Read for Device {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Device::Console(console) => console.read(buf),
+            Device::Null => Ok(0),
+            Device::Zero => {
+                buf.iter_mut().for_each(|b| *b = 0);
+                Ok(buf.len())
+            }
+            Device::Random => {
+                pi::rng::fill(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+}
+
+impl io::Write for Device {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Device::Console(console) => console.write(buf),
+            Device::Null | Device::Zero | Device::Random => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for Device {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Ok(0)
+    }
+}
+
+impl FileOps for Device {
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The directory of devices: the root of the file system.
+struct Devices;
+
+impl DirOps for Devices {
+    fn entries(&self) -> io::Result<Vec<Entry>> {
+        let devices = DEVICES.iter().filter_map(|&name| {
+            let device = Device::open(name)?;
+            Some(Entry::file(String::from(name), Metadata::default(), device))
+        });
+        Ok(devices.collect())
+    }
+}
+
+/// The device file system. See the module documentation.
+#[derive(Debug, Default)]
+pub struct DevFs;
+
+impl FileSystemOps for DevFs {
+    fn kind(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Entry> {
+        let name = match path.to_str() {
+            Some("/") => return Ok(Entry::dir(String::new(), Metadata::default(), Devices)),
+            Some(path) => path.trim_start_matches('/'),
+            None => return ioerr!(NotFound, "no such device"),
+        };
+
+        match Device::open(name) {
+            Some(device) => Ok(Entry::file(String::from(name), Metadata::default(), device)),
+            None => ioerr!(NotFound, "no such device"),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use shim::io::{Read, Write};
+    use shim::path::Path;
+
+    use fat32::traits::{Dir, Entry, File};
+
+    use super::DevFs;
+    use crate::fs::vfs::FileSystemOps;
+
+    fn open(path: &str) -> crate::fs::vfs::File {
+        let entry = DevFs.open(Path::new(path)).unwrap();
+        entry.into_file().unwrap()
+    }
+
+    #[test]
+    fn devices_listed() {
+        let root = DevFs.open(Path::new("/")).unwrap().into_dir().unwrap();
+        let names: Vec<String> = root.entries().unwrap().map(|e| e.name().into()).collect();
+        assert_eq!(names, ["console", "null", "random", "zero"]);
+        assert!(DevFs.open(Path::new("/disk")).is_err());
+    }
+
+    #[test]
+    fn null_and_zero() {
+        let mut buf = [7u8; 16];
+        let mut null = open("/null");
+        assert_eq!(null.read(&mut buf).unwrap(), 0);
+        assert_eq!(null.write(b"gone").unwrap(), 4);
+        assert_eq!(null.size(), 0);
+
+        let mut zero = open("/zero");
+        assert_eq!(zero.read(&mut buf).unwrap(), 16);
+        assert_eq!(buf, [0; 16]);
+        assert_eq!(zero.write(b"gone").unwrap(), 4);
+    }
+}
@@ -1,7 +1,11 @@
+use core::hint;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 use shim::io;
 use shim::ioerr;
 
+use pi::emmc::{self, Emmc};
+
 use fat32::traits::BlockDevice;
 
 extern "C" {
@@ -29,12 +33,48 @@ extern "C" {
     fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
 }
 
-// FIXME: Define a `#[no_mangle]` `wait_micros` function for use by `libsd`.
-// The `wait_micros` C signature is: `void wait_micros(unsigned int);`
+/// Waits for `us` microseconds. `libsd` calls this as it waits on the SD card
+/// controller; its C signature is `void wait_micros(unsigned int);`.
+#[no_mangle]
+pub extern "C" fn wait_micros(us: u32) {
+    pi::timer::spin_sleep(Duration::from_micros(us as u64));
+}
+
+/// The size, in bytes, of a sector of the SD card.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Whether `Sd::new()` has initialized the SD card controller.
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the card is addressed in sectors, as SDHC and SDXC cards are,
+/// rather than in bytes.
+static SECTOR_ADDRESSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a handle is reading or writing the card. Handles take turns; the
+/// handle the panic handler seizes keeps the card for good.
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// A buffer for one sector, aligned as `sd_readsector()` requires.
+#[repr(align(4))]
+struct SectorBuf([u8; SECTOR_SIZE]);
+
+/// Returns the error that the SD card controller's error code `code` stands
+/// for, given `what` failed.
+fn sd_error(code: i64, what: &'static str) -> io::Error {
+    let kind = match code {
+        -1 => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, what)
+}
 
 /// A handle to an SD card controller.
 #[derive(Debug)]
-pub struct Sd;
+pub struct Sd {
+    /// Whether this is the panic handler's handle, which has the card to
+    /// itself.
+    seized: bool,
+}
 
 impl Sd {
     /// Initializes the SD card controller and returns a handle to it.
@@ -43,7 +83,62 @@ impl Sd {
     /// with atomic memory access, but we can't use it yet since we haven't
     /// written the memory management unit (MMU).
     pub unsafe fn new() -> Result<Sd, io::Error> {
-        unimplemented!("Sd::new()")
+        match sd_init() {
+            0 => {}
+            code => return Err(sd_error(code as i64, "failed to initialize the SD card")),
+        }
+
+        // `libsd` keeps to itself whether the card is addressed in sectors or
+        // in bytes, but the argument of the command that read sector 1 is 1
+        // in the first case and 512 in the second.
+        let mut sd = Sd { seized: false };
+        sd.read_sector(1, &mut [0; SECTOR_SIZE])?;
+        SECTOR_ADDRESSED.store(Emmc::new().last_argument() == 1, Ordering::Relaxed);
+        INITIALIZED.store(true, Ordering::Release);
+        Ok(sd)
+    }
+
+    /// Returns another handle to the SD card controller, if `new()` has
+    /// initialized it.
+    pub fn get() -> Option<Sd> {
+        if INITIALIZED.load(Ordering::Acquire) {
+            Some(Sd { seized: false })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a handle to the SD card controller for the panic handler, if
+    /// it is initialized and no handle is using the card. No other handle
+    /// can use the card from then on.
+    pub fn seize() -> Option<Sd> {
+        let free = INITIALIZED.load(Ordering::Acquire)
+            && BUSY
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok();
+        if free {
+            Some(Sd { seized: true })
+        } else {
+            None
+        }
+    }
+
+    /// Runs `f` with the card to this handle, waiting for any other handle
+    /// using it to finish first.
+    fn exclusive<R>(&self, f: impl FnOnce() -> R) -> R {
+        if self.seized {
+            return f();
+        }
+
+        while BUSY
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        let result = f();
+        BUSY.store(false, Ordering::Release);
+        result
     }
 }
 
@@ -61,10 +156,61 @@ impl BlockDevice for Sd {
     ///
     /// An error of kind `Other` is returned for all other errors.
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
-        unimplemented!("Sd::read_sector()")
+        if buf.len() < SECTOR_SIZE {
+            return ioerr!(InvalidInput, "buffer is smaller than a sector");
+        }
+        if n > i32::max_value() as u64 {
+            return ioerr!(InvalidInput, "sector number is out of range");
+        }
+
+        let mut sector = SectorBuf([0; SECTOR_SIZE]);
+        let read = self.exclusive(|| unsafe { sd_readsector(n as i32, sector.0.as_mut_ptr()) });
+        if read <= 0 {
+            return Err(sd_error(
+                unsafe { sd_err },
+                "failed to read from the SD card",
+            ));
+        }
+
+        buf[..SECTOR_SIZE].copy_from_slice(&sector.0);
+        Ok(SECTOR_SIZE)
     }
 
-    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+    /// Writes `buf[..512]` to sector `n` of the SD card. On success, the
+    /// number of bytes written is returned.
+    ///
+    /// # Errors
+    ///
+    /// An I/O error of kind `InvalidInput` is returned if `buf.len() < 512` or
+    /// sector `n` is past what the card's addressing can reach.
+    ///
+    /// An error of kind `TimedOut` is returned if a timeout occurs while
+    /// writing to the SD card.
+    ///
+    /// An error of kind `Other` is returned for all other errors.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() < SECTOR_SIZE {
+            return ioerr!(InvalidInput, "buffer is smaller than a sector");
+        }
+        let address = if SECTOR_ADDRESSED.load(Ordering::Relaxed) {
+            n
+        } else {
+            n.saturating_mul(SECTOR_SIZE as u64)
+        };
+        if address > u32::max_value() as u64 {
+            return ioerr!(InvalidInput, "sector number is out of range");
+        }
+
+        let mut sector = [0; SECTOR_SIZE];
+        sector.copy_from_slice(&buf[..SECTOR_SIZE]);
+        let written = self.exclusive(|| Emmc::new().write_block(address as u32, &sector));
+        if let Err(e) = written {
+            let code = match e {
+                emmc::Error::TimedOut => -1,
+                emmc::Error::Failed(_) => -2,
+            };
+            return Err(sd_error(code, "failed to write to the SD card"));
+        }
+        Ok(SECTOR_SIZE)
     }
 }
@@ -0,0 +1,455 @@
+//! The temporary file system, mounted at `/tmp`: files and directories kept
+//! on the heap, and lost when the board resets.
+//!
+//! Nothing written to it touches the SD card, so it suits scratch files and
+//! tests, and it is what an initial RAM disk is unpacked into. Files can be
+//! created, truncated and extended, directories created, and either renamed.
+//! Files are dated by the clock when they are created and written, if it has
+//! been set; directories have default metadata.
+//!
+//! A file open more than once is shared: what is written through one
+//! descriptor is read through the others, as is a truncation.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::min;
+
+use shim::io::{self, SeekFrom};
+use shim::ioerr;
+use shim::path::{Component, Path};
+
+use crate::mutex::Mutex;
+
+use super::vfs::{self, DirOps, Entry, FileOps, FileSystemOps, Metadata, Timestamp};
+
+/// The contents of a file.
+type Data = Arc<Mutex<Contents>>;
+
+/// The entries of a directory, by name.
+type Children = Arc<Mutex<BTreeMap<String, Node>>>;
+
+/// A file or directory.
+#[derive(Clone)]
+enum Node {
+    File(Data),
+    Dir(Children),
+}
+
+impl Node {
+    /// Returns an entry named `name` for the node.
+    fn entry(&self, name: &str) -> Entry {
+        let name = String::from(name);
+        match self {
+            Node::File(data) => {
+                let metadata = data.lock().metadata;
+                Entry::file(name, metadata, TmpFile::new(data.clone()))
+            }
+            Node::Dir(children) => Entry::dir(name, Metadata::default(), TmpDir(children.clone())),
+        }
+    }
+}
+
+/// The bytes of a file, and when it was created and last modified.
+struct Contents {
+    bytes: Vec<u8>,
+    metadata: Metadata,
+}
+
+impl Contents {
+    /// Returns the contents of a file created now.
+    fn new() -> Contents {
+        let now = Timestamp::now();
+        Contents {
+            bytes: Vec::new(),
+            metadata: Metadata {
+                created: now,
+                modified: now,
+                ..Metadata::default()
+            },
+        }
+    }
+
+    /// Returns the bytes of the file, marking it modified now.
+    fn modify(&mut self) -> &mut Vec<u8> {
+        self.metadata.modified = Timestamp::now();
+        &mut self.bytes
+    }
+}
+
+/// A file open in the temporary file system.
+struct TmpFile {
+    data: Data,
+    offset: u64,
+}
+
+impl TmpFile {
+    fn new(data: Data) -> TmpFile {
+        TmpFile { data, offset: 0 }
+    }
+}
+
+impl io::Read for TmpFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = &self.data.lock().bytes;
+        let start = min(self.offset, data.len() as u64) as usize;
+        let read = min(buf.len(), data.len() - start);
+        buf[..read].copy_from_slice(&data[start..start + read]);
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl io::Write for TmpFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut contents = self.data.lock();
+        let data = contents.modify();
+        // The file may have been truncated below the offset since the seek.
+        let start = self.offset as usize;
+        if data.len() < start {
+            data.resize(start, 0);
+        }
+
+        let overwritten = min(buf.len(), data.len() - start);
+        data[start..start + overwritten].copy_from_slice(&buf[..overwritten]);
+        data.extend_from_slice(&buf[overwritten..]);
+        self.offset += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for TmpFile {
+    /// Seeks to the offset `pos`, which may be the end of the file but not
+    /// beyond it, as in a FAT32 file.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of the file or beyond its end results in an
+    /// `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let size = self.data.lock().bytes.len() as i128;
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::End(delta) => size + delta as i128,
+            SeekFrom::Current(delta) => self.offset as i128 + delta as i128,
+        };
+
+        if offset < 0 || offset > size {
+            return ioerr!(InvalidInput, "seek outside of the file");
+        }
+
+        self.offset = offset as u64;
+        Ok(self.offset)
+    }
+}
+
+impl FileOps for TmpFile {
+    fn size(&self) -> u64 {
+        self.data.lock().bytes.len() as u64
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        self.data.lock().modify().resize(size as usize, 0);
+        Ok(())
+    }
+}
+
+/// A directory open in the temporary file system.
+struct TmpDir(Children);
+
+impl DirOps for TmpDir {
+    fn entries(&self) -> io::Result<Vec<Entry>> {
+        let children = self.0.lock();
+        Ok(children
+            .iter()
+            .map(|(name, node)| node.entry(name))
+            .collect())
+    }
+}
+
+/// The temporary file system. See the module documentation.
+pub struct TmpFs {
+    root: Children,
+    /// Held while entries are added, replaced or moved, so that each change
+    /// is checked and made at once.
+    changes: Mutex<()>,
+}
+
+impl TmpFs {
+    /// Returns an empty file system.
+    pub fn new() -> TmpFs {
+        TmpFs {
+            root: Arc::new(Mutex::new(BTreeMap::new())),
+            changes: Mutex::new(()),
+        }
+    }
+
+    /// Returns the directory at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if any component of `path` does not name a
+    /// directory.
+    fn dir(&self, path: &Path) -> io::Result<Children> {
+        let mut dir = self.root.clone();
+        for component in path.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_str(),
+                _ => continue,
+            };
+
+            let node = name.and_then(|name| dir.lock().get(name).cloned());
+            dir = match node {
+                Some(Node::Dir(children)) => children,
+                _ => return ioerr!(InvalidInput, "not a directory"),
+            };
+        }
+        Ok(dir)
+    }
+
+    /// Returns the directory `path` is in and its name there, or `None` if
+    /// `path` is the root.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `dir()` does on the parent of `path`.
+    fn parent(&self, path: &Path) -> io::Result<Option<(Children, String)>> {
+        let (parent, name) = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => return Ok(None),
+        };
+
+        match name.to_str() {
+            Some(name) => Ok(Some((self.dir(parent)?, String::from(name)))),
+            None => ioerr!(InvalidInput, "name is not valid UTF-8"),
+        }
+    }
+}
+
+impl Default for TmpFs {
+    fn default() -> TmpFs {
+        TmpFs::new()
+    }
+}
+
+impl FileSystemOps for TmpFs {
+    fn kind(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Entry> {
+        let (dir, name) = match self.parent(path)? {
+            Some(parent) => parent,
+            None => return Ok(Node::Dir(self.root.clone()).entry("")),
+        };
+
+        let node = dir.lock().get(&name).cloned();
+        match node {
+            Some(node) => Ok(node.entry(&name)),
+            None => ioerr!(NotFound, "no such file or directory"),
+        }
+    }
+
+    /// Creates an empty file at `path`. A file already there is truncated,
+    /// so descriptors it is open as see it emptied.
+    fn create_file(&self, path: &Path) -> io::Result<vfs::File> {
+        let _changes = self.changes.lock();
+        let (dir, name) = match self.parent(path)? {
+            Some(parent) => parent,
+            None => return ioerr!(Other, "is a directory"),
+        };
+
+        let existing = dir.lock().get(&name).cloned();
+        let data = match existing {
+            Some(Node::Dir(_)) => return ioerr!(Other, "is a directory"),
+            Some(Node::File(data)) => {
+                data.lock().modify().clear();
+                data
+            }
+            None => {
+                let data: Data = Arc::new(Mutex::new(Contents::new()));
+                dir.lock().insert(name, Node::File(data.clone()));
+                data
+            }
+        };
+        Ok(vfs::File::new(TmpFile::new(data)))
+    }
+
+    /// Creates an empty directory at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AlreadyExists` if there is an entry at `path`.
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let _changes = self.changes.lock();
+        let (dir, name) = match self.parent(path)? {
+            Some(parent) => parent,
+            None => return ioerr!(AlreadyExists, "directory exists"),
+        };
+
+        let mut children = dir.lock();
+        if children.contains_key(&name) {
+            return ioerr!(AlreadyExists, "entry exists");
+        }
+        children.insert(name, Node::Dir(Arc::new(Mutex::new(BTreeMap::new()))));
+        Ok(())
+    }
+
+    /// Moves the entry at `from` to `to`, replacing the file there if one
+    /// exists. Descriptors the entry is open as stay open.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if there is no entry at `from`, `AlreadyExists` if
+    /// a directory is at `to` or a directory is moved onto a file, and
+    /// `InvalidInput` if either path is the root or a directory is moved into
+    /// itself.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let _changes = self.changes.lock();
+        let ((from_dir, from_name), (to_dir, to_name)) =
+            match (self.parent(from)?, self.parent(to)?) {
+                (Some(from), Some(to)) => (from, to),
+                _ => return ioerr!(InvalidInput, "cannot rename the root"),
+            };
+
+        let node = match from_dir.lock().get(&from_name).cloned() {
+            Some(node) => node,
+            None => return ioerr!(NotFound, "no such file or directory"),
+        };
+        if from == to {
+            return Ok(());
+        }
+        if to.starts_with(from) {
+            return ioerr!(InvalidInput, "cannot move a directory into itself");
+        }
+
+        let replaced = to_dir.lock().get(&to_name).cloned();
+        match (&node, replaced) {
+            (_, Some(Node::Dir(_))) => return ioerr!(AlreadyExists, "is a directory"),
+            (Node::Dir(_), Some(Node::File(_))) => return ioerr!(AlreadyExists, "file exists"),
+            _ => (),
+        }
+
+        from_dir.lock().remove(&from_name);
+        to_dir.lock().insert(to_name, node);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use shim::io::{self, Read, Seek, SeekFrom, Write};
+    use shim::path::Path;
+
+    use fat32::traits::{Dir, Entry, File};
+
+    use super::TmpFs;
+    use crate::fs::vfs::{self, FileSystemOps};
+
+    fn open(fs: &TmpFs, path: &str) -> io::Result<vfs::File> {
+        let entry = fs.open(Path::new(path))?;
+        Ok(entry.into_file().expect("not a file"))
+    }
+
+    fn read_all(file: &mut vfs::File) -> Vec<u8> {
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    fn names(fs: &TmpFs, path: &str) -> Vec<String> {
+        let dir = fs.open(Path::new(path)).unwrap().into_dir().unwrap();
+        dir.entries().unwrap().map(|e| e.name().into()).collect()
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let fs = TmpFs::new();
+        assert!(names(&fs, "/").is_empty());
+
+        fs.create_dir(Path::new("/logs")).unwrap();
+        assert!(fs.create_dir(Path::new("/logs")).is_err());
+        assert!(fs.create_dir(Path::new("/missing/logs")).is_err());
+
+        let mut log = fs.create_file(Path::new("/logs/boot")).unwrap();
+        log.write_all(b"hello").unwrap();
+        fs.create_file(Path::new("/a")).unwrap();
+        assert_eq!(names(&fs, "/"), ["a", "logs"]);
+        assert_eq!(names(&fs, "/logs"), ["boot"]);
+
+        let mut boot = open(&fs, "/logs/boot").unwrap();
+        assert_eq!(boot.size(), 5);
+        assert_eq!(read_all(&mut boot), b"hello");
+        assert!(boot.seek(SeekFrom::Start(6)).is_err());
+
+        assert!(fs.create_file(Path::new("/logs")).is_err());
+        assert!(fs.open(Path::new("/logs/boot/x")).is_err());
+        assert_eq!(
+            fs.open(Path::new("/logs/none")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn shared_and_truncated() {
+        let fs = TmpFs::new();
+        let mut writer = fs.create_file(Path::new("/f")).unwrap();
+        let mut reader = open(&fs, "/f").unwrap();
+
+        writer.write_all(b"abcdef").unwrap();
+        writer.seek(SeekFrom::Start(2)).unwrap();
+        writer.write_all(b"XY").unwrap();
+        assert_eq!(read_all(&mut reader), b"abXYef");
+
+        writer.set_len(3).unwrap();
+        assert_eq!(read_all(&mut reader), b"abX");
+        writer.write_all(b"!").unwrap();
+        assert_eq!(read_all(&mut reader), b"abX\0!");
+
+        reader.set_len(7).unwrap();
+        assert_eq!(read_all(&mut writer), b"abX\0!\0\0");
+
+        fs.create_file(Path::new("/f")).unwrap();
+        assert_eq!(reader.size(), 0);
+    }
+
+    #[test]
+    fn renamed() {
+        let fs = TmpFs::new();
+        fs.create_dir(Path::new("/d")).unwrap();
+        fs.create_dir(Path::new("/e")).unwrap();
+        fs.create_file(Path::new("/d/f"))
+            .unwrap()
+            .write_all(b"f")
+            .unwrap();
+        fs.create_file(Path::new("/g")).unwrap();
+
+        fs.rename(Path::new("/d/f"), Path::new("/e/h")).unwrap();
+        assert!(names(&fs, "/d").is_empty());
+        assert_eq!(read_all(&mut open(&fs, "/e/h").unwrap()), b"f");
+
+        fs.rename(Path::new("/e/h"), Path::new("/g")).unwrap();
+        assert_eq!(read_all(&mut open(&fs, "/g").unwrap()), b"f");
+
+        fs.rename(Path::new("/e"), Path::new("/d/e")).unwrap();
+        assert_eq!(names(&fs, "/"), ["d", "g"]);
+        assert!(fs.rename(Path::new("/d"), Path::new("/d/e/d")).is_err());
+        assert!(fs.rename(Path::new("/d"), Path::new("/g")).is_err());
+        assert!(fs.rename(Path::new("/g"), Path::new("/d")).is_err());
+        assert!(fs.rename(Path::new("/none"), Path::new("/x")).is_err());
+        assert!(fs.rename(Path::new("/"), Path::new("/x")).is_err());
+    }
+}
@@ -0,0 +1,722 @@
+//! The virtual file system: one namespace of paths over every file system
+//! mounted.
+//!
+//! A file system is mounted at an absolute path, its mount point, and names
+//! everything below it that no file system mounted deeper does. A path is
+//! resolved by finding the deepest mount point it is under and opening the
+//! rest of it in the file system mounted there, so paths cross from one file
+//! system into another where a mount point is. A directory lists the mount
+//! points directly inside it as subdirectories, hiding any entries of its own
+//! with the same names.
+//!
+//! The types here implement the traits of `fat32::traits`, so the rest of the
+//! kernel uses the virtual file system as it would any one file system.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::{Component, Path, PathBuf};
+
+use fat32::traits;
+
+use crate::clock::{self, DateTime};
+
+/// A file system that can be mounted. The paths given to it are absolute,
+/// relative to its own root, and have no `.` or `..` components.
+pub trait FileSystemOps: Send + Sync {
+    /// A short name for the kind of file system, such as `fat32`.
+    fn kind(&self) -> &'static str;
+
+    /// Opens the entry at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails as `fat32::traits::FileSystem::open()` does.
+    fn open(&self, path: &Path) -> io::Result<Entry>;
+
+    /// Creates an empty file at `path`, replacing the file there if one
+    /// exists, and opens it.
+    ///
+    /// The default implementation supports read-only file systems: it always
+    /// returns an error kind of `Other`.
+    fn create_file(&self, path: &Path) -> io::Result<File> {
+        let _ = path;
+        ioerr!(Other, "read-only file system")
+    }
+
+    /// Creates an empty directory at `path`.
+    ///
+    /// The default implementation supports read-only file systems: it always
+    /// returns an error kind of `Other`.
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let _ = path;
+        ioerr!(Other, "read-only file system")
+    }
+
+    /// Moves the entry at `from` to `to`, replacing the file there if one
+    /// exists.
+    ///
+    /// The default implementation supports read-only file systems: it always
+    /// returns an error kind of `Other`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let _ = (from, to);
+        ioerr!(Other, "read-only file system")
+    }
+
+    /// Writes any changes cached in memory back to the disk.
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A file open in a mounted file system.
+pub trait FileOps: io::Read + io::Write + io::Seek + Send {
+    /// Returns the size of the file in bytes.
+    fn size(&self) -> u64;
+
+    /// Writes any buffered data to disk.
+    fn sync(&mut self) -> io::Result<()>;
+
+    /// Truncates the file to `size` bytes, or extends it to `size` bytes with
+    /// zeroes. The offset is left as it is.
+    ///
+    /// The default implementation supports read-only file systems: it always
+    /// returns an error kind of `Other`.
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        let _ = size;
+        ioerr!(Other, "read-only file system")
+    }
+}
+
+/// A directory of a mounted file system.
+pub trait DirOps: Send {
+    /// Returns the entries of the directory.
+    fn entries(&self) -> io::Result<Vec<Entry>>;
+}
+
+/// A point in time, as any file system's `fat32::traits::Timestamp` gives it.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: usize,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    /// Returns the timestamp `timestamp` stands for.
+    pub fn of<T: traits::Timestamp>(timestamp: T) -> Timestamp {
+        Timestamp {
+            year: timestamp.year(),
+            month: timestamp.month(),
+            day: timestamp.day(),
+            hour: timestamp.hour(),
+            minute: timestamp.minute(),
+            second: timestamp.second(),
+        }
+    }
+
+    /// Returns the current time, or the default timestamp if the clock has
+    /// not been set.
+    pub fn now() -> Timestamp {
+        clock::now().map(Timestamp::from).unwrap_or_default()
+    }
+}
+
+impl From<DateTime> for Timestamp {
+    fn from(datetime: DateTime) -> Timestamp {
+        Timestamp {
+            year: datetime.year as usize,
+            month: datetime.month,
+            day: datetime.day,
+            hour: datetime.hour,
+            minute: datetime.minute,
+            second: datetime.second,
+        }
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        self.year
+    }
+
+    fn month(&self) -> u8 {
+        self.month
+    }
+
+    fn day(&self) -> u8 {
+        self.day
+    }
+
+    fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+/// The metadata of an entry, as any file system's `fat32::traits::Metadata`
+/// gives it.
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Metadata {
+    pub read_only: bool,
+    pub hidden: bool,
+    pub created: Timestamp,
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl Metadata {
+    /// Returns the metadata `metadata` stands for.
+    pub fn of<M: traits::Metadata>(metadata: &M) -> Metadata {
+        Metadata {
+            read_only: metadata.read_only(),
+            hidden: metadata.hidden(),
+            created: Timestamp::of(metadata.created()),
+            accessed: Timestamp::of(metadata.accessed()),
+            modified: Timestamp::of(metadata.modified()),
+        }
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn created(&self) -> Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Timestamp {
+        self.modified
+    }
+}
+
+/// A file open in the virtual file system.
+pub struct File(Box<dyn FileOps>);
+
+impl File {
+    /// Returns `file`, opened in a mounted file system, as a file of the
+    /// virtual file system.
+    pub fn new<F: FileOps + 'static>(file: F) -> File {
+        File(Box::new(file))
+    }
+
+    /// Truncates the file to `size` bytes, or extends it to `size` bytes with
+    /// zeroes. See `FileOps::set_len()`.
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        self.0.set_len(size)
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("File")
+            .field("size", &self.0.size())
+            .finish()
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl io::Seek for File {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.0.sync()
+    }
+
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+}
+
+/// A file system mounted directly inside a directory, under the name `name`.
+#[derive(Clone)]
+struct Child {
+    name: String,
+    fs: Arc<dyn FileSystemOps>,
+}
+
+/// A directory of the virtual file system.
+pub struct Dir {
+    dir: Box<dyn DirOps>,
+    /// The file systems mounted directly inside the directory.
+    children: Vec<Child>,
+}
+
+impl Dir {
+    /// Returns `dir`, of a mounted file system, as a directory of the virtual
+    /// file system.
+    pub fn new<D: DirOps + 'static>(dir: D) -> Dir {
+        Dir {
+            dir: Box::new(dir),
+            children: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Dir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let children: Vec<&str> = self.children.iter().map(|c| c.name.as_str()).collect();
+        f.debug_struct("Dir").field("mounts", &children).finish()
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = alloc::vec::IntoIter<Entry>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let mut entries = self.dir.entries()?;
+        entries.retain(|entry| !self.children.iter().any(|c| c.name == entry.name));
+        for child in &self.children {
+            let mut root = child.fs.open(Path::new("/"))?;
+            root.name = child.name.clone();
+            entries.push(root);
+        }
+        Ok(entries.into_iter())
+    }
+}
+
+/// What an entry is.
+#[derive(Debug)]
+enum Node {
+    File(File),
+    Dir(Dir),
+}
+
+/// An entry of the virtual file system: a file or a directory, with its name
+/// and metadata.
+#[derive(Debug)]
+pub struct Entry {
+    name: String,
+    metadata: Metadata,
+    node: Node,
+}
+
+impl Entry {
+    /// Returns an entry for the file `file`.
+    pub fn file<F: FileOps + 'static>(name: String, metadata: Metadata, file: F) -> Entry {
+        Entry {
+            name,
+            metadata,
+            node: Node::File(File::new(file)),
+        }
+    }
+
+    /// Returns an entry for the directory `dir`.
+    pub fn dir<D: DirOps + 'static>(name: String, metadata: Metadata, dir: D) -> Entry {
+        Entry {
+            name,
+            metadata,
+            node: Node::Dir(Dir::new(dir)),
+        }
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match self.node {
+            Node::File(ref file) => Some(file),
+            Node::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match self.node {
+            Node::File(_) => None,
+            Node::Dir(ref dir) => Some(dir),
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self.node {
+            Node::File(file) => Some(file),
+            Node::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self.node {
+            Node::File(_) => None,
+            Node::Dir(dir) => Some(dir),
+        }
+    }
+}
+
+/// Returns the absolute path `path` with its `.` and `..` components resolved
+/// lexically. `..` at the root stays at the root.
+///
+/// # Errors
+///
+/// Returns `InvalidInput` if `path` is not absolute.
+pub fn normalize(path: &Path) -> io::Result<PathBuf> {
+    if !path.is_absolute() {
+        return ioerr!(InvalidInput, "path is not absolute");
+    }
+
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+    Ok(normalized)
+}
+
+/// A file system mounted at `point`.
+struct Mount {
+    point: PathBuf,
+    fs: Arc<dyn FileSystemOps>,
+}
+
+/// The file systems mounted, by mount point.
+pub struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+/// A path resolved to the file system that names it. See
+/// `MountTable::resolve()`.
+pub struct Resolved {
+    fs: Arc<dyn FileSystemOps>,
+    /// The mount point of `fs`.
+    point: PathBuf,
+    /// The path in `fs`.
+    path: PathBuf,
+    /// The path in the virtual file system.
+    full_path: PathBuf,
+    /// The file systems mounted directly inside the path.
+    children: Vec<Child>,
+}
+
+impl MountTable {
+    /// Returns a table with nothing mounted.
+    pub const fn new() -> MountTable {
+        MountTable { mounts: Vec::new() }
+    }
+
+    /// Mounts `fs` at the absolute path `point`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if `point` is not absolute, or `AlreadyExists`
+    /// if a file system is already mounted there.
+    pub fn mount(&mut self, point: &Path, fs: Arc<dyn FileSystemOps>) -> io::Result<()> {
+        let point = normalize(point)?;
+        if self.mounts.iter().any(|mount| mount.point == point) {
+            return ioerr!(AlreadyExists, "a file system is already mounted there");
+        }
+
+        self.mounts.push(Mount { point, fs });
+        Ok(())
+    }
+
+    /// Unmounts the file system mounted at `point`, returning it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if `point` is not absolute, or `NotFound` if no
+    /// file system is mounted there.
+    pub fn unmount(&mut self, point: &Path) -> io::Result<Arc<dyn FileSystemOps>> {
+        let point = normalize(point)?;
+        match self.mounts.iter().position(|mount| mount.point == point) {
+            Some(i) => Ok(self.mounts.remove(i).fs),
+            None => ioerr!(NotFound, "no file system is mounted there"),
+        }
+    }
+
+    /// Returns the mount point and kind of each file system mounted, in the
+    /// order they were mounted.
+    pub fn mounts(&self) -> Vec<(PathBuf, &'static str)> {
+        let mounts = self.mounts.iter();
+        mounts.map(|m| (m.point.clone(), m.fs.kind())).collect()
+    }
+
+    /// Returns every file system mounted.
+    pub fn file_systems(&self) -> Vec<Arc<dyn FileSystemOps>> {
+        self.mounts.iter().map(|mount| mount.fs.clone()).collect()
+    }
+
+    /// Resolves the absolute path `path` to the file system mounted at the
+    /// deepest mount point it is under and the path in that file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if `path` is not absolute, or `NotFound` if no
+    /// file system is mounted at a mount point it is under.
+    pub fn resolve(&self, path: &Path) -> io::Result<Resolved> {
+        let full_path = normalize(path)?;
+        let mount = self
+            .mounts
+            .iter()
+            .filter(|mount| full_path.starts_with(&mount.point))
+            .max_by_key(|mount| mount.point.components().count());
+
+        let mount = match mount {
+            Some(mount) => mount,
+            None => return ioerr!(NotFound, "no file system is mounted there"),
+        };
+
+        let rest = full_path
+            .strip_prefix(&mount.point)
+            .unwrap_or(Path::new(""));
+        let children = self
+            .mounts
+            .iter()
+            .filter(|m| m.point.parent() == Some(full_path.as_path()))
+            .filter_map(|m| {
+                let name = m.point.file_name()?.to_str()?;
+                Some(Child {
+                    name: String::from(name),
+                    fs: m.fs.clone(),
+                })
+            })
+            .collect();
+
+        Ok(Resolved {
+            fs: mount.fs.clone(),
+            point: mount.point.clone(),
+            path: Path::new("/").join(rest),
+            full_path,
+            children,
+        })
+    }
+}
+
+impl Resolved {
+    /// Opens the entry at the path. A directory lists the file systems
+    /// mounted directly inside it.
+    pub fn open(self) -> io::Result<Entry> {
+        let mut entry = self.fs.open(&self.path)?;
+        if self.path.parent().is_none() {
+            // The root of a mounted file system is named by its mount point.
+            let name = self.full_path.file_name().and_then(|name| name.to_str());
+            entry.name = String::from(name.unwrap_or(""));
+        }
+
+        if let Node::Dir(ref mut dir) = entry.node {
+            dir.children = self.children;
+        }
+        Ok(entry)
+    }
+
+    /// Creates an empty file at the path, replacing the file there if one
+    /// exists, and opens it.
+    pub fn create_file(self) -> io::Result<File> {
+        if self.path.parent().is_none() || !self.children.is_empty() {
+            return ioerr!(Other, "is a directory");
+        }
+        self.fs.create_file(&self.path)
+    }
+
+    /// Creates an empty directory at the path.
+    pub fn create_dir(self) -> io::Result<()> {
+        if self.path.parent().is_none() || !self.children.is_empty() {
+            return ioerr!(AlreadyExists, "is a mount point");
+        }
+        self.fs.create_dir(&self.path)
+    }
+
+    /// Moves the entry at the path to the path `to`, replacing the file there
+    /// if one exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if either path is a mount point or is in a
+    /// directory with file systems mounted in it, or if `to` is in another
+    /// file system.
+    pub fn rename(self, to: Resolved) -> io::Result<()> {
+        if self.point != to.point {
+            return ioerr!(InvalidInput, "cannot rename across file systems");
+        }
+        let mounted = |r: &Resolved| r.path.parent().is_none() || !r.children.is_empty();
+        if mounted(&self) || mounted(&to) {
+            return ioerr!(InvalidInput, "is a mount point");
+        }
+        self.fs.rename(&self.path, &to.path)
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::string::String;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    use shim::io;
+    use shim::path::Path;
+
+    use fat32::traits::{Dir, Entry as _};
+
+    use super::{normalize, DirOps, Entry, FileSystemOps, Metadata, MountTable};
+
+    /// A file system of one directory, holding empty directories named
+    /// `names`.
+    struct Flat {
+        kind: &'static str,
+        names: &'static [&'static str],
+    }
+
+    impl DirOps for &'static [&'static str] {
+        fn entries(&self) -> io::Result<Vec<Entry>> {
+            let entries = self.iter().map(|&name| {
+                let empty: &'static [&'static str] = &[];
+                Entry::dir(String::from(name), Metadata::default(), empty)
+            });
+            Ok(entries.collect())
+        }
+    }
+
+    impl FileSystemOps for Flat {
+        fn kind(&self) -> &'static str {
+            self.kind
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Entry> {
+            match path.to_str() {
+                Some("/") => Ok(Entry::dir(String::new(), Metadata::default(), self.names)),
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+            }
+        }
+    }
+
+    fn names(table: &MountTable, path: &str) -> Vec<String> {
+        let entry = table.resolve(Path::new(path)).unwrap().open().unwrap();
+        let entries = entry.into_dir().unwrap().entries().unwrap();
+        let mut names: Vec<String> = entries.map(|e| String::from(e.name())).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn normalized_paths() {
+        let normalize = |path| normalize(Path::new(path)).unwrap();
+        assert_eq!(normalize("/"), Path::new("/"));
+        assert_eq!(normalize("/a/./b/../c/"), Path::new("/a/c"));
+        assert_eq!(normalize("/../.."), Path::new("/"));
+        assert!(super::normalize(Path::new("a/b")).is_err());
+    }
+
+    #[test]
+    fn deepest_mount_point() {
+        let mut table = MountTable::new();
+        let root = Arc::new(Flat {
+            kind: "root",
+            names: &["bin", "dev"],
+        });
+        let dev = Arc::new(Flat {
+            kind: "dev",
+            names: &["null"],
+        });
+
+        assert!(table.resolve(Path::new("/bin")).is_err());
+        table.mount(Path::new("/"), root.clone()).unwrap();
+        table.mount(Path::new("/dev"), dev).unwrap();
+        assert!(table.mount(Path::new("/dev/"), root).is_err());
+
+        let resolved = table.resolve(Path::new("/dev/null")).unwrap();
+        assert_eq!(resolved.fs.kind(), "dev");
+        assert_eq!(resolved.path, Path::new("/null"));
+
+        let resolved = table.resolve(Path::new("/device")).unwrap();
+        assert_eq!(resolved.fs.kind(), "root");
+        assert_eq!(resolved.path, Path::new("/device"));
+
+        let resolved = table.resolve(Path::new("/dev/../bin")).unwrap();
+        assert_eq!(resolved.fs.kind(), "root");
+        assert_eq!(resolved.path, Path::new("/bin"));
+
+        assert_eq!(table.unmount(Path::new("/dev")).unwrap().kind(), "dev");
+        assert_eq!(
+            table.resolve(Path::new("/dev/null")).unwrap().fs.kind(),
+            "root"
+        );
+        assert!(table.unmount(Path::new("/dev")).is_err());
+    }
+
+    #[test]
+    fn mount_points_listed() {
+        let mut table = MountTable::new();
+        let root = Arc::new(Flat {
+            kind: "root",
+            names: &["bin", "dev"],
+        });
+        let tmp = Arc::new(Flat {
+            kind: "tmp",
+            names: &["scratch"],
+        });
+
+        table.mount(Path::new("/"), root).unwrap();
+        table.mount(Path::new("/dev"), tmp.clone()).unwrap();
+        table.mount(Path::new("/tmp"), tmp).unwrap();
+        assert_eq!(names(&table, "/"), ["bin", "dev", "tmp"]);
+        assert_eq!(names(&table, "/tmp"), ["scratch"]);
+
+        let entry = table.resolve(Path::new("/tmp")).unwrap().open().unwrap();
+        assert_eq!(entry.name(), "tmp");
+        assert!(entry.is_dir());
+
+        let kinds: Vec<&str> = table.mounts().iter().map(|m| m.1).collect();
+        assert_eq!(kinds, ["root", "tmp", "tmp"]);
+    }
+}
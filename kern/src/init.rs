@@ -1,14 +1,26 @@
+use boot_protocol::{ImageHeader, KERNEL_LOAD_ADDR};
 use core::mem::zeroed;
 use core::ptr::write_volatile;
 use core::arch::global_asm;
+use pi::common::{Soc, SOC};
 
 mod panic;
 mod oom;
+mod stage;
 
 use crate::kmain;
 
 global_asm!(include_str!("init/init.s"));
 
+// `init.s` and `vm` lay out the address space for the Pi 3's peripherals.
+const _: () = assert!(matches!(SOC, Soc::Bcm2837), "the kernel only runs on the BCM2837");
+
+/// The header the kernel starts with, which `layout.ld` puts before `_start`.
+/// The bootloader and the host tools check it before loading the kernel.
+#[used]
+#[link_section = ".text.header"]
+static HEADER: ImageHeader = ImageHeader::new(KERNEL_LOAD_ADDR);
+
 unsafe fn zeros_bss() {
     extern "C" {
         static mut __bss_beg: u64;
@@ -26,6 +38,13 @@ unsafe fn zeros_bss() {
 
 #[no_mangle]
 unsafe fn kinit() -> ! {
-    zeros_bss();
+    stage::run();
+    #[cfg(test)]
+    crate::test_main();
     kmain();
+}
+
+#[no_mangle]
+unsafe fn kinit_secondary() -> ! {
+    crate::smp::kmain_secondary();
 }
\ No newline at end of file
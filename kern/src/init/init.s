@@ -7,25 +7,50 @@
 
 .global _start
 _start:
-    // read cpu affinity, start core 0, halt rest
-    mrs     x1, MPIDR_EL1
-    and     x1, x1, #3
-    cbz     x1, setup
+    // read cpu affinity into x20, which is kept until `go_kmain`. start core
+    // 0, on a stack growing down from the load address, and park the rest
+    mrs     x20, MPIDR_EL1
+    and     x20, x20, #3
+    cbnz    x20, park
+    adr     x1, __text_beg
+    b       setup
+
+// Parks a secondary core started at `_start` as the firmware parks the others:
+// waits for an entry point in the core's spin table slot, at
+// `pi::common::SPINNING_BASE` + 8 * core, and jumps to it.
+park:
+    mov     x2, #0xd8
+    add     x2, x2, x20, lsl #3
+1:  wfe
+    ldr     x3, [x2]
+    cbz     x3, 1b
+    br      x3
 
 halt:
-    // core affinity != 0, halt it
     wfe
     b       halt
 
-setup:
-    // store the desired EL1 stack pointer in x1
-    adr     x1, _start
+// Where `smp::start_cores()` releases a secondary core to: sets it up as core
+// 0 is, on the stack whose physical address `__core_stacks` holds for it.
+.global _start_secondary
+_start_secondary:
+    mrs     x20, MPIDR_EL1
+    and     x20, x20, #3
+    adrp    x1, __core_stacks
+    add     x1, x1, :lo12:__core_stacks
+    ldr     x1, [x1, x20, lsl #3]
 
+// Sets up the core with its stack pointer in x1 and its number in x20.
+setup:
     // read the current exception level into x0 (ref: C5.2.1)
     mrs     x0, CurrentEL
     and     x0, x0, #0b1100
     lsr     x0, x0, #2
 
+    // remember the entry exception level for `aarch64::entry_el()`. x19 is
+    // preserved across the exception returns below.
+    mov     x19, x0
+
 switch_to_el2:
     // switch to EL2 if we're in EL3. otherwise switch to EL1
     cmp     x0, 0b11            // EL3
@@ -61,66 +86,425 @@ switch_to_el1:
     mov     x0, #(1 << 31)      // Enable AArch64 for EL1
     orr     x0, x0, #(1 << 1)   // RES1 on A-53
     msr     HCR_EL2, x0
-    mrs     x0, HCR_EL2
 
-    // enable floating point and SVE (SIMD) (A53: 4.3.38, 4.3.34)
-    msr     CPTR_EL2, xzr     // don't trap accessing SVE registers
+    // don't trap EL1's floating point, SIMD or CP15 accesses to EL2
+    // (A53: 4.3.38, 4.3.37)
+    msr     CPTR_EL2, xzr
+    msr     HSTR_EL2, xzr
+
+    // let EL1 see the real processor and core IDs (A53: 4.3.32, 4.3.33)
+    mrs     x2, MIDR_EL1
+    msr     VPIDR_EL2, x2
+    mrs     x2, MPIDR_EL1
+    msr     VMPIDR_EL2, x2
+
+    // change execution level to EL1 (ref: C5.2.19)
+    mov     x2, #0x3c5
+    msr     SPSR_EL2, x2
+    adr     x2, set_stack
+    msr     ELR_EL2, x2
+    eret
+
+set_stack:
+    // set the current stack pointer
+    mov     sp, x1
+
+    // the rest of the EL1 set-up happens at EL1, so that it is also done when
+    // the firmware enters the kernel at EL1 directly
+
+    // enable floating point and SIMD (A53: 4.3.34)
     mrs     x0, CPACR_EL1
     orr     x0, x0, #(0b11 << 20)
     msr     CPACR_EL1, x0
 
+    // generate the timer's event stream: an event each time bit 9 of the
+    // counter goes from 0 to 1, every 53 us at 19.2 MHz, so that a core
+    // waiting in `wfe` wakes at least that often (ref: CNTKCTL_EL1)
+    mrs     x0, CNTKCTL_EL1
+    bic     x0, x0, #0xf8
+    mov     x2, #((9 << 4) | (1 << 2))
+    orr     x0, x0, x2
+    msr     CNTKCTL_EL1, x0
+
     // Set SCTLR to known state (RES1: 11, 20, 22, 23, 28, 29) (A53: 4.3.30)
     mov     x2, #0x0800
     movk    x2, #0x30d0, lsl #16
     msr     SCTLR_EL1, x2
 
-    // set up exception handlers
-    // FIXME: load `_vectors` addr into appropriate register (guide: 10.4)
+    // core 0 sets up what the cores share; the others turn on their MMU with
+    // the boot tables it built
+    cbnz    x20, enable_mmu
+    adrp    x2, __entry_el
+    str     x19, [x2, #:lo12:__entry_el]
 
-    // change execution level to EL1 (ref: C5.2.19)
-    mov     x2, #0x3c5
-    msr     SPSR_EL2, x2
-    // FIXME: Return to EL1 at `set_stack`.
+// Builds the boot translation tables, which map the first 2 GiB of physical
+// memory both at their physical addresses, through TTBR0, and at
+// `vm::KERNEL_BASE`, through TTBR1, as `vm::IdentityMap` does. Until the MMU
+// is on, everything here must be addressed relative to the pc.
+boot_tables:
+    adrp    x0, __boot_l1
+    adrp    x1, __boot_l2
 
-set_stack:
-    // set the current stack pointer
-    mov     sp, x1
+    // level 2: 2 MiB blocks, normal memory below IO_BASE and device above.
+    // the normal memory is writable and executable until `vm::VMManager`
+    // replaces these tables with ones that keep the two apart
+    ldr     x3, =0x0040000000000701     // normal, kernel RW, UXN
+    ldr     x4, =0x0060000000000605     // device, kernel RW, PXN, UXN
+    mov     x5, #0x3F000000             // IO_BASE
+    mov     x7, #0x40000000             // 1 GiB
+    mov     x2, xzr
+1:  cmp     x2, x5
+    csel    x6, x3, x4, lo
+    orr     x6, x6, x2
+    str     x6, [x1], #8
+    add     x2, x2, #(2 << 20)
+    cmp     x2, x7
+    b.lo    1b
+    sub     x1, x1, #4096
+
+    // level 1: the level 2 table, the local peripherals, then nothing
+    orr     x6, x1, #0b11
+    str     x6, [x0]
+    orr     x6, x4, x7
+    str     x6, [x0, #8]
+    add     x2, x0, #16
+    add     x3, x0, #4096
+2:  str     xzr, [x2], #8
+    cmp     x2, x3
+    b.lo    2b
 
-// zero_bss:
-//     // load the start address and number of bytes in BSS section
-//     ldr     x1, =__bss_start
-//     ldr     x2, =__bss_length
+enable_mmu:
+    adrp    x0, __boot_l1
 
-// zero_bss_loop:
-//     // zero out the BSS section, 64-bits at a time
-//     cbz     x2, go_kmain
-//     str     xzr, [x1], #8
-//     sub     x2, x2, #8
-//     cbnz    x2, zero_bss_loop
+    // memory types and translation control: see `vm::MAIR` and `vm::TCR`
+    ldr     x2, =0x4404ff
+    msr     MAIR_EL1, x2
+    ldr     x2, =0xb5193519
+    msr     TCR_EL1, x2
+    msr     TTBR0_EL1, x0
+    msr     TTBR1_EL1, x0
+    dsb     ish
+    tlbi    vmalle1
+    dsb     ish
+    isb
+
+    // turn on the MMU and the data and instruction caches
+    mrs     x2, SCTLR_EL1
+    orr     x2, x2, #(1 << 0)
+    orr     x2, x2, #(1 << 2)
+    orr     x2, x2, #(1 << 12)
+    msr     SCTLR_EL1, x2
+    isb
+
+    // move the stack and the pc to their aliases at the kernel's virtual
+    // addresses, where the kernel is linked
+    ldr     x2, =KERNEL_BASE
+    add     sp, sp, x2
+    ldr     x2, =go_kmain
+    br      x2
 
 go_kmain:
-    // jump to kmain, which shouldn't return. halt if it does
+    // set up exception handlers
+    ldr     x2, =_vectors
+    msr     VBAR_EL1, x2
+    isb
+
+    // jump to kmain, or kmain_secondary on the other cores, which shouldn't
+    // return. halt if they do
+    cbnz    x20, 1f
     bl      kinit
     b       halt
+1:  bl      kinit_secondary
+    b       halt
 
+// Saves the trap frame (see `traps::TrapFrame`) below the `lr` and `x0`
+// pushed by the vector and calls `handle_exception(info, esr, frame)`, where
+// `info` is in x0. Returns to the vector with the frame `handle_exception`
+// returns restored, calling `exception_restoring(info)` first, once on its
+// stack. That is a frame some vector saved on another kernel stack
+// to switch processes, since every vector but the FIQ's, which never
+// switches, returns the same way.
 context_save:
-    // FIXME: Save the remaining context to the stack.
+    sub     sp, sp, #800
+
+    stp     x1, x2, [sp, #40]
+    stp     x3, x4, [sp, #56]
+    stp     x5, x6, [sp, #72]
+    stp     x7, x8, [sp, #88]
+    stp     x9, x10, [sp, #104]
+    stp     x11, x12, [sp, #120]
+    stp     x13, x14, [sp, #136]
+    stp     x15, x16, [sp, #152]
+    stp     x17, x18, [sp, #168]
+    stp     x19, x20, [sp, #184]
+    stp     x21, x22, [sp, #200]
+    stp     x23, x24, [sp, #216]
+    stp     x25, x26, [sp, #232]
+    stp     x27, x28, [sp, #248]
+    str     x29, [sp, #264]
+
+    // the vector pushed the interrupted lr and x0 above the frame
+    ldr     x1, [sp, #800]
+    ldr     x2, [sp, #808]
+    str     x1, [sp, #272]
+    str     x2, [sp, #32]
+
+    stp     q0, q1, [sp, #288]
+    stp     q2, q3, [sp, #320]
+    stp     q4, q5, [sp, #352]
+    stp     q6, q7, [sp, #384]
+    stp     q8, q9, [sp, #416]
+    stp     q10, q11, [sp, #448]
+    stp     q12, q13, [sp, #480]
+    stp     q14, q15, [sp, #512]
+    stp     q16, q17, [sp, #544]
+    stp     q18, q19, [sp, #576]
+    stp     q20, q21, [sp, #608]
+    stp     q22, q23, [sp, #640]
+    stp     q24, q25, [sp, #672]
+    stp     q26, q27, [sp, #704]
+    stp     q28, q29, [sp, #736]
+    stp     q30, q31, [sp, #768]
 
+    mrs     x1, ELR_EL1
+    mrs     x2, SPSR_EL1
+    stp     x1, x2, [sp, #0]
+    mrs     x1, SP_EL0
+    mrs     x2, TPIDR_EL0
+    stp     x1, x2, [sp, #16]
+    mrs     x1, FPCR
+    mrs     x2, FPSR
+    stp     w1, w2, [sp, #280]
+
+    // x19 and x20 are callee-saved, so they keep the return address into the
+    // vector and the exception info
+    mov     x19, lr
+    mov     x20, x0
+    mrs     x1, ESR_EL1
+    mov     x2, sp
+    bl      handle_exception
+    mov     sp, x0
+    mov     x0, x20
+    bl      exception_restoring
+    mov     lr, x19
+
+// Restores the trap frame at `sp` and pops it, leaving the `lr` and `x0` for
+// the vector to restore, then returns to `lr`.
 .global context_restore
 context_restore:
-    // FIXME: Restore the context from the stack.
+    ldp     x1, x2, [sp, #0]
+    msr     ELR_EL1, x1
+    msr     SPSR_EL1, x2
+    ldp     x1, x2, [sp, #16]
+    msr     SP_EL0, x1
+    msr     TPIDR_EL0, x2
+    ldp     w1, w2, [sp, #280]
+    msr     FPCR, x1
+    msr     FPSR, x2
+
+    ldp     q0, q1, [sp, #288]
+    ldp     q2, q3, [sp, #320]
+    ldp     q4, q5, [sp, #352]
+    ldp     q6, q7, [sp, #384]
+    ldp     q8, q9, [sp, #416]
+    ldp     q10, q11, [sp, #448]
+    ldp     q12, q13, [sp, #480]
+    ldp     q14, q15, [sp, #512]
+    ldp     q16, q17, [sp, #544]
+    ldp     q18, q19, [sp, #576]
+    ldp     q20, q21, [sp, #608]
+    ldp     q22, q23, [sp, #640]
+    ldp     q24, q25, [sp, #672]
+    ldp     q26, q27, [sp, #704]
+    ldp     q28, q29, [sp, #736]
+    ldp     q30, q31, [sp, #768]
+
+    // the vector restores lr and x0 from above the frame
+    ldr     x1, [sp, #272]
+    ldr     x2, [sp, #32]
+    str     x1, [sp, #800]
+    str     x2, [sp, #808]
 
+    ldp     x1, x2, [sp, #40]
+    ldp     x3, x4, [sp, #56]
+    ldp     x5, x6, [sp, #72]
+    ldp     x7, x8, [sp, #88]
+    ldp     x9, x10, [sp, #104]
+    ldp     x11, x12, [sp, #120]
+    ldp     x13, x14, [sp, #136]
+    ldp     x15, x16, [sp, #152]
+    ldp     x17, x18, [sp, #168]
+    ldp     x19, x20, [sp, #184]
+    ldp     x21, x22, [sp, #200]
+    ldp     x23, x24, [sp, #216]
+    ldp     x25, x26, [sp, #232]
+    ldp     x27, x28, [sp, #248]
+    ldr     x29, [sp, #264]
+
+    add     sp, sp, #800
     ret
 
-// #define HANDLER(source, kind) \
-//     .align 7; \
-//     stp     lr, x0, [SP, #-16]!; \
-//     mov     x0, ##source; \
-//     movk    x0, ##kind, LSL #16; \
-//     bl      context_save; \
-//     ldp     lr, x0, [SP], #16; \
-//     eret
+// An exception vector: `source` and `kind` are the `traps::Source` and
+// `traps::Kind` of the exceptions it takes.
+.macro HANDLER source, kind
+    .align 7
+    stp     lr, x0, [SP, #-16]!
+    mov     x0, #\source
+    movk    x0, #\kind, LSL #16
+    bl      context_save
+    ldp     lr, x0, [SP], #16
+    eret
+.endm
+
+// The synchronous vector for exceptions from EL1 on SP_EL1: like `HANDLER`,
+// but see `sync_el1_entry`.
+.macro SYNC_EL1_HANDLER
+    .align 7
+    b       sync_el1_entry
+.endm
+
+// Handles a synchronous exception from EL1 as `HANDLER` does, except when the
+// page below the stack pointer is unmapped: the guard page below one of the
+// kernel's stacks, or below a process's kernel stack. The exception is most
+// likely the stack overflowing into the guard page then, and saving the trap
+// frame on that stack would fault again, so it is handled on the overflow
+// stack instead. That never returns: the interrupted stack pointer is lost,
+// so the core stops after the overflow is reported.
+sync_el1_entry:
+    msr     TPIDR_EL1, x0
+
+    sub     x0, sp, #4096
+    at      s1e1w, x0
+    isb
+    mrs     x0, PAR_EL1
+    tbnz    x0, #0, 1f
+
+    mrs     x0, TPIDR_EL1
+    stp     lr, x0, [SP, #-16]!
+    mov     x0, #1
+    movk    x0, #0, LSL #16
+    bl      context_save
+    ldp     lr, x0, [SP], #16
+    eret
+
+1:  adrp    x0, __overflow_stack_top
+    add     x0, x0, :lo12:__overflow_stack_top
+    mov     sp, x0
+    mrs     x0, TPIDR_EL1
+    stp     lr, x0, [SP, #-16]!
+    mov     x0, #1
+    movk    x0, #0, LSL #16
+    bl      context_save
+2:  wfe
+    b       2b
+
+// An FIQ vector: like `HANDLER`, but the FIQ is handled on the FIQ stack so
+// that it never depends on how much of the interrupted stack is left.
+.macro FIQ_HANDLER source
+    .align 7
+    stp     x0, x1, [SP, #-16]!
+    stp     x2, lr, [SP, #-16]!
+    mov     x0, #\source
+    movk    x0, #2, LSL #16
+    b       fiq_entry
+.endm
+
+// Switches to the FIQ stack, saves the trap frame on it and handles the FIQ,
+// then switches back and returns from the exception. The vector pushed the
+// interrupted x2, lr, x0 and x1 and put the exception info in x0. FIQs are
+// masked while one is handled, so the FIQ stack is always empty on entry.
+fiq_entry:
+    mov     x1, sp
+    adrp    x2, __fiq_stack_top
+    add     x2, x2, :lo12:__fiq_stack_top
+    mov     sp, x2
+    str     x1, [sp, #-16]!
+
+    // push the interrupted lr and x0 as `HANDLER` does and restore x1 and x2
+    ldr     lr, [x1, #8]
+    ldr     x2, [x1, #16]
+    stp     lr, x2, [sp, #-16]!
+    ldr     x2, [x1, #0]
+    ldr     x1, [x1, #24]
+    bl      context_save
+
+    // copy the restored lr and x0 back to the interrupted stack and return
+    // to it; context_save restored every other register
+    ldr     x0, [sp, #16]
+    ldr     lr, [sp, #0]
+    str     lr, [x0, #8]
+    ldr     lr, [sp, #8]
+    str     lr, [x0, #16]
+    mov     sp, x0
+    ldr     lr, [sp, #8]
+    ldr     x0, [sp, #16]
+    add     sp, sp, #32
+    eret
 
 .align 11
+.global _vectors
 _vectors:
-    // FIXME: Setup the 16 exception vectors.
+    // from the current EL, using SP_EL0
+    HANDLER 0, 0
+    HANDLER 0, 1
+    FIQ_HANDLER 0
+    HANDLER 0, 3
+
+    // from the current EL, using SP_ELx
+    SYNC_EL1_HANDLER
+    HANDLER 1, 1
+    FIQ_HANDLER 1
+    HANDLER 1, 3
+
+    // from a lower EL in AArch64
+    HANDLER 2, 0
+    HANDLER 2, 1
+    FIQ_HANDLER 2
+    HANDLER 2, 3
+
+    // from a lower EL in AArch32
+    HANDLER 3, 0
+    HANDLER 3, 1
+    FIQ_HANDLER 3
+    HANDLER 3, 3
+
+.section .bss
+// The FIQ stack, above a page that `vm::VMManager` leaves unmapped to catch
+// it overflowing.
+.balign 4096
+.global __fiq_stack_guard
+__fiq_stack_guard:
+    .space 4096
+__fiq_stack:
+    .space 4096
+__fiq_stack_top:
+
+// The stack exceptions caused by a kernel stack overflowing are handled on.
+.balign 16
+__overflow_stack:
+    .space 4096
+__overflow_stack_top:
+
+// The boot translation tables. Not in `.bss`, which is zeroed while they are
+// in use.
+.section .boot_tables, "aw", @nobits
+.balign 4096
+__boot_l1:
+    .space 4096
+__boot_l2:
+    .space 4096
+
+// The exception level the kernel was entered at. In `.data` rather than
+// `.bss`, which is zeroed after it is written.
+.section .data
+.balign 8
+.global __entry_el
+__entry_el:
+    .quad 0
+
+// The physical address of the top of each core's stack, written by
+// `smp::start_cores()` before it releases the core.
+.balign 8
+.global __core_stacks
+__core_stacks:
+    .quad 0, 0, 0, 0
@@ -1,13 +1,230 @@
-use crate::console::kprintln;
+use crate::aarch64;
+use crate::clock;
+use crate::console::{kprintln, kprintln_nolock, log, BoundedWriter};
+use crate::fs::sd::{Sd, SECTOR_SIZE};
+use crate::oops;
+use crate::symbols::Symbolized;
+use crate::traps;
+use core::fmt::Write;
+use core::mem::size_of;
 use core::panic::PanicInfo;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use fat32::traits::BlockDevice;
+use fat32::MasterBootRecord;
+use shim::{io, ioerr};
+
+/// How long a panicked board waits before the watchdog resets it, so that
+/// the panic can be read on the console first.
+const REBOOT_DELAY: Duration = Duration::from_secs(10);
+
+/// The most bytes of a panic message the panic record keeps.
+const RECORD_TEXT_SIZE: usize = 448;
+
+/// Marks a panic record as written by the panic handler.
+const RECORD_MAGIC: u64 = 0x6472_6f63_6572_2170;
+
+/// What the last panic left for the next boot to report: the message, which
+/// includes where the kernel panicked, and the uptime it panicked at. It is
+/// kept in a sector of the SD card, which `checksum` tells apart from
+/// whatever else was there.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct PanicRecord {
+    magic: u64,
+    checksum: u64,
+    uptime_micros: u64,
+    len: u64,
+    text: [u8; RECORD_TEXT_SIZE],
+}
+
+// The record must fit in a sector: this does not compile if it does not.
+const _: usize = SECTOR_SIZE - size_of::<PanicRecord>();
+
+/// The sector of the SD card the panic record is kept in, once
+/// `report_previous()` has found one; `0` until then.
+static RECORD_SECTOR: AtomicU64 = AtomicU64::new(0);
+
+impl PanicRecord {
+    /// Returns the FNV-1a hash of the record's uptime and message.
+    fn checksum(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let uptime = self.uptime_micros.to_le_bytes();
+        let len = self.len.to_le_bytes();
+        let text = &self.text[..(self.len as usize).min(RECORD_TEXT_SIZE)];
+        for &byte in uptime.iter().chain(len.iter()).chain(text) {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+        }
+        hash
+    }
+
+    /// Returns the message, if the record is one the panic handler wrote.
+    fn text(&self) -> Option<&str> {
+        let valid = self.magic == RECORD_MAGIC
+            && self.len as usize <= RECORD_TEXT_SIZE
+            && self.checksum == self.checksum();
+        if !valid {
+            return None;
+        }
+        core::str::from_utf8(&self.text[..self.len as usize]).ok()
+    }
+
+    /// Returns the record a sector holds. Any bytes make a record, but only
+    /// one the panic handler wrote has `text()`.
+    fn from_sector(sector: &[u8; SECTOR_SIZE]) -> PanicRecord {
+        unsafe { ptr::read_unaligned(sector.as_ptr() as *const PanicRecord) }
+    }
+
+    /// Returns the sector that holds the record.
+    fn to_sector(&self) -> [u8; SECTOR_SIZE] {
+        let mut sector = [0; SECTOR_SIZE];
+        unsafe { ptr::write_unaligned(sector.as_mut_ptr() as *mut PanicRecord, *self) };
+        sector
+    }
+}
+
+/// Returns the sector of `sd` to keep the panic record in: the one just
+/// before the first partition, in the gap partitioning tools leave to align
+/// it, which nothing else uses.
+fn find_record_sector(sd: &mut Sd) -> Result<u64, &'static str> {
+    let mbr = MasterBootRecord::from(&mut *sd).map_err(|_| "no master boot record")?;
+    let first = mbr
+        .partitions()
+        .iter()
+        .filter(|partition| partition.num_sectors() != 0)
+        .map(|partition| partition.start())
+        .min()
+        .ok_or("no partitions")?;
+
+    // Sector 0 is the MBR itself. A GPT's protective partition starts at
+    // sector 1, so a GPT card has no gap either.
+    if first < 2 {
+        return Err("no gap before the first partition");
+    }
+    Ok(first - 1)
+}
+
+/// Records the panic `info` for the next boot to report, if the SD card is
+/// free to write it to.
+fn record(info: &PanicInfo) {
+    let sector = RECORD_SECTOR.load(Ordering::Relaxed);
+    if sector == 0 {
+        kprintln_nolock!("panic not recorded: there is nowhere to record it yet");
+        return;
+    }
+    let mut sd = match Sd::seize() {
+        Some(sd) => sd,
+        None => {
+            kprintln_nolock!("panic not recorded: the SD card is in use");
+            return;
+        }
+    };
+
+    let mut text = [0u8; RECORD_TEXT_SIZE];
+    let mut writer = BoundedWriter::new(&mut text);
+    let _ = write!(writer, "{}", info);
+    let len = writer.as_str().len();
+
+    let mut record = PanicRecord {
+        magic: RECORD_MAGIC,
+        checksum: 0,
+        uptime_micros: clock::uptime().as_micros() as u64,
+        len: len as u64,
+        text,
+    };
+    record.checksum = record.checksum();
+    if let Err(e) = sd.write_sector(sector, &record.to_sector()) {
+        kprintln_nolock!("panic not recorded: {}", e);
+    }
+}
+
+/// Finds the sector of the SD card the panic record is kept in, and prints
+/// the record, if the last boot ended in a panic, and clears it. Panics are
+/// recorded from then on, if the card has somewhere to keep the record.
+///
+/// # Errors
+///
+/// Returns an error if the SD card is not initialized, or could not be read
+/// or written.
+pub fn report_previous() -> io::Result<()> {
+    let mut sd = match Sd::get() {
+        Some(sd) => sd,
+        None => return ioerr!(NotFound, "the SD card is not initialized"),
+    };
+    let sector = match find_record_sector(&mut sd) {
+        Ok(sector) => sector,
+        Err(e) => {
+            kprintln!("init: panics will not be recorded: {}", e);
+            return Ok(());
+        }
+    };
+
+    let mut buf = [0; SECTOR_SIZE];
+    sd.read_sector(sector, &mut buf)?;
+    let mut record = PanicRecord::from_sector(&buf);
+    if let Some(text) = record.text() {
+        let uptime = Duration::from_micros(record.uptime_micros);
+        kprintln!(
+            "init: the last boot panicked {:?} after it started:",
+            uptime
+        );
+        kprintln!("{}", text);
+
+        record.magic = 0;
+        sd.write_sector(sector, &record.to_sector())?;
+    }
+
+    RECORD_SECTOR.store(sector, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns the frame pointer of the function that calls this.
+#[inline(always)]
+fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        core::arch::asm!("mov {}, x29", out(reg) fp, options(nomem, nostack));
+    }
+    fp
+}
+
+/// Prints where each function the panicking code was called from called
+/// the next, innermost first, from the frame records starting at `fp`.
+fn backtrace(fp: usize) {
+    kprintln_nolock!("backtrace:");
+    for (depth, addr) in oops::backtrace(fp).enumerate() {
+        kprintln_nolock!("  #{:<2} {}", depth, Symbolized(addr));
+    }
+}
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    kprintln!("");
-    kprintln!("         ¯\\_(ツ)_/¯");
-    kprintln!("---------- PANIC ----------");
-    kprintln!("");
-    kprintln!("{}", info);
+    // Whoever panicked may be holding the console lock, and the heap may be
+    // what failed, so only use the lock-free, allocation-free printer here.
+    log::freeze();
+    let fp = frame_pointer();
+    kprintln_nolock!("");
+    kprintln_nolock!("         ¯\\_(ツ)_/¯");
+    kprintln_nolock!("---------- PANIC ----------");
+    kprintln_nolock!("");
+    kprintln_nolock!("{}", info);
+    backtrace(fp);
+    oops::panic(info, fp);
+
+    #[cfg(test)]
+    crate::ktest::fail();
+
+    record(info);
+    kprintln_nolock!("");
+    if traps::gdb::is_enabled() {
+        kprintln_nolock!("stopping for gdb; rebooting once it resumes");
+        traps::gdb::breakpoint();
+    }
+    kprintln_nolock!("rebooting in {} seconds", REBOOT_DELAY.as_secs());
+    pi::pm::PowerManager::new().start_watchdog(REBOOT_DELAY);
 
-    loop {}
+    loop {
+        aarch64::wfe();
+    }
 }
@@ -0,0 +1,294 @@
+//! The stages core 0 boots the kernel through, in order.
+//!
+//! Each stage sets up one part of the kernel, relying on every stage before
+//! it. A stage that fails is reported on the console. If the kernel cannot
+//! run without what the stage sets up, the failure is fatal and the core
+//! halts. Otherwise booting carries on without it and ends in a rescue shell
+//! rather than the usual one, with `/etc/rc` not run.
+//!
+//! Nothing may allocate before the `memory` stage. Failures are reported
+//! with the lock-free, allocation-free printer, so they can be reported from
+//! any stage.
+
+use core::fmt;
+
+use shim::io;
+
+use pi::common::{Soc, SOC};
+
+use crate::console::{self, kprintln, kprintln_nolock};
+use crate::{aarch64, allocator, clock, fb, irq, shell, smp, usb};
+use crate::{ALLOCATOR, FILESYSTEM, IRQ, SCHEDULER, VMM};
+
+/// What the firmware tells the kernel about the board.
+#[derive(Default)]
+struct BootInfo {
+    /// The start and end of the memory for the kernel's heap.
+    memory: Option<(usize, usize)>,
+}
+
+/// Why a stage failed.
+enum Error {
+    Message(&'static str),
+    Io(io::Error),
+    Usb(usb::Error),
+}
+
+impl From<&'static str> for Error {
+    fn from(message: &'static str) -> Error {
+        Error::Message(message)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<usb::Error> for Error {
+    fn from(error: usb::Error) -> Error {
+        Error::Usb(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Message(message) => f.write_str(message),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Usb(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// A stage of booting.
+struct Stage {
+    name: &'static str,
+    /// Whether booting stops if the stage fails.
+    fatal: bool,
+    run: unsafe fn(&mut BootInfo) -> Result<(), Error>,
+}
+
+/// The stages, in the order they run.
+const STAGES: [Stage; 14] = [
+    Stage {
+        name: "bss",
+        fatal: true,
+        run: bss,
+    },
+    Stage {
+        name: "boot info",
+        fatal: true,
+        run: boot_info,
+    },
+    Stage {
+        name: "early console",
+        fatal: true,
+        run: early_console,
+    },
+    Stage {
+        name: "host console",
+        fatal: false,
+        run: host_console,
+    },
+    Stage {
+        name: "memory",
+        fatal: true,
+        run: memory,
+    },
+    Stage {
+        name: "exception vectors",
+        fatal: true,
+        run: exception_vectors,
+    },
+    Stage {
+        name: "mmu",
+        fatal: true,
+        run: mmu,
+    },
+    Stage {
+        name: "framebuffer",
+        fatal: false,
+        run: framebuffer,
+    },
+    Stage {
+        name: "irqs",
+        fatal: true,
+        run: irqs,
+    },
+    Stage {
+        name: "drivers",
+        fatal: false,
+        run: drivers,
+    },
+    Stage {
+        name: "file system",
+        fatal: false,
+        run: file_system,
+    },
+    Stage {
+        name: "panic record",
+        fatal: false,
+        run: panic_record,
+    },
+    Stage {
+        name: "scheduler",
+        fatal: true,
+        run: scheduler,
+    },
+    Stage {
+        name: "usb",
+        fatal: false,
+        run: usb,
+    },
+];
+
+/// Zeroes the kernel's BSS section, which holds every static that starts out
+/// zeroed. Then lets `spin_sleep()` sleep, now that it can remember to: every
+/// core generates the timer's event stream from `init.s` on.
+unsafe fn bss(_: &mut BootInfo) -> Result<(), Error> {
+    super::zeros_bss();
+    pi::timer::enable_event_stream();
+    Ok(())
+}
+
+/// Checks that the kernel was built for the board, and finds the memory for
+/// the heap in the ATAGs the firmware left.
+unsafe fn boot_info(info: &mut BootInfo) -> Result<(), Error> {
+    if Soc::detect() != Some(SOC) {
+        return Err("the board's SoC is not the one the kernel was built for".into());
+    }
+    info.memory = Some(allocator::memory_map().ok_or("no memory in the ATAGs")?);
+    Ok(())
+}
+
+/// Sets up the UART behind `CONSOLE`.
+unsafe fn early_console(_: &mut BootInfo) -> Result<(), Error> {
+    console::CONSOLE.lock().initialize();
+    Ok(())
+}
+
+/// Has the console copy its output to the host through semihosting, if the
+/// kernel was built with the `semihosting` feature.
+unsafe fn host_console(_: &mut BootInfo) -> Result<(), Error> {
+    #[cfg(feature = "semihosting")]
+    crate::semihosting::initialize()?;
+    Ok(())
+}
+
+/// Starts the heap.
+unsafe fn memory(info: &mut BootInfo) -> Result<(), Error> {
+    let (start, end) = info.memory.ok_or("no memory for the heap")?;
+    ALLOCATOR.initialize(start, end);
+    Ok(())
+}
+
+/// Checks that `init.s` pointed `VBAR_EL1` at the kernel's vectors, which it
+/// does before any Rust code runs so that every core takes exceptions.
+unsafe fn exception_vectors(_: &mut BootInfo) -> Result<(), Error> {
+    extern "C" {
+        fn _vectors();
+    }
+
+    if aarch64::VBAR_EL1::read() != _vectors as usize as u64 {
+        return Err("VBAR_EL1 does not point at the vectors".into());
+    }
+    Ok(())
+}
+
+/// Replaces the boot translation tables with the kernel's.
+unsafe fn mmu(_: &mut BootInfo) -> Result<(), Error> {
+    VMM.initialize();
+    Ok(())
+}
+
+/// Sets up the terminal on the framebuffer, which shows console output from
+/// then on.
+unsafe fn framebuffer(_: &mut BootInfo) -> Result<(), Error> {
+    fb::initialize()?;
+    Ok(())
+}
+
+/// Sets up the table of IRQ handlers. IRQs stay masked until the `scheduler`
+/// stage.
+unsafe fn irqs(_: &mut BootInfo) -> Result<(), Error> {
+    IRQ.initialize();
+    Ok(())
+}
+
+/// Starts the drivers that run off interrupts: the tick and console input.
+/// Then sets the clock from the RTC, if there is one.
+unsafe fn drivers(_: &mut BootInfo) -> Result<(), Error> {
+    clock::start_tick();
+    console::CONSOLE.lock().enable_rx_interrupt();
+    clock::probe_rtc();
+    Ok(())
+}
+
+/// Mounts the file systems.
+unsafe fn file_system(_: &mut BootInfo) -> Result<(), Error> {
+    FILESYSTEM.initialize()?;
+    Ok(())
+}
+
+/// Reports the panic the last boot ended in, if it did, from the record on
+/// the SD card, and lets panics be recorded from then on.
+unsafe fn panic_record(_: &mut BootInfo) -> Result<(), Error> {
+    super::panic::report_previous()?;
+    Ok(())
+}
+
+/// Makes the boot code process 1, starts the secondary cores and unmasks
+/// IRQs, so that processes are scheduled from then on.
+unsafe fn scheduler(_: &mut BootInfo) -> Result<(), Error> {
+    SCHEDULER.initialize();
+    smp::start_cores();
+    irq::unmask();
+    Ok(())
+}
+
+/// Enumerates the USB devices, and starts the process that polls them:
+/// keyboards type console input from then on.
+unsafe fn usb(_: &mut BootInfo) -> Result<(), Error> {
+    usb::initialize()?;
+    Ok(())
+}
+
+/// Runs every stage. Returns once they have all succeeded. If a stage fails
+/// and it is fatal, halts; if not, runs a rescue shell once the rest have
+/// run.
+pub unsafe fn run() {
+    let mut info = BootInfo::default();
+    let mut failed = false;
+    for stage in STAGES.iter() {
+        if let Err(e) = (stage.run)(&mut info) {
+            kprintln_nolock!("init: {} failed: {}", stage.name, e);
+            if stage.fatal {
+                halt();
+            }
+            failed = true;
+        }
+    }
+
+    if failed {
+        rescue();
+    }
+}
+
+/// Stops booting for good.
+fn halt() -> ! {
+    kprintln_nolock!("init: cannot boot; halting");
+    loop {
+        aarch64::wfi();
+    }
+}
+
+/// Runs shells until the board is reset, for booting that failed part way.
+fn rescue() -> ! {
+    kprintln!("init: booted without everything; starting a rescue shell");
+    loop {
+        let status = shell::shell("rescue> ");
+        kprintln!("shell exited with status {}; starting a new one", status);
+    }
+}
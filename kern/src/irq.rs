@@ -0,0 +1,355 @@
+use alloc::boxed::Box;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use pi::common::NCORES;
+use pi::interrupt::{Controller, Interrupt};
+use pi::local_interrupt::{LocalController, LocalInterrupt};
+
+use crate::aarch64;
+use crate::console::kprintln_nolock;
+use crate::mutex::Mutex;
+use crate::smp;
+use crate::traps::{stats, TrapFrame};
+
+/// A function called to service an interrupt. It must clear the condition
+/// that raised the interrupt, or it is called again as soon as it returns.
+pub type IrqHandler = Box<dyn FnMut(&mut TrapFrame) + Send>;
+
+type IrqHandlers = [Slot; Interrupt::MAX];
+
+/// The slot of one interrupt's handler.
+///
+/// A handler is taken out of its slot while it runs, so that it runs with no
+/// lock held and can itself register or unregister handlers.
+enum Slot {
+    Empty,
+    Ready(IrqHandler),
+    /// The handler is running. It is put back when it returns unless it was
+    /// replaced or removed meanwhile.
+    Running,
+}
+
+impl Default for Slot {
+    fn default() -> Slot {
+        Slot::Empty
+    }
+}
+
+impl Slot {
+    /// Replaces the handler in the slot with `handler`, if any.
+    fn set(&mut self, handler: Option<IrqHandler>) {
+        *self = handler.map_or(Slot::Empty, Slot::Ready);
+    }
+
+    /// Takes the handler out of the slot to be run, if there is one.
+    fn take(&mut self) -> Option<IrqHandler> {
+        match core::mem::replace(self, Slot::Running) {
+            Slot::Ready(handler) => Some(handler),
+            other => {
+                *self = other;
+                None
+            }
+        }
+    }
+
+    /// Puts `handler`, taken out by `take()`, back in the slot, unless the
+    /// slot was changed while it ran.
+    fn put_back(&mut self, handler: IrqHandler) {
+        if let Slot::Running = self {
+            *self = Slot::Ready(handler);
+        }
+    }
+}
+
+/// The kernel's table of interrupt handlers, one per interrupt source.
+///
+/// Interrupts do not nest. Handlers run with IRQs masked, as they are on
+/// exception entry, on the stack of the code they interrupted, so they must be
+/// short and must not unmask IRQs. Code sharing data with a handler protects
+/// it with `with_irqs_disabled()` or an `IrqGuard`.
+///
+/// The peripherals' interrupts are routed to core 0, which alone handles
+/// them. Each core also has a timer interrupt of its own, from its generic
+/// timer, with a handler registered by `register_core_timer()`, and an
+/// interrupt from its performance monitors, with a handler registered by
+/// `register_core_pmu()`. A core's `smp::STOP_MAILBOX` interrupt stops it
+/// for good, in `smp::stop()`.
+///
+/// One interrupt at a time can instead be promoted to the FIQ with
+/// `register_fiq()`. The FIQ is not masked by `IrqGuard`, so it preempts IRQ
+/// handlers and IRQ-masked code alike; its handler runs on a dedicated stack
+/// with IRQs and FIQs masked. An FIQ handler must therefore not take locks or
+/// touch data that the rest of the kernel uses without masking FIQs. For the
+/// same reason, its handler is kept behind an atomic pointer rather than a
+/// lock, and is only replaced with FIQs masked and routed nowhere.
+pub struct Irq {
+    handlers: Mutex<Option<IrqHandlers>>,
+    /// The promoted interrupt and its handler, or null.
+    fiq: AtomicPtr<(Interrupt, IrqHandler)>,
+    /// The handler of each core's generic timer interrupt, if any.
+    core_timers: Mutex<[Slot; NCORES]>,
+    /// The handler of each core's performance monitors interrupt, if any.
+    core_pmus: Mutex<[Slot; NCORES]>,
+}
+
+impl Irq {
+    /// Returns an uninitialized `Irq`. The table must be initialized with
+    /// `initialize()` before handlers are registered.
+    pub const fn uninitialized() -> Irq {
+        Irq {
+            handlers: Mutex::new(None),
+            fiq: AtomicPtr::new(ptr::null_mut()),
+            core_timers: Mutex::new([Slot::Empty, Slot::Empty, Slot::Empty, Slot::Empty]),
+            core_pmus: Mutex::new([Slot::Empty, Slot::Empty, Slot::Empty, Slot::Empty]),
+        }
+    }
+
+    /// Initializes the table with no handlers registered.
+    pub fn initialize(&self) {
+        *self.handlers.lock() = Some(Default::default());
+    }
+
+    /// Registers `handler` to service `int`, replacing any handler already
+    /// registered, and enables `int`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table has not been initialized.
+    pub fn register(&self, int: Interrupt, handler: IrqHandler) {
+        let _guard = IrqGuard::new();
+        self.handlers(|handlers| handlers[int.to_index()].set(Some(handler)));
+        Controller::new().enable(int);
+    }
+
+    /// Disables `int` and removes its handler.
+    pub fn unregister(&self, int: Interrupt) {
+        let _guard = IrqGuard::new();
+        Controller::new().disable(int);
+        self.handlers(|handlers| handlers[int.to_index()].set(None));
+    }
+
+    /// Enables `int`, which must have a handler registered.
+    pub fn enable(&self, int: Interrupt) {
+        Controller::new().enable(int);
+    }
+
+    /// Disables `int`, leaving its handler registered.
+    pub fn disable(&self, int: Interrupt) {
+        Controller::new().disable(int);
+    }
+
+    /// Registers `handler` to service the interrupt of this core's EL1
+    /// physical timer, replacing any handler already registered, and routes
+    /// the interrupt to the core.
+    pub fn register_core_timer(&self, handler: IrqHandler) {
+        let _guard = IrqGuard::new();
+        let core = aarch64::affinity();
+        self.core_timers.lock()[core].set(Some(handler));
+        LocalController::new(core).enable_timer(LocalInterrupt::CntPns);
+    }
+
+    /// Registers `handler` to service the interrupt of the performance
+    /// monitors of the core `core`, replacing any handler already registered,
+    /// and routes the interrupt to the core.
+    pub fn register_core_pmu(&self, core: usize, handler: IrqHandler) {
+        let _guard = IrqGuard::new();
+        self.core_pmus.lock()[core].set(Some(handler));
+        LocalController::new(core).enable_pmu();
+    }
+
+    /// Calls the handler of every pending, enabled interrupt of this core:
+    /// its timer's and its performance monitors', then on core 0 the
+    /// peripherals'. An interrupt from the
+    /// peripherals with no handler is reported and disabled, since nothing
+    /// would ever clear it. A core asked to stop stops first.
+    ///
+    /// Each handler is taken out of the table to be run, so no lock is held
+    /// while it runs.
+    pub fn dispatch(&self, tf: &mut TrapFrame) {
+        let core = aarch64::affinity();
+        if LocalController::new(core).is_pending(LocalInterrupt::Mailbox0) {
+            smp::stop();
+        }
+        if LocalController::new(core).is_pending(LocalInterrupt::CntPns) {
+            let handler = self.core_timers.lock()[core].take();
+            if let Some(mut handler) = handler {
+                handler(tf);
+                self.core_timers.lock()[core].put_back(handler);
+            }
+        }
+        if LocalController::new(core).is_pending(LocalInterrupt::Pmu) {
+            let handler = self.core_pmus.lock()[core].take();
+            if let Some(mut handler) = handler {
+                handler(tf);
+                self.core_pmus.lock()[core].put_back(handler);
+            }
+        }
+        if core != 0 {
+            return;
+        }
+
+        let mut controller = Controller::new();
+        for int in Interrupt::iter() {
+            if !controller.is_pending(int) || !controller.is_enabled(int) {
+                continue;
+            }
+
+            stats::record_interrupt(int);
+            match self.handlers(|handlers| handlers[int.to_index()].take()) {
+                Some(mut handler) => {
+                    handler(tf);
+                    self.handlers(|handlers| handlers[int.to_index()].put_back(handler));
+                }
+                None => {
+                    kprintln_nolock!("irq: no handler for {:?}; disabling it", int);
+                    controller.disable(int);
+                }
+            }
+        }
+    }
+
+    /// Promotes `int` to the FIQ, serviced by `handler`: disables it as an
+    /// IRQ and routes it to the FIQ instead, replacing any interrupt promoted
+    /// before, then unmasks FIQs. For sources that cannot tolerate the latency
+    /// of IRQ dispatch, such as audio FIFOs and bit-banged protocols.
+    pub fn register_fiq(&self, int: Interrupt, handler: IrqHandler) {
+        let mut controller = Controller::new();
+        controller.disable(int);
+
+        mask_fiq();
+        controller.disable_fiq();
+        self.replace_fiq(Box::into_raw(Box::new((int, handler))));
+        controller.enable_fiq(int);
+        unmask_fiq();
+    }
+
+    /// Stops routing the promoted interrupt, if any, to the FIQ and removes
+    /// its handler. The interrupt is left disabled.
+    pub fn unregister_fiq(&self) {
+        mask_fiq();
+        Controller::new().disable_fiq();
+        self.replace_fiq(ptr::null_mut());
+        unmask_fiq();
+    }
+
+    /// Returns the interrupt promoted to the FIQ, if any.
+    pub fn fiq(&self) -> Option<Interrupt> {
+        Controller::new().fiq()
+    }
+
+    /// Calls the FIQ handler. An FIQ with no handler is reported and
+    /// disabled.
+    pub fn dispatch_fiq(&self, tf: &mut TrapFrame) {
+        // FIQs do not nest, and the handler is only replaced with FIQs
+        // masked and routed nowhere, so nothing else uses it while it runs.
+        match unsafe { self.fiq.load(Ordering::Acquire).as_mut() } {
+            Some((int, handler)) => {
+                stats::record_interrupt(*int);
+                handler(tf)
+            }
+            None => {
+                kprintln_nolock!("irq: no FIQ handler; disabling the FIQ");
+                Controller::new().disable_fiq();
+            }
+        }
+    }
+
+    /// Makes `fiq` the FIQ handler, freeing the one it replaces. FIQs must be
+    /// masked and routed nowhere.
+    fn replace_fiq(&self, fiq: *mut (Interrupt, IrqHandler)) {
+        let old = self.fiq.swap(fiq, Ordering::AcqRel);
+        if !old.is_null() {
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    /// Calls `f` with the handler table.
+    fn handlers<R, F: FnOnce(&mut IrqHandlers) -> R>(&self, f: F) -> R {
+        let mut handlers = self.handlers.lock();
+        f(handlers.as_mut().expect("IRQ table uninitialized"))
+    }
+}
+
+/// The IRQ mask bit of `DAIF`.
+const DAIF_I: u64 = 1 << 7;
+
+/// Masks IRQs on this core until it is dropped, then restores the mask to
+/// what it was before. Guards can be nested.
+pub struct IrqGuard {
+    daif: u64,
+}
+
+impl IrqGuard {
+    /// Masks IRQs, saving the current mask.
+    pub fn new() -> IrqGuard {
+        let daif = daif();
+        mask();
+        IrqGuard { daif }
+    }
+}
+
+impl Drop for IrqGuard {
+    fn drop(&mut self) {
+        if self.daif & DAIF_I == 0 {
+            unmask();
+        }
+    }
+}
+
+/// Calls `f` with IRQs masked on this core, then restores the mask, so that
+/// `f` can safely use data shared with an interrupt handler.
+pub fn with_irqs_disabled<R, F: FnOnce() -> R>(f: F) -> R {
+    let _guard = IrqGuard::new();
+    f()
+}
+
+/// Returns `true` if IRQs are masked on this core.
+pub fn is_masked() -> bool {
+    daif() & DAIF_I != 0
+}
+
+/// Returns the value of the `DAIF` register.
+fn daif() -> u64 {
+    #[cfg(target_os = "none")]
+    unsafe {
+        let daif: u64;
+        core::arch::asm!("mrs {}, DAIF", out(reg) daif, options(nomem, nostack));
+        daif
+    }
+
+    #[cfg(not(target_os = "none"))]
+    DAIF_I
+}
+
+/// Masks IRQs on this core.
+fn mask() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("msr DAIFSet, #0b0010", options(nomem, nostack));
+    }
+}
+
+/// Masks FIQs on this core.
+fn mask_fiq() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("msr DAIFSet, #0b0001", options(nomem, nostack));
+    }
+}
+
+/// Unmasks FIQs on this core.
+fn unmask_fiq() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("msr DAIFClr, #0b0001", options(nomem, nostack));
+    }
+}
+
+/// Unmasks IRQs on this core, so that enabled interrupts are taken.
+pub fn unmask() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("msr DAIFClr, #0b0010", options(nomem, nostack));
+    }
+}
@@ -0,0 +1,79 @@
+//! The harness for the tests that run in the kernel, on the target, rather
+//! than on the host: the `#[test_case]` functions in the `ktests` modules.
+//! `make ktest` builds them into a test kernel that boots through every
+//! stage as usual, then runs them one after another as process 1, under QEMU,
+//! instead of starting the shell.
+//!
+//! A test fails by panicking, which ends the run, as does one that runs for
+//! longer than `TIMEOUT`: a process, which any core may run, watches for it.
+//! Results are printed on the console, and the run ends by exiting QEMU
+//! through semihosting, with a status of 0 if every test passed or 1 if not.
+
+use core::any::type_name;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::console::{kprint, kprintln, kprintln_nolock};
+use crate::{clock, process, semihosting, SCHEDULER};
+
+/// How long one test may run.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the watcher checks on the running test.
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The uptime, in microseconds, the running test must end by, or 0 between
+/// tests.
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+/// A test: any function, named after its path.
+pub trait Test {
+    /// Runs the test, reporting it on the console.
+    fn run(&self);
+}
+
+impl<T: Fn()> Test for T {
+    fn run(&self) {
+        kprint!("test {} ... ", type_name::<T>());
+        self();
+        kprintln!("ok");
+    }
+}
+
+/// Runs every test in `tests`, then exits QEMU. The test harness calls this
+/// from `test_main()`.
+pub fn run(tests: &[&dyn Test]) -> ! {
+    kprintln!("running {} tests", tests.len());
+    if SCHEDULER.spawn("ktest-watch", watch).is_none() {
+        kprintln!("warning: no memory for the watcher; tests have no timeout");
+    }
+
+    for test in tests {
+        let deadline = clock::uptime() + TIMEOUT;
+        DEADLINE.store(deadline.as_micros() as u64, Ordering::Relaxed);
+        test.run();
+    }
+    DEADLINE.store(0, Ordering::Relaxed);
+
+    kprintln!("test result: ok. {} passed", tests.len());
+    semihosting::report(true)
+}
+
+/// Ends the run because the running test failed. The panic handler calls
+/// this once it has printed the panic.
+pub fn fail() -> ! {
+    kprintln_nolock!("test result: FAILED");
+    semihosting::report(false)
+}
+
+/// Ends the run if the running test overruns its deadline.
+fn watch() {
+    loop {
+        process::sleep(WATCH_INTERVAL);
+        let deadline = DEADLINE.load(Ordering::Relaxed);
+        if deadline != 0 && clock::uptime().as_micros() as u64 > deadline {
+            kprintln_nolock!("timed out after {:?}", TIMEOUT);
+            fail();
+        }
+    }
+}
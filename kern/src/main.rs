@@ -5,35 +5,62 @@
 #![feature(global_asm)]
 #![feature(optin_builtin_traits)]
 #![feature(raw_vec_internals)]
-#![cfg_attr(not(test), no_std)]
-#![cfg_attr(not(test), no_main)]
+#![cfg_attr(all(test, target_os = "none"), feature(custom_test_frameworks))]
+#![cfg_attr(all(test, target_os = "none"), test_runner(crate::ktest::run))]
+#![cfg_attr(all(test, target_os = "none"), reexport_test_harness_main = "test_main")]
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
 
-#[cfg(not(test))]
+#[cfg(target_os = "none")]
 mod init;
+#[cfg(all(test, target_os = "none"))]
+mod ktest;
 
 extern crate alloc;
 
+pub mod aarch64;
 pub mod allocator;
+pub mod clock;
 pub mod console;
+pub mod elf;
+pub mod fb;
+pub mod firmware;
 pub mod fs;
+pub mod irq;
 pub mod mutex;
+pub mod net;
+pub mod oops;
+pub mod process;
+pub mod profile;
+pub mod semihosting;
 pub mod shell;
+pub mod smp;
+pub mod symbols;
+pub mod traps;
+pub mod usb;
+pub mod vm;
 
 use console::kprintln;
 
 use allocator::Allocator;
 use fs::FileSystem;
+use irq::Irq;
+use process::GlobalScheduler;
+use vm::VMManager;
 
-#[cfg_attr(not(test), global_allocator)]
+#[cfg_attr(target_os = "none", global_allocator)]
 pub static ALLOCATOR: Allocator = Allocator::uninitialized();
 pub static FILESYSTEM: FileSystem = FileSystem::uninitialized();
+pub static IRQ: Irq = Irq::uninitialized();
+pub static VMM: VMManager = VMManager::uninitialized();
+pub static SCHEDULER: GlobalScheduler = GlobalScheduler::uninitialized();
 
+/// Runs once booting has been through every stage in `init::stage`.
 fn kmain() -> ! {
-    unsafe {
-        ALLOCATOR.initialize();
-        FILESYSTEM.initialize();
-    }
-
     kprintln!("Welcome to cs3210!");
-    shell::shell("> ");
+    shell::run_rc();
+    loop {
+        let status = shell::shell("> ");
+        kprintln!("shell exited with status {}; starting a new one", status);
+    }
 }
@@ -3,11 +3,55 @@ use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::cell::UnsafeCell;
 use core::ops::{DerefMut, Deref, Drop};
 
+use pi::common::NCORES;
+
+use crate::aarch64;
+
+/// The owner of a `Mutex` that no core holds.
+const NO_OWNER: usize = usize::max_value();
+
+/// The most mutexes a core's record of those it holds keeps.
+pub const MAX_HELD: usize = 8;
+
+const NOT_HELD: AtomicUsize = AtomicUsize::new(0);
+const NONE_HELD: [AtomicUsize; MAX_HELD] = [NOT_HELD; MAX_HELD];
+
+/// The addresses of the mutexes each core holds, for crash reports. A core
+/// holding more than `MAX_HELD` at once leaves the rest out.
+static HELD: [[AtomicUsize; MAX_HELD]; NCORES] = [NONE_HELD; NCORES];
+
+/// Returns the addresses of the mutexes `core` holds, as far as its record
+/// of them goes. An empty slot is zero.
+pub fn held_by(core: usize) -> [usize; MAX_HELD] {
+    let mut held = [0; MAX_HELD];
+    for (slot, addr) in HELD[core % NCORES].iter().zip(held.iter_mut()) {
+        *addr = slot.load(Ordering::Relaxed);
+    }
+    held
+}
+
+/// Replaces `old` with `new` in the first slot of `core`'s record of the
+/// mutexes it holds that has it, if any does.
+fn record_held(core: usize, old: usize, new: usize) {
+    for slot in HELD[core % NCORES].iter() {
+        if slot.compare_exchange(old, new, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            return;
+        }
+    }
+}
+
+/// A spin lock held by a core, rather than a process: the core that holds it
+/// can lock it again, and it is released once every guard is dropped. Other
+/// cores wait until then, sleeping in `wfe` between tries. Since a process
+/// can be preempted with a guard held, the next process on its core is not
+/// kept out.
 #[repr(align(32))]
 pub struct Mutex<T> {
     data: UnsafeCell<T>,
     lock: AtomicBool,
-    owner: AtomicUsize
+    owner: AtomicUsize,
+    /// The number of guards the owner holds.
+    depth: AtomicUsize
 }
 
 unsafe impl<T: Send> Send for Mutex<T> { }
@@ -24,41 +68,61 @@ impl<T> Mutex<T> {
     pub const fn new(val: T) -> Mutex<T> {
         Mutex {
             lock: AtomicBool::new(false),
-            owner: AtomicUsize::new(usize::max_value()),
+            owner: AtomicUsize::new(NO_OWNER),
+            depth: AtomicUsize::new(0),
             data: UnsafeCell::new(val)
         }
     }
 }
 
 impl<T> Mutex<T> {
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
+    /// Locks the mutex if it is free or this core holds it already. Returns
+    /// `None` if another core holds it.
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        let this = 0;
-        if !self.lock.load(Ordering::Relaxed) || self.owner.load(Ordering::Relaxed) == this {
-            self.lock.store(true, Ordering::Relaxed);
+        let this = aarch64::affinity();
+        // Only this core stores its own number as the owner.
+        if self.owner.load(Ordering::Relaxed) == this {
+            self.depth.fetch_add(1, Ordering::Relaxed);
+            return Some(MutexGuard { lock: &self });
+        }
+
+        let acquired = self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed);
+        if acquired.is_ok() {
             self.owner.store(this, Ordering::Relaxed);
+            self.depth.store(1, Ordering::Relaxed);
+            record_held(this, 0, self.addr());
             Some(MutexGuard { lock: &self })
         } else {
             None
         }
     }
 
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
+    /// Locks the mutex, waiting while another core holds it.
     #[inline(never)]
     pub fn lock(&self) -> MutexGuard<T> {
-        // Wait until we can "aquire" the lock, then "acquire" it.
+        // Wait until we can "aquire" the lock, then "acquire" it. The core
+        // sleeps until an event, which the holder sends once it unlocks: if
+        // it already has, the wait returns at once.
         loop {
             match self.try_lock() {
                 Some(guard) => return guard,
-                None => continue
+                None => aarch64::wfe()
             }
         }
     }
 
     fn unlock(&self) {
-        self.lock.store(false, Ordering::Relaxed);
+        if self.depth.fetch_sub(1, Ordering::Relaxed) == 1 {
+            let owner = self.owner.load(Ordering::Relaxed);
+            record_held(owner, self.addr(), 0);
+            self.owner.store(NO_OWNER, Ordering::Relaxed);
+            self.lock.store(false, Ordering::Release);
+            aarch64::sev();
+        }
+    }
+
+    fn addr(&self) -> usize {
+        self as *const Self as usize
     }
 }
 
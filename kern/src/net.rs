@@ -0,0 +1,646 @@
+//! A minimal network stack for the one Ethernet interface: ARP, IPv4 without
+//! fragments, ICMP echo, and UDP, with the interface's address leased over
+//! DHCP.
+//!
+//! The interface's driver hands the stack the frames it receives, takes the
+//! frames it queues to send, and polls it for its timers. Kernel code talks
+//! UDP through `UdpSocket`s, whose datagrams are queued until they are read.
+//! Nothing is sent before the interface has a lease but DHCP's broadcasts.
+//! `tftp` fetches files over UDP.
+
+mod addr;
+mod arp;
+mod dhcp;
+mod ethernet;
+mod icmp;
+mod ipv4;
+mod udp;
+
+pub mod tftp;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use crate::console::kprintln;
+use crate::irq::with_irqs_disabled;
+use crate::mutex::Mutex;
+use crate::{clock, process};
+
+pub use self::addr::{Ipv4Addr, MacAddr};
+pub use self::dhcp::Lease;
+
+use self::ethernet::MTU;
+
+/// The most frames queued to send, packets waiting on ARP, datagrams queued
+/// on a socket, and echo replies not yet taken.
+const MAX_TX_FRAMES: usize = 32;
+const MAX_WAITING: usize = 8;
+const MAX_DATAGRAMS: usize = 16;
+const MAX_ECHO_REPLIES: usize = 16;
+
+/// How long a packet waits for its next hop's Ethernet address before it is
+/// dropped.
+const ARP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The ports sockets bound to port 0 are given.
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+/// How often a socket waiting for a datagram checks for one.
+const RECV_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The largest UDP payload a packet carries.
+pub const MAX_PAYLOAD: usize = MTU - ipv4::HEADER_LEN - udp::HEADER_LEN;
+
+/// Why a network operation failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// There is no network interface.
+    NoInterface,
+    /// The interface has no address yet.
+    NotConfigured,
+    /// The destination is off the local network, which has no gateway.
+    Unreachable,
+    /// Another socket is bound to the port.
+    AddrInUse,
+    /// The payload does not fit in one packet.
+    TooLong,
+    /// The queue of frames to send is full.
+    Busy,
+    TimedOut,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Error::NoInterface => "no network interface",
+            Error::NotConfigured => "no network address yet",
+            Error::Unreachable => "network unreachable",
+            Error::AddrInUse => "port in use",
+            Error::TooLong => "message too long",
+            Error::Busy => "network busy",
+            Error::TimedOut => "timed out",
+        })
+    }
+}
+
+/// Counts of what the interface moved.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Stats {
+    pub rx_frames: u64,
+    pub rx_bytes: u64,
+    pub tx_frames: u64,
+    pub tx_bytes: u64,
+    /// Frames dropped: received ones nothing took, and ones there was no
+    /// room to queue or no address to send to.
+    pub dropped: u64,
+}
+
+/// The state of the interface, as `ifconfig` shows it.
+#[derive(Debug, Copy, Clone)]
+pub struct Status {
+    pub mac: MacAddr,
+    pub link: bool,
+    pub lease: Option<Lease>,
+    pub stats: Stats,
+}
+
+/// A datagram received on a socket's port.
+struct Datagram {
+    src: Ipv4Addr,
+    port: u16,
+    data: Vec<u8>,
+}
+
+/// A bound port, and the datagrams received on it.
+struct Socket {
+    port: u16,
+    queue: VecDeque<Datagram>,
+}
+
+/// The interface, and everything the stack keeps about the network.
+struct Interface {
+    mac: MacAddr,
+    link: bool,
+    dhcp: dhcp::Client,
+    arp: arp::Cache,
+    /// Packets waiting for their next hop's Ethernet address, with the hop
+    /// and when they started waiting.
+    waiting: Vec<(Ipv4Addr, Vec<u8>, Duration)>,
+    /// Frames for the driver to send.
+    tx: VecDeque<Vec<u8>>,
+    sockets: Vec<Socket>,
+    /// The port the next socket bound to port 0 is tried at.
+    next_port: u16,
+    /// The identification of the next packet sent.
+    next_id: u16,
+    /// Echo replies received: who from, their IDs and sequence numbers.
+    echo_replies: VecDeque<(Ipv4Addr, u16, u16)>,
+    stats: Stats,
+}
+
+/// The interface, once a driver attaches it. Use it through `with_net()`.
+static NET: Mutex<Option<Interface>> = Mutex::new(None);
+
+/// Calls `f` with `NET` locked and IRQs masked on this core. The lock is the
+/// core's, not the process's: were the holder preempted, the next process to
+/// run on the core would take the lock again and alias the interface.
+fn with_net<R, F: FnOnce(&mut Option<Interface>) -> R>(f: F) -> R {
+    with_irqs_disabled(|| f(&mut NET.lock()))
+}
+
+impl Interface {
+    fn lease(&self) -> Option<&Lease> {
+        self.dhcp.lease()
+    }
+
+    /// Queues `frame` for the driver to send.
+    fn queue(&mut self, frame: Vec<u8>) -> Result<(), Error> {
+        if self.tx.len() == MAX_TX_FRAMES {
+            self.stats.dropped += 1;
+            return Err(Error::Busy);
+        }
+        self.tx.push_back(frame);
+        Ok(())
+    }
+
+    /// Sends `payload` to `dst` in an IPv4 packet of protocol `protocol`,
+    /// once the Ethernet address it goes to is known. `payload` is built by
+    /// `build` from the packet's header.
+    fn send_ipv4<F>(&mut self, dst: Ipv4Addr, protocol: u8, build: F) -> Result<(), Error>
+    where
+        F: FnOnce(&ipv4::Header) -> Vec<u8>,
+    {
+        let src = self
+            .lease()
+            .map_or(Ipv4Addr::UNSPECIFIED, |lease| lease.addr);
+        let header = ipv4::Header { src, dst, protocol };
+        let payload = build(&header);
+        if payload.len() > MTU - ipv4::HEADER_LEN {
+            return Err(Error::TooLong);
+        }
+        self.next_id = self.next_id.wrapping_add(1);
+        let packet = ipv4::packet(&header, self.next_id, &payload);
+
+        let hop = match self.lease() {
+            _ if dst == Ipv4Addr::BROADCAST => None,
+            None => return Err(Error::NotConfigured),
+            Some(lease) if dst.same_network(lease.addr, lease.netmask) => {
+                let broadcast = dst.to_u32() | !lease.netmask.to_u32();
+                if dst.to_u32() == broadcast {
+                    None
+                } else {
+                    Some(dst)
+                }
+            }
+            Some(lease) => Some(lease.gateway.ok_or(Error::Unreachable)?),
+        };
+
+        let now = clock::uptime();
+        let mac = match hop {
+            None => MacAddr::BROADCAST,
+            Some(hop) => match self.arp.get(hop, now) {
+                Some(mac) => mac,
+                None => {
+                    if self.waiting.len() == MAX_WAITING {
+                        self.waiting.remove(0);
+                        self.stats.dropped += 1;
+                    }
+                    self.waiting.push((hop, packet, now));
+                    return self.send_arp(arp::REQUEST, MacAddr::default(), hop);
+                }
+            },
+        };
+        self.send_frame(mac, ethernet::TYPE_IPV4, &packet)
+    }
+
+    /// Sends an ARP packet of operation `operation` to `ip` at `mac`, or
+    /// broadcasts it if `mac` is not known.
+    fn send_arp(&mut self, operation: u16, mac: MacAddr, ip: Ipv4Addr) -> Result<(), Error> {
+        let packet = arp::Packet {
+            operation,
+            sender_mac: self.mac,
+            sender_ip: self.lease().ok_or(Error::NotConfigured)?.addr,
+            target_mac: mac,
+            target_ip: ip,
+        };
+        let dst = match operation {
+            arp::REQUEST => MacAddr::BROADCAST,
+            _ => mac,
+        };
+        self.send_frame(dst, ethernet::TYPE_ARP, &packet.to_bytes())
+    }
+
+    /// Queues a frame to `dst` carrying `payload` of type `kind`.
+    fn send_frame(&mut self, dst: MacAddr, kind: u16, payload: &[u8]) -> Result<(), Error> {
+        let header = ethernet::Header {
+            dst,
+            src: self.mac,
+            kind,
+        };
+        self.queue(ethernet::frame(&header, payload))
+    }
+
+    /// Sends `data` from the local port `src_port` to `port` at `dst`.
+    fn send_udp(
+        &mut self,
+        src_port: u16,
+        dst: Ipv4Addr,
+        port: u16,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if data.len() > MAX_PAYLOAD {
+            return Err(Error::TooLong);
+        }
+        let header = udp::Header {
+            src_port,
+            dst_port: port,
+        };
+        self.send_ipv4(dst, ipv4::PROTOCOL_UDP, |ip| {
+            udp::datagram(ip, &header, data)
+        })
+    }
+
+    /// Takes the frame `frame` the driver received.
+    fn receive(&mut self, frame: &[u8]) {
+        self.stats.rx_frames += 1;
+        self.stats.rx_bytes += frame.len() as u64;
+        let taken = match ethernet::parse(frame) {
+            Some((header, _)) if header.dst != self.mac && !header.dst.is_multicast() => false,
+            Some((header, payload)) if header.kind == ethernet::TYPE_ARP => {
+                self.receive_arp(payload)
+            }
+            Some((header, payload)) if header.kind == ethernet::TYPE_IPV4 => {
+                self.receive_ipv4(payload)
+            }
+            _ => false,
+        };
+        if !taken {
+            self.stats.dropped += 1;
+        }
+    }
+
+    /// Takes an ARP packet: learns the sender's address, sends the packets
+    /// waiting on it, and answers requests for the interface's address.
+    fn receive_arp(&mut self, packet: &[u8]) -> bool {
+        let packet = match arp::Packet::parse(packet) {
+            Some(packet) => packet,
+            None => return false,
+        };
+        if packet.sender_ip == Ipv4Addr::UNSPECIFIED {
+            return false;
+        }
+
+        let now = clock::uptime();
+        self.arp.insert(packet.sender_ip, packet.sender_mac, now);
+        let mut i = 0;
+        while i < self.waiting.len() {
+            if self.waiting[i].0 == packet.sender_ip {
+                let (_, ip_packet, _) = self.waiting.remove(i);
+                let _ = self.send_frame(packet.sender_mac, ethernet::TYPE_IPV4, &ip_packet);
+            } else {
+                i += 1;
+            }
+        }
+
+        let ours = self.lease().map(|lease| lease.addr) == Some(packet.target_ip);
+        if packet.operation == arp::REQUEST && ours {
+            let _ = self.send_arp(arp::REPLY, packet.sender_mac, packet.sender_ip);
+        }
+        true
+    }
+
+    /// Takes an IPv4 packet, if it is for the interface.
+    fn receive_ipv4(&mut self, packet: &[u8]) -> bool {
+        let (header, payload) = match ipv4::parse(packet) {
+            Some(packet) => packet,
+            None => return false,
+        };
+        let for_us = match self.lease() {
+            _ if header.dst == Ipv4Addr::BROADCAST => true,
+            // Servers may send DHCP replies to the address they offer.
+            None => header.protocol == ipv4::PROTOCOL_UDP,
+            Some(lease) => {
+                let broadcast = lease.addr.to_u32() | !lease.netmask.to_u32();
+                header.dst == lease.addr || header.dst.to_u32() == broadcast
+            }
+        };
+        if !for_us {
+            return false;
+        }
+
+        match header.protocol {
+            ipv4::PROTOCOL_ICMP => self.receive_icmp(&header, payload),
+            ipv4::PROTOCOL_UDP => self.receive_udp(&header, payload),
+            _ => false,
+        }
+    }
+
+    /// Takes an ICMP message: answers echo requests, and keeps echo replies
+    /// for `echo_reply()`.
+    fn receive_icmp(&mut self, ip: &ipv4::Header, message: &[u8]) -> bool {
+        let echo = match icmp::Echo::parse(message) {
+            Some(echo) => echo,
+            None => return false,
+        };
+        match echo.kind {
+            icmp::ECHO_REQUEST if self.lease().is_some() => {
+                let reply = icmp::Echo {
+                    kind: icmp::ECHO_REPLY,
+                    ..echo
+                };
+                let _ = self.send_ipv4(ip.src, ipv4::PROTOCOL_ICMP, |_| reply.to_bytes());
+                true
+            }
+            icmp::ECHO_REPLY => {
+                if self.echo_replies.len() == MAX_ECHO_REPLIES {
+                    self.echo_replies.pop_front();
+                }
+                self.echo_replies.push_back((ip.src, echo.id, echo.seq));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes a UDP datagram: DHCP replies go to the DHCP client, and others
+    /// to the socket bound to their port.
+    fn receive_udp(&mut self, ip: &ipv4::Header, datagram: &[u8]) -> bool {
+        let (header, data) = match udp::parse(ip, datagram) {
+            Some(datagram) => datagram,
+            None => return false,
+        };
+        if header.src_port == dhcp::SERVER_PORT && header.dst_port == dhcp::CLIENT_PORT {
+            self.update_dhcp(|dhcp, now| {
+                dhcp.receive(data, now);
+                None
+            });
+            return true;
+        }
+
+        let socket = self.sockets.iter_mut().find(|s| s.port == header.dst_port);
+        match socket {
+            Some(socket) if socket.queue.len() < MAX_DATAGRAMS => {
+                socket.queue.push_back(Datagram {
+                    src: ip.src,
+                    port: header.src_port,
+                    data: data.to_vec(),
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Has the DHCP client do `f`, then broadcasts the message `f` returns
+    /// and reports any change to the lease.
+    fn update_dhcp<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut dhcp::Client, Duration) -> Option<Vec<u8>>,
+    {
+        let old = self.lease().copied();
+        let message = f(&mut self.dhcp, clock::uptime());
+        if let Some(message) = message {
+            let (src, dst) = (dhcp::CLIENT_PORT, dhcp::SERVER_PORT);
+            let _ = self.send_udp(src, Ipv4Addr::BROADCAST, dst, &message);
+        }
+
+        let new = self.lease().copied();
+        match (old, new) {
+            (_, Some(new)) if old.map(|old| old.addr) != Some(new.addr) => kprintln!(
+                "net: leased {} netmask {} from {}",
+                new.addr,
+                new.netmask,
+                new.server
+            ),
+            (Some(_), None) => kprintln!("net: lease expired"),
+            _ => {}
+        }
+    }
+
+    /// Runs the stack's timers: DHCP's, and the expiry of packets waiting
+    /// on ARP.
+    fn poll(&mut self) {
+        if self.link {
+            self.update_dhcp(|dhcp, now| dhcp.poll(now));
+        }
+
+        let now = clock::uptime();
+        let before = self.waiting.len();
+        self.waiting.retain(|&(_, _, at)| now < at + ARP_TIMEOUT);
+        self.stats.dropped += (before - self.waiting.len()) as u64;
+    }
+}
+
+/// Attaches the interface with the Ethernet address `mac`, whose driver has
+/// it up. Its address is leased once its link is.
+pub fn attach(mac: MacAddr) {
+    let mut random = [0; 6];
+    pi::rng::fill(&mut random);
+    let [a, b, c, d, e, f] = random;
+
+    let interface = Interface {
+        mac,
+        link: false,
+        dhcp: dhcp::Client::new(mac, u32::from_le_bytes([a, b, c, d])),
+        arp: arp::Cache::default(),
+        waiting: Vec::new(),
+        tx: VecDeque::new(),
+        sockets: Vec::new(),
+        next_port: *EPHEMERAL_PORTS.start() + u16::from_le_bytes([e, f]) % 16384,
+        next_id: 0,
+        echo_replies: VecDeque::new(),
+        stats: Stats::default(),
+    };
+    with_net(|net| *net = Some(interface));
+}
+
+/// Records whether the interface's link is up, for its driver.
+pub fn set_link(up: bool) {
+    with_net(|net| {
+        if let Some(interface) = net {
+            if interface.link != up {
+                kprintln!("net: link {}", if up { "up" } else { "down" });
+            }
+            interface.link = up;
+        }
+    })
+}
+
+/// Takes the frame `frame`, without its checksum, that the driver received.
+pub fn receive(frame: &[u8]) {
+    with_net(|net| {
+        if let Some(interface) = net {
+            interface.receive(frame);
+        }
+    })
+}
+
+/// Returns the next frame for the driver to send, if any.
+pub fn transmit() -> Option<Vec<u8>> {
+    with_net(|net| {
+        let interface = net.as_mut()?;
+        let frame = interface.tx.pop_front()?;
+        interface.stats.tx_frames += 1;
+        interface.stats.tx_bytes += frame.len() as u64;
+        Some(frame)
+    })
+}
+
+/// Runs the stack's timers. The driver calls this every time it is polled.
+pub fn poll() {
+    with_net(|net| {
+        if let Some(interface) = net {
+            interface.poll();
+        }
+    })
+}
+
+/// Returns the state of the interface, if there is one.
+pub fn status() -> Option<Status> {
+    with_net(|net| {
+        net.as_ref().map(|interface| Status {
+            mac: interface.mac,
+            link: interface.link,
+            lease: interface.lease().copied(),
+            stats: interface.stats,
+        })
+    })
+}
+
+/// Sends an ICMP echo request to `dst` with the ID `id`, the sequence number
+/// `seq` and the data `data`.
+pub fn send_echo(dst: Ipv4Addr, id: u16, seq: u16, data: &[u8]) -> Result<(), Error> {
+    with_net(|net| {
+        let interface = net.as_mut().ok_or(Error::NoInterface)?;
+        if interface.lease().is_none() {
+            return Err(Error::NotConfigured);
+        }
+        let echo = icmp::Echo {
+            kind: icmp::ECHO_REQUEST,
+            id,
+            seq,
+            data,
+        };
+        interface.send_ipv4(dst, ipv4::PROTOCOL_ICMP, |_| echo.to_bytes())
+    })
+}
+
+/// Takes the echo reply with the ID `id` and the sequence number `seq`, if
+/// one was received, returning who it came from.
+pub fn echo_reply(id: u16, seq: u16) -> Option<Ipv4Addr> {
+    with_net(|net| {
+        let replies = &mut net.as_mut()?.echo_replies;
+        let i = replies.iter().position(|&(_, i, s)| (i, s) == (id, seq))?;
+        replies.remove(i).map(|(src, _, _)| src)
+    })
+}
+
+/// A UDP port, bound until the socket is dropped.
+#[derive(Debug)]
+pub struct UdpSocket {
+    port: u16,
+}
+
+impl UdpSocket {
+    /// Binds `port`, or a free ephemeral port if `port` is 0.
+    pub fn bind(port: u16) -> Result<UdpSocket, Error> {
+        with_net(|net| {
+            let interface = net.as_mut().ok_or(Error::NoInterface)?;
+            let taken = |port| {
+                port == dhcp::CLIENT_PORT
+                    || interface.sockets.iter().any(|s: &Socket| s.port == port)
+            };
+
+            let port = if port != 0 {
+                if taken(port) {
+                    return Err(Error::AddrInUse);
+                }
+                port
+            } else {
+                let start = interface.next_port;
+                let mut port = start;
+                while taken(port) {
+                    port = if port == *EPHEMERAL_PORTS.end() {
+                        *EPHEMERAL_PORTS.start()
+                    } else {
+                        port + 1
+                    };
+                    if port == start {
+                        return Err(Error::AddrInUse);
+                    }
+                }
+                interface.next_port = port.checked_add(1).unwrap_or(*EPHEMERAL_PORTS.start());
+                port
+            };
+
+            interface.sockets.push(Socket {
+                port,
+                queue: VecDeque::new(),
+            });
+            Ok(UdpSocket { port })
+        })
+    }
+
+    /// Returns the port the socket is bound to.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sends `data` to `port` at `dst`.
+    pub fn send_to(&self, data: &[u8], dst: Ipv4Addr, port: u16) -> Result<(), Error> {
+        with_net(|net| {
+            let interface = net.as_mut().ok_or(Error::NoInterface)?;
+            if interface.lease().is_none() {
+                return Err(Error::NotConfigured);
+            }
+            interface.send_udp(self.port, dst, port, data)
+        })
+    }
+
+    /// Takes the next datagram received on the socket, if any, into `buf`.
+    /// Returns the number of bytes taken, and the address and port it came
+    /// from. Bytes that do not fit in `buf` are dropped.
+    pub fn try_recv_from(&self, buf: &mut [u8]) -> Option<(usize, Ipv4Addr, u16)> {
+        let datagram = with_net(|net| {
+            let socket = net
+                .as_mut()?
+                .sockets
+                .iter_mut()
+                .find(|s| s.port == self.port)?;
+            socket.queue.pop_front()
+        })?;
+        let len = datagram.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram.data[..len]);
+        Some((len, datagram.src, datagram.port))
+    }
+
+    /// Like `try_recv_from()`, but waits up to `timeout` for a datagram,
+    /// sleeping in between checks.
+    pub fn recv_from(
+        &self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(usize, Ipv4Addr, u16), Error> {
+        let deadline = clock::uptime() + timeout;
+        loop {
+            if let Some(received) = self.try_recv_from(buf) {
+                return Ok(received);
+            } else if clock::uptime() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            process::sleep(RECV_INTERVAL);
+        }
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        with_net(|net| {
+            if let Some(interface) = net {
+                interface.sockets.retain(|s| s.port != self.port);
+            }
+        })
+    }
+}
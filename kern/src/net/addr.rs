@@ -0,0 +1,117 @@
+//! Ethernet and IPv4 addresses.
+
+use core::fmt;
+use core::str::FromStr;
+
+/// An Ethernet address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// The address frames to every host are sent to.
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+
+    /// Returns `true` if frames sent to the address are for more than one
+    /// host.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 1 != 0
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// An IPv4 address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    /// The address of a host that has none yet.
+    pub const UNSPECIFIED: Ipv4Addr = Ipv4Addr([0; 4]);
+    /// The address packets to every host on the local network are sent to.
+    pub const BROADCAST: Ipv4Addr = Ipv4Addr([0xff; 4]);
+
+    /// Returns the address as a number, most significant byte first.
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// Returns `true` if `self` and `other` are on the same network, as the
+    /// network mask `netmask` has it.
+    pub fn same_network(self, other: Ipv4Addr, netmask: Ipv4Addr) -> bool {
+        self.to_u32() & netmask.to_u32() == other.to_u32() & netmask.to_u32()
+    }
+}
+
+impl From<u32> for Ipv4Addr {
+    fn from(addr: u32) -> Ipv4Addr {
+        Ipv4Addr(addr.to_be_bytes())
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+impl FromStr for Ipv4Addr {
+    type Err = ();
+
+    /// Parses an address in dotted decimal, as in `10.0.0.1`.
+    fn from_str(s: &str) -> Result<Ipv4Addr, ()> {
+        let mut addr = [0; 4];
+        let mut parts = s.split('.');
+        for byte in addr.iter_mut() {
+            let part = parts.next().ok_or(())?;
+            if part.is_empty() || part.len() > 3 || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(());
+            }
+            *byte = part.parse().map_err(|_| ())?;
+        }
+        match parts.next() {
+            Some(_) => Err(()),
+            None => Ok(Ipv4Addr(addr)),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!("10.0.0.1".parse(), Ok(Ipv4Addr([10, 0, 0, 1])));
+        assert_eq!("255.255.255.0".parse(), Ok(Ipv4Addr([255, 255, 255, 0])));
+        assert_eq!("10.0.0".parse::<Ipv4Addr>(), Err(()));
+        assert_eq!("10.0.0.1.2".parse::<Ipv4Addr>(), Err(()));
+        assert_eq!("10.0.0.256".parse::<Ipv4Addr>(), Err(()));
+        assert_eq!("10..0.1".parse::<Ipv4Addr>(), Err(()));
+        assert_eq!("10.0.0.+1".parse::<Ipv4Addr>(), Err(()));
+    }
+
+    #[test]
+    fn formats_addresses() {
+        assert_eq!(Ipv4Addr([192, 168, 1, 20]).to_string(), "192.168.1.20");
+        let mac = MacAddr([0xb8, 0x27, 0xeb, 0x01, 0x2a, 0xff]);
+        assert_eq!(mac.to_string(), "b8:27:eb:01:2a:ff");
+    }
+
+    #[test]
+    fn compares_networks() {
+        let netmask = Ipv4Addr([255, 255, 255, 0]);
+        let addr = Ipv4Addr([10, 0, 0, 5]);
+        assert!(addr.same_network(Ipv4Addr([10, 0, 0, 1]), netmask));
+        assert!(!addr.same_network(Ipv4Addr([10, 0, 1, 1]), netmask));
+    }
+}
@@ -0,0 +1,156 @@
+//! ARP, which finds the Ethernet addresses of IPv4 addresses on the local
+//! network, and the cache of what it found.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use super::{Ipv4Addr, MacAddr};
+
+/// The size of a packet, for Ethernet and IPv4.
+pub const PACKET_LEN: usize = 28;
+
+/// The hardware and protocol types of Ethernet and IPv4, and their
+/// addresses' sizes.
+const HARDWARE_ETHERNET: u16 = 1;
+const PROTOCOL_IPV4: u16 = 0x0800;
+const HARDWARE_LEN: u8 = 6;
+const PROTOCOL_LEN: u8 = 4;
+
+/// Operations.
+pub const REQUEST: u16 = 1;
+pub const REPLY: u16 = 2;
+
+/// How many addresses the cache holds, and for how long.
+const CACHE_ENTRIES: usize = 16;
+const CACHE_LIFETIME: Duration = Duration::from_secs(300);
+
+/// A packet, for Ethernet and IPv4.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub operation: u16,
+    pub sender_mac: MacAddr,
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: MacAddr,
+    pub target_ip: Ipv4Addr,
+}
+
+impl Packet {
+    /// Parses `bytes`, or returns `None` if they are not a packet for
+    /// Ethernet and IPv4.
+    pub fn parse(bytes: &[u8]) -> Option<Packet> {
+        if bytes.len() < PACKET_LEN
+            || u16::from_be_bytes([bytes[0], bytes[1]]) != HARDWARE_ETHERNET
+            || u16::from_be_bytes([bytes[2], bytes[3]]) != PROTOCOL_IPV4
+            || bytes[4] != HARDWARE_LEN
+            || bytes[5] != PROTOCOL_LEN
+        {
+            return None;
+        }
+
+        let mac = |at: usize| {
+            let mut mac = [0; 6];
+            mac.copy_from_slice(&bytes[at..at + 6]);
+            MacAddr(mac)
+        };
+        let ip = |at: usize| Ipv4Addr([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        Some(Packet {
+            operation: u16::from_be_bytes([bytes[6], bytes[7]]),
+            sender_mac: mac(8),
+            sender_ip: ip(14),
+            target_mac: mac(18),
+            target_ip: ip(24),
+        })
+    }
+
+    /// Returns the packet's bytes.
+    pub fn to_bytes(self) -> [u8; PACKET_LEN] {
+        let mut bytes = [0; PACKET_LEN];
+        bytes[0..2].copy_from_slice(&HARDWARE_ETHERNET.to_be_bytes());
+        bytes[2..4].copy_from_slice(&PROTOCOL_IPV4.to_be_bytes());
+        bytes[4] = HARDWARE_LEN;
+        bytes[5] = PROTOCOL_LEN;
+        bytes[6..8].copy_from_slice(&self.operation.to_be_bytes());
+        bytes[8..14].copy_from_slice(&self.sender_mac.0);
+        bytes[14..18].copy_from_slice(&self.sender_ip.0);
+        bytes[18..24].copy_from_slice(&self.target_mac.0);
+        bytes[24..28].copy_from_slice(&self.target_ip.0);
+        bytes
+    }
+}
+
+/// The Ethernet addresses of IPv4 addresses, as replies and requests gave
+/// them, for `CACHE_LIFETIME`.
+#[derive(Debug, Default)]
+pub struct Cache {
+    /// Each address, its Ethernet address, and when it was learned.
+    entries: Vec<(Ipv4Addr, MacAddr, Duration)>,
+}
+
+impl Cache {
+    /// Returns the Ethernet address of `ip` as of `now`, if it is known.
+    pub fn get(&self, ip: Ipv4Addr, now: Duration) -> Option<MacAddr> {
+        self.entries
+            .iter()
+            .find(|&&(addr, _, at)| addr == ip && now < at + CACHE_LIFETIME)
+            .map(|&(_, mac, _)| mac)
+    }
+
+    /// Records that `ip` is at `mac` as of `now`. The oldest entry is
+    /// dropped if the cache is full.
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr, now: Duration) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.0 == ip) {
+            *entry = (ip, mac, now);
+            return;
+        }
+
+        if self.entries.len() == CACHE_ENTRIES {
+            let oldest = (0..self.entries.len()).min_by_key(|&i| self.entries[i].2);
+            if let Some(oldest) = oldest {
+                self.entries.swap_remove(oldest);
+            }
+        }
+        self.entries.push((ip, mac, now));
+    }
+
+    /// Returns the number of addresses held, expired or not.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let packet = Packet {
+            operation: REQUEST,
+            sender_mac: MacAddr([2, 0, 0, 0, 0, 1]),
+            sender_ip: Ipv4Addr([10, 0, 0, 5]),
+            target_mac: MacAddr::default(),
+            target_ip: Ipv4Addr([10, 0, 0, 1]),
+        };
+        assert_eq!(Packet::parse(&packet.to_bytes()), Some(packet));
+
+        let mut bytes = packet.to_bytes();
+        bytes[1] = 6;
+        assert_eq!(Packet::parse(&bytes), None);
+        assert_eq!(Packet::parse(&packet.to_bytes()[..27]), None);
+    }
+
+    #[test]
+    fn cache_expires_and_evicts() {
+        let mut cache = Cache::default();
+        let (ip, mac) = (Ipv4Addr([10, 0, 0, 1]), MacAddr([2, 0, 0, 0, 0, 1]));
+        cache.insert(ip, mac, Duration::from_secs(1));
+        assert_eq!(cache.get(ip, Duration::from_secs(2)), Some(mac));
+        assert_eq!(cache.get(ip, Duration::from_secs(301)), None);
+
+        for i in 0..CACHE_ENTRIES as u8 {
+            cache.insert(Ipv4Addr([10, 0, 1, i]), mac, Duration::from_secs(10));
+        }
+        assert_eq!(cache.len(), CACHE_ENTRIES);
+        assert_eq!(cache.get(ip, Duration::from_secs(10)), None);
+    }
+}
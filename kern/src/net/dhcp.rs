@@ -0,0 +1,362 @@
+//! A DHCP client, which leases the interface its address and learns the
+//! local network's mask and gateway.
+//!
+//! The client only builds and takes messages: the interface sends them as
+//! UDP broadcasts, and hands it the replies.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use super::{Ipv4Addr, MacAddr};
+
+/// The ports of servers and clients.
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+/// How long the client waits for a reply before it sends its message again,
+/// and how many times it asks for an offered lease before it starts over.
+const RETRY_INTERVAL: Duration = Duration::from_secs(4);
+const REQUEST_TRIES: usize = 4;
+
+/// The lease taken if the server does not say, and the shortest one taken.
+const DEFAULT_LEASE: Duration = Duration::from_secs(3600);
+const MIN_LEASE: Duration = Duration::from_secs(60);
+
+/// The `op`s of messages from clients and servers.
+const BOOT_REQUEST: u8 = 1;
+const BOOT_REPLY: u8 = 2;
+
+/// The size of a message before its options, and the cookie that starts
+/// them.
+const FIXED_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+
+/// The size messages are padded to, for relays that expect BOOTP.
+const MIN_MESSAGE_LEN: usize = 300;
+
+/// The `flags` bit that has servers broadcast their replies, since the
+/// client cannot take unicast packets before it has an address.
+const FLAG_BROADCAST: u16 = 0x8000;
+
+/// Options.
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_REQUESTED_ADDR: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_PARAMETERS: u8 = 55;
+const OPTION_END: u8 = 255;
+
+/// Message types.
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+
+/// A lease, and what the server said about the network with it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub addr: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns: Option<Ipv4Addr>,
+    /// The server that gave the lease.
+    pub server: Ipv4Addr,
+    pub duration: Duration,
+}
+
+/// Where the client is in leasing an address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    /// Looking for servers' offers.
+    Selecting,
+    /// Asking for an offered lease, some number of times so far.
+    Requesting(Lease, usize),
+    /// Holding a lease, since a time.
+    Bound(Lease, Duration),
+    /// Asking to keep a lease held since a time, half of which has passed.
+    Renewing(Lease, Duration),
+}
+
+/// A DHCP client.
+#[derive(Debug)]
+pub struct Client {
+    mac: MacAddr,
+    /// The transaction ID that ties replies to the client's messages.
+    xid: u32,
+    state: State,
+    /// When the client next sends a message.
+    next: Duration,
+}
+
+impl Client {
+    /// Returns a client for the interface with the address `mac`, which
+    /// tags its messages `xid` and has not looked for a server yet.
+    pub fn new(mac: MacAddr, xid: u32) -> Client {
+        Client {
+            mac,
+            xid,
+            state: State::Selecting,
+            next: Duration::from_secs(0),
+        }
+    }
+
+    /// Returns the lease the client holds, if any.
+    pub fn lease(&self) -> Option<&Lease> {
+        match self.state {
+            State::Bound(ref lease, _) | State::Renewing(ref lease, _) => Some(lease),
+            _ => None,
+        }
+    }
+
+    /// Returns the message the client sends at `now`, if it is time for one.
+    /// A lease that runs out is dropped.
+    pub fn poll(&mut self, now: Duration) -> Option<Vec<u8>> {
+        match self.state {
+            State::Bound(lease, since) if now >= since + lease.duration / 2 => {
+                self.state = State::Renewing(lease, since);
+                self.next = now;
+            }
+            State::Renewing(lease, since) if now >= since + lease.duration => {
+                self.state = State::Selecting;
+                self.next = now;
+            }
+            _ => {}
+        }
+        if now < self.next {
+            return None;
+        }
+
+        self.next = now + RETRY_INTERVAL;
+        match self.state {
+            State::Selecting => Some(self.message(DISCOVER, None, None)),
+            State::Requesting(_, tries) if tries == REQUEST_TRIES => {
+                self.state = State::Selecting;
+                Some(self.message(DISCOVER, None, None))
+            }
+            State::Requesting(offer, tries) => {
+                self.state = State::Requesting(offer, tries + 1);
+                Some(self.message(REQUEST, None, Some(&offer)))
+            }
+            State::Renewing(lease, _) => Some(self.message(REQUEST, Some(lease.addr), None)),
+            State::Bound(..) => None,
+        }
+    }
+
+    /// Takes `message`, received at `now`, if it is a reply to the client.
+    pub fn receive(&mut self, message: &[u8], now: Duration) {
+        let (kind, lease) = match self.parse(message) {
+            Some(reply) => reply,
+            None => return,
+        };
+
+        match (kind, self.state) {
+            (OFFER, State::Selecting) => {
+                self.state = State::Requesting(lease, 0);
+                self.next = now;
+            }
+            (ACK, State::Requesting(..)) | (ACK, State::Renewing(..)) => {
+                self.state = State::Bound(lease, now);
+            }
+            (NAK, State::Requesting(..)) | (NAK, State::Renewing(..)) => {
+                self.state = State::Selecting;
+                self.next = now;
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns a message of type `kind`, from the address `addr` if the
+    /// client has one, asking for `offer` if there is one.
+    fn message(&self, kind: u8, addr: Option<Ipv4Addr>, offer: Option<&Lease>) -> Vec<u8> {
+        let mut message = Vec::with_capacity(MIN_MESSAGE_LEN);
+        message.extend_from_slice(&[BOOT_REQUEST, 1, 6, 0]);
+        message.extend_from_slice(&self.xid.to_be_bytes());
+        message.extend_from_slice(&0u16.to_be_bytes());
+        message.extend_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        message.extend_from_slice(&addr.unwrap_or(Ipv4Addr::UNSPECIFIED).0);
+        // `yiaddr`, `siaddr` and `giaddr`.
+        message.resize(message.len() + 12, 0);
+        message.extend_from_slice(&self.mac.0);
+        message.resize(FIXED_LEN, 0);
+        message.extend_from_slice(&MAGIC_COOKIE);
+
+        message.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, kind]);
+        if let Some(offer) = offer {
+            message.extend_from_slice(&[OPTION_REQUESTED_ADDR, 4]);
+            message.extend_from_slice(&offer.addr.0);
+            message.extend_from_slice(&[OPTION_SERVER_ID, 4]);
+            message.extend_from_slice(&offer.server.0);
+        }
+        message.extend_from_slice(&[
+            OPTION_PARAMETERS,
+            4,
+            OPTION_SUBNET_MASK,
+            OPTION_ROUTER,
+            OPTION_DNS,
+            OPTION_LEASE_TIME,
+        ]);
+        message.push(OPTION_END);
+        if message.len() < MIN_MESSAGE_LEN {
+            message.resize(MIN_MESSAGE_LEN, OPTION_PAD);
+        }
+        message
+    }
+
+    /// Returns the type of `message` and the lease it offers or grants, or
+    /// `None` if it is not a reply to the client.
+    fn parse(&self, message: &[u8]) -> Option<(u8, Lease)> {
+        if message.len() < FIXED_LEN + MAGIC_COOKIE.len()
+            || message[0] != BOOT_REPLY
+            || message[4..8] != self.xid.to_be_bytes()
+            || message[28..34] != self.mac.0
+            || message[FIXED_LEN..FIXED_LEN + 4] != MAGIC_COOKIE
+        {
+            return None;
+        }
+
+        let addr = |bytes: &[u8]| Ipv4Addr([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let mut kind = None;
+        let mut lease = Lease {
+            addr: addr(&message[16..20]),
+            netmask: Ipv4Addr([255, 255, 255, 0]),
+            gateway: None,
+            dns: None,
+            server: addr(&message[20..24]),
+            duration: DEFAULT_LEASE,
+        };
+
+        let mut options = &message[FIXED_LEN + MAGIC_COOKIE.len()..];
+        while let Some((&code, rest)) = options.split_first() {
+            if code == OPTION_END {
+                break;
+            } else if code == OPTION_PAD {
+                options = rest;
+                continue;
+            }
+
+            let len = usize::from(*rest.first()?);
+            let value = rest.get(1..1 + len)?;
+            options = &rest[1 + len..];
+            match (code, len) {
+                (OPTION_MESSAGE_TYPE, 1) => kind = Some(value[0]),
+                (OPTION_SUBNET_MASK, 4) => lease.netmask = addr(value),
+                (OPTION_ROUTER, 4..=255) => lease.gateway = Some(addr(value)),
+                (OPTION_DNS, 4..=255) => lease.dns = Some(addr(value)),
+                (OPTION_SERVER_ID, 4) => lease.server = addr(value),
+                (OPTION_LEASE_TIME, 4) => {
+                    let secs = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+                    lease.duration = Duration::from_secs(u64::from(secs)).max(MIN_LEASE);
+                }
+                _ => {}
+            }
+        }
+        Some((kind?, lease))
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const MAC: MacAddr = MacAddr([0xb8, 0x27, 0xeb, 0, 0, 1]);
+    const XID: u32 = 0x1234_5678;
+    const ADDR: Ipv4Addr = Ipv4Addr([10, 0, 0, 5]);
+    const SERVER: Ipv4Addr = Ipv4Addr([10, 0, 0, 1]);
+
+    /// Returns a server's reply of type `kind` to the client.
+    fn reply(kind: u8, lease_secs: u32) -> Vec<u8> {
+        let mut message = vec![0; FIXED_LEN];
+        message[0] = BOOT_REPLY;
+        message[4..8].copy_from_slice(&XID.to_be_bytes());
+        message[16..20].copy_from_slice(&ADDR.0);
+        message[28..34].copy_from_slice(&MAC.0);
+        message.extend_from_slice(&MAGIC_COOKIE);
+        message.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, kind, OPTION_PAD]);
+        message.extend_from_slice(&[OPTION_SUBNET_MASK, 4, 255, 255, 0, 0]);
+        message.extend_from_slice(&[OPTION_ROUTER, 8, 10, 0, 0, 1, 10, 0, 0, 2]);
+        message.extend_from_slice(&[OPTION_SERVER_ID, 4, 10, 0, 0, 1]);
+        message.extend_from_slice(&[OPTION_LEASE_TIME, 4]);
+        message.extend_from_slice(&lease_secs.to_be_bytes());
+        message.push(OPTION_END);
+        message
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn leases_an_address() {
+        let mut client = Client::new(MAC, XID);
+        let discover = client.poll(secs(0)).unwrap();
+        assert_eq!(discover.len(), MIN_MESSAGE_LEN);
+        assert_eq!(&discover[FIXED_LEN + 4..FIXED_LEN + 7], &[53, 1, DISCOVER]);
+        assert_eq!(client.poll(secs(1)), None);
+
+        client.receive(&reply(OFFER, 600), secs(1));
+        let request = client.poll(secs(1)).unwrap();
+        assert_eq!(&request[FIXED_LEN + 4..FIXED_LEN + 7], &[53, 1, REQUEST]);
+        assert_eq!(
+            &request[FIXED_LEN + 7..FIXED_LEN + 13],
+            &[50, 4, 10, 0, 0, 5]
+        );
+        assert_eq!(client.lease(), None);
+
+        client.receive(&reply(ACK, 600), secs(2));
+        let lease = *client.lease().unwrap();
+        assert_eq!(lease.addr, ADDR);
+        assert_eq!(lease.netmask, Ipv4Addr([255, 255, 0, 0]));
+        assert_eq!(lease.gateway, Some(SERVER));
+        assert_eq!(lease.server, SERVER);
+        assert_eq!(lease.duration, secs(600));
+        assert_eq!(client.poll(secs(100)), None);
+    }
+
+    #[test]
+    fn renews_and_expires() {
+        let mut client = Client::new(MAC, XID);
+        client.poll(secs(0));
+        client.receive(&reply(OFFER, 600), secs(0));
+        client.poll(secs(0));
+        client.receive(&reply(ACK, 600), secs(0));
+
+        let renew = client.poll(secs(300)).unwrap();
+        assert_eq!(&renew[12..16], &ADDR.0);
+        assert!(client.lease().is_some());
+        assert_eq!(client.poll(secs(301)), None);
+        assert!(client.poll(secs(305)).is_some());
+
+        let discover = client.poll(secs(600)).unwrap();
+        assert_eq!(&discover[FIXED_LEN + 4..FIXED_LEN + 7], &[53, 1, DISCOVER]);
+        assert_eq!(client.lease(), None);
+    }
+
+    #[test]
+    fn ignores_other_clients_replies() {
+        let mut client = Client::new(MAC, XID);
+        client.poll(secs(0));
+        let mut offer = reply(OFFER, 600);
+        offer[4] ^= 1;
+        client.receive(&offer, secs(0));
+        assert_eq!(client.poll(secs(1)), None);
+
+        client.receive(&reply(ACK, 600), secs(1));
+        assert_eq!(client.lease(), None);
+    }
+
+    #[test]
+    fn starts_over_after_a_nak() {
+        let mut client = Client::new(MAC, XID);
+        client.poll(secs(0));
+        client.receive(&reply(OFFER, 600), secs(0));
+        client.poll(secs(0));
+        client.receive(&reply(NAK, 0), secs(1));
+        let discover = client.poll(secs(1)).unwrap();
+        assert_eq!(&discover[FIXED_LEN + 4..FIXED_LEN + 7], &[53, 1, DISCOVER]);
+    }
+}
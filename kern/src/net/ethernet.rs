@@ -0,0 +1,81 @@
+//! Ethernet framing.
+
+use alloc::vec::Vec;
+
+use super::MacAddr;
+
+/// The size of a frame's header.
+pub const HEADER_LEN: usize = 14;
+
+/// The smallest frame, without its checksum: shorter ones are padded.
+const MIN_FRAME_LEN: usize = 60;
+
+/// The largest payload a frame carries.
+pub const MTU: usize = 1500;
+
+/// The types of the payloads the stack handles.
+pub const TYPE_IPV4: u16 = 0x0800;
+pub const TYPE_ARP: u16 = 0x0806;
+
+/// A frame's header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub dst: MacAddr,
+    pub src: MacAddr,
+    /// The type of the payload.
+    pub kind: u16,
+}
+
+/// Splits `frame` into its header and payload, or returns `None` if it is
+/// too short to have a header.
+pub fn parse(frame: &[u8]) -> Option<(Header, &[u8])> {
+    if frame.len() < HEADER_LEN {
+        return None;
+    }
+
+    let mut dst = [0; 6];
+    let mut src = [0; 6];
+    dst.copy_from_slice(&frame[0..6]);
+    src.copy_from_slice(&frame[6..12]);
+    let header = Header {
+        dst: MacAddr(dst),
+        src: MacAddr(src),
+        kind: u16::from_be_bytes([frame[12], frame[13]]),
+    };
+    Some((header, &frame[HEADER_LEN..]))
+}
+
+/// Returns a frame with the header `header` carrying `payload`, padded to
+/// the smallest frame.
+pub fn frame(header: &Header, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MIN_FRAME_LEN.max(HEADER_LEN + payload.len()));
+    frame.extend_from_slice(&header.dst.0);
+    frame.extend_from_slice(&header.src.0);
+    frame.extend_from_slice(&header.kind.to_be_bytes());
+    frame.extend_from_slice(payload);
+    if frame.len() < MIN_FRAME_LEN {
+        frame.resize(MIN_FRAME_LEN, 0);
+    }
+    frame
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let header = Header {
+            dst: MacAddr::BROADCAST,
+            src: MacAddr([2, 0, 0, 0, 0, 1]),
+            kind: TYPE_ARP,
+        };
+        let frame = frame(&header, b"payload");
+        assert_eq!(frame.len(), MIN_FRAME_LEN);
+
+        let (parsed, payload) = parse(&frame).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(&payload[..7], b"payload");
+        assert!(parse(&frame[..HEADER_LEN - 1]).is_none());
+    }
+}
@@ -0,0 +1,75 @@
+//! ICMP echo requests and replies: what `ping` sends, and answers.
+
+use alloc::vec::Vec;
+
+use super::ipv4::{checksum, sum};
+
+/// The types of echo messages.
+pub const ECHO_REPLY: u8 = 0;
+pub const ECHO_REQUEST: u8 = 8;
+
+/// The size of an echo message's header.
+const HEADER_LEN: usize = 8;
+
+/// An echo request or reply.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Echo<'a> {
+    /// `ECHO_REQUEST` or `ECHO_REPLY`.
+    pub kind: u8,
+    pub id: u16,
+    pub seq: u16,
+    /// The data, which a reply carries back unchanged.
+    pub data: &'a [u8],
+}
+
+impl<'a> Echo<'a> {
+    /// Parses `bytes`, or returns `None` if they are not an echo message
+    /// with a good checksum.
+    pub fn parse(bytes: &'a [u8]) -> Option<Echo<'a>> {
+        if bytes.len() < HEADER_LEN || bytes[1] != 0 || checksum(sum(0, bytes)) != 0 {
+            return None;
+        }
+
+        match bytes[0] {
+            kind @ ECHO_REPLY | kind @ ECHO_REQUEST => Some(Echo {
+                kind,
+                id: u16::from_be_bytes([bytes[4], bytes[5]]),
+                seq: u16::from_be_bytes([bytes[6], bytes[7]]),
+                data: &bytes[HEADER_LEN..],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the message's bytes.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len());
+        bytes.extend_from_slice(&[self.kind, 0, 0, 0]);
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        bytes.extend_from_slice(self.data);
+        let checksum = checksum(sum(0, &bytes));
+        bytes[2..4].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echoes_round_trip() {
+        let echo = Echo {
+            kind: ECHO_REQUEST,
+            id: 0x1234,
+            seq: 3,
+            data: b"abc",
+        };
+        let mut bytes = echo.to_bytes();
+        assert_eq!(Echo::parse(&bytes), Some(echo));
+
+        bytes[8] = b'x';
+        assert_eq!(Echo::parse(&bytes), None);
+    }
+}
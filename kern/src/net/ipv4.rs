@@ -0,0 +1,153 @@
+//! IPv4 headers, and the Internet checksum IPv4 and the protocols it carries
+//! use. Fragments are not reassembled: they are dropped.
+
+use alloc::vec::Vec;
+
+use super::Ipv4Addr;
+
+/// The size of a header without options, which is all the stack sends.
+pub const HEADER_LEN: usize = 20;
+
+/// The protocols the stack handles.
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_UDP: u8 = 17;
+
+/// The time to live of the packets the stack sends.
+const TTL: u8 = 64;
+
+/// The flag that has a packet not fragmented, and the fields that are not 0
+/// in fragments.
+const DONT_FRAGMENT: u16 = 1 << 14;
+const MORE_FRAGMENTS: u16 = 1 << 13;
+const FRAGMENT_OFFSET: u16 = (1 << 13) - 1;
+
+/// A packet's header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub protocol: u8,
+}
+
+/// Adds the 16-bit words of `bytes` to `sum`, for a checksum. A last odd
+/// byte is padded with 0.
+pub fn sum(mut sum: u32, bytes: &[u8]) -> u32 {
+    let mut words = bytes.chunks_exact(2);
+    for word in &mut words {
+        sum += u32::from(u16::from_be_bytes([word[0], word[1]]));
+    }
+    if let [byte] = words.remainder() {
+        sum += u32::from(*byte) << 8;
+    }
+    sum
+}
+
+/// Returns the checksum of a `sum` of words: the ones' complement of their
+/// ones' complement sum.
+pub fn checksum(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Returns the sum of the pseudo-header UDP checksums cover, for a datagram
+/// of `len` bytes.
+pub fn pseudo_header_sum(header: &Header, len: usize) -> u32 {
+    let sum = sum(0, &header.src.0);
+    let sum = self::sum(sum, &header.dst.0);
+    sum + u32::from(header.protocol) + len as u32
+}
+
+/// Splits `packet` into its header and payload. Returns `None` if it is not
+/// a whole IPv4 packet with a good checksum, or if it is a fragment.
+pub fn parse(packet: &[u8]) -> Option<(Header, &[u8])> {
+    if packet.len() < HEADER_LEN || packet[0] >> 4 != 4 {
+        return None;
+    }
+
+    let header_len = usize::from(packet[0] & 0xf) * 4;
+    let len = usize::from(u16::from_be_bytes([packet[2], packet[3]]));
+    if header_len < HEADER_LEN || len < header_len || len > packet.len() {
+        return None;
+    }
+    if checksum(sum(0, &packet[..header_len])) != 0 {
+        return None;
+    }
+    let fragment = u16::from_be_bytes([packet[6], packet[7]]);
+    if fragment & (MORE_FRAGMENTS | FRAGMENT_OFFSET) != 0 {
+        return None;
+    }
+
+    let header = Header {
+        src: Ipv4Addr([packet[12], packet[13], packet[14], packet[15]]),
+        dst: Ipv4Addr([packet[16], packet[17], packet[18], packet[19]]),
+        protocol: packet[9],
+    };
+    Some((header, &packet[header_len..len]))
+}
+
+/// Returns a packet with the header `header` and the identification `id`,
+/// carrying `payload`.
+pub fn packet(header: &Header, id: u16, payload: &[u8]) -> Vec<u8> {
+    let len = (HEADER_LEN + payload.len()) as u16;
+    let mut packet = Vec::with_capacity(usize::from(len));
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&len.to_be_bytes());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&DONT_FRAGMENT.to_be_bytes());
+    packet.extend_from_slice(&[TTL, header.protocol, 0, 0]);
+    packet.extend_from_slice(&header.src.0);
+    packet.extend_from_slice(&header.dst.0);
+    let checksum = checksum(sum(0, &packet));
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        // The example from RFC 1071.
+        let bytes = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(sum(0, &bytes)), !0xddf2);
+        assert_eq!(sum(0, &[0x12]), 0x1200);
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let header = Header {
+            src: Ipv4Addr([10, 0, 0, 5]),
+            dst: Ipv4Addr([10, 0, 0, 1]),
+            protocol: PROTOCOL_UDP,
+        };
+        let mut packet = packet(&header, 7, b"data");
+        assert_eq!(packet.len(), HEADER_LEN + 4);
+        assert_eq!(parse(&packet), Some((header, &b"data"[..])));
+
+        // Ethernet padding is not part of the packet.
+        packet.extend_from_slice(&[0; 10]);
+        assert_eq!(parse(&packet), Some((header, &b"data"[..])));
+
+        packet[12] ^= 1;
+        assert_eq!(parse(&packet), None);
+    }
+
+    #[test]
+    fn drops_fragments() {
+        let header = Header {
+            src: Ipv4Addr([10, 0, 0, 5]),
+            dst: Ipv4Addr([10, 0, 0, 1]),
+            protocol: PROTOCOL_UDP,
+        };
+        let mut packet = packet(&header, 7, b"data");
+        packet[6] = (MORE_FRAGMENTS >> 8) as u8;
+        packet[10..12].copy_from_slice(&[0, 0]);
+        let checksum = checksum(sum(0, &packet[..HEADER_LEN]));
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(parse(&packet), None);
+    }
+}
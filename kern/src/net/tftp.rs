@@ -0,0 +1,274 @@
+//! A TFTP client, for fetching kernels and programs from a server on the
+//! local network.
+//!
+//! Transfers are in octet mode and ask for blocks as large as fit in one
+//! packet, falling back to the standard 512 bytes if the server does not
+//! take the option.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str;
+use core::time::Duration;
+
+use shim::io;
+
+use super::{Error as NetError, Ipv4Addr, UdpSocket, MAX_PAYLOAD};
+
+/// The port servers take requests on.
+pub const SERVER_PORT: u16 = 69;
+
+/// The packets' opcodes.
+const READ_REQUEST: u16 = 1;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+const OPTION_ACK: u16 = 6;
+
+/// The error codes sent to a server when a transfer is abandoned.
+const DISK_FULL: u16 = 3;
+const ILLEGAL_OPERATION: u16 = 4;
+
+/// The size of a data packet's header.
+const DATA_HEADER_LEN: usize = 4;
+
+/// The block size servers use unless they take the `blksize` option, and the
+/// largest block that fits in one packet.
+const DEFAULT_BLOCK_SIZE: usize = 512;
+const MAX_BLOCK_SIZE: usize = MAX_PAYLOAD - DATA_HEADER_LEN;
+
+/// How long to wait for the server before sending the last packet again, and
+/// how many times to send it again before giving up.
+const TIMEOUT: Duration = Duration::from_secs(1);
+const RETRIES: usize = 5;
+
+/// Why a transfer failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The network failed, or the server stopped answering.
+    Net(NetError),
+    /// Writing what was received failed.
+    Io(io::Error),
+    /// The server refused or abandoned the transfer, saying why.
+    Refused(String),
+    /// The server sent something that makes no sense at that point.
+    Protocol,
+}
+
+impl From<NetError> for Error {
+    fn from(error: NetError) -> Error {
+        Error::Net(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Net(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Refused(message) => write!(f, "server error: {}", message),
+            Error::Protocol => f.write_str("unexpected packet from server"),
+        }
+    }
+}
+
+/// A packet a server sends.
+#[derive(Debug, PartialEq, Eq)]
+enum Packet<'a> {
+    Data {
+        block: u16,
+        data: &'a [u8],
+    },
+    Error {
+        code: u16,
+        message: &'a str,
+    },
+    /// The options the server took, as NUL-terminated names and values.
+    OptionAck(&'a [u8]),
+}
+
+impl<'a> Packet<'a> {
+    /// Parses `bytes`, or returns `None` if they are not a packet a server
+    /// sends.
+    fn parse(bytes: &'a [u8]) -> Option<Packet<'a>> {
+        if bytes.len() < 2 {
+            return None;
+        }
+
+        let field = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        match field(0) {
+            DATA if bytes.len() >= DATA_HEADER_LEN => Some(Packet::Data {
+                block: field(2),
+                data: &bytes[DATA_HEADER_LEN..],
+            }),
+            ERROR if bytes.len() >= 4 => {
+                let message = bytes[4..].split(|&b| b == 0).next().unwrap_or(&[]);
+                Some(Packet::Error {
+                    code: field(2),
+                    message: str::from_utf8(message).unwrap_or("(garbled message)"),
+                })
+            }
+            OPTION_ACK => Some(Packet::OptionAck(&bytes[2..])),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a request to read the file `name`, asking for blocks of
+/// `block_size` bytes.
+fn read_request(name: &str, block_size: usize) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(32 + name.len());
+    packet.extend_from_slice(&READ_REQUEST.to_be_bytes());
+    for field in &[name, "octet", "blksize", &block_size.to_string()] {
+        packet.extend_from_slice(field.as_bytes());
+        packet.push(0);
+    }
+    packet
+}
+
+/// Returns an acknowledgement of the block `block`.
+fn ack(block: u16) -> Vec<u8> {
+    let mut packet = ACK.to_be_bytes().to_vec();
+    packet.extend_from_slice(&block.to_be_bytes());
+    packet
+}
+
+/// Returns an error packet telling the server why a transfer was abandoned.
+fn error(code: u16, message: &str) -> Vec<u8> {
+    let mut packet = ERROR.to_be_bytes().to_vec();
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    packet
+}
+
+/// Returns the block size in a server's acknowledged `options`, or `None` if
+/// it did not take the option or its value is garbled.
+fn acked_block_size(options: &[u8]) -> Option<usize> {
+    let mut fields = options.split(|&b| b == 0);
+    while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+        if name.eq_ignore_ascii_case(b"blksize") {
+            return str::from_utf8(value).ok()?.parse().ok();
+        }
+    }
+    None
+}
+
+/// Fetches the file `name` from the server at `server`, writing it to
+/// `into`. Returns the file's size.
+pub fn get(server: Ipv4Addr, name: &str, into: &mut dyn io::Write) -> Result<usize, Error> {
+    let socket = UdpSocket::bind(0)?;
+    // The server answers from a port of its own, which the rest of the
+    // transfer goes to.
+    let mut transfer_port = None;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut expected: u16 = 1;
+    let mut size = 0;
+    let mut last = read_request(name, MAX_BLOCK_SIZE);
+    let mut buf = [0; MAX_PAYLOAD];
+    let mut tries = 0;
+
+    socket.send_to(&last, server, SERVER_PORT)?;
+    loop {
+        let (len, src, src_port) = match socket.recv_from(&mut buf, TIMEOUT) {
+            Ok(received) => received,
+            Err(NetError::TimedOut) if tries < RETRIES => {
+                tries += 1;
+                socket.send_to(&last, server, transfer_port.unwrap_or(SERVER_PORT))?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if src != server || transfer_port.map_or(false, |p| p != src_port) {
+            continue;
+        }
+        let port = *transfer_port.get_or_insert(src_port);
+
+        match Packet::parse(&buf[..len]) {
+            Some(Packet::OptionAck(options)) if expected == 1 => {
+                block_size = acked_block_size(options).unwrap_or(DEFAULT_BLOCK_SIZE);
+                if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+                    let _ = socket.send_to(&error(ILLEGAL_OPERATION, "bad blksize"), server, port);
+                    return Err(Error::Protocol);
+                }
+                last = ack(0);
+            }
+            Some(Packet::Data { block, data }) if block == expected => {
+                if data.len() > block_size {
+                    let _ =
+                        socket.send_to(&error(ILLEGAL_OPERATION, "block too large"), server, port);
+                    return Err(Error::Protocol);
+                }
+                if let Err(e) = into.write_all(data) {
+                    let _ = socket.send_to(&error(DISK_FULL, "write failed"), server, port);
+                    return Err(Error::Io(e));
+                }
+
+                size += data.len();
+                last = ack(block);
+                expected = expected.wrapping_add(1);
+                if data.len() < block_size {
+                    // Nothing answers the last acknowledgement, so if it is
+                    // lost the server just gives up on its own.
+                    socket.send_to(&last, server, port)?;
+                    return Ok(size);
+                }
+            }
+            // The server did not get the last acknowledgement: send it again.
+            Some(Packet::Data { block, .. }) if block == expected.wrapping_sub(1) => {}
+            Some(Packet::Error { message, .. }) => return Err(Error::Refused(message.into())),
+            _ => {
+                let _ =
+                    socket.send_to(&error(ILLEGAL_OPERATION, "unexpected packet"), server, port);
+                return Err(Error::Protocol);
+            }
+        }
+
+        tries = 0;
+        socket.send_to(&last, server, port)?;
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_requests_ask_for_octet_mode_and_a_block_size() {
+        assert_eq!(
+            read_request("kernel8.img", 1468),
+            b"\0\x01kernel8.img\0octet\0blksize\01468\0"
+        );
+    }
+
+    #[test]
+    fn server_packets_parse() {
+        assert_eq!(
+            Packet::parse(b"\0\x03\x01\x02abc"),
+            Some(Packet::Data {
+                block: 0x0102,
+                data: b"abc"
+            })
+        );
+        assert_eq!(
+            Packet::parse(b"\0\x05\0\x01File not found\0"),
+            Some(Packet::Error {
+                code: 1,
+                message: "File not found"
+            })
+        );
+        assert_eq!(
+            Packet::parse(b"\0\x06blksize\01024\0"),
+            Some(Packet::OptionAck(b"blksize\01024\0"))
+        );
+        assert_eq!(Packet::parse(b"\0\x04\0\x01"), None);
+        assert_eq!(Packet::parse(b"\0\x03\0"), None);
+    }
+
+    #[test]
+    fn block_sizes_are_read_from_option_acks() {
+        assert_eq!(acked_block_size(b"tsize\0100\0BLKSIZE\01024\0"), Some(1024));
+        assert_eq!(acked_block_size(b"tsize\0100\0"), None);
+        assert_eq!(acked_block_size(b"blksize\0lots\0"), None);
+    }
+}
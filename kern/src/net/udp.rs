@@ -0,0 +1,89 @@
+//! UDP datagrams.
+
+use alloc::vec::Vec;
+
+use super::ipv4::{self, checksum, pseudo_header_sum, sum};
+
+/// The size of a datagram's header.
+pub const HEADER_LEN: usize = 8;
+
+/// A datagram's ports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub src_port: u16,
+    pub dst_port: u16,
+}
+
+/// Splits `datagram`, carried in a packet with the header `ip`, into its
+/// header and payload. Returns `None` if it is cut short or its checksum is
+/// bad.
+pub fn parse<'a>(ip: &ipv4::Header, datagram: &'a [u8]) -> Option<(Header, &'a [u8])> {
+    if datagram.len() < HEADER_LEN {
+        return None;
+    }
+
+    let len = usize::from(u16::from_be_bytes([datagram[4], datagram[5]]));
+    if len < HEADER_LEN || len > datagram.len() {
+        return None;
+    }
+    let datagram = &datagram[..len];
+    // A checksum of 0 means the sender did not compute one.
+    if datagram[6..8] != [0, 0] && checksum(sum(pseudo_header_sum(ip, len), datagram)) != 0 {
+        return None;
+    }
+
+    let header = Header {
+        src_port: u16::from_be_bytes([datagram[0], datagram[1]]),
+        dst_port: u16::from_be_bytes([datagram[2], datagram[3]]),
+    };
+    Some((header, &datagram[HEADER_LEN..]))
+}
+
+/// Returns a datagram with the header `header` carrying `payload`, to be
+/// carried in a packet with the header `ip`.
+pub fn datagram(ip: &ipv4::Header, header: &Header, payload: &[u8]) -> Vec<u8> {
+    let len = HEADER_LEN + payload.len();
+    let mut datagram = Vec::with_capacity(len);
+    datagram.extend_from_slice(&header.src_port.to_be_bytes());
+    datagram.extend_from_slice(&header.dst_port.to_be_bytes());
+    datagram.extend_from_slice(&(len as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+
+    // A computed checksum of 0 is sent as its other form, all ones.
+    let checksum = match checksum(sum(pseudo_header_sum(ip, len), &datagram)) {
+        0 => 0xffff,
+        checksum => checksum,
+    };
+    datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+    datagram
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    use crate::net::Ipv4Addr;
+
+    const IP: ipv4::Header = ipv4::Header {
+        src: Ipv4Addr([10, 0, 0, 5]),
+        dst: Ipv4Addr([10, 0, 0, 1]),
+        protocol: ipv4::PROTOCOL_UDP,
+    };
+
+    #[test]
+    fn datagrams_round_trip() {
+        let header = Header {
+            src_port: 49152,
+            dst_port: 69,
+        };
+        let mut datagram = datagram(&IP, &header, b"hello");
+        assert_eq!(parse(&IP, &datagram), Some((header, &b"hello"[..])));
+
+        datagram[9] ^= 1;
+        assert_eq!(parse(&IP, &datagram), None);
+
+        datagram[6..8].copy_from_slice(&[0, 0]);
+        assert!(parse(&IP, &datagram).is_some());
+    }
+}
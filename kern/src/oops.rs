@@ -0,0 +1,209 @@
+//! Crash reports, or oopses, for what the kernel cannot recover from: panics
+//! and the exceptions it halts on.
+//!
+//! An oops follows the usual report on the console, framed so that a script
+//! reading the serial log, such as `bin/oops.py`, can pick it out:
+//!
+//! ```text
+//! -----BEGIN OOPS-----
+//! version: 1
+//! reason: exception
+//! core: 0
+//! uptime: 12.000345
+//! ...
+//! -----END OOPS-----
+//! ```
+//!
+//! Every line between the markers is a `key: value` pair, with any newlines
+//! in the value escaped. Some keys, like `frame`, `lock` and `log`, repeat,
+//! in order. Everything is printed with the lock-free, allocation-free
+//! printer, and what other subsystems know is only read if no other core is
+//! using it, so an oops can be reported from anywhere.
+//!
+//! Whoever reports a crash freezes the kernel's log before printing anything
+//! about it, so that the `log` lines are what led up to the crash.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use crate::aarch64::{self, FAR_EL1};
+use crate::clock;
+use crate::console::{kprintln_nolock, log};
+use crate::mutex;
+use crate::symbols::Symbolized;
+use crate::traps::{Info, Kind, Syndrome, TrapFrame};
+use crate::{ALLOCATOR, SCHEDULER};
+
+/// The line an oops starts with.
+pub const BEGIN: &str = "-----BEGIN OOPS-----";
+
+/// The line an oops ends with.
+pub const END: &str = "-----END OOPS-----";
+
+/// The version of the format, which changes whenever a key's meaning does.
+const VERSION: u32 = 1;
+
+/// The most frames of a backtrace an oops or a panic lists.
+pub const MAX_FRAMES: usize = 32;
+
+/// The number of the latest lines of the kernel's log an oops includes.
+const LOG_LINES: usize = 20;
+
+/// Reports the panic `info`. `fp` is the frame pointer of the panic handler.
+pub fn panic(info: &PanicInfo, fp: usize) {
+    begin("panic");
+    kprintln_nolock!("message: {}", OneLine(info));
+    for addr in backtrace(fp) {
+        kprintln_nolock!("frame: {}", Symbolized(addr));
+    }
+    end();
+}
+
+/// Reports the exception `info`, with syndrome `esr`, that interrupted the
+/// state `tf`.
+pub fn exception(info: Info, esr: u32, tf: &TrapFrame) {
+    begin("exception");
+    kprintln_nolock!("exception: {:?} from {:?}", info.kind, info.source);
+    kprintln_nolock!("esr: {:#010x}", esr);
+    if info.kind == Kind::Synchronous {
+        kprintln_nolock!("syndrome: {:?}", Syndrome::from(esr));
+    }
+    kprintln_nolock!("far: {:#018x}", FAR_EL1::read());
+    kprintln_nolock!("pc: {}", Symbolized(tf.elr as usize));
+    kprintln_nolock!("elr: {:#018x}", tf.elr);
+    kprintln_nolock!("spsr: {:#018x}", tf.spsr);
+    kprintln_nolock!("sp0: {:#018x}", tf.sp);
+    kprintln_nolock!("tpidr: {:#018x}", tf.tpidr);
+    for (i, x) in tf.x.iter().enumerate() {
+        kprintln_nolock!("x{}: {:#018x}", i, x);
+    }
+    for addr in backtrace(tf.x[29] as usize) {
+        kprintln_nolock!("frame: {}", Symbolized(addr));
+    }
+    end();
+}
+
+/// Starts an oops for `reason`, with what every oops reports first.
+fn begin(reason: &str) {
+    let core = aarch64::affinity();
+    let uptime = clock::uptime();
+    kprintln_nolock!("");
+    kprintln_nolock!("{}", BEGIN);
+    kprintln_nolock!("version: {}", VERSION);
+    kprintln_nolock!("reason: {}", reason);
+    kprintln_nolock!("core: {}", core);
+    kprintln_nolock!("uptime: {}.{:06}", uptime.as_secs(), uptime.subsec_micros());
+}
+
+/// Ends an oops, with what every oops reports last: the state of the rest of
+/// the kernel.
+fn end() {
+    let core = aarch64::affinity();
+    let reported = SCHEDULER.try_running(|id, name| {
+        kprintln_nolock!("process: {} {}", id, OneLine(name));
+    });
+    if reported.is_none() {
+        kprintln_nolock!("process: unknown");
+    }
+
+    for &addr in mutex::held_by(core).iter().filter(|&&addr| addr != 0) {
+        kprintln_nolock!("lock: {:#x}", addr);
+    }
+
+    match ALLOCATOR.try_stats() {
+        Some(stats) => kprintln_nolock!(
+            "heap: total={} used={} peak={} free={}",
+            stats.total,
+            stats.used,
+            stats.peak,
+            stats.free
+        ),
+        None => kprintln_nolock!("heap: unknown"),
+    }
+
+    log::try_with(|log| {
+        for line in log.lines().skip(log.len().saturating_sub(LOG_LINES)) {
+            kprintln_nolock!("log: {}", OneLine(text(line)));
+        }
+    });
+
+    kprintln_nolock!("{}", END);
+}
+
+/// Returns the longest prefix of `bytes` that is UTF-8. A line of the log may
+/// have been split in the middle of a character.
+fn text(bytes: &[u8]) -> &str {
+    match core::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+    }
+}
+
+/// Returns where each function called the next, innermost first, by
+/// following the chain of frame records from the one `fp` points to: the
+/// address of each call. At most `MAX_FRAMES` are returned.
+pub fn backtrace(mut fp: usize) -> impl Iterator<Item = usize> {
+    core::iter::from_fn(move || {
+        if fp == 0
+            || fp % 8 != 0
+            || !aarch64::can_access(fp, false)
+            || !aarch64::can_access(fp + 8, false)
+        {
+            return None;
+        }
+        // A frame record is the caller's frame pointer, then the return
+        // address, just past the call.
+        let (next, lr) = unsafe { (*(fp as *const usize), *((fp + 8) as *const usize)) };
+        if lr < 4 {
+            return None;
+        }
+
+        // Callers' frames are further up the stack.
+        fp = if next > fp { next } else { 0 };
+        Some(lr - 4)
+    })
+    .take(MAX_FRAMES)
+}
+
+/// Displays a value on one line, with `\n`, `\r` and `\` escaped.
+struct OneLine<T>(T);
+
+impl<T: fmt::Display> fmt::Display for OneLine<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct Escaper<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+        impl fmt::Write for Escaper<'_, '_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for c in s.chars() {
+                    match c {
+                        '\n' => self.0.write_str("\\n")?,
+                        '\r' => self.0.write_str("\\r")?,
+                        '\\' => self.0.write_str("\\\\")?,
+                        c => self.0.write_char(c)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        write!(Escaper(f), "{}", self.0)
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{text, OneLine};
+
+    #[test]
+    fn one_line() {
+        assert_eq!(format!("{}", OneLine("a\nb\r\\c")), "a\\nb\\r\\\\c");
+        assert_eq!(format!("{}", OneLine(12)), "12");
+    }
+
+    #[test]
+    fn text_drops_split_character() {
+        assert_eq!(text(b"plain"), "plain");
+        assert_eq!(text("ツ".as_bytes()), "ツ");
+        assert_eq!(text(&"aツ".as_bytes()[..2]), "a");
+    }
+}
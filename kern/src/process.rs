@@ -0,0 +1,485 @@
+pub mod channel;
+pub mod exec;
+pub mod fd;
+pub mod pipe;
+mod scheduler;
+pub mod signal;
+mod stack;
+mod state;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::mem::size_of;
+use core::time::Duration;
+
+use kernel_api::decode;
+
+use crate::aarch64;
+use crate::clock;
+use crate::console::ConsoleFile;
+use crate::irq;
+use crate::traps::TrapFrame;
+use crate::vm::{SharedSpace, UserSpace};
+use crate::SCHEDULER;
+use crate::VMM;
+
+pub use kernel_api::{NICE_MAX, NICE_MIN, WNOHANG};
+
+pub use self::exec::{ExecError, Image};
+pub use self::fd::{Description, FdTable, SharedFdTable, Stream};
+pub use self::scheduler::{GlobalScheduler, TIME_SLICE};
+pub use self::signal::Signals;
+pub use self::stack::Stack;
+pub use self::state::State;
+
+/// A process ID. The process running `kmain` is 1.
+pub type Id = u64;
+
+/// Something a process can block until, such as there being bytes to read
+/// from a pipe, named by the address of what it concerns.
+pub type Event = usize;
+
+/// The ID of the idle process, which runs when no other process can.
+pub const IDLE_ID: Id = 0;
+
+/// The size of a kernel thread's stack.
+pub const KERNEL_STACK_SIZE: usize = 32 << 10;
+
+/// The `SPSR_EL1` a kernel thread starts with: EL1 using `SP_EL1`, with no
+/// exceptions masked.
+const SPSR_EL1H: u64 = 0b0101;
+
+/// The number of bytes the exception vectors push above a trap frame: the
+/// interrupted `lr` and `x0`.
+const VECTOR_PUSH: usize = 16;
+
+/// A snapshot of a process, as listed by `GlobalScheduler::processes()`.
+#[derive(Debug, Clone)]
+pub struct Info {
+    pub id: Id,
+    pub parent: Option<Id>,
+    pub name: String,
+    pub state: State,
+    pub nice: i32,
+    /// The core running the process, if it is running.
+    pub core: Option<usize>,
+    /// How long the process has run for, counting its run so far if it is
+    /// running.
+    pub cpu_time: Duration,
+    /// The number of times the process has been switched from.
+    pub switches: u64,
+}
+
+/// When `SIGALRM` is next sent to a process, and how often after that.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Alarm {
+    /// The uptime at which the alarm goes off.
+    pub due: Duration,
+    /// The time after which the alarm goes off again, or `None` if it only
+    /// goes off once.
+    pub interval: Option<Duration>,
+}
+
+/// The function a kernel thread runs.
+type Thread = Box<dyn FnOnce() + Send>;
+
+/// A thread of execution with a kernel stack of its own, and optionally a user
+/// address space.
+///
+/// The threads of a user program are processes too, each with an ID of its
+/// own, which share their address space and file descriptors: see
+/// `Process::thread()`.
+///
+/// A process that is not running is resumed by returning from the exception
+/// that stopped it: its registers are in the trap frame the exception vector
+/// saved on its kernel stack, and switching to it means restoring that frame
+/// instead of the current one. See `GlobalScheduler::switch()`.
+///
+/// A user program runs at EL0 with a stack of its own in its address space.
+/// Its kernel stack is empty while it runs, so each exception it takes saves
+/// its trap frame at the top of that stack.
+pub struct Process {
+    pub id: Id,
+    pub name: String,
+    pub state: State,
+    /// The process that started this one and may wait for it to exit, or
+    /// `None` once nothing will: see `GlobalScheduler::exit()`.
+    pub parent: Option<Id>,
+    /// The trap frame saved on the kernel stack when the process last
+    /// stopped running, or null while it runs.
+    frame: *mut TrapFrame,
+    /// The kernel stack, or `None` for `kmain`'s, the boot stack.
+    stack: Option<Stack>,
+    /// The user address space, shared with the process's threads. While the
+    /// process runs, it is the one active in `VMM` on its core.
+    space: Option<SharedSpace>,
+    /// Whether the process runs a user program, rather than kernel code only.
+    user: bool,
+    /// The open file descriptors, shared with the process's threads.
+    pub files: SharedFdTable,
+    /// The signals sent to the process, and what it does with them.
+    pub signals: Signals,
+    /// The nice value, from `NICE_MIN` to `NICE_MAX`: the lower it is, the
+    /// sooner the process runs.
+    pub nice: i32,
+    /// The number of time slices the process has waited for in the ready
+    /// queue, which raise its priority until its time slice ends.
+    age: i32,
+    /// How long the process has run for, up to the last time it was
+    /// switched from.
+    cpu_time: Duration,
+    /// The number of times the process has been switched from, whether it
+    /// gave up the core or was preempted.
+    switches: u64,
+    /// The alarm set by `SYS_ALARM`, if any. A child's starts unset.
+    alarm: Option<Alarm>,
+    /// The most CPU time the process may use, if it is limited, past which
+    /// the scheduler kills it. Children and threads start with the same limit.
+    cpu_limit: Option<Duration>,
+}
+
+// The trap frame is on the process's own kernel stack, which moves with it.
+unsafe impl Send for Process {}
+
+impl Process {
+    /// Returns the process for the code already running on the boot stack:
+    /// `kmain`.
+    fn adopt(id: Id, name: &str) -> Process {
+        Process {
+            id,
+            name: String::from(name),
+            state: State::Running,
+            parent: None,
+            frame: core::ptr::null_mut(),
+            stack: None,
+            space: None,
+            user: false,
+            files: FdTable::new().shared(),
+            signals: Signals::new(),
+            nice: 0,
+            age: 0,
+            cpu_time: Duration::from_secs(0),
+            switches: 0,
+            alarm: None,
+            cpu_limit: None,
+        }
+    }
+
+    /// Returns a kernel thread, ready to run, that calls `f` and then exits,
+    /// or `None` if there is no memory for its stack.
+    fn kernel_thread(id: Id, name: &str, parent: Option<Id>, f: Thread) -> Option<Process> {
+        let stack = Stack::new(KERNEL_STACK_SIZE)?;
+        let mut tf = TrapFrame::default();
+        tf.elr = thread_start as usize as u64;
+        tf.spsr = SPSR_EL1H;
+        tf.x[0] = Box::into_raw(Box::new(f)) as u64;
+
+        Some(Process {
+            id,
+            name: String::from(name),
+            state: State::Ready,
+            parent,
+            frame: push_frame(&stack, tf),
+            stack: Some(stack),
+            space: None,
+            user: false,
+            files: FdTable::new().shared(),
+            signals: Signals::new(),
+            nice: 0,
+            age: 0,
+            cpu_time: Duration::from_secs(0),
+            switches: 0,
+            alarm: None,
+            cpu_limit: None,
+        })
+    }
+
+    /// Returns a process, ready to run, that starts the program `image` in
+    /// user mode, or `None` if there is no memory for its kernel stack. Its
+    /// standard input, output and error are the console.
+    fn user(id: Id, name: &str, parent: Option<Id>, image: Image) -> Option<Process> {
+        let stack = Stack::new(KERNEL_STACK_SIZE)?;
+        Some(Process {
+            id,
+            name: String::from(name),
+            state: State::Ready,
+            parent,
+            frame: push_frame(&stack, image.frame),
+            stack: Some(stack),
+            space: Some(image.space.shared()),
+            user: true,
+            files: FdTable::with_stdio(console()).shared(),
+            signals: Signals::new(),
+            nice: 0,
+            age: 0,
+            cpu_time: Duration::from_secs(0),
+            switches: 0,
+            alarm: None,
+            cpu_limit: None,
+        })
+    }
+
+    /// Returns a child of this process, which is running and stopped with the
+    /// trap frame `tf` in a `SYS_FORK` system call, with the ID `id` and the
+    /// user address space `space`. The child is ready to return from the
+    /// system call with 0 in `x0`, with a copy of every other register, of
+    /// the file descriptors, of the signal actions and of the nice value.
+    /// Returns `None` if there is no memory for its kernel stack, or if this
+    /// process's kernel stack holds anything but `tf`, since only the trap
+    /// frame can be copied: only a process that trapped from user mode can
+    /// fork.
+    fn fork(&self, tf: &TrapFrame, id: Id, space: UserSpace) -> Option<Process> {
+        if !self.trapped_from_user(tf) {
+            return None;
+        }
+
+        let stack = Stack::new(KERNEL_STACK_SIZE)?;
+        let mut child = *tf;
+        child.x[0] = 0;
+
+        Some(Process {
+            id,
+            name: self.name.clone(),
+            state: State::Ready,
+            parent: Some(self.id),
+            frame: push_frame(&stack, child),
+            stack: Some(stack),
+            space: Some(space.shared()),
+            user: true,
+            files: self.files.lock().clone().shared(),
+            signals: self.signals.forked(),
+            nice: self.nice,
+            age: 0,
+            cpu_time: Duration::from_secs(0),
+            switches: 0,
+            alarm: None,
+            cpu_limit: self.cpu_limit,
+        })
+    }
+
+    /// Returns a thread of this process with the ID `id`, ready to run at
+    /// EL0 with the trap frame `frame`. The thread shares this process's
+    /// address space and file descriptors, and starts with its signal actions
+    /// and nice value. Returns `None` if this process is a kernel thread, or
+    /// there is no memory for the thread's kernel stack.
+    fn thread(&self, id: Id, frame: TrapFrame) -> Option<Process> {
+        let space = self.space.clone()?;
+        let stack = Stack::new(KERNEL_STACK_SIZE)?;
+
+        Some(Process {
+            id,
+            name: self.name.clone(),
+            state: State::Ready,
+            parent: Some(self.id),
+            frame: push_frame(&stack, frame),
+            stack: Some(stack),
+            space: Some(space),
+            user: true,
+            files: self.files.clone(),
+            signals: self.signals.forked(),
+            nice: self.nice,
+            age: 0,
+            cpu_time: Duration::from_secs(0),
+            switches: 0,
+            alarm: None,
+            cpu_limit: self.cpu_limit,
+        })
+    }
+
+    /// Returns `true` if this process and `other` are threads sharing an
+    /// address space.
+    fn shares_space(&self, other: &Process) -> bool {
+        match (&self.space, &other.space) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Replaces the program of this process, which is running and stopped
+    /// with the trap frame `tf` in a `SYS_EXEC` system call, with `image`,
+    /// named `name`: its address space becomes the active one, freeing the
+    /// last unless other threads share it, and `tf` enters it. The file
+    /// descriptors stay open and the process's limits are kept, while signal
+    /// handlers are reset to the default action. Returns `false`, changing
+    /// nothing, unless the process trapped from user mode: a kernel thread's
+    /// stack holds more than `tf`.
+    fn exec(&mut self, tf: &mut TrapFrame, name: &str, mut image: Image) -> bool {
+        if !self.trapped_from_user(tf) {
+            return false;
+        }
+
+        if let Some(ref space) = self.space {
+            image.space.set_memory_limit(space.lock().memory_limit());
+        }
+        let space = image.space.shared();
+        drop(VMM.activate(space.clone()));
+        self.space = Some(space);
+        *tf = image.frame;
+        self.name = String::from(name);
+        self.signals.reset_handlers();
+        true
+    }
+
+    /// Returns `true` if `tf`, a trap frame of this process, is the only
+    /// thing on its kernel stack: the process trapped from user mode.
+    fn trapped_from_user(&self, tf: &TrapFrame) -> bool {
+        match self.stack {
+            Some(ref stack) => {
+                stack.top() - VECTOR_PUSH - size_of::<TrapFrame>() == tf as *const _ as usize
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if `va` is in the guard page below this process's
+    /// kernel stack.
+    pub fn stack_guards(&self, va: usize) -> bool {
+        match self.stack {
+            Some(ref stack) => stack.guard().contains(&va),
+            None => false,
+        }
+    }
+}
+
+/// Writes `tf` to the top of `stack` where an exception vector would have
+/// saved it, and returns its address, so that restoring it starts a process.
+fn push_frame(stack: &Stack, tf: TrapFrame) -> *mut TrapFrame {
+    let frame = (stack.top() - VECTOR_PUSH - size_of::<TrapFrame>()) as *mut TrapFrame;
+    unsafe { frame.write(tf) };
+    frame
+}
+
+/// Returns a new description of the console, for a program's standard
+/// input, output and error.
+fn console() -> Description {
+    fd::description(ConsoleFile::new())
+}
+
+/// Where a kernel thread starts: calls the thread's function `f`, then exits
+/// with status 0.
+extern "C" fn thread_start(f: *mut Thread) -> ! {
+    let f = unsafe { Box::from_raw(f) };
+    f();
+    exit(0)
+}
+
+/// What the idle process runs: waits for an interrupt, which may have made
+/// another process ready to run, then gives it the processor.
+fn idle() -> ! {
+    loop {
+        aarch64::wfi();
+        yield_now();
+    }
+}
+
+/// Gives up the processor to the next process ready to run, if any. Returns
+/// once the scheduler switches back to the caller. Makes the `SYS_YIELD`
+/// system call.
+pub fn yield_now() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("svc #0", options(nostack));
+    }
+}
+
+/// Blocks the current process until `ready()` returns `true`, letting others
+/// run meanwhile, or until `timeout` has passed. Returns `true` if it is
+/// ready, or `false` if it timed out. `ready()` is called with IRQs masked,
+/// and again each time `event` is woken by `GlobalScheduler::wake()`, which
+/// whatever makes it true must call.
+pub fn block_until<F: FnMut() -> bool>(
+    event: Event,
+    timeout: Option<Duration>,
+    mut ready: F,
+) -> bool {
+    let until = timeout.map(|timeout| clock::uptime() + timeout);
+    loop {
+        let woken = irq::with_irqs_disabled(|| {
+            if ready() {
+                return Some(true);
+            } else if until.map_or(false, |until| clock::uptime() >= until) {
+                return Some(false);
+            }
+            // If another core wakes the event before this process stops, it
+            // is made ready to run again rather than blocked.
+            SCHEDULER.block(event, until);
+            yield_now();
+            None
+        });
+        if let Some(ready) = woken {
+            return ready;
+        }
+    }
+}
+
+/// Puts the current process to sleep for at least `duration`, to the nearest
+/// millisecond, letting others run meanwhile. Returns how long it slept,
+/// which can be longer: sleepers are woken by the timer tick. Makes the
+/// `SYS_SLEEP` system call.
+pub fn sleep(duration: Duration) -> Duration {
+    let ms = duration.as_millis() as u64;
+
+    #[cfg(target_os = "none")]
+    let ms = {
+        let mut ms = ms;
+        unsafe { core::arch::asm!("svc #1", inout("x0") ms, options(nostack)) };
+        ms
+    };
+
+    Duration::from_millis(ms)
+}
+
+/// Ends the current process with the exit status `status`, which its parent
+/// collects with `wait()`. Its kernel stack is freed once the scheduler has
+/// switched away from it. Makes the `SYS_EXIT` system call.
+pub fn exit(status: i32) -> ! {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("svc #2", in("x0") status as u64, options(noreturn));
+    }
+
+    #[cfg(not(target_os = "none"))]
+    unreachable!("process {} exited in a test", status)
+}
+
+/// The exit status of a process whose program was killed by an exception it
+/// caused, such as a segmentation fault.
+pub const KILLED: i32 = -1;
+
+/// Waits for a child of the current process to exit, returning its ID and
+/// exit status, or `None` if the process has no children.
+pub fn wait() -> Option<(Id, i32)> {
+    waitpid(None, 0)
+}
+
+/// Waits for the child `pid`, or any child if it is `None`, to exit, and
+/// collects it: returns its ID and exit status, and forgets it. Returns
+/// `None` if there is no such child or, with `WNOHANG` in `options`, if it
+/// is still running. Makes the `SYS_WAIT` system call.
+pub fn waitpid(pid: Option<Id>, options: u64) -> Option<(Id, i32)> {
+    let id: u64;
+    let status: u64;
+
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!(
+            "svc #3",
+            inout("x0") pid.unwrap_or(0) => id,
+            inout("x1") options => status,
+            options(nostack)
+        );
+    }
+
+    #[cfg(not(target_os = "none"))]
+    {
+        let _ = (pid, options);
+        id = 0;
+        status = 0;
+    }
+
+    match decode(id) {
+        Ok(0) | Err(_) => None,
+        Ok(id) => Some((id, status as i32)),
+    }
+}
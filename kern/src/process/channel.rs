@@ -0,0 +1,207 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::mem;
+use core::time::Duration;
+
+use kernel_api::{OsError, OsResult, MESSAGE_SIZE};
+use shim::io;
+use shim::ioerr;
+
+use crate::mutex::Mutex;
+use crate::SCHEDULER;
+
+use super::{block_until, Description, Event};
+
+/// The most messages that can wait to be received by an endpoint: senders
+/// block once it has this many.
+pub const CHANNEL_DEPTH: usize = 16;
+
+/// A message sent through a channel: `MESSAGE_SIZE` bytes, and possibly an
+/// open file handed to the receiver.
+pub struct Message {
+    pub data: [u8; MESSAGE_SIZE],
+    pub handle: Option<Description>,
+}
+
+/// The state shared by the two endpoints of a channel.
+struct Shared {
+    /// The messages waiting to be received by each endpoint.
+    queues: [VecDeque<Message>; 2],
+    /// Whether each endpoint is still open.
+    open: [bool; 2],
+}
+
+/// One end of a channel, which carries messages both ways between its two
+/// endpoints, in the order they were sent.
+///
+/// Unlike a pipe, a channel keeps messages apart, and can pass open files.
+/// An endpoint is not a stream of bytes: reading and writing it fail.
+pub struct Endpoint {
+    shared: Arc<Mutex<Shared>>,
+    /// Which of the channel's endpoints this is: 0 or 1.
+    side: usize,
+}
+
+/// Returns the two endpoints of a new channel.
+pub fn channel() -> (Endpoint, Endpoint) {
+    let shared = Arc::new(Mutex::new(Shared {
+        queues: [VecDeque::new(), VecDeque::new()],
+        open: [true, true],
+    }));
+    let other = Endpoint {
+        shared: shared.clone(),
+        side: 1,
+    };
+    (Endpoint { shared, side: 0 }, other)
+}
+
+impl Endpoint {
+    /// Sends `message` to the other endpoint, waiting while it has
+    /// `CHANNEL_DEPTH` messages waiting. Fails with `BrokenPipe` if the other
+    /// endpoint is closed.
+    pub fn send(&mut self, message: Message) -> OsResult<()> {
+        let peer = 1 - self.side;
+        block_until(self.event(), None, || {
+            let shared = self.shared.lock();
+            shared.queues[peer].len() < CHANNEL_DEPTH || !shared.open[peer]
+        });
+
+        {
+            let mut shared = self.shared.lock();
+            if !shared.open[peer] {
+                return Err(OsError::BrokenPipe);
+            }
+            shared.queues[peer].push_back(message);
+        }
+        SCHEDULER.wake(self.event());
+        Ok(())
+    }
+
+    /// Receives the next message from the other endpoint, waiting for one at
+    /// most `timeout`, or for as long as it takes if it is `None`. Fails with
+    /// `BrokenPipe` if there is none and the other endpoint is closed, and
+    /// with `TimedOut` if none arrives in time.
+    pub fn receive(&mut self, timeout: Option<Duration>) -> OsResult<Message> {
+        let side = self.side;
+        let ready = block_until(self.event(), timeout, || {
+            let shared = self.shared.lock();
+            !shared.queues[side].is_empty() || !shared.open[1 - side]
+        });
+
+        let message = self.shared.lock().queues[side].pop_front();
+        match message {
+            Some(message) => {
+                SCHEDULER.wake(self.event());
+                Ok(message)
+            }
+            None if ready => Err(OsError::BrokenPipe),
+            None => Err(OsError::TimedOut),
+        }
+    }
+
+    /// Returns the event processes using the channel block until: a message
+    /// arriving or leaving either queue, or an endpoint closing.
+    fn event(&self) -> Event {
+        &*self.shared as *const Mutex<Shared> as Event
+    }
+}
+
+impl io::Read for Endpoint {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        ioerr!(InvalidInput, "channels carry messages, not bytes")
+    }
+}
+
+impl io::Write for Endpoint {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(InvalidInput, "channels carry messages, not bytes")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        let unreceived = {
+            let mut shared = self.shared.lock();
+            shared.open[self.side] = false;
+            mem::take(&mut shared.queues[self.side])
+        };
+        // The files in messages never received are closed, which may take
+        // the channel's lock.
+        drop(unreceived);
+        SCHEDULER.wake(self.event());
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    use kernel_api::{OsError, MESSAGE_SIZE};
+    use shim::io::Cursor;
+
+    use super::{channel, Message, CHANNEL_DEPTH};
+    use crate::process::fd;
+
+    fn message(byte: u8) -> Message {
+        Message {
+            data: [byte; MESSAGE_SIZE],
+            handle: None,
+        }
+    }
+
+    #[test]
+    fn messages_both_ways() {
+        let (mut a, mut b) = channel();
+        for i in 0..CHANNEL_DEPTH as u8 {
+            a.send(message(i)).unwrap();
+        }
+        b.send(message(0xff)).unwrap();
+
+        for i in 0..CHANNEL_DEPTH as u8 {
+            assert_eq!(b.receive(None).unwrap().data, [i; MESSAGE_SIZE]);
+        }
+        assert_eq!(a.receive(None).unwrap().data, [0xff; MESSAGE_SIZE]);
+    }
+
+    #[test]
+    fn passing_files() {
+        let (mut a, mut b) = channel();
+        let file = fd::description(Cursor::new(Vec::new()));
+        a.send(Message {
+            data: [0; MESSAGE_SIZE],
+            handle: Some(file.clone()),
+        })
+        .unwrap();
+
+        let handle = b.receive(None).unwrap().handle.unwrap();
+        assert!(Arc::ptr_eq(&handle, &file));
+
+        // files in messages never received are closed with the endpoint
+        a.send(Message {
+            data: [0; MESSAGE_SIZE],
+            handle: Some(file.clone()),
+        })
+        .unwrap();
+        drop(b);
+        assert_eq!(Arc::strong_count(&file), 2);
+    }
+
+    #[test]
+    fn closed_endpoints() {
+        let (mut a, b) = channel();
+        a.send(message(1)).unwrap();
+        drop(b);
+        assert_eq!(a.send(message(2)).err(), Some(OsError::BrokenPipe));
+
+        let (mut a, mut b) = channel();
+        a.send(message(1)).unwrap();
+        drop(a);
+        assert_eq!(b.receive(None).unwrap().data, [1; MESSAGE_SIZE]);
+        assert_eq!(b.receive(None).err(), Some(OsError::BrokenPipe));
+    }
+}
@@ -0,0 +1,407 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+
+use shim::io::{self, Read};
+use shim::path::Path;
+
+use fat32::traits::{File, FileSystem};
+use kernel_api::OsError;
+
+use crate::elf::{self, ProgramHeader, ET_DYN, PF_W, PF_X, PT_DYNAMIC, PT_LOAD};
+use crate::traps::TrapFrame;
+use crate::vm::{page_align, Attributes, FaultError, RegionError, UserSpace};
+use crate::vm::{PAGE_SIZE, USER_BASE, USER_END};
+use crate::FILESYSTEM;
+
+/// The largest program `exec` will load.
+pub const MAX_PROGRAM_SIZE: u64 = 16 << 20;
+
+/// The address a position-independent executable is loaded at.
+pub const PIE_BASE: usize = USER_BASE;
+
+/// The address just past a program's stack.
+pub const USER_STACK_TOP: usize = USER_END;
+
+/// The size of a program's stack. Its pages are only mapped when touched.
+pub const USER_STACK_SIZE: usize = 1 << 20;
+
+/// The most bytes of arguments a program can be given, counting each one's
+/// terminating NUL.
+pub const ARG_MAX: usize = 4096;
+
+/// The `SPSR_EL1` a program starts with: EL0, with no exceptions masked.
+const SPSR_EL0T: u64 = 0;
+
+/// Errors from loading a program.
+#[derive(Debug)]
+pub enum ExecError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is not an executable this kernel can run.
+    Elf(elf::Error),
+    /// The arguments are longer than `ARG_MAX`.
+    ArgsTooLong,
+    /// There was no memory left for the program.
+    OutOfMemory,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::Io(e) => write!(f, "{}", e),
+            ExecError::Elf(e) => write!(f, "{}", e),
+            ExecError::ArgsTooLong => write!(f, "argument list too long"),
+            ExecError::OutOfMemory => write!(f, "out of memory"),
+        }
+    }
+}
+
+impl From<io::Error> for ExecError {
+    fn from(e: io::Error) -> ExecError {
+        ExecError::Io(e)
+    }
+}
+
+impl From<elf::Error> for ExecError {
+    fn from(e: elf::Error) -> ExecError {
+        ExecError::Elf(e)
+    }
+}
+
+impl From<FaultError> for ExecError {
+    fn from(_: FaultError) -> ExecError {
+        // Loading only writes to the regions it added.
+        ExecError::OutOfMemory
+    }
+}
+
+impl From<ExecError> for OsError {
+    fn from(e: ExecError) -> OsError {
+        match e {
+            ExecError::Io(ref e) if e.kind() == io::ErrorKind::NotFound => OsError::NoEntry,
+            ExecError::Io(_) => OsError::Io,
+            ExecError::Elf(_) => OsError::BadExecutable,
+            ExecError::ArgsTooLong => OsError::TooBig,
+            ExecError::OutOfMemory => OsError::NoMemory,
+        }
+    }
+}
+
+/// A program loaded into a user address space, ready to start.
+pub struct Image {
+    /// The address space holding its segments and stack.
+    pub space: UserSpace,
+    /// The trap frame that enters it at EL0: at its entry point, with its
+    /// stack pointer at `argc` and `argv` in `x0` and `x1`.
+    pub frame: TrapFrame,
+}
+
+/// Reads the program at the absolute path `path` from the file system.
+pub fn read_program(path: &Path) -> Result<Vec<u8>, ExecError> {
+    let mut file = FILESYSTEM.open_file(path)?;
+    if file.size() > MAX_PROGRAM_SIZE {
+        return Err(io::Error::new(io::ErrorKind::Other, "file too large").into());
+    }
+
+    let mut bytes = vec![0; file.size() as usize];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Loads the ELF executable `bytes` into `space`, which must have no regions,
+/// with a stack holding the arguments `args`.
+///
+/// Each loadable segment gets a region of its own, mapped with the
+/// permissions its flags ask for: code is read-only, and no segment may be
+/// both writable and executable, or share a page with another. A fixed
+/// address executable is loaded where it was linked, and a
+/// position-independent one at `PIE_BASE`, with its relocations applied.
+///
+/// The heap starts empty, just past the last segment.
+///
+/// The stack ends at `USER_STACK_TOP`. At the stack pointer are `argc`, the
+/// `argc` pointers of `argv` followed by a null pointer, and an empty
+/// environment: another null pointer. The strings are above them.
+pub fn load(bytes: &[u8], args: &[&str], mut space: UserSpace) -> Result<Image, ExecError> {
+    let header = elf::parse_header(bytes)?;
+    let base = if header.kind == ET_DYN { PIE_BASE } else { 0 };
+
+    let mut segments = Vec::new();
+    let mut dynamic = None;
+    for i in 0..header.phnum {
+        let ph = elf::program_header(bytes, &header, i)?;
+        match ph.kind {
+            PT_LOAD if ph.memsz > 0 => segments.push(ph),
+            PT_DYNAMIC => dynamic = Some(ph),
+            _ => (),
+        }
+    }
+    if segments.is_empty() {
+        return Err(elf::Error::Unsupported("no loadable segments").into());
+    }
+
+    for ph in &segments {
+        add_segment(&mut space, ph, base)?;
+        let end = (ph.offset as usize).saturating_add(ph.filesz as usize);
+        if bytes.get(ph.offset as usize..end).is_none() {
+            return Err(elf::Error::Truncated.into());
+        }
+    }
+
+    // The relocations are applied to a copy of the segments laid out as they
+    // are in memory, with the first at the start.
+    let image = match dynamic {
+        Some(ref dynamic) if header.kind == ET_DYN => Some(relocated(bytes, &segments, dynamic)?),
+        _ => None,
+    };
+    for ph in &segments {
+        let (vaddr, filesz) = (ph.vaddr as usize, ph.filesz as usize);
+        let data = match image {
+            Some(ref image) => &image[vaddr..vaddr + filesz],
+            None => &bytes[ph.offset as usize..ph.offset as usize + filesz],
+        };
+        space.write(base + vaddr, data)?;
+    }
+
+    let entry = base.wrapping_add(header.entry as usize);
+    if !space.region(entry).map_or(false, |r| r.attrs.executable) {
+        return Err(elf::Error::Unsupported("entry point outside of the program's code").into());
+    }
+
+    let heap = space.regions().iter().map(|region| region.end).max();
+    space
+        .add_heap(heap.unwrap_or(USER_BASE))
+        .and_then(|()| space.add_stack(USER_STACK_TOP, USER_STACK_SIZE))
+        .map_err(|_| elf::Error::Unsupported("segment overlaps the stack"))?;
+    let (sp, stack) = stack_contents(USER_STACK_TOP, args)?;
+    space.write(sp, &stack)?;
+
+    let mut frame = TrapFrame::default();
+    frame.elr = entry as u64;
+    frame.spsr = SPSR_EL0T;
+    frame.sp = sp as u64;
+    frame.x[0] = args.len() as u64;
+    frame.x[1] = (sp + size_of::<u64>()) as u64;
+    Ok(Image { space, frame })
+}
+
+/// Adds the region of `space` holding the loadable segment `ph` of a program
+/// loaded at `base`.
+fn add_segment(space: &mut UserSpace, ph: &ProgramHeader, base: usize) -> Result<(), ExecError> {
+    let attrs = if ph.flags & PF_W != 0 && ph.flags & PF_X != 0 {
+        return Err(elf::Error::Unsupported("writable and executable segment").into());
+    } else if ph.flags & PF_X != 0 {
+        Attributes::USER_CODE
+    } else if ph.flags & PF_W != 0 {
+        Attributes::USER_DATA
+    } else {
+        Attributes::USER_RODATA
+    };
+
+    if ph.filesz > ph.memsz {
+        return Err(elf::Error::Unsupported("bad segment size").into());
+    }
+    let start = base.checked_add(ph.vaddr as usize);
+    let end = start.and_then(|start| start.checked_add(ph.memsz as usize));
+    let (start, end) = match (start, end.and_then(page_align)) {
+        (Some(start), Some(end)) => (start & !(PAGE_SIZE - 1), end),
+        _ => return Err(elf::Error::Unsupported("segment outside of user memory").into()),
+    };
+
+    space
+        .add_region(start, end - start, attrs)
+        .map_err(|e| match e {
+            RegionError::Overlaps => elf::Error::Unsupported("segments share a page"),
+            _ => elf::Error::Unsupported("segment outside of user memory"),
+        })?;
+    Ok(())
+}
+
+/// Returns the loadable segments `segments` of the position-independent
+/// executable `bytes` laid out as they are in memory, relative to address 0,
+/// with the relocations the dynamic segment `dynamic` lists applied for a
+/// load address of `PIE_BASE`.
+fn relocated(
+    bytes: &[u8],
+    segments: &[ProgramHeader],
+    dynamic: &ProgramHeader,
+) -> Result<Vec<u8>, ExecError> {
+    let size = segments
+        .iter()
+        .map(|ph| ph.vaddr.saturating_add(ph.memsz))
+        .max()
+        .unwrap_or(0);
+    if size > MAX_PROGRAM_SIZE {
+        return Err(elf::Error::Unsupported("program too large").into());
+    }
+
+    let mut image = vec![0; size as usize];
+    for ph in segments {
+        let (offset, filesz, vaddr) = (ph.offset as usize, ph.filesz as usize, ph.vaddr as usize);
+        image[vaddr..vaddr + filesz].copy_from_slice(&bytes[offset..offset + filesz]);
+    }
+    elf::relocate(&mut image, dynamic, PIE_BASE as u64)?;
+    Ok(image)
+}
+
+/// Returns the initial stack pointer of a program whose stack ends at `top`
+/// and that is given the arguments `args`, and the bytes from there to `top`:
+/// see `load()`.
+fn stack_contents(top: usize, args: &[&str]) -> Result<(usize, Vec<u8>), ExecError> {
+    let strings: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    if strings > ARG_MAX {
+        return Err(ExecError::ArgsTooLong);
+    }
+
+    // argc, argv, its null pointer and the environment's
+    let words = args.len() + 3;
+    let sp = (top - strings - words * size_of::<u64>()) & !0xf;
+    let mut stack = vec![0; top - sp];
+
+    let mut string = top - strings;
+    for (i, arg) in args.iter().enumerate() {
+        let at = (i + 1) * size_of::<u64>();
+        stack[at..at + 8].copy_from_slice(&(string as u64).to_le_bytes());
+        stack[string - sp..string - sp + arg.len()].copy_from_slice(arg.as_bytes());
+        string += arg.len() + 1;
+    }
+    stack[..8].copy_from_slice(&(args.len() as u64).to_le_bytes());
+    Ok((sp, stack))
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::elf::Error;
+
+    /// Returns an AArch64 ELF executable of type `kind` entered at `entry`,
+    /// with a loadable segment for each of `segments`: its flags, virtual
+    /// address, contents and size in memory.
+    fn program(kind: u16, entry: u64, segments: &[(u32, u64, &[u8], u64)]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 64];
+        bytes[..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 2;
+        bytes[5] = 1;
+        bytes[16..18].copy_from_slice(&kind.to_le_bytes());
+        bytes[18..20].copy_from_slice(&183u16.to_le_bytes());
+        bytes[24..32].copy_from_slice(&entry.to_le_bytes());
+        bytes[32..40].copy_from_slice(&64u64.to_le_bytes());
+        bytes[54..56].copy_from_slice(&56u16.to_le_bytes());
+        bytes[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+        let mut offset = (64 + 56 * segments.len()) as u64;
+        for &(flags, vaddr, contents, memsz) in segments {
+            bytes.extend_from_slice(&PT_LOAD.to_le_bytes());
+            bytes.extend_from_slice(&flags.to_le_bytes());
+            let len = contents.len() as u64;
+            for field in &[offset, vaddr, vaddr, len, memsz, PAGE_SIZE as u64] {
+                bytes.extend_from_slice(&field.to_le_bytes());
+            }
+            offset += len;
+        }
+        for &(_, _, contents, _) in segments {
+            bytes.extend_from_slice(contents);
+        }
+        bytes
+    }
+
+    fn unsupported(result: Result<Image, ExecError>) -> &'static str {
+        match result {
+            Err(ExecError::Elf(Error::Unsupported(what))) => what,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("loaded a bad program"),
+        }
+    }
+
+    fn u64_at(space: &mut UserSpace, va: usize) -> u64 {
+        let mut word = [0; 8];
+        space.read(va, &mut word).unwrap();
+        u64::from_le_bytes(word)
+    }
+
+    #[test]
+    fn loads_segments_and_arguments() {
+        let base = USER_BASE as u64;
+        let bytes = program(
+            2,
+            base + 4,
+            &[
+                (PF_X | 4, base, &[1, 2, 3, 4, 5, 6, 7, 8], 8),
+                (PF_W | 4, base + 0x1800, &[9, 10], 0x1000),
+            ],
+        );
+        let mut image = load(&bytes, &["prog", "-v"], UserSpace::new(&[])).unwrap();
+
+        assert_eq!(image.frame.elr, base + 4);
+        assert_eq!(image.frame.spsr, SPSR_EL0T);
+        let regions = image.space.regions();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].attrs, Attributes::USER_CODE);
+        assert_eq!(
+            (regions[1].start, regions[1].end),
+            (USER_BASE + 0x1000, USER_BASE + 0x3000)
+        );
+        assert_eq!(regions[1].attrs, Attributes::USER_DATA);
+        assert_eq!(image.space.brk(), Some(USER_BASE + 0x3000));
+
+        let mut code = [0; 8];
+        image.space.read(USER_BASE, &mut code).unwrap();
+        assert_eq!(code, [1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut data = [0; 4];
+        image.space.read(USER_BASE + 0x1800, &mut data).unwrap();
+        assert_eq!(data, [9, 10, 0, 0]);
+
+        let sp = image.frame.sp as usize;
+        assert_eq!(sp % 16, 0);
+        assert_eq!(u64_at(&mut image.space, sp), 2);
+        assert_eq!(image.frame.x[..2], [2, sp as u64 + 8]);
+        let argv1 = u64_at(&mut image.space, sp + 16) as usize;
+        let mut arg = [0; 3];
+        image.space.read(argv1, &mut arg).unwrap();
+        assert_eq!(&arg, b"-v\0");
+        assert_eq!(u64_at(&mut image.space, sp + 24), 0);
+        assert_eq!(u64_at(&mut image.space, sp + 32), 0);
+    }
+
+    #[test]
+    fn bad_programs() {
+        let base = USER_BASE as u64;
+        let code: &[u8] = &[0; 4];
+        let load = |segments: &[(u32, u64, &[u8], u64)], entry: u64| {
+            load(&program(2, entry, segments), &[], UserSpace::new(&[]))
+        };
+
+        assert_eq!(
+            unsupported(load(&[(PF_X | PF_W, base, code, 4)], base)),
+            "writable and executable segment"
+        );
+        assert_eq!(
+            unsupported(load(
+                &[(PF_X, base, code, 4), (PF_W, base + 8, code, 4)],
+                base
+            )),
+            "segments share a page"
+        );
+        assert_eq!(
+            unsupported(load(&[(PF_X, 0x1000, code, 4)], 0x1000)),
+            "segment outside of user memory"
+        );
+        assert_eq!(
+            unsupported(load(
+                &[(PF_X, base, code, 4), (PF_W, base + 0x1000, code, 4)],
+                base + 0x1000
+            )),
+            "entry point outside of the program's code"
+        );
+        assert!(match super::load(
+            &program(2, base, &[(PF_X, base, code, 4)]),
+            &[&"x".repeat(ARG_MAX)],
+            UserSpace::new(&[])
+        ) {
+            Err(ExecError::ArgsTooLong) => true,
+            _ => false,
+        });
+    }
+}
@@ -0,0 +1,227 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use shim::io;
+
+use crate::mutex::Mutex;
+
+/// The most file descriptors a process can have open at once.
+pub const MAX_FDS: usize = 64;
+
+/// Something a file descriptor can refer to: a stream of bytes that can be
+/// read, written or both. An operation it does not support fails.
+pub trait Stream: io::Read + io::Write + Send {
+    /// Returns the stream as `Any`, so that operations particular to its type
+    /// can be found by downcasting.
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+impl<T: io::Read + io::Write + Send + 'static> Stream for T {
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// An open stream, shared by the file descriptors duplicated from the one it
+/// was opened as, including those a child inherits from `fork`.
+pub type Description = Arc<Mutex<Box<dyn Stream>>>;
+
+/// Returns a new description of `stream`.
+pub fn description<S: Stream + 'static>(stream: S) -> Description {
+    Arc::new(Mutex::new(Box::new(stream)))
+}
+
+/// A file descriptor table, shared by the threads of a process.
+pub type SharedFdTable = Arc<Mutex<FdTable>>;
+
+/// A process's file descriptors: small integers naming the streams it has
+/// open, each below the table's limit, which is at most `MAX_FDS`.
+///
+/// Cloning the table duplicates every descriptor: the copies refer to the
+/// same descriptions as the originals.
+#[derive(Clone)]
+pub struct FdTable {
+    fds: Vec<Option<Description>>,
+    limit: usize,
+}
+
+impl FdTable {
+    /// Returns a table with no descriptors open.
+    pub fn new() -> FdTable {
+        FdTable {
+            fds: Vec::new(),
+            limit: MAX_FDS,
+        }
+    }
+
+    /// Returns this table, to be shared by threads.
+    pub fn shared(self) -> SharedFdTable {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Returns a table with `description` open as descriptors 0, 1 and 2: a
+    /// program's standard input, output and error.
+    pub fn with_stdio(description: Description) -> FdTable {
+        FdTable {
+            fds: vec![Some(description); 3],
+            limit: MAX_FDS,
+        }
+    }
+
+    /// Returns the limit descriptors must be below.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Makes new descriptors have to be below `limit`, or `MAX_FDS` if that
+    /// is lower. Those already open stay open.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.min(MAX_FDS);
+    }
+
+    /// Opens `description` as the lowest numbered descriptor not in use,
+    /// returning it, or `None` if every one below the limit is open.
+    pub fn open(&mut self, description: Description) -> Option<usize> {
+        match self.fds.iter().take(self.limit).position(Option::is_none) {
+            Some(fd) => {
+                self.fds[fd] = Some(description);
+                Some(fd)
+            }
+            None if self.fds.len() < self.limit => {
+                self.fds.push(Some(description));
+                Some(self.fds.len() - 1)
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the description `fd` refers to, if it is open.
+    pub fn get(&self, fd: usize) -> Option<&Description> {
+        self.fds.get(fd)?.as_ref()
+    }
+
+    /// Closes `fd`, returning the description it referred to, or `None` if it
+    /// was not open. The description is closed once no descriptor refers to
+    /// it.
+    pub fn close(&mut self, fd: usize) -> Option<Description> {
+        let description = self.fds.get_mut(fd)?.take();
+        while let Some(None) = self.fds.last() {
+            self.fds.pop();
+        }
+        description
+    }
+
+    /// Makes `new` refer to the description `old` does, returning the
+    /// description `new` referred to before, if any. Returns `None`, changing
+    /// nothing, if `old` is not open or `new` is not below the limit.
+    pub fn dup2(&mut self, old: usize, new: usize) -> Option<Option<Description>> {
+        let description = self.get(old)?.clone();
+        if new >= self.limit {
+            return None;
+        } else if new >= self.fds.len() {
+            self.fds.resize(new + 1, None);
+        }
+        Some(self.fds[new].replace(description))
+    }
+
+    /// Returns the number of descriptors open.
+    pub fn count(&self) -> usize {
+        self.fds.iter().filter(|fd| fd.is_some()).count()
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::sync::Arc;
+
+    use shim::io::Cursor;
+
+    use super::{Description, FdTable, MAX_FDS};
+
+    fn description() -> Description {
+        super::description(Cursor::new(Vec::new()))
+    }
+
+    #[test]
+    fn lowest_free_descriptor() {
+        let mut table = FdTable::new();
+        for fd in 0..3 {
+            assert_eq!(table.open(description()), Some(fd));
+        }
+
+        assert!(table.close(1).is_some());
+        assert!(table.close(1).is_none());
+        assert!(table.get(1).is_none());
+        assert_eq!(table.open(description()), Some(1));
+
+        while table.count() < MAX_FDS {
+            table.open(description());
+        }
+        assert_eq!(table.open(description()), None);
+    }
+
+    #[test]
+    fn standard_descriptors() {
+        let file = description();
+        let mut table = FdTable::with_stdio(file.clone());
+        assert_eq!(table.count(), 3);
+        for fd in 0..3 {
+            assert!(Arc::ptr_eq(table.get(fd).unwrap(), &file));
+        }
+        assert_eq!(table.open(description()), Some(3));
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut table = FdTable::new();
+        let file = description();
+        table.open(file.clone());
+        table.open(description());
+
+        assert!(table.dup2(0, 1).unwrap().is_some());
+        assert!(Arc::ptr_eq(table.get(1).unwrap(), &file));
+        assert!(table.dup2(0, 5).unwrap().is_none());
+        assert!(Arc::ptr_eq(table.get(5).unwrap(), &file));
+        assert_eq!(table.count(), 3);
+        assert_eq!(table.open(description()), Some(2));
+
+        assert!(table.dup2(4, 6).is_none());
+        assert!(table.dup2(0, MAX_FDS).is_none());
+        assert!(table.dup2(0, 0).unwrap().is_some());
+        assert_eq!(Arc::strong_count(&file), 4);
+    }
+
+    #[test]
+    fn limit() {
+        let mut table = FdTable::with_stdio(description());
+        table.set_limit(MAX_FDS + 1);
+        assert_eq!(table.limit(), MAX_FDS);
+
+        // descriptors already open stay open, but no new ones reach the limit
+        table.set_limit(2);
+        assert_eq!(table.count(), 3);
+        assert_eq!(table.open(description()), None);
+        assert!(table.dup2(0, 2).is_none());
+        table.close(1);
+        assert_eq!(table.open(description()), Some(1));
+        assert!(table.dup2(0, 1).is_some());
+    }
+
+    #[test]
+    fn clones_share_descriptions() {
+        let mut table = FdTable::new();
+        let file = description();
+        table.open(file.clone());
+
+        let mut child = table.clone();
+        assert!(Arc::ptr_eq(child.get(0).unwrap(), &file));
+        assert_eq!(Arc::strong_count(&file), 3);
+
+        child.close(0);
+        assert_eq!(Arc::strong_count(&file), 2);
+        assert!(table.get(0).is_some());
+    }
+}
@@ -0,0 +1,191 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use shim::io;
+use shim::ioerr;
+
+use crate::mutex::Mutex;
+use crate::SCHEDULER;
+
+use super::{block_until, Event};
+
+/// The most bytes a pipe holds: writers block once it is full.
+pub const PIPE_SIZE: usize = 4096;
+
+/// The state shared by the two ends of a pipe.
+#[derive(Debug)]
+struct Buffer {
+    /// The bytes written and not yet read.
+    bytes: VecDeque<u8>,
+    /// The number of read ends still open.
+    readers: usize,
+    /// The number of write ends still open.
+    writers: usize,
+}
+
+/// The read end of a pipe.
+///
+/// A read blocks until there are bytes to read, and returns as many as there
+/// are, up to the length of the buffer, or 0 once every write end has been
+/// closed and all the bytes read. Writing to it fails.
+#[derive(Debug)]
+pub struct PipeReader(Arc<Mutex<Buffer>>);
+
+/// The write end of a pipe.
+///
+/// A write blocks until the pipe has room for at least one byte, and writes
+/// as many as fit. It fails with `BrokenPipe` once every read end has been
+/// closed. Reading from it fails.
+#[derive(Debug)]
+pub struct PipeWriter(Arc<Mutex<Buffer>>);
+
+/// Returns the two ends of a new, empty pipe: what is written to the writer
+/// is read from the reader, in order.
+pub fn pipe() -> (PipeReader, PipeWriter) {
+    let buffer = Arc::new(Mutex::new(Buffer {
+        bytes: VecDeque::with_capacity(PIPE_SIZE),
+        readers: 1,
+        writers: 1,
+    }));
+    (PipeReader(buffer.clone()), PipeWriter(buffer))
+}
+
+/// Returns the event processes using the pipe `buffer` block until: its
+/// readers until there are bytes or no writers, its writers until there is
+/// room or no readers.
+fn event(buffer: &Arc<Mutex<Buffer>>) -> Event {
+    &**buffer as *const Mutex<Buffer> as Event
+}
+
+impl io::Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        block_until(event(&self.0), None, || {
+            let buffer = self.0.lock();
+            !buffer.bytes.is_empty() || buffer.writers == 0
+        });
+
+        let n = {
+            let mut buffer = self.0.lock();
+            let n = buf.len().min(buffer.bytes.len());
+            for (dst, src) in buf.iter_mut().zip(buffer.bytes.drain(..n)) {
+                *dst = src;
+            }
+            n
+        };
+        SCHEDULER.wake(event(&self.0));
+        Ok(n)
+    }
+}
+
+impl io::Write for PipeReader {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "cannot write to the read end of a pipe")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.0.lock().readers -= 1;
+        SCHEDULER.wake(event(&self.0));
+    }
+}
+
+impl io::Read for PipeWriter {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "cannot read from the write end of a pipe")
+    }
+}
+
+impl io::Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        block_until(event(&self.0), None, || {
+            let buffer = self.0.lock();
+            buffer.bytes.len() < PIPE_SIZE || buffer.readers == 0
+        });
+
+        let n = {
+            let mut buffer = self.0.lock();
+            if buffer.readers == 0 {
+                return ioerr!(BrokenPipe, "the read end of the pipe is closed");
+            }
+            let n = buf.len().min(PIPE_SIZE - buffer.bytes.len());
+            buffer.bytes.extend(&buf[..n]);
+            n
+        };
+        SCHEDULER.wake(event(&self.0));
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.0.lock().writers -= 1;
+        SCHEDULER.wake(event(&self.0));
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use shim::io::{ErrorKind, Read, Write};
+
+    use super::{pipe, PIPE_SIZE};
+
+    #[test]
+    fn bytes_in_order() {
+        let (mut reader, mut writer) = pipe();
+        assert_eq!(writer.write(b"hello, ").unwrap(), 7);
+        assert_eq!(writer.write(b"world").unwrap(), 5);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"hello, w");
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"orld");
+
+        assert!(reader.write(b"x").is_err());
+        assert!(writer.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn full_pipe() {
+        let (mut reader, mut writer) = pipe();
+        let bytes = [7u8; PIPE_SIZE + 10];
+        assert_eq!(writer.write(&bytes).unwrap(), PIPE_SIZE);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 16);
+        assert_eq!(writer.write(&bytes).unwrap(), 16);
+    }
+
+    #[test]
+    fn closed_ends() {
+        let (mut reader, mut writer) = pipe();
+        writer.write_all(b"last").unwrap();
+        drop(writer);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        let (reader, mut writer) = pipe();
+        drop(reader);
+        let e = writer.write(b"lost").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,1641 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::iter;
+use core::mem;
+use core::ptr;
+use core::time::Duration;
+
+use kernel_api::{encode, OsError, OsResult, SIGALRM, SIGKILL};
+use pi::common::NCORES;
+
+use crate::aarch64;
+use crate::clock;
+use crate::console::kprintln_nolock;
+use crate::irq;
+use crate::mutex::Mutex;
+use crate::traps::TrapFrame;
+use crate::VMM;
+
+use super::signal::{self, Action};
+use super::{idle, Alarm, Event, FdTable, Id, Image, Info, Process, Stack, State};
+use super::{IDLE_ID, KILLED, NICE_MAX, NICE_MIN, WNOHANG};
+
+/// How long a process runs for before it is preempted, if another with as
+/// good a priority is ready to run: two timer ticks.
+pub const TIME_SLICE: Duration = Duration::from_millis(20);
+
+/// The kernel's processes, and the one running on each core.
+///
+/// Each core has a queue of processes ready to run and an idle process of its
+/// own. Of the processes ready to run on a core, the one with the best priority
+/// runs next, the one that became ready first among equals, until it yields,
+/// sleeps or its time slice runs out at a timer tick. It then keeps the core
+/// only if no other has as good a priority as its nice value. A process's
+/// priority is its nice value, lowered by one for each time slice it has
+/// waited, so that none waits forever, and processes with the same nice value
+/// take turns. One that becomes ready with a better priority than the running
+/// process preempts it at the next tick, which keeps the shell responsive while
+/// programs with higher nice values compute.
+///
+/// A core whose queue is empty takes the next process from the longest queue
+/// of another, so that work spreads across the cores; an idle core looks for
+/// work at each of its timer ticks. The sleeping, waiting, blocked and zombie
+/// processes are shared, and a process woken joins the queue of the core that
+/// woke it.
+///
+/// Sleeping processes wait in order of the time they wake at, and are woken
+/// by the first interrupt after it, or as soon as a signal is sent to them.
+/// Alarms go off at the first interrupt after they are due, too. When none is
+/// ready, a core's idle process waits for an interrupt.
+///
+/// A process that exits becomes a zombie, keeping only its ID and exit
+/// status, until its parent collects it with `wait()`. Its children become
+/// orphans, with no parent: an orphan is forgotten as soon as it exits, so
+/// nothing is left behind by processes that are never waited for.
+///
+/// The scheduler is only ever used with IRQs masked, since the timer
+/// interrupt switches processes. Data that kernel threads share with each
+/// other must be protected the same way: a process holding a `Mutex` can be
+/// preempted, and the next one on its core is not kept out.
+pub struct GlobalScheduler(Mutex<Option<Scheduler>>);
+
+impl GlobalScheduler {
+    /// Returns an uninitialized scheduler. Until `initialize()` is called,
+    /// nothing can be spawned and switching does nothing.
+    pub const fn uninitialized() -> GlobalScheduler {
+        GlobalScheduler(Mutex::new(None))
+    }
+
+    /// Makes the code that calls this process 1, `kmain`, and starts core 0's
+    /// idle process.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no memory for the idle process's stack.
+    pub fn initialize(&self) {
+        let idle = Process::kernel_thread(IDLE_ID, "idle", None, Box::new(|| idle()))
+            .expect("no memory for the idle process");
+        let scheduler = Scheduler::new(Process::adopt(1, "kmain"), idle);
+        self.with(|s| *s = Some(scheduler));
+    }
+
+    /// Makes the code that calls this on a secondary core, running on `stack`,
+    /// the core's idle process, then unmasks IRQs and idles: from then on,
+    /// the core runs processes too.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler is uninitialized.
+    pub fn run_core(&self, stack: Stack) -> ! {
+        let mut process = Process::adopt(IDLE_ID, &format!("idle{}", aarch64::affinity()));
+        process.stack = Some(stack);
+        let now = clock::uptime();
+        self.with(|scheduler| {
+            let scheduler = scheduler.as_mut().expect("scheduler uninitialized");
+            scheduler.start_core(process, now)
+        });
+        irq::unmask();
+        idle()
+    }
+
+    /// Starts a kernel thread named `name`, a child of the running process
+    /// with the same nice value, that calls `f` and then exits. Returns its
+    /// ID, or `None` if there is no memory for its stack.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler is uninitialized.
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, name: &str, f: F) -> Option<Id> {
+        let f = Box::new(f);
+        self.with(|scheduler| {
+            let scheduler = scheduler.as_mut().expect("scheduler uninitialized");
+            let (id, parent) = (scheduler.last_id + 1, Some(scheduler.core().running.id));
+            let mut process = Process::kernel_thread(id, name, parent, f)?;
+            process.nice = scheduler.core().running.nice;
+            Some(scheduler.add(process))
+        })
+    }
+
+    /// Starts the program `image` in user mode in a new process named `name`,
+    /// a child of the running process with the same nice value. Returns its
+    /// ID, or `None` if there is no memory for its kernel stack.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler is uninitialized.
+    pub fn start(&self, name: &str, image: Image) -> Option<Id> {
+        self.with(|scheduler| {
+            let scheduler = scheduler.as_mut().expect("scheduler uninitialized");
+            let (id, parent) = (scheduler.last_id + 1, Some(scheduler.core().running.id));
+            let mut process = Process::user(id, name, parent, image)?;
+            process.nice = scheduler.core().running.nice;
+            Some(scheduler.add(process))
+        })
+    }
+
+    /// Returns the ID of the process running on this core, if the scheduler
+    /// is initialized.
+    pub fn current(&self) -> Option<Id> {
+        self.with(|scheduler| scheduler.as_ref().map(|s| s.core().running.id))
+    }
+
+    /// Calls `f` with the ID and name of the process running on this core.
+    /// Returns `None` without calling it if the scheduler is uninitialized
+    /// or another core is using it: this never waits, so that crash reports
+    /// can use it whatever they interrupted.
+    pub fn try_running<R>(&self, f: impl FnOnce(Id, &str) -> R) -> Option<R> {
+        let scheduler = self.0.try_lock()?;
+        let core = scheduler.as_ref()?.cores[aarch64::affinity()].as_ref()?;
+        Some(f(core.running.id, &core.running.name))
+    }
+
+    /// Returns the ID of the running process's parent, if it has one.
+    pub fn parent(&self) -> Option<Id> {
+        self.with(|scheduler| scheduler.as_ref().and_then(|s| s.core().running.parent))
+    }
+
+    /// Returns a snapshot of each process: for each core, the one running,
+    /// then those ready to run in the order they became ready, then its idle
+    /// process; then the sleeping ones in the order they wake, those waiting
+    /// for children, the blocked ones and the zombies.
+    pub fn processes(&self) -> Vec<Info> {
+        let now = clock::uptime();
+        self.with(|scheduler| {
+            scheduler
+                .iter()
+                .flat_map(|s| s.processes().map(move |p| s.info(p, now)))
+                .collect()
+        })
+    }
+
+    /// Switches from the running process, stopped with the trap frame `tf`,
+    /// to the next one ready to run, if any. Returns the trap frame to
+    /// restore to resume it, or `tf` to keep running the current process.
+    pub fn switch(&self, tf: &mut TrapFrame) -> *mut TrapFrame {
+        let now = clock::uptime();
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => scheduler.switch(tf, now),
+            None => tf,
+        })
+    }
+
+    /// Wakes the sleeping processes whose time has come, then switches from
+    /// the running process, interrupted with the trap frame `tf`, as
+    /// `switch()` does if it is the idle process, if a process with a better
+    /// priority is ready, or if its time slice has run out and one with as
+    /// good a priority is. Otherwise returns `tf`.
+    pub fn preempt(&self, tf: &mut TrapFrame) -> *mut TrapFrame {
+        let now = clock::uptime();
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => {
+                scheduler.wake(now);
+                if scheduler.should_preempt(now) {
+                    scheduler.switch(tf, now)
+                } else {
+                    tf
+                }
+            }
+            None => tf,
+        })
+    }
+
+    /// Puts the running process, stopped with the trap frame `tf` in a
+    /// `SYS_SLEEP` or `SYS_NANOSLEEP` system call, to sleep for `duration`,
+    /// and switches to the next one ready to run. When the process wakes, how
+    /// long it slept, counted in `unit`s, is returned to it in `x0`.
+    ///
+    /// Until the scheduler is initialized there is nothing else to run, so
+    /// this spins instead.
+    pub fn sleep(&self, tf: &mut TrapFrame, duration: Duration, unit: Duration) -> *mut TrapFrame {
+        let now = clock::uptime();
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => {
+                scheduler.core_mut().running.state = State::Sleeping {
+                    since: now,
+                    until: now + duration,
+                    unit,
+                };
+                scheduler.switch(tf, now)
+            }
+            None => {
+                pi::timer::spin_sleep(duration);
+                tf.x[0] = units(clock::uptime() - now, unit);
+                tf
+            }
+        })
+    }
+
+    /// Marks the running process blocked until `event` is woken, or the
+    /// uptime `until` if there is one: it stops running the next time the
+    /// scheduler switches, and is not run again until then. Does nothing
+    /// until the scheduler is initialized.
+    pub fn block(&self, event: Event, until: Option<Duration>) {
+        self.with(|scheduler| {
+            if let Some(ref mut scheduler) = *scheduler {
+                scheduler.core_mut().running.state = State::Blocked { event, until };
+            }
+        })
+    }
+
+    /// Makes every process blocked until `event` ready to run, including any
+    /// that another core has not yet switched from.
+    pub fn wake(&self, event: Event) {
+        self.with(|scheduler| {
+            if let Some(ref mut scheduler) = *scheduler {
+                scheduler.unblock(event);
+            }
+        })
+    }
+
+    /// Ends the running process, stopped with the trap frame `tf` in a
+    /// `SYS_EXIT` system call, with the exit status `status`, and switches to
+    /// the next one ready to run. Its files are closed first, unless other
+    /// threads share them, and its kernel stack is freed once the scheduler
+    /// runs on another. If its parent is waiting for it, the parent collects
+    /// it at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler is uninitialized, since there is nothing to
+    /// switch to.
+    pub fn exit(&self, tf: &mut TrapFrame, status: i32) -> *mut TrapFrame {
+        // Closing a file can wake processes, which needs the scheduler.
+        let files = self.with(|scheduler| {
+            let running = &mut scheduler.as_mut()?.core_mut().running;
+            Some(mem::replace(&mut running.files, FdTable::new().shared()))
+        });
+        drop(files);
+
+        let now = clock::uptime();
+        self.with(|scheduler| {
+            let scheduler = scheduler.as_mut().expect("scheduler uninitialized");
+            scheduler.exit(status);
+            scheduler.switch(tf, now)
+        })
+    }
+
+    /// Collects a child of the running process, stopped with the trap frame
+    /// `tf` in a `SYS_WAIT` system call, that has exited: the child `pid`, or
+    /// any child if it is `None`. Its ID and exit status are returned in `x0`
+    /// and `x1`. If no such child has exited yet, the process waits for one
+    /// to, and the scheduler switches to the next process ready to run,
+    /// unless `options` has `WNOHANG`, in which case `x0` is 0. If there is
+    /// no such child, the call fails with `NoChild`.
+    pub fn wait(&self, tf: &mut TrapFrame, pid: Option<Id>, options: u64) -> *mut TrapFrame {
+        let now = clock::uptime();
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => scheduler.wait(tf, pid, options, now),
+            None => {
+                tf.x[0] = encode(Err(OsError::NoChild));
+                tf
+            }
+        })
+    }
+
+    /// Starts a child of the running process, stopped with the trap frame
+    /// `tf` in a `SYS_FORK` system call, that is a copy of it: its user
+    /// address space is shared copy-on-write and its file descriptors are
+    /// duplicated. The child's ID is returned in `x0`, and 0 to the child
+    /// when it runs. Fails with `NotPermitted` if the process is a kernel
+    /// thread, and `NoMemory` if there is no memory for the child's kernel
+    /// stack.
+    pub fn fork(&self, tf: &mut TrapFrame) -> *mut TrapFrame {
+        let child = self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => scheduler.fork(tf),
+            None => Err(OsError::NotPermitted),
+        });
+        tf.x[0] = encode(child);
+        tf
+    }
+
+    /// Replaces the program of the running process, stopped with the trap
+    /// frame `tf` in a `SYS_EXEC` system call, with `image`, and renames the
+    /// process `name`. The other threads of its program are sent `SIGKILL`.
+    /// Returns `false`, changing nothing, if the process is not running a
+    /// user program: a kernel thread cannot exec.
+    pub fn exec(&self, tf: &mut TrapFrame, name: &str, image: Image) -> bool {
+        let now = clock::uptime();
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => {
+                let id = scheduler.core().running.id;
+                let threads: Vec<Id> = scheduler
+                    .processes()
+                    .filter(|p| p.id != id && p.shares_space(&scheduler.core().running))
+                    .map(|p| p.id)
+                    .collect();
+                if !scheduler.core_mut().running.exec(tf, name, image) {
+                    return false;
+                }
+                for thread in threads {
+                    let _ = scheduler.kill(thread, SIGKILL);
+                }
+                scheduler.wake(now);
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Starts a thread of the running process, a user program, with the trap
+    /// frame `frame`: a process sharing its address space and file
+    /// descriptors, whose parent it is. Returns the thread's ID. Fails with
+    /// `NotPermitted` if the process is a kernel thread, and `NoMemory` if
+    /// there is no memory for the thread's kernel stack.
+    pub fn clone_thread(&self, frame: TrapFrame) -> OsResult<Id> {
+        self.with(|scheduler| {
+            let scheduler = scheduler.as_mut().ok_or(OsError::NotPermitted)?;
+            let running = &scheduler.core().running;
+            if !running.user {
+                return Err(OsError::NotPermitted);
+            }
+            let thread = running.thread(scheduler.last_id + 1, frame);
+            Ok(scheduler.add(thread.ok_or(OsError::NoMemory)?))
+        })
+    }
+
+    /// Calls `f` with the running process's file descriptors, returning what
+    /// it returns, or `None` if the scheduler is uninitialized. Descriptions
+    /// should be cloned out of the table to be used, rather than used in `f`,
+    /// which runs with IRQs masked.
+    pub fn with_files<R, F: FnOnce(&mut FdTable) -> R>(&self, f: F) -> Option<R> {
+        self.with(|scheduler| {
+            scheduler
+                .as_mut()
+                .map(|s| f(&mut s.core().running.files.lock()))
+        })
+    }
+
+    /// Sends `signal` to the process `id`, which receives it the next time it
+    /// returns to user mode, waking it if it is asleep or blocked. Fails with
+    /// `InvalidArgument` if there is no such signal, `NoProcess` if there is
+    /// no such process, and `NotPermitted` if it is a kernel thread, which
+    /// never receives signals.
+    pub fn kill(&self, id: Id, signal: u32) -> OsResult<()> {
+        if !signal::is_valid(signal) {
+            return Err(OsError::InvalidArgument);
+        }
+        let now = clock::uptime();
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => {
+                scheduler.kill(id, signal)?;
+                scheduler.wake(now);
+                Ok(())
+            }
+            None => Err(OsError::NoProcess),
+        })
+    }
+
+    /// Sets the running process's alarm to send it `SIGALRM` once `after` has
+    /// passed, then every `interval` unless it is zero, or cancels the alarm
+    /// if `after` is zero. Returns how long the alarm it replaces had left to
+    /// go, which is zero if there was none. Fails with `NotPermitted` if the
+    /// process is a kernel thread.
+    pub fn set_alarm(&self, after: Duration, interval: Duration) -> OsResult<Duration> {
+        let zero = Duration::from_secs(0);
+        let now = clock::uptime();
+        let alarm = match after {
+            after if after == zero => None,
+            after => Some(Alarm {
+                due: now + after,
+                interval: Some(interval).filter(|&interval| interval > zero),
+            }),
+        };
+
+        self.with(|scheduler| {
+            let running = &mut scheduler
+                .as_mut()
+                .ok_or(OsError::NotPermitted)?
+                .core_mut()
+                .running;
+            if !running.user {
+                return Err(OsError::NotPermitted);
+            }
+            let last = mem::replace(&mut running.alarm, alarm);
+            Ok(last.map_or(zero, |last| last.due.checked_sub(now).unwrap_or_default()))
+        })
+    }
+
+    /// Returns the running process's CPU time limit, or `None` if it has none.
+    /// Fails with `NotPermitted` if the process is a kernel thread.
+    pub fn cpu_limit(&self) -> OsResult<Option<Duration>> {
+        self.with(|scheduler| {
+            let running = &scheduler
+                .as_ref()
+                .ok_or(OsError::NotPermitted)?
+                .core()
+                .running;
+            if !running.user {
+                return Err(OsError::NotPermitted);
+            }
+            Ok(running.cpu_limit)
+        })
+    }
+
+    /// Limits the CPU time of the running process to `limit`, or lifts the
+    /// limit if it is `None`: once the process has run for longer, the
+    /// scheduler kills it. Fails with `NotPermitted` if the process is a
+    /// kernel thread.
+    pub fn set_cpu_limit(&self, limit: Option<Duration>) -> OsResult<()> {
+        self.with(|scheduler| {
+            let running = &mut scheduler
+                .as_mut()
+                .ok_or(OsError::NotPermitted)?
+                .core_mut()
+                .running;
+            if !running.user {
+                return Err(OsError::NotPermitted);
+            }
+            running.cpu_limit = limit;
+            Ok(())
+        })
+    }
+
+    /// Sets the running process's action for `signal` to `action`, returning
+    /// the previous one. Fails with `InvalidArgument` if there is no such
+    /// signal or its action cannot change.
+    pub fn sigaction(&self, signal: u32, action: Action) -> OsResult<Action> {
+        self.with(|scheduler| {
+            scheduler
+                .as_mut()
+                .and_then(|s| s.core_mut().running.signals.set_action(signal, action))
+                .ok_or(OsError::InvalidArgument)
+        })
+    }
+
+    /// Sets the nice value of the process `id` to `nice`, clamped to
+    /// `NICE_MIN..=NICE_MAX`, returning the previous one. Fails with
+    /// `NoProcess` if there is no such process. When the running process is
+    /// a user program, it also fails with `NotPermitted` if `id` is a kernel
+    /// thread or `nice` is lower than its nice value.
+    pub fn set_nice(&self, id: Id, nice: i32) -> OsResult<i32> {
+        self.with(|scheduler| match *scheduler {
+            Some(ref mut scheduler) => scheduler.set_nice(id, nice),
+            None => Err(OsError::NoProcess),
+        })
+    }
+
+    /// Returns the nice value of the process `id`. Fails with `NoProcess` if
+    /// there is no such process.
+    pub fn nice(&self, id: Id) -> OsResult<i32> {
+        self.with(|scheduler| {
+            scheduler
+                .iter()
+                .flat_map(|s| s.processes())
+                .find(|p| p.id == id)
+                .map(|p| p.nice)
+                .ok_or(OsError::NoProcess)
+        })
+    }
+
+    /// Returns `true` if a signal is pending for the running process: a
+    /// blocking operation that can be interrupted should give up.
+    pub fn signal_pending(&self) -> bool {
+        self.with(|scheduler| {
+            scheduler
+                .as_ref()
+                .map_or(false, |s| s.core().running.signals.is_pending())
+        })
+    }
+
+    /// Acts on the signals pending for the running process, if `tf`, its
+    /// trap frame, returns to user mode: ends the process for those with the
+    /// default action, and makes `tf` enter the handlers of the others.
+    /// Returns the trap frame to restore: `tf`, or that of the next process if
+    /// this one ended, whose signals are then acted on in turn.
+    pub fn deliver_signals(&self, mut tf: *mut TrapFrame) -> *mut TrapFrame {
+        loop {
+            let frame = unsafe { &mut *tf };
+            if frame.spsr & SPSR_MODE != 0 {
+                return tf;
+            }
+
+            let pending = self.with(|scheduler| {
+                let scheduler = scheduler.as_mut()?;
+                let running = &mut scheduler.core_mut().running;
+                Some((running.id, running.signals.take()?))
+            });
+            match pending {
+                None => return tf,
+                Some((_, (_, Action::Ignore))) => (),
+                Some((_, (signal, Action::Default))) => {
+                    tf = self.exit(frame, signal::exit_status(signal));
+                }
+                Some((id, (signal, action))) => {
+                    if let Err(e) = signal::enter_handler(frame, signal, action) {
+                        kprintln_nolock!("process {} killed: signal {}: {}", id, signal, e);
+                        tf = self.exit(frame, KILLED);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the name of the process whose kernel stack's guard page holds
+    /// `va`, if any.
+    pub fn stack_overflow_in(&self, va: usize) -> Option<String> {
+        self.with(|scheduler| {
+            scheduler
+                .as_ref()?
+                .processes()
+                .find(|process| process.stack_guards(va))
+                .map(|process| process.name.clone())
+        })
+    }
+
+    /// Lets other cores run the process this core last switched from, now
+    /// that it has left that process's kernel stack. See
+    /// `traps::exception_restoring()`.
+    pub fn switched(&self) {
+        self.with(|scheduler| {
+            if let Some(ref mut scheduler) = *scheduler {
+                scheduler.switched();
+            }
+        })
+    }
+
+    /// Calls `f` with the scheduler, set to act for this core, with IRQs
+    /// masked.
+    fn with<R, F: FnOnce(&mut Option<Scheduler>) -> R>(&self, f: F) -> R {
+        irq::with_irqs_disabled(|| {
+            let mut scheduler = self.0.lock();
+            if let Some(ref mut scheduler) = *scheduler {
+                scheduler.current = aarch64::affinity();
+            }
+            f(&mut scheduler)
+        })
+    }
+}
+
+/// The bits of `SPSR_EL1` that select the exception level and stack pointer
+/// an exception returns to: all clear for EL0.
+const SPSR_MODE: u64 = 0b1_1111;
+
+/// A core's running process and those waiting for their turn on it.
+struct Core {
+    running: Process,
+    /// The processes ready to run, in the order they became ready.
+    ready: VecDeque<Process>,
+    /// The idle process, unless it is running.
+    idle: Option<Process>,
+    /// The kernel stack of the last process to exit, kept until the core
+    /// runs on another stack so that it can be freed.
+    exited: Option<Stack>,
+    /// The process the core switched from in the exception it is handling,
+    /// whose kernel stack it is still running on: no other core may run the
+    /// process until the exception returns on the next one's stack.
+    leaving: Option<Id>,
+    /// The uptime at which the running process was given the core.
+    slice_start: Duration,
+    /// The uptime at which the running process was switched to, from which
+    /// its CPU time is counted.
+    since: Duration,
+}
+
+impl Core {
+    /// Returns a core running `running` since the uptime `since`, with
+    /// nothing else ready, and `idle` to run when no process is, unless
+    /// `running` is the idle process.
+    fn new(running: Process, idle: Option<Process>, since: Duration) -> Core {
+        Core {
+            running,
+            ready: VecDeque::new(),
+            idle,
+            exited: None,
+            leaving: None,
+            slice_start: since,
+            since,
+        }
+    }
+}
+
+/// The processes of every core, and those not ready to run.
+struct Scheduler {
+    /// Each core's processes, once it has started.
+    cores: [Option<Core>; NCORES],
+    /// The core the scheduler is acting for: the one using it.
+    current: usize,
+    /// The sleeping processes, in the order they wake.
+    sleeping: VecDeque<Process>,
+    /// The processes waiting for a child to exit.
+    waiting: Vec<Process>,
+    /// The processes blocked until an event.
+    blocked: Vec<Process>,
+    /// The processes that have exited and not been collected by their
+    /// parents.
+    zombies: Vec<Process>,
+    /// The ID given to the last process created.
+    last_id: Id,
+}
+
+impl Scheduler {
+    /// Returns a scheduler whose only process is `running`, on core 0, with
+    /// `idle` to run when no process is ready.
+    fn new(running: Process, idle: Process) -> Scheduler {
+        Scheduler {
+            last_id: running.id,
+            cores: [
+                Some(Core::new(running, Some(idle), Duration::from_secs(0))),
+                None,
+                None,
+                None,
+            ],
+            current: 0,
+            sleeping: VecDeque::new(),
+            waiting: Vec::new(),
+            blocked: Vec::new(),
+            zombies: Vec::new(),
+        }
+    }
+
+    /// Starts the current core, on which `idle`, its idle process, has been
+    /// running since the uptime `now`.
+    fn start_core(&mut self, idle: Process, now: Duration) {
+        self.cores[self.current] = Some(Core::new(idle, None, now));
+    }
+
+    /// Returns the current core.
+    fn core(&self) -> &Core {
+        self.cores[self.current].as_ref().expect("core not started")
+    }
+
+    /// Returns the current core.
+    fn core_mut(&mut self) -> &mut Core {
+        self.cores[self.current].as_mut().expect("core not started")
+    }
+
+    /// Adds `process`, whose ID must be greater than any before, to the end
+    /// of the current core's ready queue, returning its ID.
+    fn add(&mut self, process: Process) -> Id {
+        self.last_id = process.id;
+        self.core_mut().ready.push_back(process);
+        self.last_id
+    }
+
+    /// See `GlobalScheduler::fork()`. Returns the child's ID.
+    fn fork(&mut self, tf: &TrapFrame) -> OsResult<Id> {
+        if !self.core().running.trapped_from_user(tf) {
+            return Err(OsError::NotPermitted);
+        }
+
+        let space = VMM.fork_user().ok_or(OsError::NotPermitted)?;
+        let child = self.core().running.fork(tf, self.last_id + 1, space);
+        Ok(self.add(child.ok_or(OsError::NoMemory)?))
+    }
+
+    /// See `GlobalScheduler::set_nice()`.
+    fn set_nice(&mut self, id: Id, nice: i32) -> OsResult<i32> {
+        let user = VMM.has_user_space();
+        let process = self
+            .processes_mut()
+            .find(|p| p.id == id)
+            .ok_or(OsError::NoProcess)?;
+        let nice = nice.max(NICE_MIN).min(NICE_MAX);
+        if user && (nice < process.nice || !process.user) {
+            return Err(OsError::NotPermitted);
+        }
+        Ok(mem::replace(&mut process.nice, nice))
+    }
+
+    /// See `GlobalScheduler::kill()`. `signal` is valid.
+    fn kill(&mut self, id: Id, signal: u32) -> OsResult<()> {
+        let process = self
+            .processes_mut()
+            .find(|p| p.id == id)
+            .ok_or(OsError::NoProcess)?;
+        if let State::Zombie(_) = process.state {
+            return Ok(());
+        } else if !process.user {
+            return Err(OsError::NotPermitted);
+        }
+        process.signals.raise(signal);
+        self.unblock_where(|p| p.id == id);
+        Ok(())
+    }
+
+    /// Returns a snapshot of `process`, one of this scheduler's, at the uptime
+    /// `now`.
+    fn info(&self, process: &Process, now: Duration) -> Info {
+        let running = self
+            .cores
+            .iter()
+            .enumerate()
+            .filter_map(|(i, core)| Some((i, core.as_ref()?)))
+            .find(|&(_, core)| ptr::eq(&core.running, process));
+        let running_for = running.map_or(Duration::from_secs(0), |(_, core)| {
+            now.checked_sub(core.since).unwrap_or_default()
+        });
+
+        Info {
+            id: process.id,
+            parent: process.parent,
+            name: process.name.clone(),
+            state: process.state,
+            nice: process.nice,
+            core: running.map(|(i, _)| i),
+            cpu_time: process.cpu_time + running_for,
+            switches: process.switches,
+        }
+    }
+
+    /// Returns every process, in the order of `processes()`.
+    fn processes_mut(&mut self) -> impl Iterator<Item = &mut Process> {
+        let cores = self.cores.iter_mut().flatten().flat_map(|core| {
+            iter::once(&mut core.running)
+                .chain(core.ready.iter_mut())
+                .chain(core.idle.iter_mut())
+        });
+        cores
+            .chain(self.sleeping.iter_mut())
+            .chain(self.waiting.iter_mut())
+            .chain(self.blocked.iter_mut())
+            .chain(self.zombies.iter_mut())
+    }
+
+    /// Returns every process, in the order of `GlobalScheduler::processes()`.
+    fn processes(&self) -> impl Iterator<Item = &Process> {
+        let cores = self.cores.iter().flatten().flat_map(|core| {
+            iter::once(&core.running)
+                .chain(core.ready.iter())
+                .chain(core.idle.iter())
+        });
+        cores
+            .chain(self.sleeping.iter())
+            .chain(self.waiting.iter())
+            .chain(self.blocked.iter())
+            .chain(self.zombies.iter())
+    }
+
+    /// See `GlobalScheduler::exit()`. Makes the running process a zombie with
+    /// the exit status `status`, to be put away by `switch()`, orphans its
+    /// children and hands it to its parent if the parent is waiting for it.
+    fn exit(&mut self, status: i32) {
+        let running = &mut self.core_mut().running;
+        let (id, parent) = (running.id, running.parent);
+        running.state = State::Zombie(status);
+        running.alarm = None;
+
+        for process in self.processes_mut().filter(|p| p.parent == Some(id)) {
+            process.parent = None;
+        }
+        self.zombies.retain(|zombie| zombie.parent.is_some());
+
+        let waiting = self.waiting.iter().position(|p| match p.state {
+            State::Waiting(pid) => Some(p.id) == parent && pid.map_or(true, |pid| pid == id),
+            _ => false,
+        });
+        if let Some(index) = waiting {
+            let mut parent = self.waiting.remove(index);
+            collected(unsafe { &mut *parent.frame }, id, status);
+            parent.state = State::Ready;
+            let core = self.core_mut();
+            core.ready.push_back(parent);
+            core.running.parent = None;
+        }
+    }
+
+    /// See `GlobalScheduler::wait()`. `now` is the uptime.
+    fn wait(
+        &mut self,
+        tf: &mut TrapFrame,
+        pid: Option<Id>,
+        options: u64,
+        now: Duration,
+    ) -> *mut TrapFrame {
+        let id = self.core().running.id;
+        let is_awaited = |p: &Process| p.parent == Some(id) && pid.map_or(true, |pid| p.id == pid);
+
+        if let Some(index) = self.zombies.iter().position(is_awaited) {
+            let zombie = self.zombies.remove(index);
+            if let State::Zombie(status) = zombie.state {
+                collected(tf, zombie.id, status);
+            }
+            return tf;
+        } else if !self.processes().any(is_awaited) {
+            tf.x[0] = encode(Err(OsError::NoChild));
+            return tf;
+        } else if options & WNOHANG != 0 {
+            tf.x[0] = 0;
+            return tf;
+        }
+
+        self.core_mut().running.state = State::Waiting(pid);
+        self.switch(tf, now)
+    }
+
+    /// Sends `SIGALRM` to the processes whose alarms are due by the uptime
+    /// `now`, then moves the sleeping processes due to wake by then or with a
+    /// signal pending, and the blocked ones whose timeout has passed or that
+    /// were just sent `SIGALRM`, to the end of the current core's ready queue.
+    fn wake(&mut self, now: Duration) {
+        let mut alarmed = Vec::new();
+        for process in self.processes_mut() {
+            if let Some(alarm) = process.alarm.filter(|alarm| alarm.due <= now) {
+                process.signals.raise(SIGALRM);
+                process.alarm = alarm.interval.map(|interval| Alarm {
+                    due: (alarm.due + interval).max(now),
+                    interval: Some(interval),
+                });
+                alarmed.push(process.id);
+            }
+        }
+
+        self.unblock_where(|p| match p.state {
+            State::Blocked { until, .. } => {
+                until.map_or(false, |until| until <= now) || alarmed.contains(&p.id)
+            }
+            _ => false,
+        });
+
+        let is_due = |p: &Process| match p.state {
+            State::Sleeping { until, .. } => until <= now,
+            _ => unreachable!("process in the sleep queue is awake"),
+        };
+        while let Some(i) = self
+            .sleeping
+            .iter()
+            .position(|p| is_due(p) || p.signals.is_pending())
+        {
+            let mut process = self.sleeping.remove(i).unwrap();
+            if let State::Sleeping { since, unit, .. } = process.state {
+                let slept = units(now.checked_sub(since).unwrap_or_default(), unit);
+                unsafe { (*process.frame).x[0] = slept };
+            }
+            process.state = State::Ready;
+            self.core_mut().ready.push_back(process);
+        }
+    }
+
+    /// Sends `SIGKILL` to the running process if, by the uptime `now`, it has
+    /// run for longer than its CPU time limit, reporting that it did.
+    fn enforce_cpu_limit(&mut self, now: Duration) {
+        let core = self.core_mut();
+        let used = core.running.cpu_time + now.checked_sub(core.since).unwrap_or_default();
+        let running = &mut core.running;
+        match running.cpu_limit {
+            Some(limit) if running.user && used > limit => {
+                kprintln_nolock!(
+                    "process {} ({}) killed: CPU time limit of {:?} exceeded",
+                    running.id,
+                    running.name,
+                    limit
+                );
+                running.signals.raise(SIGKILL);
+                running.cpu_limit = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Moves the processes blocked until `event` to the end of the current
+    /// core's ready queue, in the order they blocked.
+    fn unblock(&mut self, event: Event) {
+        self.unblock_where(|p| match p.state {
+            State::Blocked { event: e, .. } => e == event,
+            _ => false,
+        });
+    }
+
+    /// Moves the blocked processes for which `f` returns `true` to the end of
+    /// the current core's ready queue, in the order they blocked. Those still
+    /// running on a core, not yet switched from, are made running again
+    /// instead, so that they are not blocked. `f` is only called with blocked
+    /// processes.
+    fn unblock_where<F: Fn(&Process) -> bool>(&mut self, f: F) {
+        for core in self.cores.iter_mut().flatten() {
+            if let State::Blocked { .. } = core.running.state {
+                if f(&core.running) {
+                    core.running.state = State::Running;
+                }
+            }
+        }
+
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if f(&self.blocked[i]) {
+                let mut process = self.blocked.remove(i);
+                process.state = State::Ready;
+                self.core_mut().ready.push_back(process);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Adds `process`, which is sleeping, to the sleeping processes, after
+    /// those that wake no later than it does.
+    fn add_sleeping(&mut self, process: Process) {
+        let wakes = |p: &Process| match p.state {
+            State::Sleeping { until, .. } => until,
+            _ => unreachable!("process in the sleep queue is awake"),
+        };
+        let until = wakes(&process);
+        let index = self
+            .sleeping
+            .iter()
+            .position(|p| wakes(p) > until)
+            .unwrap_or(self.sleeping.len());
+        self.sleeping.insert(index, process);
+    }
+
+    /// Returns the core and the index in its ready queue of the process to
+    /// run next on the current core: the first of those with the best
+    /// priority in the current core's queue or, if there are none, in the
+    /// longest queue of another core. Processes that another core is leaving
+    /// are passed over.
+    fn next_ready(&self) -> Option<(usize, usize)> {
+        let current = self.current;
+        let leaving = |p: &Process| {
+            self.cores.iter().enumerate().any(|(i, core)| {
+                i != current
+                    && core
+                        .as_ref()
+                        .map_or(false, |core| core.leaving == Some(p.id))
+            })
+        };
+        let best = |core: &Core| {
+            core.ready
+                .iter()
+                .enumerate()
+                .filter(|&(_, p)| !leaving(p))
+                .min_by_key(|&(_, p)| priority(p))
+                .map(|(i, _)| i)
+        };
+
+        if let Some(index) = best(self.core()) {
+            return Some((current, index));
+        }
+        self.cores
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != current)
+            .filter_map(|(i, core)| Some((i, core.as_ref()?)))
+            .max_by_key(|&(_, core)| core.ready.len())
+            .and_then(|(i, core)| Some((i, best(core)?)))
+    }
+
+    /// Returns the process at `index` in the ready queue of the core `core`.
+    fn ready_process(&self, (core, index): (usize, usize)) -> &Process {
+        &self.cores[core].as_ref().expect("core not started").ready[index]
+    }
+
+    /// Returns `true` if the running process should give up the current core
+    /// at the uptime `now`: it is the idle process and another is ready, a
+    /// process with a better priority than the one it was chosen with is
+    /// ready, or its time slice is over and one with as good a priority as
+    /// its nice value is. When a time slice is over, the processes ready to
+    /// run on the core age by one, and if the running one keeps the core, its
+    /// next slice starts.
+    fn should_preempt(&mut self, now: Duration) -> bool {
+        if self.core().running.id == IDLE_ID {
+            return self.next_ready().is_some();
+        }
+
+        let core = self.core_mut();
+        let slice_over = now.checked_sub(core.slice_start).unwrap_or_default() >= TIME_SLICE;
+        if slice_over {
+            for process in core.ready.iter_mut() {
+                process.age += 1;
+            }
+            core.running.age = 0;
+        }
+        let running = priority(&core.running);
+        match self
+            .next_ready()
+            .map(|next| priority(self.ready_process(next)))
+        {
+            Some(best) if best < running || (slice_over && best == running) => return true,
+            _ if slice_over => self.core_mut().slice_start = now,
+            _ => (),
+        }
+        false
+    }
+
+    /// See `GlobalScheduler::switch()`. The running process is put back in
+    /// the current core's ready queue unless it has exited or is going to
+    /// sleep or wait. `now` is the uptime, at which the next process's time
+    /// slice starts.
+    fn switch(&mut self, tf: &mut TrapFrame, now: Duration) -> *mut TrapFrame {
+        // Unless the core already switched in this exception, it is running
+        // on the stack of the running process, which has not exited, so the
+        // last one's stack is no longer in use.
+        let switched = self.core().leaving.is_some();
+        if !switched {
+            self.core_mut().exited = None;
+        }
+        self.core_mut().slice_start = now;
+        self.wake(now);
+        self.enforce_cpu_limit(now);
+
+        let next = match self.next_ready() {
+            Some((core, index)) => {
+                let core = self.cores[core].as_mut().expect("core not started");
+                core.ready.remove(index).unwrap()
+            }
+            None if self.core().running.state == State::Running => return tf,
+            None => self
+                .core_mut()
+                .idle
+                .take()
+                .expect("idle process running twice"),
+        };
+
+        let core = self.core_mut();
+        core.running.frame = tf;
+        core.running.cpu_time += now.checked_sub(core.since).unwrap_or_default();
+        core.running.switches += 1;
+        core.since = now;
+        let mut last = mem::replace(&mut core.running, next);
+        last.age = 0;
+        switch_address_space(&core.running);
+        if !switched {
+            core.leaving = Some(last.id);
+        }
+        match last.state {
+            State::Zombie(_) => {
+                let stack = last.stack.take();
+                if !switched {
+                    core.exited = stack;
+                }
+                last.space = None;
+                last.files = FdTable::new().shared();
+                if last.parent.is_some() {
+                    self.zombies.push(last);
+                }
+            }
+            State::Sleeping { .. } => self.add_sleeping(last),
+            State::Waiting(_) => self.waiting.push(last),
+            State::Blocked { .. } => self.blocked.push(last),
+            _ if last.id == IDLE_ID => {
+                last.state = State::Ready;
+                core.idle = Some(last);
+            }
+            _ => {
+                last.state = State::Ready;
+                core.ready.push_back(last);
+            }
+        }
+
+        let running = &mut self.core_mut().running;
+        running.state = State::Running;
+        mem::replace(&mut running.frame, ptr::null_mut())
+    }
+
+    /// See `GlobalScheduler::switched()`.
+    fn switched(&mut self) {
+        if let Some(ref mut core) = self.cores[self.current] {
+            core.leaving = None;
+        }
+    }
+}
+
+/// Returns the priority of `process`: its nice value, lowered by one for each
+/// time slice it has waited to run, counting the wait before the running
+/// process's slice. The lower it is, the sooner the process runs.
+fn priority(process: &Process) -> i32 {
+    process.nice.saturating_sub(process.age)
+}
+
+/// Activates the user address space of `next`, which is about to run, or the
+/// kernel's identity map if it has none.
+fn switch_address_space(next: &Process) {
+    match next.space {
+        Some(ref space) => drop(VMM.activate(space.clone())),
+        None if VMM.has_user_space() => drop(VMM.deactivate()),
+        None => (),
+    }
+}
+
+/// Returns `duration` counted in `unit`s, rounded down.
+fn units(duration: Duration, unit: Duration) -> u64 {
+    (duration.as_nanos() / unit.as_nanos().max(1)) as u64
+}
+
+/// Returns the result of a `SYS_WAIT` system call that collected the child
+/// `id`, which exited with `status`, in `tf`.
+fn collected(tf: &mut TrapFrame, id: Id, status: i32) {
+    tf.x[0] = id;
+    tf.x[1] = status as u64;
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use core::time::Duration;
+
+    use kernel_api::{decode, OsError, SIGALRM, SIGINT, SIGKILL};
+
+    use super::{Alarm, Process, Scheduler, State, IDLE_ID, NICE_MIN, TIME_SLICE, WNOHANG};
+    use crate::traps::TrapFrame;
+
+    /// Returns a process, ready to run, that resumes with `frame`.
+    fn ready(id: u64, frame: &mut TrapFrame) -> Process {
+        let mut process = Process::adopt(id, "thread");
+        process.state = State::Ready;
+        process.frame = frame;
+        process
+    }
+
+    /// Trap frames for the processes of a test to resume with.
+    type Frames<'a> = &'a mut [TrapFrame];
+
+    /// Returns a scheduler running process 1, "kmain", with the idle process
+    /// ready, and `frames` split into kmain's frame, the idle process's, and
+    /// the rest, for the processes a test adds.
+    fn fixture(frames: Frames) -> (Scheduler, Frames, Frames, Frames) {
+        let (kmain, rest) = frames.split_at_mut(1);
+        let (idle, rest) = rest.split_at_mut(1);
+        let scheduler = Scheduler::new(Process::adopt(1, "kmain"), ready(IDLE_ID, &mut idle[0]));
+        (scheduler, kmain, idle, rest)
+    }
+
+    fn ids(scheduler: &Scheduler) -> Vec<u64> {
+        scheduler.processes().map(|p| p.id).collect()
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut frames = [TrapFrame::default(); 4];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        scheduler.add(ready(2, &mut rest[0]));
+        scheduler.add(ready(3, &mut rest[1]));
+
+        assert_eq!(
+            scheduler.switch(&mut kmain[0], ms(0)),
+            &mut rest[0] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [2, 3, 1, IDLE_ID]);
+        assert_eq!(scheduler.core().running.state, State::Running);
+        assert_eq!(scheduler.core().ready[1].state, State::Ready);
+
+        // a process that exits is replaced by the next in line
+        scheduler.exit(0);
+        assert_eq!(
+            scheduler.switch(&mut rest[0], ms(1)),
+            &mut rest[1] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [3, 1, IDLE_ID]);
+        assert_eq!(
+            scheduler.switch(&mut rest[1], ms(2)),
+            &mut kmain[0] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [1, 3, IDLE_ID]);
+
+        // with nothing else ready, the running process keeps running
+        scheduler.core_mut().ready.clear();
+        assert_eq!(
+            scheduler.switch(&mut kmain[0], ms(3)),
+            &mut kmain[0] as *mut _
+        );
+        assert_eq!(scheduler.core().running.id, 1);
+    }
+
+    #[test]
+    fn statistics() {
+        let mut frames = [TrapFrame::default(); 3];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        scheduler.add(ready(2, &mut rest[0]));
+
+        scheduler.switch(&mut kmain[0], ms(30));
+        scheduler.switch(&mut rest[0], ms(45));
+        scheduler.switch(&mut kmain[0], ms(50));
+
+        // the running process's time counts up to now
+        let info = scheduler.info(&scheduler.core().running, ms(60));
+        assert_eq!((info.id, info.core), (2, Some(0)));
+        assert_eq!(info.cpu_time, ms(25));
+        assert_eq!(info.switches, 1);
+        let info = scheduler.info(&scheduler.core().ready[0], ms(60));
+        assert_eq!((info.id, info.core), (1, None));
+        assert_eq!(info.cpu_time, ms(35));
+        assert_eq!(info.switches, 2);
+    }
+
+    #[test]
+    fn blocking() {
+        let mut frames = [TrapFrame::default(); 4];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        scheduler.add(ready(2, &mut rest[0]));
+        scheduler.add(ready(3, &mut rest[1]));
+
+        // blocked processes are passed over until their event is woken
+        let forever = State::Blocked {
+            event: 0x1000,
+            until: None,
+        };
+        scheduler.core_mut().running.state = forever;
+        scheduler.switch(&mut kmain[0], ms(0));
+        scheduler.core_mut().running.state = State::Blocked {
+            event: 0x2000,
+            until: Some(ms(50)),
+        };
+        scheduler.switch(&mut rest[0], ms(1));
+        assert_eq!(scheduler.core().running.id, 3);
+        assert!(scheduler.core().ready.is_empty());
+
+        scheduler.unblock(0x3000);
+        scheduler.wake(ms(49));
+        assert!(scheduler.core().ready.is_empty());
+        scheduler.unblock(0x2000);
+        assert_eq!(ids(&scheduler), [3, 2, IDLE_ID, 1]);
+        assert_eq!(scheduler.core().ready[0].state, State::Ready);
+        assert_eq!(scheduler.blocked[0].state, forever);
+
+        // or their timeout passes
+        scheduler.core_mut().running.state = State::Blocked {
+            event: 0x2000,
+            until: Some(ms(60)),
+        };
+        scheduler.switch(&mut rest[1], ms(2));
+        assert_eq!(scheduler.blocked.len(), 2);
+        scheduler.wake(ms(60));
+        assert_eq!(ids(&scheduler), [2, 3, IDLE_ID, 1]);
+    }
+
+    #[test]
+    fn signals_only_reach_user_processes() {
+        let mut frames = [TrapFrame::default(); 3];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        scheduler.add(ready(2, &mut rest[0]));
+
+        assert_eq!(scheduler.kill(3, SIGINT), Err(OsError::NoProcess));
+        assert_eq!(scheduler.kill(2, SIGINT), Err(OsError::NotPermitted));
+        assert!(!scheduler.core().ready[0].signals.is_pending());
+
+        // a zombie ignores them
+        scheduler.core_mut().ready[0].parent = Some(1);
+        scheduler.switch(&mut kmain[0], ms(0));
+        scheduler.exit(0);
+        scheduler.switch(&mut rest[0], ms(1));
+        assert_eq!(scheduler.zombies[0].id, 2);
+        assert_eq!(scheduler.kill(2, SIGINT), Ok(()));
+    }
+
+    #[test]
+    fn signals_unblock() {
+        let mut frames = [TrapFrame::default(); 3];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        let mut process = ready(2, &mut rest[0]);
+        process.user = true;
+        scheduler.add(process);
+
+        scheduler.switch(&mut kmain[0], ms(0));
+        scheduler.core_mut().running.state = State::Blocked {
+            event: 0x1000,
+            until: None,
+        };
+        scheduler.switch(&mut rest[0], ms(1));
+        assert_eq!(scheduler.blocked[0].id, 2);
+
+        // a blocked process is woken to notice its signal
+        assert_eq!(scheduler.kill(2, SIGINT), Ok(()));
+        assert!(scheduler.blocked.is_empty());
+        assert_eq!(scheduler.core().ready[0].id, 2);
+        assert!(scheduler.core().ready[0].signals.is_pending());
+    }
+
+    #[test]
+    fn idle_and_time_slices() {
+        let mut frames = [TrapFrame::default(); 4];
+        let (mut scheduler, kmain, idle, rest) = fixture(&mut frames);
+
+        // the idle process runs once nothing else can
+        scheduler.exit(0);
+        assert_eq!(
+            scheduler.switch(&mut kmain[0], ms(0)),
+            &mut idle[0] as *mut _
+        );
+        assert_eq!(scheduler.core().running.id, IDLE_ID);
+        assert!(!scheduler.should_preempt(ms(100)));
+
+        // and gives way as soon as another is ready
+        scheduler.add(ready(2, &mut rest[0]));
+        assert!(scheduler.should_preempt(ms(100)));
+        assert_eq!(
+            scheduler.switch(&mut idle[0], ms(100)),
+            &mut rest[0] as *mut _
+        );
+        assert!(scheduler.core().idle.is_some());
+        assert!(scheduler.core().ready.is_empty());
+
+        // which runs for a whole time slice, or more if no other is ready
+        assert!(!scheduler.should_preempt(ms(100) + TIME_SLICE));
+        scheduler.add(ready(3, &mut rest[1]));
+        assert!(!scheduler.should_preempt(ms(100) + TIME_SLICE * 2 - ms(1)));
+        assert!(scheduler.should_preempt(ms(100) + TIME_SLICE * 2));
+    }
+
+    #[test]
+    fn priorities() {
+        let mut frames = [TrapFrame::default(); 5];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        for (i, &nice) in [5, 0].iter().enumerate() {
+            let mut process = ready(i as u64 + 2, &mut rest[i]);
+            process.nice = nice;
+            scheduler.add(process);
+        }
+
+        // the best priority runs first, and those with the same take turns
+        assert_eq!(
+            scheduler.switch(&mut kmain[0], ms(0)),
+            &mut rest[1] as *mut _
+        );
+        assert!(scheduler.should_preempt(ms(20)));
+        scheduler.switch(&mut rest[1], ms(20));
+        assert!(scheduler.should_preempt(ms(40)));
+        scheduler.switch(&mut kmain[0], ms(40));
+        assert_eq!(ids(&scheduler), [3, 2, 1, IDLE_ID]);
+
+        // a worse one ages a level per time slice it waits, until it runs
+        scheduler.exit(0);
+        scheduler.switch(&mut rest[1], ms(40));
+        assert!(!scheduler.should_preempt(ms(60)));
+        assert!(!scheduler.should_preempt(ms(80)));
+        assert!(scheduler.should_preempt(ms(100)));
+        assert_eq!(
+            scheduler.switch(&mut kmain[0], ms(100)),
+            &mut rest[0] as *mut _
+        );
+        assert!(!scheduler.should_preempt(ms(110)));
+
+        // and one better than the running one preempts it at once
+        let mut process = ready(4, &mut rest[2]);
+        process.nice = -5;
+        scheduler.add(process);
+        assert!(scheduler.should_preempt(ms(110)));
+
+        assert_eq!(scheduler.set_nice(4, -100), Ok(-5));
+        assert_eq!(scheduler.core().ready[1].nice, NICE_MIN);
+        assert_eq!(scheduler.set_nice(5, 0), Err(OsError::NoProcess));
+    }
+
+    #[test]
+    fn cores() {
+        let mut frames = [TrapFrame::default(); 5];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        scheduler.current = 1;
+        scheduler.start_core(Process::adopt(IDLE_ID, "idle1"), ms(0));
+        assert_eq!(ids(&scheduler), [1, IDLE_ID, IDLE_ID]);
+
+        // an idle core takes work from a busy one
+        scheduler.current = 0;
+        scheduler.add(ready(2, &mut rest[0]));
+        scheduler.add(ready(3, &mut rest[1]));
+        scheduler.switch(&mut kmain[0], ms(0));
+        scheduler.current = 1;
+        assert!(scheduler.should_preempt(ms(10)));
+        assert_eq!(
+            scheduler.switch(&mut rest[2], ms(10)),
+            &mut rest[1] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [2, 1, IDLE_ID, 3, IDLE_ID]);
+
+        // but not a process whose stack the other is still leaving
+        scheduler.switched();
+        assert_eq!(
+            scheduler.switch(&mut rest[1], ms(20)),
+            &mut rest[1] as *mut _
+        );
+        scheduler.current = 0;
+        scheduler.switched();
+        scheduler.current = 1;
+        assert_eq!(
+            scheduler.switch(&mut rest[1], ms(30)),
+            &mut kmain[0] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [2, IDLE_ID, 1, 3, IDLE_ID]);
+
+        // a process blocking on one core is not lost if another wakes it first
+        scheduler.core_mut().running.state = State::Blocked {
+            event: 0x1000,
+            until: None,
+        };
+        scheduler.current = 0;
+        scheduler.unblock(0x1000);
+        scheduler.current = 1;
+        assert_eq!(scheduler.core().running.state, State::Running);
+    }
+
+    #[test]
+    fn sleep_queue() {
+        let mut frames = [TrapFrame::default(); 4];
+        let (mut scheduler, kmain, idle, rest) = fixture(&mut frames);
+        scheduler.add(ready(2, &mut rest[0]));
+        scheduler.add(ready(3, &mut rest[1]));
+
+        let sleep = |scheduler: &mut Scheduler, now: u64, duration: u64| {
+            scheduler.core_mut().running.state = State::Sleeping {
+                since: ms(now),
+                until: ms(now + duration),
+                unit: ms(1),
+            };
+        };
+
+        // sleepers are kept in the order they wake, not the order they slept
+        sleep(&mut scheduler, 0, 50);
+        assert_eq!(
+            scheduler.switch(&mut kmain[0], ms(0)),
+            &mut rest[0] as *mut _
+        );
+        sleep(&mut scheduler, 10, 20);
+        assert_eq!(
+            scheduler.switch(&mut rest[0], ms(10)),
+            &mut rest[1] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [3, IDLE_ID, 2, 1]);
+
+        // each wakes once its time has come, told how long it slept
+        scheduler.wake(ms(29));
+        assert!(scheduler.core().ready.is_empty());
+        scheduler.wake(ms(35));
+        assert_eq!(ids(&scheduler), [3, 2, IDLE_ID, 1]);
+        assert_eq!(scheduler.core().ready[0].state, State::Ready);
+        assert_eq!(rest[0].x[0], 25);
+
+        // a sleeper with nothing else to run leaves the processor idle
+        sleep(&mut scheduler, 40, 10);
+        assert_eq!(
+            scheduler.switch(&mut rest[1], ms(40)),
+            &mut rest[0] as *mut _
+        );
+        sleep(&mut scheduler, 40, 100);
+        assert_eq!(
+            scheduler.switch(&mut rest[0], ms(40)),
+            &mut idle[0] as *mut _
+        );
+        assert_eq!(
+            scheduler.switch(&mut idle[0], ms(60)),
+            &mut kmain[0] as *mut _
+        );
+        assert_eq!((kmain[0].x[0], rest[1].x[0]), (60, 20));
+        assert_eq!(ids(&scheduler), [1, 3, IDLE_ID, 2]);
+    }
+
+    #[test]
+    fn cpu_limit() {
+        let mut frames = [TrapFrame::default(); 3];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        let mut process = ready(2, &mut rest[0]);
+        process.user = true;
+        process.cpu_limit = Some(ms(20));
+        scheduler.add(process);
+
+        scheduler.switch(&mut kmain[0], ms(0));
+        scheduler.switch(&mut rest[0], ms(15));
+        scheduler.switch(&mut kmain[0], ms(20));
+        scheduler.core_mut().ready.clear();
+        scheduler.switch(&mut rest[0], ms(25));
+        assert!(!scheduler.core().running.signals.is_pending());
+
+        // the time it has run for counts, though it was not switched from
+        scheduler.switch(&mut rest[0], ms(26));
+        let running = &mut scheduler.core_mut().running;
+        assert_eq!(
+            running.signals.take().map(|(signal, _)| signal),
+            Some(SIGKILL)
+        );
+        assert_eq!(running.cpu_limit, None);
+    }
+
+    #[test]
+    fn alarms() {
+        let mut frames = [TrapFrame::default(); 3];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        scheduler.add(ready(2, &mut rest[0]));
+        scheduler.core_mut().ready[0].alarm = Some(Alarm {
+            due: ms(20),
+            interval: Some(ms(30)),
+        });
+
+        // a process asleep when its alarm goes off wakes early, told how
+        // long it slept
+        scheduler.core_mut().running.state = State::Sleeping {
+            since: ms(0),
+            until: ms(100),
+            unit: Duration::from_nanos(1),
+        };
+        scheduler.switch(&mut kmain[0], ms(0));
+        scheduler.core_mut().running.state = State::Sleeping {
+            since: ms(10),
+            until: ms(100),
+            unit: ms(1),
+        };
+        scheduler.switch(&mut rest[0], ms(10));
+        scheduler.wake(ms(19));
+        assert!(scheduler.core().ready.is_empty());
+        scheduler.wake(ms(25));
+        assert_eq!(ids(&scheduler), [IDLE_ID, 2, 1]);
+        assert_eq!(rest[0].x[0], 15);
+
+        // and again after each interval
+        let process = &mut scheduler.core_mut().ready[0];
+        assert_eq!(
+            process.signals.take().map(|(signal, _)| signal),
+            Some(SIGALRM)
+        );
+        assert_eq!(process.alarm.unwrap().due, ms(50));
+        scheduler.wake(ms(50));
+        let process = &mut scheduler.core_mut().ready[0];
+        assert!(process.signals.is_pending());
+        process.alarm = None;
+
+        // signals wake sleepers too
+        assert_eq!(scheduler.kill(1, SIGINT), Err(OsError::NotPermitted));
+        scheduler.sleeping[0].user = true;
+        assert_eq!(scheduler.kill(1, SIGINT), Ok(()));
+        scheduler.wake(ms(60));
+        assert_eq!(ids(&scheduler), [IDLE_ID, 2, 1]);
+        assert_eq!(kmain[0].x[0], 60_000_000);
+    }
+
+    #[test]
+    fn exit_and_wait() {
+        let mut frames = [TrapFrame::default(); 5];
+        let (mut scheduler, kmain, _, rest) = fixture(&mut frames);
+        for (i, &parent) in [1, 2, 1].iter().enumerate() {
+            let mut child = ready(i as u64 + 2, &mut rest[i]);
+            child.parent = Some(parent);
+            scheduler.add(child);
+        }
+
+        // a parent waits for its child, which does not wait for its own
+        let tf = scheduler.wait(&mut kmain[0], Some(2), 0, ms(0));
+        assert_eq!(tf, &mut rest[0] as *mut _);
+        assert_eq!(scheduler.wait(&mut rest[0], None, WNOHANG, ms(0)), tf);
+        assert_eq!(rest[0].x[0], 0);
+
+        // exiting wakes the waiting parent and orphans the children
+        scheduler.exit(7);
+        assert_eq!(
+            scheduler.switch(&mut rest[0], ms(1)),
+            &mut rest[1] as *mut _
+        );
+        assert_eq!((kmain[0].x[0], kmain[0].x[1]), (2, 7));
+        assert_eq!(ids(&scheduler), [3, 4, 1, IDLE_ID]);
+        assert_eq!(scheduler.core().running.parent, None);
+
+        // an orphan is forgotten, a child not yet waited for is kept
+        scheduler.exit(1);
+        assert_eq!(
+            scheduler.switch(&mut rest[1], ms(2)),
+            &mut rest[2] as *mut _
+        );
+        scheduler.exit(-1);
+        assert_eq!(
+            scheduler.switch(&mut rest[2], ms(3)),
+            &mut kmain[0] as *mut _
+        );
+        assert_eq!(ids(&scheduler), [1, IDLE_ID, 4]);
+        assert_eq!(scheduler.zombies[0].state, State::Zombie(-1));
+
+        // until its parent collects it
+        let tf = &mut kmain[0] as *mut _;
+        assert_eq!(scheduler.wait(&mut kmain[0], None, 0, ms(4)), tf);
+        assert_eq!((kmain[0].x[0], kmain[0].x[1] as i32), (4, -1));
+        assert_eq!(ids(&scheduler), [1, IDLE_ID]);
+        assert_eq!(scheduler.wait(&mut kmain[0], None, 0, ms(5)), tf);
+        assert_eq!(decode(kmain[0].x[0]), Err(OsError::NoChild));
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod ktests {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::time::Duration;
+
+    use crate::process;
+    use crate::{clock, SCHEDULER};
+
+    #[test_case]
+    fn spawned_threads_run_and_exit() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut children = [0; 4];
+        for child in children.iter_mut() {
+            let runs = runs.clone();
+            *child = SCHEDULER
+                .spawn("ktest", move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+        }
+
+        for &child in children.iter() {
+            assert_eq!(process::waitpid(Some(child), 0), Some((child, 0)));
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), children.len());
+    }
+
+    #[test_case]
+    fn sleep_lasts_long_enough() {
+        let start = clock::uptime();
+        let slept = process::sleep(Duration::from_millis(50));
+        assert!(slept >= Duration::from_millis(50));
+        assert!(clock::uptime() - start >= Duration::from_millis(50));
+    }
+}
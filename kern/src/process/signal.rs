@@ -0,0 +1,262 @@
+use core::mem::size_of;
+use core::slice;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use kernel_api::{NSIG, SIGKILL, SIG_DFL, SIG_IGN};
+
+use crate::traps::TrapFrame;
+use crate::vm::FaultError;
+use crate::VMM;
+
+use super::Id;
+
+/// The bits of `SPSR_EL1` a signal handler's return can restore: the
+/// condition flags. Everything else stays as it is for user programs.
+const SPSR_NZCV: u64 = 0xF << 28;
+
+/// The process `Ctrl-C` interrupts, or 0 if none.
+static FOREGROUND: AtomicU64 = AtomicU64::new(0);
+
+/// What a process does when it receives a signal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// End the process.
+    Default,
+    /// Nothing.
+    Ignore,
+    /// Call the user function at `handler`, which returns to `trampoline`.
+    Handle { handler: u64, trampoline: u64 },
+}
+
+impl Action {
+    /// Returns the action a `SYS_SIGACTION` system call asks for with the
+    /// handler `handler` and trampoline `trampoline`.
+    pub fn from_handler(handler: u64, trampoline: u64) -> Action {
+        match handler {
+            SIG_DFL => Action::Default,
+            SIG_IGN => Action::Ignore,
+            handler => Action::Handle {
+                handler,
+                trampoline,
+            },
+        }
+    }
+
+    /// Returns the handler a `SYS_SIGACTION` system call sets this action
+    /// with.
+    pub fn handler(&self) -> u64 {
+        match *self {
+            Action::Default => SIG_DFL,
+            Action::Ignore => SIG_IGN,
+            Action::Handle { handler, .. } => handler,
+        }
+    }
+}
+
+/// A process's signals: those it has been sent and not yet received, and
+/// what it does with each.
+#[derive(Debug, Clone)]
+pub struct Signals {
+    /// Bit `n` is set if the signal `n` is pending.
+    pending: u32,
+    actions: [Action; NSIG as usize],
+}
+
+impl Signals {
+    /// Returns signals with none pending and every action the default.
+    pub fn new() -> Signals {
+        Signals {
+            pending: 0,
+            actions: [Action::Default; NSIG as usize],
+        }
+    }
+
+    /// Makes `signal` pending, unless it is ignored. Returns `false` if
+    /// there is no such signal.
+    pub fn raise(&mut self, signal: u32) -> bool {
+        if !is_valid(signal) {
+            return false;
+        } else if self.actions[signal as usize] != Action::Ignore {
+            self.pending |= 1 << signal;
+        }
+        true
+    }
+
+    /// Returns `true` if a signal is pending.
+    pub fn is_pending(&self) -> bool {
+        self.pending != 0
+    }
+
+    /// Sets the action for `signal` to `action`, returning the previous one,
+    /// or `None`, changing nothing, if there is no such signal or it is
+    /// `SIGKILL`, whose action cannot change. Ignoring a pending signal
+    /// discards it.
+    pub fn set_action(&mut self, signal: u32, action: Action) -> Option<Action> {
+        if !is_valid(signal) || signal == SIGKILL {
+            return None;
+        } else if action == Action::Ignore {
+            self.pending &= !(1 << signal);
+        }
+        Some(core::mem::replace(
+            &mut self.actions[signal as usize],
+            action,
+        ))
+    }
+
+    /// Takes the lowest pending signal, returning it and its action.
+    pub fn take(&mut self) -> Option<(u32, Action)> {
+        if self.pending == 0 {
+            return None;
+        }
+        let signal = self.pending.trailing_zeros();
+        self.pending &= !(1 << signal);
+        Some((signal, self.actions[signal as usize]))
+    }
+
+    /// Returns a child's signals after `fork()`: the same actions, with none
+    /// pending.
+    pub fn forked(&self) -> Signals {
+        Signals {
+            pending: 0,
+            actions: self.actions,
+        }
+    }
+
+    /// Resets the handled signals to their default actions, as `exec()`
+    /// does: the handlers are gone with the program.
+    pub fn reset_handlers(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let Action::Handle { .. } = *action {
+                *action = Action::Default;
+            }
+        }
+    }
+}
+
+impl Default for Signals {
+    fn default() -> Signals {
+        Signals::new()
+    }
+}
+
+/// Returns `true` if `signal` is a signal's number.
+pub fn is_valid(signal: u32) -> bool {
+    signal > 0 && signal < NSIG
+}
+
+/// Returns the exit status of a process ended by `signal`.
+pub fn exit_status(signal: u32) -> i32 {
+    128 + signal as i32
+}
+
+/// Makes `tf`, which returns to user mode, call the handler of `action` with
+/// `signal` instead: `tf` is saved below its stack pointer on the user
+/// stack, where `sigreturn()` finds it once the handler returns to its
+/// trampoline. Fails, changing nothing, if the stack cannot be written.
+pub fn enter_handler(tf: &mut TrapFrame, signal: u32, action: Action) -> Result<(), FaultError> {
+    let (handler, trampoline) = match action {
+        Action::Handle {
+            handler,
+            trampoline,
+        } => (handler, trampoline),
+        _ => return Ok(()),
+    };
+
+    let sp = (tf.sp as usize)
+        .checked_sub(size_of::<TrapFrame>())
+        .ok_or(FaultError::Unmapped)?
+        & !0xF;
+    let saved =
+        unsafe { slice::from_raw_parts(tf as *const _ as *const u8, size_of::<TrapFrame>()) };
+    VMM.write_user(sp, saved)?;
+
+    tf.elr = handler;
+    tf.sp = sp as u64;
+    tf.x[0] = signal as u64;
+    tf.x[30] = trampoline;
+    Ok(())
+}
+
+/// Restores the trap frame `enter_handler()` saved for the signal handler
+/// that returned with `tf`, in a `SYS_SIGRETURN` system call. Only the
+/// condition flags of the saved `SPSR_EL1` are restored, so that a program
+/// cannot raise its own privileges. Fails, changing nothing, if the saved
+/// frame cannot be read.
+pub fn sigreturn(tf: &mut TrapFrame) -> Result<(), FaultError> {
+    let mut saved = TrapFrame::default();
+    let bytes = unsafe {
+        slice::from_raw_parts_mut(&mut saved as *mut _ as *mut u8, size_of::<TrapFrame>())
+    };
+    VMM.read_user(tf.sp as usize, bytes)?;
+
+    saved.spsr = (saved.spsr & SPSR_NZCV) | (tf.spsr & !SPSR_NZCV);
+    *tf = saved;
+    Ok(())
+}
+
+/// Makes `id` the process `Ctrl-C` at the console sends `SIGINT`, or no
+/// process if it is `None`.
+pub fn set_foreground(id: Option<Id>) {
+    FOREGROUND.store(id.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Returns the process `Ctrl-C` at the console sends `SIGINT`, if any.
+pub fn foreground() -> Option<Id> {
+    match FOREGROUND.load(Ordering::Relaxed) {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use kernel_api::{SIGINT, SIGKILL, SIGTERM, SIGUSR1};
+
+    use super::{Action, Signals};
+
+    const HANDLER: Action = Action::Handle {
+        handler: 0x1000,
+        trampoline: 0x2000,
+    };
+
+    #[test]
+    fn pending_signals() {
+        let mut signals = Signals::new();
+        assert!(!signals.raise(0));
+        assert!(!signals.raise(32));
+        assert!(signals.raise(SIGTERM));
+        assert!(signals.raise(SIGINT));
+        assert!(signals.is_pending());
+
+        assert_eq!(signals.take(), Some((SIGINT, Action::Default)));
+        assert_eq!(signals.take(), Some((SIGTERM, Action::Default)));
+        assert_eq!(signals.take(), None);
+    }
+
+    #[test]
+    fn actions() {
+        let mut signals = Signals::new();
+        assert_eq!(signals.set_action(SIGINT, HANDLER), Some(Action::Default));
+        assert_eq!(signals.set_action(SIGKILL, Action::Ignore), None);
+
+        // ignored signals are never pending
+        signals.raise(SIGUSR1);
+        signals.set_action(SIGUSR1, Action::Ignore);
+        signals.raise(SIGUSR1);
+        assert!(!signals.is_pending());
+
+        let mut child = signals.forked();
+        signals.raise(SIGINT);
+        assert!(!child.is_pending());
+        child.reset_handlers();
+        assert_eq!(
+            child.set_action(SIGINT, Action::Default),
+            Some(Action::Default)
+        );
+        assert_eq!(
+            child.set_action(SIGUSR1, Action::Default),
+            Some(Action::Ignore)
+        );
+        assert_eq!(signals.take(), Some((SIGINT, HANDLER)));
+    }
+}
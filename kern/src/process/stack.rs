@@ -0,0 +1,51 @@
+use alloc::alloc::{alloc, dealloc};
+use core::alloc::Layout;
+use core::ops::Range;
+
+use crate::vm::{Attributes, PAGE_SIZE};
+use crate::VMM;
+
+/// A kernel stack on the heap, above a guard page that is left unmapped to
+/// catch the stack overflowing.
+pub struct Stack {
+    /// The start of the allocation: the guard page.
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// The stack is owned memory like a `Box`'s.
+unsafe impl Send for Stack {}
+
+impl Stack {
+    /// Allocates a stack of `size` bytes, a whole number of pages, and its
+    /// guard page. Returns `None` if there is not enough memory.
+    pub fn new(size: usize) -> Option<Stack> {
+        assert!(size % PAGE_SIZE == 0, "Stack::new: bad size {:#x}", size);
+
+        let layout = Layout::from_size_align(size + PAGE_SIZE, PAGE_SIZE).ok()?;
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            return None;
+        }
+
+        VMM.unmap(ptr as usize, PAGE_SIZE);
+        Some(Stack { ptr, layout })
+    }
+
+    /// Returns the address just past the stack: the initial stack pointer.
+    pub fn top(&self) -> usize {
+        self.ptr as usize + self.layout.size()
+    }
+
+    /// Returns the addresses of the guard page.
+    pub fn guard(&self) -> Range<usize> {
+        self.ptr as usize..self.ptr as usize + PAGE_SIZE
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        VMM.set_attributes(self.ptr as usize, PAGE_SIZE, Attributes::KERNEL_RAM);
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
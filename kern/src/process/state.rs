@@ -0,0 +1,44 @@
+use core::fmt;
+use core::time::Duration;
+
+use super::{Event, Id};
+
+/// What a process is doing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum State {
+    /// Waiting for its turn to run.
+    Ready,
+    /// Running on the processor.
+    Running,
+    /// Asleep since the uptime `since`, until the uptime `until`. How long
+    /// it slept is returned to it counted in `unit`s.
+    Sleeping {
+        since: Duration,
+        until: Duration,
+        unit: Duration,
+    },
+    /// Waiting for its child with the ID, or any child if `None`, to exit.
+    Waiting(Option<Id>),
+    /// Blocked until the event happens, or the uptime `until` if there is
+    /// one.
+    Blocked {
+        event: Event,
+        until: Option<Duration>,
+    },
+    /// Exited with the status, and not yet collected by its parent.
+    Zombie(i32),
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            State::Ready => "ready",
+            State::Running => "running",
+            State::Sleeping { .. } => "sleeping",
+            State::Waiting(_) => "waiting",
+            State::Blocked { .. } => "blocked",
+            State::Zombie(_) => "zombie",
+        };
+        f.pad(name)
+    }
+}
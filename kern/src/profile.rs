@@ -0,0 +1,213 @@
+//! A sampling profiler. While it runs, each core's performance monitors
+//! interrupt the core every `PERIOD` cycles, and the interrupt counts where
+//! the core was: in which `BUCKET_SIZE`-byte range of the kernel's code, in a
+//! user program, or elsewhere in the kernel.
+//!
+//! A core can only program its own performance monitors, so each core starts
+//! sampling at its next tick after `start()`, and stops at its next sample
+//! after `stop()`.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use pi::common::NCORES;
+
+use crate::aarch64::{self, PMCCFILTR_EL0, PMCCNTR_EL0, PMCNTENCLR_EL0, PMCNTENSET_EL0};
+use crate::aarch64::{PMCR_EL0, PMINTENCLR_EL1, PMINTENSET_EL1, PMOVSCLR_EL0};
+use crate::clock;
+use crate::irq::with_irqs_disabled;
+use crate::mutex::Mutex;
+use crate::traps::TrapFrame;
+use crate::IRQ;
+
+/// The number of processor cycles between samples: about a millisecond at
+/// the Pi 3's 1.2 GHz.
+pub const PERIOD: u64 = 1_200_000;
+
+/// The size of the ranges of code samples are counted in, in bytes.
+pub const BUCKET_SIZE: usize = 64;
+
+/// The mode bits of `SPSR_EL1`, which are zero for code at EL0.
+const SPSR_M: u64 = 0b1111;
+
+/// The samples of a run of the profiler.
+struct Profile {
+    /// The address of the kernel's code.
+    text: usize,
+    /// The number of samples in each `BUCKET_SIZE` bytes of the kernel's
+    /// code, in order.
+    hits: Vec<u64>,
+    /// The number of samples in user programs.
+    user: u64,
+    /// The number of samples in the kernel, outside its code.
+    elsewhere: u64,
+    /// The uptime the run started at.
+    started: Duration,
+    /// How long the run lasted, once it is over.
+    elapsed: Option<Duration>,
+}
+
+/// The samples of the running profiler, or of its last run. They are shared
+/// with the interrupt handler, so they are only touched with IRQs masked.
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+/// Whether the profiler runs.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether each core samples.
+static SAMPLING: [AtomicBool; NCORES] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+
+/// What the profiler counted, from `report()`.
+#[derive(Debug)]
+pub struct Report {
+    /// How long the profiler ran.
+    pub elapsed: Duration,
+    /// The number of samples.
+    pub total: u64,
+    /// The number of samples in user programs.
+    pub user: u64,
+    /// The number of samples in the kernel, outside its code.
+    pub elsewhere: u64,
+    /// The address of each `BUCKET_SIZE`-byte range of the kernel's code with
+    /// any samples, and their number, most first.
+    pub hot: Vec<(usize, u64)>,
+}
+
+/// Returns the start and end of the kernel's code.
+fn text() -> (usize, usize) {
+    extern "C" {
+        static __text_beg: u8;
+        static __rodata_beg: u8;
+    }
+
+    unsafe {
+        (
+            &__text_beg as *const u8 as usize,
+            &__rodata_beg as *const u8 as usize,
+        )
+    }
+}
+
+/// Starts the profiler, forgetting the samples of its last run. Returns
+/// `false` if it already runs.
+pub fn start() -> bool {
+    if RUNNING.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    let (text, end) = text();
+    let profile = Profile {
+        text,
+        hits: vec![0; (end - text + BUCKET_SIZE - 1) / BUCKET_SIZE],
+        user: 0,
+        elsewhere: 0,
+        started: clock::uptime(),
+        elapsed: None,
+    };
+    with_irqs_disabled(|| *PROFILE.lock() = Some(profile));
+    for core in 0..NCORES {
+        IRQ.register_core_pmu(core, Box::new(sample));
+    }
+    RUNNING.store(true, Ordering::Relaxed);
+    true
+}
+
+/// Stops the profiler, keeping its samples. Returns `false` if it was not
+/// running.
+pub fn stop() -> bool {
+    if !RUNNING.swap(false, Ordering::Relaxed) {
+        return false;
+    }
+
+    with_irqs_disabled(|| {
+        if let Some(ref mut profile) = *PROFILE.lock() {
+            profile.elapsed = Some(clock::uptime() - profile.started);
+        }
+    });
+    true
+}
+
+/// Returns `true` if the profiler runs.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Returns what the profiler counted so far, or `None` if it never ran.
+pub fn report() -> Option<Report> {
+    with_irqs_disabled(|| {
+        let profile = PROFILE.lock();
+        let profile = profile.as_ref()?;
+        let mut hot: Vec<(usize, u64)> = profile
+            .hits
+            .iter()
+            .enumerate()
+            .filter(|&(_, &hits)| hits > 0)
+            .map(|(i, &hits)| (profile.text + i * BUCKET_SIZE, hits))
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let code: u64 = hot.iter().map(|&(_, hits)| hits).sum();
+        Some(Report {
+            elapsed: profile
+                .elapsed
+                .unwrap_or_else(|| clock::uptime() - profile.started),
+            total: code + profile.user + profile.elsewhere,
+            user: profile.user,
+            elsewhere: profile.elsewhere,
+            hot,
+        })
+    })
+}
+
+/// Starts this core sampling if the profiler runs and it does not yet. Each
+/// core's tick calls this.
+pub fn tick() {
+    let core = aarch64::affinity();
+    if RUNNING.load(Ordering::Relaxed) && !SAMPLING[core].swap(true, Ordering::Relaxed) {
+        unsafe {
+            // Count cycles at EL0 and EL1 alike, overflowing at 32 bits.
+            PMCCFILTR_EL0::write(0);
+            PMCR_EL0::modify(|pmcr| (pmcr | PMCR_EL0::E) & !PMCR_EL0::LC);
+            PMCCNTR_EL0::write((1 << 32) - PERIOD);
+            PMOVSCLR_EL0::write(PMCNTENSET_EL0::C);
+            PMINTENSET_EL1::write(PMCNTENSET_EL0::C);
+            PMCNTENSET_EL0::write(PMCNTENSET_EL0::C);
+        }
+    }
+}
+
+/// Counts a sample of the code interrupted at `tf`, then makes this core's
+/// cycle counter overflow again after `PERIOD` more cycles, or stops this
+/// core sampling if the profiler was stopped.
+fn sample(tf: &mut TrapFrame) {
+    unsafe { PMOVSCLR_EL0::write(PMCNTENSET_EL0::C) };
+    if !RUNNING.load(Ordering::Relaxed) {
+        unsafe {
+            PMINTENCLR_EL1::write(PMCNTENSET_EL0::C);
+            PMCNTENCLR_EL0::write(PMCNTENSET_EL0::C);
+        }
+        SAMPLING[aarch64::affinity()].store(false, Ordering::Relaxed);
+        return;
+    }
+
+    if let Some(ref mut profile) = *PROFILE.lock() {
+        let pc = tf.elr as usize;
+        let bucket = pc.wrapping_sub(profile.text) / BUCKET_SIZE;
+        if tf.spsr & SPSR_M == 0 {
+            profile.user += 1;
+        } else if let Some(hits) = profile.hits.get_mut(bucket) {
+            *hits += 1;
+        } else {
+            profile.elsewhere += 1;
+        }
+    }
+    unsafe { PMCCNTR_EL0::write((1 << 32) - PERIOD) };
+}
@@ -0,0 +1,120 @@
+use core::fmt;
+use shim::io;
+
+use crate::console::{self, Sink};
+
+/// Semihosting operation numbers (ARM DUI 0471, chapter 8).
+mod op {
+    pub const SYS_WRITE0: u64 = 0x04;
+    pub const SYS_EXIT: u64 = 0x18;
+}
+
+/// `ADP_Stopped_ApplicationExit`: the reason code reported to `SYS_EXIT` for a
+/// normal program exit.
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+/// The size of the buffer used to NUL-terminate strings for `SYS_WRITE0`.
+const WRITE_CHUNK_SIZE: usize = 128;
+
+/// Issues the semihosting call `op` with the parameter `param` and returns the
+/// host's result.
+///
+/// Semihosting calls are serviced by QEMU only when it is started with
+/// `-semihosting`. On real hardware with no debugger attached, `hlt` traps as
+/// an undefined instruction.
+///
+/// # Safety
+///
+/// `param` must be valid for the operation `op`. For most operations, it is
+/// the address of a parameter block that must remain valid for the duration
+/// of the call.
+#[inline(always)]
+unsafe fn call(op: u64, param: u64) -> u64 {
+    #[cfg(target_os = "none")]
+    {
+        let ret: u64;
+        core::arch::asm!(
+            "hlt #0xf000",
+            inout("x0") op => ret,
+            in("x1") param,
+            options(nostack)
+        );
+        ret
+    }
+
+    #[cfg(not(target_os = "none"))]
+    {
+        let _ = (op, param);
+        unimplemented!("semihosting is unavailable in host tests")
+    }
+}
+
+/// Writes `bytes` to the host's console.
+pub fn write_bytes(bytes: &[u8]) {
+    // SYS_WRITE0 takes a NUL-terminated string, so copy the input into a
+    // terminated buffer one chunk at a time.
+    let mut chunk = [0u8; WRITE_CHUNK_SIZE + 1];
+    for part in bytes.chunks(WRITE_CHUNK_SIZE) {
+        chunk[..part.len()].copy_from_slice(part);
+        chunk[part.len()] = 0;
+        unsafe {
+            call(op::SYS_WRITE0, chunk.as_ptr() as u64);
+        }
+    }
+}
+
+/// Terminates the emulator, reporting `code` as its exit status. This is how
+/// in-kernel tests report their result when run headlessly under QEMU.
+pub fn exit(code: u32) -> ! {
+    let block: [u64; 2] = [ADP_STOPPED_APPLICATION_EXIT, code as u64];
+    unsafe {
+        call(op::SYS_EXIT, block.as_ptr() as u64);
+    }
+
+    // A host that doesn't support exiting returns here; there's nothing left
+    // to do but stop.
+    loop {}
+}
+
+/// Terminates the emulator with a zero exit status if `passed` is `true` and
+/// with a status of `1` otherwise.
+pub fn report(passed: bool) -> ! {
+    exit(if passed { 0 } else { 1 })
+}
+
+/// Has the console copy its output to the host's console. The host must
+/// service semihosting calls, or the first write traps.
+pub fn initialize() -> Result<(), &'static str> {
+    // `write_bytes()` neither waits nor allocates, so it serves both ways.
+    let sink = Sink {
+        write: write_bytes,
+        write_nolock: write_bytes,
+    };
+    if !console::add_sink(sink) {
+        return Err("no room for another console sink");
+    }
+    Ok(())
+}
+
+/// A writer which writes to the host through semihosting. Unlike the
+/// UART, it needs no initialization and works under QEMU's `-nographic`.
+#[derive(Debug, Default)]
+pub struct HostConsole;
+
+impl fmt::Write for HostConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl io::Write for HostConsole {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,143 +1,265 @@
+mod bench;
+mod builtins;
+mod command;
+mod complete;
+mod fs;
+mod gpio;
+mod hexdump;
+mod install;
+mod mem;
+mod net;
+mod pipe;
+mod proc;
+mod run;
+mod sys;
+
 use shim::io;
 use shim::path::{Path, PathBuf};
 
-use stack_vec::StackVec;
+use fat32::traits::{File, FileSystem};
 
-use pi::atags::Atags;
+use crate::console::{kprintln, ConsoleWriter, History, LineEditor};
+use crate::FILESYSTEM;
 
-use fat32::traits::FileSystem;
-use fat32::traits::{Dir, Entry};
+use self::command::{Command, Error};
+use self::complete::ShellCompleter;
+use self::pipe::Stage;
 
-use crate::console::{kprint, kprintln, CONSOLE};
-use crate::ALLOCATOR;
-use crate::FILESYSTEM;
+/// The maximum number of bytes that can fit in a command
+const MAX_COMMAND_LEN: usize = 512;
+/// The max number of arguments that a command can take
+const MAX_ARGUMENTS: usize = 64;
+/// The largest script `sh` will run.
+const MAX_SCRIPT_SIZE: u64 = 64 << 10;
+/// The script run at boot, if it exists.
+const RC_PATH: &str = "/etc/rc";
 
-/// Error type for `Command` parse failures.
-#[derive(Debug)]
-enum Error {
-    Empty,
-    TooManyArgs,
+/// State belonging to a single running shell.
+pub struct Shell {
+    /// The absolute path that relative paths are resolved against.
+    cwd: PathBuf,
+    /// The status passed to `exit`, once it has been run.
+    exit: Option<i32>,
 }
 
-/// A structure representing a single shell command.
-struct Command<'v, 's> {
-    args: StackVec<'v, &'s str>,
-}
+impl Shell {
+    /// Returns a new shell whose working directory is `cwd`.
+    fn new(cwd: PathBuf) -> Shell {
+        Shell { cwd, exit: None }
+    }
+
+    /// Reads and runs lines from the console, prompting with `prefix`, until
+    /// `exit` is run. Returns the status passed to `exit`.
+    fn interact(&mut self, prefix: &str) -> i32 {
+        // Each visible character entered will be buffered here
+        let mut input_buf = [0u8; MAX_COMMAND_LEN];
+        let mut history = History::new();
+
+        loop {
+            proc::reap_finished();
 
-impl<'v, 's> Command<'v, 's> {
-    /// Parse a command from a string `s` using `buf` as storage for the
-    /// arguments.
+            // The completer completes against the working directory as it is
+            // now, so it and the editor are made afresh for each line.
+            let mut completer = ShellCompleter::new(self.cwd.clone());
+            let mut editor = LineEditor::new(&mut input_buf);
+            editor.set_history(&mut history);
+            editor.set_completer(&mut completer);
+            editor.set_prompt(prefix);
+            match editor.read_line() {
+                Ok(line) => {
+                    self.run(line);
+                }
+                Err(e) => kprintln!("Error parsing input: {}", e),
+            }
+
+            if let Some(status) = self.exit {
+                return status;
+            }
+        }
+    }
+
+    /// Parses and runs the pipeline `line`, reporting any parse errors.
     ///
-    /// # Errors
+    /// Each command's output is the next command's input. The commands run
+    /// one after another, so each command's output is buffered in full, up to
+    /// `PIPE_BUF_SIZE` bytes. The first command's input is empty. The last
+    /// command's output goes to the console or, if it was redirected, to the
+    /// named file. A failing command stops the pipeline, as does `exit`.
     ///
-    /// If `s` contains no arguments, returns `Error::Empty`. If there are more
-    /// arguments than `buf` can hold, returns `Error::TooManyArgs`.
-    fn parse(s: &'s str, buf: &'v mut [&'s str]) -> Result<Command<'v, 's>, Error> {
-        let mut args = StackVec::new(buf);
-        for arg in s.split(' ').filter(|a| !a.is_empty()) {
-            args.push(arg).map_err(|_| Error::TooManyArgs)?;
-        }
+    /// Returns `true` if the line parsed and every command ran successfully.
+    fn run(&mut self, line: &str) -> bool {
+        // Set aside some memory to hold the argument strings of one command.
+        // Each command is parsed into it in turn; the argument list borrowing
+        // it must not outlive the command, so it is remade for each one.
+        let mut arg_buf = [0u8; MAX_COMMAND_LEN + 1];
 
-        if args.is_empty() {
-            return Err(Error::Empty);
+        // Check every command before running any of them.
+        let count = command::stages(line).count();
+        for (i, stage) in command::stages(line).enumerate() {
+            let mut command_buf = [""; MAX_ARGUMENTS];
+            match Command::parse(stage, &mut arg_buf, &mut command_buf) {
+                Ok(ref command) if command.redirect.is_some() && i + 1 < count => {
+                    kprintln!("Error: only the last command in a pipeline can redirect its output");
+                    return false;
+                }
+                Ok(_) => (),
+                Err(Error::Empty) if count == 1 => return true,
+                Err(Error::Empty) => {
+                    kprintln!("Error: empty command in pipeline");
+                    return false;
+                }
+                Err(e) => {
+                    report_parse_error(e);
+                    return false;
+                }
+            }
         }
 
-        Ok(Command { args })
-    }
+        pipe::run_stages(count, |i, input, output| {
+            let mut command_buf = [""; MAX_ARGUMENTS];
+            let stage = command::stages(line).nth(i).unwrap_or("");
+            let command = match Command::parse(stage, &mut arg_buf, &mut command_buf) {
+                Ok(command) => command,
+                Err(_) => return Stage::Failed,
+            };
 
-    /// Returns this command's path. This is equivalent to the first argument.
-    fn path(&self) -> &str {
-        self.args[0]
+            let output = output.map(|output| output as &mut dyn io::Write);
+            if !self.execute(&command, input, output) {
+                Stage::Failed
+            } else if self.exit.is_some() {
+                Stage::Exited
+            } else {
+                Stage::Ran
+            }
+        })
     }
-}
-
-/// The maximum number of bytes that can fit in a command
-const MAX_COMMAND_LEN: usize = 512;
-/// The max number of arguments that a command can take
-const MAX_ARGUMENTS: usize = 64;
 
-/// Starts a shell using `prefix` as the prefix for each line. This function
-/// never returns.
-pub fn shell(prefix: &str) -> ! {
-    // Each visible character entered will be buffered here
-    let mut input_buf = [0u8; MAX_COMMAND_LEN];
+    /// Runs each line of the script at the absolute path `path` as if it had
+    /// been typed, skipping blank lines and `#` comments. Returns the status
+    /// passed to `exit` or, if the script ends without running it, `0`.
+    ///
+    /// If `stop_on_error` is `true`, the first line that fails ends the
+    /// script with status `1`.
+    fn run_script(&mut self, path: &Path, stop_on_error: bool) -> io::Result<i32> {
+        let bytes = fs::read_file(path, MAX_SCRIPT_SIZE)?;
+        let script = core::str::from_utf8(&bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "script is not UTF-8"))?;
 
-    loop {
-        // Set aside some memory to hold the argument strings the user enters.
-        // We need to reset this every loop, or else there will be dangling
-        // references to input_buf  left on every run through.
-        let mut command_buf = [""; MAX_ARGUMENTS];
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
 
-        kprint!("{} ", prefix);
+            let succeeded = self.run(line);
+            if let Some(status) = self.exit {
+                return Ok(status);
+            } else if !succeeded && stop_on_error {
+                kprintln!("{}:{}: stopping after error", path.display(), number + 1);
+                return Ok(1);
+            }
+        }
+        Ok(0)
+    }
 
-        let input = match read_next_line(&mut input_buf) {
-            Ok(s) => s,
-            Err(e) => {
-                kprintln!("Error parsing input: {}", e);
-                continue;
+    /// Looks up and runs `command`, reporting unknown commands, bad argument
+    /// counts and errors. The command reads from `input` and writes to
+    /// `output` or, if that is `None`, to the console or the file its output
+    /// was redirected to. Returns `true` if the command ran successfully.
+    fn execute(
+        &mut self,
+        command: &Command,
+        input: &mut dyn io::Read,
+        output: Option<&mut dyn io::Write>,
+    ) -> bool {
+        let builtin = match builtins::find(command.path()) {
+            Some(builtin) => builtin,
+            None => {
+                kprintln!("unknown command: {}", command.path());
+                return false;
             }
         };
 
-        let command = match Command::parse(input, &mut command_buf) {
-            Ok(command) => command,
-            Err(e) => {
-                match e {
-                    Error::Empty => (),
-                    Error::TooManyArgs => {
-                        kprintln!("Error: too many arguments");
-                    }
-                };
-                continue;
-            }
+        if !builtin.accepts(command.params().len()) {
+            kprintln!("usage: {}", builtin.usage);
+            return false;
+        }
+
+        let result = match (output, &command.redirect) {
+            (Some(output), _) => (builtin.handler)(self, command, input, output),
+            (None, None) => (builtin.handler)(self, command, input, &mut ConsoleWriter),
+            (None, Some(redirect)) => match fs::open_redirect(&self.cwd, redirect) {
+                Ok(mut file) => {
+                    let result = (builtin.handler)(self, command, input, &mut file);
+                    result.and_then(|_| file.sync().map_err(builtins::Error::from))
+                }
+                Err(e) => {
+                    kprintln!("{}: {}", redirect.path, e);
+                    return false;
+                }
+            },
         };
 
-        match command.path() {
-            "echo" => {
-                echo(command);
+        match result {
+            Ok(()) => true,
+            Err(builtins::Error::Usage) => {
+                kprintln!("usage: {}", builtin.usage);
+                false
             }
-            s => {
-                kprintln!("unknown command: {}", s);
+            Err(builtins::Error::Reported) => false,
+            Err(e) => {
+                kprintln!("{}: {}", builtin.name, e);
+                false
             }
-        };
+        }
     }
 }
 
-/// Reads the next line of input into a `str`, using the provided buffer as storage.
-fn read_next_line(buf: &mut [u8]) -> Result<&str, core::str::Utf8Error> {
-    let mut input = StackVec::new(buf);
-
-    loop {
-        let next_byte = CONSOLE.lock().read_byte();
-
-        // we check is_full and is_empty in the conditionals so we always fall back on the bell
-        if (next_byte.is_ascii_graphic() || next_byte == b' ') && !input.is_full() {
-            kprint!("{}", next_byte as char);
-            input.push(next_byte).unwrap();
-        } else if next_byte == b'\r' || next_byte == b'\n' {
-            kprintln!("");
-            break;
-        } else if (next_byte == 8 || next_byte == 127) && !input.is_empty() {
-            kprint!("\u{8} \u{8}"); // remove from the screen
-            let _ = input.pop();
-        } else {
-            kprint!("\u{7}");
+/// Prints a description of the command parse error `error`.
+fn report_parse_error(error: Error) {
+    match error {
+        Error::Empty => (),
+        Error::TooManyArgs => {
+            kprintln!("Error: too many arguments");
         }
-    }
-
-    core::str::from_utf8(input.into_slice())
+        Error::TooLong => {
+            kprintln!("Error: command too long");
+        }
+        Error::UnterminatedQuote(quote) => {
+            kprintln!("Error: unterminated {} quote", quote);
+        }
+        Error::DanglingEscape => {
+            kprintln!("Error: nothing to escape after trailing \\");
+        }
+        Error::MissingRedirectTarget => {
+            kprintln!("Error: expected a file name after > or >>");
+        }
+        Error::DuplicateRedirect => {
+            kprintln!("Error: output can only be redirected once");
+        }
+    };
 }
 
-/// A simple echo program, printing arguments passed into the program.
+/// Starts a shell using `prefix` as the prefix for each line. Returns the
+/// status passed to `exit` once it is run.
 ///
-/// Eventually this will be a separate binary, but for now the kernel can't handle that.
-fn echo(command: Command) {
-    // every word but the first will get a leading space
-    let mut use_leading_space = false;
-    for arg in &command.args[1..] {
-        let prefix = if use_leading_space { " " } else { "" };
-        kprint!("{}{}", prefix, arg);
-        use_leading_space = true;
+/// Shells are independent of one another, so one can be started from
+/// anywhere, including from within another shell.
+pub fn shell(prefix: &str) -> i32 {
+    Shell::new(PathBuf::from("/")).interact(prefix)
+}
+
+/// Runs the boot script, `/etc/rc`, if there is one. The script's commands
+/// run in a shell of their own, in the root directory.
+pub fn run_rc() {
+    let path = Path::new(RC_PATH);
+    if FILESYSTEM.open_file(path).is_err() {
+        return;
     }
 
-    kprintln!("");
+    match Shell::new(PathBuf::from("/")).run_script(path, false) {
+        Ok(0) => (),
+        Ok(status) => kprintln!("{}: exited with status {}", RC_PATH, status),
+        Err(e) => kprintln!("{}: {}", RC_PATH, e),
+    }
 }
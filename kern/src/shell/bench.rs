@@ -0,0 +1,265 @@
+use alloc::alloc::{alloc, dealloc};
+use alloc::vec;
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr;
+use core::time::Duration;
+
+use shim::io::{self, Seek, SeekFrom};
+
+use fat32::traits::{File, FileSystem};
+use pi::timer::Stopwatch;
+
+use crate::console::{kprint, kprintln};
+use crate::FILESYSTEM;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::fs::resolve;
+use super::mem::parse_number;
+use super::Shell;
+
+/// The usage message for `bench`.
+const USAGE: &str = "usage: bench mem [size]\n       \
+                     bench alloc [count]\n       \
+                     bench sd <file>\n       \
+                     bench uart [size]";
+
+/// The default size of the buffers filled and copied by `bench mem`.
+const MEM_DEFAULT_SIZE: usize = 1 << 20;
+/// The number of times `bench mem` fills and copies its buffers.
+const MEM_ROUNDS: usize = 16;
+
+/// The default number of allocations made by `bench alloc`.
+const ALLOC_DEFAULT_COUNT: usize = 10_000;
+/// The sizes `bench alloc` cycles through.
+const ALLOC_SIZES: [usize; 6] = [16, 64, 128, 512, 1024, 4096];
+
+/// The size of each read `bench sd` makes when reading sequentially.
+const SD_CHUNK_SIZE: usize = 4096;
+/// The size of each read `bench sd` makes when reading randomly: a sector.
+const SD_RANDOM_READ_SIZE: usize = 512;
+/// The number of random reads `bench sd` makes.
+const SD_RANDOM_READS: usize = 64;
+
+/// The default number of bytes `bench uart` writes.
+const UART_DEFAULT_SIZE: usize = 4096;
+/// The text `bench uart` writes, one line at a time.
+const UART_LINE: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ\r\n";
+
+/// Measures the performance of memory, the allocator, the SD card or the
+/// console.
+pub fn bench(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    let number = |default: usize| match params.get(1) {
+        Some(arg) => match parse_number(arg).filter(|&n| n > 0) {
+            Some(n) => Ok(n),
+            None => fail!("invalid number: {}", arg),
+        },
+        None => Ok(default),
+    };
+
+    match (params[0], params.len()) {
+        ("mem", 1..=2) => bench_mem(number(MEM_DEFAULT_SIZE)?, out)?,
+        ("alloc", 1..=2) => bench_alloc(number(ALLOC_DEFAULT_COUNT)?, out)?,
+        ("sd", 2) => bench_sd(shell, params[1], out)?,
+        ("uart", 1..=2) => bench_uart(number(UART_DEFAULT_SIZE)?, out)?,
+        _ => {
+            kprintln!("{}", USAGE);
+            return Err(Error::Reported);
+        }
+    }
+    Ok(())
+}
+
+/// Times filling and copying buffers of `size` bytes.
+fn bench_mem(size: usize, out: &mut dyn io::Write) -> io::Result<()> {
+    let mut src = vec![0u8; size];
+    let mut dst = vec![0u8; size];
+    let total = (size * MEM_ROUNDS) as u64;
+
+    let mut stopwatch = Stopwatch::start();
+    for round in 0..MEM_ROUNDS {
+        unsafe { ptr::write_bytes(src.as_mut_ptr(), round as u8, size) };
+    }
+    report(out, "mem fill", total, stopwatch.restart())?;
+
+    for _ in 0..MEM_ROUNDS {
+        dst.copy_from_slice(&src);
+    }
+    let elapsed = stopwatch.elapsed();
+
+    // Read the result so that the copies cannot be optimized away.
+    let _ = unsafe { ptr::read_volatile(&dst[size - 1]) };
+    report(out, "mem copy", total, elapsed)
+}
+
+/// Times `count` allocations and deallocations of assorted sizes.
+fn bench_alloc(count: usize, out: &mut dyn io::Write) -> Result<(), Error> {
+    let stopwatch = Stopwatch::start();
+    for i in 0..count {
+        let size = ALLOC_SIZES[i % ALLOC_SIZES.len()];
+        let layout = Layout::from_size_align(size, 16).unwrap();
+        unsafe {
+            let ptr = alloc(layout);
+            if ptr.is_null() {
+                fail!("allocation failed after {} allocations", i);
+            }
+            dealloc(ptr, layout);
+        }
+    }
+
+    let elapsed = stopwatch.elapsed();
+    writeln!(
+        out,
+        "alloc: {} allocations in {} ({} per second)",
+        count,
+        Elapsed(elapsed),
+        per_second(count as u64, elapsed)
+    )?;
+    Ok(())
+}
+
+/// Times reading the file at `path` sequentially and at random offsets.
+fn bench_sd(shell: &Shell, path: &str, out: &mut dyn io::Write) -> Result<(), Error> {
+    let path = resolve(&shell.cwd, path);
+    let mut file = match FILESYSTEM.open_file(&path) {
+        Ok(file) => file,
+        Err(e) => fail!("{}: {}", path.display(), e),
+    };
+
+    let mut buf = [0u8; SD_CHUNK_SIZE];
+    let mut total = 0;
+    let stopwatch = Stopwatch::start();
+    loop {
+        match io::Read::read(&mut file, &mut buf)? {
+            0 => break,
+            n => total += n as u64,
+        }
+    }
+    report(out, "sd sequential", total, stopwatch.elapsed())?;
+
+    let span = file.size().saturating_sub(SD_RANDOM_READ_SIZE as u64);
+    let mut rng = XorShift::new(pi::timer::current_time().as_micros() as u64);
+    let stopwatch = Stopwatch::start();
+    for _ in 0..SD_RANDOM_READS {
+        file.seek(SeekFrom::Start(rng.next() % (span + 1)))?;
+        io::Read::read_exact(&mut file, &mut buf[..SD_RANDOM_READ_SIZE])?;
+    }
+    let total = (SD_RANDOM_READS * SD_RANDOM_READ_SIZE) as u64;
+    report(out, "sd random", total, stopwatch.elapsed())?;
+    Ok(())
+}
+
+/// Times writing `size` bytes of text to the console.
+fn bench_uart(size: usize, out: &mut dyn io::Write) -> io::Result<()> {
+    let stopwatch = Stopwatch::start();
+    let mut written = 0;
+    while written < size {
+        let n = UART_LINE.len().min(size - written);
+        let line = core::str::from_utf8(&UART_LINE[..n]).unwrap();
+        kprint!("{}", line);
+        written += n;
+    }
+
+    let elapsed = stopwatch.elapsed();
+    kprintln!();
+    report(out, "uart write", written as u64, elapsed)
+}
+
+/// Writes the result of moving `bytes` bytes in `elapsed` time.
+fn report(out: &mut dyn io::Write, name: &str, bytes: u64, elapsed: Duration) -> io::Result<()> {
+    writeln!(
+        out,
+        "{}: {} bytes in {} ({}/s)",
+        name,
+        bytes,
+        Elapsed(elapsed),
+        ByteRate(per_second(bytes, elapsed))
+    )
+}
+
+/// Returns how many of `amount` happened per second, given that they took
+/// `elapsed` time.
+fn per_second(amount: u64, elapsed: Duration) -> u64 {
+    let micros = elapsed.as_micros().max(1);
+    (amount as u128 * 1_000_000 / micros) as u64
+}
+
+/// A duration, formatted in milliseconds with microsecond precision.
+struct Elapsed(Duration);
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let micros = self.0.as_micros();
+        write!(f, "{}.{:03} ms", micros / 1000, micros % 1000)
+    }
+}
+
+/// A number of bytes per second, formatted in the largest unit that keeps
+/// the value at least one, with two decimal places.
+struct ByteRate(u64);
+
+impl fmt::Display for ByteRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 >= 1 << (10 * (unit + 1)) {
+            unit += 1;
+        }
+
+        let hundredths = self.0 as u128 * 100 >> (10 * unit);
+        write!(
+            f,
+            "{}.{:02} {}",
+            hundredths / 100,
+            hundredths % 100,
+            UNITS[unit]
+        )
+    }
+}
+
+/// A xorshift pseudo-random number generator, for picking read offsets.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        XorShift(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{per_second, ByteRate, Elapsed};
+    use core::time::Duration;
+
+    #[test]
+    fn rates() {
+        assert_eq!(per_second(1000, Duration::from_millis(500)), 2000);
+        assert_eq!(per_second(5, Duration::from_secs(0)), 5_000_000);
+
+        assert_eq!(ByteRate(512).to_string(), "512.00 B");
+        assert_eq!(ByteRate(1536).to_string(), "1.50 KiB");
+        assert_eq!(ByteRate(3 << 20).to_string(), "3.00 MiB");
+        assert_eq!(ByteRate(5 << 30).to_string(), "5.00 GiB");
+
+        assert_eq!(
+            Elapsed(Duration::from_micros(12_345)).to_string(),
+            "12.345 ms"
+        );
+        assert_eq!(Elapsed(Duration::from_micros(7)).to_string(), "0.007 ms");
+    }
+}
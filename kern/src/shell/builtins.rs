@@ -0,0 +1,555 @@
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+use shim::io;
+
+use super::bench;
+use super::command::Command;
+use super::fs;
+use super::gpio;
+use super::hexdump;
+use super::install;
+use super::mem;
+use super::net;
+use super::proc;
+use super::run;
+use super::sys;
+use super::Shell;
+
+/// The number of lines `head` prints when no count is given.
+const HEAD_DEFAULT_LINES: usize = 10;
+/// The number of bytes `head` reads from its input at a time.
+const HEAD_CHUNK_SIZE: usize = 512;
+
+/// Commands that may be invoked with a width suffix, as in `peek.b`. Their
+/// handlers read the suffix from the command's path.
+const WIDTH_SUFFIXED: &[&str] = &["peek", "poke"];
+
+/// The signature of a built-in command's implementation.
+///
+/// A handler reads its input from the first stream it is given, which is the
+/// output of the previous command in a pipeline, and writes its output to the
+/// second, which is the console unless the output is piped or redirected. If
+/// the command fails, the handler returns why, to be reported by the shell.
+pub type Handler =
+    fn(&mut Shell, &Command, &mut dyn io::Read, &mut dyn io::Write) -> Result<(), Error>;
+
+/// Why a built-in command failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments are not ones the command takes. The shell shows the
+    /// command's usage.
+    Usage,
+    /// The command failed for the reason given.
+    Failed(String),
+    /// The command failed, and has already reported why.
+    Reported,
+    /// Reading the command's input or writing its output failed.
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage => f.write_str("invalid arguments"),
+            Error::Failed(reason) => f.write_str(reason),
+            Error::Reported => f.write_str("failed"),
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Returns from a handler with `Error::Failed`, its reason formatted from the
+/// arguments as by `format!`.
+pub macro fail($($arg:tt)*) {
+    return Err(Error::Failed(format!($($arg)*)))
+}
+
+/// A command built into the shell.
+pub struct Builtin {
+    /// The name used to invoke the command.
+    pub name: &'static str,
+    /// A synopsis of the command's arguments, shown by `help`.
+    pub usage: &'static str,
+    /// A one-line description of what the command does, shown by `help`.
+    pub help: &'static str,
+    /// The minimum number of arguments, not counting the command's name.
+    pub min_args: usize,
+    /// The maximum number of arguments, not counting the command's name.
+    pub max_args: usize,
+    /// The function implementing the command.
+    pub handler: Handler,
+}
+
+impl Builtin {
+    /// Returns `true` if `n` arguments is an acceptable number for this
+    /// command.
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.min_args && n <= self.max_args
+    }
+}
+
+/// Every command built into the shell. To add a command, write its handler
+/// and add an entry here.
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "echo",
+        usage: "echo [args...]",
+        help: "print the arguments, separated by spaces",
+        min_args: 0,
+        max_args: usize::max_value(),
+        handler: echo,
+    },
+    Builtin {
+        name: "ls",
+        usage: "ls [-a] [directory]",
+        help: "list a directory's entries; -a includes hidden entries",
+        min_args: 0,
+        max_args: 2,
+        handler: fs::ls,
+    },
+    Builtin {
+        name: "cat",
+        usage: "cat [file]...",
+        help: "print the contents of files, or copy the input if none are given",
+        min_args: 0,
+        max_args: usize::max_value(),
+        handler: fs::cat,
+    },
+    Builtin {
+        name: "head",
+        usage: "head [-n lines]",
+        help: "print the first lines of the input (default: 10)",
+        min_args: 0,
+        max_args: 2,
+        handler: head,
+    },
+    Builtin {
+        name: "hexdump",
+        usage: "hexdump [<file|address> [length]]",
+        help: "dump a file, memory at a 0x address, or the input in hex",
+        min_args: 0,
+        max_args: 2,
+        handler: hexdump::hexdump,
+    },
+    Builtin {
+        name: "peek",
+        usage: "peek[.b|.h|.w|.d] <address> [count]",
+        help: "read values from physical memory or device registers",
+        min_args: 1,
+        max_args: 2,
+        handler: mem::peek,
+    },
+    Builtin {
+        name: "poke",
+        usage: "poke[.b|.h|.w|.d] <address> <value>",
+        help: "write a value to physical memory or a device register",
+        min_args: 2,
+        max_args: 2,
+        handler: mem::poke,
+    },
+    Builtin {
+        name: "meminfo",
+        usage: "meminfo",
+        help: "show heap usage and physical memory regions",
+        min_args: 0,
+        max_args: 0,
+        handler: mem::meminfo,
+    },
+    Builtin {
+        name: "vmmap",
+        usage: "vmmap [pid]",
+        help: "show the virtual memory mappings of the installed translation tables",
+        min_args: 0,
+        max_args: 1,
+        handler: mem::vmmap,
+    },
+    Builtin {
+        name: "lsatag",
+        usage: "lsatag",
+        help: "list the boot tags passed by the firmware",
+        min_args: 0,
+        max_args: 0,
+        handler: sys::lsatag,
+    },
+    Builtin {
+        name: "uptime",
+        usage: "uptime",
+        help: "print the time since boot",
+        min_args: 0,
+        max_args: 0,
+        handler: sys::uptime,
+    },
+    Builtin {
+        name: "dmesg",
+        usage: "dmesg",
+        help: "print the kernel's log: the latest console output",
+        min_args: 0,
+        max_args: 0,
+        handler: sys::dmesg,
+    },
+    Builtin {
+        name: "irqstat",
+        usage: "irqstat [reset]",
+        help: "print how often each exception and interrupt happened, or reset the counts",
+        min_args: 0,
+        max_args: 1,
+        handler: sys::irqstat,
+    },
+    Builtin {
+        name: "date",
+        usage: "date [YYYY-MM-DD HH:MM:SS]",
+        help: "print the date and time (UTC), or set the clock",
+        min_args: 0,
+        max_args: 2,
+        handler: sys::date,
+    },
+    Builtin {
+        name: "bench",
+        usage: "bench mem|alloc|sd|uart [arg]",
+        help: "measure memory, allocator, SD card or console performance",
+        min_args: 1,
+        max_args: 2,
+        handler: bench::bench,
+    },
+    Builtin {
+        name: "profile",
+        usage: "profile start|stop|report [count]",
+        help: "sample where the cores spend their time, and show the hottest code",
+        min_args: 1,
+        max_args: 2,
+        handler: sys::profile,
+    },
+    Builtin {
+        name: "sleep",
+        usage: "sleep <ms>",
+        help: "wait for the given number of milliseconds",
+        min_args: 1,
+        max_args: 1,
+        handler: sys::sleep,
+    },
+    Builtin {
+        name: "exec",
+        usage: "exec <program> [args...]",
+        help: "run an ELF executable in user mode and wait for it to exit",
+        min_args: 1,
+        max_args: usize::max_value(),
+        handler: proc::exec,
+    },
+    Builtin {
+        name: "ps",
+        usage: "ps",
+        help: "list the processes",
+        min_args: 0,
+        max_args: 0,
+        handler: proc::ps,
+    },
+    Builtin {
+        name: "top",
+        usage: "top [seconds]",
+        help: "measure how busy the cores and processes are (default: 1 s)",
+        min_args: 0,
+        max_args: 1,
+        handler: proc::top,
+    },
+    Builtin {
+        name: "spin",
+        usage: "spin [seconds]",
+        help: "start a background process that spins without yielding (default: 10 s)",
+        min_args: 0,
+        max_args: 1,
+        handler: proc::spin,
+    },
+    Builtin {
+        name: "wait",
+        usage: "wait [pid]",
+        help: "wait for a background process to exit and print its status",
+        min_args: 0,
+        max_args: 1,
+        handler: proc::wait,
+    },
+    Builtin {
+        name: "kill",
+        usage: "kill <pid> [signal]",
+        help: "send a signal to a process, SIGTERM by default",
+        min_args: 1,
+        max_args: 2,
+        handler: proc::kill,
+    },
+    Builtin {
+        name: "renice",
+        usage: "renice <nice> <pid>",
+        help: "set a process's nice value, from -20 (first) to 19 (last)",
+        min_args: 2,
+        max_args: 2,
+        handler: proc::renice,
+    },
+    Builtin {
+        name: "debug",
+        usage: "debug [on|off|gdb|brk]",
+        help: "show or set whether breakpoints stop at the debug prompt or for gdb; brk hits one",
+        min_args: 0,
+        max_args: 1,
+        handler: sys::debug,
+    },
+    Builtin {
+        name: "reboot",
+        usage: "reboot [-f]",
+        help: "sync the file system and reset the board; -f ignores sync errors",
+        min_args: 0,
+        max_args: 1,
+        handler: sys::reboot,
+    },
+    Builtin {
+        name: "halt",
+        usage: "halt [-f]",
+        help: "sync the file system and stop; -f ignores sync errors",
+        min_args: 0,
+        max_args: 1,
+        handler: sys::halt,
+    },
+    Builtin {
+        name: "shutdown",
+        usage: "shutdown [-f]",
+        help: "the same as halt",
+        min_args: 0,
+        max_args: 1,
+        handler: sys::halt,
+    },
+    Builtin {
+        name: "gpio",
+        usage: "gpio set|write|read|pull <pin> [value]",
+        help: "configure, drive or read a GPIO pin",
+        min_args: 2,
+        max_args: 3,
+        handler: gpio::gpio,
+    },
+    Builtin {
+        name: "ifconfig",
+        usage: "ifconfig",
+        help: "show the network interface's address, link and traffic",
+        min_args: 0,
+        max_args: 0,
+        handler: net::ifconfig,
+    },
+    Builtin {
+        name: "ping",
+        usage: "ping <address> [count]",
+        help: "send echo requests to an IPv4 address and report the replies",
+        min_args: 1,
+        max_args: 2,
+        handler: net::ping,
+    },
+    Builtin {
+        name: "tftp",
+        usage: "tftp get <server> <file> <path|0xaddr> [len]",
+        help: "fetch a file from a TFTP server into a file or memory",
+        min_args: 4,
+        max_args: 5,
+        handler: net::tftp,
+    },
+    Builtin {
+        name: "run",
+        usage: "run <program>",
+        help: "load a program and run it, reporting its exit status",
+        min_args: 1,
+        max_args: 1,
+        handler: run::run,
+    },
+    Builtin {
+        name: "install",
+        usage: "install <file|0xaddr> [len]",
+        help: "receive a file or memory image over the console with XMODEM",
+        min_args: 1,
+        max_args: 2,
+        handler: install::install,
+    },
+    Builtin {
+        name: "cd",
+        usage: "cd [directory]",
+        help: "change the working directory (default: /)",
+        min_args: 0,
+        max_args: 1,
+        handler: fs::cd,
+    },
+    Builtin {
+        name: "pwd",
+        usage: "pwd",
+        help: "print the working directory",
+        min_args: 0,
+        max_args: 0,
+        handler: fs::pwd,
+    },
+    Builtin {
+        name: "sh",
+        usage: "sh [-e] [script]",
+        help: "run a script, or a nested shell; -e stops a script at its first error",
+        min_args: 0,
+        max_args: 2,
+        handler: sh,
+    },
+    Builtin {
+        name: "exit",
+        usage: "exit [status]",
+        help: "leave the shell with the given status (default: 0)",
+        min_args: 0,
+        max_args: 1,
+        handler: exit,
+    },
+    Builtin {
+        name: "help",
+        usage: "help [command]",
+        help: "list all commands, or describe one",
+        min_args: 0,
+        max_args: 1,
+        handler: help,
+    },
+];
+
+/// Returns the built-in command invoked as `name`, if there is one.
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    // Commands taking a width suffix are found by the name before the `.`
+    let base = match name.splitn(2, '.').next() {
+        Some(base) if WIDTH_SUFFIXED.contains(&base) => base,
+        _ => name,
+    };
+    BUILTINS.iter().find(|builtin| builtin.name == base)
+}
+
+/// A simple echo program, printing arguments passed into the program.
+///
+/// Eventually this will be a separate binary, but for now the kernel can't handle that.
+fn echo(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    // every word but the first will get a leading space
+    let mut use_leading_space = false;
+    for arg in command.params() {
+        let prefix = if use_leading_space { " " } else { "" };
+        write!(out, "{}{}", prefix, arg)?;
+        use_leading_space = true;
+    }
+
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Runs a script or, without one, a nested interactive shell, in a shell of
+/// its own that starts in the current working directory.
+fn sh(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let (stop_on_error, script) = match command.params() {
+        [] => (false, None),
+        ["-e", script] => (true, Some(script)),
+        [script] if !script.starts_with('-') => (false, Some(script)),
+        _ => return Err(Error::Usage),
+    };
+
+    let mut nested = Shell::new(shell.cwd.clone());
+    let status = match script {
+        Some(script) => {
+            let path = fs::resolve(&shell.cwd, script);
+            match nested.run_script(&path, stop_on_error) {
+                Ok(status) => status,
+                Err(e) => fail!("{}: {}", path.display(), e),
+            }
+        }
+        None => nested.interact("sh> "),
+    };
+
+    if status != 0 {
+        fail!("exited with status {}", status);
+    }
+    Ok(())
+}
+
+/// Makes the shell exit with the given status once the current line is done.
+fn exit(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let status = match command.params().first() {
+        Some(arg) => match arg.parse() {
+            Ok(status) => status,
+            Err(_) => fail!("invalid status: {}", arg),
+        },
+        None => 0,
+    };
+
+    shell.exit = Some(status);
+    Ok(())
+}
+
+/// Lists every built-in command, or prints the usage of the one named.
+fn help(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    if let Some(&name) = command.params().first() {
+        match find(name) {
+            Some(builtin) => writeln!(out, "usage: {}\n  {}", builtin.usage, builtin.help)?,
+            None => fail!("unknown command: {}", name),
+        }
+        return Ok(());
+    }
+
+    let width = BUILTINS.iter().map(|b| b.usage.len()).max().unwrap_or(0);
+    for builtin in BUILTINS {
+        writeln!(out, "  {:width$}  {}", builtin.usage, builtin.help, width = width)?;
+    }
+    Ok(())
+}
+
+/// Copies the first lines of the input to the output: ten, unless a count is
+/// given with `-n`.
+fn head(
+    _: &mut Shell,
+    command: &Command,
+    input: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let mut lines = match command.params() {
+        [] => HEAD_DEFAULT_LINES,
+        ["-n", count] => match count.parse() {
+            Ok(count) => count,
+            Err(_) => fail!("invalid line count: {}", count),
+        },
+        _ => return Err(Error::Usage),
+    };
+
+    let mut buf = [0u8; HEAD_CHUNK_SIZE];
+    while lines > 0 {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        let mut end = 0;
+        while end < n && lines > 0 {
+            if buf[end] == b'\n' {
+                lines -= 1;
+            }
+            end += 1;
+        }
+        out.write_all(&buf[..end])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use stack_vec::StackVec;
+
+/// Error type for `Command` parse failures.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Empty,
+    TooManyArgs,
+    /// The input is too long for the storage given to hold its arguments.
+    TooLong,
+    /// The input ends inside a quotation started by the given quote character.
+    UnterminatedQuote(char),
+    /// The input ends with a backslash that has nothing to escape.
+    DanglingEscape,
+    /// A `>` or `>>` is not followed by a file name.
+    MissingRedirectTarget,
+    /// The input redirects output more than once.
+    DuplicateRedirect,
+}
+
+/// Where a command's output should be sent instead of the console.
+#[derive(Debug, PartialEq)]
+pub struct Redirect<'s> {
+    /// The file named after the `>` or `>>`, as written.
+    pub path: &'s str,
+    /// `true` for `>>`, which appends to the file instead of replacing it.
+    pub append: bool,
+}
+
+/// A structure representing a single shell command.
+pub struct Command<'v, 's> {
+    pub args: StackVec<'v, &'s str>,
+    pub redirect: Option<Redirect<'s>>,
+}
+
+/// The quoting context the parser is in.
+#[derive(Copy, Clone, PartialEq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+impl<'v, 's> Command<'v, 's> {
+    /// Parse a command from a string `s` using `buf` as storage for the
+    /// arguments and `scratch` as storage for the argument strings.
+    ///
+    /// Arguments are separated by spaces. Within an argument, text inside
+    /// single quotes is taken literally; text inside double quotes is taken
+    /// literally except that `\"` and `\\` stand for `"` and `\`; and, outside
+    /// of quotes, a backslash makes the character after it literal. Quoted and
+    /// unquoted text can be mixed in a single argument: `a"b c"d` is `ab cd`.
+    ///
+    /// An unquoted `>` or `>>` redirects the command's output to the file named
+    /// by the argument following it, which is stored in `redirect` rather than
+    /// `args`. The operator need not be surrounded by spaces: `echo hi>log` is
+    /// `echo hi > log`.
+    ///
+    /// The unescaped arguments are written to `scratch` one after another, each
+    /// terminated by a NUL byte. A `scratch` of `s.len() + 1` bytes is always
+    /// sufficient.
+    ///
+    /// # Errors
+    ///
+    /// If `s` contains no arguments, returns `Error::Empty`. If there are more
+    /// arguments than `buf` can hold, returns `Error::TooManyArgs`. If the
+    /// arguments do not fit in `scratch`, returns `Error::TooLong`. If `s` ends
+    /// inside quotes or with an unescaped backslash, returns
+    /// `Error::UnterminatedQuote` or `Error::DanglingEscape`, respectively. If
+    /// a redirection has no file name or is repeated, returns
+    /// `Error::MissingRedirectTarget` or `Error::DuplicateRedirect`.
+    pub fn parse(
+        s: &str,
+        scratch: &'s mut [u8],
+        buf: &'v mut [&'s str],
+    ) -> Result<Command<'v, 's>, Error> {
+        let (len, redirect_at) = unescape(s, scratch)?;
+
+        // Every argument written to scratch is NUL-terminated. Unescaping only
+        // ever removes whole ASCII characters, so each argument is valid UTF-8.
+        if len == 0 {
+            return Err(Error::Empty);
+        }
+
+        let scratch: &'s [u8] = scratch;
+        let mut args = StackVec::new(buf);
+        let mut redirect = None;
+        for (i, arg) in scratch[..len - 1].split(|&b| b == 0).enumerate() {
+            let arg = unsafe { core::str::from_utf8_unchecked(arg) };
+            match redirect_at {
+                Some((at, append)) if at == i => {
+                    redirect = Some(Redirect { path: arg, append })
+                }
+                _ => args.push(arg).map_err(|_| Error::TooManyArgs)?,
+            }
+        }
+
+        if args.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        Ok(Command { args, redirect })
+    }
+
+    /// Returns this command's path. This is equivalent to the first argument.
+    pub fn path(&self) -> &str {
+        self.args[0]
+    }
+
+    /// Returns the arguments following the command's path.
+    pub fn params(&self) -> &[&'s str] {
+        &self.args[1..]
+    }
+}
+
+/// Returns an iterator over the commands of the pipeline `s`: the pieces of
+/// `s` separated by `|` characters that are not quoted or escaped.
+pub fn stages(s: &str) -> Stages<'_> {
+    Stages { rest: Some(s) }
+}
+
+/// An iterator over the commands of a pipeline. See `stages()`.
+pub struct Stages<'s> {
+    rest: Option<&'s str>,
+}
+
+impl<'s> Iterator for Stages<'s> {
+    type Item = &'s str;
+
+    fn next(&mut self) -> Option<&'s str> {
+        let rest = self.rest?;
+        let mut quote = Quote::None;
+        let mut bytes = rest.bytes().enumerate();
+        while let Some((i, byte)) = bytes.next() {
+            match (quote, byte) {
+                (Quote::None, b'|') => {
+                    self.rest = Some(&rest[i + 1..]);
+                    return Some(&rest[..i]);
+                }
+                (Quote::None, b'\'') => quote = Quote::Single,
+                (Quote::None, b'"') => quote = Quote::Double,
+                (Quote::Single, b'\'') | (Quote::Double, b'"') => quote = Quote::None,
+                (Quote::None, b'\\') | (Quote::Double, b'\\') => {
+                    bytes.next();
+                }
+                _ => (),
+            }
+        }
+
+        self.rest = None;
+        Some(rest)
+    }
+}
+
+/// Splits `s` into arguments, removing quotes and escapes, and writes each to
+/// `out` followed by a NUL byte. Returns the number of bytes written and, if
+/// `s` redirects output, the index of the argument naming the target and
+/// whether it is appended to.
+fn unescape(s: &str, out: &mut [u8]) -> Result<(usize, Option<(usize, bool)>), Error> {
+    let mut len = 0;
+    let mut emit = |byte: u8| -> Result<(), Error> {
+        *out.get_mut(len).ok_or(Error::TooLong)? = byte;
+        len += 1;
+        Ok(())
+    };
+
+    let mut quote = Quote::None;
+    let mut in_arg = false;
+    let mut count = 0;
+    let mut redirect = None;
+    let mut bytes = s.bytes().peekable();
+    while let Some(byte) = bytes.next() {
+        match (quote, byte) {
+            (Quote::None, b' ') | (Quote::None, b'>') => {
+                if in_arg {
+                    emit(0)?;
+                    in_arg = false;
+                    count += 1;
+                }
+
+                if byte == b'>' {
+                    match redirect {
+                        // A second operator before the first one's target.
+                        Some((at, _)) if at == count => return Err(Error::MissingRedirectTarget),
+                        Some(_) => return Err(Error::DuplicateRedirect),
+                        None => (),
+                    }
+
+                    let append = bytes.peek() == Some(&b'>');
+                    if append {
+                        bytes.next();
+                    }
+                    redirect = Some((count, append));
+                }
+                continue;
+            }
+            (Quote::None, b'\'') => quote = Quote::Single,
+            (Quote::None, b'"') => quote = Quote::Double,
+            (Quote::Single, b'\'') | (Quote::Double, b'"') => quote = Quote::None,
+            (Quote::None, b'\\') => emit(bytes.next().ok_or(Error::DanglingEscape)?)?,
+            (Quote::Double, b'\\') => match bytes.next() {
+                Some(escaped @ b'"') | Some(escaped @ b'\\') => emit(escaped)?,
+                Some(other) => {
+                    emit(b'\\')?;
+                    emit(other)?;
+                }
+                None => return Err(Error::UnterminatedQuote('"')),
+            },
+            (_, byte) => emit(byte)?,
+        }
+
+        in_arg = true;
+    }
+
+    match quote {
+        Quote::Single => return Err(Error::UnterminatedQuote('\'')),
+        Quote::Double => return Err(Error::UnterminatedQuote('"')),
+        Quote::None if in_arg => {
+            emit(0)?;
+            count += 1;
+        }
+        Quote::None => (),
+    }
+
+    match redirect {
+        Some((at, _)) if at >= count => Err(Error::MissingRedirectTarget),
+        _ => Ok((len, redirect)),
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{stages, Command, Error, Redirect};
+
+    fn parse(s: &str) -> Result<Vec<String>, Error> {
+        let mut scratch = [0u8; 64];
+        let mut buf = [""; 8];
+        let command = Command::parse(s, &mut scratch, &mut buf)?;
+        Ok(command.args.iter().map(|a| a.to_string()).collect())
+    }
+
+    fn redirect(s: &str) -> Result<Option<(String, bool)>, Error> {
+        let mut scratch = [0u8; 64];
+        let mut buf = [""; 8];
+        let command = Command::parse(s, &mut scratch, &mut buf)?;
+        Ok(command
+            .redirect
+            .map(|Redirect { path, append }| (path.to_string(), append)))
+    }
+
+    #[test]
+    fn splits_on_spaces() {
+        assert_eq!(parse("  echo a   bc "), Ok(vec!["echo".into(), "a".into(), "bc".into()]));
+        assert_eq!(parse("   "), Err(Error::Empty));
+        assert_eq!(parse("a b c d e f g h i"), Err(Error::TooManyArgs));
+    }
+
+    #[test]
+    fn quotes_and_escapes() {
+        assert_eq!(parse(r#"cat "my file""#), Ok(vec!["cat".into(), "my file".into()]));
+        assert_eq!(parse(r#"echo 'a "b"' "c 'd'""#).unwrap()[1..], ["a \"b\"", "c 'd'"]);
+        assert_eq!(parse(r#"echo a"b c"d ''"#).unwrap()[1..], ["ab cd", ""]);
+        assert_eq!(parse(r#"echo a\ b \\ \'"#).unwrap()[1..], ["a b", "\\", "'"]);
+        assert_eq!(parse(r#"echo "\"\\\n""#).unwrap()[1..], ["\"\\\\n"]);
+        assert_eq!(parse(r#"echo '\'"#).unwrap()[1..], ["\\"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("echo 'abc"), Err(Error::UnterminatedQuote('\'')));
+        assert_eq!(parse("echo \"abc"), Err(Error::UnterminatedQuote('"')));
+        assert_eq!(parse("echo abc\\"), Err(Error::DanglingEscape));
+
+        let mut scratch = [0u8; 4];
+        let mut buf = [""; 8];
+        assert!(Command::parse("echo", &mut scratch, &mut buf).is_err());
+    }
+
+    #[test]
+    fn redirection() {
+        assert_eq!(parse("echo a > log b").unwrap(), ["echo", "a", "b"]);
+        assert_eq!(redirect("echo a > log b"), Ok(Some(("log".into(), false))));
+        assert_eq!(redirect("echo a>>'my log'"), Ok(Some(("my log".into(), true))));
+        assert_eq!(redirect("echo '>' \\> \">>\""), Ok(None));
+        assert_eq!(parse("echo '>' \\> \">>\"").unwrap()[1..], [">", ">", ">>"]);
+
+        assert_eq!(parse("> log"), Err(Error::Empty));
+        assert_eq!(parse("echo >"), Err(Error::MissingRedirectTarget));
+        assert_eq!(parse("echo > >> log"), Err(Error::MissingRedirectTarget));
+        assert_eq!(parse("echo > a > b"), Err(Error::DuplicateRedirect));
+    }
+
+    #[test]
+    fn pipeline_stages() {
+        let split = |s| stages(s).collect::<Vec<_>>();
+        assert_eq!(split("echo a"), ["echo a"]);
+        assert_eq!(split("cat f | head|wc"), ["cat f ", " head", "wc"]);
+        assert_eq!(
+            split(r#"echo '|' "|\"|" \| x"#),
+            [r#"echo '|' "|\"|" \| x"#]
+        );
+        assert_eq!(split("| a |"), ["", " a ", ""]);
+    }
+}
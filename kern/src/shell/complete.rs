@@ -0,0 +1,74 @@
+use alloc::string::String;
+use shim::path::PathBuf;
+
+use fat32::traits::FileSystem;
+use fat32::traits::{Dir, Entry, Metadata};
+
+use crate::console::Completer;
+use crate::FILESYSTEM;
+
+use super::builtins::BUILTINS;
+use super::fs::resolve;
+
+/// Completes the shell's command lines for its line editor.
+///
+/// The editor holds on to its completer while the line it read runs, and the
+/// line may change the shell's working directory, so the completer keeps a
+/// copy of it rather than borrowing the shell.
+pub struct ShellCompleter {
+    /// The absolute path that relative paths are completed against.
+    cwd: PathBuf,
+}
+
+impl ShellCompleter {
+    /// Returns a completer that completes relative paths against `cwd`.
+    pub fn new(cwd: PathBuf) -> ShellCompleter {
+        ShellCompleter { cwd }
+    }
+}
+
+impl Completer for ShellCompleter {
+    /// Completes the first word of a line against the built-in command names
+    /// and every other word against the entries of the directory it names.
+    fn complete(&mut self, line: &str, word: &str, f: &mut dyn FnMut(&str)) {
+        let is_first_word = !line[..line.len() - word.len()].contains(|c| c != ' ');
+        if is_first_word {
+            BUILTINS.iter().for_each(|builtin| f(builtin.name));
+        } else {
+            self.complete_path(word, f);
+        }
+    }
+}
+
+impl ShellCompleter {
+    /// Calls `f` with each path naming an entry in the directory named by the
+    /// partial path `word`. Directories are suffixed with a `/`.
+    fn complete_path(&self, word: &str, f: &mut dyn FnMut(&str)) {
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => word.split_at(i + 1),
+            None => ("", word),
+        };
+
+        let path = resolve(&self.cwd, if dir.is_empty() { "." } else { dir });
+        let entries = match FILESYSTEM.open_dir(&path).and_then(|dir| dir.entries()) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries {
+            let name = entry.name();
+            let is_hidden = entry.metadata().hidden() || name.starts_with('.');
+            if !name.starts_with(prefix) || (is_hidden && !prefix.starts_with('.')) {
+                continue;
+            }
+
+            let mut candidate = String::from(dir);
+            candidate.push_str(name);
+            if entry.is_dir() {
+                candidate.push('/');
+            }
+
+            f(&candidate);
+        }
+    }
+}
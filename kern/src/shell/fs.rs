@@ -0,0 +1,214 @@
+use alloc::vec::Vec;
+
+use shim::io::{self, Read, Seek, SeekFrom};
+use shim::path::{Component, Path, PathBuf};
+
+use fat32::traits::FileSystem;
+use fat32::traits::{Dir, Entry, File, Metadata, Timestamp};
+
+use crate::console::kprintln;
+use crate::FILESYSTEM;
+
+use super::builtins::{fail, Error};
+use super::command::{Command, Redirect};
+use super::Shell;
+
+/// The number of bytes `cat` reads from a file at a time.
+const CAT_CHUNK_SIZE: usize = 512;
+
+/// Returns the absolute path named by `path`, which is interpreted relative to
+/// `cwd` unless it is absolute. `.` and `..` components are resolved
+/// lexically; `..` at the root stays at the root.
+pub fn resolve(cwd: &Path, path: &str) -> PathBuf {
+    let mut resolved = PathBuf::from("/");
+    let path = Path::new(path);
+    let base = if path.is_absolute() { None } else { Some(cwd) };
+
+    for component in base.into_iter().flat_map(Path::components).chain(path.components()) {
+        match component {
+            Component::RootDir => resolved = PathBuf::from("/"),
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(name) => resolved.push(name),
+            Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+
+    resolved
+}
+
+/// Lists the entries of a directory along with their attributes and sizes.
+///
+/// Hidden entries are only shown when `-a` is passed.
+pub fn ls(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let mut show_hidden = false;
+    let mut target = None;
+    for &arg in command.params() {
+        match arg {
+            "-a" if !show_hidden => show_hidden = true,
+            _ if target.is_none() => target = Some(arg),
+            _ => return Err(Error::Usage),
+        }
+    }
+
+    let path = resolve(&shell.cwd, target.unwrap_or("."));
+    let entries = match FILESYSTEM.open_dir(&path).and_then(|dir| dir.entries()) {
+        Ok(entries) => entries,
+        Err(e) => fail!("{}: {}", path.display(), e),
+    };
+
+    for entry in entries {
+        let metadata = entry.metadata();
+        if metadata.hidden() && !show_hidden {
+            continue;
+        }
+
+        print_entry(&entry, out)?;
+    }
+    Ok(())
+}
+
+/// Writes a single `ls` line describing `entry` to `out`.
+fn print_entry<E: Entry>(entry: &E, out: &mut dyn io::Write) -> io::Result<()> {
+    let metadata = entry.metadata();
+    let modified = metadata.modified();
+
+    write!(
+        out,
+        "{}{}{} {:04}-{:02}-{:02} {:02}:{:02}:{:02} ",
+        if entry.is_dir() { 'd' } else { '-' },
+        if metadata.read_only() { 'r' } else { 'w' },
+        if metadata.hidden() { 'h' } else { '-' },
+        modified.year(),
+        modified.month(),
+        modified.day(),
+        modified.hour(),
+        modified.minute(),
+        modified.second(),
+    )?;
+
+    match entry.as_file() {
+        Some(file) => writeln!(out, "{:>10} {}", file.size(), entry.name()),
+        None => writeln!(out, "{:>10} {}/", "", entry.name()),
+    }
+}
+
+/// Prints the contents of each file named on the command line. With no
+/// arguments, copies the input to the output. A file that cannot be read is
+/// reported, and the rest are still printed.
+pub fn cat(
+    shell: &mut Shell,
+    command: &Command,
+    input: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let mut buf = [0u8; CAT_CHUNK_SIZE];
+    if command.params().is_empty() {
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            out.write_all(&buf[..n])?;
+        }
+    }
+
+    let mut failed = false;
+    for &arg in command.params() {
+        let path = resolve(&shell.cwd, arg);
+        let mut file = match FILESYSTEM.open_file(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                kprintln!("cat: {}: {}", path.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) => {
+                    kprintln!("cat: {}: {}", path.display(), e);
+                    failed = true;
+                    break;
+                }
+            };
+            if n == 0 {
+                break;
+            }
+
+            out.write_all(&buf[..n])?;
+        }
+    }
+
+    if failed {
+        return Err(Error::Reported);
+    }
+    Ok(())
+}
+
+/// Reads the whole of the file at the absolute path `path`, which must be no
+/// larger than `max` bytes.
+pub fn read_file(path: &Path, max: u64) -> io::Result<Vec<u8>> {
+    let mut file = FILESYSTEM.open_file(path)?;
+    if file.size() > max {
+        return Err(io::Error::new(io::ErrorKind::Other, "file too large"));
+    }
+
+    let mut bytes = Vec::with_capacity(file.size() as usize);
+    let mut buf = [0u8; CAT_CHUNK_SIZE];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(bytes),
+            n => bytes.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+/// Opens the file named by `redirect` so that a command's output can be
+/// written to it: a `>>` target is opened for appending, and a `>` target is
+/// created or emptied.
+pub fn open_redirect(cwd: &Path, redirect: &Redirect) -> io::Result<impl File> {
+    let path = resolve(cwd, redirect.path);
+    if redirect.append {
+        let mut file = FILESYSTEM.open_file(&path)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(file)
+    } else {
+        FILESYSTEM.create_file(&path)
+    }
+}
+
+/// Changes the shell's working directory. With no arguments, changes to `/`.
+pub fn cd(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let target = command.params().first().cloned().unwrap_or("/");
+    let path = resolve(&shell.cwd, target);
+    match FILESYSTEM.open_dir(&path) {
+        Ok(_) => shell.cwd = path,
+        Err(e) => fail!("{}: {}", path.display(), e),
+    }
+    Ok(())
+}
+
+/// Prints the shell's working directory.
+pub fn pwd(
+    shell: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    writeln!(out, "{}", shell.cwd.display())?;
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use shim::io;
+
+use pi::gpio::{Function, Gpio, Pull};
+
+use crate::console::kprintln;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::Shell;
+
+/// The highest numbered GPIO pin.
+const MAX_PIN: u8 = 53;
+
+/// The pins the console's mini UART uses. Reconfiguring them would cut off
+/// the shell, so `gpio` refuses to.
+const CONSOLE_PINS: [u8; 2] = [14, 15];
+
+/// The usage message for `gpio`.
+const USAGE: &str = "usage: gpio set <pin> in|out|alt<N>\n       \
+                     gpio write <pin> 0|1\n       \
+                     gpio read <pin>\n       \
+                     gpio pull <pin> up|down|none";
+
+/// Configures, drives and reads GPIO pins.
+pub fn gpio(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    let pin = match params.get(1).map(|pin| parse_pin(pin)) {
+        Some(Some(pin)) => pin,
+        Some(None) => fail!("invalid pin: {} (pins are 0-{})", params[1], MAX_PIN),
+        None => {
+            kprintln!("{}", USAGE);
+            return Err(Error::Reported);
+        }
+    };
+
+    match (params[0], params.get(2).cloned()) {
+        ("set", Some(function)) => match parse_function(function) {
+            Some(_) if CONSOLE_PINS.contains(&pin) => {
+                fail!("pin {} is in use by the console", pin);
+            }
+            Some(function) => {
+                Gpio::new(pin).into_alt(function);
+            }
+            None => fail!("invalid function: {}", function),
+        },
+        ("write", Some(level)) => {
+            let gpio = Gpio::new(pin);
+            if gpio.function() != Function::Output {
+                fail!("pin {} is not an output; use: gpio set {} out", pin, pin);
+            }
+
+            let mut gpio = gpio.into_output();
+            match level {
+                "0" => gpio.clear(),
+                "1" => gpio.set(),
+                _ => fail!("invalid level: {} (use 0 or 1)", level),
+            }
+        }
+        ("read", None) => {
+            let gpio = Gpio::new(pin);
+            writeln!(
+                out,
+                "{} ({})",
+                gpio.level() as u8,
+                function_name(gpio.function())
+            )?;
+        }
+        ("pull", Some(pull)) => {
+            let pull = match pull {
+                "up" => Pull::Up,
+                "down" => Pull::Down,
+                "none" => Pull::Off,
+                _ => fail!("invalid pull: {} (use up, down or none)", pull),
+            };
+            Gpio::new(pin).set_pull(pull);
+        }
+        _ => {
+            kprintln!("{}", USAGE);
+            return Err(Error::Reported);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a GPIO pin number.
+fn parse_pin(s: &str) -> Option<u8> {
+    s.parse().ok().filter(|&pin| pin <= MAX_PIN)
+}
+
+/// Parses a pin function as written for `gpio set`.
+fn parse_function(s: &str) -> Option<Function> {
+    match s {
+        "in" => Some(Function::Input),
+        "out" => Some(Function::Output),
+        "alt0" => Some(Function::Alt0),
+        "alt1" => Some(Function::Alt1),
+        "alt2" => Some(Function::Alt2),
+        "alt3" => Some(Function::Alt3),
+        "alt4" => Some(Function::Alt4),
+        "alt5" => Some(Function::Alt5),
+        _ => None,
+    }
+}
+
+/// Returns the name `gpio set` uses for `function`.
+fn function_name(function: Function) -> &'static str {
+    match function {
+        Function::Input => "in",
+        Function::Output => "out",
+        Function::Alt0 => "alt0",
+        Function::Alt1 => "alt1",
+        Function::Alt2 => "alt2",
+        Function::Alt3 => "alt3",
+        Function::Alt4 => "alt4",
+        Function::Alt5 => "alt5",
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{function_name, parse_function, parse_pin};
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse_pin("0"), Some(0));
+        assert_eq!(parse_pin("53"), Some(53));
+        assert_eq!(parse_pin("54"), None);
+        assert_eq!(parse_pin("-1"), None);
+
+        for &name in ["in", "out", "alt0", "alt3", "alt5"].iter() {
+            assert_eq!(parse_function(name).map(function_name), Some(name));
+        }
+        assert_eq!(parse_function("alt6"), None);
+    }
+}
@@ -0,0 +1,165 @@
+use core::slice;
+
+use shim::io;
+
+use fat32::traits::FileSystem;
+
+use crate::vm::phys_to_virt;
+use crate::FILESYSTEM;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::fs::resolve;
+use super::mem;
+use super::Shell;
+
+/// The number of bytes shown on each line of a dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// Dumps a file, a range of physical memory, or the input in the canonical
+/// offset, hex and ASCII format.
+///
+/// An argument starting with `0x` or `0X` is a physical address, and a length
+/// must follow it. Any other argument names a file, of which at most the
+/// given length is shown. With no arguments, the input is dumped.
+pub fn hexdump(
+    shell: &mut Shell,
+    command: &Command,
+    input: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let len = match command.params().get(1) {
+        None => None,
+        Some(len) => match mem::parse_number(len) {
+            Some(len) => Some(len),
+            None => fail!("invalid length: {}", len),
+        },
+    };
+
+    let target = match command.params().first() {
+        Some(&target) => target,
+        None => return Ok(dump(input, 0, usize::max_value(), out)?),
+    };
+
+    if target.starts_with("0x") || target.starts_with("0X") {
+        let addr = match mem::parse_number(target) {
+            Some(addr) => addr,
+            None => fail!("invalid address: {}", target),
+        };
+
+        let len = match len {
+            Some(len) => len,
+            None => return Err(Error::Usage),
+        };
+
+        if !mem::is_ram(addr, len) {
+            fail!("{:#x}+{:#x} is not within RAM", addr, len);
+        }
+
+        // `is_ram` guarantees the whole range is readable memory
+        let mut bytes = unsafe { slice::from_raw_parts(phys_to_virt(addr) as *const u8, len) };
+        return Ok(dump(&mut bytes, addr, len, out)?);
+    }
+
+    let path = resolve(&shell.cwd, target);
+    match FILESYSTEM.open_file(&path) {
+        Ok(mut file) => Ok(dump(&mut file, 0, len.unwrap_or(usize::max_value()), out)?),
+        Err(e) => fail!("{}: {}", path.display(), e),
+    }
+}
+
+/// Writes a canonical dump of at most `limit` bytes read from `input` to
+/// `out`, numbering the first byte `offset`.
+///
+/// Each line shows the offset of its first byte, up to sixteen bytes in hex,
+/// and the same bytes as ASCII with unprintable bytes shown as `.`. A final
+/// line shows the offset just past the last byte.
+fn dump<R: io::Read + ?Sized>(
+    input: &mut R,
+    offset: usize,
+    limit: usize,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let mut line = [0u8; BYTES_PER_LINE];
+    let mut read = 0;
+    while read < limit {
+        let want = BYTES_PER_LINE.min(limit - read);
+        let n = fill(input, &mut line[..want])?;
+        if n == 0 {
+            break;
+        }
+
+        write!(out, "{:08x} ", offset + read)?;
+        for i in 0..BYTES_PER_LINE {
+            if i % 8 == 0 {
+                write!(out, " ")?;
+            }
+            match line[..n].get(i) {
+                Some(byte) => write!(out, "{:02x} ", byte)?,
+                None => write!(out, "   ")?,
+            }
+        }
+
+        write!(out, " |")?;
+        for &byte in &line[..n] {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte
+            } else {
+                b'.'
+            };
+            out.write_all(&[c])?;
+        }
+        writeln!(out, "|")?;
+
+        read += n;
+        if n < want {
+            break;
+        }
+    }
+
+    writeln!(out, "{:08x}", offset + read)
+}
+
+/// Reads from `input` until `buf` is full or the input ends, returning the
+/// number of bytes read.
+fn fill<R: io::Read + ?Sized>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match input.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::dump;
+
+    fn dump_to_string(bytes: &[u8], offset: usize, limit: usize) -> String {
+        let mut out = Vec::new();
+        dump(&mut &bytes[..], offset, limit, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn canonical_format() {
+        assert_eq!(
+            dump_to_string(b"Hello, world!\n\x00\x01\xffxyz", 0, 100),
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+             00000010  ff 78 79 7a                                       |.xyz|\n\
+             00000014\n"
+        );
+        assert_eq!(dump_to_string(b"", 0, 100), "00000000\n");
+    }
+
+    #[test]
+    fn offset_and_limit() {
+        assert_eq!(
+            dump_to_string(b"abcdef", 0x80000, 3),
+            "00080000  61 62 63                                          |abc|\n\
+             00080003\n"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::slice;
+use core::time::Duration;
+
+use shim::io::{self, Write};
+use shim::ioerr;
+
+use fat32::traits::{File, FileSystem};
+use xmodem::Xmodem;
+
+use crate::console::{kprintln, RawConsole};
+use crate::vm::phys_to_virt;
+use crate::FILESYSTEM;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::fs::resolve;
+use super::mem::{is_ram, parse_number};
+use super::Shell;
+
+/// The largest file `install` and `tftp` will store.
+const MAX_FILE_SIZE: usize = 16 << 20;
+
+/// How long to wait for the sender before asking it to start again.
+const START_TIMEOUT: Duration = Duration::from_millis(750);
+
+/// The number of times to ask the sender to start before giving up: about a
+/// minute's worth.
+const START_ATTEMPTS: usize = 80;
+
+/// Receives a blob over the console with XMODEM, storing it as a file or in
+/// physical memory.
+///
+/// The sender pads the blob to a multiple of 128 bytes. When `len` is given,
+/// only the first `len` bytes are kept.
+pub fn install(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    let len = match params.get(1).map(|len| parse_number(len)) {
+        Some(Some(len)) => Some(len),
+        Some(None) => fail!("invalid length: {}", params[1]),
+        None => None,
+    };
+
+    store(shell, params[0], len, out, |into| receive(into))
+}
+
+/// Stores a blob that `receive` writes out into physical memory, when `dest`
+/// is an address, or into the file at the path `dest`, keeping at most `len`
+/// bytes. `receive` returns the size of the blob.
+pub fn store<E: fmt::Display>(
+    shell: &Shell,
+    dest: &str,
+    len: Option<usize>,
+    out: &mut dyn io::Write,
+    receive: impl FnOnce(&mut dyn io::Write) -> Result<usize, E>,
+) -> Result<(), Error> {
+    if dest.starts_with("0x") || dest.starts_with("0X") {
+        store_to_memory(dest, len, out, receive)
+    } else {
+        store_to_file(shell, dest, len, out, receive)
+    }
+}
+
+/// Stores a blob of at most `len` bytes into memory at the address `arg`.
+fn store_to_memory<E: fmt::Display>(
+    arg: &str,
+    len: Option<usize>,
+    out: &mut dyn io::Write,
+    receive: impl FnOnce(&mut dyn io::Write) -> Result<usize, E>,
+) -> Result<(), Error> {
+    let addr = match parse_number(arg) {
+        Some(addr) => addr,
+        None => fail!("invalid address: {}", arg),
+    };
+    let len = match len {
+        Some(len) => len,
+        None => fail!("a length is required when installing to memory"),
+    };
+    if !is_ram(addr, len) {
+        fail!("{:#x}-{:#x} is not RAM", addr, addr + len);
+    }
+
+    // The blob may be longer than `len`, so anything past it is dropped
+    // rather than written over whatever follows.
+    let dest = unsafe { slice::from_raw_parts_mut(phys_to_virt(addr) as *mut u8, len) };
+    let received = match receive(&mut Truncating { dest, pos: 0 }) {
+        Ok(received) => received,
+        Err(e) => fail!("receive failed: {}", e),
+    };
+
+    writeln!(out, "installed {} bytes at {:#x}", received.min(len), addr)?;
+    Ok(())
+}
+
+/// Stores a blob into the file at `path`, keeping at most `len` bytes.
+fn store_to_file<E: fmt::Display>(
+    shell: &Shell,
+    path: &str,
+    len: Option<usize>,
+    out: &mut dyn io::Write,
+    receive: impl FnOnce(&mut dyn io::Write) -> Result<usize, E>,
+) -> Result<(), Error> {
+    let path = resolve(&shell.cwd, path);
+    let mut data = Capped {
+        buf: Vec::new(),
+        max: MAX_FILE_SIZE,
+    };
+    if let Err(e) = receive(&mut data) {
+        fail!("receive failed: {}", e);
+    }
+
+    let mut data = data.buf;
+    data.truncate(len.unwrap_or(data.len()));
+
+    let result = FILESYSTEM.create_file(&path).and_then(|mut file| {
+        file.write_all(&data)?;
+        file.sync()
+    });
+    match result {
+        Ok(()) => writeln!(out, "installed {} bytes to {}", data.len(), path.display())?,
+        Err(e) => fail!("{}: {}", path.display(), e),
+    }
+    Ok(())
+}
+
+/// Receives an XMODEM transfer over the console into `into`, asking the
+/// sender to start until it does. Returns the number of bytes received.
+fn receive<W: io::Write>(mut into: W) -> io::Result<usize> {
+    kprintln!("install: waiting for the sender (XMODEM)...");
+
+    let mut console = RawConsole::new();
+    console.set_read_timeout(Some(START_TIMEOUT));
+    let mut result = ioerr!(TimedOut, "no sender");
+    for _ in 0..START_ATTEMPTS {
+        result = Xmodem::receive(&mut console, &mut into);
+        match result {
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            _ => break,
+        }
+    }
+    result
+}
+
+/// A writer into a slice which silently drops whatever does not fit.
+struct Truncating<'a> {
+    dest: &'a mut [u8],
+    pos: usize,
+}
+
+impl io::Write for Truncating<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.dest.len() - self.pos);
+        self.dest[self.pos..self.pos + n].copy_from_slice(&buf[..n]);
+        self.pos += n;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer into a vector which fails once `max` bytes have been written.
+struct Capped {
+    buf: Vec<u8>,
+    max: usize,
+}
+
+impl io::Write for Capped {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + buf.len() > self.max {
+            return ioerr!(Other, "file too large");
+        }
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
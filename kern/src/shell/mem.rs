@@ -0,0 +1,371 @@
+use alloc::format;
+use core::fmt;
+use core::ptr;
+
+use shim::io;
+
+use pi::atags::Atags;
+use pi::common::{IO_BASE, IO_BASE_END, LOCAL_BASE, LOCAL_END};
+
+use crate::allocator;
+use crate::vm::{phys_to_virt, Mapping, L2_BLOCK_SIZE, PAGE_SIZE};
+use crate::{ALLOCATOR, VMM};
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::Shell;
+
+/// The number of bytes shown on each line of `peek` output.
+const PEEK_BYTES_PER_LINE: usize = 16;
+
+/// The size of a single `peek` or `poke` access.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Width {
+    Byte,
+    Half,
+    Word,
+    Double,
+}
+
+impl Width {
+    /// Returns the width named by the suffix of a command invoked as
+    /// `name.suffix`: `b`, `h`, `w` or `d` for 8, 16, 32 or 64 bits. Without a
+    /// suffix, the width is a 32-bit word, the size of most device registers.
+    pub fn from_command_name(name: &str) -> Option<Width> {
+        match name.splitn(2, '.').nth(1) {
+            None | Some("w") => Some(Width::Word),
+            Some("b") => Some(Width::Byte),
+            Some("h") => Some(Width::Half),
+            Some("d") => Some(Width::Double),
+            Some(_) => None,
+        }
+    }
+
+    /// Returns the number of bytes accessed at this width.
+    pub fn bytes(self) -> usize {
+        match self {
+            Width::Byte => 1,
+            Width::Half => 2,
+            Width::Word => 4,
+            Width::Double => 8,
+        }
+    }
+
+    /// Returns the largest value that fits in this width.
+    fn max_value(self) -> u64 {
+        u64::max_value() >> (64 - 8 * self.bytes())
+    }
+}
+
+/// Parses an unsigned number written in decimal or, with a `0x` prefix, in
+/// hexadecimal.
+pub fn parse_number(s: &str) -> Option<usize> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Returns `true` if the `len` bytes starting at the physical address `addr`
+/// all lie within a single region of RAM reported by the firmware, and the
+/// kernel maps all of them: the guard pages below its stacks are not.
+///
+/// Memory-mapped peripherals are not RAM, and reading them can have side
+/// effects, so they are never considered valid.
+pub fn is_ram(addr: usize, len: usize) -> bool {
+    let end = match addr.checked_add(len) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    let in_region = Atags::get().filter_map(|tag| tag.mem()).any(|mem| {
+        let start = mem.start as usize;
+        addr >= start && end <= start + mem.size as usize
+    });
+    in_region
+        && (addr - addr % PAGE_SIZE..end)
+            .step_by(PAGE_SIZE)
+            .all(|page| VMM.translate(phys_to_virt(page)).is_some())
+}
+
+/// Returns `true` if the `len` bytes starting at the physical address `addr`
+/// all lie within a single range of memory-mapped peripheral registers.
+pub fn is_mmio(addr: usize, len: usize) -> bool {
+    let end = match addr.checked_add(len) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    // The ARM-local peripherals are the core timers, mailboxes and
+    // interrupt routing registers.
+    [(IO_BASE, IO_BASE_END), (LOCAL_BASE, LOCAL_END)]
+        .iter()
+        .any(|&(start, limit)| addr >= start && end <= limit)
+}
+
+/// Checks that `count` accesses of `width` starting at `addr` are aligned and
+/// lie within RAM or peripheral registers, failing with the problem if not.
+fn check_access(addr: usize, width: Width, count: usize) -> Result<(), Error> {
+    let len = match count.checked_mul(width.bytes()) {
+        Some(len) => len,
+        None => fail!("count too large"),
+    };
+
+    if addr % width.bytes() != 0 {
+        fail!("{:#x} is not {}-byte aligned", addr, width.bytes());
+    } else if !is_ram(addr, len) && !is_mmio(addr, len) {
+        fail!("{:#x}+{:#x} is not RAM or a device", addr, len);
+    }
+    Ok(())
+}
+
+/// Parses the address given as a command's first argument, failing if it is
+/// invalid.
+fn parse_address(arg: &str) -> Result<usize, Error> {
+    match parse_number(arg) {
+        Some(addr) => Ok(addr),
+        None => fail!("invalid address: {}", arg),
+    }
+}
+
+/// Reads one or more values from physical memory with volatile accesses of
+/// the width given by the command's suffix, printing them in hex.
+pub fn peek(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let name = command.path();
+    let width = match Width::from_command_name(name) {
+        Some(width) => width,
+        None => fail!("unknown width; use .b, .h, .w or .d"),
+    };
+
+    let params = command.params();
+    let addr = parse_address(params[0])?;
+
+    let count = match params.get(1).map(|count| parse_number(count)) {
+        None => 1,
+        Some(Some(count)) => count,
+        Some(None) => fail!("invalid count: {}", params[1]),
+    };
+
+    check_access(addr, width, count)?;
+
+    let per_line = PEEK_BYTES_PER_LINE / width.bytes();
+    for i in 0..count {
+        let at = addr + i * width.bytes();
+        if i % per_line == 0 {
+            if i != 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{:08x}:", at)?;
+        }
+
+        // `check_access` guarantees the address is aligned and accessible
+        let va = phys_to_virt(at);
+        let value = unsafe {
+            match width {
+                Width::Byte => ptr::read_volatile(va as *const u8) as u64,
+                Width::Half => ptr::read_volatile(va as *const u16) as u64,
+                Width::Word => ptr::read_volatile(va as *const u32) as u64,
+                Width::Double => ptr::read_volatile(va as *const u64),
+            }
+        };
+        write!(out, " {:01$x}", value, 2 * width.bytes())?;
+    }
+
+    if count != 0 {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Writes a value to physical memory with a single volatile access of the
+/// width given by the command's suffix.
+pub fn poke(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let name = command.path();
+    let width = match Width::from_command_name(name) {
+        Some(width) => width,
+        None => fail!("unknown width; use .b, .h, .w or .d"),
+    };
+
+    let params = command.params();
+    let addr = parse_address(params[0])?;
+
+    let value = match parse_number(params[1]) {
+        Some(value) if value as u64 <= width.max_value() => value as u64,
+        _ => fail!("invalid {}-byte value: {}", width.bytes(), params[1]),
+    };
+
+    check_access(addr, width, 1)?;
+
+    // `check_access` guarantees the address is aligned and accessible
+    let va = phys_to_virt(addr);
+    unsafe {
+        match width {
+            Width::Byte => ptr::write_volatile(va as *mut u8, value as u8),
+            Width::Half => ptr::write_volatile(va as *mut u16, value as u16),
+            Width::Word => ptr::write_volatile(va as *mut u32, value as u32),
+            Width::Double => ptr::write_volatile(va as *mut u64, value),
+        }
+    }
+    Ok(())
+}
+
+/// A number of bytes, displayed in the largest binary unit that divides it
+/// evenly, such as `12 KiB`.
+pub struct Size(pub usize);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+        let (mut value, mut unit) = (self.0, 0);
+        while value != 0 && value % 1024 == 0 && unit + 1 < UNITS.len() {
+            value /= 1024;
+            unit += 1;
+        }
+        write!(f, "{} {}", value, UNITS[unit])
+    }
+}
+
+/// Prints the heap's usage, the free blocks held by each of the allocator's
+/// bins, and the regions of physical memory reported by the firmware.
+pub fn meminfo(
+    _: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    match (allocator::memory_map(), ALLOCATOR.stats()) {
+        (Some((start, end)), Some(stats)) => {
+            writeln!(out, "heap: {:#010x}-{:#010x}", start, end)?;
+            writeln!(out, "  total  {}", Size(stats.total))?;
+            writeln!(
+                out,
+                "  used   {} (peak {})",
+                Size(stats.used),
+                Size(stats.peak)
+            )?;
+            writeln!(out, "  free   {}", Size(stats.free))?;
+
+            if let Some(bins) = stats.bins {
+                writeln!(out, "bins:")?;
+                for bin in bins.iter() {
+                    writeln!(out, "  {:>9}  {} free", Size(bin.size), bin.free_blocks)?;
+                }
+            }
+        }
+        _ => writeln!(out, "heap: not initialized")?,
+    }
+
+    writeln!(out, "memory regions:")?;
+    for mem in Atags::get().filter_map(|tag| tag.mem()) {
+        let (start, size) = (mem.start as usize, mem.size as usize);
+        writeln!(
+            out,
+            "  {:#010x}-{:#010x}  {}",
+            start,
+            start + size,
+            Size(size)
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the mappings made by the installed translation tables, for the
+/// upper half of the address space and then the lower half. Processes are
+/// not supported yet, so a process's address space cannot be shown.
+pub fn vmmap(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    if let Some(pid) = command.params().first() {
+        fail!("no such process: {}", pid);
+    }
+
+    let (upper, lower) = VMM.mappings();
+    writeln!(out, "upper half (TTBR1_EL1):")?;
+    write_mappings(out, &upper)?;
+    writeln!(out, "lower half (TTBR0_EL1):")?;
+    write_mappings(out, &lower)?;
+    Ok(())
+}
+
+/// Writes a line for each of `mappings`: its virtual addresses, the physical
+/// address they map to, its size and the size of the blocks or pages that
+/// map it, and its attributes.
+fn write_mappings(out: &mut dyn io::Write, mappings: &[Mapping]) -> io::Result<()> {
+    for mapping in mappings {
+        let granule = match mapping.size {
+            PAGE_SIZE => "4K",
+            L2_BLOCK_SIZE => "2M",
+            _ => "1G",
+        };
+        writeln!(
+            out,
+            "  {:#018x}-{:#018x} -> {:#010x}  {:>9} {}  {}",
+            mapping.va,
+            mapping.va + mapping.len,
+            mapping.pa,
+            format!("{}", Size(mapping.len)),
+            granule,
+            mapping.attrs
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{is_mmio, parse_number, Size, Width};
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("4096"), Some(4096));
+        assert_eq!(parse_number("0x80000"), Some(0x80000));
+        assert_eq!(parse_number("0XfF"), Some(0xff));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("-1"), None);
+        assert_eq!(parse_number("12ab"), None);
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(Width::from_command_name("peek"), Some(Width::Word));
+        assert_eq!(Width::from_command_name("peek.b"), Some(Width::Byte));
+        assert_eq!(Width::from_command_name("poke.h"), Some(Width::Half));
+        assert_eq!(Width::from_command_name("poke.d"), Some(Width::Double));
+        assert_eq!(Width::from_command_name("peek.q"), None);
+        assert_eq!(Width::Half.max_value(), 0xffff);
+        assert_eq!(Width::Double.max_value(), u64::max_value());
+    }
+
+    #[test]
+    fn mmio_ranges() {
+        assert!(is_mmio(0x3F20_0000, 4));
+        assert!(is_mmio(0x4000_0034, 4));
+        assert!(!is_mmio(0x3EFF_FFFC, 8));
+        assert!(!is_mmio(0x40FF_FFFC, 8));
+        assert!(!is_mmio(usize::max_value(), 2));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(Size(0).to_string(), "0 B");
+        assert_eq!(Size(1000).to_string(), "1000 B");
+        assert_eq!(Size(8 << 10).to_string(), "8 KiB");
+        assert_eq!(Size(948 << 20).to_string(), "948 MiB");
+        assert_eq!(Size((1 << 20) + 1024).to_string(), "1025 KiB");
+    }
+}
@@ -0,0 +1,161 @@
+use core::time::Duration;
+
+use shim::io;
+
+use crate::clock;
+use crate::net::{self, tftp, Ipv4Addr};
+use crate::process;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::install::store;
+use super::mem::parse_number;
+use super::mem::Size;
+use super::Shell;
+
+/// The number of echo requests `ping` sends when no count is given, how far
+/// apart, and how long it waits for each reply.
+const PING_DEFAULT_COUNT: u16 = 4;
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often `ping` checks for a reply.
+const PING_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The data echo requests carry.
+const PING_DATA: &[u8] = b"rustos ping";
+
+/// Prints the network interface's address, link and lease, and how much it
+/// has sent and received.
+pub fn ifconfig(
+    _: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let status = match net::status() {
+        Some(status) => status,
+        None => fail!("no network interface"),
+    };
+
+    let link = if status.link { "up" } else { "down" };
+    writeln!(out, "eth0  link {}  ether {}", link, status.mac)?;
+    match status.lease {
+        Some(lease) => {
+            write!(out, "      inet {} netmask {}", lease.addr, lease.netmask)?;
+            if let Some(gateway) = lease.gateway {
+                write!(out, " gateway {}", gateway)?;
+            }
+            writeln!(out)?;
+        }
+        None => writeln!(out, "      no address leased yet")?,
+    }
+
+    let stats = status.stats;
+    writeln!(
+        out,
+        "      rx {} frames ({})  tx {} frames ({})  dropped {}",
+        stats.rx_frames,
+        Size(stats.rx_bytes as usize),
+        stats.tx_frames,
+        Size(stats.tx_bytes as usize),
+        stats.dropped
+    )?;
+    Ok(())
+}
+
+/// Sends echo requests to an address, one a second, four by default, and
+/// reports the replies.
+pub fn ping(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    let dst: Ipv4Addr = match params[0].parse() {
+        Ok(dst) => dst,
+        Err(_) => fail!("invalid address: {}", params[0]),
+    };
+    let count = match params.get(1).map(|arg| arg.parse()) {
+        None => PING_DEFAULT_COUNT,
+        Some(Ok(count)) if count > 0 => count,
+        Some(_) => fail!("invalid count: {}", params[1]),
+    };
+
+    let mut id = [0; 2];
+    pi::rng::fill(&mut id);
+    let id = u16::from_le_bytes(id);
+    let mut received = 0;
+    for seq in 1..=count {
+        let sent = clock::uptime();
+        if let Err(e) = net::send_echo(dst, id, seq, PING_DATA) {
+            fail!("{}", e);
+        }
+
+        let mut replied = None;
+        while replied.is_none() && clock::uptime() < sent + PING_TIMEOUT {
+            process::sleep(PING_POLL_INTERVAL);
+            replied = net::echo_reply(id, seq);
+        }
+        match replied {
+            Some(src) => {
+                received += 1;
+                let time = clock::uptime() - sent;
+                writeln!(
+                    out,
+                    "reply from {}: seq={} time={} ms",
+                    src,
+                    seq,
+                    time.as_millis()
+                )?;
+            }
+            None => writeln!(out, "no reply: seq={}", seq)?,
+        }
+
+        if seq < count {
+            let next = sent + PING_INTERVAL;
+            let now = clock::uptime();
+            if now < next {
+                process::sleep(next - now);
+            }
+        }
+    }
+
+    writeln!(
+        out,
+        "{} sent, {} received, {}% lost",
+        count,
+        received,
+        u32::from(count - received) * 100 / u32::from(count)
+    )?;
+    Ok(())
+}
+
+/// Fetches a file from a TFTP server, storing it as a file or in physical
+/// memory as `install` does.
+pub fn tftp(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    if params[0] != "get" {
+        fail!("unknown operation: {}", params[0]);
+    }
+    let server: Ipv4Addr = match params[1].parse() {
+        Ok(server) => server,
+        Err(_) => fail!("invalid address: {}", params[1]),
+    };
+    let len = match params.get(4).map(|len| parse_number(len)) {
+        Some(Some(len)) => Some(len),
+        Some(None) => fail!("invalid length: {}", params[4]),
+        None => None,
+    };
+
+    let name = params[2];
+    store(shell, params[3], len, out, |into| {
+        tftp::get(server, name, into)
+    })
+}
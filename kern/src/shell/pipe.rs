@@ -0,0 +1,157 @@
+use shim::io;
+
+use stack_vec::StackVec;
+
+/// The number of bytes of output a pipeline stage can pass to the next.
+pub const PIPE_BUF_SIZE: usize = 4096;
+
+/// A fixed-size buffer connecting two stages of a pipeline.
+///
+/// Stages run one after another, so a stage's entire output is held in the
+/// pipe until the next stage reads it. Writing more than the pipe can hold is
+/// an error rather than a reason to allocate.
+pub struct Pipe<'a> {
+    buf: StackVec<'a, u8>,
+    /// The offset of the next byte to be read.
+    read: usize,
+}
+
+impl<'a> Pipe<'a> {
+    /// Returns a new, empty pipe using `storage` as its buffer.
+    pub fn new(storage: &'a mut [u8]) -> Pipe<'a> {
+        Pipe {
+            buf: StackVec::new(storage),
+            read: 0,
+        }
+    }
+
+    /// Discards the pipe's contents, read or not.
+    pub fn clear(&mut self) {
+        self.buf.truncate(0);
+        self.read = 0;
+    }
+}
+
+impl<'a> io::Read for Pipe<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let unread = &self.buf.as_slice()[self.read..];
+        let n = unread.len().min(buf.len());
+        buf[..n].copy_from_slice(&unread[..n]);
+        self.read += n;
+        Ok(n)
+    }
+}
+
+impl<'a> io::Write for Pipe<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf
+            .extend_from_slice(buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "pipe buffer full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What became of one stage of a pipeline.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// The stage ran successfully; the pipeline goes on to the next one.
+    Ran,
+    /// The stage ran `exit`; the pipeline ends successfully.
+    Exited,
+    /// The stage failed; the pipeline ends unsuccessfully.
+    Failed,
+}
+
+/// Runs the `count` stages of a pipeline one after another, each reading the
+/// output of the one before it. `run_stage(i, input, output)` runs stage
+/// `i`; `output` is `None` for the last stage, whose output is not piped.
+///
+/// The first stage that fails or exits ends the pipeline. Returns `true` if
+/// no stage failed.
+pub fn run_stages<F>(count: usize, mut run_stage: F) -> bool
+where
+    F: FnMut(usize, &mut Pipe, Option<&mut Pipe>) -> Stage,
+{
+    let mut pipe_bufs = [[0u8; PIPE_BUF_SIZE]; 2];
+    let [input_buf, output_buf] = &mut pipe_bufs;
+    let mut input = Pipe::new(input_buf);
+    let mut output = Pipe::new(output_buf);
+    for i in 0..count {
+        output.clear();
+        let stage = if i + 1 == count {
+            run_stage(i, &mut input, None)
+        } else {
+            run_stage(i, &mut input, Some(&mut output))
+        };
+
+        match stage {
+            Stage::Failed => return false,
+            Stage::Exited => return true,
+            Stage::Ran => core::mem::swap(&mut input, &mut output),
+        }
+    }
+    true
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{run_stages, Pipe, Stage};
+    use shim::io::{Read, Write};
+
+    #[test]
+    fn bounded_round_trip() {
+        let mut storage = [0u8; 8];
+        let mut pipe = Pipe::new(&mut storage);
+        assert_eq!(pipe.write(b"hello").unwrap(), 5);
+        assert_eq!(pipe.write(b" world").unwrap(), 3);
+        assert!(pipe.write(b"!").is_err());
+
+        let mut out = [0u8; 5];
+        assert_eq!(pipe.read(&mut out).unwrap(), 5);
+        assert_eq!(&out, b"hello");
+        assert_eq!(pipe.read(&mut out).unwrap(), 3);
+        assert_eq!(&out[..3], b" wo");
+        assert_eq!(pipe.read(&mut out).unwrap(), 0);
+
+        pipe.clear();
+        assert_eq!(pipe.write(b"again").unwrap(), 5);
+        assert_eq!(pipe.read(&mut out).unwrap(), 5);
+    }
+
+    #[test]
+    fn failed_stage_stops_pipeline() {
+        let mut ran = [false; 3];
+        let succeeded = run_stages(3, |i, input, output| {
+            ran[i] = true;
+            let mut buf = [0u8; 8];
+            match (i, output) {
+                (0, Some(output)) => {
+                    output.write_all(b"a").unwrap();
+                    Stage::Ran
+                }
+                (1, Some(_)) => {
+                    assert_eq!(input.read(&mut buf).unwrap(), 1);
+                    Stage::Failed
+                }
+                _ => Stage::Ran,
+            }
+        });
+        assert!(!succeeded);
+        assert_eq!(ran, [true, true, false]);
+
+        let mut ran = [false; 3];
+        assert!(run_stages(3, |i, _, _| {
+            ran[i] = true;
+            Stage::Exited
+        }));
+        assert_eq!(ran, [true, false, false]);
+
+        assert!(run_stages(3, |i, _, output| {
+            assert_eq!(output.is_none(), i == 2);
+            Stage::Ran
+        }));
+    }
+}
@@ -0,0 +1,347 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use kernel_api::SIGTERM;
+use shim::io;
+
+use crate::console::kprintln;
+use crate::process::{self, exec, signal, Info, IDLE_ID, WNOHANG};
+use crate::smp;
+use crate::SCHEDULER;
+use crate::VMM;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::fs::resolve;
+use super::Shell;
+
+/// How long `spin` spins when no duration is given, in seconds.
+const SPIN_DEFAULT_SECS: u64 = 10;
+
+/// How long `top` measures for when no duration is given, in seconds.
+const TOP_DEFAULT_SECS: u64 = 1;
+
+/// Lists the processes with their nice values, the core running each one,
+/// how long each has run for and how many times it has been switched from:
+/// each core's running process, then the others ready to run on it in the
+/// order they became ready, then the rest.
+pub fn ps(
+    _: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    writeln!(
+        out,
+        "{:>5} {:>5} {:>3} {:>3}  {:<8} {:>10} {:>8}  {}",
+        "PID", "PPID", "NI", "CPU", "STATE", "TIME", "SWITCHES", "NAME"
+    )?;
+    for process in SCHEDULER.processes() {
+        let parent = process
+            .parent
+            .map_or(String::from("-"), |id| id.to_string());
+        let core = process
+            .core
+            .map_or(String::from("-"), |core| core.to_string());
+        writeln!(
+            out,
+            "{:>5} {:>5} {:>3} {:>3}  {:<8} {:>10} {:>8}  {}",
+            process.id,
+            parent,
+            process.nice,
+            core,
+            process.state,
+            CpuTime(process.cpu_time),
+            process.switches,
+            process.name
+        )?;
+    }
+    Ok(())
+}
+
+/// Measures how much of the processors' time each process uses over a
+/// number of seconds, one by default, then lists the processes that ran,
+/// busiest first, after how busy the cores were overall.
+pub fn top(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let secs = match command.params().get(0).map(|arg| arg.parse()) {
+        None => TOP_DEFAULT_SECS,
+        Some(Ok(secs)) if secs > 0 => secs,
+        Some(_) => fail!("invalid duration: {} (in seconds)", command.params()[0]),
+    };
+
+    let before = SCHEDULER.processes();
+    let interval = process::sleep(Duration::from_secs(secs));
+    let after = SCHEDULER.processes();
+    let usage = usage(&before, &after);
+
+    let cores = smp::cores();
+    let idle: Duration = usage
+        .iter()
+        .filter(|(process, _)| process.id == IDLE_ID)
+        .map(|&(_, used)| used)
+        .sum();
+    let busy = 100 - percent(idle, interval * cores as u32).min(100);
+    writeln!(
+        out,
+        "{} cores, {}% busy over {} s",
+        cores,
+        busy,
+        CpuTime(interval)
+    )?;
+
+    writeln!(
+        out,
+        "{:>5} {:>3} {:>4} {:>10} {:>8}  {}",
+        "PID", "CPU", "%CPU", "TIME", "SWITCHES", "NAME"
+    )?;
+    for (process, used) in usage {
+        if used == Duration::from_secs(0) || process.id == IDLE_ID {
+            continue;
+        }
+        let core = process
+            .core
+            .map_or(String::from("-"), |core| core.to_string());
+        writeln!(
+            out,
+            "{:>5} {:>3} {:>4} {:>10} {:>8}  {}",
+            process.id,
+            core,
+            percent(used, interval),
+            CpuTime(process.cpu_time),
+            process.switches,
+            process.name
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns each process in `after` with the CPU time it used since the
+/// snapshot `before` was taken, busiest first. A process with the same ID
+/// and name as one in `before` is taken to be the same one: the idle
+/// processes share an ID.
+fn usage<'a>(before: &[Info], after: &'a [Info]) -> Vec<(&'a Info, Duration)> {
+    let mut usage: Vec<_> = after
+        .iter()
+        .map(|process| {
+            let earlier = before
+                .iter()
+                .find(|p| p.id == process.id && p.name == process.name)
+                .map_or(Duration::from_secs(0), |p| p.cpu_time);
+            let used = process.cpu_time.checked_sub(earlier);
+            (process, used.unwrap_or(process.cpu_time))
+        })
+        .collect();
+    usage.sort_by(|a, b| b.1.cmp(&a.1));
+    usage
+}
+
+/// Returns `part` as a whole percentage of `whole`.
+fn percent(part: Duration, whole: Duration) -> u64 {
+    (part.as_micros() * 100 / whole.as_micros().max(1)) as u64
+}
+
+/// A process's CPU time, formatted in minutes and seconds to the hundredth.
+struct CpuTime(Duration);
+
+impl fmt::Display for CpuTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let time = format!(
+            "{}:{:02}.{:02}",
+            secs / 60,
+            secs % 60,
+            self.0.subsec_millis() / 10
+        );
+        f.pad(&time)
+    }
+}
+
+/// Waits for a process started by the shell to exit, or any of them if no
+/// ID is given, and prints its exit status.
+pub fn wait(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let pid = match command.params().get(0).map(|arg| arg.parse()) {
+        None => None,
+        Some(Ok(pid)) => Some(pid),
+        Some(Err(_)) => fail!("invalid process ID: {}", command.params()[0]),
+    };
+
+    match process::waitpid(pid, 0) {
+        Some((id, status)) => writeln!(out, "process {} exited with status {}", id, status)?,
+        None => fail!("no such child process"),
+    }
+    Ok(())
+}
+
+/// Sends a signal to a process: `SIGTERM`, unless another is given by number.
+pub fn kill(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    let pid = match params[0].parse() {
+        Ok(pid) => pid,
+        Err(_) => fail!("invalid process ID: {}", params[0]),
+    };
+    let signal = match params.get(1).map(|arg| arg.parse()) {
+        None => SIGTERM,
+        Some(Ok(signal)) => signal,
+        Some(Err(_)) => fail!("invalid signal: {}", params[1]),
+    };
+
+    if let Err(e) = SCHEDULER.kill(pid, signal) {
+        fail!("{}: {}", pid, e);
+    }
+    Ok(())
+}
+
+/// Sets the nice value of the process given by the second argument to the
+/// first: the lower it is, the sooner the process runs.
+pub fn renice(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    let nice = match params[0].parse() {
+        Ok(nice) => nice,
+        Err(_) => fail!("invalid nice value: {}", params[0]),
+    };
+    let pid = match params[1].parse() {
+        Ok(pid) => pid,
+        Err(_) => fail!("invalid process ID: {}", params[1]),
+    };
+
+    if let Err(e) = SCHEDULER.set_nice(pid, nice) {
+        fail!("{}: {}", pid, e);
+    }
+    Ok(())
+}
+
+/// Loads an ELF executable from the file system and runs it in user mode in
+/// a new process, given the command's arguments, the first of which names
+/// it, then waits for it to exit. An exit status other than 0 is reported as
+/// a failure. Meanwhile, `Ctrl-C` sends it `SIGINT`.
+pub fn exec(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let path = resolve(&shell.cwd, command.params()[0]);
+    let result = exec::read_program(&path)
+        .and_then(|bytes| exec::load(&bytes, command.params(), VMM.new_user_space()));
+    let image = match result {
+        Ok(image) => image,
+        Err(e) => fail!("{}: {}", path.display(), e),
+    };
+
+    let name = command.params()[0].rsplit('/').next().unwrap_or_default();
+    let id = match SCHEDULER.start(name, image) {
+        Some(id) => id,
+        None => fail!("out of memory"),
+    };
+
+    // `Ctrl-C` interrupts the program until it exits.
+    signal::set_foreground(Some(id));
+    let collected = process::waitpid(Some(id), 0);
+    signal::set_foreground(None);
+    if let Some((_, status)) = collected {
+        if status != 0 {
+            fail!("{}: exited with status {}", path.display(), status);
+        }
+    }
+    Ok(())
+}
+
+/// Collects the processes started by the shell that have exited since the
+/// last prompt, reporting each one's exit status.
+pub fn reap_finished() {
+    while let Some((id, status)) = process::waitpid(None, WNOHANG) {
+        kprintln!("[{}] exited with status {}", id, status);
+    }
+}
+
+/// Starts a kernel thread that keeps the processor busy for a while without
+/// ever yielding, to show that the shell keeps running alongside it.
+pub fn spin(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let secs = match command.params().get(0).map(|arg| arg.parse()) {
+        None => SPIN_DEFAULT_SECS,
+        Some(Ok(secs)) => secs,
+        Some(Err(_)) => fail!("invalid duration: {} (in seconds)", command.params()[0]),
+    };
+
+    let spinner = move || pi::timer::spin_sleep(Duration::from_secs(secs));
+    match SCHEDULER.spawn("spin", spinner) {
+        Some(id) => writeln!(out, "spin: started process {}", id)?,
+        None => fail!("out of memory"),
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use core::time::Duration;
+
+    use super::{percent, usage, CpuTime};
+    use crate::process::{Info, State};
+
+    fn info(id: u64, name: &str, ms: u64) -> Info {
+        Info {
+            id,
+            parent: None,
+            name: String::from(name),
+            state: State::Ready,
+            nice: 0,
+            core: None,
+            cpu_time: Duration::from_millis(ms),
+            switches: 0,
+        }
+    }
+
+    #[test]
+    fn cpu_usage() {
+        let before = [info(0, "idle", 100), info(0, "idle1", 50), info(2, "a", 10)];
+        let after = [
+            info(0, "idle", 400),
+            info(0, "idle1", 900),
+            info(2, "a", 610),
+            info(3, "b", 20),
+        ];
+        let used: Vec<_> = usage(&before, &after)
+            .into_iter()
+            .map(|(p, used)| (p.name.as_str(), used.as_millis()))
+            .collect();
+        assert_eq!(used, [("idle1", 850), ("a", 600), ("idle", 300), ("b", 20)]);
+
+        let second = Duration::from_secs(1);
+        assert_eq!(percent(Duration::from_millis(255), second), 25);
+
+        let time = Duration::from_millis(61_237);
+        assert_eq!(CpuTime(time).to_string(), "1:01.23");
+        assert_eq!(format!("{:>8}", CpuTime(time)), " 1:01.23");
+    }
+}
@@ -0,0 +1,250 @@
+use alloc::alloc::{alloc_zeroed, dealloc};
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::fmt;
+use core::ops::Range;
+use core::slice;
+
+use shim::io;
+
+use crate::aarch64;
+use crate::elf::{self, ProgramHeader, ET_DYN, PF_W, PF_X, PT_DYNAMIC, PT_LOAD};
+use crate::vm::{page_align, Attributes, PAGE_SIZE};
+use crate::VMM;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::fs::{read_file, resolve};
+use super::Shell;
+
+/// The largest program `run` will load.
+const MAX_PROGRAM_SIZE: u64 = 16 << 20;
+
+/// The smallest alignment a program is loaded at: a page, so that
+/// page-relative addressing (`adrp`) works and its code can be mapped apart
+/// from its data.
+const MIN_LOAD_ALIGN: usize = PAGE_SIZE;
+
+/// The signature of a program's entry point. The value returned is the
+/// program's exit status.
+type Entry = extern "C" fn() -> i32;
+
+/// Memory holding a loaded program, freed when dropped.
+///
+/// The kernel's memory is never both writable and executable, so the pages
+/// holding the program's code are made read-only and executable by
+/// `map_code()`, and given back to the heap as ordinary RAM.
+struct Image {
+    ptr: *mut u8,
+    layout: Layout,
+    /// The page aligned ranges of offsets into the image holding code.
+    code: Vec<Range<usize>>,
+    /// Whether `code` is currently mapped executable.
+    mapped: bool,
+}
+
+impl Image {
+    /// Allocates `size` zeroed bytes, rounded up to whole pages, aligned to
+    /// at least `MIN_LOAD_ALIGN`.
+    fn new(size: usize, align: usize) -> io::Result<Image> {
+        let size = page_align(size.max(1)).ok_or(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bad segment size",
+        ))?;
+        let layout = Layout::from_size_align(size, align.max(MIN_LOAD_ALIGN))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad segment alignment"))?;
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(io::Error::new(io::ErrorKind::Other, "out of memory"));
+        }
+        Ok(Image {
+            ptr,
+            layout,
+            code: Vec::new(),
+            mapped: false,
+        })
+    }
+
+    /// Returns the address the image is loaded at.
+    fn base(&self) -> usize {
+        self.ptr as usize
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+
+    /// Makes the pages holding the program's code read-only and executable.
+    fn map_code(&mut self) {
+        for range in &self.code {
+            VMM.set_attributes(
+                self.base() + range.start,
+                range.end - range.start,
+                Attributes::KERNEL_TEXT,
+            );
+        }
+        self.mapped = true;
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        if self.mapped {
+            VMM.set_attributes(self.base(), self.layout.size(), Attributes::KERNEL_RAM);
+        }
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+/// Loads a program from the file system and runs it to completion. An exit
+/// status other than 0 is reported as a failure.
+///
+/// The program may be a flat binary, which is entered at its first byte, or a
+/// position-independent ELF executable. Either way it must be able to run at
+/// whatever address it is loaded at, and it runs in the kernel's context: it
+/// is entered as an `extern "C" fn() -> i32` whose return value is the exit
+/// status. Its code is read-only: a flat binary cannot write to itself, and an
+/// ELF executable's code may not share a page with writable data.
+pub fn run(
+    shell: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let path = resolve(&shell.cwd, command.params()[0]);
+    let result = read_file(&path, MAX_PROGRAM_SIZE)
+        .map_err(LoadError::Io)
+        .and_then(|bytes| load(&bytes));
+    let (mut image, entry) = match result {
+        Ok(loaded) => loaded,
+        Err(e) => fail!("{}: {}", path.display(), e),
+    };
+
+    aarch64::sync_instruction_cache(image.base(), image.layout.size());
+    image.map_code();
+    let entry: Entry = unsafe { core::mem::transmute(entry) };
+    let status = entry();
+    drop(image);
+
+    if status != 0 {
+        fail!("{}: exited with status {}", path.display(), status);
+    }
+    Ok(())
+}
+
+/// Loads the program `bytes` into memory, returning the memory it occupies
+/// and the address of its entry point.
+fn load(bytes: &[u8]) -> Result<(Image, usize), LoadError> {
+    if !elf::is_elf(bytes) {
+        let mut image = Image::new(bytes.len(), MIN_LOAD_ALIGN)?;
+        image.as_mut_slice()[..bytes.len()].copy_from_slice(bytes);
+        image.code.push(0..image.layout.size());
+        let entry = image.base();
+        return Ok((image, entry));
+    }
+
+    load_elf(bytes)
+}
+
+/// Errors from loading a program.
+enum LoadError {
+    Elf(elf::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Elf(e) => write!(f, "{}", e),
+            LoadError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<elf::Error> for LoadError {
+    fn from(e: elf::Error) -> LoadError {
+        LoadError::Elf(e)
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+/// Loads the position-independent ELF executable `bytes` into memory,
+/// applying its relocations.
+fn load_elf(bytes: &[u8]) -> Result<(Image, usize), LoadError> {
+    let header = elf::parse_header(bytes)?;
+    if header.kind != ET_DYN {
+        // Fixed-address executables need an address space of their own.
+        return Err(elf::Error::Unsupported("not position-independent").into());
+    }
+
+    let mut size = 0;
+    let mut align = MIN_LOAD_ALIGN;
+    let mut dynamic: Option<ProgramHeader> = None;
+    for i in 0..header.phnum {
+        let ph = elf::program_header(bytes, &header, i)?;
+        match ph.kind {
+            PT_LOAD => {
+                let end = ph
+                    .vaddr
+                    .checked_add(ph.memsz)
+                    .ok_or(elf::Error::Truncated)?;
+                if ph.filesz > ph.memsz || end > MAX_PROGRAM_SIZE {
+                    return Err(elf::Error::Unsupported("bad segment size").into());
+                }
+                size = size.max(end as usize);
+                align = align.max(ph.align as usize);
+            }
+            PT_DYNAMIC => dynamic = Some(ph),
+            _ => (),
+        }
+    }
+
+    let mut image = Image::new(size, align)?;
+    let base = image.base();
+    let mut writable = Vec::new();
+    for i in 0..header.phnum {
+        let ph = elf::program_header(bytes, &header, i)?;
+        if ph.kind != PT_LOAD {
+            continue;
+        }
+
+        let (offset, filesz, vaddr) = (ph.offset as usize, ph.filesz as usize, ph.vaddr as usize);
+        let data = bytes
+            .get(offset..offset.saturating_add(filesz))
+            .ok_or(elf::Error::Truncated)?;
+        image.as_mut_slice()[vaddr..vaddr + filesz].copy_from_slice(data);
+
+        // `size` bounds the segment, so aligning its end cannot overflow
+        let pages = vaddr & !(PAGE_SIZE - 1)..page_align(vaddr + ph.memsz as usize).unwrap();
+        if ph.flags & PF_X != 0 && ph.flags & PF_W != 0 {
+            return Err(elf::Error::Unsupported("writable and executable segment").into());
+        } else if ph.flags & PF_X != 0 {
+            image.code.push(pages);
+        } else if ph.flags & PF_W != 0 {
+            writable.push(pages);
+        }
+    }
+
+    let overlaps = |a: &Range<usize>, b: &Range<usize>| a.start < b.end && b.start < a.end;
+    if writable
+        .iter()
+        .any(|w| image.code.iter().any(|code| overlaps(w, code)))
+    {
+        return Err(elf::Error::Unsupported("code shares a page with writable data").into());
+    }
+
+    if let Some(dynamic) = dynamic {
+        elf::relocate(image.as_mut_slice(), &dynamic, base as u64)?;
+    }
+
+    let entry = header.entry as usize;
+    if entry >= size {
+        return Err(elf::Error::Unsupported("entry point outside of the program").into());
+    }
+    Ok((image, base + entry))
+}
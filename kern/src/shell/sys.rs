@@ -0,0 +1,368 @@
+use alloc::format;
+use core::time::Duration;
+
+use shim::io;
+
+use pi::atags::{Atag, Atags};
+use pi::interrupt::Interrupt;
+
+use crate::aarch64;
+use crate::clock::{self, DateTime};
+use crate::console::{kprintln, kprintln_nolock, log};
+use crate::irq::IrqGuard;
+use crate::process;
+use crate::profile;
+use crate::smp;
+use crate::symbols;
+use crate::traps::stats::{self, Counter};
+use crate::traps::{self, Kind};
+use crate::FILESYSTEM;
+
+use super::builtins::{fail, Error};
+use super::command::Command;
+use super::mem::{parse_number, Size};
+use super::Shell;
+
+/// Prints each boot tag the firmware passed to the kernel.
+pub fn lsatag(
+    _: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    for tag in Atags::get() {
+        match tag {
+            Atag::Core(core) => writeln!(
+                out,
+                "core      flags {:#x}, page size {}, root device {:#x}",
+                core.flags,
+                Size(core.page_size as usize),
+                core.root_dev
+            )?,
+            Atag::Mem(mem) => {
+                let (start, size) = (mem.start as usize, mem.size as usize);
+                writeln!(
+                    out,
+                    "mem       {:#010x}-{:#010x} ({})",
+                    start,
+                    start + size,
+                    Size(size)
+                )?
+            }
+            Atag::Serial(_) => writeln!(out, "serial    {:016x}", tag.serial().unwrap_or(0))?,
+            Atag::Revision(revision) => writeln!(out, "revision  {:#x}", revision.rev)?,
+            Atag::Cmd(cmd) => writeln!(out, "cmdline   {}", cmd)?,
+            Atag::Unknown(id) => writeln!(out, "unknown   {:#010x}", id)?,
+            Atag::None => (),
+        }
+    }
+    Ok(())
+}
+
+/// Prints the time elapsed since boot and the number of ticks counted.
+pub fn uptime(
+    _: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let uptime = clock::uptime();
+    let secs = uptime.as_secs();
+    writeln!(
+        out,
+        "up {}d {:02}:{:02}:{:02}.{:03}, {} ticks",
+        secs / 86400,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        uptime.subsec_millis(),
+        clock::ticks()
+    )?;
+    Ok(())
+}
+
+/// Prints the kernel's log, which crash reports include the end of.
+pub fn dmesg(
+    _: &mut Shell,
+    _: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    out.write_all(&log::contents())?;
+    Ok(())
+}
+
+/// Prints how many times each kind and class of exception and each interrupt
+/// has happened, and when the last one did, or resets the counts.
+pub fn irqstat(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    match command.params().get(0) {
+        None => (),
+        Some(&"reset") => {
+            stats::reset();
+            return Ok(());
+        }
+        Some(arg) => fail!("unknown argument: {}", arg),
+    }
+
+    let row = |out: &mut dyn io::Write, name: &str, counter: &Counter| match counter.last() {
+        Some(last) => writeln!(
+            out,
+            "  {:<18} {:>10}  last at {}.{:06}s",
+            name,
+            counter.count(),
+            last.as_secs(),
+            last.subsec_micros()
+        ),
+        None => writeln!(out, "  {:<18} {:>10}", name, counter.count()),
+    };
+
+    writeln!(out, "exceptions:")?;
+    for &(name, kind) in &[
+        ("synchronous", Kind::Synchronous),
+        ("irq", Kind::Irq),
+        ("fiq", Kind::Fiq),
+        ("serror", Kind::SError),
+    ] {
+        row(out, name, stats::by_kind(kind))?;
+    }
+
+    writeln!(out, "synchronous exceptions by class:")?;
+    for class in 0..stats::CLASSES as u32 {
+        let counter = stats::by_class(class);
+        if counter.count() == 0 {
+            continue;
+        }
+        match traps::class_name(class) {
+            Some(name) => row(out, name, counter)?,
+            None => row(out, &format!("class {:#04x}", class), counter)?,
+        }
+    }
+
+    writeln!(out, "interrupts:")?;
+    for int in Interrupt::iter() {
+        row(out, &format!("{:?}", int), stats::by_interrupt(int))?;
+    }
+    Ok(())
+}
+
+/// Prints the current date and time or, given a date and time, sets the clock.
+pub fn date(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    match command.params() {
+        [] => match clock::now() {
+            Some(now) => writeln!(out, "{} UTC", now)?,
+            None => fail!("clock not set; use: date YYYY-MM-DD HH:MM:SS"),
+        },
+        [datetime] => set_date(datetime, DateTime::parse(datetime))?,
+        [date, time] => set_date(date, DateTime::from_parts(date, time))?,
+        _ => return Err(Error::Usage),
+    }
+    Ok(())
+}
+
+/// Sets the clock to `now`, or fails if `arg` is not a valid date or the RTC
+/// could not be set.
+fn set_date(arg: &str, now: Option<DateTime>) -> Result<(), Error> {
+    match now.map(clock::set) {
+        Some(Ok(())) => Ok(()),
+        Some(Err(e)) => fail!("clock set, but not the RTC: {:?}", e),
+        None => fail!("invalid date: {}", arg),
+    }
+}
+
+/// Waits for the given number of milliseconds.
+pub fn sleep(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let arg = command.params()[0];
+    match arg.parse() {
+        Ok(ms) => {
+            process::sleep(Duration::from_millis(ms));
+        }
+        Err(_) => fail!("invalid duration: {} (in milliseconds)", arg),
+    }
+    Ok(())
+}
+
+/// The number of ranges of code `profile report` shows when no count is given.
+const PROFILE_DEFAULT_ROWS: usize = 20;
+
+/// Starts or stops the sampling profiler, or shows where the samples so far
+/// fell.
+pub fn profile(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    let params = command.params();
+    match params[0] {
+        "start" if !profile::start() => fail!("already running"),
+        "stop" if !profile::stop() => fail!("not running"),
+        "start" | "stop" => (),
+        "report" => {
+            let rows = match params.get(1) {
+                Some(arg) => match parse_number(arg) {
+                    Some(rows) => rows,
+                    None => fail!("invalid count: {}", arg),
+                },
+                None => PROFILE_DEFAULT_ROWS,
+            };
+            match profile::report() {
+                Some(report) => write_profile(out, &report, rows)?,
+                None => fail!("nothing sampled yet"),
+            }
+        }
+        arg => fail!("unknown argument: {}", arg),
+    }
+    Ok(())
+}
+
+/// Writes `report` for `profile report`, with its `rows` hottest ranges of
+/// code.
+fn write_profile(out: &mut dyn io::Write, report: &profile::Report, rows: usize) -> io::Result<()> {
+    // Percentages of the samples, to a tenth of a percent.
+    let percent = |hits: u64| {
+        let tenths = hits * 1000 / report.total.max(1);
+        (tenths / 10, tenths % 10)
+    };
+    let (user, elsewhere) = (percent(report.user), percent(report.elsewhere));
+    let state = if profile::is_running() { " so far" } else { "" };
+    writeln!(
+        out,
+        "{} samples in {}.{:03}s{}",
+        report.total,
+        report.elapsed.as_secs(),
+        report.elapsed.subsec_millis(),
+        state
+    )?;
+    writeln!(
+        out,
+        "{}.{}% in user programs, {}.{}% in the kernel outside its code",
+        user.0, user.1, elsewhere.0, elsewhere.1
+    )?;
+
+    writeln!(out, "{:>10} {:>6}  code", "samples", "%")?;
+    for &(addr, hits) in report.hot.iter().take(rows) {
+        let (whole, tenths) = percent(hits);
+        write!(
+            out,
+            "{:>10} {:>3}.{}%  {:#x}-{:#x}",
+            hits,
+            whole,
+            tenths,
+            addr,
+            addr + profile::BUCKET_SIZE
+        )?;
+        match symbols::lookup(addr) {
+            Some((symbol, offset)) => writeln!(out, "  {}+{:#x}", symbol.name, offset)?,
+            None => writeln!(out)?,
+        }
+    }
+    Ok(())
+}
+
+/// Shows or sets whether breakpoints stop at the debug prompt or in the GDB
+/// stub, or hits a breakpoint.
+pub fn debug(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+) -> Result<(), Error> {
+    match command.params().get(0) {
+        None => {
+            let state = if traps::gdb::is_enabled() {
+                "gdb"
+            } else if traps::debug::is_enabled() {
+                "on"
+            } else {
+                "off"
+            };
+            writeln!(out, "debug prompt on breakpoints: {}", state)?;
+        }
+        Some(&"on") => {
+            traps::gdb::set_enabled(false);
+            traps::debug::set_enabled(true);
+        }
+        Some(&"off") => {
+            traps::gdb::set_enabled(false);
+            traps::debug::set_enabled(false);
+        }
+        Some(&"gdb") => {
+            traps::debug::set_enabled(false);
+            traps::gdb::set_enabled(true);
+        }
+        Some(&"brk") => {
+            #[cfg(target_os = "none")]
+            unsafe {
+                core::arch::asm!("brk #0", options(nomem, nostack));
+            }
+        }
+        Some(arg) => fail!("unknown argument: {}", arg),
+    }
+    Ok(())
+}
+
+/// Writes cached file system changes to the disk and resets the board.
+pub fn reboot(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    sync_before(command, "rebooting")?;
+    pi::pm::reset();
+}
+
+/// Writes cached file system changes to the disk and stops the kernel: every
+/// core, and with them every process. The board stays powered until it is
+/// reset or unplugged.
+pub fn halt(
+    _: &mut Shell,
+    command: &Command,
+    _: &mut dyn io::Read,
+    _: &mut dyn io::Write,
+) -> Result<(), Error> {
+    sync_before(command, "halting")?;
+
+    // Nothing may run from here on, and the other cores may have been
+    // stopped holding the console's lock.
+    smp::stop_other_cores();
+    let _guard = IrqGuard::new();
+    kprintln_nolock!("System halted. It is now safe to remove power.");
+    loop {
+        aarch64::wfe();
+    }
+}
+
+/// Syncs the file system ahead of `action`. Fails unless it is safe to go
+/// ahead, which is always the case when the command was passed `-f`.
+fn sync_before(command: &Command, action: &str) -> Result<(), Error> {
+    let force = match command.params() {
+        [] => false,
+        ["-f"] => true,
+        _ => return Err(Error::Usage),
+    };
+
+    match FILESYSTEM.sync() {
+        Ok(()) => Ok(()),
+        Err(e) if force => {
+            kprintln!("{}: sync failed: {}; {} anyway", command.path(), e, action);
+            Ok(())
+        }
+        Err(e) => fail!("sync failed: {}; not {} (use -f to force)", e, action),
+    }
+}
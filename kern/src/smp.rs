@@ -0,0 +1,139 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use pi::common::{NCORES, SPINNING_BASE};
+use pi::local_interrupt::LocalController;
+
+use crate::aarch64;
+use crate::clock;
+use crate::console::{kprintln, kprintln_nolock};
+use crate::mutex::Mutex;
+use crate::process::{Stack, KERNEL_STACK_SIZE};
+use crate::vm::virt_to_phys;
+use crate::{SCHEDULER, VMM};
+
+/// How long `start_cores()` waits for each core to start.
+const START_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long `stop_other_cores()` waits for each core to stop.
+const STOP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The mailbox of each core that `stop_other_cores()` writes to, which
+/// interrupts the core.
+pub const STOP_MAILBOX: usize = 0;
+
+/// Whether each core is running the kernel.
+static RUNNING: [AtomicBool; NCORES] = [
+    AtomicBool::new(true),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+
+/// The stack of each secondary core, from `start_cores()` until the core
+/// takes it in `kmain_secondary()`.
+static STACKS: Mutex<[Option<Stack>; NCORES]> = Mutex::new([None, None, None, None]);
+
+/// Starts the secondary cores, which the firmware parked at boot, spinning on
+/// their slots of the spin table at `SPINNING_BASE`: gives each a stack and
+/// releases it to `_start_secondary` in `init.s`, which sets it up as core 0
+/// was and calls `kmain_secondary()`. The scheduler must be initialized.
+/// Returns the number of cores running, reporting any that did not start.
+pub fn start_cores() -> usize {
+    extern "C" {
+        static mut __core_stacks: [u64; NCORES];
+        fn _start_secondary();
+    }
+
+    LocalController::new(0).enable_mailbox(STOP_MAILBOX);
+
+    for core in 1..NCORES {
+        let stack = match Stack::new(KERNEL_STACK_SIZE) {
+            Some(stack) => stack,
+            None => {
+                kprintln!("smp: no memory for core {}'s stack", core);
+                break;
+            }
+        };
+
+        // The core reads these with its MMU and caches off.
+        unsafe {
+            __core_stacks[core] = virt_to_phys(stack.top()) as u64;
+            aarch64::clean_data_cache(&__core_stacks[core] as *const _ as usize, 8);
+            STACKS.lock()[core] = Some(stack);
+
+            let slot = SPINNING_BASE.add(core);
+            slot.write_volatile(virt_to_phys(_start_secondary as usize));
+            aarch64::clean_data_cache(slot as usize, 8);
+        }
+        aarch64::sev();
+
+        let start = clock::uptime();
+        while !RUNNING[core].load(Ordering::Acquire) {
+            if clock::uptime() - start > START_TIMEOUT {
+                kprintln!("smp: core {} did not start", core);
+                break;
+            }
+        }
+    }
+
+    cores()
+}
+
+/// Returns the number of cores running the kernel.
+pub fn cores() -> usize {
+    RUNNING
+        .iter()
+        .filter(|running| running.load(Ordering::Relaxed))
+        .count()
+}
+
+/// Where a secondary core continues once `init.s` has set it up: installs the
+/// kernel's translation tables, starts the core's tick and makes the core's
+/// boot code its idle process, leaving it to run processes.
+pub fn kmain_secondary() -> ! {
+    let core = aarch64::affinity();
+    let stack = STACKS.lock()[core]
+        .take()
+        .expect("secondary core started without a stack");
+
+    VMM.initialize_core();
+    clock::start_core_tick();
+    LocalController::new(core).enable_mailbox(STOP_MAILBOX);
+    RUNNING[core].store(true, Ordering::Release);
+    SCHEDULER.run_core(stack)
+}
+
+/// Stops every core but this one for good, so that nothing but the code
+/// calling this runs from then on: interrupts each with its `STOP_MAILBOX`,
+/// on which it parks itself in `stop()`. Waits for each to stop, reporting
+/// any that did not, such as one spinning with IRQs masked.
+pub fn stop_other_cores() {
+    let this = aarch64::affinity();
+    for core in (0..NCORES).filter(|&core| core != this) {
+        if RUNNING[core].load(Ordering::Acquire) {
+            LocalController::new(core).send(STOP_MAILBOX, 1);
+        }
+    }
+
+    for core in (0..NCORES).filter(|&core| core != this) {
+        let start = clock::uptime();
+        while RUNNING[core].load(Ordering::Acquire) {
+            if clock::uptime() - start > STOP_TIMEOUT {
+                kprintln_nolock!("smp: core {} did not stop", core);
+                break;
+            }
+        }
+    }
+}
+
+/// Parks this core for good, with IRQs masked. Called by the IRQ handler when
+/// `stop_other_cores()` writes to the core's `STOP_MAILBOX`.
+pub fn stop() -> ! {
+    let core = aarch64::affinity();
+    LocalController::new(core).clear_mailbox(STOP_MAILBOX, !0);
+    RUNNING[core].store(false, Ordering::Release);
+    loop {
+        aarch64::wfe();
+    }
+}
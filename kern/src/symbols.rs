@@ -0,0 +1,255 @@
+//! The kernel's symbol table: the name, address and size of each function in
+//! the kernel's code, so that addresses in it can be printed by name.
+//!
+//! The table is embedded in the kernel's image, in the `.ksyms` section,
+//! which is `TABLE_SIZE` bytes of zeroes when the kernel is linked, since the
+//! functions' addresses are only known then. `bin/gen-ksyms.py` fills it in
+//! from the ELF's own symbol table afterwards, as `make build` does. The
+//! table of a kernel built otherwise is empty.
+//!
+//! The table starts with a header of four little-endian `u32`s: `MAGIC`, the
+//! number of symbols, the length of their names and zero. An entry for each
+//! symbol follows, in address order: its offset from the start of the
+//! kernel's code, its size, and the offset and length of its name, each a
+//! little-endian `u32`. Then come the names, in UTF-8.
+
+use core::fmt;
+
+/// The size of the space reserved for the table, in bytes.
+pub const TABLE_SIZE: usize = 192 << 10;
+
+/// What a filled-in table starts with.
+const MAGIC: &[u8; 4] = b"KSYM";
+
+/// The size of the table's header, in bytes.
+const HEADER_SIZE: usize = 16;
+/// The size of each entry, in bytes.
+const ENTRY_SIZE: usize = 16;
+
+/// The space for the table. It is read through `__ksyms_beg`, since the
+/// compiler would take it to hold zeroes.
+#[used]
+#[link_section = ".ksyms"]
+static TABLE: [u8; TABLE_SIZE] = [0; TABLE_SIZE];
+
+/// A function in the kernel's code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub addr: usize,
+    pub size: usize,
+}
+
+impl Symbol<'_> {
+    /// Returns `true` if `addr` is in the function.
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.addr && addr - self.addr < self.size
+    }
+}
+
+/// A symbol table, laid out as the module describes, for code at `base`.
+#[derive(Debug, Copy, Clone)]
+pub struct Table<'a> {
+    base: usize,
+    entries: &'a [u8],
+    names: &'a [u8],
+}
+
+/// Reads the little-endian `u32` at `offset` in `bytes`.
+fn read_u32(bytes: &[u8], offset: usize) -> usize {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word) as usize
+}
+
+impl<'a> Table<'a> {
+    /// Returns the table in `bytes`, for code at `base`, or `None` if `bytes`
+    /// do not hold one.
+    pub fn parse(base: usize, bytes: &'a [u8]) -> Option<Table<'a>> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return None;
+        }
+
+        let count = read_u32(bytes, 4);
+        let names_len = read_u32(bytes, 8);
+        let names_start = HEADER_SIZE.checked_add(count.checked_mul(ENTRY_SIZE)?)?;
+        let names_end = names_start.checked_add(names_len)?;
+        if names_end > bytes.len() {
+            return None;
+        }
+
+        Some(Table {
+            base,
+            entries: &bytes[HEADER_SIZE..names_start],
+            names: &bytes[names_start..names_end],
+        })
+    }
+
+    /// Returns the number of symbols in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_SIZE
+    }
+
+    /// Returns `true` if the table has no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the `i`th symbol, in address order. A symbol whose name is
+    /// not in the table is given an empty one.
+    pub fn get(&self, i: usize) -> Option<Symbol<'a>> {
+        if i >= self.len() {
+            return None;
+        }
+
+        let entry = &self.entries[i * ENTRY_SIZE..(i + 1) * ENTRY_SIZE];
+        let (start, len) = (read_u32(entry, 8), read_u32(entry, 12));
+        let name = self
+            .names
+            .get(start..start.saturating_add(len))
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .unwrap_or("");
+        Some(Symbol {
+            name,
+            addr: self.base + read_u32(entry, 0),
+            size: read_u32(entry, 4),
+        })
+    }
+
+    /// Returns the function `addr` is in, if any, and how far into it `addr`
+    /// is.
+    pub fn lookup(&self, addr: usize) -> Option<(Symbol<'a>, usize)> {
+        // The number of symbols that start at or before `addr`.
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.get(mid)?.addr <= addr {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        let symbol = self.get(low.checked_sub(1)?)?;
+        if symbol.contains(addr) {
+            Some((symbol, addr - symbol.addr))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the first function named `name`, if any.
+    pub fn find(&self, name: &str) -> Option<Symbol<'a>> {
+        (0..self.len())
+            .filter_map(|i| self.get(i))
+            .find(|symbol| symbol.name == name)
+    }
+}
+
+/// Returns the kernel's symbol table, or `None` if it was not filled in.
+pub fn table() -> Option<Table<'static>> {
+    #[cfg(target_os = "none")]
+    {
+        extern "C" {
+            static __text_beg: u8;
+            static __ksyms_beg: u8;
+            static __ksyms_end: u8;
+        }
+
+        let _ = &TABLE;
+        unsafe {
+            let start = &__ksyms_beg as *const u8;
+            let len = &__ksyms_end as *const u8 as usize - start as usize;
+            let bytes = core::slice::from_raw_parts(start, len);
+            Table::parse(&__text_beg as *const u8 as usize, bytes)
+        }
+    }
+
+    #[cfg(not(target_os = "none"))]
+    None
+}
+
+/// Returns the kernel's function `addr` is in, if any, and how far into it
+/// `addr` is.
+pub fn lookup(addr: usize) -> Option<(Symbol<'static>, usize)> {
+    table()?.lookup(addr)
+}
+
+/// An address, displayed with the function of the kernel's that it is in,
+/// if any, as in `0xffffff8000081234 <kernel::kmain+0x14>`.
+#[derive(Debug, Copy, Clone)]
+pub struct Symbolized(pub usize);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)?;
+        match lookup(self.0) {
+            Some((symbol, offset)) => write!(f, " <{}+{:#x}>", symbol.name, offset),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// Returns a table of the symbols `(offset, size, name)`.
+    fn table(symbols: &[(u32, u32, &str)]) -> Vec<u8> {
+        let names: String = symbols.iter().map(|&(_, _, name)| name).collect();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        for &word in &[symbols.len() as u32, names.len() as u32, 0] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+
+        let mut start = 0;
+        for &(offset, size, name) in symbols {
+            for &word in &[offset, size, start, name.len() as u32] {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+            start += name.len() as u32;
+        }
+        bytes.extend_from_slice(names.as_bytes());
+        bytes.resize(bytes.len() + 32, 0);
+        bytes
+    }
+
+    #[test]
+    fn lookup() {
+        let bytes = table(&[
+            (0, 0x10, "_start"),
+            (0x20, 0x8, "kinit"),
+            (0x28, 0x40, "kmain"),
+        ]);
+        let table = Table::parse(0x8_0000, &bytes).unwrap();
+        assert_eq!(table.len(), 3);
+
+        let kinit = Symbol {
+            name: "kinit",
+            addr: 0x8_0020,
+            size: 0x8,
+        };
+        assert_eq!(table.lookup(0x8_0024), Some((kinit, 4)));
+        assert_eq!(table.lookup(0x8_0000).map(|(s, _)| s.name), Some("_start"));
+        assert_eq!(
+            table.lookup(0x8_0067).map(|(s, o)| (s.name, o)),
+            Some(("kmain", 0x3f))
+        );
+        assert_eq!(table.lookup(0x8_0014), None);
+        assert_eq!(table.lookup(0x8_0068), None);
+        assert_eq!(table.lookup(0x7_fffc), None);
+        assert_eq!(table.find("kmain").map(|s| s.addr), Some(0x8_0028));
+        assert_eq!(table.find("main"), None);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Table::parse(0, &[0; 64]).is_none());
+
+        let mut bytes = table(&[(0, 4, "f")]);
+        bytes[4] = 100;
+        assert!(Table::parse(0, &bytes).is_none());
+        assert!(Table::parse(0, &bytes[..8]).is_none());
+    }
+}
@@ -0,0 +1,328 @@
+pub mod debug;
+mod frame;
+pub mod gdb;
+pub mod stats;
+mod syndrome;
+pub mod syscall;
+
+use crate::aarch64::FAR_EL1;
+use crate::console::{kprintln_nolock, log};
+use crate::oops;
+use crate::process;
+use crate::symbols::Symbolized;
+use crate::vm::{self, AccessKind, FaultError};
+use crate::{IRQ, SCHEDULER, VMM};
+
+pub use self::frame::TrapFrame;
+pub use self::syndrome::{class_name, Fault, Syndrome};
+
+/// The kind of an exception, from its position in the vector table.
+#[repr(u16)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Kind {
+    Synchronous = 0,
+    Irq = 1,
+    Fiq = 2,
+    SError = 3,
+}
+
+/// Where an exception was taken from, from its position in the vector table.
+#[repr(u16)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Source {
+    CurrentSpEl0 = 0,
+    CurrentSpElx = 1,
+    LowerAArch64 = 2,
+    LowerAArch32 = 3,
+}
+
+/// Information about an exception, passed to `handle_exception` by its
+/// vector.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Info {
+    pub source: Source,
+    pub kind: Kind,
+}
+
+/// Handles an exception taken to EL1. `info` says which vector took it, `esr`
+/// is the value of `ESR_EL1` and `tf` is the interrupted state, which is
+/// restored, with any changes made here, when this function returns. Every
+/// exception is counted in `stats`.
+///
+/// IRQs and FIQs are dispatched to the handlers registered with `IRQ`, after
+/// which an IRQ switches processes if the running one's time slice is over.
+/// Breakpoints and single steps stop in the GDB stub if it catches them, as
+/// `gdb` describes. Other breakpoints are reported and skipped, then stop at
+/// the debug prompt if it is enabled; other single steps always stop at it.
+/// System calls are handled by `syscall::handle_syscall()`, which may switch
+/// processes. Translation faults in the regions of the active user address
+/// space map a zeroed page, and writes to pages shared copy-on-write copy them,
+/// before retrying the access. Any other synchronous exception a user program
+/// causes is reported and ends its process with the exit status
+/// `process::KILLED`. Any other exception is reported, naming the stack if it
+/// hit a stack's guard page, along with an oops, and stops the kernel, since
+/// the interrupted code cannot safely continue.
+///
+/// Before returning to user mode, the signals pending for the process are
+/// acted on, as `GlobalScheduler::deliver_signals()` describes.
+///
+/// Returns the trap frame to restore: `tf`, or the frame of the process to
+/// switch to.
+#[no_mangle]
+pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame) -> *mut TrapFrame {
+    let tf = handle(info, esr, tf);
+    SCHEDULER.deliver_signals(tf)
+}
+
+/// Called by `context_save` in `init.s` once it is on the stack of the trap
+/// frame `handle_exception()` returned, just before restoring it: this core
+/// has left the kernel stack of any process it switched from, which other
+/// cores may now run. FIQs are left out, since they never switch and may have
+/// interrupted the scheduler.
+#[no_mangle]
+pub extern "C" fn exception_restoring(info: Info) {
+    if info.kind != Kind::Fiq {
+        SCHEDULER.switched();
+    }
+}
+
+/// Handles an exception for `handle_exception()`, but for signals.
+fn handle(info: Info, esr: u32, tf: &mut TrapFrame) -> *mut TrapFrame {
+    stats::record_exception(info, esr);
+    if info.kind == Kind::Irq {
+        IRQ.dispatch(tf);
+        return SCHEDULER.preempt(tf);
+    } else if info.kind == Kind::Fiq {
+        IRQ.dispatch_fiq(tf);
+        return tf;
+    } else if info.kind != Kind::Synchronous {
+        fatal(info, esr, tf);
+    }
+
+    let syndrome = Syndrome::from(esr);
+    if resolve_user_fault(info, esr, syndrome) {
+        return tf;
+    }
+
+    match syndrome {
+        Syndrome::Brk(_) | Syndrome::Step if gdb::catches(syndrome) => gdb::stop(tf, syndrome),
+        Syndrome::Brk(imm) => {
+            kprintln_nolock!("breakpoint: brk #{} at {:#x}", imm, tf.elr);
+            tf.elr += 4;
+            if debug::is_enabled() {
+                debug::prompt(tf);
+            }
+        }
+        // `ELR_EL1` points at the next instruction to execute.
+        Syndrome::Step => {
+            kprintln_nolock!("step: stopped at {:#x}", tf.elr);
+            debug::prompt(tf);
+        }
+        Syndrome::Svc(num) => return syscall::handle_syscall(num, tf),
+        _ if from_user(info) => {
+            kprintln_nolock!(
+                "process {} killed: {:?} at {:#x}",
+                SCHEDULER.current().unwrap_or_default(),
+                syndrome,
+                tf.elr
+            );
+            return SCHEDULER.exit(tf, process::KILLED);
+        }
+        _ => fatal(info, esr, tf),
+    }
+    tf
+}
+
+/// Returns `true` if the exception was taken from EL0.
+fn from_user(info: Info) -> bool {
+    match info.source {
+        Source::LowerAArch64 | Source::LowerAArch32 => true,
+        Source::CurrentSpEl0 | Source::CurrentSpElx => false,
+    }
+}
+
+/// Resolves an abort that is a translation fault on user memory, or a
+/// permission fault on a write to it, with the active user address space,
+/// returning `true` if the faulting access can be retried. A fault the address
+/// space does not allow is reported.
+fn resolve_user_fault(info: Info, esr: u32, syndrome: Syndrome) -> bool {
+    let from_user = from_user(info);
+    let access = match syndrome {
+        Syndrome::DataAbort {
+            kind: Fault::Translation,
+            write,
+            ..
+        } => {
+            if write {
+                AccessKind::Write
+            } else {
+                AccessKind::Read
+            }
+        }
+        Syndrome::DataAbort {
+            kind: Fault::Permission,
+            write: true,
+            ..
+        } => AccessKind::Write,
+        // The kernel never runs user code.
+        Syndrome::InstructionAbort {
+            kind: Fault::Translation,
+            ..
+        } if from_user => AccessKind::Execute,
+        _ => return false,
+    };
+    if !syndrome::far_is_valid(esr) {
+        return false;
+    }
+
+    let far = FAR_EL1::read() as usize;
+    match VMM.handle_user_fault(far, access) {
+        Ok(()) => true,
+        Err(FaultError::StackOverflow) if from_user => {
+            kprintln_nolock!("stack overflow in the user program at {:#x}", far);
+            false
+        }
+        Err(FaultError::MemoryLimit) if from_user => {
+            kprintln_nolock!(
+                "the user program at {:#x} exceeded its memory limit of {} KiB",
+                far,
+                VMM.set_user_memory_limit(None).unwrap_or_default() / 1024
+            );
+            false
+        }
+        Err(e) if from_user => {
+            kprintln_nolock!("segmentation fault at {:#x}: {}", far, e);
+            false
+        }
+        // `report()` describes the kernel's own faults well enough.
+        Err(_) => false,
+    }
+}
+
+/// Reports an exception the kernel cannot recover from, then an oops for it,
+/// and stops the kernel.
+fn fatal(info: Info, esr: u32, tf: &TrapFrame) -> ! {
+    log::freeze();
+    report(info, esr, tf);
+    oops::exception(info, esr, tf);
+    halt();
+}
+
+/// Prints a description of an exception and the state it interrupted.
+///
+/// The exception may have interrupted code holding the console lock, so this
+/// only uses the lock-free printer.
+fn report(info: Info, esr: u32, tf: &TrapFrame) {
+    kprintln_nolock!("");
+    kprintln_nolock!("---------- EXCEPTION ----------");
+    kprintln_nolock!("{:?} exception from {:?}", info.kind, info.source);
+    kprintln_nolock!("  at {}", Symbolized(tf.elr as usize));
+    if let Some(far) = overflow_address(info, esr) {
+        match vm::stack_overflow_in(far) {
+            Some(stack) => kprintln_nolock!("stack overflow in {}", stack),
+            None => {
+                if let Some(name) = SCHEDULER.stack_overflow_in(far) {
+                    kprintln_nolock!("stack overflow in {}", name);
+                }
+            }
+        }
+    }
+    if info.kind == Kind::Synchronous {
+        let syndrome = Syndrome::from(esr);
+        kprintln_nolock!("  {:?}", syndrome);
+        report_abort(info, esr, syndrome, tf);
+    }
+    kprintln_nolock!(
+        "  esr  {:#010x} (class {:#04x}, iss {:#09x})",
+        esr,
+        syndrome::class(esr),
+        syndrome::iss(esr)
+    );
+    kprintln_nolock!("  far  {:#018x}", FAR_EL1::read());
+    dump_registers(tf);
+}
+
+/// Returns the address a data abort from the kernel faulted on, which may be
+/// in the guard page below a stack, if the exception is one.
+fn overflow_address(info: Info, esr: u32) -> Option<usize> {
+    match Syndrome::from(esr) {
+        Syndrome::DataAbort { .. }
+            if info.kind == Kind::Synchronous
+                && !from_user(info)
+                && syndrome::far_is_valid(esr) =>
+        {
+            Some(FAR_EL1::read() as usize)
+        }
+        _ => None,
+    }
+}
+
+/// If `syndrome` is an abort, prints what was being accessed, where and by
+/// whom, and why the access faulted.
+fn report_abort(info: Info, esr: u32, syndrome: Syndrome, tf: &TrapFrame) {
+    let (access, kind, level) = match syndrome {
+        Syndrome::DataAbort {
+            kind,
+            level,
+            write: true,
+        } => ("write to", kind, level),
+        Syndrome::DataAbort { kind, level, .. } => ("read from", kind, level),
+        Syndrome::InstructionAbort { kind, level } => ("instruction fetch from", kind, level),
+        _ => return,
+    };
+
+    let by = match info.source {
+        Source::LowerAArch64 | Source::LowerAArch32 => "user code",
+        Source::CurrentSpEl0 | Source::CurrentSpElx => "the kernel",
+    };
+    if syndrome::far_is_valid(esr) {
+        kprintln_nolock!(
+            "  {} {:#x} by {} at {:#x}",
+            access,
+            FAR_EL1::read(),
+            by,
+            tf.elr
+        );
+    } else {
+        kprintln_nolock!("  {} an unknown address by {} at {:#x}", access, by, tf.elr);
+    }
+
+    if kind.has_level() {
+        kprintln_nolock!("  {} at translation level {}", kind, level);
+    } else {
+        kprintln_nolock!("  {}", kind);
+    }
+}
+
+/// Prints the registers saved in `tf`.
+fn dump_registers(tf: &TrapFrame) {
+    kprintln_nolock!("  elr  {:#018x}  spsr {:#018x}", tf.elr, tf.spsr);
+    kprintln_nolock!("  sp0  {:#018x}  tpidr {:#018x}", tf.sp, tf.tpidr);
+    for (i, pair) in tf.x.chunks(2).enumerate() {
+        match *pair {
+            [a, b] => kprintln_nolock!(
+                "  x{:<2}  {:#018x}  x{:<2}  {:#018x}",
+                2 * i,
+                a,
+                2 * i + 1,
+                b
+            ),
+            [a] => kprintln_nolock!("  x{:<2}  {:#018x}", 2 * i, a),
+            _ => (),
+        }
+    }
+}
+
+/// Stops the kernel after an exception it cannot recover from. Kernel threads
+/// may hold locks or have left shared data half updated, so an abort in one
+/// stops everything rather than only the code at fault. A user program's
+/// exceptions only end its own process.
+fn halt() -> ! {
+    loop {
+        #[cfg(target_os = "none")]
+        unsafe {
+            core::arch::asm!("wfe", options(nomem, nostack));
+        }
+    }
+}
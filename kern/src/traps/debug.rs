@@ -0,0 +1,109 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use pi::uart::MiniUart;
+use stack_vec::StackString;
+
+use crate::aarch64::MDSCR_EL1;
+use crate::console::{kprint_nolock, kprintln_nolock};
+
+use super::TrapFrame;
+
+/// The debug exception mask bit of `SPSR_EL1`.
+const SPSR_D: u64 = 1 << 9;
+/// The software step bit of `SPSR_EL1`: set to step the next instruction.
+const SPSR_SS: u64 = 1 << 21;
+
+/// The longest command the debug prompt reads.
+const MAX_LINE: usize = 64;
+
+/// Whether breakpoints stop at the debug prompt.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether breakpoints stop at the debug prompt. When disabled,
+/// breakpoints are only reported.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns `true` if breakpoints stop at the debug prompt.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Stops the code interrupted at `tf` and reads debug commands until told to
+/// resume it, either freely or for a single instruction.
+///
+/// The prompt runs in the exception handler with interrupts masked, so it
+/// polls the UART directly rather than going through the console.
+pub fn prompt(tf: &mut TrapFrame) {
+    stop_stepping(tf);
+
+    let mut uart = MiniUart::new();
+    let mut buf = [0u8; MAX_LINE];
+    loop {
+        kprint_nolock!("debug {:#x}> ", tf.elr);
+        match read_line(&mut uart, &mut buf) {
+            "" => (),
+            "c" | "continue" => return,
+            "s" | "step" => {
+                start_stepping(tf);
+                return;
+            }
+            "r" | "regs" => super::dump_registers(tf),
+            "h" | "help" => {
+                kprintln_nolock!("c, continue  resume execution");
+                kprintln_nolock!("s, step      execute one instruction and stop again");
+                kprintln_nolock!("r, regs      print the interrupted registers");
+            }
+            other => kprintln_nolock!("unknown command: {} (try `help`)", other),
+        }
+    }
+}
+
+/// Reads a line into `buf`, echoing it, and returns it without surrounding
+/// whitespace. Input past the end of `buf` is dropped.
+fn read_line<'a>(uart: &mut MiniUart, buf: &'a mut [u8]) -> &'a str {
+    let mut line = StackString::new(buf);
+    loop {
+        match uart.read_byte() {
+            b'\r' | b'\n' => break,
+            8 | 127 => {
+                if line.pop().is_some() {
+                    kprint_nolock!("\x08 \x08");
+                }
+            }
+            byte @ b' '..=b'~' => {
+                if line.push(byte as char).is_ok() {
+                    uart.write_byte(byte);
+                }
+            }
+            _ => (),
+        }
+    }
+    kprintln_nolock!();
+
+    line.into_str().trim()
+}
+
+/// Arranges for a step exception to be taken after the interrupted code
+/// executes one more instruction.
+pub(super) fn start_stepping(tf: &mut TrapFrame) {
+    #[cfg(target_os = "none")]
+    unsafe {
+        // Debug exceptions are never taken while the OS lock is set, which it
+        // is out of reset.
+        core::arch::asm!("msr OSLAR_EL1, xzr", options(nomem, nostack));
+        MDSCR_EL1::modify(|mdscr| mdscr | MDSCR_EL1::SS | MDSCR_EL1::KDE);
+    }
+
+    tf.spsr = (tf.spsr | SPSR_SS) & !SPSR_D;
+}
+
+/// Stops single-stepping, masking debug exceptions in the interrupted code
+/// again.
+pub(super) fn stop_stepping(tf: &mut TrapFrame) {
+    unsafe {
+        MDSCR_EL1::modify(|mdscr| mdscr & !MDSCR_EL1::SS);
+    }
+    tf.spsr = (tf.spsr | SPSR_D) & !SPSR_SS;
+}
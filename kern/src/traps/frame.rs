@@ -0,0 +1,49 @@
+/// The state of the interrupted code, saved on the stack by `context_save`
+/// when an exception is taken and restored by `context_restore` when the
+/// handler returns. Changes a handler makes take effect on return.
+///
+/// The layout must match the offsets used in `init.s`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TrapFrame {
+    /// The address execution resumes at: `ELR_EL1`.
+    pub elr: u64,
+    /// The saved processor state: `SPSR_EL1`.
+    pub spsr: u64,
+    /// The EL0 stack pointer: `SP_EL0`.
+    pub sp: u64,
+    /// The EL0 thread ID register: `TPIDR_EL0`.
+    pub tpidr: u64,
+    /// The general purpose registers `x0` through `x30`.
+    pub x: [u64; 31],
+    /// The floating point control register: `FPCR`.
+    pub fpcr: u32,
+    /// The floating point status register: `FPSR`.
+    pub fpsr: u32,
+    /// The SIMD and floating point registers `q0` through `q31`.
+    pub q: [u128; 32],
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::TrapFrame;
+    use core::mem::{align_of, size_of};
+
+    #[test]
+    fn layout() {
+        // `init.s` hard-codes these offsets.
+        let frame = TrapFrame::default();
+        let base = &frame as *const _ as usize;
+        let offset = |field: usize| field - base;
+
+        assert_eq!(offset(&frame.elr as *const _ as usize), 0);
+        assert_eq!(offset(&frame.tpidr as *const _ as usize), 24);
+        assert_eq!(offset(&frame.x[0] as *const _ as usize), 32);
+        assert_eq!(offset(&frame.x[30] as *const _ as usize), 272);
+        assert_eq!(offset(&frame.fpcr as *const _ as usize), 280);
+        assert_eq!(offset(&frame.fpsr as *const _ as usize), 284);
+        assert_eq!(offset(&frame.q[0] as *const _ as usize), 288);
+        assert_eq!(size_of::<TrapFrame>(), 800);
+        assert_eq!(size_of::<TrapFrame>() % align_of::<TrapFrame>(), 0);
+    }
+}
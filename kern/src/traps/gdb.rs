@@ -0,0 +1,727 @@
+//! A stub speaking GDB's remote serial protocol on the console's UART, so
+//! that GDB can debug the kernel and the user programs it runs.
+//!
+//! The stub takes over the core that stops in it, with IRQs masked, and
+//! serves GDB's requests until told to resume: it reads and writes the
+//! registers of the interrupted code and memory, plants breakpoints, which
+//! are `brk` instructions, and continues or single steps. The other cores
+//! carry on. Breakpoints in user programs are planted in the address space
+//! active on the stopped core.
+//!
+//! The kernel stops in the stub:
+//!
+//! * when GDB connects to the console's serial port, since the first packet
+//!   it sends starts with `MAGIC`, which the console watches for;
+//! * when GDB, once attached, interrupts it with `Ctrl-C`;
+//! * at any breakpoint or single step while GDB is attached;
+//! * at any breakpoint, and on a panic, once `set_enabled(true)` is called;
+//! * at `breakpoint()`.
+//!
+//! GDB's `detach` resumes the kernel with every breakpoint removed, and its
+//! `kill` resets the board.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use pi::uart::MiniUart;
+
+use crate::aarch64;
+use crate::console::{kprintln_nolock, BoundedWriter};
+use crate::mutex::Mutex;
+use crate::symbols::Symbolized;
+use crate::vm::{PAGE_SIZE, USER_BASE, USER_END};
+use crate::VMM;
+
+use super::{debug, Syndrome, TrapFrame};
+
+/// The immediate of the `brk` instructions the stub plants, and of the one
+/// `breakpoint()` executes.
+pub const BRK_IMM: u16 = 0xdb;
+
+/// What GDB sends first when it connects: the start of a `qSupported`
+/// packet.
+pub const MAGIC: &[u8] = b"$qSupported";
+
+/// The byte GDB sends to interrupt the running program.
+const INTERRUPT: u8 = 0x03;
+
+/// The signal GDB is told an interrupt stopped the kernel with.
+const SIGINT: u8 = 2;
+/// The signal GDB is told a breakpoint or single step stopped it with.
+const SIGTRAP: u8 = 5;
+
+/// The largest packet the stub reads or sends, which it tells GDB.
+const PACKET_SIZE: usize = 2048;
+
+/// The most bytes of memory one packet reads: two hex digits each.
+const MAX_READ: usize = PACKET_SIZE / 2;
+
+/// The most breakpoints planted at once.
+const MAX_BREAKPOINTS: usize = 32;
+
+/// GDB's numbers for the AArch64 registers that are not `x0` to `x30`.
+const REG_SP: usize = 31;
+const REG_PC: usize = 32;
+const REG_CPSR: usize = 33;
+const REG_V0: usize = 34;
+const REG_V31: usize = 65;
+const REG_FPSR: usize = 66;
+const REG_FPCR: usize = 67;
+/// The number of registers in a `g` packet.
+const NUM_REGS: usize = 68;
+
+/// The mode bits of `SPSR_EL1`, and their value for code at EL1 on `SP_EL1`.
+const SPSR_M: u64 = 0b1111;
+const SPSR_EL1H: u64 = 0b0101;
+
+/// How far above its trap frame the stack pointer of code interrupted at EL1
+/// on `SP_EL1` was: the size of the frame and of the two registers the
+/// vector pushes before it, as `init.s` saves them.
+const FRAME_SP_OFFSET: u64 = 816;
+
+/// Whether every breakpoint and panic stops in the stub.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether GDB is attached: it has talked to the stub and not detached.
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+
+/// A breakpoint: a planted `brk` and the instruction it replaced.
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    addr: usize,
+    original: [u8; 4],
+}
+
+/// The breakpoints planted.
+static BREAKPOINTS: Mutex<[Option<Breakpoint>; MAX_BREAKPOINTS]> =
+    Mutex::new([None; MAX_BREAKPOINTS]);
+
+/// Sets whether every breakpoint and panic stops in the stub. Otherwise only
+/// those GDB planted or those of `breakpoint()` do, or any while GDB is
+/// attached.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns `true` if every breakpoint and panic stops in the stub.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Stops in the stub, as a breakpoint GDB planted does.
+pub fn breakpoint() {
+    // The immediate is `BRK_IMM`.
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("brk #0xdb", options(nomem, nostack));
+    }
+}
+
+/// Returns `true` if the exception `syndrome` stops in the stub, rather than
+/// being handled as `traps` otherwise handles it.
+pub fn catches(syndrome: Syndrome) -> bool {
+    let attached = ATTACHED.load(Ordering::Relaxed);
+    match syndrome {
+        Syndrome::Brk(imm) => imm == BRK_IMM || attached || is_enabled(),
+        Syndrome::Step => attached,
+        _ => false,
+    }
+}
+
+/// Stops in the stub at the breakpoint or single step `syndrome` that
+/// interrupted `tf`, and returns once GDB resumes it. A `brk` compiled into
+/// the code is skipped, unlike one GDB planted: GDB expects its own to be
+/// where the program counter is, and removes them before resuming.
+pub fn stop(tf: &mut TrapFrame, syndrome: Syndrome) {
+    if let Syndrome::Brk(_) = syndrome {
+        if !is_planted(tf.elr as usize) {
+            tf.elr += 4;
+        }
+    }
+    serve(tf, SIGTRAP);
+}
+
+/// Stops in the stub at the code `tf` interrupted, as GDB's interrupt or
+/// `MAGIC` asks, and returns once GDB resumes it.
+pub fn interrupt(tf: &mut TrapFrame) {
+    serve(tf, SIGINT);
+}
+
+/// Watches the bytes received on the console for those that stop the kernel
+/// in the stub: `MAGIC`, or GDB's interrupt while it is attached.
+#[derive(Debug, Default)]
+pub struct Watcher {
+    /// How many bytes of `MAGIC` were just received.
+    matched: usize,
+}
+
+impl Watcher {
+    /// Returns a watcher that has received nothing.
+    pub fn new() -> Watcher {
+        Watcher::default()
+    }
+
+    /// Takes the next byte received, returning `true` if the kernel should
+    /// stop in the stub.
+    pub fn watch(&mut self, byte: u8) -> bool {
+        if byte == INTERRUPT && ATTACHED.load(Ordering::Relaxed) {
+            self.matched = 0;
+            return true;
+        }
+
+        self.matched = if byte == MAGIC[self.matched] {
+            self.matched + 1
+        } else if byte == MAGIC[0] {
+            1
+        } else {
+            0
+        };
+        if self.matched == MAGIC.len() {
+            self.matched = 0;
+            return true;
+        }
+        false
+    }
+}
+
+/// What to do once a packet is handled.
+enum Next {
+    /// Send the reply and read the next packet.
+    Reply,
+    /// Resume the interrupted code.
+    Resume,
+    /// Send the reply, then resume the interrupted code without GDB.
+    Detach,
+    /// Reset the board.
+    Kill,
+}
+
+/// Serves GDB's requests about the code interrupted at `tf`, which stopped
+/// with the signal `signal`, until GDB resumes it.
+fn serve(tf: &mut TrapFrame, signal: u8) {
+    debug::stop_stepping(tf);
+
+    let mut uart = MiniUart::new();
+    let mut packet = [0u8; PACKET_SIZE];
+    let mut storage = [0u8; PACKET_SIZE];
+    if ATTACHED.load(Ordering::Relaxed) {
+        let mut reply = BoundedWriter::new(&mut storage);
+        let _ = write!(reply, "S{:02x}", signal);
+        send(&mut uart, reply.as_bytes());
+    } else {
+        kprintln_nolock!(
+            "gdb: stopped at {}; attach GDB to this port",
+            Symbolized(tf.elr as usize)
+        );
+    }
+
+    loop {
+        let packet = receive(&mut uart, &mut packet);
+        ATTACHED.store(true, Ordering::Relaxed);
+
+        let mut reply = BoundedWriter::new(&mut storage);
+        match handle(tf, packet, signal, &mut reply) {
+            Next::Reply => send(&mut uart, reply.as_bytes()),
+            Next::Resume => return,
+            Next::Detach => {
+                send(&mut uart, reply.as_bytes());
+                remove_breakpoints();
+                ATTACHED.store(false, Ordering::Relaxed);
+                return;
+            }
+            Next::Kill => pi::pm::reset(),
+        }
+    }
+}
+
+/// Handles the packet `packet` about the code interrupted at `tf`, which
+/// stopped with the signal `signal`, writing the reply to `reply`. Packets
+/// the stub does not support get an empty reply.
+fn handle(tf: &mut TrapFrame, packet: &[u8], signal: u8, reply: &mut BoundedWriter) -> Next {
+    let (&command, args) = match packet.split_first() {
+        Some(split) => split,
+        None => return Next::Reply,
+    };
+
+    let ok = match command {
+        b'?' => {
+            let _ = write!(reply, "S{:02x}", signal);
+            return Next::Reply;
+        }
+        b'g' => {
+            for value in (0..NUM_REGS).filter_map(|n| register(tf, n)) {
+                write_hex(reply, value.bytes());
+            }
+            return Next::Reply;
+        }
+        b'G' => set_registers(tf, args),
+        b'p' => match parse_hex(args).and_then(|n| register(tf, n as usize)) {
+            Some(value) => {
+                write_hex(reply, value.bytes());
+                return Next::Reply;
+            }
+            None => false,
+        },
+        b'P' => match split_at(args, b'=') {
+            Some((n, value)) => match parse_hex(n) {
+                Some(n) => set_register(tf, n as usize, value),
+                None => false,
+            },
+            None => false,
+        },
+        b'm' => {
+            let mut buf = [0u8; MAX_READ];
+            match parse_range(args) {
+                Some((addr, len)) if len <= MAX_READ && read_memory(addr, &mut buf[..len]) => {
+                    write_hex(reply, &buf[..len]);
+                    return Next::Reply;
+                }
+                _ => false,
+            }
+        }
+        b'M' => {
+            let mut buf = [0u8; MAX_READ];
+            match split_at(args, b':') {
+                Some((range, data)) => match parse_range(range) {
+                    Some((addr, len)) if len <= MAX_READ => {
+                        decode_hex(data, &mut buf[..len]) && write_memory(addr, &buf[..len])
+                    }
+                    _ => false,
+                },
+                None => false,
+            }
+        }
+        b'c' | b's' => {
+            if !args.is_empty() {
+                match parse_hex(args) {
+                    Some(addr) => tf.elr = addr,
+                    None => return error(reply),
+                }
+            }
+            if command == b's' {
+                debug::start_stepping(tf);
+            }
+            return Next::Resume;
+        }
+        b'Z' | b'z' => match breakpoint_address(args) {
+            Some(addr) if command == b'Z' => insert_breakpoint(addr),
+            Some(addr) => remove_breakpoint(addr),
+            // Only software breakpoints are supported.
+            None => return Next::Reply,
+        },
+        b'D' => {
+            let _ = reply.write_str("OK");
+            return Next::Detach;
+        }
+        b'k' => return Next::Kill,
+        b'q' if args.starts_with(b"Supported") => {
+            let _ = write!(reply, "PacketSize={:x}", PACKET_SIZE);
+            return Next::Reply;
+        }
+        b'q' if args == b"Attached" => {
+            let _ = reply.write_str("1");
+            return Next::Reply;
+        }
+        _ => return Next::Reply,
+    };
+
+    if ok {
+        let _ = reply.write_str("OK");
+        Next::Reply
+    } else {
+        error(reply)
+    }
+}
+
+/// Writes an error reply to `reply`.
+fn error(reply: &mut BoundedWriter) -> Next {
+    let _ = reply.write_str("E01");
+    Next::Reply
+}
+
+/// Reads packets until one arrives intact, acknowledging each, and returns
+/// its data. Bytes outside packets are ignored, and a packet with the wrong
+/// checksum is refused so that GDB sends it again.
+fn receive<'a>(uart: &mut MiniUart, buf: &'a mut [u8]) -> &'a [u8] {
+    loop {
+        while uart.read_byte() != b'$' {}
+
+        let mut len = 0;
+        let mut sum = 0u8;
+        let mut fits = true;
+        loop {
+            match uart.read_byte() {
+                b'#' => break,
+                byte => {
+                    sum = sum.wrapping_add(byte);
+                    fits &= len < buf.len();
+                    if fits {
+                        buf[len] = byte;
+                        len += 1;
+                    }
+                }
+            }
+        }
+
+        let checksum = [uart.read_byte(), uart.read_byte()];
+        let mut expected = [0u8; 1];
+        if fits && decode_hex(&checksum, &mut expected) && expected[0] == sum {
+            uart.write_byte(b'+');
+            return &buf[..len];
+        }
+        uart.write_byte(b'-');
+    }
+}
+
+/// Sends a packet holding `data`, again each time GDB refuses it.
+fn send(uart: &mut MiniUart, data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    let mut checksum = [0u8; 2];
+    let mut writer = BoundedWriter::new(&mut checksum);
+    let _ = write!(writer, "{:02x}", sum);
+
+    loop {
+        uart.write_byte(b'$');
+        data.iter().for_each(|&byte| uart.write_byte(byte));
+        uart.write_byte(b'#');
+        writer
+            .as_bytes()
+            .iter()
+            .for_each(|&byte| uart.write_byte(byte));
+
+        loop {
+            match uart.read_byte() {
+                b'+' => return,
+                b'-' => break,
+                _ => (),
+            }
+        }
+    }
+}
+
+/// The value of a register, little-endian, in as many bytes as GDB expects.
+struct Register {
+    bytes: [u8; 16],
+    len: usize,
+}
+
+impl Register {
+    fn new(value: &[u8]) -> Register {
+        let mut bytes = [0; 16];
+        bytes[..value.len()].copy_from_slice(value);
+        Register {
+            bytes,
+            len: value.len(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Returns the value of GDB's register `n` in `tf`, if there is one.
+fn register(tf: &TrapFrame, n: usize) -> Option<Register> {
+    let register = match n {
+        0..=30 => Register::new(&tf.x[n].to_le_bytes()),
+        REG_SP => Register::new(&stack_pointer(tf).to_le_bytes()),
+        REG_PC => Register::new(&tf.elr.to_le_bytes()),
+        REG_CPSR => Register::new(&(tf.spsr as u32).to_le_bytes()),
+        REG_V0..=REG_V31 => Register::new(&tf.q[n - REG_V0].to_le_bytes()),
+        REG_FPSR => Register::new(&tf.fpsr.to_le_bytes()),
+        REG_FPCR => Register::new(&tf.fpcr.to_le_bytes()),
+        _ => return None,
+    };
+    Some(register)
+}
+
+/// Sets GDB's register `n` in `tf` to the hex digits `value`. Returns `false`
+/// if there is no such register or `value` is not a value for it. The stack
+/// pointer of code at EL1 on `SP_EL1` is not in the frame, so setting it does
+/// nothing.
+fn set_register(tf: &mut TrapFrame, n: usize, value: &[u8]) -> bool {
+    let len = match register(tf, n) {
+        Some(register) => register.len,
+        None => return false,
+    };
+    let mut bytes = [0u8; 16];
+    if !decode_hex(value, &mut bytes[..len]) {
+        return false;
+    }
+
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&bytes[..8]);
+    let low = u64::from_le_bytes(low);
+    match n {
+        0..=30 => tf.x[n] = low,
+        REG_SP if tf.spsr & SPSR_M == SPSR_EL1H => (),
+        REG_SP => tf.sp = low,
+        REG_PC => tf.elr = low,
+        REG_CPSR => tf.spsr = (tf.spsr & !0xffff_ffff) | (low & 0xffff_ffff),
+        REG_V0..=REG_V31 => tf.q[n - REG_V0] = u128::from_le_bytes(bytes),
+        REG_FPSR => tf.fpsr = low as u32,
+        REG_FPCR => tf.fpcr = low as u32,
+        _ => return false,
+    }
+    true
+}
+
+/// Sets every register in `tf` to the hex digits of a `G` packet, `values`.
+fn set_registers(tf: &mut TrapFrame, mut values: &[u8]) -> bool {
+    for n in 0..NUM_REGS {
+        let digits = register(tf, n).map_or(0, |r| 2 * r.len);
+        if values.len() < digits || !set_register(tf, n, &values[..digits]) {
+            return false;
+        }
+        values = &values[digits..];
+    }
+    true
+}
+
+/// Returns the stack pointer of the code interrupted at `tf`.
+fn stack_pointer(tf: &TrapFrame) -> u64 {
+    if tf.spsr & SPSR_M == SPSR_EL1H {
+        tf as *const TrapFrame as u64 + FRAME_SP_OFFSET
+    } else {
+        tf.sp
+    }
+}
+
+/// Returns `true` if `addr` is in the user address space.
+fn is_user(addr: usize) -> bool {
+    addr >= USER_BASE && addr < USER_END
+}
+
+/// Returns the addresses of the pages the `len` bytes at `addr` are on.
+fn pages(addr: usize, len: usize) -> impl Iterator<Item = usize> {
+    (addr & !(PAGE_SIZE - 1)..addr.saturating_add(len)).step_by(PAGE_SIZE)
+}
+
+/// Copies the bytes at `addr` to `buf`, returning `false` if any of them
+/// cannot be read.
+fn read_memory(addr: usize, buf: &mut [u8]) -> bool {
+    if is_user(addr) {
+        return VMM.read_user(addr, buf).is_ok();
+    }
+    if !pages(addr, buf.len()).all(|page| aarch64::can_access(page, false)) {
+        return false;
+    }
+
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = unsafe { ((addr + i) as *const u8).read_volatile() };
+    }
+    true
+}
+
+/// Copies `bytes` to `addr`, returning `false` if any of them cannot be
+/// written. The kernel's code is written as `VMManager::patch_text()` writes
+/// it, and user memory as any code is, so that instruction fetches see the
+/// change.
+fn write_memory(addr: usize, bytes: &[u8]) -> bool {
+    if is_user(addr) {
+        let written = VMM.write_user(addr, bytes).is_ok();
+        if written {
+            aarch64::sync_instruction_cache(addr, bytes.len());
+        }
+        return written;
+    }
+    if VMM.patch_text(addr, bytes) {
+        return true;
+    }
+    if !pages(addr, bytes.len()).all(|page| aarch64::can_access(page, true)) {
+        return false;
+    }
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        unsafe { ((addr + i) as *mut u8).write_volatile(byte) };
+    }
+    true
+}
+
+/// Returns the `brk #BRK_IMM` instruction.
+fn brk() -> [u8; 4] {
+    (0xd420_0000u32 | (BRK_IMM as u32) << 5).to_le_bytes()
+}
+
+/// Returns `true` if a breakpoint is planted at `addr`.
+fn is_planted(addr: usize) -> bool {
+    BREAKPOINTS
+        .lock()
+        .iter()
+        .flatten()
+        .any(|bp| bp.addr == addr)
+}
+
+/// Plants a breakpoint at `addr`, returning `false` if it cannot be.
+fn insert_breakpoint(addr: usize) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.iter().flatten().any(|bp| bp.addr == addr) {
+        return true;
+    }
+    let slot = match breakpoints.iter_mut().find(|bp| bp.is_none()) {
+        Some(slot) => slot,
+        None => return false,
+    };
+
+    let mut original = [0u8; 4];
+    if !read_memory(addr, &mut original) || !write_memory(addr, &brk()) {
+        return false;
+    }
+    *slot = Some(Breakpoint { addr, original });
+    true
+}
+
+/// Removes the breakpoint at `addr`, returning `false` if there is none.
+fn remove_breakpoint(addr: usize) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let slot = breakpoints
+        .iter_mut()
+        .find(|bp| matches!(bp, Some(bp) if bp.addr == addr));
+    match slot.and_then(|slot| slot.take()) {
+        Some(bp) => write_memory(bp.addr, &bp.original),
+        None => false,
+    }
+}
+
+/// Removes every breakpoint.
+fn remove_breakpoints() {
+    for slot in BREAKPOINTS.lock().iter_mut() {
+        if let Some(bp) = slot.take() {
+            write_memory(bp.addr, &bp.original);
+        }
+    }
+}
+
+/// Returns the address of a `Z` or `z` packet's arguments, `args`, for a
+/// software breakpoint: `0,addr,kind`.
+fn breakpoint_address(args: &[u8]) -> Option<usize> {
+    let mut fields = args.split(|&byte| byte == b',');
+    if fields.next() != Some(&b"0"[..]) {
+        return None;
+    }
+    fields.next().and_then(parse_hex).map(|addr| addr as usize)
+}
+
+/// Splits `bytes` at the first `separator`, which neither part includes.
+fn split_at(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let i = bytes.iter().position(|&byte| byte == separator)?;
+    Some((&bytes[..i], &bytes[i + 1..]))
+}
+
+/// Parses `addr,len`, both in hex.
+fn parse_range(args: &[u8]) -> Option<(usize, usize)> {
+    let (addr, len) = split_at(args, b',')?;
+    Some((parse_hex(addr)? as usize, parse_hex(len)? as usize))
+}
+
+/// Returns the value of the hex digit `digit`.
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a number of at most 16 hex digits.
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |value, &digit| {
+        Some(value << 4 | hex_digit(digit)? as u64)
+    })
+}
+
+/// Decodes pairs of hex digits, `digits`, into exactly the bytes of `out`.
+fn decode_hex(digits: &[u8], out: &mut [u8]) -> bool {
+    if digits.len() != 2 * out.len() {
+        return false;
+    }
+    for (byte, pair) in out.iter_mut().zip(digits.chunks(2)) {
+        match (hex_digit(pair[0]), hex_digit(pair[1])) {
+            (Some(high), Some(low)) => *byte = high << 4 | low,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Writes `bytes` to `reply` as pairs of hex digits.
+fn write_hex(reply: &mut BoundedWriter, bytes: &[u8]) {
+    for byte in bytes {
+        let _ = write!(reply, "{:02x}", byte);
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex(b"ffffff8000080000"), Some(0xffff_ff80_0008_0000));
+        assert_eq!(parse_hex(b"1A"), Some(0x1a));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"12g"), None);
+        assert_eq!(parse_range(b"80000,4"), Some((0x80000, 4)));
+
+        let mut bytes = [0u8; 2];
+        assert!(decode_hex(b"00d4", &mut bytes));
+        assert_eq!(bytes, [0x00, 0xd4]);
+        assert!(!decode_hex(b"00d", &mut bytes));
+        assert_eq!(brk(), [0x60, 0x1b, 0x20, 0xd4]);
+        assert_eq!(breakpoint_address(b"0,80010,4"), Some(0x80010));
+        assert_eq!(breakpoint_address(b"1,80010,4"), None);
+    }
+
+    #[test]
+    fn registers() {
+        let mut tf = TrapFrame::default();
+        tf.x[1] = 0x1122;
+        tf.elr = 0x80000;
+        tf.spsr = 0x3c4;
+        tf.sp = 0x7000;
+
+        let mut storage = [0u8; PACKET_SIZE];
+        let mut reply = BoundedWriter::new(&mut storage);
+        handle(&mut tf, b"p1", SIGTRAP, &mut reply);
+        assert_eq!(reply.as_str(), "2211000000000000");
+
+        let mut reply = BoundedWriter::new(&mut storage);
+        handle(&mut tf, b"g", SIGTRAP, &mut reply);
+        assert_eq!(reply.as_bytes().len(), 2 * (33 * 8 + 4 + 32 * 16 + 4 + 4));
+        let mut packet = [b'G'; PACKET_SIZE];
+        let len = reply.as_bytes().len();
+        packet[1..len + 1].copy_from_slice(reply.as_bytes());
+        assert_eq!(&reply.as_str()[31 * 16..32 * 16], "0070000000000000");
+
+        let mut other = TrapFrame::default();
+        let mut reply = BoundedWriter::new(&mut storage);
+        handle(&mut other, &packet[..len + 1], SIGTRAP, &mut reply);
+        assert_eq!(reply.as_str(), "OK");
+        assert_eq!(
+            (other.x[1], other.elr, other.spsr, other.sp),
+            (0x1122, 0x80000, 0x3c4, 0x7000)
+        );
+
+        let mut reply = BoundedWriter::new(&mut storage);
+        handle(&mut tf, b"P20=0400080000000000", SIGTRAP, &mut reply);
+        assert_eq!((reply.as_str(), tf.elr), ("OK", 0x80004));
+        let mut reply = BoundedWriter::new(&mut storage);
+        handle(&mut tf, b"P99=00", SIGTRAP, &mut reply);
+        assert_eq!(reply.as_str(), "E01");
+    }
+
+    #[test]
+    fn magic() {
+        let mut watcher = Watcher::new();
+        let stops: usize = b"ls $$qSupported:multiprocess+"
+            .iter()
+            .filter(|&&byte| watcher.watch(byte))
+            .count();
+        assert_eq!(stops, 1);
+        assert!(!watcher.watch(INTERRUPT));
+    }
+}
@@ -0,0 +1,129 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use pi::interrupt::Interrupt;
+
+use crate::clock;
+
+use super::{syndrome, Info, Kind};
+
+/// The number of exception kinds: the entries of each vector table group.
+pub const KINDS: usize = 4;
+/// The number of exception classes in `ESR_EL1`.
+pub const CLASSES: usize = 64;
+
+/// Counts occurrences of an event and remembers when the last one happened.
+pub struct Counter {
+    count: AtomicU64,
+    /// Microseconds since boot of the last occurrence, plus one, or zero if
+    /// there has been none.
+    last: AtomicU64,
+}
+
+impl Counter {
+    /// Returns a counter with no occurrences recorded.
+    pub const fn new() -> Counter {
+        Counter {
+            count: AtomicU64::new(0),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Records an occurrence at `now`, the time since boot.
+    pub fn record(&self, now: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.last
+            .store(now.as_micros() as u64 + 1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of occurrences recorded.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time since boot of the last occurrence, if any.
+    pub fn last(&self) -> Option<Duration> {
+        match self.last.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros - 1)),
+        }
+    }
+
+    /// Forgets every occurrence recorded.
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.last.store(0, Ordering::Relaxed);
+    }
+}
+
+const ZERO: Counter = Counter::new();
+
+/// Exceptions taken, by kind.
+static BY_KIND: [Counter; KINDS] = [ZERO; KINDS];
+/// Synchronous exceptions taken, by exception class.
+static BY_CLASS: [Counter; CLASSES] = [ZERO; CLASSES];
+/// Interrupts dispatched, by source, whether as an IRQ or the FIQ.
+static BY_INTERRUPT: [Counter; Interrupt::MAX] = [ZERO; Interrupt::MAX];
+
+/// Records an exception described by `info` with syndrome `esr`.
+pub fn record_exception(info: Info, esr: u32) {
+    let now = clock::uptime();
+    BY_KIND[info.kind as usize].record(now);
+    if info.kind == Kind::Synchronous {
+        BY_CLASS[syndrome::class(esr) as usize].record(now);
+    }
+}
+
+/// Records the dispatch of the interrupt `int`.
+pub fn record_interrupt(int: Interrupt) {
+    BY_INTERRUPT[int.to_index()].record(clock::uptime());
+}
+
+/// Returns the counter of exceptions of kind `kind`.
+pub fn by_kind(kind: Kind) -> &'static Counter {
+    &BY_KIND[kind as usize]
+}
+
+/// Returns the counter of synchronous exceptions of class `class`.
+pub fn by_class(class: u32) -> &'static Counter {
+    &BY_CLASS[class as usize % CLASSES]
+}
+
+/// Returns the counter of dispatches of the interrupt `int`.
+pub fn by_interrupt(int: Interrupt) -> &'static Counter {
+    &BY_INTERRUPT[int.to_index()]
+}
+
+/// Resets every counter.
+pub fn reset() {
+    let all = BY_KIND
+        .iter()
+        .chain(BY_CLASS.iter())
+        .chain(BY_INTERRUPT.iter());
+    for counter in all {
+        counter.reset();
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::Counter;
+    use core::time::Duration;
+
+    #[test]
+    fn counter() {
+        let counter = Counter::new();
+        assert_eq!(counter.count(), 0);
+        assert_eq!(counter.last(), None);
+
+        counter.record(Duration::from_micros(0));
+        assert_eq!(counter.last(), Some(Duration::from_micros(0)));
+        counter.record(Duration::from_millis(1500));
+        assert_eq!(counter.count(), 2);
+        assert_eq!(counter.last(), Some(Duration::from_millis(1500)));
+
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+        assert_eq!(counter.last(), None);
+    }
+}
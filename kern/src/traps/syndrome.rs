@@ -0,0 +1,224 @@
+use core::fmt;
+
+/// The kind of fault behind an instruction or data abort, from the fault
+/// status code in the syndrome.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Fault {
+    AddressSize,
+    Translation,
+    AccessFlag,
+    Permission,
+    Alignment,
+    TlbConflict,
+    External,
+    Other(u8),
+}
+
+impl From<u32> for Fault {
+    /// Decodes the fault status code in the low six bits of an abort's ISS.
+    fn from(iss: u32) -> Fault {
+        match iss & 0b11_1111 {
+            0b00_0000..=0b00_0011 => Fault::AddressSize,
+            0b00_0100..=0b00_0111 => Fault::Translation,
+            0b00_1000..=0b00_1011 => Fault::AccessFlag,
+            0b00_1100..=0b00_1111 => Fault::Permission,
+            0b01_0000 => Fault::External,
+            0b10_0001 => Fault::Alignment,
+            0b11_0000 => Fault::TlbConflict,
+            code => Fault::Other(code as u8),
+        }
+    }
+}
+
+impl Fault {
+    /// Returns `true` if faults of this kind happen at a particular level of
+    /// the translation table walk.
+    pub fn has_level(&self) -> bool {
+        match *self {
+            Fault::AddressSize | Fault::Translation | Fault::AccessFlag | Fault::Permission => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::AddressSize => write!(f, "address size fault"),
+            Fault::Translation => write!(f, "translation fault"),
+            Fault::AccessFlag => write!(f, "access flag fault"),
+            Fault::Permission => write!(f, "permission fault"),
+            Fault::Alignment => write!(f, "alignment fault"),
+            Fault::TlbConflict => write!(f, "TLB conflict"),
+            Fault::External => write!(f, "external abort"),
+            Fault::Other(code) => write!(f, "fault status {:#04x}", code),
+        }
+    }
+}
+
+/// The cause of a synchronous exception, decoded from `ESR_EL1`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Syndrome {
+    /// An undefined instruction, or any other exception with no more specific
+    /// class.
+    Unknown,
+    WfiWfe,
+    SimdFp,
+    IllegalExecutionState,
+    /// A system call made with `svc #imm`.
+    Svc(u16),
+    Hvc(u16),
+    Smc(u16),
+    MsrMrsSystem,
+    InstructionAbort {
+        kind: Fault,
+        level: u8,
+    },
+    PcAlignmentFault,
+    DataAbort {
+        kind: Fault,
+        level: u8,
+        write: bool,
+    },
+    SpAlignmentFault,
+    TrappedFpu,
+    SError,
+    Breakpoint,
+    Step,
+    Watchpoint,
+    /// A breakpoint instruction, `brk #imm`.
+    Brk(u16),
+    /// Any other exception class.
+    Other(u32),
+}
+
+/// Returns the exception class of the syndrome `esr`.
+pub fn class(esr: u32) -> u32 {
+    esr >> 26
+}
+
+/// Returns the instruction-specific syndrome of the syndrome `esr`.
+pub fn iss(esr: u32) -> u32 {
+    esr & 0x1ff_ffff
+}
+
+/// Returns a short name for the exception class `class`, or `None` if it is
+/// not one the kernel expects to see.
+pub fn class_name(class: u32) -> Option<&'static str> {
+    let name = match class {
+        0b00_0000 => "unknown",
+        0b00_0001 => "wfi/wfe",
+        0b00_0111 => "simd/fp",
+        0b00_1110 => "illegal state",
+        0b01_0101 => "svc",
+        0b01_0110 => "hvc",
+        0b01_0111 => "smc",
+        0b01_1000 => "msr/mrs",
+        0b10_0000 | 0b10_0001 => "instruction abort",
+        0b10_0010 => "pc alignment",
+        0b10_0100 | 0b10_0101 => "data abort",
+        0b10_0110 => "sp alignment",
+        0b10_1100 => "trapped fpu",
+        0b10_1111 => "serror",
+        0b11_0000 | 0b11_0001 => "breakpoint",
+        0b11_0010 | 0b11_0011 => "step",
+        0b11_0100 | 0b11_0101 => "watchpoint",
+        0b11_1100 => "brk",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Returns `true` if `FAR_EL1` holds the faulting address of the abort with
+/// syndrome `esr`. The address is unknown when the abort's FnV bit is set.
+pub fn far_is_valid(esr: u32) -> bool {
+    iss(esr) & (1 << 10) == 0
+}
+
+impl From<u32> for Syndrome {
+    /// Decodes the value of `ESR_EL1`.
+    fn from(esr: u32) -> Syndrome {
+        use self::Syndrome::*;
+
+        let iss = iss(esr);
+        let imm = iss as u16;
+        let level = (iss & 0b11) as u8;
+        match class(esr) {
+            0b00_0000 => Unknown,
+            0b00_0001 => WfiWfe,
+            0b00_0111 => SimdFp,
+            0b00_1110 => IllegalExecutionState,
+            0b01_0101 => Svc(imm),
+            0b01_0110 => Hvc(imm),
+            0b01_0111 => Smc(imm),
+            0b01_1000 => MsrMrsSystem,
+            0b10_0000 | 0b10_0001 => InstructionAbort {
+                kind: Fault::from(iss),
+                level,
+            },
+            0b10_0010 => PcAlignmentFault,
+            0b10_0100 | 0b10_0101 => DataAbort {
+                kind: Fault::from(iss),
+                level,
+                write: iss & (1 << 6) != 0,
+            },
+            0b10_0110 => SpAlignmentFault,
+            0b10_1100 => TrappedFpu,
+            0b10_1111 => SError,
+            0b11_0000 | 0b11_0001 => Breakpoint,
+            0b11_0010 | 0b11_0011 => Step,
+            0b11_0100 | 0b11_0101 => Watchpoint,
+            0b11_1100 => Brk(imm),
+            other => Other(other),
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{far_is_valid, Fault, Syndrome};
+
+    #[test]
+    fn decoding() {
+        assert_eq!(Syndrome::from(0x0200_0000), Syndrome::Unknown);
+        assert_eq!(Syndrome::from(0x5600_0007), Syndrome::Svc(7));
+        assert_eq!(Syndrome::from(0xf200_0001), Syndrome::Brk(1));
+        assert_eq!(
+            Syndrome::from(0x9600_0045),
+            Syndrome::DataAbort {
+                kind: Fault::Translation,
+                level: 1,
+                write: true
+            }
+        );
+        assert_eq!(
+            Syndrome::from(0x8600_000f),
+            Syndrome::InstructionAbort {
+                kind: Fault::Permission,
+                level: 3
+            }
+        );
+        assert_eq!(
+            Syndrome::from(0x9600_0021),
+            Syndrome::DataAbort {
+                kind: Fault::Alignment,
+                level: 1,
+                write: false
+            }
+        );
+        assert_eq!(Syndrome::from(0x1c00_0000), Syndrome::SimdFp);
+        assert_eq!(Syndrome::from(0x0c00_0000), Syndrome::Other(3));
+    }
+
+    #[test]
+    fn faults() {
+        assert!(far_is_valid(0x9600_0045));
+        assert!(!far_is_valid(0x9600_0410));
+
+        assert!(Fault::Translation.has_level());
+        assert!(!Fault::Alignment.has_level());
+
+        assert_eq!(Fault::Permission.to_string(), "permission fault");
+        assert_eq!(Fault::Other(0x3d).to_string(), "fault status 0x3d");
+    }
+}
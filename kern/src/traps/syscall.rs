@@ -0,0 +1,548 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::time::Duration;
+
+use shim::io::{self, Read, Seek, SeekFrom, Write};
+use shim::path::Path;
+
+use fat32::traits::FileSystem;
+use kernel_api::{encode, OsError, OsResult, MESSAGE_SIZE, NO_HANDLE, NO_TIMEOUT};
+use kernel_api::{OPEN_APPEND, OPEN_CREATE};
+use kernel_api::{RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIM_INFINITY};
+use kernel_api::{SYS_ALARM, SYS_NANOSLEEP};
+use kernel_api::{SYS_BRK, SYS_CHANNEL, SYS_RECEIVE, SYS_SEND};
+use kernel_api::{SYS_CLONE, SYS_FUTEX_WAIT, SYS_FUTEX_WAKE};
+use kernel_api::{SYS_CLOSE, SYS_DUP2, SYS_OPEN, SYS_PIPE, SYS_READ, SYS_WRITE};
+use kernel_api::{SYS_EXEC, SYS_EXIT, SYS_FORK, SYS_SLEEP, SYS_WAIT, SYS_YIELD};
+use kernel_api::{SYS_GETPID, SYS_GETRANDOM, SYS_GETTIME};
+use kernel_api::{SYS_GETPRIORITY, SYS_SETPRIORITY};
+use kernel_api::{SYS_GETRLIMIT, SYS_SETRLIMIT};
+use kernel_api::{SYS_KILL, SYS_SIGACTION, SYS_SIGRETURN};
+
+use crate::clock;
+use crate::console::kprintln_nolock;
+use crate::process::channel::{self, Endpoint, Message};
+use crate::process::exec::{self, ARG_MAX};
+use crate::process::fd::{self, Description, Stream};
+use crate::process::pipe;
+use crate::process::signal::{self, Action};
+use crate::process::{block_until, Id, KILLED, NICE_MAX, NICE_MIN};
+use crate::vm::{phys_to_virt, AccessKind};
+use crate::FILESYSTEM;
+use crate::SCHEDULER;
+use crate::VMM;
+
+use super::TrapFrame;
+
+/// The most bytes copied between a user program and a file at a time. A
+/// `SYS_READ` reads at most this many.
+const IO_CHUNK: usize = 4096;
+
+/// Handles the system call `num`, the immediate of the `svc` instruction, made
+/// by the process stopped with the trap frame `tf`, following the calling
+/// convention `kernel_api` describes. Returns the trap frame to restore: `tf`,
+/// or that of another process if the caller must wait.
+///
+/// Unknown system calls are reported, and fail with `NotImplemented`.
+pub fn handle_syscall(num: u16, tf: &mut TrapFrame) -> *mut TrapFrame {
+    let (x0, x1) = (tf.x[0], tf.x[1]);
+    match num {
+        SYS_YIELD => SCHEDULER.switch(tf),
+        SYS_SLEEP => SCHEDULER.sleep(tf, Duration::from_millis(x0), Duration::from_millis(1)),
+        SYS_NANOSLEEP => SCHEDULER.sleep(tf, Duration::from_nanos(x0), Duration::from_nanos(1)),
+        SYS_EXIT => SCHEDULER.exit(tf, x0 as i32),
+        SYS_WAIT => {
+            let pid = match x0 {
+                0 => None,
+                pid => Some(pid),
+            };
+            SCHEDULER.wait(tf, pid, x1)
+        }
+        SYS_FORK => SCHEDULER.fork(tf),
+        SYS_CLONE => {
+            let mut frame = *tf;
+            frame.elr = x0;
+            frame.sp = x1;
+            frame.tpidr = tf.x[3];
+            frame.x = [0; 31];
+            frame.x[0] = tf.x[2];
+            tf.x[0] = encode(SCHEDULER.clone_thread(frame));
+            tf
+        }
+        SYS_FUTEX_WAIT => {
+            tf.x[0] = encode(futex_wait(x0, x1, tf.x[2]).map(|()| 0));
+            tf
+        }
+        SYS_FUTEX_WAKE => {
+            let result = futex_event(x0).map(|event| SCHEDULER.wake(event));
+            tf.x[0] = encode(result.map(|()| 0));
+            tf
+        }
+        SYS_EXEC => {
+            if let Err(e) = exec(tf) {
+                tf.x[0] = encode(Err(e));
+            }
+            tf
+        }
+        SYS_OPEN => {
+            tf.x[0] = encode(open(x0, x1, tf.x[2]));
+            tf
+        }
+        SYS_READ => {
+            tf.x[0] = encode(read(x0, x1, tf.x[2]));
+            tf
+        }
+        SYS_WRITE => {
+            tf.x[0] = encode(write(x0, x1, tf.x[2]));
+            tf
+        }
+        SYS_CLOSE => {
+            let file = SCHEDULER.with_files(|files| files.close(x0 as usize));
+            tf.x[0] = match file {
+                Some(Some(_)) => 0,
+                _ => encode(Err(OsError::BadDescriptor)),
+            };
+            tf
+        }
+        SYS_PIPE => {
+            let (reader, writer) = pipe::pipe();
+            match open_pair(fd::description(reader), fd::description(writer)) {
+                Ok((reader, writer)) => tf.x[..2].copy_from_slice(&[reader, writer]),
+                Err(e) => tf.x[0] = encode(Err(e)),
+            }
+            tf
+        }
+        SYS_DUP2 => {
+            let replaced = SCHEDULER.with_files(|files| files.dup2(x0 as usize, x1 as usize));
+            tf.x[0] = match replaced {
+                Some(Some(_)) => x1,
+                _ => encode(Err(OsError::BadDescriptor)),
+            };
+            tf
+        }
+        SYS_CHANNEL => {
+            let (a, b) = channel::channel();
+            match open_pair(fd::description(a), fd::description(b)) {
+                Ok((a, b)) => tf.x[..2].copy_from_slice(&[a, b]),
+                Err(e) => tf.x[0] = encode(Err(e)),
+            }
+            tf
+        }
+        SYS_SEND => {
+            tf.x[0] = encode(send(x0, x1, tf.x[2]).map(|()| 0));
+            tf
+        }
+        SYS_RECEIVE => {
+            match receive(x0, x1, tf.x[2]) {
+                Ok(handle) => tf.x[..2].copy_from_slice(&[0, handle]),
+                Err(e) => tf.x[0] = encode(Err(e)),
+            }
+            tf
+        }
+        SYS_KILL => {
+            let result = x1
+                .try_into()
+                .map_err(|_| OsError::InvalidArgument)
+                .and_then(|signal| SCHEDULER.kill(x0, signal));
+            tf.x[0] = encode(result.map(|()| 0));
+            tf
+        }
+        SYS_SIGACTION => {
+            let result = x0
+                .try_into()
+                .map_err(|_| OsError::InvalidArgument)
+                .and_then(|signal| SCHEDULER.sigaction(signal, Action::from_handler(x1, tf.x[2])));
+            tf.x[0] = encode(result.map(|previous| previous.handler()));
+            tf
+        }
+        SYS_SETPRIORITY => {
+            let nice = (x1 as i64).max(NICE_MIN.into()).min(NICE_MAX.into()) as i32;
+            let result = SCHEDULER.set_nice(process_id(x0), nice);
+            tf.x[0] = encode(result.map(|_| 0));
+            tf
+        }
+        SYS_GETPRIORITY => {
+            let result = SCHEDULER.nice(process_id(x0));
+            tf.x[0] = encode(result.map(|nice| (20 - nice) as u64));
+            tf
+        }
+        SYS_BRK => {
+            let brk = match x0 {
+                0 => None,
+                brk => Some(brk as usize),
+            };
+            tf.x[0] = encode(match VMM.set_user_brk(brk) {
+                Ok(brk) => Ok(brk as u64),
+                Err(_) if !VMM.has_user_space() => Err(OsError::NotPermitted),
+                Err(_) => Err(OsError::NoMemory),
+            });
+            tf
+        }
+        SYS_ALARM => {
+            let (after, interval) = (Duration::from_nanos(x0), Duration::from_nanos(x1));
+            let left = SCHEDULER.set_alarm(after, interval);
+            tf.x[0] = encode(left.map(|left| left.as_nanos() as u64));
+            tf
+        }
+        SYS_GETPID => {
+            tf.x[0] = SCHEDULER.current().unwrap_or_default();
+            tf.x[1] = SCHEDULER.parent().unwrap_or_default();
+            tf
+        }
+        SYS_GETTIME => {
+            tf.x[0] = clock::uptime().as_nanos() as u64;
+            tf
+        }
+        SYS_GETRANDOM => {
+            tf.x[0] = encode(getrandom(x0, x1));
+            tf
+        }
+        SYS_GETRLIMIT => {
+            tf.x[0] = encode(getrlimit(x0));
+            tf
+        }
+        SYS_SETRLIMIT => {
+            tf.x[0] = encode(setrlimit(x0, x1).map(|()| 0));
+            tf
+        }
+        SYS_SIGRETURN => match signal::sigreturn(tf) {
+            Ok(()) => tf,
+            Err(e) => {
+                kprintln_nolock!(
+                    "process {} killed: bad signal frame at {:#x}: {}",
+                    SCHEDULER.current().unwrap_or_default(),
+                    tf.sp,
+                    e
+                );
+                SCHEDULER.exit(tf, KILLED)
+            }
+        },
+        _ => {
+            // `ELR_EL1` already points past the `svc` instruction.
+            kprintln_nolock!("unknown system call {} at {:#x}", num, tf.elr - 4);
+            tf.x[0] = encode(Err(OsError::NotImplemented));
+            tf
+        }
+    }
+}
+
+/// Returns the process a system call's argument `pid` names: the caller if
+/// it is 0.
+fn process_id(pid: u64) -> Id {
+    match pid {
+        0 => SCHEDULER.current().unwrap_or_default(),
+        pid => pid,
+    }
+}
+
+/// Handles a `SYS_EXEC` system call made with the trap frame `tf`, which is
+/// only changed if it succeeds.
+fn exec(tf: &mut TrapFrame) -> OsResult<()> {
+    let path = user_string(tf.x[0], tf.x[1])?;
+    let (argv, argc) = (tf.x[2], tf.x[3]);
+    if argc > ARG_MAX as u64 {
+        return Err(OsError::TooBig);
+    }
+
+    let mut args = Vec::new();
+    for i in 0..argc {
+        let va = argv.checked_add(i * 16).ok_or(OsError::BadAddress)?;
+        let arg = user_bytes(va, 16)?;
+        let (va, len) = arg.split_at(8);
+        let va = u64::from_le_bytes(va.try_into().unwrap());
+        let len = u64::from_le_bytes(len.try_into().unwrap());
+        args.push(user_string(va, len)?);
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let path = Path::new(&path);
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if path.is_absolute() => name,
+        _ => return Err(OsError::InvalidArgument),
+    };
+    let bytes = exec::read_program(path)?;
+    let image = exec::load(&bytes, &args, VMM.new_user_space())?;
+    if SCHEDULER.exec(tf, name, image) {
+        Ok(())
+    } else {
+        Err(OsError::NotPermitted)
+    }
+}
+
+/// Handles a `SYS_OPEN` system call: opens the file at the path of `len`
+/// bytes at `va` with the options `options`, returning its descriptor.
+fn open(va: u64, len: u64, options: u64) -> OsResult<u64> {
+    if options & !(OPEN_CREATE | OPEN_APPEND) != 0 {
+        return Err(OsError::InvalidArgument);
+    }
+
+    let path = user_string(va, len)?;
+    let path = Path::new(&path);
+    if !path.is_absolute() {
+        return Err(OsError::InvalidArgument);
+    }
+
+    let mut file = match options & OPEN_CREATE {
+        0 => FILESYSTEM.open_file(path),
+        _ => FILESYSTEM.create_file(path),
+    }
+    .map_err(io_error)?;
+    if options & OPEN_APPEND != 0 {
+        file.seek(SeekFrom::End(0)).map_err(io_error)?;
+    }
+
+    // As in `open_pair()`, the file must be closed after the scheduler is
+    // unlocked if it cannot be opened.
+    let description = fd::description(file);
+    let fd = SCHEDULER.with_files(|files| files.open(description.clone()));
+    fd.and_then(|fd| fd)
+        .map(|fd| fd as u64)
+        .ok_or(OsError::TooManyFiles)
+}
+
+/// Handles a `SYS_READ` system call: reads at most `len` bytes of the file
+/// `fd` to `va`.
+fn read(fd: u64, va: u64, len: u64) -> OsResult<u64> {
+    let file = file(fd)?;
+    let mut buf = vec![0; len.min(IO_CHUNK as u64) as usize];
+    let n = file.lock().read(&mut buf).map_err(io_error)?;
+    VMM.write_user(va as usize, &buf[..n])
+        .map_err(|_| OsError::BadAddress)?;
+    Ok(n as u64)
+}
+
+/// Handles a `SYS_WRITE` system call: writes the `len` bytes at `va` to the
+/// file `fd`. If some are written before an error, returns how many.
+fn write(fd: u64, va: u64, len: u64) -> OsResult<u64> {
+    let file = file(fd)?;
+    let mut done = 0;
+    while done < len {
+        let result = va
+            .checked_add(done)
+            .ok_or(OsError::BadAddress)
+            .and_then(|va| user_bytes(va, (len - done).min(IO_CHUNK as u64)))
+            .and_then(|bytes| file.lock().write(&bytes).map_err(io_error));
+        match result {
+            Ok(0) => break,
+            Ok(n) => done += n as u64,
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(done)
+}
+
+/// Opens `a` and `b`, the two ends of a new pipe or channel, in the running
+/// process, returning their descriptors. Fails with `TooManyFiles`, opening
+/// neither, if it cannot open both.
+fn open_pair(a: Description, b: Description) -> OsResult<(u64, u64)> {
+    // Closing an end can wake processes, so the ends must be dropped after
+    // the scheduler is unlocked.
+    let (a, b) = (a.clone(), b.clone());
+    let fds = SCHEDULER.with_files(|files| {
+        if files.count() + 2 > files.limit() {
+            return None;
+        }
+        Some((files.open(a)? as u64, files.open(b)? as u64))
+    });
+    fds.and_then(|fds| fds).ok_or(OsError::TooManyFiles)
+}
+
+/// Handles a `SYS_SEND` system call: sends the message at `va` through the
+/// channel endpoint `fd`, with the file `handle` unless it is `NO_HANDLE`.
+fn send(fd: u64, va: u64, handle: u64) -> OsResult<()> {
+    let mut data = [0; MESSAGE_SIZE];
+    data.copy_from_slice(&user_bytes(va, MESSAGE_SIZE as u64)?);
+    let handle = match handle {
+        NO_HANDLE => None,
+        handle => Some(file(handle)?),
+    };
+    with_endpoint(fd, |endpoint| endpoint.send(Message { data, handle }))
+}
+
+/// Returns the event the threads waiting on the `u32` at `va` in the caller's
+/// address space block until: the kernel's address for the word, which is the
+/// same in every thread sharing the address space. The word's page is made
+/// the caller's own first, as a write to it would. Fails with
+/// `InvalidArgument` if `va` is unaligned, and `BadAddress` if it is not
+/// writable.
+fn futex_event(va: u64) -> OsResult<usize> {
+    let va = va as usize;
+    if va % 4 != 0 {
+        return Err(OsError::InvalidArgument);
+    }
+
+    VMM.handle_user_fault(va, AccessKind::Write)
+        .map_err(|_| OsError::BadAddress)?;
+    VMM.translate(va)
+        .map(phys_to_virt)
+        .ok_or(OsError::BadAddress)
+}
+
+/// Handles a `SYS_FUTEX_WAIT` system call: waits while the `u32` at `va`
+/// holds `expected`, at most `timeout` milliseconds unless it is
+/// `NO_TIMEOUT`.
+fn futex_wait(va: u64, expected: u64, timeout: u64) -> OsResult<()> {
+    let event = futex_event(va)?;
+    let timeout = match timeout {
+        NO_TIMEOUT => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+    let holds_expected = || {
+        let mut word = [0; 4];
+        VMM.read_user(va as usize, &mut word).is_ok()
+            && u64::from(u32::from_ne_bytes(word)) == expected
+    };
+
+    if !holds_expected() {
+        return Err(OsError::WouldBlock);
+    }
+    let woken = block_until(event, timeout, || {
+        SCHEDULER.signal_pending() || !holds_expected()
+    });
+    if !woken {
+        Err(OsError::TimedOut)
+    } else if SCHEDULER.signal_pending() {
+        Err(OsError::Interrupted)
+    } else {
+        Ok(())
+    }
+}
+
+/// Handles a `SYS_GETRANDOM` system call: fills at most `IO_CHUNK` of the
+/// `len` bytes at `va` with random bytes. Returns the number filled.
+fn getrandom(va: u64, len: u64) -> OsResult<u64> {
+    let mut buf = vec![0; (len as usize).min(IO_CHUNK)];
+    pi::rng::fill(&mut buf);
+    VMM.write_user(va as usize, &buf)
+        .map_err(|_| OsError::BadAddress)?;
+    Ok(buf.len() as u64)
+}
+
+/// Handles a `SYS_GETRLIMIT` system call: returns the caller's limit on
+/// `resource`.
+fn getrlimit(resource: u64) -> OsResult<u64> {
+    let limit = match resource {
+        RLIMIT_AS => VMM
+            .set_user_memory_limit(None)
+            .ok_or(OsError::NotPermitted)? as u64,
+        RLIMIT_NOFILE => SCHEDULER
+            .with_files(|files| files.limit() as u64)
+            .ok_or(OsError::NotPermitted)?,
+        RLIMIT_CPU => SCHEDULER
+            .cpu_limit()?
+            .map_or(RLIM_INFINITY, |limit| limit.as_nanos() as u64),
+        _ => return Err(OsError::InvalidArgument),
+    };
+    Ok(limit.min(RLIM_INFINITY))
+}
+
+/// Handles a `SYS_SETRLIMIT` system call: lowers the caller's limit on
+/// `resource` to `limit`.
+fn setrlimit(resource: u64, limit: u64) -> OsResult<()> {
+    if limit > getrlimit(resource)? {
+        return Err(OsError::NotPermitted);
+    }
+
+    match resource {
+        RLIMIT_AS => drop(VMM.set_user_memory_limit(Some(limit as usize))),
+        RLIMIT_NOFILE => drop(SCHEDULER.with_files(|files| files.set_limit(limit as usize))),
+        RLIMIT_CPU => SCHEDULER.set_cpu_limit(match limit {
+            RLIM_INFINITY => None,
+            ns => Some(Duration::from_nanos(ns)),
+        })?,
+        _ => return Err(OsError::InvalidArgument),
+    }
+    Ok(())
+}
+
+/// Handles a `SYS_RECEIVE` system call: receives a message from the channel
+/// endpoint `fd` to `va`, waiting at most `timeout` milliseconds unless it is
+/// `NO_TIMEOUT`. Returns the descriptor of the file passed with it, or
+/// `NO_HANDLE`.
+fn receive(fd: u64, va: u64, timeout: u64) -> OsResult<u64> {
+    let timeout = match timeout {
+        NO_TIMEOUT => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+
+    // Whatever the message holds, it can then be delivered.
+    VMM.write_user(va as usize, &[0; MESSAGE_SIZE])
+        .map_err(|_| OsError::BadAddress)?;
+    if SCHEDULER.with_files(|files| files.count() < files.limit()) != Some(true) {
+        return Err(OsError::TooManyFiles);
+    }
+
+    let message = with_endpoint(fd, |endpoint| endpoint.receive(timeout))?;
+    VMM.write_user(va as usize, &message.data)
+        .map_err(|_| OsError::BadAddress)?;
+    match message.handle {
+        Some(handle) => SCHEDULER
+            .with_files(|files| files.open(handle.clone()))
+            .and_then(|fd| fd)
+            .map(|fd| fd as u64)
+            .ok_or(OsError::TooManyFiles),
+        None => Ok(NO_HANDLE),
+    }
+}
+
+/// Calls `f` with the channel endpoint the running process's file descriptor
+/// `fd` refers to, returning what it returns. Fails with `BadDescriptor` if
+/// `fd` is not open or not an endpoint.
+fn with_endpoint<R, F>(fd: u64, f: F) -> OsResult<R>
+where
+    F: FnOnce(&mut Endpoint) -> OsResult<R>,
+{
+    let file = file(fd)?;
+    let mut stream = file.lock();
+    let stream: &mut dyn Stream = &mut **stream;
+    match stream.as_any().downcast_mut::<Endpoint>() {
+        Some(endpoint) => f(endpoint),
+        None => Err(OsError::BadDescriptor),
+    }
+}
+
+/// Returns the description the running process's file descriptor `fd`
+/// refers to. Fails with `BadDescriptor` if it is not open.
+fn file(fd: u64) -> OsResult<Description> {
+    SCHEDULER
+        .with_files(|files| files.get(fd as usize).cloned())
+        .and_then(|file| file)
+        .ok_or(OsError::BadDescriptor)
+}
+
+/// Returns the error a system call reports for the I/O error `e`.
+fn io_error(e: io::Error) -> OsError {
+    match e.kind() {
+        io::ErrorKind::NotFound => OsError::NoEntry,
+        io::ErrorKind::PermissionDenied => OsError::NotPermitted,
+        io::ErrorKind::BrokenPipe => OsError::BrokenPipe,
+        io::ErrorKind::WouldBlock => OsError::WouldBlock,
+        io::ErrorKind::InvalidInput => OsError::InvalidArgument,
+        io::ErrorKind::Interrupted => OsError::Interrupted,
+        io::ErrorKind::TimedOut => OsError::TimedOut,
+        _ => OsError::Io,
+    }
+}
+
+/// Returns a copy of the `len` bytes at `va` in the caller's user address
+/// space. Fails with `BadAddress` if they are not all in its regions, and
+/// `TooBig` if there are more than `ARG_MAX`.
+fn user_bytes(va: u64, len: u64) -> OsResult<Vec<u8>> {
+    if len > ARG_MAX as u64 {
+        return Err(OsError::TooBig);
+    }
+
+    let mut bytes = vec![0; len as usize];
+    VMM.read_user(va as usize, &mut bytes)
+        .map_err(|_| OsError::BadAddress)?;
+    Ok(bytes)
+}
+
+/// Returns a copy of the UTF-8 string of `len` bytes at `va` in the caller's
+/// user address space. See `user_bytes()`.
+fn user_string(va: u64, len: u64) -> OsResult<String> {
+    String::from_utf8(user_bytes(va, len)?).map_err(|_| OsError::InvalidArgument)
+}
@@ -0,0 +1,381 @@
+//! The USB host stack, on the DWC2 controller the `pi` crate drives: the
+//! devices attached when the kernel boots are enumerated, through any hubs
+//! between them and the controller, and given to the drivers that handle
+//! them, which a process polls from then on.
+//!
+//! Transfers are carried out one at a time, while the caller waits, through
+//! a buffer the controller reads and writes itself. Devices attached later
+//! are not noticed.
+
+mod device;
+mod hub;
+mod keyboard;
+mod keys;
+mod lan9514;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use pi::timer;
+use pi::usb::{self, EndpointType, Host, Pid, Transfer};
+
+use crate::console::kprintln;
+use crate::firmware::{self, Device as Power};
+use crate::irq::with_irqs_disabled;
+use crate::mutex::Mutex;
+use crate::{aarch64, process, SCHEDULER};
+
+pub use self::device::{Descriptor, Descriptors, Device, Endpoint, Interface, Request};
+
+/// The size of the buffer transfers are carried out through, which bounds
+/// their length. Every packet size divides it.
+const BUFFER_SIZE: usize = 4096;
+
+/// The highest address a device can be given.
+const MAX_ADDRESS: u8 = 127;
+
+/// How many times a control transfer or bulk output is tried again while the
+/// endpoint is not ready for it, and how long apart.
+const NAK_RETRIES: usize = 100;
+const NAK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How often drivers are polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Why the USB stack failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The controller did not come out of reset.
+    Controller,
+    /// The firmware would not power the controller on.
+    Power(&'static str),
+    Transfer(usb::Error),
+    /// A device answered with a descriptor that makes no sense.
+    BadDescriptor,
+    /// Every address is taken.
+    TooManyDevices,
+    /// A transfer is longer than `BUFFER_SIZE`.
+    TooLong,
+    /// A device did not finish what it was asked to in time.
+    Timeout,
+}
+
+impl From<usb::Error> for Error {
+    fn from(error: usb::Error) -> Error {
+        Error::Transfer(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Controller => f.write_str("the USB controller did not reset"),
+            Error::Power(message) => f.write_str(message),
+            Error::Transfer(error) => write!(f, "USB transfer failed: {}", error),
+            Error::BadDescriptor => f.write_str("bad USB descriptor"),
+            Error::TooManyDevices => f.write_str("too many USB devices"),
+            Error::TooLong => f.write_str("USB transfer too long"),
+            Error::Timeout => f.write_str("USB device timed out"),
+        }
+    }
+}
+
+/// A driver for an attached device.
+pub trait Driver: Send {
+    /// Checks on the device, and does whatever it needs done.
+    fn poll(&mut self, bus: &mut Bus);
+}
+
+/// The buffer transfers are carried out through. It is aligned to a cache
+/// line, so that nothing else shares its lines.
+#[repr(C, align(64))]
+struct Buffer([u8; BUFFER_SIZE]);
+
+/// The controller, and what it takes to carry out transfers with it.
+pub struct Bus {
+    host: Host,
+    buffer: Box<Buffer>,
+    /// The address the next device enumerated is given.
+    next_address: u8,
+}
+
+impl Bus {
+    /// Returns the next free address, taking it.
+    fn allocate_address(&mut self) -> Result<u8, Error> {
+        if self.next_address > MAX_ADDRESS {
+            return Err(Error::TooManyDevices);
+        }
+        self.next_address += 1;
+        Ok(self.next_address - 1)
+    }
+
+    /// Carries out `request` with `device`'s default control endpoint, with
+    /// `data` as the request's data: sent to the device, or filled by it if
+    /// the request is for input. Returns the number of bytes moved.
+    pub fn control(
+        &mut self,
+        device: &Device,
+        request: Request,
+        data: &mut [u8],
+    ) -> Result<usize, Error> {
+        if data.len() > BUFFER_SIZE {
+            return Err(Error::TooLong);
+        }
+
+        let mut transfer = Transfer {
+            device: device.address,
+            endpoint: 0,
+            kind: EndpointType::Control,
+            speed: device.speed,
+            max_packet: device.max_packet0,
+            input: false,
+            pid: Pid::Setup,
+            split: device.tt,
+        };
+        let setup = request.to_bytes(data.len() as u16);
+        self.buffer.0[..setup.len()].copy_from_slice(&setup);
+        self.dma(&transfer, setup.len(), NAK_RETRIES)?;
+
+        let mut len = 0;
+        if !data.is_empty() {
+            transfer.input = request.is_input();
+            transfer.pid = Pid::Data1;
+            if !transfer.input {
+                self.buffer.0[..data.len()].copy_from_slice(data);
+            }
+            len = self.dma(&transfer, data.len(), NAK_RETRIES)?.0;
+            if transfer.input {
+                data[..len].copy_from_slice(&self.buffer.0[..len]);
+            }
+        }
+
+        // The status stage goes the other way from the data, or in.
+        transfer.input = data.is_empty() || !request.is_input();
+        transfer.pid = Pid::Data1;
+        self.dma(&transfer, 0, NAK_RETRIES)?;
+        Ok(len)
+    }
+
+    /// Reads a report from `device`'s interrupt endpoint `endpoint` into
+    /// `data`, starting with the packet ID `toggle`, which is then updated.
+    /// Returns the number of bytes read, or `None` if the endpoint had
+    /// nothing new to report.
+    pub fn interrupt_in(
+        &mut self,
+        device: &Device,
+        endpoint: &Endpoint,
+        toggle: &mut Pid,
+        data: &mut [u8],
+    ) -> Result<Option<usize>, Error> {
+        match self.transfer(device, endpoint, toggle, data.len(), 0) {
+            Ok(len) => {
+                data[..len].copy_from_slice(&self.buffer.0[..len]);
+                Ok(Some(len))
+            }
+            Err(Error::Transfer(usb::Error::Nak)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Reads from `device`'s bulk endpoint `endpoint` into `data`, like
+    /// `interrupt_in()`. Returns the number of bytes read, which is 0 if the
+    /// endpoint had nothing to send.
+    pub fn bulk_in(
+        &mut self,
+        device: &Device,
+        endpoint: &Endpoint,
+        toggle: &mut Pid,
+        data: &mut [u8],
+    ) -> Result<usize, Error> {
+        match self.transfer(device, endpoint, toggle, data.len(), 0) {
+            Ok(len) => {
+                data[..len].copy_from_slice(&self.buffer.0[..len]);
+                Ok(len)
+            }
+            Err(Error::Transfer(usb::Error::Nak)) => Ok(0),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes `data` to `device`'s bulk endpoint `endpoint`, starting with
+    /// the packet ID `toggle`, which is then updated.
+    pub fn bulk_out(
+        &mut self,
+        device: &Device,
+        endpoint: &Endpoint,
+        toggle: &mut Pid,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if data.len() > BUFFER_SIZE {
+            return Err(Error::TooLong);
+        }
+        self.buffer.0[..data.len()].copy_from_slice(data);
+        self.transfer(device, endpoint, toggle, data.len(), NAK_RETRIES)?;
+        Ok(())
+    }
+
+    /// Carries out a transfer of `len` bytes through the buffer with
+    /// `device`'s endpoint `endpoint`, starting with the packet ID `toggle`,
+    /// which is then updated. Returns the number of bytes transferred.
+    fn transfer(
+        &mut self,
+        device: &Device,
+        endpoint: &Endpoint,
+        toggle: &mut Pid,
+        len: usize,
+        retries: usize,
+    ) -> Result<usize, Error> {
+        if len > BUFFER_SIZE {
+            return Err(Error::TooLong);
+        }
+
+        let transfer = Transfer {
+            device: device.address,
+            endpoint: endpoint.number,
+            kind: endpoint.kind,
+            speed: device.speed,
+            max_packet: endpoint.max_packet,
+            input: endpoint.input,
+            pid: *toggle,
+            split: device.tt,
+        };
+        let (len, next) = self.dma(&transfer, len, retries)?;
+        *toggle = next;
+        Ok(len)
+    }
+
+    /// Carries out `transfer` of `len` bytes through the buffer, keeping it
+    /// out of the data cache while the controller has it. A transfer the
+    /// endpoint is not ready for is tried again up to `retries` times.
+    fn dma(
+        &mut self,
+        transfer: &Transfer,
+        len: usize,
+        retries: usize,
+    ) -> Result<(usize, Pid), Error> {
+        let va = self.buffer.0.as_ptr() as usize;
+        let mut tries = 0;
+        loop {
+            aarch64::clean_invalidate_data_cache(va, BUFFER_SIZE);
+            // The buffer is `BUFFER_SIZE` bytes, and neither used nor cached
+            // until the controller is done with it.
+            let result = unsafe { self.host.transfer(transfer, firmware::bus_addr(va), len) };
+            aarch64::clean_invalidate_data_cache(va, BUFFER_SIZE);
+            match result {
+                Err(usb::Error::Nak) if tries < retries => {
+                    tries += 1;
+                    timer::spin_sleep(NAK_INTERVAL);
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+}
+
+/// The bus, and the drivers of the devices on it.
+struct Stack {
+    bus: Bus,
+    drivers: Vec<Box<dyn Driver>>,
+}
+
+/// The USB stack, once it is set up. It is only locked with IRQs masked: the
+/// lock is the core's, not the process's, so were the holder preempted, the
+/// next process to run on the core would take it again.
+static USB: Mutex<Option<Stack>> = Mutex::new(None);
+
+/// Powers the controller on and enumerates the devices attached to it, then
+/// starts a process that polls their drivers. Does nothing if there is no
+/// controller, as under QEMU.
+pub fn initialize() -> Result<(), Error> {
+    firmware::set_power(Power::Usb, true).map_err(Error::Power)?;
+    let mut host = Host::new();
+    if !host.is_present() {
+        kprintln!("usb: no controller; USB devices are unavailable");
+        return Ok(());
+    }
+    if !host.initialize() {
+        return Err(Error::Controller);
+    }
+
+    let mut bus = Bus {
+        buffer: Box::new(Buffer([0; BUFFER_SIZE])),
+        host,
+        next_address: 1,
+    };
+    let mut drivers = Vec::new();
+    if bus.host.is_connected() {
+        if let Some(speed) = bus.host.reset_port() {
+            attach(&mut bus, &mut drivers, speed, None)?;
+        }
+    }
+
+    with_irqs_disabled(|| *USB.lock() = Some(Stack { bus, drivers }));
+    if SCHEDULER.spawn("usb", poll).is_none() {
+        kprintln!("usb: no memory for the poller; USB devices are unavailable");
+    }
+    Ok(())
+}
+
+/// Enumerates the device just reset at `speed`, reached through the hub and
+/// port `tt` if it needs split transactions, and whatever is attached to it
+/// if it is a hub. Devices no driver handles are left configured but
+/// unused.
+fn attach(
+    bus: &mut Bus,
+    drivers: &mut Vec<Box<dyn Driver>>,
+    speed: usb::Speed,
+    tt: Option<(u8, u8)>,
+) -> Result<(), Error> {
+    let device = Device::enumerate(bus, speed, tt)?;
+    kprintln!(
+        "usb: device {}: {:04x}:{:04x}, class {:02x}",
+        device.address,
+        device.vendor,
+        device.product,
+        device.class
+    );
+
+    if device.is_hub() {
+        for port in 1..=hub::power_ports(bus, &device)? {
+            let attached = hub::reset_port(bus, &device, port).and_then(|speed| match speed {
+                Some(speed) => attach(bus, drivers, speed, hub::tt(&device, port, speed)),
+                None => Ok(()),
+            });
+            if let Err(e) = attached {
+                kprintln!("usb: hub {} port {}: {}", device.address, port, e);
+            }
+        }
+    } else if let Some(driver) = probe(bus, &device)? {
+        drivers.push(driver);
+    }
+    Ok(())
+}
+
+/// Returns the driver that handles `device`, if there is one.
+fn probe(bus: &mut Bus, device: &Device) -> Result<Option<Box<dyn Driver>>, Error> {
+    if let Some(keyboard) = keyboard::Keyboard::probe(bus, device)? {
+        kprintln!("usb: device {} is a keyboard", device.address);
+        return Ok(Some(Box::new(keyboard)));
+    }
+    if let Some(lan) = lan9514::Lan9514::probe(bus, device)? {
+        return Ok(Some(Box::new(lan)));
+    }
+    Ok(None)
+}
+
+/// Polls the drivers, forever.
+fn poll() {
+    loop {
+        with_irqs_disabled(|| {
+            if let Some(ref mut stack) = *USB.lock() {
+                let Stack { bus, drivers } = stack;
+                for driver in drivers.iter_mut() {
+                    driver.poll(bus);
+                }
+            }
+        });
+        process::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,321 @@
+//! Devices: enumerating them, the requests made of them, and the descriptors
+//! they describe themselves with.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use pi::timer;
+use pi::usb::{EndpointType, Speed};
+
+use super::{Bus, Error};
+
+/// How long a device may take to switch to the address it is given.
+const SET_ADDRESS_RECOVERY: Duration = Duration::from_millis(10);
+
+/// The size of the default control endpoint's packets that every device
+/// supports, and that the first request of it is made with.
+const MIN_MAX_PACKET: u16 = 8;
+
+/// The class of hubs.
+const CLASS_HUB: u8 = 0x09;
+
+/// Standard request codes.
+const GET_DESCRIPTOR: u8 = 6;
+const SET_ADDRESS: u8 = 5;
+const SET_CONFIGURATION: u8 = 9;
+
+/// Descriptor types.
+const DEVICE: u8 = 1;
+const CONFIGURATION: u8 = 2;
+const INTERFACE: u8 = 4;
+const ENDPOINT: u8 = 5;
+
+/// The sizes of the device descriptor, and of the configuration descriptor
+/// without the descriptors that follow it.
+const DEVICE_LEN: usize = 18;
+const CONFIGURATION_LEN: usize = 9;
+
+/// A request made of a device through its default control endpoint, as its
+/// setup packet carries it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The request's direction, type and recipient.
+    pub kind: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+}
+
+impl Request {
+    /// The `kind` bit set for requests that read from the device.
+    pub const IN: u8 = 0x80;
+
+    /// Returns `true` if the request reads from the device.
+    pub fn is_input(&self) -> bool {
+        self.kind & Request::IN != 0
+    }
+
+    /// Returns the setup packet of the request, with `len` bytes of data.
+    pub fn to_bytes(&self, len: u16) -> [u8; 8] {
+        let [value_lo, value_hi] = self.value.to_le_bytes();
+        let [index_lo, index_hi] = self.index.to_le_bytes();
+        let [len_lo, len_hi] = len.to_le_bytes();
+        [
+            self.kind,
+            self.request,
+            value_lo,
+            value_hi,
+            index_lo,
+            index_hi,
+            len_lo,
+            len_hi,
+        ]
+    }
+
+    /// The request for the descriptor of type `kind`, number `index`.
+    pub fn get_descriptor(kind: u8, index: u8) -> Request {
+        Request {
+            kind: Request::IN,
+            request: GET_DESCRIPTOR,
+            value: u16::from(kind) << 8 | u16::from(index),
+            index: 0,
+        }
+    }
+}
+
+/// An interface, as its descriptor gives it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub number: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+}
+
+/// An endpoint other than the default control endpoint, as its descriptor
+/// gives it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub number: u8,
+    /// Whether data moves from the device to the host.
+    pub input: bool,
+    pub kind: EndpointType,
+    pub max_packet: u16,
+    /// How often the endpoint is polled, for periodic endpoints, in frames
+    /// or in powers of two of microframes depending on the device's speed.
+    pub interval: u8,
+}
+
+/// One of the descriptors that follow a configuration descriptor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Descriptor<'a> {
+    Interface(Interface),
+    Endpoint(Endpoint),
+    /// Any other descriptor: its type and its bytes, including its length
+    /// and type.
+    Other(u8, &'a [u8]),
+}
+
+/// An iterator over the descriptors in a configuration descriptor. It ends
+/// early at a descriptor whose length makes no sense.
+pub struct Descriptors<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Descriptors<'a> {
+    /// Returns an iterator over the descriptors in `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Descriptors<'a> {
+        Descriptors { bytes }
+    }
+}
+
+impl<'a> Iterator for Descriptors<'a> {
+    type Item = Descriptor<'a>;
+
+    fn next(&mut self) -> Option<Descriptor<'a>> {
+        let len = usize::from(*self.bytes.first()?);
+        if len < 2 || len > self.bytes.len() {
+            self.bytes = &[];
+            return None;
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(match bytes[1] {
+            INTERFACE if len >= 9 => Descriptor::Interface(Interface {
+                number: bytes[2],
+                class: bytes[5],
+                subclass: bytes[6],
+                protocol: bytes[7],
+            }),
+            ENDPOINT if len >= 7 => Descriptor::Endpoint(Endpoint {
+                number: bytes[2] & 0xF,
+                input: bytes[2] & 0x80 != 0,
+                kind: match bytes[3] & 0b11 {
+                    0 => EndpointType::Control,
+                    1 => EndpointType::Isochronous,
+                    2 => EndpointType::Bulk,
+                    _ => EndpointType::Interrupt,
+                },
+                max_packet: u16::from_le_bytes([bytes[4], bytes[5]]) & 0x7FF,
+                interval: bytes[6],
+            }),
+            kind => Descriptor::Other(kind, bytes),
+        })
+    }
+}
+
+/// An enumerated, configured device.
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub address: u8,
+    pub speed: Speed,
+    /// The hub and port split transactions reach the device through, if it
+    /// needs them.
+    pub tt: Option<(u8, u8)>,
+    /// The size of the default control endpoint's packets.
+    pub max_packet0: u16,
+    pub class: u8,
+    pub vendor: u16,
+    pub product: u16,
+    /// The configuration the device is in, as its descriptor and the
+    /// descriptors that follow it.
+    pub configuration: Vec<u8>,
+}
+
+impl Device {
+    /// Enumerates the device just reset at `speed`, at address 0, reached
+    /// through the hub and port `tt` if it needs split transactions: gives
+    /// it an address, reads its descriptors and puts it in its first
+    /// configuration.
+    pub fn enumerate(bus: &mut Bus, speed: Speed, tt: Option<(u8, u8)>) -> Result<Device, Error> {
+        let mut device = Device {
+            address: 0,
+            speed,
+            tt,
+            max_packet0: MIN_MAX_PACKET,
+            class: 0,
+            vendor: 0,
+            product: 0,
+            configuration: Vec::new(),
+        };
+
+        // The start of the device descriptor holds the endpoint's real
+        // packet size.
+        let mut descriptor = [0; DEVICE_LEN];
+        let request = Request::get_descriptor(DEVICE, 0);
+        if bus.control(&device, request, &mut descriptor[..8])? < 8 || descriptor[7] == 0 {
+            return Err(Error::BadDescriptor);
+        }
+        device.max_packet0 = u16::from(descriptor[7]);
+
+        let address = bus.allocate_address()?;
+        let request = Request {
+            kind: 0,
+            request: SET_ADDRESS,
+            value: u16::from(address),
+            index: 0,
+        };
+        bus.control(&device, request, &mut [])?;
+        timer::spin_sleep(SET_ADDRESS_RECOVERY);
+        device.address = address;
+
+        let request = Request::get_descriptor(DEVICE, 0);
+        if bus.control(&device, request, &mut descriptor)? < DEVICE_LEN {
+            return Err(Error::BadDescriptor);
+        }
+        device.class = descriptor[4];
+        device.vendor = u16::from_le_bytes([descriptor[8], descriptor[9]]);
+        device.product = u16::from_le_bytes([descriptor[10], descriptor[11]]);
+
+        let mut header = [0; CONFIGURATION_LEN];
+        let request = Request::get_descriptor(CONFIGURATION, 0);
+        if bus.control(&device, request, &mut header)? < CONFIGURATION_LEN {
+            return Err(Error::BadDescriptor);
+        }
+        let len = usize::from(u16::from_le_bytes([header[2], header[3]]));
+        if len < CONFIGURATION_LEN {
+            return Err(Error::BadDescriptor);
+        }
+        let mut configuration = vec![0; len];
+        if bus.control(&device, request, &mut configuration)? < len {
+            return Err(Error::BadDescriptor);
+        }
+
+        let request = Request {
+            kind: 0,
+            request: SET_CONFIGURATION,
+            value: u16::from(configuration[5]),
+            index: 0,
+        };
+        bus.control(&device, request, &mut [])?;
+        device.configuration = configuration;
+        Ok(device)
+    }
+
+    /// Returns `true` if the device is a hub.
+    pub fn is_hub(&self) -> bool {
+        self.class == CLASS_HUB
+    }
+
+    /// Returns an iterator over the descriptors that follow the
+    /// configuration descriptor.
+    pub fn descriptors(&self) -> Descriptors<'_> {
+        Descriptors::new(self.configuration.get(CONFIGURATION_LEN..).unwrap_or(&[]))
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// The configuration of a boot keyboard, after its header.
+    const KEYBOARD: [u8; 25] = [
+        0x09, 0x04, 0x00, 0x00, 0x01, 0x03, 0x01, 0x01, 0x00, // interface
+        0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 0x3f, 0x00, // HID
+        0x07, 0x05, 0x81, 0x03, 0x08, 0x00, 0x0a, // endpoint
+    ];
+
+    #[test]
+    fn parses_descriptors() {
+        let descriptors: Vec<_> = Descriptors::new(&KEYBOARD).collect();
+        assert_eq!(
+            descriptors,
+            [
+                Descriptor::Interface(Interface {
+                    number: 0,
+                    class: 3,
+                    subclass: 1,
+                    protocol: 1,
+                }),
+                Descriptor::Other(0x21, &KEYBOARD[9..18]),
+                Descriptor::Endpoint(Endpoint {
+                    number: 1,
+                    input: true,
+                    kind: EndpointType::Interrupt,
+                    max_packet: 8,
+                    interval: 10,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_bad_lengths() {
+        let mut bytes = KEYBOARD;
+        bytes[9] = 0;
+        assert_eq!(Descriptors::new(&bytes).count(), 1);
+
+        bytes[9] = 30;
+        assert_eq!(Descriptors::new(&bytes).count(), 1);
+        assert_eq!(Descriptors::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn encodes_setup_packets() {
+        let request = Request::get_descriptor(CONFIGURATION, 0);
+        assert!(request.is_input());
+        assert_eq!(request.to_bytes(9), [0x80, 6, 0, 2, 0, 0, 9, 0]);
+    }
+}
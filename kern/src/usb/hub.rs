@@ -0,0 +1,145 @@
+//! Hubs: powering their ports, and resetting the devices attached to them
+//! so that they can be enumerated.
+
+use core::time::Duration;
+
+use pi::timer;
+use pi::usb::Speed;
+
+use super::{Bus, Device, Error, Request};
+
+/// The type of the hub descriptor, and its size up to the fields read.
+const HUB_DESCRIPTOR: u8 = 0x29;
+const HUB_DESCRIPTOR_LEN: usize = 7;
+
+/// The `kind`s of the hub class's requests: of the hub, and of a port.
+const TO_HUB: u8 = 0x20;
+const TO_PORT: u8 = 0x23;
+
+/// Request codes.
+const GET_STATUS: u8 = 0;
+const CLEAR_FEATURE: u8 = 1;
+const SET_FEATURE: u8 = 3;
+const GET_DESCRIPTOR: u8 = 6;
+
+/// Port features.
+const PORT_RESET: u16 = 4;
+const PORT_POWER: u16 = 8;
+const C_PORT_CONNECTION: u16 = 16;
+const C_PORT_RESET: u16 = 20;
+
+/// Port status bits, and the change bit set when a reset is done.
+const STATUS_CONNECTION: u32 = 1 << 0;
+const STATUS_ENABLE: u32 = 1 << 1;
+const STATUS_LOW_SPEED: u32 = 1 << 9;
+const STATUS_HIGH_SPEED: u32 = 1 << 10;
+const STATUS_RESET_CHANGED: u32 = 1 << 20;
+
+/// How long apart a port is checked on while it resets, how many times,
+/// and how long the device attached to it takes to recover afterwards.
+const RESET_INTERVAL: Duration = Duration::from_millis(10);
+const RESET_CHECKS: usize = 10;
+const RESET_RECOVERY: Duration = Duration::from_millis(10);
+
+/// Powers each of `hub`'s ports on, waiting for the power to be good, and
+/// returns how many it has. Ports are numbered from 1.
+pub fn power_ports(bus: &mut Bus, hub: &Device) -> Result<u8, Error> {
+    let mut descriptor = [0; HUB_DESCRIPTOR_LEN];
+    let request = Request {
+        kind: Request::IN | TO_HUB,
+        request: GET_DESCRIPTOR,
+        value: u16::from(HUB_DESCRIPTOR) << 8,
+        index: 0,
+    };
+    if bus.control(hub, request, &mut descriptor)? < HUB_DESCRIPTOR_LEN {
+        return Err(Error::BadDescriptor);
+    }
+
+    let ports = descriptor[2];
+    for port in 1..=ports {
+        set_feature(bus, hub, port, PORT_POWER)?;
+    }
+    // The descriptor gives the time the power takes in units of 2ms.
+    let power_good = u64::from(descriptor[5]) * 2;
+    timer::spin_sleep(Duration::from_millis(power_good));
+    Ok(ports)
+}
+
+/// Resets the device attached to `hub`'s port `port`. Returns the device's
+/// speed, or `None` if nothing is attached or the port is not enabled.
+pub fn reset_port(bus: &mut Bus, hub: &Device, port: u8) -> Result<Option<Speed>, Error> {
+    if status(bus, hub, port)? & STATUS_CONNECTION == 0 {
+        return Ok(None);
+    }
+    clear_feature(bus, hub, port, C_PORT_CONNECTION)?;
+
+    set_feature(bus, hub, port, PORT_RESET)?;
+    for _ in 0..RESET_CHECKS {
+        timer::spin_sleep(RESET_INTERVAL);
+        if status(bus, hub, port)? & STATUS_RESET_CHANGED != 0 {
+            break;
+        }
+    }
+    clear_feature(bus, hub, port, C_PORT_RESET)?;
+    timer::spin_sleep(RESET_RECOVERY);
+
+    let status = status(bus, hub, port)?;
+    Ok(if status & STATUS_ENABLE == 0 {
+        None
+    } else if status & STATUS_LOW_SPEED != 0 {
+        Some(Speed::Low)
+    } else if status & STATUS_HIGH_SPEED != 0 {
+        Some(Speed::High)
+    } else {
+        Some(Speed::Full)
+    })
+}
+
+/// Returns the hub and port split transactions reach a device through, if
+/// the device needs them: if it is attached at `speed` to `hub`'s port
+/// `port`.
+pub fn tt(hub: &Device, port: u8, speed: Speed) -> Option<(u8, u8)> {
+    match (speed, hub.speed) {
+        (Speed::High, _) => None,
+        (_, Speed::High) => Some((hub.address, port)),
+        _ => hub.tt,
+    }
+}
+
+/// Returns the status of `hub`'s port `port`: its status bits, and its
+/// change bits above them.
+fn status(bus: &mut Bus, hub: &Device, port: u8) -> Result<u32, Error> {
+    let mut status = [0; 4];
+    let request = Request {
+        kind: Request::IN | TO_PORT,
+        request: GET_STATUS,
+        value: 0,
+        index: u16::from(port),
+    };
+    bus.control(hub, request, &mut status)?;
+    Ok(u32::from_le_bytes(status))
+}
+
+/// Sets the feature `feature` of `hub`'s port `port`.
+fn set_feature(bus: &mut Bus, hub: &Device, port: u8, feature: u16) -> Result<(), Error> {
+    let request = Request {
+        kind: TO_PORT,
+        request: SET_FEATURE,
+        value: feature,
+        index: u16::from(port),
+    };
+    bus.control(hub, request, &mut [])?;
+    Ok(())
+}
+
+/// Clears the feature `feature` of `hub`'s port `port`.
+fn clear_feature(bus: &mut Bus, hub: &Device, port: u8, feature: u16) -> Result<(), Error> {
+    let request = Request {
+        kind: TO_PORT,
+        request: CLEAR_FEATURE,
+        value: feature,
+        index: u16::from(port),
+    };
+    bus.control(hub, request, &mut [])?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! The driver for keyboards in the HID boot protocol, whose keys are typed
+//! as console input.
+
+use pi::timer;
+use pi::usb::{EndpointType, Pid};
+
+use super::keys::{Key, Keys};
+use super::{Bus, Descriptor, Device, Driver, Endpoint, Error, Request};
+use crate::console::{self, kprintln};
+use crate::fb;
+
+/// The class, subclass and protocol of boot keyboard interfaces.
+const CLASS_HID: u8 = 0x03;
+const SUBCLASS_BOOT: u8 = 0x01;
+const PROTOCOL_KEYBOARD: u8 = 0x01;
+
+/// The `kind` of the HID class's requests of an interface.
+const TO_INTERFACE: u8 = 0x21;
+
+/// Request codes.
+const SET_REPORT: u8 = 0x09;
+const SET_IDLE: u8 = 0x0A;
+const SET_PROTOCOL: u8 = 0x0B;
+
+/// The value of `SET_PROTOCOL` that picks the boot protocol.
+const BOOT_PROTOCOL: u16 = 0;
+
+/// The value of `SET_REPORT` for output reports, and the bit of the output
+/// report that lights caps lock.
+const OUTPUT_REPORT: u16 = 0x0200;
+const LED_CAPS_LOCK: u8 = 1 << 1;
+
+/// The size of a boot protocol report.
+const REPORT_LEN: usize = 8;
+
+/// How many lines the terminal scrolls for a page.
+const PAGE: isize = 24;
+
+/// A boot keyboard.
+pub struct Keyboard {
+    device: Device,
+    interface: u8,
+    /// The interrupt endpoint reports come from, and the packet ID of the
+    /// next one.
+    endpoint: Endpoint,
+    toggle: Pid,
+    keys: Keys,
+    /// Whether polling has failed, and is not tried any more.
+    failed: bool,
+}
+
+impl Keyboard {
+    /// Returns the driver for `device` if it has a boot keyboard interface,
+    /// having switched the interface to the boot protocol.
+    pub fn probe(bus: &mut Bus, device: &Device) -> Result<Option<Keyboard>, Error> {
+        let mut interface = None;
+        let mut endpoint = None;
+        for descriptor in device.descriptors() {
+            match descriptor {
+                Descriptor::Interface(_) if interface.is_some() => break,
+                Descriptor::Interface(found) => {
+                    let keyboard = (CLASS_HID, SUBCLASS_BOOT, PROTOCOL_KEYBOARD);
+                    if (found.class, found.subclass, found.protocol) == keyboard {
+                        interface = Some(found.number);
+                    }
+                }
+                Descriptor::Endpoint(found)
+                    if interface.is_some()
+                        && found.input
+                        && found.kind == EndpointType::Interrupt =>
+                {
+                    endpoint = Some(found);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let (interface, endpoint) = match (interface, endpoint) {
+            (Some(interface), Some(endpoint)) => (interface, endpoint),
+            _ => return Ok(None),
+        };
+
+        let interface_request = |request, value| Request {
+            kind: TO_INTERFACE,
+            request,
+            value,
+            index: u16::from(interface),
+        };
+        bus.control(
+            device,
+            interface_request(SET_PROTOCOL, BOOT_PROTOCOL),
+            &mut [],
+        )?;
+        // Reports only when a key is pressed or let go; repeats are made
+        // here.
+        bus.control(device, interface_request(SET_IDLE, 0), &mut [])?;
+
+        Ok(Some(Keyboard {
+            device: device.clone(),
+            interface,
+            endpoint,
+            toggle: Pid::Data0,
+            keys: Keys::new(),
+            failed: false,
+        }))
+    }
+
+    /// Lights caps lock's LED if caps lock is on, or puts it out.
+    fn set_leds(&mut self, bus: &mut Bus) {
+        let leds = if self.keys.caps_lock() {
+            LED_CAPS_LOCK
+        } else {
+            0
+        };
+        let request = Request {
+            kind: TO_INTERFACE,
+            request: SET_REPORT,
+            value: OUTPUT_REPORT,
+            index: u16::from(self.interface),
+        };
+        // Not every keyboard has LEDs to light.
+        let _ = bus.control(&self.device, request, &mut [leds]);
+    }
+}
+
+impl Driver for Keyboard {
+    fn poll(&mut self, bus: &mut Bus) {
+        if self.failed {
+            return;
+        }
+
+        let mut report = [0; REPORT_LEN];
+        let received =
+            bus.interrupt_in(&self.device, &self.endpoint, &mut self.toggle, &mut report);
+        let now = timer::current_time();
+        let mut caps_lock = false;
+        match received {
+            Ok(Some(_)) => self
+                .keys
+                .report(&report, now, |key| caps_lock |= press(key)),
+            Ok(None) => {}
+            Err(e) => {
+                kprintln!("usb: keyboard {} failed: {}", self.device.address, e);
+                self.failed = true;
+                return;
+            }
+        }
+        self.keys.repeat(now, |key| {
+            press(key);
+        });
+
+        if caps_lock {
+            self.set_leds(bus);
+        }
+    }
+}
+
+/// Does what pressing a key does. Returns `true` if it switched caps lock.
+fn press(key: Key) -> bool {
+    match key {
+        Key::Byte(byte) => console::receive(byte),
+        Key::Meta(byte) => {
+            console::receive(0x1b);
+            console::receive(byte);
+        }
+        Key::Sequence(bytes) => bytes.iter().for_each(|&byte| console::receive(byte)),
+        Key::ScrollBack => fb::scroll(PAGE),
+        Key::ScrollForward => fb::scroll(-PAGE),
+        Key::CapsLock => return true,
+    }
+    false
+}
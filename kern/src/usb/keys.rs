@@ -0,0 +1,295 @@
+//! Translating the reports of keyboards in the boot protocol into the bytes
+//! a terminal would send for the same keys, with a US keymap.
+//!
+//! A report is a byte of modifier bits, a reserved byte, then the usages of
+//! up to six keys held down. Keys are pressed when they first appear in a
+//! report; the last one pressed repeats while it is held.
+
+use core::time::Duration;
+
+/// The modifier bits of reports, for the left and right keys.
+const CTRL: u8 = 0x11;
+const SHIFT: u8 = 0x22;
+const ALT: u8 = 0x44;
+
+/// The usage reported for every key when too many are held to tell which.
+const ROLLOVER_ERROR: u8 = 0x01;
+
+/// Usages of keys that are not in `KEYMAP`.
+const CAPS_LOCK: u8 = 0x39;
+const INSERT: u8 = 0x49;
+const HOME: u8 = 0x4a;
+const PAGE_UP: u8 = 0x4b;
+const DELETE: u8 = 0x4c;
+const END: u8 = 0x4d;
+const PAGE_DOWN: u8 = 0x4e;
+const RIGHT: u8 = 0x4f;
+const LEFT: u8 = 0x50;
+const DOWN: u8 = 0x51;
+const UP: u8 = 0x52;
+
+/// The first and last usages of the keypad's keys, and what they type.
+const KEYPAD_FIRST: u8 = 0x54;
+const KEYPAD_LAST: u8 = 0x63;
+const KEYPAD: &[u8; 16] = b"/*-+\r1234567890.";
+
+/// What the keys up to `CAPS_LOCK` type, and type with shift, indexed by
+/// usage, or 0 for keys that type nothing.
+#[rustfmt::skip]
+const KEYMAP: [(u8, u8); CAPS_LOCK as usize] = [
+    (0, 0), (0, 0), (0, 0), (0, 0),
+    (b'a', b'A'), (b'b', b'B'), (b'c', b'C'), (b'd', b'D'),
+    (b'e', b'E'), (b'f', b'F'), (b'g', b'G'), (b'h', b'H'),
+    (b'i', b'I'), (b'j', b'J'), (b'k', b'K'), (b'l', b'L'),
+    (b'm', b'M'), (b'n', b'N'), (b'o', b'O'), (b'p', b'P'),
+    (b'q', b'Q'), (b'r', b'R'), (b's', b'S'), (b't', b'T'),
+    (b'u', b'U'), (b'v', b'V'), (b'w', b'W'), (b'x', b'X'),
+    (b'y', b'Y'), (b'z', b'Z'), (b'1', b'!'), (b'2', b'@'),
+    (b'3', b'#'), (b'4', b'$'), (b'5', b'%'), (b'6', b'^'),
+    (b'7', b'&'), (b'8', b'*'), (b'9', b'('), (b'0', b')'),
+    (b'\r', b'\r'), (0x1b, 0x1b), (0x7f, 0x7f), (b'\t', b'\t'),
+    (b' ', b' '), (b'-', b'_'), (b'=', b'+'), (b'[', b'{'),
+    (b']', b'}'), (b'\\', b'|'), (b'#', b'~'), (b';', b':'),
+    (b'\'', b'"'), (b'`', b'~'), (b',', b'<'), (b'.', b'>'),
+    (b'/', b'?'),
+];
+
+/// How long a key is held before it repeats, and how often it repeats then.
+pub const REPEAT_DELAY: Duration = Duration::from_millis(500);
+pub const REPEAT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// What pressing a key does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// Types a byte.
+    Byte(u8),
+    /// Types a byte after `ESC`, as terminals do for keys pressed with alt.
+    Meta(u8),
+    /// Types an escape sequence.
+    Sequence(&'static [u8]),
+    /// Scrolls the terminal back a page, or forward.
+    ScrollBack,
+    ScrollForward,
+    /// Switches caps lock on or off.
+    CapsLock,
+}
+
+/// Returns what pressing the key `usage` does, with the modifier bits
+/// `modifiers` and caps lock on if `caps_lock`, or `None` if nothing.
+pub fn translate(usage: u8, modifiers: u8, caps_lock: bool) -> Option<Key> {
+    let shift = modifiers & SHIFT != 0;
+    let sequence = |bytes| Some(Key::Sequence(bytes));
+    let byte = match usage {
+        CAPS_LOCK => return Some(Key::CapsLock),
+        INSERT => return sequence(b"\x1b[2~"),
+        HOME => return sequence(b"\x1b[H"),
+        PAGE_UP if shift => return Some(Key::ScrollBack),
+        PAGE_UP => return sequence(b"\x1b[5~"),
+        DELETE => return sequence(b"\x1b[3~"),
+        END => return sequence(b"\x1b[F"),
+        PAGE_DOWN if shift => return Some(Key::ScrollForward),
+        PAGE_DOWN => return sequence(b"\x1b[6~"),
+        RIGHT => return sequence(b"\x1b[C"),
+        LEFT => return sequence(b"\x1b[D"),
+        DOWN => return sequence(b"\x1b[B"),
+        UP => return sequence(b"\x1b[A"),
+        KEYPAD_FIRST..=KEYPAD_LAST => KEYPAD[usize::from(usage - KEYPAD_FIRST)],
+        _ => {
+            let &(plain, shifted) = KEYMAP.get(usize::from(usage))?;
+            let shift = shift ^ (caps_lock && plain.is_ascii_lowercase());
+            if shift {
+                shifted
+            } else {
+                plain
+            }
+        }
+    };
+    if byte == 0 {
+        return None;
+    }
+
+    let byte = match byte {
+        b'a'..=b'z' | b'@'..=b'_' | b' ' if modifiers & CTRL != 0 => byte & 0x1f,
+        _ => byte,
+    };
+    if modifiers & ALT != 0 {
+        Some(Key::Meta(byte))
+    } else {
+        Some(Key::Byte(byte))
+    }
+}
+
+/// The state of a keyboard, as its reports have left it.
+#[derive(Debug)]
+pub struct Keys {
+    /// The keys held, as the last report gave them.
+    held: [u8; 6],
+    modifiers: u8,
+    caps_lock: bool,
+    /// The key that repeats, and when it next does.
+    repeat: Option<(u8, Duration)>,
+}
+
+impl Keys {
+    /// Returns the state of a keyboard with no keys held and caps lock off.
+    pub fn new() -> Keys {
+        Keys {
+            held: [0; 6],
+            modifiers: 0,
+            caps_lock: false,
+            repeat: None,
+        }
+    }
+
+    /// Returns `true` if caps lock is on.
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    /// Takes the report `report`, received at `now`, calling `f` with what
+    /// each key it presses does.
+    pub fn report<F: FnMut(Key)>(&mut self, report: &[u8], now: Duration, mut f: F) {
+        if report.len() < 8 || report[2..8].contains(&ROLLOVER_ERROR) {
+            return;
+        }
+
+        let held = &report[2..8];
+        self.modifiers = report[0];
+        if let Some((usage, _)) = self.repeat {
+            if !held.contains(&usage) {
+                self.repeat = None;
+            }
+        }
+
+        for &usage in held {
+            if usage == 0 || self.held.contains(&usage) {
+                continue;
+            }
+            match translate(usage, self.modifiers, self.caps_lock) {
+                Some(Key::CapsLock) => {
+                    self.caps_lock = !self.caps_lock;
+                    f(Key::CapsLock);
+                }
+                Some(key) => {
+                    self.repeat = Some((usage, now + REPEAT_DELAY));
+                    f(key);
+                }
+                None => {}
+            }
+        }
+        self.held.copy_from_slice(held);
+    }
+
+    /// Calls `f` with what the last key pressed does, if it is still held
+    /// and due to repeat at `now`.
+    pub fn repeat<F: FnMut(Key)>(&mut self, now: Duration, mut f: F) {
+        if let Some((usage, at)) = self.repeat {
+            if now >= at {
+                self.repeat = Some((usage, now + REPEAT_INTERVAL));
+                if let Some(key) = translate(usage, self.modifiers, self.caps_lock) {
+                    f(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const A: u8 = 0x04;
+    const C: u8 = 0x06;
+    const ONE: u8 = 0x1e;
+
+    #[test]
+    fn translates_keys() {
+        assert_eq!(translate(A, 0, false), Some(Key::Byte(b'a')));
+        assert_eq!(translate(A, 0x02, false), Some(Key::Byte(b'A')));
+        assert_eq!(translate(A, 0x20, true), Some(Key::Byte(b'a')));
+        assert_eq!(translate(ONE, 0x02, false), Some(Key::Byte(b'!')));
+        assert_eq!(translate(ONE, 0, true), Some(Key::Byte(b'1')));
+        assert_eq!(translate(C, 0x01, false), Some(Key::Byte(0x03)));
+        assert_eq!(translate(C, 0x40, false), Some(Key::Meta(b'c')));
+        assert_eq!(translate(0x2a, 0, false), Some(Key::Byte(0x7f)));
+        assert_eq!(translate(0x59, 0x02, false), Some(Key::Byte(b'1')));
+        assert_eq!(translate(0x00, 0, false), None);
+        assert_eq!(translate(0xe0, 0, false), None);
+    }
+
+    #[test]
+    fn translates_special_keys() {
+        assert_eq!(translate(UP, 0, false), Some(Key::Sequence(b"\x1b[A")));
+        assert_eq!(translate(DELETE, 0, false), Some(Key::Sequence(b"\x1b[3~")));
+        assert_eq!(
+            translate(PAGE_UP, 0, false),
+            Some(Key::Sequence(b"\x1b[5~"))
+        );
+        assert_eq!(translate(PAGE_UP, 0x02, false), Some(Key::ScrollBack));
+        assert_eq!(translate(PAGE_DOWN, 0x20, false), Some(Key::ScrollForward));
+        assert_eq!(translate(CAPS_LOCK, 0, false), Some(Key::CapsLock));
+    }
+
+    fn report(keys: &mut Keys, report: [u8; 8], ms: u64) -> Vec<Key> {
+        let mut pressed = Vec::new();
+        keys.report(&report, Duration::from_millis(ms), |key| pressed.push(key));
+        pressed
+    }
+
+    #[test]
+    fn presses_new_keys() {
+        let mut keys = Keys::new();
+        assert_eq!(
+            report(&mut keys, [0, 0, A, 0, 0, 0, 0, 0], 0),
+            [Key::Byte(b'a')]
+        );
+        assert_eq!(
+            report(&mut keys, [0, 0, A, C, 0, 0, 0, 0], 10),
+            [Key::Byte(b'c')]
+        );
+        assert_eq!(report(&mut keys, [0, 0, C, 0, 0, 0, 0, 0], 20), []);
+        assert_eq!(report(&mut keys, [0, 0, 1, 1, 1, 1, 1, 1], 30), []);
+        assert_eq!(report(&mut keys, [0, 0, 0, 0, 0, 0, 0, 0], 40), []);
+        assert_eq!(
+            report(&mut keys, [2, 0, C, 0, 0, 0, 0, 0], 50),
+            [Key::Byte(b'C')]
+        );
+    }
+
+    #[test]
+    fn caps_lock_toggles() {
+        let mut keys = Keys::new();
+        assert_eq!(
+            report(&mut keys, [0, 0, CAPS_LOCK, 0, 0, 0, 0, 0], 0),
+            [Key::CapsLock]
+        );
+        assert!(keys.caps_lock());
+        assert_eq!(
+            report(&mut keys, [0, 0, CAPS_LOCK, A, 0, 0, 0, 0], 10),
+            [Key::Byte(b'A')]
+        );
+        assert_eq!(report(&mut keys, [0, 0, 0, 0, 0, 0, 0, 0], 20), []);
+        assert_eq!(
+            report(&mut keys, [0, 0, CAPS_LOCK, 0, 0, 0, 0, 0], 30),
+            [Key::CapsLock]
+        );
+        assert!(!keys.caps_lock());
+    }
+
+    #[test]
+    fn held_keys_repeat() {
+        let mut keys = Keys::new();
+        let mut repeated = Vec::new();
+        report(&mut keys, [0, 0, A, 0, 0, 0, 0, 0], 0);
+        keys.repeat(Duration::from_millis(499), |key| repeated.push(key));
+        assert_eq!(repeated, []);
+        keys.repeat(Duration::from_millis(500), |key| repeated.push(key));
+        keys.repeat(Duration::from_millis(520), |key| repeated.push(key));
+        keys.repeat(Duration::from_millis(533), |key| repeated.push(key));
+        assert_eq!(repeated, [Key::Byte(b'a'), Key::Byte(b'a')]);
+
+        report(&mut keys, [0, 0, 0, 0, 0, 0, 0, 0], 540);
+        keys.repeat(Duration::from_millis(2000), |key| repeated.push(key));
+        assert_eq!(repeated.len(), 2);
+    }
+}
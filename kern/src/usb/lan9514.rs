@@ -0,0 +1,363 @@
+//! The driver for the Ethernet controller of the SMSC LAN9514, the hub and
+//! Ethernet controller the Pi 3 Model B's USB ports and Ethernet port are
+//! on. Frames go through the network stack in `net`.
+//!
+//! The controller's registers are read and written with vendor requests.
+//! Frames are received from one bulk endpoint and sent to another, each with
+//! a header of the controller's own.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use pi::timer;
+use pi::usb::{EndpointType, Pid};
+
+use super::{Bus, Descriptor, Device, Driver, Endpoint, Error, Request};
+use crate::console::kprintln;
+use crate::net::{self, MacAddr};
+use crate::{clock, firmware};
+
+/// The IDs of the LAN9514's Ethernet controller.
+const VENDOR_SMSC: u16 = 0x0424;
+const PRODUCT_LAN9514: u16 = 0xEC00;
+
+/// The `kind` of vendor requests of the device, and the vendor requests that
+/// write and read a register.
+const VENDOR: u8 = 0x40;
+const WRITE_REGISTER: u8 = 0xA0;
+const READ_REGISTER: u8 = 0xA1;
+
+/// Registers.
+const TX_CFG: u16 = 0x10;
+const HW_CFG: u16 = 0x14;
+const PM_CTRL: u16 = 0x20;
+const LED_GPIO_CFG: u16 = 0x24;
+const BURST_CAP: u16 = 0x38;
+const INT_STS: u16 = 0x08;
+const MAC_CR: u16 = 0x100;
+const ADDRH: u16 = 0x104;
+const ADDRL: u16 = 0x108;
+const MII_ADDR: u16 = 0x114;
+const MII_DATA: u16 = 0x118;
+
+/// `TX_CFG` bits.
+const TX_CFG_ON: u32 = 1 << 2;
+
+/// `HW_CFG` bits: a soft reset, and whether an empty bulk input gets a
+/// packet with no data rather than a NAK, so that it can be polled for.
+const HW_CFG_LRST: u32 = 1 << 3;
+const HW_CFG_BIR: u32 = 1 << 12;
+
+/// `PM_CTRL` bits.
+const PM_CTRL_PHY_RST: u32 = 1 << 4;
+
+/// `LED_GPIO_CFG`'s setting that has the LEDs show speed, link and duplex.
+const LED_GPIO_CFG_LEDS: u32 = 0x0111_0000;
+
+/// `MAC_CR` bits.
+const MAC_CR_RXEN: u32 = 1 << 2;
+const MAC_CR_TXEN: u32 = 1 << 3;
+const MAC_CR_FDPX: u32 = 1 << 20;
+
+/// `MII_ADDR` bits and fields.
+const MII_BUSY: u32 = 1 << 0;
+const MII_WRITE: u32 = 1 << 1;
+const MII_REG_SHIFT: u32 = 6;
+const MII_PHY_SHIFT: u32 = 11;
+
+/// The address of the internal PHY.
+const PHY: u32 = 1;
+
+/// PHY registers and bits.
+const PHY_BMCR: u32 = 0;
+const PHY_BMSR: u32 = 1;
+const PHY_ADVERTISE: u32 = 4;
+const PHY_LPA: u32 = 5;
+const BMCR_RESET: u32 = 1 << 15;
+const BMCR_AUTONEG: u32 = 1 << 12;
+const BMCR_RESTART_AUTONEG: u32 = 1 << 9;
+const BMSR_LINK: u32 = 1 << 2;
+const ADVERTISE_ALL: u32 = 0x01E1;
+const ADVERTISE_FULL_DUPLEX: u32 = 0x0140;
+
+/// The bits of the commands a sent frame starts with: it is a whole frame,
+/// of a length.
+const TX_FIRST_SEG: u32 = 1 << 13;
+const TX_LAST_SEG: u32 = 1 << 12;
+
+/// The fields of the status a received frame starts with: its length with
+/// its checksum, and whether it was received with errors.
+const RX_LEN_SHIFT: u32 = 16;
+const RX_LEN_MASK: u32 = 0x3FFF;
+const RX_ERROR: u32 = 1 << 15;
+
+/// The size of the commands before a sent frame, of the status before a
+/// received one, and of a frame's checksum.
+const TX_HEADER_LEN: usize = 8;
+const RX_HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 4;
+
+/// How much a bulk input asks for: a whole frame and its status.
+const RX_BUFFER_LEN: usize = 2048;
+
+/// The most frames received in one poll.
+const RX_FRAMES_PER_POLL: usize = 8;
+
+/// How often the link is checked on.
+const LINK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the controller and the PHY may take to come out of reset, and
+/// the PHY to answer, in checks a millisecond apart.
+const RESET_CHECKS: usize = 100;
+
+/// The LAN9514's Ethernet controller.
+pub struct Lan9514 {
+    device: Device,
+    /// The bulk endpoints frames are received from and sent to, and the
+    /// packet IDs of their next transfers.
+    rx: Endpoint,
+    rx_toggle: Pid,
+    tx: Endpoint,
+    tx_toggle: Pid,
+    /// A frame received, with its status.
+    buffer: Vec<u8>,
+    link: bool,
+    /// When the link is next checked on.
+    next_link_check: Duration,
+    /// Whether driving the controller has failed, and is not tried any
+    /// more.
+    failed: bool,
+}
+
+impl Lan9514 {
+    /// Returns the driver for `device` if it is a LAN9514's Ethernet
+    /// controller, having set the controller up and attached it to the
+    /// network stack.
+    pub fn probe(bus: &mut Bus, device: &Device) -> Result<Option<Lan9514>, Error> {
+        if (device.vendor, device.product) != (VENDOR_SMSC, PRODUCT_LAN9514) {
+            return Ok(None);
+        }
+
+        let bulk = |input| {
+            device
+                .descriptors()
+                .find_map(|descriptor| match descriptor {
+                    Descriptor::Endpoint(e) if e.kind == EndpointType::Bulk && e.input == input => {
+                        Some(e)
+                    }
+                    _ => None,
+                })
+        };
+        let (rx, tx) = match (bulk(true), bulk(false)) {
+            (Some(rx), Some(tx)) => (rx, tx),
+            _ => return Err(Error::BadDescriptor),
+        };
+
+        let mut lan = Lan9514 {
+            device: device.clone(),
+            rx,
+            rx_toggle: Pid::Data0,
+            tx,
+            tx_toggle: Pid::Data0,
+            buffer: vec![0; RX_BUFFER_LEN],
+            link: false,
+            next_link_check: Duration::from_secs(0),
+            failed: false,
+        };
+        let mac = lan.reset(bus)?;
+        kprintln!(
+            "usb: device {} is an Ethernet controller, {}",
+            device.address,
+            mac
+        );
+        net::attach(mac);
+        Ok(Some(lan))
+    }
+
+    /// Resets the controller and its PHY, and sets them up to send and
+    /// receive. Returns the controller's MAC address: the one the firmware
+    /// assigns the board, or the one the controller has if the firmware does
+    /// not say.
+    fn reset(&mut self, bus: &mut Bus) -> Result<MacAddr, Error> {
+        self.write(bus, HW_CFG, HW_CFG_LRST)?;
+        self.wait(bus, HW_CFG, HW_CFG_LRST)?;
+        self.write(bus, PM_CTRL, PM_CTRL_PHY_RST)?;
+        self.wait(bus, PM_CTRL, PM_CTRL_PHY_RST)?;
+
+        let mac = match firmware::mac_address() {
+            Ok(mac) if mac != [0; 6] => {
+                let [a, b, c, d, e, f] = mac;
+                self.write(bus, ADDRL, u32::from_le_bytes([a, b, c, d]))?;
+                self.write(bus, ADDRH, u32::from_le_bytes([e, f, 0, 0]))?;
+                MacAddr(mac)
+            }
+            _ => {
+                let [a, b, c, d] = self.read(bus, ADDRL)?.to_le_bytes();
+                let [e, f, _, _] = self.read(bus, ADDRH)?.to_le_bytes();
+                MacAddr([a, b, c, d, e, f])
+            }
+        };
+
+        // One frame per bulk input, and none held back to fill a burst.
+        self.write(bus, HW_CFG, HW_CFG_BIR)?;
+        self.write(bus, BURST_CAP, 0)?;
+        self.write(bus, INT_STS, !0)?;
+        self.write(bus, LED_GPIO_CFG, LED_GPIO_CFG_LEDS)?;
+
+        self.write_phy(bus, PHY_BMCR, BMCR_RESET)?;
+        for _ in 0..RESET_CHECKS {
+            if self.read_phy(bus, PHY_BMCR)? & BMCR_RESET == 0 {
+                break;
+            }
+            timer::spin_sleep(Duration::from_millis(1));
+        }
+        self.write_phy(bus, PHY_ADVERTISE, ADVERTISE_ALL)?;
+        self.write_phy(bus, PHY_BMCR, BMCR_AUTONEG | BMCR_RESTART_AUTONEG)?;
+
+        self.write(bus, MAC_CR, MAC_CR_RXEN | MAC_CR_TXEN)?;
+        self.write(bus, TX_CFG, TX_CFG_ON)?;
+        Ok(mac)
+    }
+
+    /// Checks whether the link is up, and tells the network stack if that
+    /// changed. A link that comes up at full duplex has the controller
+    /// switched to it.
+    fn check_link(&mut self, bus: &mut Bus) -> Result<(), Error> {
+        // The link bit stays clear once the link has gone down until read.
+        self.read_phy(bus, PHY_BMSR)?;
+        let link = self.read_phy(bus, PHY_BMSR)? & BMSR_LINK != 0;
+        if link && !self.link {
+            let common = self.read_phy(bus, PHY_ADVERTISE)? & self.read_phy(bus, PHY_LPA)?;
+            let mac_cr = self.read(bus, MAC_CR)?;
+            let mac_cr = if common & ADVERTISE_FULL_DUPLEX != 0 {
+                mac_cr | MAC_CR_FDPX
+            } else {
+                mac_cr & !MAC_CR_FDPX
+            };
+            self.write(bus, MAC_CR, mac_cr)?;
+        }
+        if link != self.link {
+            net::set_link(link);
+        }
+        self.link = link;
+        Ok(())
+    }
+
+    /// Receives the frames the controller has, then sends the ones the
+    /// network stack has queued.
+    fn exchange(&mut self, bus: &mut Bus) -> Result<(), Error> {
+        for _ in 0..RX_FRAMES_PER_POLL {
+            let len = bus.bulk_in(
+                &self.device,
+                &self.rx,
+                &mut self.rx_toggle,
+                &mut self.buffer,
+            )?;
+            if len < RX_HEADER_LEN {
+                break;
+            }
+
+            let status = u32::from_le_bytes([
+                self.buffer[0],
+                self.buffer[1],
+                self.buffer[2],
+                self.buffer[3],
+            ]);
+            let frame_len = ((status >> RX_LEN_SHIFT) & RX_LEN_MASK) as usize;
+            let end = RX_HEADER_LEN + frame_len;
+            if status & RX_ERROR == 0 && frame_len > CRC_LEN && end <= len {
+                net::receive(&self.buffer[RX_HEADER_LEN..end - CRC_LEN]);
+            }
+        }
+
+        net::poll();
+        while let Some(frame) = net::transmit() {
+            let len = frame.len() as u32;
+            let mut packet = Vec::with_capacity(TX_HEADER_LEN + frame.len());
+            packet.extend_from_slice(&(len | TX_FIRST_SEG | TX_LAST_SEG).to_le_bytes());
+            packet.extend_from_slice(&len.to_le_bytes());
+            packet.extend_from_slice(&frame);
+            bus.bulk_out(&self.device, &self.tx, &mut self.tx_toggle, &packet)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the value of the register `reg`.
+    fn read(&self, bus: &mut Bus, reg: u16) -> Result<u32, Error> {
+        let mut value = [0; 4];
+        let request = Request {
+            kind: Request::IN | VENDOR,
+            request: READ_REGISTER,
+            value: 0,
+            index: reg,
+        };
+        bus.control(&self.device, request, &mut value)?;
+        Ok(u32::from_le_bytes(value))
+    }
+
+    /// Writes `value` to the register `reg`.
+    fn write(&self, bus: &mut Bus, reg: u16, value: u32) -> Result<(), Error> {
+        let request = Request {
+            kind: VENDOR,
+            request: WRITE_REGISTER,
+            value: 0,
+            index: reg,
+        };
+        bus.control(&self.device, request, &mut value.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Waits for the bits `bits` of the register `reg` to clear.
+    fn wait(&self, bus: &mut Bus, reg: u16, bits: u32) -> Result<(), Error> {
+        for _ in 0..RESET_CHECKS {
+            if self.read(bus, reg)? & bits == 0 {
+                return Ok(());
+            }
+            timer::spin_sleep(Duration::from_millis(1));
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Returns the value of the PHY's register `reg`.
+    fn read_phy(&self, bus: &mut Bus, reg: u32) -> Result<u32, Error> {
+        self.wait(bus, MII_ADDR, MII_BUSY)?;
+        let addr = PHY << MII_PHY_SHIFT | reg << MII_REG_SHIFT | MII_BUSY;
+        self.write(bus, MII_ADDR, addr)?;
+        self.wait(bus, MII_ADDR, MII_BUSY)?;
+        Ok(self.read(bus, MII_DATA)? & 0xFFFF)
+    }
+
+    /// Writes `value` to the PHY's register `reg`.
+    fn write_phy(&self, bus: &mut Bus, reg: u32, value: u32) -> Result<(), Error> {
+        self.wait(bus, MII_ADDR, MII_BUSY)?;
+        self.write(bus, MII_DATA, value)?;
+        let addr = PHY << MII_PHY_SHIFT | reg << MII_REG_SHIFT | MII_WRITE | MII_BUSY;
+        self.write(bus, MII_ADDR, addr)?;
+        self.wait(bus, MII_ADDR, MII_BUSY)
+    }
+}
+
+impl Driver for Lan9514 {
+    fn poll(&mut self, bus: &mut Bus) {
+        if self.failed {
+            return;
+        }
+
+        let now = clock::uptime();
+        let mut result = Ok(());
+        if now >= self.next_link_check {
+            self.next_link_check = now + LINK_INTERVAL;
+            result = self.check_link(bus);
+        }
+        if let Err(e) = result.and_then(|_| self.exchange(bus)) {
+            kprintln!(
+                "usb: Ethernet controller {} failed: {}",
+                self.device.address,
+                e
+            );
+            self.failed = true;
+            net::set_link(false);
+        }
+    }
+}
@@ -0,0 +1,768 @@
+mod asid;
+mod descriptor;
+mod frame;
+mod table;
+mod user;
+mod walk;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use pi::common::{IO_BASE, IO_BASE_END, NCORES};
+
+use crate::aarch64::{self, SCTLR_EL1, TTBR0_EL1, TTBR1_EL1};
+use crate::mutex::Mutex;
+use crate::VMM;
+
+use self::descriptor::CONTIGUOUS_ENTRIES;
+
+pub use self::descriptor::{Access, Attributes, Descriptor, MemoryKind};
+pub use self::table::{IdentityMap, Table, ENTRIES};
+pub use self::user::{AccessKind, FaultError, Region, RegionError, SharedSpace, UserSpace};
+pub use self::walk::Mapping;
+
+/// The size of a page: the translation granule.
+pub const PAGE_SIZE: usize = 4096;
+/// The size of the block a level 2 descriptor maps.
+pub const L2_BLOCK_SIZE: usize = 2 << 20;
+/// The size of the block a level 1 descriptor maps.
+pub const L1_BLOCK_SIZE: usize = 1 << 30;
+
+/// The number of bits in a virtual address. With 4 KiB pages, translation
+/// starts at level 1.
+pub const VA_BITS: usize = 39;
+
+/// The lowest address of the upper half of the address space, translated
+/// through `TTBR1_EL1`. The kernel is linked here, at the start of an alias
+/// of physical memory; `layout.ld` and `init.s` use the same value.
+pub const KERNEL_BASE: usize = !((1 << VA_BITS) - 1);
+
+/// The start of the window that `map_device()` maps peripherals into, just
+/// above the kernel's alias of the first 2 GiB of physical memory.
+pub const DEVICE_BASE: usize = KERNEL_BASE + 2 * L1_BLOCK_SIZE;
+
+/// The lowest address of user memory in the lower half of the address space,
+/// above the kernel's identity map and device window.
+pub const USER_BASE: usize = 4 * L1_BLOCK_SIZE;
+/// The address just past the end of user memory: the end of the lower half.
+pub const USER_END: usize = 1 << VA_BITS;
+
+/// The memory types indexed by `MemoryKind`: normal write-back, device
+/// nGnRE and normal non-cacheable. `init.s` installs them.
+pub const MAIR: u64 = 0xFF | 0x04 << 8 | 0x44 << 16;
+
+/// The translation control: two `VA_BITS`-bit address spaces with 4 KiB
+/// pages, walked through write-back, inner shareable memory, and 32-bit
+/// physical addresses. ASIDs are 8 bits, and come from `TTBR0_EL1`. `init.s`
+/// installs it.
+pub const TCR: u64 = (64 - VA_BITS as u64) // T0SZ
+    | 0b01 << 8 // IRGN0: write-back, write-allocate
+    | 0b01 << 10 // ORGN0: write-back, write-allocate
+    | 0b11 << 12 // SH0: inner shareable
+    | 0b00 << 14 // TG0: 4 KiB
+    | (64 - VA_BITS as u64) << 16 // T1SZ
+    | 0b01 << 24 // IRGN1: write-back, write-allocate
+    | 0b01 << 26 // ORGN1: write-back, write-allocate
+    | 0b11 << 28 // SH1: inner shareable
+    | 0b10 << 30; // TG1: 4 KiB
+
+/// The most pages whose translations are invalidated one by one. Changing more
+/// invalidates the whole TLB instead.
+const TLBI_MAX_PAGES: usize = 64;
+
+/// Returns the kernel's virtual address for the physical address `pa`: its
+/// alias above `KERNEL_BASE`.
+///
+/// Host tests run in the host's address space, where memory is addressed by
+/// its "physical" address directly.
+pub fn phys_to_virt(pa: usize) -> usize {
+    if cfg!(not(target_os = "none")) {
+        pa
+    } else {
+        pa + KERNEL_BASE
+    }
+}
+
+/// Returns the physical address of the kernel's virtual address `va`. Lower
+/// half addresses are identity mapped, and returned unchanged.
+pub fn virt_to_phys(va: usize) -> usize {
+    if va >= KERNEL_BASE {
+        va - KERNEL_BASE
+    } else {
+        va
+    }
+}
+
+/// Rounds `n` up to a whole number of pages, or returns `None` on overflow.
+pub fn page_align(n: usize) -> Option<usize> {
+    n.checked_add(PAGE_SIZE - 1).map(|n| n & !(PAGE_SIZE - 1))
+}
+
+/// The kernel's virtual memory.
+///
+/// `init.s` turns on the MMU with boot translation tables that map physical
+/// memory both at its own addresses and at `KERNEL_BASE`, where the kernel
+/// runs. `initialize()` replaces them with an `IdentityMap` on the heap,
+/// installed for both halves, and moves the `pi` drivers to a mapping of the
+/// peripherals from `map_device()`.
+///
+/// The lower half stays identity mapped unless a `UserSpace` is activated in
+/// its place. User address spaces keep the kernel's mappings below
+/// `USER_BASE`, so the kernel can rely on them either way. Each core has its
+/// own active user address space, which the methods for the user address
+/// space act on: that of the core calling them. Threads of one process may
+/// have the same address space active on several cores at once.
+///
+/// Changes to the tables invalidate only the TLB entries they make stale: by
+/// address, or by ASID for a whole user address space.
+pub struct VMManager {
+    kernel: Mutex<Option<Box<IdentityMap>>>,
+    /// The user address space installed in each core's `TTBR0_EL1`, if any.
+    user: [Mutex<Option<SharedSpace>>; NCORES],
+    /// The address of a writable alias of the kernel's code, once mapped by
+    /// `patch_text()`.
+    text_alias: Mutex<Option<usize>>,
+}
+
+impl VMManager {
+    /// Returns an uninitialized `VMManager`. The boot translation tables stay
+    /// installed until `initialize()` is called.
+    pub const fn uninitialized() -> VMManager {
+        VMManager {
+            kernel: Mutex::new(None),
+            user: [
+                Mutex::new(None),
+                Mutex::new(None),
+                Mutex::new(None),
+                Mutex::new(None),
+            ],
+            text_alias: Mutex::new(None),
+        }
+    }
+
+    /// Builds the kernel's translation tables, with the kernel's code and
+    /// constants read-only, everything writable never executable and the
+    /// guard pages below the kernel's stacks unmapped, and installs them in
+    /// place of the boot tables. Then points the `pi` drivers at a mapping of
+    /// the peripherals. The allocator must be initialized.
+    pub fn initialize(&self) {
+        let mut map = IdentityMap::new();
+        let (text, rodata, data) = kernel_image();
+        map.set_attributes(text, rodata, Attributes::KERNEL_TEXT);
+        map.set_attributes(rodata, data, Attributes::KERNEL_RODATA);
+        for &(_, guard) in kernel_stack_guards().iter() {
+            let pa = virt_to_phys(guard);
+            map.unmap(pa, pa + PAGE_SIZE);
+        }
+        unsafe { install(map.base()) };
+        *self.kernel.lock() = Some(map);
+
+        let io = self.map_device(IO_BASE, IO_BASE_END - IO_BASE);
+        unsafe { pi::common::set_io_base(io) };
+    }
+
+    /// Installs the kernel's translation tables on a secondary core, in place
+    /// of the boot tables it turned its MMU on with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized.
+    pub fn initialize_core(&self) {
+        let base = self
+            .kernel
+            .lock()
+            .as_ref()
+            .expect("VMManager uninitialized")
+            .base();
+        unsafe { install(base) };
+    }
+
+    /// Maps the `len` bytes of peripheral registers at the physical address
+    /// `pa` as device memory, returning the virtual address of `pa`. The
+    /// mapping covers every page the range touches.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized or the device window is
+    /// full.
+    pub fn map_device(&self, pa: usize, len: usize) -> usize {
+        let offset = pa % PAGE_SIZE;
+        let pages = (offset + len + PAGE_SIZE - 1) / PAGE_SIZE;
+
+        let va = self
+            .kernel
+            .lock()
+            .as_mut()
+            .expect("VMManager uninitialized")
+            .map_device(pa - offset, pages)
+            .expect("device window full");
+        aarch64::tables_sync();
+        va + offset
+    }
+
+    /// Writes `bytes` over the kernel's code at the virtual address `va`, as
+    /// a debugger plants breakpoints, and makes the change visible to
+    /// instruction fetches. Returns `false`, writing nothing, if the bytes are
+    /// not all in the kernel's code.
+    ///
+    /// The code is never writable, so the bytes are written through an alias
+    /// of it mapped as device memory, which `map_device()` maps the first
+    /// time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized.
+    pub fn patch_text(&self, va: usize, bytes: &[u8]) -> bool {
+        let (text, rodata, _) = kernel_image();
+        let pa = virt_to_phys(va);
+        if pa < text || pa + bytes.len() > rodata {
+            return false;
+        }
+
+        let alias = *self
+            .text_alias
+            .lock()
+            .get_or_insert_with(|| self.map_device(text, rodata - text));
+        for (i, &byte) in bytes.iter().enumerate() {
+            let target = (alias + pa - text + i) as *mut u8;
+            unsafe { target.write_volatile(byte) };
+        }
+
+        // The cached copies of the code are stale, but never dirty.
+        aarch64::clean_invalidate_data_cache(va, bytes.len());
+        aarch64::sync_instruction_cache(va, bytes.len());
+        true
+    }
+
+    /// Changes the attributes of the `len` bytes of RAM at the kernel's
+    /// virtual address `va`, both page aligned. Memory returned to the heap
+    /// must be given back `Attributes::KERNEL_RAM`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized or the range is not
+    /// page aligned RAM.
+    pub fn set_attributes(&self, va: usize, len: usize, attrs: Attributes) {
+        let pa = virt_to_phys(va);
+        let split = self
+            .kernel
+            .lock()
+            .as_mut()
+            .expect("VMManager uninitialized")
+            .set_attributes(pa, pa + len, attrs);
+        invalidate_ram(pa, len, split);
+    }
+
+    /// Unmaps the `len` bytes of RAM at the kernel's virtual address `va`,
+    /// both page aligned, so that accessing them faults. Memory returned to
+    /// the heap must be mapped again with `set_attributes()` first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized or the range is not
+    /// page aligned RAM.
+    pub fn unmap(&self, va: usize, len: usize) {
+        let pa = virt_to_phys(va);
+        let split = self
+            .kernel
+            .lock()
+            .as_mut()
+            .expect("VMManager uninitialized")
+            .unmap(pa, pa + len);
+        invalidate_ram(pa, len, split);
+    }
+
+    /// Returns the physical address that the virtual address `va` maps to,
+    /// if any. Before `initialize()`, the boot tables' mapping is assumed.
+    pub fn translate(&self, va: usize) -> Option<usize> {
+        if va >= USER_BASE && va < USER_END {
+            return self
+                .with_user(|space| space.translate(va))
+                .flatten()
+                .map(|(_, pa)| pa);
+        }
+
+        match *self.kernel.lock() {
+            Some(ref map) => map.translate(va).map(|(_, pa)| pa),
+            None => Some(virt_to_phys(va)),
+        }
+    }
+
+    /// Returns the mappings of the upper half of the address space, then
+    /// those of the lower half: the active user address space's, if there is
+    /// one, or else the identity map's. Each half's are in address order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized.
+    pub fn mappings(&self) -> (Vec<Mapping>, Vec<Mapping>) {
+        let user = self.user().lock();
+        let kernel = self.kernel.lock();
+        let map = kernel.as_ref().expect("VMManager uninitialized");
+
+        let mut upper = Vec::new();
+        map.walk(|mapping| upper.push(mapping));
+        let mut lower = Vec::new();
+        match *user {
+            Some(ref space) => space.lock().walk(|mapping| lower.push(mapping)),
+            // The identity map is installed for both halves.
+            None => map.walk(|mapping| {
+                lower.push(Mapping {
+                    va: mapping.va - KERNEL_BASE,
+                    ..mapping
+                })
+            }),
+        }
+        (upper, lower)
+    }
+
+    /// Returns a new, empty user address space sharing the kernel's mappings
+    /// below `USER_BASE`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized.
+    pub fn new_user_space(&self) -> UserSpace {
+        let kernel = self.kernel.lock();
+        let map = kernel.as_ref().expect("VMManager uninitialized");
+        UserSpace::new(map.lower_entries())
+    }
+
+    /// Installs `space` in `TTBR0_EL1`, returning the user address space it
+    /// replaces, if any. The TLB keeps the translations of the address
+    /// spaces with ASIDs of their own.
+    pub fn activate(&self, space: SharedSpace) -> Option<SharedSpace> {
+        let mut user = self.user().lock();
+        {
+            let space = space.lock();
+            unsafe { install_user(space.base(), space.asid()) };
+        }
+        let last = user.replace(space);
+        if last
+            .as_ref()
+            .map_or(false, |space| space.lock().asid().is_none())
+        {
+            aarch64::tlb_invalidate_asid(0);
+        }
+        last
+    }
+
+    /// Removes the active user address space, if any, putting the kernel's
+    /// identity map back in `TTBR0_EL1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `VMManager` is uninitialized.
+    pub fn deactivate(&self) -> Option<SharedSpace> {
+        let mut user = self.user().lock();
+        let base = self
+            .kernel
+            .lock()
+            .as_ref()
+            .expect("VMManager uninitialized")
+            .base();
+        unsafe { install_user(base, Some(0)) };
+        let space = user.take();
+        if space
+            .as_ref()
+            .map_or(false, |space| space.lock().asid().is_none())
+        {
+            aarch64::tlb_invalidate_asid(0);
+        }
+        space
+    }
+
+    /// Returns `true` if a user address space is active on this core.
+    pub fn has_user_space(&self) -> bool {
+        self.user().lock().is_some()
+    }
+
+    /// Returns a copy-on-write copy of the active user address space, if any.
+    /// See `UserSpace::fork()`.
+    pub fn fork_user(&self) -> Option<UserSpace> {
+        self.with_user(|space| {
+            let child = space.fork();
+            aarch64::tlb_invalidate_asid(space.asid().unwrap_or(0));
+            child
+        })
+    }
+
+    /// Copies the bytes at `va` in the active user address space to `buf`.
+    /// See `UserSpace::read()`.
+    pub fn read_user(&self, va: usize, buf: &mut [u8]) -> Result<(), FaultError> {
+        self.with_user(|space| space.read(va, buf))
+            .unwrap_or(Err(FaultError::Unmapped))
+    }
+
+    /// Copies `bytes` to `va` in the active user address space. See
+    /// `UserSpace::store()`.
+    pub fn write_user(&self, va: usize, bytes: &[u8]) -> Result<(), FaultError> {
+        self.with_user(|space| {
+            let result = space.store(va, bytes);
+            let pages = (va % PAGE_SIZE + bytes.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+            aarch64::tlb_invalidate_pages(Some(space.asid().unwrap_or(0)), va, pages);
+            result
+        })
+        .unwrap_or(Err(FaultError::Unmapped))
+    }
+
+    /// Resolves a fault on an `access` to `va` in the active user address
+    /// space, by mapping a zeroed page or copying a shared one. See
+    /// `UserSpace::handle_fault()`. Once this returns `Ok`, the access can be
+    /// retried.
+    pub fn handle_user_fault(&self, va: usize, access: AccessKind) -> Result<(), FaultError> {
+        self.with_user(|space| {
+            space.handle_fault(va, access)?;
+            aarch64::tlb_invalidate_pages(Some(space.asid().unwrap_or(0)), va, 1);
+            Ok(())
+        })
+        .unwrap_or(Err(FaultError::Unmapped))
+    }
+
+    /// Moves the program break of the active user address space to `brk`,
+    /// unless it is `None`, returning where the break is. See
+    /// `UserSpace::set_brk()`.
+    pub fn set_user_brk(&self, brk: Option<usize>) -> Result<usize, RegionError> {
+        self.with_user(|space| {
+            let end = space.heap().ok_or(RegionError::OutOfRange)?.end;
+            if let Some(brk) = brk {
+                space.set_brk(brk)?;
+                let new_end = space.heap().map_or(end, |heap| heap.end);
+                if new_end < end {
+                    let pages = (end - new_end) / PAGE_SIZE;
+                    aarch64::tlb_invalidate_pages(Some(space.asid().unwrap_or(0)), new_end, pages);
+                }
+            }
+            space.brk().ok_or(RegionError::OutOfRange)
+        })
+        .unwrap_or(Err(RegionError::OutOfRange))
+    }
+
+    /// Sets the memory limit of the active user address space to `limit`
+    /// bytes, unless it is `None`, returning the limit. Returns `None` if
+    /// there is no active user address space. See
+    /// `UserSpace::set_memory_limit()`.
+    pub fn set_user_memory_limit(&self, limit: Option<usize>) -> Option<usize> {
+        self.with_user(|space| {
+            if let Some(limit) = limit {
+                space.set_memory_limit(limit);
+            }
+            space.memory_limit()
+        })
+    }
+
+    /// Calls `f` with this core's active user address space, returning what
+    /// it returns, or `None` if there is none.
+    fn with_user<R, F: FnOnce(&mut UserSpace) -> R>(&self, f: F) -> Option<R> {
+        let user = self.user().lock();
+        let mut space = user.as_ref()?.lock();
+        Some(f(&mut space))
+    }
+
+    /// Returns this core's active user address space.
+    fn user(&self) -> &Mutex<Option<SharedSpace>> {
+        &self.user[aarch64::affinity()]
+    }
+}
+
+/// Maps `len` bytes of peripheral registers at the physical address `pa`.
+/// See `VMManager::map_device()`.
+pub fn map_device(pa: usize, len: usize) -> usize {
+    VMM.map_device(pa, len)
+}
+
+/// Returns the name of the kernel stack whose guard page contains `va`, if
+/// any: an access there means that stack overflowed.
+pub fn stack_overflow_in(va: usize) -> Option<&'static str> {
+    kernel_stack_guards()
+        .iter()
+        .find(|&&(_, guard)| guard <= va && va < guard + PAGE_SIZE)
+        .map(|&(name, _)| name)
+}
+
+/// Returns `true` if the MMU is on.
+pub fn is_enabled() -> bool {
+    SCTLR_EL1::read() & SCTLR_EL1::M != 0
+}
+
+/// Returns the physical addresses of the start of the kernel's code, its
+/// constants and its data, each page aligned by `layout.ld`. In host tests,
+/// where there is no such image, all three are `0`.
+fn kernel_image() -> (usize, usize, usize) {
+    #[cfg(target_os = "none")]
+    {
+        extern "C" {
+            static __text_beg: u8;
+            static __rodata_beg: u8;
+            static __data_beg: u8;
+        }
+
+        unsafe {
+            (
+                virt_to_phys(&__text_beg as *const u8 as usize),
+                virt_to_phys(&__rodata_beg as *const u8 as usize),
+                virt_to_phys(&__data_beg as *const u8 as usize),
+            )
+        }
+    }
+
+    #[cfg(not(target_os = "none"))]
+    (0, 0, 0)
+}
+
+/// Returns the kernel's stacks, each named by what runs on it, with the virtual
+/// address of the page below it that `layout.ld` and `init.s` leave as a
+/// guard.
+fn kernel_stack_guards() -> [(&'static str, usize); 2] {
+    extern "C" {
+        static __boot_stack_guard: u8;
+        static __fiq_stack_guard: u8;
+    }
+
+    unsafe {
+        [
+            ("kmain", &__boot_stack_guard as *const u8 as usize),
+            ("the FIQ handler", &__fiq_stack_guard as *const u8 as usize),
+        ]
+    }
+}
+
+/// Invalidates the TLB's translations of the `len` bytes of RAM at the
+/// physical address `pa`, at both its alias and its identity mapped address,
+/// after their descriptors changed. The runs of pages marked contiguous that
+/// they are in are invalidated whole. If a block was `split`, or there are
+/// too many pages, the whole TLB is invalidated instead.
+fn invalidate_ram(pa: usize, len: usize, split: bool) {
+    let run = CONTIGUOUS_ENTRIES * PAGE_SIZE;
+    let start = pa - pa % run;
+    let end = (pa + len + run - 1) / run * run;
+    let pages = (end - start) / PAGE_SIZE;
+
+    if split || pages > TLBI_MAX_PAGES {
+        aarch64::tlb_invalidate_all();
+    } else {
+        aarch64::tlb_invalidate_pages(None, phys_to_virt(start), pages);
+        aarch64::tlb_invalidate_pages(None, start, pages);
+    }
+}
+
+/// Installs the translation tables at the physical address `base` for both
+/// halves of the address space, and makes writable memory never executable.
+///
+/// # Safety
+///
+/// The tables must map every address the kernel uses to the same physical
+/// address the current tables do, and the kernel's code must not be
+/// writable.
+unsafe fn install(base: usize) {
+    TTBR0_EL1::write(base as u64);
+    TTBR1_EL1::write(base as u64);
+    SCTLR_EL1::modify(|sctlr| sctlr | SCTLR_EL1::WXN);
+    aarch64::tlb_invalidate_all();
+}
+
+/// Installs the translation tables at the physical address `base` for the
+/// lower half of the address space, tagged with `asid`. `None` means the
+/// tables share ASID 0 with others, so the TLB's translations for it are
+/// invalidated.
+///
+/// # Safety
+///
+/// The tables must map everything below `USER_BASE` as the kernel's do, and
+/// no other tables may be installed with the same ASID while the TLB could
+/// hold their translations.
+unsafe fn install_user(base: usize, asid: Option<u16>) {
+    TTBR0_EL1::write(base as u64 | (asid.unwrap_or(0) as u64) << 48);
+    if asid.is_none() {
+        aarch64::tlb_invalidate_asid(0);
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{
+        Attributes, IdentityMap, DEVICE_BASE, KERNEL_BASE, L1_BLOCK_SIZE, MAIR, PAGE_SIZE, TCR,
+    };
+    use pi::common::IO_BASE;
+
+    #[test]
+    fn identity_map() {
+        let map = IdentityMap::new();
+        assert_eq!(map.base() % 4096, 0);
+
+        let (ram, pa) = map.translate(0x8_0123).unwrap();
+        assert_eq!(pa, 0x8_0123);
+        assert_eq!(ram.attributes(), Attributes::KERNEL_RAM);
+
+        let (io, pa) = map.translate(IO_BASE + 0x21_5040).unwrap();
+        assert_eq!(pa, IO_BASE + 0x21_5040);
+        assert_eq!(io.attributes(), Attributes::DEVICE);
+
+        let (local, pa) = map.translate(0x4000_0040).unwrap();
+        assert_eq!(pa, 0x4000_0040);
+        assert_eq!(local.attributes(), Attributes::DEVICE);
+
+        assert!(map.translate(2 * L1_BLOCK_SIZE).is_none());
+        assert!(map.translate(1 << 40).is_none());
+    }
+
+    #[test]
+    fn kernel_alias() {
+        let map = IdentityMap::new();
+
+        let (ram, pa) = map.translate(KERNEL_BASE + 0x8_0123).unwrap();
+        assert_eq!(pa, 0x8_0123);
+        assert_eq!(ram.attributes(), Attributes::KERNEL_RAM);
+
+        let (io, pa) = map.translate(KERNEL_BASE + IO_BASE + 0x20_0000).unwrap();
+        assert_eq!(pa, IO_BASE + 0x20_0000);
+        assert_eq!(io.attributes(), Attributes::DEVICE);
+
+        assert!(map.translate(KERNEL_BASE + 2 * L1_BLOCK_SIZE).is_none());
+        assert!(map.translate(KERNEL_BASE - 1).is_none());
+    }
+
+    #[test]
+    fn device_window() {
+        let mut map = IdentityMap::new();
+
+        let gpio = map.map_device(IO_BASE + 0x20_0000, 1).unwrap();
+        assert_eq!(gpio, DEVICE_BASE);
+        let (page, pa) = map.translate(gpio + 0x94).unwrap();
+        assert_eq!(pa, IO_BASE + 0x20_0094);
+        assert_eq!(page.attributes(), Attributes::DEVICE);
+
+        // the window fills in order, crossing into a second level 3 table
+        let io = map.map_device(IO_BASE, 4096).unwrap();
+        assert_eq!(io, DEVICE_BASE + PAGE_SIZE);
+        let (_, pa) = map.translate(io + 0x21_5040).unwrap();
+        assert_eq!(pa, IO_BASE + 0x21_5040);
+        let (_, pa) = map.translate(io + 4095 * PAGE_SIZE + 8).unwrap();
+        assert_eq!(pa, IO_BASE + 4095 * PAGE_SIZE + 8);
+        assert!(map.translate(io + 4096 * PAGE_SIZE).is_none());
+
+        assert!(map.map_device(IO_BASE, 1 << 18).is_none());
+    }
+
+    #[test]
+    fn split_blocks() {
+        let mut map = IdentityMap::new();
+        map.set_attributes(0x8_0000, 0x9_0000, Attributes::KERNEL_TEXT);
+
+        let (text, pa) = map.translate(KERNEL_BASE + 0x8_0010).unwrap();
+        assert_eq!(pa, 0x8_0010);
+        assert_eq!(text.attributes(), Attributes::KERNEL_TEXT);
+
+        // the rest of the split block keeps the block's attributes
+        for &addr in &[0x7_f000, 0x9_0000, 0x1f_f000] {
+            let (page, pa) = map.translate(addr).unwrap();
+            assert_eq!(pa, addr);
+            assert_eq!(page.attributes(), Attributes::KERNEL_RAM);
+        }
+
+        let (block, _) = map.translate(0x20_0000).unwrap();
+        assert_eq!(block.attributes(), Attributes::KERNEL_RAM);
+    }
+
+    #[test]
+    fn contiguous_hints() {
+        let mut map = IdentityMap::new();
+        let hinted = |map: &IdentityMap, va| map.translate(va).unwrap().0.is_contiguous();
+
+        // runs of 16 blocks of RAM, but not the run reaching the peripherals
+        assert!(hinted(&map, 0x20_0000));
+        assert!(hinted(&map, 0x3c00_0000));
+        assert!(!hinted(&map, 0x3e00_0000));
+        assert!(!hinted(&map, IO_BASE));
+
+        map.set_attributes(0x8_0000, 0x9_0000, Attributes::KERNEL_TEXT);
+        assert!(!hinted(&map, 0x20_0000));
+        assert!(hinted(&map, 0x200_0000));
+        for &addr in &[0x7_f000, 0x8_0000, 0x9_0000] {
+            assert!(hinted(&map, addr));
+        }
+
+        // splitting keeps the pages' attributes
+        map.split_block(0x200_0000);
+        let (page, _) = map.translate(0x201_0000).unwrap();
+        assert!(page.is_contiguous());
+        assert_eq!(page.attributes(), Attributes::KERNEL_RAM);
+        assert!(!hinted(&map, 0x220_0000));
+
+        map.unmap(0x3_f000, 0x4_0000);
+        assert!(!hinted(&map, 0x3_0000));
+        assert!(hinted(&map, 0x2_0000));
+    }
+
+    #[test]
+    fn unmap_pages() {
+        let mut map = IdentityMap::new();
+        map.unmap(0x3_f000, 0x4_0000);
+
+        assert!(map.translate(0x3_f800).is_none());
+        assert!(map.translate(KERNEL_BASE + 0x3_f000).is_none());
+        for &addr in &[0x3_e000, 0x4_0000] {
+            let (page, pa) = map.translate(KERNEL_BASE + addr).unwrap();
+            assert_eq!(pa, addr);
+            assert_eq!(page.attributes(), Attributes::KERNEL_RAM);
+        }
+    }
+
+    #[test]
+    fn boot_registers() {
+        // `init.s` hardcodes these
+        assert_eq!(KERNEL_BASE, 0xFFFF_FF80_0000_0000);
+        assert_eq!(MAIR, 0x44_04ff);
+        assert_eq!(TCR, 0xb519_3519);
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod ktests {
+    use pi::common::IO_BASE;
+
+    use super::*;
+
+    static DATA: u64 = 0;
+
+    #[test_case]
+    fn kernel_mappings() {
+        let va = &DATA as *const u64 as usize;
+        assert_eq!(VMM.translate(va), Some(virt_to_phys(va)));
+
+        let (text, _, _) = kernel_image();
+        assert!(aarch64::can_access(phys_to_virt(text), false));
+        assert!(!aarch64::can_access(phys_to_virt(text), true));
+        assert!(aarch64::can_access(va, false));
+    }
+
+    #[test_case]
+    fn device_mappings() {
+        let va = VMM.map_device(IO_BASE, PAGE_SIZE);
+        assert!(va >= DEVICE_BASE);
+        assert_eq!(VMM.translate(va), Some(IO_BASE));
+        VMM.unmap(va, PAGE_SIZE);
+        assert_eq!(VMM.translate(va), None);
+    }
+
+    #[test_case]
+    fn user_space_forks_copy_on_write() {
+        let mut parent = VMM.new_user_space();
+        parent.add_heap(USER_BASE).unwrap();
+        parent.set_brk(USER_BASE + 2 * PAGE_SIZE).unwrap();
+        let at = USER_BASE + PAGE_SIZE - 2;
+        parent.store(at, b"cow!").unwrap();
+
+        let mut child = parent.fork();
+        child.store(at, b"moo!").unwrap();
+        let mut buf = [0u8; 4];
+        parent.read(at, &mut buf).unwrap();
+        assert_eq!(&buf, b"cow!");
+        child.read(at, &mut buf).unwrap();
+        assert_eq!(&buf, b"moo!");
+        assert_eq!(
+            child.read(USER_BASE + 2 * PAGE_SIZE, &mut buf),
+            Err(FaultError::Unmapped)
+        );
+    }
+}
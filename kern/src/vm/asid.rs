@@ -0,0 +1,27 @@
+use crate::aarch64;
+use crate::mutex::Mutex;
+
+/// The number of address space identifiers: `TCR_EL1` selects 8-bit ones.
+const ASIDS: usize = 256;
+
+/// The ASIDs in use, a bit each. ASID 0 belongs to the kernel's identity map,
+/// and to user address spaces that could not get one of their own.
+static USED: Mutex<[u64; ASIDS / 64]> = Mutex::new([1, 0, 0, 0]);
+
+/// Allocates an unused ASID, invalidating anything the TLB still caches for
+/// the address space that last had it, or returns `None` if all are in use.
+pub fn alloc() -> Option<u16> {
+    let mut used = USED.lock();
+    let (i, word) = used.iter_mut().enumerate().find(|(_, word)| **word != !0)?;
+    let bit = (!*word).trailing_zeros() as usize;
+    *word |= 1 << bit;
+
+    let asid = (i * 64 + bit) as u16;
+    aarch64::tlb_invalidate_asid(asid);
+    Some(asid)
+}
+
+/// Frees `asid`, which `alloc()` returned, for reuse.
+pub fn free(asid: u16) {
+    USED.lock()[asid as usize / 64] &= !(1 << (asid % 64));
+}
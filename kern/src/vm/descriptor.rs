@@ -0,0 +1,322 @@
+use core::fmt;
+
+/// The descriptor is valid.
+const VALID: u64 = 1 << 0;
+/// At levels 1 and 2, the descriptor points to a table rather than mapping a
+/// block. At level 3, it must be set for the descriptor to map a page.
+const TABLE: u64 = 1 << 1;
+/// The position of the index into `MAIR_EL1` of the memory's type.
+const ATTR_INDEX_SHIFT: u64 = 2;
+/// The position of the access permission bits.
+const AP_SHIFT: u64 = 6;
+/// The position of the shareability bits.
+const SH_SHIFT: u64 = 8;
+/// The access flag. Accessing memory mapped with this clear faults.
+const AF: u64 = 1 << 10;
+/// Not global: the TLB caches the translation for the current ASID only.
+const NG: u64 = 1 << 11;
+/// One of a run of descriptors that map a contiguous range with the same
+/// attributes, which the TLB may cache as a single entry.
+const CONTIGUOUS: u64 = 1 << 52;
+/// Memory that EL1 may not execute.
+const PXN: u64 = 1 << 53;
+/// Memory that EL0 may not execute.
+const UXN: u64 = 1 << 54;
+/// The bits of a descriptor holding the output address.
+const ADDRESS_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+/// The number of descriptors in a run that may be marked contiguous. The run
+/// must start at an index that is a multiple of it, and map a range aligned
+/// to its size.
+pub const CONTIGUOUS_ENTRIES: usize = 16;
+
+/// Inner shareable: kept coherent between the cores.
+const INNER_SHAREABLE: u64 = 0b11;
+/// Outer shareable, as device memory always is.
+const OUTER_SHAREABLE: u64 = 0b10;
+
+/// The type of some memory, which decides how it may be cached and how
+/// accesses to it may be reordered. The value of each variant is its index in
+/// `MAIR_EL1`, which `vm::MAIR` sets up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Ordinary RAM, cached write-back.
+    Normal = 0,
+    /// Memory-mapped peripherals: never cached, never gathered or reordered.
+    Device = 1,
+    /// RAM shared with devices that do not snoop the caches.
+    NonCacheable = 2,
+}
+
+/// Who may read and write some memory. EL1 can always read memory EL0 can.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    KernelReadWrite = 0b00,
+    UserReadWrite = 0b01,
+    KernelReadOnly = 0b10,
+    UserReadOnly = 0b11,
+}
+
+impl Access {
+    /// Returns `true` if EL0 may access memory with these permissions.
+    pub fn is_user(self) -> bool {
+        self == Access::UserReadWrite || self == Access::UserReadOnly
+    }
+}
+
+/// The attributes of a mapping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Attributes {
+    pub kind: MemoryKind,
+    pub access: Access,
+    /// Whether code may run from the memory: the kernel's for kernel
+    /// mappings, user code's for user mappings.
+    pub executable: bool,
+}
+
+impl Attributes {
+    /// RAM used by the kernel for data. Writable, so never executable.
+    pub const KERNEL_RAM: Attributes = Attributes {
+        kind: MemoryKind::Normal,
+        access: Access::KernelReadWrite,
+        executable: false,
+    };
+
+    /// The kernel's code.
+    pub const KERNEL_TEXT: Attributes = Attributes {
+        kind: MemoryKind::Normal,
+        access: Access::KernelReadOnly,
+        executable: true,
+    };
+
+    /// The kernel's constants.
+    pub const KERNEL_RODATA: Attributes = Attributes {
+        kind: MemoryKind::Normal,
+        access: Access::KernelReadOnly,
+        executable: false,
+    };
+
+    /// A user program's data and stack.
+    pub const USER_DATA: Attributes = Attributes {
+        kind: MemoryKind::Normal,
+        access: Access::UserReadWrite,
+        executable: false,
+    };
+
+    /// A user program's code.
+    pub const USER_CODE: Attributes = Attributes {
+        kind: MemoryKind::Normal,
+        access: Access::UserReadOnly,
+        executable: true,
+    };
+
+    /// A user program's constants.
+    pub const USER_RODATA: Attributes = Attributes {
+        kind: MemoryKind::Normal,
+        access: Access::UserReadOnly,
+        executable: false,
+    };
+
+    /// Peripherals' registers.
+    pub const DEVICE: Attributes = Attributes {
+        kind: MemoryKind::Device,
+        access: Access::KernelReadWrite,
+        executable: false,
+    };
+
+    /// Returns the bits of a block or page descriptor with these attributes.
+    fn bits(&self) -> u64 {
+        let shareability = match self.kind {
+            MemoryKind::Device => OUTER_SHAREABLE,
+            _ => INNER_SHAREABLE,
+        };
+        let execute_never = match (self.executable, self.access.is_user()) {
+            (true, false) => UXN,
+            (true, true) => PXN,
+            (false, _) => PXN | UXN,
+        };
+
+        // User mappings differ between address spaces, the kernel's do not.
+        let global = if self.access.is_user() { NG } else { 0 };
+
+        (self.kind as u64) << ATTR_INDEX_SHIFT
+            | (self.access as u64) << AP_SHIFT
+            | shareability << SH_SHIFT
+            | AF
+            | global
+            | execute_never
+    }
+
+    /// Decodes the attributes of the block or page descriptor `raw`.
+    fn from_bits(raw: u64) -> Attributes {
+        let kind = match (raw >> ATTR_INDEX_SHIFT) & 0b111 {
+            0 => MemoryKind::Normal,
+            2 => MemoryKind::NonCacheable,
+            _ => MemoryKind::Device,
+        };
+        let access = match (raw >> AP_SHIFT) & 0b11 {
+            0b00 => Access::KernelReadWrite,
+            0b01 => Access::UserReadWrite,
+            0b10 => Access::KernelReadOnly,
+            _ => Access::UserReadOnly,
+        };
+        let execute_never = if access.is_user() { UXN } else { PXN };
+
+        Attributes {
+            kind,
+            access,
+            executable: raw & execute_never == 0,
+        }
+    }
+}
+
+impl fmt::Display for Attributes {
+    /// Shows the memory type, whose mapping it is, and what it allows, as in
+    /// `normal   kernel r-x`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            MemoryKind::Normal => "normal",
+            MemoryKind::Device => "device",
+            MemoryKind::NonCacheable => "uncached",
+        };
+        let (owner, write) = match self.access {
+            Access::KernelReadWrite => ("kernel", 'w'),
+            Access::UserReadWrite => ("user", 'w'),
+            Access::KernelReadOnly => ("kernel", '-'),
+            Access::UserReadOnly => ("user", '-'),
+        };
+        let execute = if self.executable { 'x' } else { '-' };
+        write!(f, "{:<8} {:<6} r{}{}", kind, owner, write, execute)
+    }
+}
+
+/// An entry in a translation table: invalid, a pointer to the next level's
+/// table, or a mapping of a block or page.
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct Descriptor(u64);
+
+impl Descriptor {
+    /// Returns a descriptor that maps nothing. Accesses through it fault.
+    pub const fn invalid() -> Descriptor {
+        Descriptor(0)
+    }
+
+    /// Returns a level 1 or 2 descriptor pointing to the table at the
+    /// physical address `addr`, which must be page aligned.
+    pub fn table(addr: usize) -> Descriptor {
+        Descriptor(addr as u64 & ADDRESS_MASK | TABLE | VALID)
+    }
+
+    /// Returns a level 1 or 2 descriptor mapping the block at the physical
+    /// address `addr`, which must be aligned to the block size of the level.
+    pub fn block(addr: usize, attrs: Attributes) -> Descriptor {
+        Descriptor(addr as u64 & ADDRESS_MASK | attrs.bits() | VALID)
+    }
+
+    /// Returns a level 3 descriptor mapping the page at the physical address
+    /// `addr`, which must be page aligned.
+    pub fn page(addr: usize, attrs: Attributes) -> Descriptor {
+        Descriptor(addr as u64 & ADDRESS_MASK | attrs.bits() | TABLE | VALID)
+    }
+
+    /// Returns `true` if this descriptor is valid.
+    pub fn is_valid(&self) -> bool {
+        self.0 & VALID != 0
+    }
+
+    /// Returns `true` if this valid level 1 or 2 descriptor points to a
+    /// table. At level 3, this is `true` of every valid descriptor.
+    pub fn is_table(&self) -> bool {
+        self.is_valid() && self.0 & TABLE != 0
+    }
+
+    /// Returns `true` if this block or page descriptor is marked as one of a
+    /// contiguous run.
+    pub fn is_contiguous(&self) -> bool {
+        self.0 & CONTIGUOUS != 0
+    }
+
+    /// Returns this block or page descriptor with the contiguous hint set or
+    /// cleared. See `CONTIGUOUS_ENTRIES`.
+    pub fn with_contiguous(self, contiguous: bool) -> Descriptor {
+        if contiguous {
+            Descriptor(self.0 | CONTIGUOUS)
+        } else {
+            Descriptor(self.0 & !CONTIGUOUS)
+        }
+    }
+
+    /// Returns the physical address this descriptor points to or maps.
+    pub fn address(&self) -> usize {
+        (self.0 & ADDRESS_MASK) as usize
+    }
+
+    /// Returns the attributes of this block or page descriptor.
+    pub fn attributes(&self) -> Attributes {
+        Attributes::from_bits(self.0)
+    }
+
+    /// Returns the raw value of this descriptor.
+    pub fn raw(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Descriptor({:#018x})", self.0)
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{Access, Attributes, Descriptor, MemoryKind};
+
+    #[test]
+    fn encoding() {
+        assert!(!Descriptor::invalid().is_valid());
+
+        let table = Descriptor::table(0x8_1000);
+        assert!(table.is_table());
+        assert_eq!(table.raw(), 0x8_1003);
+        assert_eq!(table.address(), 0x8_1000);
+
+        let block = Descriptor::block(0x20_0000, Attributes::KERNEL_RAM);
+        assert!(block.is_valid() && !block.is_table());
+        assert_eq!(block.raw(), 0x0060_0000_0020_0701);
+        assert_eq!(block.attributes(), Attributes::KERNEL_RAM);
+
+        let text = Descriptor::page(0x8_0000, Attributes::KERNEL_TEXT);
+        assert_eq!(text.raw(), 0x0040_0000_0008_0783);
+        assert_eq!(text.attributes(), Attributes::KERNEL_TEXT);
+
+        let rodata = Descriptor::page(0x9_0000, Attributes::KERNEL_RODATA);
+        assert_eq!(rodata.raw(), 0x0060_0000_0009_0783);
+        assert_eq!(rodata.attributes(), Attributes::KERNEL_RODATA);
+
+        let device = Descriptor::block(0x3F00_0000, Attributes::DEVICE);
+        assert_eq!(device.raw(), 0x0060_0000_3F00_0605);
+        assert_eq!(device.attributes(), Attributes::DEVICE);
+
+        let user = Attributes {
+            kind: MemoryKind::Normal,
+            access: Access::UserReadOnly,
+            executable: true,
+        };
+        let page = Descriptor::page(0x1234_5000, user);
+        assert_eq!(page.raw(), 0x0020_0000_1234_5FC3);
+        assert_eq!(page.attributes(), user);
+
+        let hinted = page.with_contiguous(true);
+        assert!(hinted.is_contiguous() && !page.is_contiguous());
+        assert_eq!(hinted.raw(), 0x0030_0000_1234_5FC3);
+        assert_eq!(hinted.attributes(), user);
+        assert_eq!(hinted.address(), 0x1234_5000);
+        assert_eq!(hinted.with_contiguous(false), page);
+
+        assert_eq!(Attributes::KERNEL_TEXT.to_string(), "normal   kernel r-x");
+        assert_eq!(Attributes::DEVICE.to_string(), "device   kernel rw-");
+        assert_eq!(user.to_string(), "normal   user   r-x");
+    }
+}
@@ -0,0 +1,76 @@
+use alloc::alloc::{alloc_zeroed, dealloc};
+use alloc::collections::BTreeMap;
+use core::alloc::Layout;
+use core::ptr;
+
+use super::{phys_to_virt, virt_to_phys, PAGE_SIZE};
+use crate::mutex::Mutex;
+
+/// The number of owners of each frame that more than one address space maps,
+/// by physical address. A frame missing from the map has a single owner.
+static OWNERS: Mutex<Option<BTreeMap<usize, usize>>> = Mutex::new(None);
+
+/// Returns the layout of a page frame: a page, page aligned.
+fn layout() -> Layout {
+    unsafe { Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE) }
+}
+
+/// Allocates a zeroed page frame from the kernel's heap, returning its
+/// physical address, or `None` if memory is exhausted.
+pub fn alloc() -> Option<usize> {
+    let ptr = unsafe { alloc_zeroed(layout()) };
+    if ptr.is_null() {
+        None
+    } else {
+        Some(virt_to_phys(ptr as usize))
+    }
+}
+
+/// Allocates a page frame holding a copy of the frame at the physical address
+/// `pa`, returning its physical address, or `None` if memory is exhausted.
+pub fn copy(pa: usize) -> Option<usize> {
+    let ptr = unsafe { alloc::alloc::alloc(layout()) };
+    if ptr.is_null() {
+        return None;
+    }
+
+    unsafe { ptr::copy_nonoverlapping(phys_to_virt(pa) as *const u8, ptr, PAGE_SIZE) };
+    Some(virt_to_phys(ptr as usize))
+}
+
+/// Records one more owner of the frame at the physical address `pa`.
+pub fn share(pa: usize) {
+    let mut owners = OWNERS.lock();
+    *owners
+        .get_or_insert_with(BTreeMap::new)
+        .entry(pa)
+        .or_insert(1) += 1;
+}
+
+/// Returns the number of owners of the frame at the physical address `pa`.
+pub fn owners(pa: usize) -> usize {
+    match *OWNERS.lock() {
+        Some(ref owners) => owners.get(&pa).cloned().unwrap_or(1),
+        None => 1,
+    }
+}
+
+/// Gives up one owner's claim on the frame at the physical address `pa`,
+/// returning it to the heap if that was the last owner.
+///
+/// # Safety
+///
+/// The frame must have come from `alloc()` or `copy()`, and the owner must no
+/// longer map it.
+pub unsafe fn release(pa: usize) {
+    let mut owners = OWNERS.lock();
+    if let Some(count) = owners.as_mut().and_then(|owners| owners.get_mut(&pa)) {
+        *count -= 1;
+        if *count == 1 {
+            owners.as_mut().unwrap().remove(&pa);
+        }
+        return;
+    }
+
+    dealloc(phys_to_virt(pa) as *mut u8, layout())
+}
@@ -0,0 +1,282 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use pi::common::IO_BASE;
+
+use super::descriptor::{Attributes, Descriptor, CONTIGUOUS_ENTRIES};
+use super::walk::{self, Mapping};
+use super::{
+    phys_to_virt, virt_to_phys, DEVICE_BASE, KERNEL_BASE, L1_BLOCK_SIZE, L2_BLOCK_SIZE, PAGE_SIZE,
+    USER_BASE, VA_BITS,
+};
+
+/// The number of descriptors in a translation table.
+pub const ENTRIES: usize = PAGE_SIZE / 8;
+
+/// The index of the level 1 descriptor for the device window.
+const DEVICE_WINDOW: usize = (DEVICE_BASE - KERNEL_BASE) / L1_BLOCK_SIZE;
+
+/// A translation table: a page of descriptors.
+#[repr(C, align(4096))]
+pub struct Table {
+    pub entries: [Descriptor; ENTRIES],
+}
+
+impl Table {
+    /// Returns a table that maps nothing.
+    pub const fn empty() -> Table {
+        Table {
+            entries: [Descriptor::invalid(); ENTRIES],
+        }
+    }
+
+    /// Returns the physical address of this table.
+    pub fn address(&self) -> usize {
+        virt_to_phys(self as *const Table as usize)
+    }
+}
+
+/// Translation tables mapping the first 2 GiB of the physical address space
+/// at the same virtual addresses. Installed for the upper half of the address
+/// space too, they map the same memory at `KERNEL_BASE`.
+///
+/// Everything below the peripherals, including the memory the GPU keeps for
+/// itself, is mapped as normal memory in 2 MiB blocks. The peripherals, and
+/// the core-local peripherals in the second gigabyte, are mapped as device
+/// memory.
+///
+/// `split_block()` splits a block into pages where parts of it need different
+/// attributes, as `set_attributes()` does around the kernel's code. Runs of
+/// blocks or pages that map a contiguous, aligned range with the same
+/// attributes are marked with the contiguous hint, so the TLB can cache each
+/// run as one entry; changing a mapping re-marks the runs around it.
+///
+/// The third gigabyte, at `DEVICE_BASE` in the upper half, is a window that
+/// `map_device()` maps peripherals into page by page. Since both halves share
+/// the level 1 table, the window also appears in the lower half.
+#[repr(C)]
+pub struct IdentityMap {
+    l1: Table,
+    l2: Table,
+    /// The level 2 table of the device window.
+    devices: Table,
+    /// The level 3 tables of the device window, in address order.
+    device_pages: Vec<Box<Table>>,
+    /// The number of bytes of the device window in use.
+    device_len: usize,
+    /// The level 3 tables of blocks split into pages.
+    split: Vec<Box<Table>>,
+}
+
+impl IdentityMap {
+    /// Builds the identity map.
+    pub fn new() -> Box<IdentityMap> {
+        let mut map = Box::new(IdentityMap {
+            l1: Table::empty(),
+            l2: Table::empty(),
+            devices: Table::empty(),
+            device_pages: Vec::new(),
+            device_len: 0,
+            split: Vec::new(),
+        });
+
+        for (i, entry) in map.l2.entries.iter_mut().enumerate() {
+            let addr = i * L2_BLOCK_SIZE;
+            let attrs = if addr < IO_BASE {
+                Attributes::KERNEL_RAM
+            } else {
+                Attributes::DEVICE
+            };
+            *entry = Descriptor::block(addr, attrs);
+        }
+
+        hint_contiguous(&mut map.l2.entries, L2_BLOCK_SIZE);
+        map.l1.entries[0] = Descriptor::table(map.l2.address());
+        map.l1.entries[1] = Descriptor::block(L1_BLOCK_SIZE, Attributes::DEVICE);
+        map.l1.entries[DEVICE_WINDOW] = Descriptor::table(map.devices.address());
+        map
+    }
+
+    /// Changes the attributes of the identity mapped pages from the physical
+    /// address `start` up to `end`, splitting the blocks that map them into
+    /// pages as needed. Returns `true` if a block was split, which changes
+    /// the contiguous hints of the blocks around it, so that the TLB must be
+    /// invalidated entirely rather than for the pages and the runs they are
+    /// in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` or `end` is not page aligned, or if `end` is beyond
+    /// the first gigabyte.
+    pub fn set_attributes(&mut self, start: usize, end: usize, attrs: Attributes) -> bool {
+        self.replace_pages(start, end, |pa| Descriptor::page(pa, attrs))
+    }
+
+    /// Unmaps the pages from the physical address `start` up to `end`, so
+    /// that accessing them faults. See `set_attributes()`.
+    pub fn unmap(&mut self, start: usize, end: usize) -> bool {
+        self.replace_pages(start, end, |_| Descriptor::invalid())
+    }
+
+    /// Splits the 2 MiB block mapping the physical address `pa` into pages
+    /// with the block's attributes, so that they can be changed one by one.
+    /// Returns the level 3 table now mapping the block, which may already have
+    /// been split.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pa` is beyond the first gigabyte.
+    pub fn split_block(&mut self, pa: usize) -> &mut Table {
+        assert!(pa < L1_BLOCK_SIZE, "split_block: bad address {:#x}", pa);
+
+        let l2 = pa / L2_BLOCK_SIZE;
+        let block = self.l2.entries[l2];
+        if !block.is_table() {
+            let mut table = Box::new(Table::empty());
+            for (i, entry) in table.entries.iter_mut().enumerate() {
+                let addr = block.address() + i * PAGE_SIZE;
+                *entry = Descriptor::page(addr, block.attributes());
+            }
+            hint_contiguous(&mut table.entries, PAGE_SIZE);
+            self.l2.entries[l2] = Descriptor::table(table.address());
+            self.split.push(table);
+            hint_contiguous(&mut self.l2.entries, L2_BLOCK_SIZE);
+        }
+
+        unsafe { &mut *(phys_to_virt(self.l2.entries[l2].address()) as *mut Table) }
+    }
+
+    /// Replaces the level 3 descriptor of each page from the physical address
+    /// `start` up to `end` with `page(pa)`, where `pa` is the page's address,
+    /// splitting the blocks that map them into pages as needed. Returns `true`
+    /// if a block was split.
+    fn replace_pages<F>(&mut self, start: usize, end: usize, page: F) -> bool
+    where
+        F: Fn(usize) -> Descriptor,
+    {
+        assert!(
+            start % PAGE_SIZE == 0 && end % PAGE_SIZE == 0 && end <= L1_BLOCK_SIZE,
+            "replace_pages: bad range {:#x}-{:#x}",
+            start,
+            end
+        );
+
+        let mut split = false;
+        let mut pa = start;
+        while pa < end {
+            let block_end = core::cmp::min(end, (pa / L2_BLOCK_SIZE + 1) * L2_BLOCK_SIZE);
+            split |= !self.l2.entries[pa / L2_BLOCK_SIZE].is_table();
+            let table = self.split_block(pa);
+            for pa in (pa..block_end).step_by(PAGE_SIZE) {
+                table.entries[(pa / PAGE_SIZE) % ENTRIES] = page(pa);
+            }
+            hint_contiguous(&mut table.entries, PAGE_SIZE);
+            pa = block_end;
+        }
+        split
+    }
+
+    /// Maps the `pages` pages starting at the physical address `pa`, which
+    /// must be page aligned, as device memory at the next free addresses of
+    /// the device window. Returns the virtual address of the first page, or
+    /// `None` if the window is too full to fit them.
+    pub fn map_device(&mut self, pa: usize, pages: usize) -> Option<usize> {
+        let start = self.device_len;
+        let end = pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|len| start.checked_add(len))
+            .filter(|&end| end <= L1_BLOCK_SIZE)?;
+
+        for (i, offset) in (start..end).step_by(PAGE_SIZE).enumerate() {
+            let l2 = offset / L2_BLOCK_SIZE;
+            if l2 == self.device_pages.len() {
+                let table = Box::new(Table::empty());
+                self.devices.entries[l2] = Descriptor::table(table.address());
+                self.device_pages.push(table);
+            }
+
+            let page = Descriptor::page(pa + i * PAGE_SIZE, Attributes::DEVICE);
+            self.device_pages[l2].entries[(offset / PAGE_SIZE) % ENTRIES] = page;
+        }
+
+        self.device_len = end;
+        Some(DEVICE_BASE + start)
+    }
+
+    /// Returns the physical address of the level 1 table, to be installed in
+    /// a translation table base register.
+    pub fn base(&self) -> usize {
+        self.l1.address()
+    }
+
+    /// Returns the level 1 descriptors mapping the lower half below
+    /// `USER_BASE`, which user address spaces share.
+    pub fn lower_entries(&self) -> &[Descriptor] {
+        &self.l1.entries[..USER_BASE / L1_BLOCK_SIZE]
+    }
+
+    /// Calls `f` with each mapping of the upper half of the address space, in
+    /// address order. See `walk::walk()`.
+    pub fn walk<F: FnMut(Mapping)>(&self, f: F) {
+        walk::walk(&self.l1, KERNEL_BASE, f);
+    }
+
+    /// Walks the tables to find the descriptor that maps the virtual address
+    /// `va`, in either half of the address space, returning it with the
+    /// physical address `va` translates to.
+    pub fn translate(&self, va: usize) -> Option<(Descriptor, usize)> {
+        let va = if va >= KERNEL_BASE {
+            va - KERNEL_BASE
+        } else if va < 1 << VA_BITS {
+            va
+        } else {
+            return None;
+        };
+
+        let l1 = self.l1.entries[va / L1_BLOCK_SIZE];
+        if !l1.is_valid() {
+            return None;
+        } else if !l1.is_table() {
+            return Some((l1, l1.address() + va % L1_BLOCK_SIZE));
+        }
+
+        let l2_table = unsafe { &*(phys_to_virt(l1.address()) as *const Table) };
+        let l2 = l2_table.entries[(va / L2_BLOCK_SIZE) % ENTRIES];
+        if !l2.is_valid() {
+            return None;
+        } else if !l2.is_table() {
+            return Some((l2, l2.address() + va % L2_BLOCK_SIZE));
+        }
+
+        let l3_table = unsafe { &*(phys_to_virt(l2.address()) as *const Table) };
+        let l3 = l3_table.entries[(va / PAGE_SIZE) % ENTRIES];
+        if !l3.is_valid() {
+            return None;
+        }
+        Some((l3, l3.address() + va % PAGE_SIZE))
+    }
+}
+
+/// Sets the contiguous hint on each run of `CONTIGUOUS_ENTRIES` descriptors in
+/// `entries`, a whole table of blocks or pages of `size` bytes, that map one
+/// aligned range with the same attributes, and clears it on every other
+/// block or page.
+fn hint_contiguous(entries: &mut [Descriptor], size: usize) {
+    let leaf = |entry: Descriptor| entry.is_valid() && (size == PAGE_SIZE || !entry.is_table());
+
+    for run in entries.chunks_mut(CONTIGUOUS_ENTRIES) {
+        let first = run[0].with_contiguous(false);
+        let contiguous = leaf(first)
+            && first.address() % (CONTIGUOUS_ENTRIES * size) == 0
+            && run.iter().enumerate().all(|(i, entry)| {
+                let entry = entry.with_contiguous(false);
+                leaf(entry)
+                    && entry.address() == first.address() + i * size
+                    && entry.raw() - entry.address() as u64 == first.raw() - first.address() as u64
+            });
+
+        for entry in run.iter_mut().filter(|entry| leaf(**entry)) {
+            *entry = entry.with_contiguous(contiguous);
+        }
+    }
+}
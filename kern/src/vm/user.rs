@@ -0,0 +1,916 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ptr;
+
+use crate::aarch64;
+use crate::mutex::Mutex;
+
+use super::descriptor::{Access, Attributes, Descriptor};
+use super::table::{Table, ENTRIES};
+use super::walk::{self, Mapping};
+use super::{asid, frame, page_align};
+use super::{phys_to_virt, L1_BLOCK_SIZE, L2_BLOCK_SIZE, PAGE_SIZE, USER_BASE, USER_END};
+
+/// The most memory a new user address space may map: see
+/// `UserSpace::set_memory_limit()`.
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 << 20;
+
+/// The kind of access that faulted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Why a fault in a user address space could not be resolved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FaultError {
+    /// The address is not in any region.
+    Unmapped,
+    /// The region the address is in does not allow the access.
+    Protection,
+    /// The address is in the guard page below a stack: the stack overflowed.
+    StackOverflow,
+    /// There was no memory left for a page frame.
+    OutOfMemory,
+    /// Mapping another page would take the address space over its memory
+    /// limit.
+    MemoryLimit,
+}
+
+impl fmt::Display for FaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FaultError::Unmapped => write!(f, "address not mapped"),
+            FaultError::Protection => write!(f, "access not permitted"),
+            FaultError::StackOverflow => write!(f, "stack overflow"),
+            FaultError::OutOfMemory => write!(f, "out of memory"),
+            FaultError::MemoryLimit => write!(f, "memory limit exceeded"),
+        }
+    }
+}
+
+/// Why a region could not be added to a user address space.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegionError {
+    /// The start or length is not page aligned, or the length is zero.
+    Unaligned,
+    /// The region is not between `USER_BASE` and `USER_END`.
+    OutOfRange,
+    /// The region overlaps one already added, or a stack's guard page.
+    Overlaps,
+}
+
+/// A region of a user address space that may be accessed: a virtual memory
+/// area. Pages in it are only mapped when first accessed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// The first address of the region, page aligned.
+    pub start: usize,
+    /// The address just past the region, page aligned.
+    pub end: usize,
+    /// The attributes its pages are mapped with.
+    pub attrs: Attributes,
+}
+
+impl Region {
+    /// Returns `true` if `va` lies within this region.
+    pub fn contains(&self, va: usize) -> bool {
+        self.start <= va && va < self.end
+    }
+
+    /// Returns `true` if this region's attributes permit `access` from EL0.
+    fn allows(&self, access: AccessKind) -> bool {
+        match access {
+            AccessKind::Read => true,
+            AccessKind::Write => self.attrs.access == Access::UserReadWrite,
+            AccessKind::Execute => self.attrs.executable,
+        }
+    }
+}
+
+/// A user address space, shared by the threads of a process.
+pub type SharedSpace = Arc<Mutex<UserSpace>>;
+
+/// The lower half of the address space as a user program sees it: the regions
+/// it may use, and the translation tables mapping the pages of them it has
+/// touched.
+///
+/// Below `USER_BASE`, the tables share the kernel's level 1 descriptors, so
+/// the kernel's identity map survives installing them. EL0 cannot access
+/// those mappings.
+///
+/// Address spaces made by `fork()` share page frames copy-on-write, so a frame
+/// may be mapped by several of them; `frame` counts its owners.
+///
+/// Each address space has its own ASID while there are enough to go around,
+/// so the TLB can keep its translations across switches between them.
+///
+/// A program's heap is a region apart from the others, which ends at its
+/// program break and grows or shrinks as the break moves: see `set_brk()`.
+///
+/// The pages an address space maps are limited, so that a runaway program
+/// cannot take all of memory: see `set_memory_limit()`.
+pub struct UserSpace {
+    l1: Box<Table>,
+    /// The level 2 tables.
+    l2_tables: Vec<Box<Table>>,
+    /// The level 3 tables. Their valid descriptors map frames this address
+    /// space owns, or shares with others.
+    l3_tables: Vec<Box<Table>>,
+    regions: Vec<Region>,
+    /// The addresses of the pages below stacks that are never mapped.
+    guards: Vec<usize>,
+    /// The heap, ending at the page holding the last byte below `brk`.
+    heap: Option<Region>,
+    /// The program break: the address just past the heap.
+    brk: usize,
+    /// The number of user pages mapped.
+    pages: usize,
+    /// The most user pages that may be mapped.
+    max_pages: usize,
+    /// The address space identifier the TLB tags its translations with, or
+    /// `None` if every ASID was in use and it shares ASID 0.
+    asid: Option<u16>,
+}
+
+impl UserSpace {
+    /// Returns an address space with no regions, sharing the level 1
+    /// descriptors `kernel` of the kernel's part of the lower half.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `kernel` reaches `USER_BASE`.
+    pub fn new(kernel: &[Descriptor]) -> UserSpace {
+        assert!(kernel.len() <= USER_BASE / L1_BLOCK_SIZE);
+
+        let mut l1 = Box::new(Table::empty());
+        l1.entries[..kernel.len()].copy_from_slice(kernel);
+        UserSpace {
+            l1,
+            l2_tables: Vec::new(),
+            l3_tables: Vec::new(),
+            regions: Vec::new(),
+            guards: Vec::new(),
+            heap: None,
+            brk: 0,
+            pages: 0,
+            max_pages: DEFAULT_MEMORY_LIMIT / PAGE_SIZE,
+            asid: asid::alloc(),
+        }
+    }
+
+    /// Returns this address space, to be shared by threads.
+    pub fn shared(self) -> SharedSpace {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Returns the physical address of the level 1 table, to be installed in
+    /// `TTBR0_EL1`.
+    pub fn base(&self) -> usize {
+        self.l1.address()
+    }
+
+    /// Returns the ASID of this address space, to be installed in
+    /// `TTBR0_EL1` with its tables, or `None` if it has to share ASID 0.
+    pub fn asid(&self) -> Option<u16> {
+        self.asid
+    }
+
+    /// Returns this address space's regions, in the order they were added,
+    /// leaving out the heap.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the region containing `va`, if any, which may be the heap.
+    pub fn region(&self, va: usize) -> Option<&Region> {
+        self.regions
+            .iter()
+            .chain(self.heap.iter())
+            .find(|region| region.contains(va))
+    }
+
+    /// Returns the heap region, if this address space has one. It is empty
+    /// until the program break is first moved up.
+    pub fn heap(&self) -> Option<&Region> {
+        self.heap.as_ref()
+    }
+
+    /// Returns the program break, if this address space has a heap.
+    pub fn brk(&self) -> Option<usize> {
+        self.heap.map(|_| self.brk)
+    }
+
+    /// Returns the number of bytes of user memory mapped: whole pages, some
+    /// of which may be shared with other address spaces.
+    pub fn resident(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+
+    /// Returns the most bytes of user memory this address space may map.
+    pub fn memory_limit(&self) -> usize {
+        self.max_pages * PAGE_SIZE
+    }
+
+    /// Limits the user memory this address space may map to `limit` bytes,
+    /// rounded down to whole pages. Mapping a page past the limit fails with
+    /// `FaultError::MemoryLimit`; the pages already mapped stay mapped.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.max_pages = limit / PAGE_SIZE;
+    }
+
+    /// Adds a region of `len` bytes starting at `start` whose pages are mapped
+    /// with `attrs`, which must allow EL0 access, when first accessed.
+    pub fn add_region(
+        &mut self,
+        start: usize,
+        len: usize,
+        attrs: Attributes,
+    ) -> Result<(), RegionError> {
+        assert!(
+            attrs.access.is_user(),
+            "add_region: {:?} is not user memory",
+            attrs
+        );
+
+        if start % PAGE_SIZE != 0 || len % PAGE_SIZE != 0 || len == 0 {
+            return Err(RegionError::Unaligned);
+        }
+
+        let end = match start.checked_add(len) {
+            Some(end) if start >= USER_BASE && end <= USER_END => end,
+            _ => return Err(RegionError::OutOfRange),
+        };
+
+        if self.overlaps(start, end) {
+            return Err(RegionError::Overlaps);
+        }
+
+        self.regions.push(Region { start, end, attrs });
+        Ok(())
+    }
+
+    /// Adds a stack region of `len` bytes ending at `top`, with an unmapped
+    /// guard page below it that no region may use, so that overflowing the
+    /// stack faults with `FaultError::StackOverflow`.
+    pub fn add_stack(&mut self, top: usize, len: usize) -> Result<(), RegionError> {
+        let start = top.wrapping_sub(len);
+        let guard = start.wrapping_sub(PAGE_SIZE);
+        if top % PAGE_SIZE != 0 || len % PAGE_SIZE != 0 || len == 0 {
+            return Err(RegionError::Unaligned);
+        } else if start > top || guard > start || guard < USER_BASE {
+            return Err(RegionError::OutOfRange);
+        } else if self.overlaps(guard, start) {
+            return Err(RegionError::Overlaps);
+        }
+
+        self.add_region(start, len, Attributes::USER_DATA)?;
+        self.guards.push(guard);
+        Ok(())
+    }
+
+    /// Adds an empty heap at `start`, which the program break starts at.
+    pub fn add_heap(&mut self, start: usize) -> Result<(), RegionError> {
+        if start % PAGE_SIZE != 0 {
+            return Err(RegionError::Unaligned);
+        } else if start < USER_BASE || start >= USER_END || self.heap.is_some() {
+            return Err(RegionError::OutOfRange);
+        } else if self.overlaps(start, start + 1) {
+            return Err(RegionError::Overlaps);
+        }
+
+        self.heap = Some(Region {
+            start,
+            end: start,
+            attrs: Attributes::USER_DATA,
+        });
+        self.brk = start;
+        Ok(())
+    }
+
+    /// Moves the program break to `brk`, which may be anywhere from the start
+    /// of the heap up to the next region or guard page. Growing the heap maps
+    /// nothing until its new pages are touched; shrinking it unmaps the pages
+    /// past the new break.
+    ///
+    /// If this address space is installed and the heap shrank, the TLB
+    /// entries for the pages it lost must be invalidated afterwards.
+    pub fn set_brk(&mut self, brk: usize) -> Result<(), RegionError> {
+        let heap = self.heap.ok_or(RegionError::OutOfRange)?;
+        let end = match page_align(brk) {
+            Some(end) if brk >= heap.start && end <= USER_END => end,
+            _ => return Err(RegionError::OutOfRange),
+        };
+
+        if end > heap.end && self.overlaps(heap.end, end) {
+            return Err(RegionError::Overlaps);
+        }
+        for va in (end..heap.end).step_by(PAGE_SIZE) {
+            if self.translate(va).is_some() {
+                let entry = self.entry_mut(va);
+                unsafe { frame::release(entry.address()) };
+                *entry = Descriptor::invalid();
+                self.pages -= 1;
+            }
+        }
+
+        self.heap = Some(Region { end, ..heap });
+        self.brk = brk;
+        Ok(())
+    }
+
+    /// Returns `true` if a region, the heap or a guard page overlaps `start`
+    /// up to `end`.
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.regions
+            .iter()
+            .chain(self.heap.iter())
+            .any(|r| start < r.end && r.start < end)
+            || self
+                .guards
+                .iter()
+                .any(|&guard| start < guard + PAGE_SIZE && guard < end)
+    }
+
+    /// Returns a copy of this address space that shares its mapped pages
+    /// copy-on-write: both map every page read-only, and the first write to a
+    /// page of a writable region gives the writer a copy of its own.
+    ///
+    /// If this address space is installed, its TLB entries must be
+    /// invalidated afterwards: see `asid()`.
+    pub fn fork(&mut self) -> UserSpace {
+        let mut child = UserSpace::new(&self.l1.entries[..USER_BASE / L1_BLOCK_SIZE]);
+        child.regions = self.regions.clone();
+        child.guards = self.guards.clone();
+        child.heap = self.heap;
+        child.brk = self.brk;
+        child.pages = self.pages;
+        child.max_pages = self.max_pages;
+
+        self.for_each_page(|va, entry| {
+            let attrs = entry.attributes();
+            let shared = Attributes {
+                access: if attrs.access == Access::UserReadWrite {
+                    Access::UserReadOnly
+                } else {
+                    attrs.access
+                },
+                ..attrs
+            };
+
+            *entry = Descriptor::page(entry.address(), shared);
+            *child.entry_mut(va) = *entry;
+            frame::share(entry.address());
+        });
+        child
+    }
+
+    /// Resolves a fault on an `access` to `va`, if `va` is in a region that
+    /// allows the access. A translation fault maps a zeroed page; a write to a
+    /// page shared by `fork()` maps a copy of it, or the page itself once no
+    /// other address space shares it.
+    ///
+    /// If this address space is installed, the TLB entry for `va` must be
+    /// invalidated afterwards.
+    pub fn handle_fault(&mut self, va: usize, access: AccessKind) -> Result<(), FaultError> {
+        if self
+            .guards
+            .iter()
+            .any(|&guard| guard <= va && va < guard + PAGE_SIZE)
+        {
+            return Err(FaultError::StackOverflow);
+        }
+
+        let region = *self.region(va).ok_or(FaultError::Unmapped)?;
+        if !region.allows(access) {
+            return Err(FaultError::Protection);
+        }
+
+        if self.translate(va).is_none() {
+            self.map_new_page(va)?;
+        }
+        let entry = self.entry_mut(va);
+        if access == AccessKind::Write && entry.attributes().access != region.attrs.access {
+            let shared = entry.address();
+            let frame = if frame::owners(shared) == 1 {
+                shared
+            } else {
+                let copy = frame::copy(shared).ok_or(FaultError::OutOfMemory)?;
+                unsafe { frame::release(shared) };
+                copy
+            };
+            *entry = Descriptor::page(frame, region.attrs);
+        }
+        Ok(())
+    }
+
+    /// Copies `bytes` to the user address `va`, mapping zeroed pages where
+    /// nothing is mapped yet, whatever access the regions allow: this is how
+    /// the kernel fills in a program's code, which is made visible to
+    /// instruction fetches. A page shared by `fork()` is copied first, as a
+    /// write to it would be.
+    ///
+    /// If this address space is installed, the TLB entries for the pages
+    /// written must be invalidated afterwards.
+    pub fn write(&mut self, va: usize, bytes: &[u8]) -> Result<(), FaultError> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = va + done;
+            let region = *self.region(at).ok_or(FaultError::Unmapped)?;
+            if self.translate(at).is_none() {
+                self.map_new_page(at)?;
+            }
+            let entry = self.entry_mut(at);
+            let frame = if frame::owners(entry.address()) > 1 {
+                let shared = entry.address();
+                let copy = frame::copy(shared).ok_or(FaultError::OutOfMemory)?;
+                unsafe { frame::release(shared) };
+                copy
+            } else {
+                entry.address()
+            };
+            *entry = Descriptor::page(frame, region.attrs);
+
+            let offset = at % PAGE_SIZE;
+            let len = (PAGE_SIZE - offset).min(bytes.len() - done);
+            let dst = (phys_to_virt(frame) + offset) as *mut u8;
+            unsafe { ptr::copy_nonoverlapping(bytes[done..].as_ptr(), dst, len) };
+            if region.attrs.executable {
+                aarch64::sync_instruction_cache(dst as usize, len);
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Copies the bytes at the user address `va` to `buf`, mapping zeroed
+    /// pages where nothing is mapped yet, as a read from EL0 would.
+    pub fn read(&mut self, va: usize, buf: &mut [u8]) -> Result<(), FaultError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = va.checked_add(done).ok_or(FaultError::Unmapped)?;
+            self.handle_fault(at, AccessKind::Read)?;
+            let (_, pa) = self.translate(at).ok_or(FaultError::Unmapped)?;
+
+            let len = (PAGE_SIZE - at % PAGE_SIZE).min(buf.len() - done);
+            let src = phys_to_virt(pa) as *const u8;
+            unsafe { ptr::copy_nonoverlapping(src, buf[done..].as_mut_ptr(), len) };
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Copies `bytes` to the user address `va`, mapping zeroed pages where
+    /// nothing is mapped yet and copying pages shared by `fork()`, as a write
+    /// from EL0 would.
+    ///
+    /// If this address space is installed, the TLB entries for the pages
+    /// written must be invalidated afterwards.
+    pub fn store(&mut self, va: usize, bytes: &[u8]) -> Result<(), FaultError> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = va.checked_add(done).ok_or(FaultError::Unmapped)?;
+            self.handle_fault(at, AccessKind::Write)?;
+            let (_, pa) = self.translate(at).ok_or(FaultError::Unmapped)?;
+
+            let len = (PAGE_SIZE - at % PAGE_SIZE).min(bytes.len() - done);
+            let dst = phys_to_virt(pa) as *mut u8;
+            unsafe { ptr::copy_nonoverlapping(bytes[done..].as_ptr(), dst, len) };
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Maps a zeroed page, with the attributes of its region, at the user
+    /// address `va`, where nothing is mapped yet. Fails if that would take
+    /// this address space over its memory limit.
+    fn map_new_page(&mut self, va: usize) -> Result<(), FaultError> {
+        let region = *self.region(va).ok_or(FaultError::Unmapped)?;
+        if self.pages >= self.max_pages {
+            return Err(FaultError::MemoryLimit);
+        }
+        let frame = frame::alloc().ok_or(FaultError::OutOfMemory)?;
+        *self.entry_mut(va) = Descriptor::page(frame, region.attrs);
+        self.pages += 1;
+        Ok(())
+    }
+
+    /// Calls `f` with each mapping of the lower half of the address space,
+    /// including the kernel's below `USER_BASE`, in address order. See
+    /// `walk::walk()`.
+    pub fn walk<F: FnMut(Mapping)>(&self, f: F) {
+        walk::walk(&self.l1, 0, f);
+    }
+
+    /// Walks the tables to find the descriptor that maps the user address
+    /// `va`, returning it with the physical address `va` translates to.
+    pub fn translate(&self, va: usize) -> Option<(Descriptor, usize)> {
+        if va < USER_BASE || va >= USER_END {
+            return None;
+        }
+
+        let l1 = self.l1.entries[va / L1_BLOCK_SIZE];
+        if !l1.is_table() {
+            return None;
+        }
+        let l2 = table_at(l1).entries[(va / L2_BLOCK_SIZE) % ENTRIES];
+        if !l2.is_table() {
+            return None;
+        }
+        let l3 = table_at(l2).entries[(va / PAGE_SIZE) % ENTRIES];
+        if !l3.is_valid() {
+            return None;
+        }
+        Some((l3, l3.address() + va % PAGE_SIZE))
+    }
+
+    /// Returns the level 3 descriptor for the user address `va`, creating the
+    /// tables leading to it if they do not exist.
+    fn entry_mut(&mut self, va: usize) -> &mut Descriptor {
+        let l2 = next_table(
+            &mut self.l1.entries[va / L1_BLOCK_SIZE],
+            &mut self.l2_tables,
+        );
+        let l3 = next_table(
+            &mut l2.entries[(va / L2_BLOCK_SIZE) % ENTRIES],
+            &mut self.l3_tables,
+        );
+        &mut l3.entries[(va / PAGE_SIZE) % ENTRIES]
+    }
+
+    /// Calls `f` with the address and the level 3 descriptor of every mapped
+    /// user page.
+    fn for_each_page<F: FnMut(usize, &mut Descriptor)>(&mut self, mut f: F) {
+        for i in USER_BASE / L1_BLOCK_SIZE..ENTRIES {
+            let l1 = self.l1.entries[i];
+            if !l1.is_table() {
+                continue;
+            }
+
+            for (j, &l2) in table_at(l1).entries.iter().enumerate() {
+                if !l2.is_table() {
+                    continue;
+                }
+
+                let l3_table = unsafe { &mut *(phys_to_virt(l2.address()) as *mut Table) };
+                for (k, entry) in l3_table.entries.iter_mut().enumerate() {
+                    if entry.is_valid() {
+                        f(i * L1_BLOCK_SIZE + j * L2_BLOCK_SIZE + k * PAGE_SIZE, entry);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for UserSpace {
+    fn drop(&mut self) {
+        if let Some(asid) = self.asid {
+            asid::free(asid);
+        }
+
+        for table in &self.l3_tables {
+            for entry in table.entries.iter().filter(|entry| entry.is_valid()) {
+                unsafe { frame::release(entry.address()) };
+            }
+        }
+    }
+}
+
+/// Returns the table the valid table descriptor `entry` points to.
+fn table_at<'a>(entry: Descriptor) -> &'a Table {
+    unsafe { &*(phys_to_virt(entry.address()) as *const Table) }
+}
+
+/// Returns the table the descriptor `entry` points to, first pointing it to a
+/// new empty table, kept in `tables`, if it is invalid.
+fn next_table<'a>(entry: &mut Descriptor, tables: &mut Vec<Box<Table>>) -> &'a mut Table {
+    if !entry.is_valid() {
+        let table = Box::new(Table::empty());
+        *entry = Descriptor::table(table.address());
+        tables.push(table);
+    }
+    unsafe { &mut *(phys_to_virt(entry.address()) as *mut Table) }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::{frame, AccessKind, FaultError, RegionError, UserSpace};
+    use crate::vm::{Access, Attributes, PAGE_SIZE, USER_BASE, USER_END};
+
+    #[test]
+    fn regions() {
+        let mut space = UserSpace::new(&[]);
+        assert_eq!(
+            space.add_region(USER_BASE, 4 * PAGE_SIZE, Attributes::USER_CODE),
+            Ok(())
+        );
+        assert_eq!(
+            space.add_region(USER_BASE + 3 * PAGE_SIZE, PAGE_SIZE, Attributes::USER_DATA),
+            Err(RegionError::Overlaps)
+        );
+        assert_eq!(
+            space.add_region(
+                USER_BASE + 4 * PAGE_SIZE + 8,
+                PAGE_SIZE,
+                Attributes::USER_DATA
+            ),
+            Err(RegionError::Unaligned)
+        );
+        assert_eq!(
+            space.add_region(USER_BASE - PAGE_SIZE, PAGE_SIZE, Attributes::USER_DATA),
+            Err(RegionError::OutOfRange)
+        );
+        assert_eq!(
+            space.add_region(USER_END - PAGE_SIZE, 2 * PAGE_SIZE, Attributes::USER_DATA),
+            Err(RegionError::OutOfRange)
+        );
+        assert_eq!(
+            space.add_region(USER_END - PAGE_SIZE, PAGE_SIZE, Attributes::USER_DATA),
+            Ok(())
+        );
+
+        assert_eq!(space.regions().len(), 2);
+        assert_eq!(
+            space.region(USER_BASE + 0x3fff).unwrap().attrs,
+            Attributes::USER_CODE
+        );
+        assert!(space.region(USER_BASE + 4 * PAGE_SIZE).is_none());
+    }
+
+    #[test]
+    fn demand_paging() {
+        let mut space = UserSpace::new(&[]);
+        let stack = USER_END - 256 * PAGE_SIZE;
+        space
+            .add_region(stack, 256 * PAGE_SIZE, Attributes::USER_DATA)
+            .unwrap();
+        space
+            .add_region(USER_BASE, PAGE_SIZE, Attributes::USER_CODE)
+            .unwrap();
+
+        // nothing is mapped until it is touched
+        assert!(space.translate(USER_END - 8).is_none());
+        assert_eq!(space.handle_fault(USER_END - 8, AccessKind::Write), Ok(()));
+        let (page, pa) = space.translate(USER_END - 8).unwrap();
+        assert_eq!(page.attributes(), Attributes::USER_DATA);
+        assert_eq!(pa % PAGE_SIZE, PAGE_SIZE - 8);
+        assert!(space.translate(USER_END - 2 * PAGE_SIZE).is_none());
+
+        // pages are zeroed, and faulting on a mapped page leaves it alone
+        let frame = pa - (PAGE_SIZE - 8);
+        let bytes = unsafe { core::slice::from_raw_parts(frame as *const u8, PAGE_SIZE) };
+        assert!(bytes.iter().all(|&b| b == 0));
+        space.handle_fault(USER_END - 16, AccessKind::Read).unwrap();
+        assert_eq!(space.translate(USER_END - 16).unwrap().1, pa - 8);
+
+        assert_eq!(
+            space.handle_fault(USER_BASE, AccessKind::Write),
+            Err(FaultError::Protection)
+        );
+        assert_eq!(
+            space.handle_fault(stack, AccessKind::Execute),
+            Err(FaultError::Protection)
+        );
+        assert_eq!(
+            space.handle_fault(USER_BASE + PAGE_SIZE, AccessKind::Read),
+            Err(FaultError::Unmapped)
+        );
+        assert_eq!(space.handle_fault(USER_BASE, AccessKind::Execute), Ok(()));
+    }
+
+    #[test]
+    fn stack_guard() {
+        let mut space = UserSpace::new(&[]);
+        let stack = USER_END - 16 * PAGE_SIZE;
+        assert_eq!(space.add_stack(USER_END, 16 * PAGE_SIZE), Ok(()));
+        assert_eq!(space.region(USER_END - 8).unwrap().start, stack);
+
+        // the page below the stack belongs to no region, and stays unmapped
+        let guard = stack - PAGE_SIZE;
+        assert!(space.region(guard).is_none());
+        assert_eq!(
+            space.handle_fault(guard + 8, AccessKind::Write),
+            Err(FaultError::StackOverflow)
+        );
+        assert!(space.translate(guard).is_none());
+        assert_eq!(
+            space.add_region(guard, PAGE_SIZE, Attributes::USER_DATA),
+            Err(RegionError::Overlaps)
+        );
+        assert_eq!(
+            space.add_stack(guard + PAGE_SIZE, PAGE_SIZE),
+            Err(RegionError::Overlaps)
+        );
+
+        // nor may a guard page overlap a region
+        space
+            .add_region(USER_BASE, PAGE_SIZE, Attributes::USER_CODE)
+            .unwrap();
+        assert_eq!(
+            space.add_stack(USER_BASE + 2 * PAGE_SIZE, PAGE_SIZE),
+            Err(RegionError::Overlaps)
+        );
+        assert_eq!(
+            space.add_stack(USER_BASE + 3 * PAGE_SIZE, PAGE_SIZE),
+            Ok(())
+        );
+        assert_eq!(
+            space.add_stack(USER_BASE, PAGE_SIZE),
+            Err(RegionError::OutOfRange)
+        );
+
+        let mut child = space.fork();
+        assert_eq!(
+            child.handle_fault(guard, AccessKind::Read),
+            Err(FaultError::StackOverflow)
+        );
+    }
+
+    #[test]
+    fn heap() {
+        let mut space = UserSpace::new(&[]);
+        space
+            .add_region(USER_BASE, PAGE_SIZE, Attributes::USER_CODE)
+            .unwrap();
+        assert_eq!(space.set_brk(USER_BASE), Err(RegionError::OutOfRange));
+        let heap = USER_BASE + PAGE_SIZE;
+        assert_eq!(space.add_heap(heap), Ok(()));
+        assert_eq!(space.brk(), Some(heap));
+        space.add_stack(heap + 8 * PAGE_SIZE, PAGE_SIZE).unwrap();
+
+        // the heap grows a page at a time, and nothing is mapped in it until
+        // it is touched
+        assert_eq!(space.set_brk(heap + 8), Ok(()));
+        assert_eq!(space.heap().unwrap().end, heap + PAGE_SIZE);
+        assert_eq!(space.handle_fault(heap + 8, AccessKind::Write), Ok(()));
+        assert_eq!(
+            space.handle_fault(heap + PAGE_SIZE, AccessKind::Write),
+            Err(FaultError::Unmapped)
+        );
+        assert_eq!(space.set_brk(heap + 3 * PAGE_SIZE), Ok(()));
+        assert_eq!(space.store(heap + 2 * PAGE_SIZE, &[1]), Ok(()));
+        assert!(space.translate(heap + PAGE_SIZE).is_none());
+
+        // up to the stack's guard page, but not below the heap's start
+        assert_eq!(
+            space.set_brk(heap + 6 * PAGE_SIZE + 1),
+            Err(RegionError::Overlaps)
+        );
+        assert_eq!(space.set_brk(heap - 1), Err(RegionError::OutOfRange));
+        assert_eq!(space.brk(), Some(heap + 3 * PAGE_SIZE));
+
+        // a child gets the parent's heap
+        let child = space.fork();
+        assert_eq!(child.brk(), space.brk());
+        assert_eq!(child.region(heap + 8), space.heap());
+
+        // shrinking unmaps the pages past the break
+        let (_, page) = space.translate(heap + 2 * PAGE_SIZE).unwrap();
+        assert_eq!(space.set_brk(heap + 8), Ok(()));
+        assert!(space.translate(heap + 2 * PAGE_SIZE).is_none());
+        assert!(space.translate(heap).is_some());
+        assert_eq!(frame::owners(page & !(PAGE_SIZE - 1)), 1);
+        assert_eq!(space.set_brk(heap), Ok(()));
+        assert!(space.region(heap).is_none());
+        assert_eq!(
+            space.add_region(heap + PAGE_SIZE, PAGE_SIZE, Attributes::USER_DATA),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut space = UserSpace::new(&[]);
+        space
+            .add_region(USER_BASE, 4 * PAGE_SIZE, Attributes::USER_DATA)
+            .unwrap();
+        space.set_memory_limit(2 * PAGE_SIZE + 1);
+        assert_eq!(space.memory_limit(), 2 * PAGE_SIZE);
+
+        // only pages newly mapped count
+        assert_eq!(space.write(USER_BASE, &[1; 8]), Ok(()));
+        assert_eq!(space.handle_fault(USER_BASE, AccessKind::Write), Ok(()));
+        assert_eq!(space.store(USER_BASE + PAGE_SIZE, &[2]), Ok(()));
+        assert_eq!(space.resident(), 2 * PAGE_SIZE);
+        assert_eq!(
+            space.handle_fault(USER_BASE + 2 * PAGE_SIZE, AccessKind::Read),
+            Err(FaultError::MemoryLimit)
+        );
+        assert!(space.translate(USER_BASE + 2 * PAGE_SIZE).is_none());
+
+        // a child is held to the same limit, and so counts the pages it shares
+        let mut child = space.fork();
+        assert_eq!(child.resident(), 2 * PAGE_SIZE);
+        assert_eq!(
+            child.store(USER_BASE + 3 * PAGE_SIZE, &[3]),
+            Err(FaultError::MemoryLimit)
+        );
+        assert_eq!(child.store(USER_BASE, &[3]), Ok(()));
+    }
+
+    #[test]
+    fn asids() {
+        let mut a = UserSpace::new(&[]);
+        let b = UserSpace::new(&[]);
+        assert!(a.asid().is_some() && b.asid().is_some());
+        assert_ne!(a.asid(), b.asid());
+        assert_ne!(a.asid(), Some(0));
+        assert_ne!(a.fork().asid(), a.asid());
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let mut parent = UserSpace::new(&[]);
+        parent
+            .add_region(USER_BASE, PAGE_SIZE, Attributes::USER_CODE)
+            .unwrap();
+        let data = USER_BASE + 16 * PAGE_SIZE;
+        parent
+            .add_region(data, 2 * PAGE_SIZE, Attributes::USER_DATA)
+            .unwrap();
+
+        parent.handle_fault(USER_BASE, AccessKind::Execute).unwrap();
+        parent.handle_fault(data, AccessKind::Write).unwrap();
+        let (_, original) = parent.translate(data).unwrap();
+        unsafe { *(original as *mut u64) = 0xdead_beef };
+
+        let mut child = parent.fork();
+        assert_eq!(child.regions(), parent.regions());
+        assert!(child.translate(data + PAGE_SIZE).is_none());
+        for space in &[&parent, &child] {
+            let (page, pa) = space.translate(data).unwrap();
+            assert_eq!(pa, original);
+            assert_eq!(page.attributes().access, Access::UserReadOnly);
+            assert_eq!(
+                space.translate(USER_BASE).unwrap().0.attributes(),
+                Attributes::USER_CODE
+            );
+        }
+        assert_eq!(frame::owners(original), 2);
+
+        // the first writer gets a copy
+        child.handle_fault(data, AccessKind::Write).unwrap();
+        let (page, copy) = child.translate(data).unwrap();
+        assert_ne!(copy, original);
+        assert_eq!(page.attributes(), Attributes::USER_DATA);
+        assert_eq!(unsafe { *(copy as *const u64) }, 0xdead_beef);
+        assert_eq!(frame::owners(original), 1);
+
+        // the last keeps the original
+        parent.handle_fault(data, AccessKind::Write).unwrap();
+        let (page, pa) = parent.translate(data).unwrap();
+        assert_eq!(pa, original);
+        assert_eq!(page.attributes(), Attributes::USER_DATA);
+
+        // read-only pages stay shared
+        let code = parent.translate(USER_BASE).unwrap().1;
+        assert_eq!(child.translate(USER_BASE).unwrap().1, code);
+        assert_eq!(frame::owners(code), 2);
+        drop(child);
+        assert_eq!(frame::owners(code), 1);
+    }
+
+    #[test]
+    fn copying_in_and_out() {
+        let mut space = UserSpace::new(&[]);
+        space
+            .add_region(USER_BASE, 2 * PAGE_SIZE, Attributes::USER_CODE)
+            .unwrap();
+
+        // the kernel may write to read-only pages, across page boundaries
+        let bytes: Vec<u8> = (0..64).collect();
+        let va = USER_BASE + PAGE_SIZE - 32;
+        assert_eq!(space.write(va, &bytes), Ok(()));
+        let (page, _) = space.translate(USER_BASE + PAGE_SIZE).unwrap();
+        assert_eq!(page.attributes(), Attributes::USER_CODE);
+
+        let mut buf = [0u8; 96];
+        assert_eq!(space.read(va - 16, &mut buf), Ok(()));
+        assert!(buf[..16].iter().all(|&b| b == 0));
+        assert_eq!(&buf[16..80], &bytes[..]);
+
+        assert_eq!(
+            space.write(USER_BASE + 2 * PAGE_SIZE - 1, &[1, 2]),
+            Err(FaultError::Unmapped)
+        );
+        assert_eq!(
+            space.read(USER_BASE - 1, &mut buf[..1]),
+            Err(FaultError::Unmapped)
+        );
+
+        // stores are checked like writes from user mode
+        assert_eq!(space.store(va, &[1]), Err(FaultError::Protection));
+        let data = USER_BASE + 4 * PAGE_SIZE;
+        space
+            .add_region(data, PAGE_SIZE, Attributes::USER_DATA)
+            .unwrap();
+        assert_eq!(space.store(data + 8, &bytes[..4]), Ok(()));
+        assert_eq!(space.read(data + 8, &mut buf[..4]), Ok(()));
+        assert_eq!(&buf[..4], &bytes[..4]);
+    }
+}
@@ -0,0 +1,104 @@
+use super::descriptor::Attributes;
+use super::table::{Table, ENTRIES};
+use super::{phys_to_virt, L1_BLOCK_SIZE, PAGE_SIZE};
+
+/// A range of virtual addresses that translation tables map to a range of
+/// physical addresses of the same length, with the same attributes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// The first virtual address mapped.
+    pub va: usize,
+    /// The physical address `va` maps to.
+    pub pa: usize,
+    /// The number of bytes mapped.
+    pub len: usize,
+    /// The size of the blocks or pages that map the range.
+    pub size: usize,
+    pub attrs: Attributes,
+}
+
+/// Walks the translation tables whose level 1 table is `l1`, which maps the
+/// addresses from `base`, calling `f` with each of their mappings in address
+/// order. Consecutive blocks or pages of the same size that map consecutive
+/// physical addresses with the same attributes make up a single mapping.
+pub fn walk<F: FnMut(Mapping)>(l1: &Table, base: usize, mut f: F) {
+    let mut pending: Option<Mapping> = None;
+    walk_table(l1, base, L1_BLOCK_SIZE, &mut |next| match pending {
+        Some(ref mut last)
+            if last.va + last.len == next.va
+                && last.pa + last.len == next.pa
+                && last.size == next.size
+                && last.attrs == next.attrs =>
+        {
+            last.len += next.len
+        }
+        _ => {
+            if let Some(last) = pending.replace(next) {
+                f(last);
+            }
+        }
+    });
+
+    if let Some(last) = pending {
+        f(last);
+    }
+}
+
+/// Calls `f` with each block or page mapped by `table`, whose descriptors
+/// each map `size` bytes from `base` on, and by the tables it points to.
+fn walk_table(table: &Table, base: usize, size: usize, f: &mut dyn FnMut(Mapping)) {
+    for (i, entry) in table.entries.iter().enumerate() {
+        let va = base + i * size;
+        if !entry.is_valid() {
+            continue;
+        } else if size > PAGE_SIZE && entry.is_table() {
+            let next = unsafe { &*(phys_to_virt(entry.address()) as *const Table) };
+            walk_table(next, va, size / ENTRIES, f);
+        } else {
+            f(Mapping {
+                va,
+                pa: entry.address(),
+                len: size,
+                size,
+                attrs: entry.attributes(),
+            });
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use crate::vm::{
+        Attributes, IdentityMap, KERNEL_BASE, L1_BLOCK_SIZE, L2_BLOCK_SIZE, PAGE_SIZE,
+    };
+    use pi::common::IO_BASE;
+
+    #[test]
+    fn merged_mappings() {
+        let mut map = IdentityMap::new();
+        map.set_attributes(0x8_0000, 0x9_0000, Attributes::KERNEL_TEXT);
+
+        let mut mappings = Vec::new();
+        map.walk(|mapping| mappings.push(mapping));
+
+        let (ram, text, device) = (
+            Attributes::KERNEL_RAM,
+            Attributes::KERNEL_TEXT,
+            Attributes::DEVICE,
+        );
+        let expected = [
+            (0, 0x8_0000, PAGE_SIZE, ram),
+            (0x8_0000, 0x1_0000, PAGE_SIZE, text),
+            (0x9_0000, 0x17_0000, PAGE_SIZE, ram),
+            (0x20_0000, IO_BASE - 0x20_0000, L2_BLOCK_SIZE, ram),
+            (IO_BASE, L1_BLOCK_SIZE - IO_BASE, L2_BLOCK_SIZE, device),
+            (L1_BLOCK_SIZE, L1_BLOCK_SIZE, L1_BLOCK_SIZE, device),
+        ];
+        assert_eq!(mappings.len(), expected.len());
+        for (mapping, &(pa, len, size, attrs)) in mappings.iter().zip(expected.iter()) {
+            assert_eq!(mapping.va, KERNEL_BASE + pa);
+            assert_eq!((mapping.pa, mapping.len), (pa, len));
+            assert_eq!((mapping.size, mapping.attrs), (size, attrs));
+        }
+    }
+}
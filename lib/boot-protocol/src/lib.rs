@@ -0,0 +1,165 @@
+#![no_std]
+
+//! What the bootloader, the kernel and the host tools that load them agree
+//! on: where images are loaded, what an image finds in its registers when it
+//! is entered, and the header an image starts with.
+//!
+//! The linker scripts cannot import these, so `boot/.cargo/layout.ld` and
+//! `kern/.cargo/layout.ld` repeat the addresses; they say so where they do.
+
+#[cfg(test)]
+mod tests;
+
+use core::convert::TryInto;
+use core::fmt;
+
+/// The physical address the firmware loads `kernel8.img` at by default, and
+/// the bootloader loads the images it receives at. The kernel is linked to
+/// run here.
+pub const KERNEL_LOAD_ADDR: usize = 0x80000;
+
+/// The physical address the bootloader is linked to run at. `config.txt`'s
+/// `kernel_address` has the firmware load it here, out of the way of the
+/// images it loads.
+pub const BOOTLOADER_ADDR: usize = 0x4000000;
+
+/// The largest image the bootloader can load: it must end before the
+/// bootloader begins.
+pub const MAX_KERNEL_SIZE: usize = BOOTLOADER_ADDR - KERNEL_LOAD_ADDR;
+
+/// The physical address the firmware leaves the ATAGs at.
+pub const ATAGS_ADDR: usize = 0x100;
+
+/// What `x1` holds when the bootloader enters an image: `RSOSBOOT` in ASCII,
+/// little-endian. The firmware enters images with `x1` zeroed.
+///
+/// Either way, an image is entered at its first byte, on core 0, with the
+/// other cores parked in the firmware's spin table, and with:
+///
+///   * `x0`: the address of the ATAGs, `ATAGS_ADDR`
+///   * `x1`: `LOADER_MAGIC` if the bootloader loaded the image, `0` if the
+///     firmware did
+pub const LOADER_MAGIC: u64 = 0x544F_4F42_534F_5352;
+
+/// The magic number that marks an image as starting with an `ImageHeader`.
+pub const IMAGE_MAGIC: [u8; 4] = *b"RSOS";
+
+/// The version of the protocol this crate describes, which images record in
+/// their headers.
+pub const VERSION: u32 = 1;
+
+/// `b #32`: the branch over the header to the code that follows it.
+const BRANCH_OVER_HEADER: u32 = 0x1400_0000 | (ImageHeader::SIZE as u32 / 4);
+
+/// The header an image starts with, so that loaders can check it is one they
+/// can load before they do. Its first word branches over the rest of it, so
+/// the image is still entered at its first byte.
+///
+/// Images without a header, like the programs in `ext/`, are loaded as they
+/// are, unchecked.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImageHeader {
+    /// The instruction that branches over the header.
+    pub branch: u32,
+    /// `IMAGE_MAGIC`.
+    pub magic: [u8; 4],
+    /// The version of the protocol the image follows.
+    pub version: u32,
+    /// Reserved; zero.
+    pub flags: u32,
+    /// The physical address the image must be loaded at.
+    pub load_addr: u64,
+    /// Reserved; zero.
+    pub reserved: u64,
+}
+
+impl ImageHeader {
+    /// The size of the header in bytes.
+    pub const SIZE: usize = 32;
+
+    /// Returns the header of an image that follows this version of the
+    /// protocol and must be loaded at `load_addr`.
+    pub const fn new(load_addr: usize) -> ImageHeader {
+        ImageHeader {
+            branch: BRANCH_OVER_HEADER,
+            magic: IMAGE_MAGIC,
+            version: VERSION,
+            flags: 0,
+            load_addr: load_addr as u64,
+            reserved: 0,
+        }
+    }
+
+    /// Reads the header `image` starts with. Returns `None` if it does not
+    /// start with one.
+    pub fn parse(image: &[u8]) -> Option<ImageHeader> {
+        if image.len() < ImageHeader::SIZE || image[4..8] != IMAGE_MAGIC {
+            return None;
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes(image[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(image[i..i + 8].try_into().unwrap());
+        Some(ImageHeader {
+            branch: u32_at(0),
+            magic: IMAGE_MAGIC,
+            version: u32_at(8),
+            flags: u32_at(12),
+            load_addr: u64_at(16),
+            reserved: u64_at(24),
+        })
+    }
+}
+
+/// Why an image cannot be loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The image follows a different version of the protocol.
+    Version(u32),
+    /// The image must be loaded somewhere else.
+    LoadAddress(u64),
+    /// The image does not fit below the bootloader.
+    TooLarge(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Version(version) => write!(
+                f,
+                "image follows boot protocol version {}, not {}",
+                version, VERSION
+            ),
+            Error::LoadAddress(addr) => write!(
+                f,
+                "image must be loaded at {:#x}, not {:#x}",
+                addr, KERNEL_LOAD_ADDR
+            ),
+            Error::TooLarge(len) => write!(
+                f,
+                "image is {} bytes, more than the {} that fit",
+                len, MAX_KERNEL_SIZE
+            ),
+        }
+    }
+}
+
+/// Checks that the bootloader can load `image` at `KERNEL_LOAD_ADDR` and
+/// enter it. Images without a header are not checked.
+pub fn check_kernel(image: &[u8]) -> Result<(), Error> {
+    let header = match ImageHeader::parse(image) {
+        Some(header) => header,
+        None => return Ok(()),
+    };
+
+    if header.version != VERSION {
+        return Err(Error::Version(header.version));
+    }
+    if header.load_addr != KERNEL_LOAD_ADDR as u64 {
+        return Err(Error::LoadAddress(header.load_addr));
+    }
+    if image.len() > MAX_KERNEL_SIZE {
+        return Err(Error::TooLarge(image.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use core::mem::size_of;
+
+use super::*;
+
+fn bytes(header: &ImageHeader) -> [u8; ImageHeader::SIZE] {
+    let mut bytes = [0; ImageHeader::SIZE];
+    bytes[0..4].copy_from_slice(&header.branch.to_le_bytes());
+    bytes[4..8].copy_from_slice(&header.magic);
+    bytes[8..12].copy_from_slice(&header.version.to_le_bytes());
+    bytes[12..16].copy_from_slice(&header.flags.to_le_bytes());
+    bytes[16..24].copy_from_slice(&header.load_addr.to_le_bytes());
+    bytes[24..32].copy_from_slice(&header.reserved.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn header_layout() {
+    assert_eq!(size_of::<ImageHeader>(), ImageHeader::SIZE);
+
+    let header = ImageHeader::new(KERNEL_LOAD_ADDR);
+    let image = unsafe { *(&header as *const ImageHeader as *const [u8; 32]) };
+    assert_eq!(image, bytes(&header));
+    assert_eq!(&image[..4], &[0x08, 0x00, 0x00, 0x14]);
+    assert_eq!(&image[4..8], b"RSOS");
+}
+
+#[test]
+fn loader_magic() {
+    assert_eq!(LOADER_MAGIC.to_le_bytes(), *b"RSOSBOOT");
+}
+
+#[test]
+fn parse_round_trip() {
+    let header = ImageHeader::new(BOOTLOADER_ADDR);
+    let mut image = bytes(&header).to_vec();
+    image.extend_from_slice(&[0xAA; 64]);
+
+    assert_eq!(ImageHeader::parse(&image), Some(header));
+    assert_eq!(ImageHeader::parse(&image[..ImageHeader::SIZE - 1]), None);
+    assert_eq!(ImageHeader::parse(&[0; 64]), None);
+}
+
+#[test]
+fn check_kernel_images() {
+    let mut image = bytes(&ImageHeader::new(KERNEL_LOAD_ADDR)).to_vec();
+    assert_eq!(check_kernel(&image), Ok(()));
+
+    let elsewhere = bytes(&ImageHeader::new(BOOTLOADER_ADDR));
+    assert_eq!(
+        check_kernel(&elsewhere),
+        Err(Error::LoadAddress(BOOTLOADER_ADDR as u64))
+    );
+
+    let mut future = ImageHeader::new(KERNEL_LOAD_ADDR);
+    future.version = VERSION + 1;
+    assert_eq!(
+        check_kernel(&bytes(&future)),
+        Err(Error::Version(VERSION + 1))
+    );
+
+    image.resize(MAX_KERNEL_SIZE + 1, 0);
+    assert_eq!(
+        check_kernel(&image),
+        Err(Error::TooLarge(MAX_KERNEL_SIZE + 1))
+    );
+}
+
+#[test]
+fn headerless_images_are_unchecked() {
+    assert_eq!(check_kernel(&[]), Ok(()));
+    assert_eq!(check_kernel(&[0; MAX_KERNEL_SIZE + 1]), Ok(()));
+}
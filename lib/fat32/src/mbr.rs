@@ -30,6 +30,10 @@ const_assert_size!(CHS, 3);
 const ACTIVE_PART_FLAG: u8 = 0x80;
 const INACTIVE_PARTFLAG: u8 = 0x00;
 
+// Partition types of FAT32 partitions, addressed by CHS and by LBA
+const FAT32_CHS_PART_TYPE: u8 = 0x0B;
+const FAT32_LBA_PART_TYPE: u8 = 0x0C;
+
 /// Metadata about an entry in the MBR partition table
 #[repr(C, packed)]
 pub struct PartitionEntry {
@@ -56,6 +60,23 @@ impl fmt::Debug for PartitionEntry {
 
 const_assert_size!(PartitionEntry, 16);
 
+impl PartitionEntry {
+    /// Returns `true` if the partition holds a FAT32 file system.
+    pub fn is_fat32(&self) -> bool {
+        self.partition_type == FAT32_CHS_PART_TYPE || self.partition_type == FAT32_LBA_PART_TYPE
+    }
+
+    /// The physical sector where the partition begins.
+    pub fn start(&self) -> u64 {
+        self.sector_offset as u64
+    }
+
+    /// The number of physical sectors in the partition.
+    pub fn num_sectors(&self) -> u64 {
+        self.total_sectors as u64
+    }
+}
+
 // The "magic" two byte signature that indicates a valid MBR bootsector
 const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
 
@@ -129,4 +150,9 @@ impl MasterBootRecord {
 
         Ok(mbr)
     }
+
+    /// Returns the entries of the partition table, in order.
+    pub fn partitions(&self) -> &[PartitionEntry; 4] {
+        &self.partition_table
+    }
 }
@@ -113,4 +113,20 @@ pub trait FileSystem: Sized {
             .into_dir()
             .ok_or(io::Error::new(io::ErrorKind::Other, "not a directory"))
     }
+
+    /// Creates an empty file at `path`, replacing the file there if one
+    /// exists, and opens it. `path` must be absolute.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the error conditions for `open()` on the parent of
+    /// `path`, this method returns an error kind of `Other` if the entry at
+    /// `path` is a directory.
+    ///
+    /// The default implementation supports read-only file systems: it always
+    /// returns an error kind of `Other`.
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let _ = path;
+        Err(io::Error::new(io::ErrorKind::Other, "read-only file system"))
+    }
 }
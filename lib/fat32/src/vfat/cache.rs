@@ -3,6 +3,7 @@ use alloc::vec::Vec;
 use core::fmt;
 use hashbrown::HashMap;
 use shim::io;
+use shim::ioerr;
 
 use crate::traits::BlockDevice;
 
@@ -87,7 +88,9 @@ impl CachedPartition {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
-        unimplemented!("CachedPartition::get_mut()")
+        let entry = self.entry(sector)?;
+        entry.dirty = true;
+        Ok(&mut entry.data)
     }
 
     /// Returns a reference to the cached sector `sector`. If the sector is not
@@ -97,23 +100,56 @@ impl CachedPartition {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get(&mut self, sector: u64) -> io::Result<&[u8]> {
-        unimplemented!("CachedPartition::get()")
+        Ok(&self.entry(sector)?.data)
+    }
+
+    /// Returns the cache entry of the logical sector `sector`, reading it from
+    /// the disk first if it is not already cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidInput` if the sector is outside of the partition, or
+    /// the error reading it from the disk.
+    fn entry(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
+        if !self.cache.contains_key(&sector) {
+            let physical = match self.virtual_to_physical(sector) {
+                Some(physical) => physical,
+                None => return ioerr!(InvalidInput, "sector is outside of the partition"),
+            };
+
+            let mut data = Vec::with_capacity(self.partition.sector_size as usize);
+            for n in physical..physical + self.factor() {
+                self.device.read_all_sector(n, &mut data)?;
+            }
+
+            if data.len() != self.partition.sector_size as usize {
+                return ioerr!(UnexpectedEof, "short read of a sector");
+            }
+
+            self.cache.insert(sector, CacheEntry { data, dirty: false });
+        }
+
+        Ok(self.cache.get_mut(&sector).unwrap())
     }
 }
 
-// FIXME: Implement `BlockDevice` for `CacheDevice`. The `read_sector` and
-// `write_sector` methods should only read/write from/to cached sectors.
 impl BlockDevice for CachedPartition {
     fn sector_size(&self) -> u64 {
-        unimplemented!()
+        self.partition.sector_size
     }
 
     fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> io::Result<usize> {
-        unimplemented!()
+        let data = self.get(sector)?;
+        let len = core::cmp::min(data.len(), buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
     }
 
     fn write_sector(&mut self, sector: u64, buf: &[u8]) -> io::Result<usize> {
-        unimplemented!()
+        let data = self.get_mut(sector)?;
+        let len = core::cmp::min(data.len(), buf.len());
+        data[..len].copy_from_slice(&buf[..len]);
+        Ok(len)
     }
 }
 
@@ -7,4 +7,25 @@ impl From<u32> for Cluster {
     }
 }
 
-// TODO: Implement any useful helper methods on `Cluster`.
+impl Cluster {
+    /// The first cluster of the data region.
+    const FIRST_DATA_CLUSTER: u32 = 2;
+
+    /// Returns the cluster's number, as its FAT entry and directory entries
+    /// name it.
+    pub fn number(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns the index of the cluster in the data region, which starts at
+    /// cluster 2.
+    pub fn data_index(&self) -> u64 {
+        self.0.saturating_sub(Self::FIRST_DATA_CLUSTER) as u64
+    }
+
+    /// Returns `true` if the cluster can hold data. Directory entries of
+    /// empty files and of `..` in a directory of the root name cluster 0.
+    pub fn is_data(&self) -> bool {
+        self.0 >= Self::FIRST_DATA_CLUSTER
+    }
+}
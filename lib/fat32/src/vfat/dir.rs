@@ -4,23 +4,36 @@ use alloc::vec::Vec;
 use shim::const_assert_size;
 use shim::ffi::OsStr;
 use shim::io;
-use shim::newioerr;
+use shim::ioerr;
 
 use crate::traits;
-use crate::util::VecExt;
 use crate::vfat::{Attributes, Date, Metadata, Time, Timestamp};
 use crate::vfat::{Cluster, Entry, File, VFatHandle};
 
 #[derive(Debug)]
 pub struct Dir<HANDLE: VFatHandle> {
     pub vfat: HANDLE,
-    // FIXME: Fill me in.
+    pub(crate) first_cluster: Cluster,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatRegularDirEntry {
-    // FIXME: Fill me in.
+    name: [u8; 8],
+    extension: [u8; 3],
+    attributes: Attributes,
+    _reserved: u8,
+    _created_tenths: u8,
+    created_time: Time,
+    created_date: Date,
+    accessed_date: Date,
+    cluster_high: u16,
+    modified_time: Time,
+    modified_date: Date,
+    cluster_low: u16,
+    size: u32,
 }
 
 const_assert_size!(VFatRegularDirEntry, 32);
@@ -28,7 +41,14 @@ const_assert_size!(VFatRegularDirEntry, 32);
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatLfnDirEntry {
-    // FIXME: Fill me in.
+    sequence: u8,
+    name1: [u16; 5],
+    _attributes: Attributes,
+    _entry_type: u8,
+    _checksum: u8,
+    name2: [u16; 6],
+    _zero: u16,
+    name3: [u16; 2],
 }
 
 const_assert_size!(VFatLfnDirEntry, 32);
@@ -36,7 +56,10 @@ const_assert_size!(VFatLfnDirEntry, 32);
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatUnknownDirEntry {
-    // FIXME: Fill me in.
+    id: u8,
+    _unknown: [u8; 10],
+    attributes: Attributes,
+    _rest: [u8; 20],
 }
 
 const_assert_size!(VFatUnknownDirEntry, 32);
@@ -47,7 +70,104 @@ pub union VFatDirEntry {
     long_filename: VFatLfnDirEntry,
 }
 
+/// The first byte of the entry that follows the last entry of a directory.
+const END_OF_DIR: u8 = 0x00;
+
+/// The first byte of a deleted entry, which is free to reuse.
+const DELETED: u8 = 0xE5;
+
+/// Stands for `DELETED` as the first byte of a short name that starts with
+/// 0xE5, so the entry is not taken to be deleted.
+const ESCAPED_DELETED: u8 = 0x05;
+
+/// The number of UCS-2 characters of a long file name in each of its entries.
+const LFN_CHARS_PER_ENTRY: usize = 13;
+
+impl VFatRegularDirEntry {
+    /// Returns the entry's short name: its name and, if it has one, its
+    /// extension, joined by a `.`.
+    fn short_name(&self) -> String {
+        let mut name = self.name;
+        if name[0] == ESCAPED_DELETED {
+            name[0] = DELETED;
+        }
+
+        let trim = |bytes: &[u8]| -> String {
+            let len = bytes.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+            bytes[..len].iter().map(|&b| b as char).collect()
+        };
+
+        let mut short_name = trim(&name);
+        let extension = trim(&self.extension);
+        if !extension.is_empty() {
+            short_name.push('.');
+            short_name.push_str(&extension);
+        }
+        short_name
+    }
+
+    fn first_cluster(&self) -> Cluster {
+        Cluster::from((self.cluster_high as u32) << 16 | self.cluster_low as u32)
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            attributes: self.attributes,
+            created: Timestamp {
+                date: self.created_date,
+                time: self.created_time,
+            },
+            accessed: Timestamp {
+                date: self.accessed_date,
+                time: Time::default(),
+            },
+            modified: Timestamp {
+                date: self.modified_date,
+                time: self.modified_time,
+            },
+        }
+    }
+}
+
+impl VFatLfnDirEntry {
+    /// Copies the entry's part of a long file name into its place in `name`,
+    /// growing `name` as needed.
+    fn copy_name_into(&self, name: &mut Vec<u16>) {
+        let index = (self.sequence & 0x1F) as usize;
+        if index == 0 {
+            return;
+        }
+
+        let start = (index - 1) * LFN_CHARS_PER_ENTRY;
+        if name.len() < start + LFN_CHARS_PER_ENTRY {
+            name.resize(start + LFN_CHARS_PER_ENTRY, 0xFFFF);
+        }
+
+        let (name1, name2, name3) = (self.name1, self.name2, self.name3);
+        let chars = name1.iter().chain(name2.iter()).chain(name3.iter());
+        for (slot, &c) in name[start..].iter_mut().zip(chars) {
+            *slot = c;
+        }
+    }
+}
+
 impl<HANDLE: VFatHandle> Dir<HANDLE> {
+    /// Returns the file system's root directory.
+    pub fn root(vfat: HANDLE) -> Dir<HANDLE> {
+        let first_cluster = vfat.lock(|vfat| vfat.root_cluster());
+        Dir {
+            vfat,
+            first_cluster,
+            name: String::new(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Returns `true` if `self` is the file system's root directory.
+    pub fn is_root(&self) -> bool {
+        self.first_cluster == self.vfat.lock(|vfat| vfat.root_cluster())
+    }
+
     /// Finds the entry named `name` in `self` and returns it. Comparison is
     /// case-insensitive.
     ///
@@ -59,10 +179,119 @@ impl<HANDLE: VFatHandle> Dir<HANDLE> {
     /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
     /// is returned.
     pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry<HANDLE>> {
-        unimplemented!("Dir::find()")
+        use crate::traits::{Dir, Entry};
+
+        let name = match name.as_ref().to_str() {
+            Some(name) => name,
+            None => return ioerr!(InvalidInput, "name is not valid UTF-8"),
+        };
+
+        match self
+            .entries()?
+            .find(|entry| entry.name().eq_ignore_ascii_case(name))
+        {
+            Some(entry) => Ok(entry),
+            None => ioerr!(NotFound, "no such file or directory"),
+        }
+    }
+}
+
+/// An iterator over the entries of a directory. See `Dir::entries()`.
+pub struct EntryIter<HANDLE: VFatHandle> {
+    vfat: HANDLE,
+    root_cluster: Cluster,
+    data: Vec<u8>,
+    offset: usize,
+}
+
+impl<HANDLE: VFatHandle> EntryIter<HANDLE> {
+    /// Returns the next raw entry, or `None` if there are no more.
+    fn next_raw(&mut self) -> Option<VFatDirEntry> {
+        let bytes = self.data.get(self.offset..self.offset + 32)?;
+        self.offset += 32;
+        // `VFatDirEntry` is 32 bytes, and any 32 bytes are one.
+        Some(unsafe { (bytes.as_ptr() as *const VFatDirEntry).read_unaligned() })
+    }
+}
+
+impl<HANDLE: VFatHandle> Iterator for EntryIter<HANDLE> {
+    type Item = Entry<HANDLE>;
+
+    fn next(&mut self) -> Option<Entry<HANDLE>> {
+        let mut long_name = Vec::new();
+        loop {
+            let raw = self.next_raw()?;
+            let unknown = unsafe { raw.unknown };
+            match unknown.id {
+                END_OF_DIR => {
+                    self.offset = self.data.len();
+                    return None;
+                }
+                DELETED => {
+                    long_name.clear();
+                    continue;
+                }
+                _ => (),
+            }
+
+            if unknown.attributes.long_file_name() {
+                unsafe { raw.long_filename }.copy_name_into(&mut long_name);
+                continue;
+            }
+
+            let regular = unsafe { raw.regular };
+            let name = if long_name.is_empty() {
+                regular.short_name()
+            } else {
+                let len = long_name
+                    .iter()
+                    .position(|&c| c == 0x0000 || c == 0xFFFF)
+                    .unwrap_or(long_name.len());
+                core::char::decode_utf16(long_name[..len].iter().cloned())
+                    .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+                    .collect()
+            };
+
+            let metadata = regular.metadata();
+            let first_cluster = regular.first_cluster();
+            let vfat = self.vfat.clone();
+            return Some(if metadata.attributes.directory() {
+                // The `..` entry of a directory in the root names cluster 0.
+                let first_cluster = if first_cluster.is_data() {
+                    first_cluster
+                } else {
+                    self.root_cluster
+                };
+
+                Entry::Dir(Dir {
+                    vfat,
+                    first_cluster,
+                    name,
+                    metadata,
+                })
+            } else {
+                Entry::File(File::new(vfat, first_cluster, name, metadata, regular.size))
+            });
+        }
     }
 }
 
 impl<HANDLE: VFatHandle> traits::Dir for Dir<HANDLE> {
-    // FIXME: Implement `trait::Dir` for `Dir`.
+    type Entry = Entry<HANDLE>;
+    type Iter = EntryIter<HANDLE>;
+
+    fn entries(&self) -> io::Result<EntryIter<HANDLE>> {
+        let mut data = Vec::new();
+        let root_cluster = self.vfat.lock(|vfat| {
+            vfat.read_chain(self.first_cluster, &mut data)?;
+            Ok::<_, io::Error>(vfat.root_cluster())
+        })?;
+
+        Ok(EntryIter {
+            vfat: self.vfat.clone(),
+            root_cluster,
+            data,
+            offset: 0,
+        })
+    }
 }
@@ -69,6 +69,44 @@ impl BiosParameterBlock {
 
         Ok(ebpb)
     }
+
+    /// The size, in bytes, of a logical sector.
+    pub fn bytes_per_sector(&self) -> u16 {
+        self.bytes_per_sector
+    }
+
+    /// The number of logical sectors in a cluster.
+    pub fn sectors_per_cluster(&self) -> u8 {
+        self.sectors_per_cluster
+    }
+
+    /// The number of logical sectors before the first FAT.
+    pub fn num_reserved_sectors(&self) -> u16 {
+        self.num_reserved_sectors
+    }
+
+    /// The number of copies of the FAT.
+    pub fn num_fats(&self) -> u8 {
+        self.num_fats
+    }
+
+    /// The number of logical sectors in one copy of the FAT.
+    pub fn sectors_per_fat(&self) -> u32 {
+        self.sectors_per_fat
+    }
+
+    /// The number of logical sectors in the file system.
+    pub fn num_sectors(&self) -> u32 {
+        match self.total_logical_sectors {
+            0 => self.total_logical_sector_overflow,
+            n => n as u32,
+        }
+    }
+
+    /// The cluster where the root directory begins.
+    pub fn root_cluster(&self) -> Cluster {
+        self.root_cluster
+    }
 }
 
 impl fmt::Debug for BiosParameterBlock {
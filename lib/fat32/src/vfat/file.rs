@@ -1,6 +1,7 @@
 use alloc::string::String;
 
 use shim::io::{self, SeekFrom};
+use shim::ioerr;
 
 use crate::traits;
 use crate::vfat::{Cluster, Metadata, VFatHandle};
@@ -8,10 +9,100 @@ use crate::vfat::{Cluster, Metadata, VFatHandle};
 #[derive(Debug)]
 pub struct File<HANDLE: VFatHandle> {
     pub vfat: HANDLE,
-    // FIXME: Fill me in.
+    pub(crate) first_cluster: Cluster,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    size: u32,
+    /// The offset of the next byte to read.
+    offset: u32,
+    /// The cluster holding the byte at `cluster_offset`, the start of the
+    /// cluster that was last read, so reading on from it need not walk the
+    /// chain from the start. `None` before the first read.
+    cluster: Option<Cluster>,
+    cluster_offset: u32,
 }
 
-// FIXME: Implement `traits::File` (and its supertraits) for `File`.
+impl<HANDLE: VFatHandle> File<HANDLE> {
+    pub(crate) fn new(
+        vfat: HANDLE,
+        first_cluster: Cluster,
+        name: String,
+        metadata: Metadata,
+        size: u32,
+    ) -> File<HANDLE> {
+        File {
+            vfat,
+            first_cluster,
+            name,
+            metadata,
+            size,
+            offset: 0,
+            cluster: None,
+            cluster_offset: 0,
+        }
+    }
+}
+
+impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
+    /// The file system is read-only, so there is never anything to write.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size as u64
+    }
+}
+
+impl<HANDLE: VFatHandle> io::Read for File<HANDLE> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = (self.size - self.offset) as usize;
+        let len = core::cmp::min(left, buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let (mut cluster, mut cluster_offset) = match self.cluster {
+            Some(cluster) if self.cluster_offset <= self.offset => (cluster, self.cluster_offset),
+            _ => (self.first_cluster, 0),
+        };
+
+        let offset = self.offset;
+        let read = self.vfat.lock(|vfat| -> io::Result<usize> {
+            let cluster_size = vfat.cluster_size() as u32;
+            let mut read = 0;
+            while read < len {
+                let position = offset + read as u32;
+                while position >= cluster_offset + cluster_size {
+                    cluster = match vfat.next_cluster(cluster)? {
+                        Some(next) => next,
+                        None => return ioerr!(UnexpectedEof, "cluster chain ended early"),
+                    };
+                    cluster_offset += cluster_size;
+                }
+
+                let start = (position - cluster_offset) as usize;
+                read += vfat.read_cluster(cluster, start, &mut buf[read..len])?;
+            }
+            Ok(read)
+        })?;
+
+        self.offset += read as u32;
+        self.cluster = Some(cluster);
+        self.cluster_offset = cluster_offset;
+        Ok(read)
+    }
+}
+
+impl<HANDLE: VFatHandle> io::Write for File<HANDLE> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "read-only file system")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
     /// Seek to offset `pos` in the file.
@@ -27,7 +118,18 @@ impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
     ///
     /// Seeking before the start of a file or beyond the end of the file results
     /// in an `InvalidInput` error.
-    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
-        unimplemented!("File::seek()")
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::End(delta) => self.size as i128 + delta as i128,
+            SeekFrom::Current(delta) => self.offset as i128 + delta as i128,
+        };
+
+        if offset < 0 || offset > self.size as i128 {
+            return ioerr!(InvalidInput, "seek outside of the file");
+        }
+
+        self.offset = offset as u32;
+        Ok(self.offset as u64)
     }
 }
@@ -1,7 +1,5 @@
 use core::fmt;
 
-use alloc::string::String;
-
 use crate::traits;
 
 /// A date as represented in FAT32 on-disk structures.
@@ -19,6 +17,39 @@ pub struct Time(u16);
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Attributes(u8);
 
+impl Attributes {
+    const READ_ONLY: u8 = 0x01;
+    const HIDDEN: u8 = 0x02;
+    const SYSTEM: u8 = 0x04;
+    const VOLUME_ID: u8 = 0x08;
+    const DIRECTORY: u8 = 0x10;
+
+    /// The attributes of a long file name entry: all of read only, hidden,
+    /// system and volume ID at once.
+    const LFN: u8 = Self::READ_ONLY | Self::HIDDEN | Self::SYSTEM | Self::VOLUME_ID;
+
+    /// Returns `true` if the entry may not be written to.
+    pub fn read_only(&self) -> bool {
+        self.0 & Self::READ_ONLY != 0
+    }
+
+    /// Returns `true` if the entry should be hidden from directory listings.
+    pub fn hidden(&self) -> bool {
+        self.0 & Self::HIDDEN != 0
+    }
+
+    /// Returns `true` if the entry is a directory.
+    pub fn directory(&self) -> bool {
+        self.0 & Self::DIRECTORY != 0
+    }
+
+    /// Returns `true` if the entry is part of a long file name rather than an
+    /// entry of its own.
+    pub fn long_file_name(&self) -> bool {
+        self.0 == Self::LFN
+    }
+}
+
 /// A structure containing a date and time.
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Timestamp {
@@ -29,11 +60,92 @@ pub struct Timestamp {
 /// Metadata for a directory entry.
 #[derive(Default, Debug, Clone)]
 pub struct Metadata {
-    // FIXME: Fill me in.
+    pub attributes: Attributes,
+    pub created: Timestamp,
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        1980 + (self.date.0 >> 9) as usize
+    }
+
+    fn month(&self) -> u8 {
+        ((self.date.0 >> 5) & 0xF) as u8
+    }
+
+    fn day(&self) -> u8 {
+        (self.date.0 & 0x1F) as u8
+    }
+
+    fn hour(&self) -> u8 {
+        (self.time.0 >> 11) as u8
+    }
+
+    fn minute(&self) -> u8 {
+        ((self.time.0 >> 5) & 0x3F) as u8
+    }
+
+    fn second(&self) -> u8 {
+        // FAT32 counts seconds in units of two.
+        ((self.time.0 & 0x1F) * 2) as u8
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes.read_only()
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes.hidden()
+    }
+
+    fn created(&self) -> Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Timestamp {
+        self.modified
+    }
 }
 
-// FIXME: Implement `traits::Timestamp` for `Timestamp`.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use crate::traits::Timestamp;
 
-// FIXME: Implement `traits::Metadata` for `Metadata`.
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year(),
+            self.month(),
+            self.day(),
+            self.hour(),
+            self.minute(),
+            self.second()
+        )
+    }
+}
 
-// FIXME: Implement `fmt::Display` (to your liking) for `Metadata`.
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{} created {} modified {} accessed {}",
+            flag(self.attributes.directory(), 'd'),
+            flag(self.attributes.read_only(), 'r'),
+            flag(self.attributes.hidden(), 'h'),
+            self.created,
+            self.modified,
+            self.accessed
+        )
+    }
+}
@@ -6,7 +6,6 @@ use alloc::vec::Vec;
 
 use shim::io;
 use shim::ioerr;
-use shim::newioerr;
 use shim::path;
 use shim::path::Path;
 
@@ -35,45 +34,174 @@ pub struct VFat<HANDLE: VFatHandle> {
 }
 
 impl<HANDLE: VFatHandle> VFat<HANDLE> {
+    /// Mounts the first FAT32 partition of `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Mbr` if the device's MBR is invalid, `NotFound` if none of its
+    /// partitions is a FAT32 one, `BadSignature` if the partition's EBPB is
+    /// invalid, or `Io` if reading from the device fails.
     pub fn from<T>(mut device: T) -> Result<HANDLE, Error>
     where
         T: BlockDevice + 'static,
     {
-        unimplemented!("VFat::from()")
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let partition = match mbr.partitions().iter().find(|p| p.is_fat32()) {
+            Some(partition) => partition,
+            None => return Err(Error::NotFound),
+        };
+
+        let ebpb = BiosParameterBlock::from(&mut device, partition.start())?;
+        let sector_size = ebpb.bytes_per_sector() as u64;
+        if sector_size < device.sector_size() || sector_size % device.sector_size() != 0 {
+            return Err(Error::BadSignature);
+        }
+
+        let device = CachedPartition::new(
+            device,
+            Partition {
+                start: partition.start(),
+                num_sectors: ebpb.num_sectors() as u64,
+                sector_size,
+            },
+        );
+
+        let fat_start_sector = ebpb.num_reserved_sectors() as u64;
+        let fat_sectors = ebpb.num_fats() as u64 * ebpb.sectors_per_fat() as u64;
+        Ok(HANDLE::new(VFat {
+            phantom: PhantomData,
+            device,
+            bytes_per_sector: ebpb.bytes_per_sector(),
+            sectors_per_cluster: ebpb.sectors_per_cluster(),
+            sectors_per_fat: ebpb.sectors_per_fat(),
+            fat_start_sector,
+            data_start_sector: fat_start_sector + fat_sectors,
+            rootdir_cluster: ebpb.root_cluster(),
+        }))
+    }
+
+    /// The cluster where the root directory begins.
+    pub(crate) fn root_cluster(&self) -> Cluster {
+        self.rootdir_cluster
+    }
+
+    /// The size, in bytes, of a cluster.
+    pub(crate) fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// Reads from `offset` bytes into `cluster` into `buf`, up to the end of
+    /// the cluster, and returns the number of bytes read.
+    pub(crate) fn read_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        if !cluster.is_data() {
+            return ioerr!(InvalidData, "cluster is not a data cluster");
+        }
+
+        let sector_size = self.bytes_per_sector as usize;
+        let len = core::cmp::min(buf.len(), self.cluster_size().saturating_sub(offset));
+        let first_sector =
+            self.data_start_sector + cluster.data_index() * self.sectors_per_cluster as u64;
+
+        let mut read = 0;
+        while read < len {
+            let position = offset + read;
+            let sector = first_sector + (position / sector_size) as u64;
+            let start = position % sector_size;
+            let data = self.device.get(sector)?;
+            let n = core::cmp::min(sector_size - start, len - read);
+            buf[read..read + n].copy_from_slice(&data[start..start + n]);
+            read += n;
+        }
+        Ok(read)
     }
 
-    // TODO: The following methods may be useful here:
-    //
-    //  * A method to read from an offset of a cluster into a buffer.
-    //
-    //    fn read_cluster(
-    //        &mut self,
-    //        cluster: Cluster,
-    //        offset: usize,
-    //        buf: &mut [u8]
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to read all of the clusters chained from a starting cluster
-    //    into a vector.
-    //
-    //    fn read_chain(
-    //        &mut self,
-    //        start: Cluster,
-    //        buf: &mut Vec<u8>
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to return a reference to a `FatEntry` for a cluster where the
-    //    reference points directly into a cached sector.
-    //
-    //    fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry>;
+    /// Appends every cluster of the chain that starts at `start` to `buf`, and
+    /// returns the number of bytes appended.
+    pub(crate) fn read_chain(&mut self, start: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let cluster_size = self.cluster_size();
+        let initial_len = buf.len();
+        let mut cluster = Some(start);
+        while let Some(current) = cluster {
+            // A chain longer than the FAT has entries must loop.
+            if buf.len() - initial_len >= self.max_chain_bytes() {
+                return ioerr!(InvalidData, "cluster chain loops");
+            }
+
+            let len = buf.len();
+            buf.resize(len + cluster_size, 0);
+            self.read_cluster(current, 0, &mut buf[len..])?;
+            cluster = self.next_cluster(current)?;
+        }
+        Ok(buf.len() - initial_len)
+    }
+
+    /// Returns the cluster after `cluster` in its chain, or `None` if it is the
+    /// last.
+    pub(crate) fn next_cluster(&mut self, cluster: Cluster) -> io::Result<Option<Cluster>> {
+        match self.fat_entry(cluster)?.status() {
+            Status::Data(next) => Ok(Some(next)),
+            Status::Eoc(_) => Ok(None),
+            _ => ioerr!(InvalidData, "cluster chain names an unused cluster"),
+        }
+    }
+
+    /// Returns the FAT entry of `cluster`, as it is in the cached sector.
+    fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry> {
+        let entries_per_sector = self.bytes_per_sector as u64 / size_of::<FatEntry>() as u64;
+        let index = cluster.number() as u64;
+        if index / entries_per_sector >= self.sectors_per_fat as u64 {
+            return ioerr!(InvalidData, "cluster is outside of the FAT");
+        }
+
+        let sector = self.fat_start_sector + index / entries_per_sector;
+        let entries: &[FatEntry] = unsafe { self.device.get(sector)?.cast() };
+        Ok(&entries[(index % entries_per_sector) as usize])
+    }
+
+    /// The most bytes a chain of distinct clusters can hold.
+    fn max_chain_bytes(&self) -> usize {
+        let entries =
+            self.sectors_per_fat as usize * self.bytes_per_sector as usize / size_of::<FatEntry>();
+        entries * self.cluster_size()
+    }
 }
 
 impl<'a, HANDLE: VFatHandle> FileSystem for &'a HANDLE {
-    type File = crate::traits::Dummy;
-    type Dir = crate::traits::Dummy;
-    type Entry = crate::traits::Dummy;
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Entry = Entry<HANDLE>;
 
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        unimplemented!("FileSystem::open()")
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return ioerr!(InvalidInput, "path is not absolute");
+        }
+
+        let mut entry = Entry::Dir(Dir::root(self.clone()));
+        for component in path.components() {
+            let name = match component {
+                path::Component::Normal(name) => name,
+                path::Component::ParentDir => "..".as_ref(),
+                _ => continue,
+            };
+
+            let dir = match entry {
+                Entry::Dir(dir) => dir,
+                Entry::File(_) => return ioerr!(InvalidInput, "not a directory"),
+            };
+
+            // The root has no `..` entry: it is its own parent.
+            entry = if component == path::Component::ParentDir && dir.is_root() {
+                Entry::Dir(dir)
+            } else {
+                dir.find(name)?
+            };
+        }
+        Ok(entry)
     }
 }
@@ -0,0 +1,507 @@
+//! The interface between the kernel and the programs it runs: the system call
+//! numbers, how their arguments and results are passed, and the errors they
+//! return.
+//!
+//! # Calling convention
+//!
+//! A system call is made with `svc #n`, where `n` is one of the `SYS_`
+//! numbers. Its arguments are passed in `x0` to `x5`, and its result is
+//! returned in `x0`, with a second one in `x1` for the calls that have one.
+//! Every other register is preserved.
+//!
+//! A call that fails returns the negated `OsError` code in `x0`, so a value
+//! from -4095 to -1 is an error and anything else a result: see `encode()`
+//! and `decode()`. A failed call has no other effect.
+//!
+//! # Stability
+//!
+//! Numbers, error codes and the meaning of each call's arguments and results
+//! never change once released. A call that needs a different interface gets
+//! a new number, and the old one keeps working.
+
+#![no_std]
+
+pub mod syscall;
+
+use core::fmt;
+
+/// Gives up the processor to the next process ready to run. Returns nothing.
+pub const SYS_YIELD: u16 = 0;
+
+/// Sleeps for at least the number of milliseconds in `x0`, or until a signal
+/// is sent to the caller. Returns the number that actually passed.
+pub const SYS_SLEEP: u16 = 1;
+
+/// Ends the calling process with the exit status in `x0`. Never returns.
+pub const SYS_EXIT: u16 = 2;
+
+/// Waits for the child whose ID is in `x0`, or any child if it is 0, to exit,
+/// with the `W` options in `x1`. Returns the child's ID, with its exit status
+/// in `x1`, or 0 if `WNOHANG` is given and no such child has exited yet.
+///
+/// Fails with `NoChild` if there is no such child.
+pub const SYS_WAIT: u16 = 3;
+
+/// Starts a copy of the calling process, sharing its open files. Returns the
+/// child's ID, and 0 in the child.
+///
+/// Fails with `NotPermitted` if the caller is a kernel thread, and `NoMemory`.
+pub const SYS_FORK: u16 = 4;
+
+/// Replaces the calling program with the ELF executable at the absolute path
+/// in the `x1` bytes at `x0`, given the `x3` arguments described at `x2`: the
+/// address and length of each, as two `u64`s. Open files stay open. Does not
+/// return if it succeeds.
+///
+/// Fails with `BadAddress` if the path or arguments are not readable,
+/// `InvalidArgument` if they are not UTF-8 or the path is relative, `TooBig`
+/// if the arguments are too long, `NoEntry` or `Io` if the file cannot be
+/// read, `BadExecutable` if it is not a program the kernel can run,
+/// `NotPermitted` if the caller is a kernel thread, and `NoMemory`.
+pub const SYS_EXEC: u16 = 5;
+
+/// Reads up to `x2` bytes from the file descriptor `x0` into the memory at
+/// `x1`. Returns the number read, which is 0 at the end of the file and may
+/// be fewer than asked for.
+///
+/// Fails with `BadDescriptor` if `x0` is not open, `BadAddress` if the memory
+/// is not writable, and with whatever error reading the file gives.
+pub const SYS_READ: u16 = 6;
+
+/// Writes the `x2` bytes at `x1` to the file descriptor `x0`. Returns the
+/// number written.
+///
+/// Fails with `BadDescriptor` if `x0` is not open, `BadAddress` if the memory
+/// is not readable, and with whatever error writing the file gives.
+pub const SYS_WRITE: u16 = 7;
+
+/// Closes the file descriptor `x0`. Returns nothing.
+///
+/// Fails with `BadDescriptor` if it is not open.
+pub const SYS_CLOSE: u16 = 8;
+
+/// Opens a pipe. Returns the file descriptor of its read end, with that of its
+/// write end in `x1`.
+///
+/// Reading from a pipe waits until there are bytes to read, and returns 0
+/// once every descriptor of its write end is closed. Writing to it waits
+/// until there is room, and fails with `BrokenPipe` once every descriptor of
+/// its read end is closed.
+///
+/// Fails with `TooManyFiles` if the caller cannot open two more files.
+pub const SYS_PIPE: u16 = 9;
+
+/// Makes the file descriptor `x1` refer to the file `x0` does, closing it
+/// first if it is open. Returns `x1`.
+///
+/// Fails with `BadDescriptor` if `x0` is not open or `x1` is too large.
+pub const SYS_DUP2: u16 = 10;
+
+/// Opens a channel, which carries messages of `MESSAGE_SIZE` bytes both ways
+/// between its two endpoints. Returns the file descriptor of one endpoint,
+/// with that of the other in `x1`.
+///
+/// Fails with `TooManyFiles` if the caller cannot open two more files.
+pub const SYS_CHANNEL: u16 = 11;
+
+/// Sends the `MESSAGE_SIZE` bytes at `x1` through the channel endpoint `x0`,
+/// with the file descriptor `x2` unless it is `NO_HANDLE`: the receiver gets
+/// a descriptor of its own for the same file. Waits while too many messages
+/// are waiting to be received. Returns nothing.
+///
+/// Fails with `BadDescriptor` if `x0` is not an open channel endpoint or `x2`
+/// is not open, `BadAddress` if the message is not readable, and
+/// `BrokenPipe` if the other endpoint is closed.
+pub const SYS_SEND: u16 = 12;
+
+/// Receives the next message from the channel endpoint `x0` into the
+/// `MESSAGE_SIZE` bytes at `x1`, waiting for one at most the number of
+/// milliseconds in `x2`, or for as long as it takes if it is `NO_TIMEOUT`.
+/// Returns 0, with the file descriptor of the file passed with the message in
+/// `x1`, or `NO_HANDLE` if there is none.
+///
+/// Fails with `BadDescriptor` if `x0` is not an open channel endpoint,
+/// `BadAddress` if the buffer is not writable, `TooManyFiles` if the caller
+/// cannot open another file, `BrokenPipe` if no message is waiting and the
+/// other endpoint is closed, and `TimedOut` if none arrives in time.
+pub const SYS_RECEIVE: u16 = 13;
+
+/// The size of every message sent through a channel.
+pub const MESSAGE_SIZE: usize = 64;
+
+/// The file descriptor given to `SYS_SEND`, and returned by `SYS_RECEIVE`,
+/// when no file is passed with a message.
+pub const NO_HANDLE: u64 = u64::MAX;
+
+/// The timeout given to `SYS_RECEIVE` and `SYS_FUTEX_WAIT` to wait for as
+/// long as it takes.
+pub const NO_TIMEOUT: u64 = u64::MAX;
+
+/// Sends the signal `x1` to the process `x0`. Returns nothing.
+///
+/// Fails with `InvalidArgument` if there is no such signal, `NoProcess` if
+/// there is no such process, and `NotPermitted` if it is a kernel thread.
+pub const SYS_KILL: u16 = 14;
+
+/// Sets what the calling process does when it receives the signal `x0`: its
+/// default action if `x1` is `SIG_DFL`, nothing if it is `SIG_IGN`, and
+/// otherwise call the handler at `x1` with the signal in `x0`, returning to
+/// the trampoline at `x2`, which must make the `SYS_SIGRETURN` system call
+/// without moving the stack pointer. Returns the previous `x1`.
+///
+/// Handlers are reset to the default action by `SYS_EXEC`; ignored signals
+/// stay ignored, and a child from `SYS_FORK` starts with its parent's
+/// actions.
+///
+/// Fails with `InvalidArgument` if there is no such signal or it is
+/// `SIGKILL`.
+pub const SYS_SIGACTION: u16 = 15;
+
+/// Returns from a signal handler to what the process was doing when the
+/// signal arrived. Made by the trampoline given to `SYS_SIGACTION`. Does not
+/// return; the process is killed if its stack has been overwritten.
+pub const SYS_SIGRETURN: u16 = 16;
+
+/// Sets the nice value of the process `x0`, or the caller if it is 0, to `x1`
+/// taken as a signed number, clamped to `NICE_MIN..=NICE_MAX`. Returns
+/// nothing.
+///
+/// Fails with `NoProcess` if there is no such process, and `NotPermitted` if
+/// it is a kernel thread or the call would lower its nice value: a program
+/// can only give up priority.
+pub const SYS_SETPRIORITY: u16 = 17;
+
+/// Returns 20 minus the nice value of the process `x0`, or the caller if it is
+/// 0: a number from 1 to 40, so that it cannot be taken for an error.
+///
+/// Fails with `NoProcess` if there is no such process.
+pub const SYS_GETPRIORITY: u16 = 18;
+
+/// Moves the program break, the end of the caller's heap, to `x0`, unless it
+/// is 0. Returns where the break is. The heap starts empty just past the
+/// program's last segment; its pages are zeroed when first touched, and those
+/// the heap no longer covers are freed.
+///
+/// Fails with `NotPermitted` if the caller is a kernel thread, and `NoMemory`
+/// if the break would be below the start of the heap or the heap would run
+/// into other memory, such as the stack.
+pub const SYS_BRK: u16 = 19;
+
+/// Sleeps for at least the number of nanoseconds in `x0`, or until a signal
+/// is sent to the caller. Returns the number that actually passed.
+///
+/// Sleepers are woken by the timer tick, so however short the sleep, it
+/// usually lasts until the next tick.
+pub const SYS_NANOSLEEP: u16 = 20;
+
+/// Sets the caller's alarm to send it `SIGALRM` once the number of
+/// nanoseconds in `x0` has passed, and then every `x1` nanoseconds unless it
+/// is 0. If `x0` is 0, the alarm is cancelled instead. Returns the number of
+/// nanoseconds the alarm it replaces had left to go, or 0 if there was none.
+///
+/// Alarms go off at the first timer tick after they are due. A child from
+/// `SYS_FORK` starts with no alarm; `SYS_EXEC` keeps it.
+///
+/// Fails with `NotPermitted` if the caller is a kernel thread.
+pub const SYS_ALARM: u16 = 21;
+
+/// Starts a thread of the calling program: a process sharing its address
+/// space and open files, which the caller is the parent of. The thread starts
+/// at the address in `x0`, with its stack pointer at `x1`, the argument in
+/// `x2` in its `x0`, its other registers zeroed, and its thread pointer,
+/// `TPIDR_EL0`, set to `x3`. Returns the thread's ID.
+///
+/// A thread ends with `SYS_EXIT`, which leaves the rest of the program
+/// running, and is waited for with `SYS_WAIT`. `SYS_EXEC` in any thread ends
+/// the others.
+///
+/// Fails with `NotPermitted` if the caller is a kernel thread, and `NoMemory`.
+pub const SYS_CLONE: u16 = 22;
+
+/// Waits while the `u32` at `x0`, which must be aligned, holds the value in
+/// `x1`, until `SYS_FUTEX_WAKE` is called for it, for at most `x2`
+/// milliseconds unless it is `NO_TIMEOUT`.
+///
+/// Fails with `WouldBlock` if the value differs to begin with, `Interrupted`
+/// if a signal is sent to the caller, `TimedOut` if the time runs out,
+/// `InvalidArgument` if the address is unaligned, and `BadAddress` if it is
+/// not writable.
+pub const SYS_FUTEX_WAIT: u16 = 23;
+
+/// Wakes the threads waiting in `SYS_FUTEX_WAIT` on the `u32` at `x0`, which
+/// should be changed first, so that they see it has.
+///
+/// Fails with `InvalidArgument` if the address is unaligned, and `BadAddress`
+/// if it is not writable.
+pub const SYS_FUTEX_WAKE: u16 = 24;
+
+/// Returns the caller's process ID, with its parent's in `x1`, or 0 if it has
+/// none. A thread's parent is the thread that started it.
+pub const SYS_GETPID: u16 = 25;
+
+/// Returns the number of nanoseconds since the system timer started counting
+/// at boot. It never goes backwards, and is the same on every core.
+pub const SYS_GETTIME: u16 = 26;
+
+/// Fills up to `x1` bytes at `x0` with random bytes from the hardware random
+/// number generator. Returns the number filled, which may be fewer than asked
+/// for.
+///
+/// Fails with `BadAddress` if the memory is not writable.
+pub const SYS_GETRANDOM: u16 = 27;
+
+/// Returns the caller's limit on the resource `x0`, one of the `RLIMIT_`
+/// values, which is `RLIM_INFINITY` if there is none.
+///
+/// Fails with `InvalidArgument` if there is no such resource, and
+/// `NotPermitted` if the caller is a kernel thread.
+pub const SYS_GETRLIMIT: u16 = 28;
+
+/// Lowers the caller's limit on the resource `x0`, one of the `RLIMIT_`
+/// values, to `x1`. Returns nothing. A process that goes over a limit is
+/// killed, with a message on the console, or for `RLIMIT_NOFILE`, fails to
+/// open more files.
+///
+/// A child from `SYS_FORK` or thread from `SYS_CLONE` starts with the
+/// caller's limits, and `SYS_EXEC` keeps them. The memory and file limits
+/// are shared by the threads of a program, but each thread has a CPU time
+/// limit of its own.
+///
+/// Fails with `InvalidArgument` if there is no such resource, and
+/// `NotPermitted` if `x1` is above the limit or the caller is a kernel
+/// thread.
+pub const SYS_SETRLIMIT: u16 = 29;
+
+/// The resource limiting the bytes of memory a program may have mapped, in
+/// whole pages, including pages it shares with its parent or children. It
+/// starts at 256 MiB.
+pub const RLIMIT_AS: u64 = 0;
+
+/// The resource limiting the file descriptors a process may use: each must be
+/// below the limit. It starts at the most the kernel allows.
+pub const RLIMIT_NOFILE: u64 = 1;
+
+/// The resource limiting the nanoseconds of CPU time a process may use. It
+/// starts unlimited.
+pub const RLIMIT_CPU: u64 = 2;
+
+/// The limit on a resource that is unlimited.
+pub const RLIM_INFINITY: u64 = i64::MAX as u64;
+
+/// Opens the file at the absolute path of `x1` bytes at `x0`, for reading and
+/// writing, with the `OPEN_` options `x2`. Returns the file descriptor it is
+/// open as, the lowest one not in use. Devices, such as `/dev/null`, are
+/// opened as files are.
+///
+/// Fails with `BadAddress` if the path is not readable, `InvalidArgument` if
+/// it is not UTF-8 or is relative or the options are unknown, `NoEntry` if
+/// there is no such file, `Io` if it is a directory or cannot be created,
+/// and `TooManyFiles` if the caller cannot open another file.
+pub const SYS_OPEN: u16 = 30;
+
+/// With this option, `SYS_OPEN` creates the file if there is none, and
+/// empties it if there is.
+pub const OPEN_CREATE: u64 = 1;
+
+/// With this option, `SYS_OPEN` opens the file with its offset at the end.
+pub const OPEN_APPEND: u64 = 2;
+
+/// The lowest nice value, which has the highest priority.
+///
+/// A process ready to run is chosen over those with higher nice values. Every
+/// process starts with its parent's, 0 for the first.
+pub const NICE_MIN: i32 = -20;
+
+/// The highest nice value, which has the lowest priority.
+pub const NICE_MAX: i32 = 19;
+
+/// Sent to the program running in the foreground when `Ctrl-C` is typed at
+/// the console.
+pub const SIGINT: u32 = 2;
+
+/// Ends the process: it cannot be handled or ignored.
+pub const SIGKILL: u32 = 9;
+
+/// For programs to use as they see fit.
+pub const SIGUSR1: u32 = 10;
+
+/// For programs to use as they see fit.
+pub const SIGUSR2: u32 = 12;
+
+/// Sent by the caller's alarm: see `SYS_ALARM`.
+pub const SIGALRM: u32 = 14;
+
+/// Asks the process to end.
+pub const SIGTERM: u32 = 15;
+
+/// The number of signals: they are numbered from 1 to `NSIG - 1`.
+///
+/// The default action of every signal is to end the process, with the exit
+/// status 128 plus the signal.
+pub const NSIG: u32 = 32;
+
+/// The `SYS_SIGACTION` handler for a signal's default action.
+pub const SIG_DFL: u64 = 0;
+
+/// The `SYS_SIGACTION` handler for a signal to be ignored.
+pub const SIG_IGN: u64 = 1;
+
+/// The file descriptor every user program starts with open for reading its
+/// input: the console, unless its parent arranged otherwise.
+pub const STDIN: u64 = 0;
+
+/// The file descriptor every user program starts with open for writing its
+/// output.
+pub const STDOUT: u64 = 1;
+
+/// The file descriptor every user program starts with open for writing its
+/// error messages.
+pub const STDERR: u64 = 2;
+
+/// With this option, `SYS_WAIT` returns 0 rather than block if no child has
+/// exited yet.
+pub const WNOHANG: u64 = 1;
+
+/// Why a system call failed. The values are the codes it returns, negated.
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OsError {
+    /// The caller may not do this.
+    NotPermitted = 1,
+    /// There is no such file or directory.
+    NoEntry = 2,
+    /// There is no such process.
+    NoProcess = 3,
+    /// The call was interrupted before it could finish.
+    Interrupted = 4,
+    /// A device failed.
+    Io = 5,
+    /// The arguments are too long.
+    TooBig = 7,
+    /// The file is not a program the kernel can run.
+    BadExecutable = 8,
+    /// The file descriptor is not open, or not for this kind of access.
+    BadDescriptor = 9,
+    /// The caller has no such child.
+    NoChild = 10,
+    /// The call would have to block, and was asked not to.
+    WouldBlock = 11,
+    /// There is not enough memory.
+    NoMemory = 12,
+    /// An argument points to memory the caller cannot access.
+    BadAddress = 14,
+    /// An argument is invalid.
+    InvalidArgument = 22,
+    /// The caller has too many files open.
+    TooManyFiles = 24,
+    /// The other end of the pipe has been closed.
+    BrokenPipe = 32,
+    /// There is no system call with this number.
+    NotImplemented = 38,
+    /// The call waited as long as it was allowed to.
+    TimedOut = 110,
+    /// The kernel returned an error code this version does not know.
+    Unknown = 4095,
+}
+
+/// The result of a system call.
+pub type OsResult<T> = Result<T, OsError>;
+
+impl OsError {
+    /// Returns the error with the code `code`, or `Unknown` if there is none.
+    pub fn from_code(code: u16) -> OsError {
+        use OsError::*;
+        [
+            NotPermitted,
+            NoEntry,
+            NoProcess,
+            Interrupted,
+            Io,
+            TooBig,
+            BadExecutable,
+            BadDescriptor,
+            NoChild,
+            WouldBlock,
+            NoMemory,
+            BadAddress,
+            InvalidArgument,
+            TooManyFiles,
+            BrokenPipe,
+            NotImplemented,
+            TimedOut,
+        ]
+        .iter()
+        .cloned()
+        .find(|e| *e as u16 == code)
+        .unwrap_or(Unknown)
+    }
+}
+
+impl fmt::Display for OsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match *self {
+            OsError::NotPermitted => "operation not permitted",
+            OsError::NoEntry => "no such file or directory",
+            OsError::NoProcess => "no such process",
+            OsError::Interrupted => "interrupted",
+            OsError::Io => "I/O error",
+            OsError::TooBig => "argument list too long",
+            OsError::BadExecutable => "not an executable",
+            OsError::BadDescriptor => "bad file descriptor",
+            OsError::NoChild => "no child processes",
+            OsError::WouldBlock => "operation would block",
+            OsError::NoMemory => "out of memory",
+            OsError::BadAddress => "bad address",
+            OsError::InvalidArgument => "invalid argument",
+            OsError::TooManyFiles => "too many open files",
+            OsError::BrokenPipe => "broken pipe",
+            OsError::NotImplemented => "function not implemented",
+            OsError::TimedOut => "timed out",
+            OsError::Unknown => "unknown error",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// The highest error code: `x0` values from `-MAX_ERROR` to -1 are errors.
+const MAX_ERROR: u64 = 4095;
+
+/// Returns the value of `x0` that reports `result`. Results from
+/// `-MAX_ERROR` up, as `i64`s, cannot be returned.
+pub fn encode(result: OsResult<u64>) -> u64 {
+    match result {
+        Ok(value) => {
+            debug_assert!(decode(value).is_ok(), "result looks like an error");
+            value
+        }
+        Err(e) => (e as u64).wrapping_neg(),
+    }
+}
+
+/// Returns the result a system call reported with the value `x0`.
+pub fn decode(x0: u64) -> OsResult<u64> {
+    if x0.wrapping_neg() <= MAX_ERROR && x0 != 0 {
+        Err(OsError::from_code(x0.wrapping_neg() as u16))
+    } else {
+        Ok(x0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, OsError};
+
+    #[test]
+    fn results_and_errors() {
+        for &value in &[0, 1, 4096, u64::MAX - 4095] {
+            assert_eq!(decode(encode(Ok(value))), Ok(value));
+        }
+        for &e in &[OsError::NotPermitted, OsError::NoChild, OsError::Unknown] {
+            assert_eq!(decode(encode(Err(e))), Err(e));
+        }
+
+        assert_eq!(encode(Err(OsError::NoMemory)), -12i64 as u64);
+        assert_eq!(decode(-1i64 as u64), Err(OsError::NotPermitted));
+        assert_eq!(decode(-6i64 as u64), Err(OsError::Unknown));
+    }
+}
@@ -0,0 +1,375 @@
+//! The system calls, as functions a user program can call. See the crate
+//! documentation for the calling convention.
+
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+
+use crate::{decode, OsError, OsResult, MESSAGE_SIZE, NO_HANDLE, NO_TIMEOUT, SIG_DFL, SIG_IGN};
+
+/// The most arguments `exec()` can pass.
+pub const MAX_EXEC_ARGS: usize = 64;
+
+/// Makes the system call numbered `$num` with the arguments `$x0` to `$x3`,
+/// returning `x0` and `x1` as they are afterwards.
+#[cfg(target_arch = "aarch64")]
+macro_rules! svc {
+    ($num:literal, $x0:expr, $x1:expr, $x2:expr, $x3:expr) => {{
+        let (x0, x1): (u64, u64);
+        unsafe {
+            core::arch::asm!(
+                concat!("svc #", $num),
+                inout("x0") $x0 => x0,
+                inout("x1") $x1 => x1,
+                in("x2") $x2,
+                in("x3") $x3,
+                options(nostack)
+            );
+        }
+        (x0, x1)
+    }};
+}
+
+/// Elsewhere, as in tests run on the host, every system call fails with
+/// `NotImplemented`.
+#[cfg(not(target_arch = "aarch64"))]
+macro_rules! svc {
+    ($num:literal, $x0:expr, $x1:expr, $x2:expr, $x3:expr) => {{
+        let _ = ($x0, $x1, $x2, $x3);
+        (crate::encode(Err(OsError::NotImplemented)), 0u64)
+    }};
+}
+
+// The trampoline signal handlers return to. It must not touch the stack,
+// where the kernel saved the state the signal interrupted.
+#[cfg(target_arch = "aarch64")]
+core::arch::global_asm!(
+    ".global __kernel_api_sigreturn",
+    "__kernel_api_sigreturn:",
+    "svc #16",
+);
+
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    fn __kernel_api_sigreturn();
+}
+
+/// Returns `duration` in nanoseconds, or `u64::MAX` if it is longer.
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+/// Returns the address of the trampoline signal handlers return to.
+fn trampoline() -> u64 {
+    #[cfg(target_arch = "aarch64")]
+    let address = __kernel_api_sigreturn as usize as u64;
+    #[cfg(not(target_arch = "aarch64"))]
+    let address = 0;
+    address
+}
+
+/// What a process does when it receives a signal.
+#[derive(Copy, Clone)]
+pub enum SignalAction {
+    /// The signal's default action: ending the process.
+    Default,
+    /// Nothing.
+    Ignore,
+    /// Call the function with the signal, then carry on.
+    Handle(extern "C" fn(u32)),
+}
+
+/// Gives up the processor to the next process ready to run, if any. Makes
+/// the `SYS_YIELD` system call.
+pub fn yield_now() {
+    svc!(0, 0u64, 0u64, 0u64, 0u64);
+}
+
+/// Sleeps for at least `duration`, to the nearest millisecond, returning how
+/// long it slept. Makes the `SYS_SLEEP` system call.
+pub fn sleep(duration: Duration) -> Duration {
+    let (ms, _) = svc!(1, duration.as_millis() as u64, 0u64, 0u64, 0u64);
+    Duration::from_millis(ms)
+}
+
+/// Sleeps for at least `duration`, unless a signal is sent to the caller
+/// first, returning how long it slept. Makes the `SYS_NANOSLEEP` system
+/// call.
+pub fn nanosleep(duration: Duration) -> Duration {
+    let (ns, _) = svc!(20, nanos(duration), 0u64, 0u64, 0u64);
+    Duration::from_nanos(ns)
+}
+
+/// Makes `SIGALRM` be sent to the caller once `after` has passed, then every
+/// `interval` if there is one, replacing any alarm already set, or cancels
+/// the alarm if `after` is zero. Returns how long the alarm it replaces had
+/// left to go. Makes the `SYS_ALARM` system call.
+pub fn alarm(after: Duration, interval: Option<Duration>) -> OsResult<Duration> {
+    let interval = interval.map_or(0, nanos);
+    let (x0, _) = svc!(21, nanos(after), interval, 0u64, 0u64);
+    decode(x0).map(Duration::from_nanos)
+}
+
+/// Ends the calling process with the exit status `status`. Makes the
+/// `SYS_EXIT` system call.
+pub fn exit(status: i32) -> ! {
+    svc!(2, status as u64, 0u64, 0u64, 0u64);
+    unreachable!("exit returned")
+}
+
+/// Waits for the child `pid`, or any child if it is `None`, to exit, returning
+/// its ID and exit status, or `None` if `options` has `WNOHANG` and it has
+/// not exited yet. Makes the `SYS_WAIT` system call.
+pub fn waitpid(pid: Option<u64>, options: u64) -> OsResult<Option<(u64, i32)>> {
+    let (id, status) = svc!(3, pid.unwrap_or(0), options, 0u64, 0u64);
+    match decode(id)? {
+        0 => Ok(None),
+        id => Ok(Some((id, status as i32))),
+    }
+}
+
+/// Waits for any child to exit, returning its ID and exit status.
+pub fn wait() -> OsResult<(u64, i32)> {
+    waitpid(None, 0).map(|child| child.expect("waited without WNOHANG"))
+}
+
+/// Starts a thread of the calling program that calls `entry` with `arg` on
+/// the stack whose top is `stack`, with its thread pointer set to `tls`,
+/// returning the thread's ID. Makes the `SYS_CLONE` system call.
+///
+/// # Safety
+///
+/// `stack` must be the 16-byte aligned top of memory that nothing else uses
+/// until the thread exits, big enough for what `entry` does.
+pub unsafe fn clone(
+    entry: extern "C" fn(u64) -> !,
+    stack: usize,
+    arg: u64,
+    tls: u64,
+) -> OsResult<u64> {
+    let (id, _) = svc!(22, entry as usize as u64, stack as u64, arg, tls);
+    decode(id)
+}
+
+/// Waits while `word` holds `expected`, until `futex_wake()` is called for
+/// it, for at most `timeout` unless it is `None`. Makes the
+/// `SYS_FUTEX_WAIT` system call.
+pub fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> OsResult<()> {
+    let va = word as *const AtomicU32 as u64;
+    let timeout = timeout.map_or(NO_TIMEOUT, |t| t.as_millis() as u64);
+    let (x0, _) = svc!(23, va, expected as u64, timeout, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Wakes the threads waiting on `word` in `futex_wait()`. Makes the
+/// `SYS_FUTEX_WAKE` system call.
+pub fn futex_wake(word: &AtomicU32) -> OsResult<()> {
+    let va = word as *const AtomicU32 as u64;
+    let (x0, _) = svc!(24, va, 0u64, 0u64, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Returns the calling process's ID. Makes the `SYS_GETPID` system call.
+pub fn getpid() -> u64 {
+    let (id, _) = svc!(25, 0u64, 0u64, 0u64, 0u64);
+    id
+}
+
+/// Returns the ID of the calling process's parent, if it has one. Makes the
+/// `SYS_GETPID` system call.
+pub fn getppid() -> Option<u64> {
+    let (_, parent) = svc!(25, 0u64, 0u64, 0u64, 0u64);
+    Some(parent).filter(|&parent| parent != 0)
+}
+
+/// Returns the time since the system timer started counting at boot, which
+/// never goes backwards. Makes the `SYS_GETTIME` system call.
+pub fn gettime() -> Duration {
+    let (ns, _) = svc!(26, 0u64, 0u64, 0u64, 0u64);
+    Duration::from_nanos(ns)
+}
+
+/// Fills `buf` with random bytes from the hardware random number generator.
+/// Makes the `SYS_GETRANDOM` system call, as many times as that takes.
+pub fn getrandom(mut buf: &mut [u8]) -> OsResult<()> {
+    while !buf.is_empty() {
+        let (x0, _) = svc!(27, buf.as_mut_ptr() as u64, buf.len() as u64, 0u64, 0u64);
+        let filled = decode(x0)? as usize;
+        buf = &mut buf[filled..];
+    }
+    Ok(())
+}
+
+/// Returns the caller's limit on `resource`, one of the `RLIMIT_` values, or
+/// `RLIM_INFINITY` if there is none. Makes the `SYS_GETRLIMIT` system call.
+pub fn getrlimit(resource: u64) -> OsResult<u64> {
+    let (x0, _) = svc!(28, resource, 0u64, 0u64, 0u64);
+    decode(x0)
+}
+
+/// Lowers the caller's limit on `resource`, one of the `RLIMIT_` values, to
+/// `limit`. Makes the `SYS_SETRLIMIT` system call.
+pub fn setrlimit(resource: u64, limit: u64) -> OsResult<()> {
+    let (x0, _) = svc!(29, resource, limit, 0u64, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Starts a copy of the calling process, returning the child's ID, or 0 in
+/// the child. Makes the `SYS_FORK` system call.
+pub fn fork() -> OsResult<u64> {
+    let (id, _) = svc!(4, 0u64, 0u64, 0u64, 0u64);
+    decode(id)
+}
+
+/// Replaces the calling program with the one at the absolute path `path`,
+/// given the arguments `args`, of which there can be at most
+/// `MAX_EXEC_ARGS`. Only returns if that fails. Makes the `SYS_EXEC` system
+/// call.
+pub fn exec(path: &str, args: &[&str]) -> OsError {
+    if args.len() > MAX_EXEC_ARGS {
+        return OsError::TooBig;
+    }
+
+    let mut described = [[0u64; 2]; MAX_EXEC_ARGS];
+    for (arg, description) in args.iter().zip(described.iter_mut()) {
+        *description = [arg.as_ptr() as u64, arg.len() as u64];
+    }
+
+    let (x0, _) = svc!(
+        5,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        described.as_ptr() as u64,
+        args.len() as u64
+    );
+    match decode(x0) {
+        Err(e) => e,
+        Ok(_) => OsError::Unknown,
+    }
+}
+
+/// Opens the file at the absolute path `path`, with the `OPEN_` options
+/// `options`, returning its file descriptor. Makes the `SYS_OPEN` system
+/// call.
+pub fn open(path: &str, options: u64) -> OsResult<u64> {
+    let (fd, _) = svc!(30, path.as_ptr() as u64, path.len() as u64, options, 0u64);
+    decode(fd)
+}
+
+/// Reads from the file descriptor `fd` into `buf`, returning the number of
+/// bytes read: 0 at the end of the file. Makes the `SYS_READ` system call.
+pub fn read(fd: u64, buf: &mut [u8]) -> OsResult<usize> {
+    let (n, _) = svc!(6, fd, buf.as_mut_ptr() as u64, buf.len() as u64, 0u64);
+    decode(n).map(|n| n as usize)
+}
+
+/// Writes `buf` to the file descriptor `fd`, returning the number of bytes
+/// written. Makes the `SYS_WRITE` system call.
+pub fn write(fd: u64, buf: &[u8]) -> OsResult<usize> {
+    let (n, _) = svc!(7, fd, buf.as_ptr() as u64, buf.len() as u64, 0u64);
+    decode(n).map(|n| n as usize)
+}
+
+/// Closes the file descriptor `fd`. Makes the `SYS_CLOSE` system call.
+pub fn close(fd: u64) -> OsResult<()> {
+    let (x0, _) = svc!(8, fd, 0u64, 0u64, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Opens a pipe, returning the file descriptors of its read and write ends.
+/// Makes the `SYS_PIPE` system call.
+pub fn pipe() -> OsResult<(u64, u64)> {
+    let (reader, writer) = svc!(9, 0u64, 0u64, 0u64, 0u64);
+    decode(reader).map(|reader| (reader, writer))
+}
+
+/// Makes the file descriptor `new` refer to the file `old` does, closing it
+/// first if it is open. Makes the `SYS_DUP2` system call.
+pub fn dup2(old: u64, new: u64) -> OsResult<u64> {
+    let (fd, _) = svc!(10, old, new, 0u64, 0u64);
+    decode(fd)
+}
+
+/// Opens a channel, returning the file descriptors of its two endpoints.
+/// Makes the `SYS_CHANNEL` system call.
+pub fn channel() -> OsResult<(u64, u64)> {
+    let (a, b) = svc!(11, 0u64, 0u64, 0u64, 0u64);
+    decode(a).map(|a| (a, b))
+}
+
+/// Sends `message` through the channel endpoint `fd`, passing the file
+/// descriptor `handle` along with it if there is one. Makes the `SYS_SEND`
+/// system call.
+pub fn send(fd: u64, message: &[u8; MESSAGE_SIZE], handle: Option<u64>) -> OsResult<()> {
+    let handle = handle.unwrap_or(NO_HANDLE);
+    let (x0, _) = svc!(12, fd, message.as_ptr() as u64, handle, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Receives the next message from the channel endpoint `fd` into `message`,
+/// waiting at most `timeout` for one, or for as long as it takes if it is
+/// `None`. Returns the file descriptor of the file passed with it, if any.
+/// Makes the `SYS_RECEIVE` system call.
+pub fn receive(
+    fd: u64,
+    message: &mut [u8; MESSAGE_SIZE],
+    timeout: Option<Duration>,
+) -> OsResult<Option<u64>> {
+    let timeout = timeout.map_or(NO_TIMEOUT, |t| t.as_millis() as u64);
+    let (x0, handle) = svc!(13, fd, message.as_mut_ptr() as u64, timeout, 0u64);
+    decode(x0).map(|_| Some(handle).filter(|&handle| handle != NO_HANDLE))
+}
+
+/// Sends the signal `signal` to the process `pid`. Makes the `SYS_KILL`
+/// system call.
+pub fn kill(pid: u64, signal: u32) -> OsResult<()> {
+    let (x0, _) = svc!(14, pid, signal as u64, 0u64, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Sets what the calling process does when it receives `signal`. Makes the
+/// `SYS_SIGACTION` system call.
+pub fn signal(signal: u32, action: SignalAction) -> OsResult<()> {
+    let handler = match action {
+        SignalAction::Default => SIG_DFL,
+        SignalAction::Ignore => SIG_IGN,
+        SignalAction::Handle(f) => f as usize as u64,
+    };
+    let (x0, _) = svc!(15, signal as u64, handler, trampoline(), 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Sets the nice value of the process `pid`, or the caller if it is `None`,
+/// to `nice`. Makes the `SYS_SETPRIORITY` system call.
+pub fn setpriority(pid: Option<u64>, nice: i32) -> OsResult<()> {
+    let (x0, _) = svc!(17, pid.unwrap_or(0), nice as i64 as u64, 0u64, 0u64);
+    decode(x0).map(|_| ())
+}
+
+/// Returns the nice value of the process `pid`, or the caller if it is
+/// `None`. Makes the `SYS_GETPRIORITY` system call.
+pub fn getpriority(pid: Option<u64>) -> OsResult<i32> {
+    let (x0, _) = svc!(18, pid.unwrap_or(0), 0u64, 0u64, 0u64);
+    decode(x0).map(|priority| 20 - priority as i32)
+}
+
+/// Moves the program break, the end of the caller's heap, to `brk`, or leaves
+/// it if `brk` is 0, returning where it is. Makes the `SYS_BRK` system call.
+pub fn brk(brk: usize) -> OsResult<usize> {
+    let (x0, _) = svc!(19, brk as u64, 0u64, 0u64, 0u64);
+    decode(x0).map(|brk| brk as usize)
+}
+
+/// Moves the program break by `increment` bytes, returning where it was: the
+/// start of the memory added to the heap if `increment` is positive. Makes
+/// the `SYS_BRK` system call.
+pub fn sbrk(increment: isize) -> OsResult<usize> {
+    let (x0, _) = svc!(19, 0u64, 0u64, 0u64, 0u64);
+    let old = decode(x0)? as usize;
+    if increment != 0 {
+        match (old as isize).checked_add(increment) {
+            Some(new) if new > 0 => brk(new as usize)?,
+            _ => return Err(OsError::NoMemory),
+        };
+    }
+    Ok(old)
+}
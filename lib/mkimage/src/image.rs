@@ -0,0 +1,541 @@
+//! Writes a disk image: an MBR partition table with one FAT32 partition,
+//! formatted and filled with a tree of files.
+//!
+//! The whole tree is known up front, so files and directories are laid out
+//! one after another, each in a run of consecutive clusters.
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::tree::{Kind, Node};
+
+/// The size of a sector, in bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// Where the partition starts: 1 MiB in, as partitioning tools align it.
+const PARTITION_START: u64 = 2048;
+
+/// The partition type of FAT32 partitions addressed by LBA.
+const FAT32_LBA_PART_TYPE: u8 = 0x0C;
+
+/// The layout of the volume's reserved sectors, and where the root directory
+/// starts.
+const RESERVED_SECTORS: u64 = 32;
+const FS_INFO_SECTOR: u64 = 1;
+const BACKUP_BOOT_SECTOR: u64 = 6;
+const NUM_FATS: u64 = 2;
+const ROOT_CLUSTER: u32 = 2;
+
+/// The fewest clusters a FAT32 volume may have: drivers take a volume with
+/// fewer to be FAT16.
+const MIN_CLUSTERS: u64 = 65525;
+
+/// The FAT entries for the media descriptor and for the end of a chain.
+const MEDIA_ENTRY: u32 = 0x0FFF_FFF8;
+const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+
+/// Directory entry attributes.
+const DIRECTORY: u8 = 0x10;
+const ARCHIVE: u8 = 0x20;
+const LFN: u8 = 0x0F;
+
+/// The size of a directory entry, the number of UCS-2 characters of a long
+/// file name in each of its entries, and the flag marking the last of them.
+const ENTRY_SIZE: usize = 32;
+const LFN_CHARS_PER_ENTRY: usize = 13;
+const LAST_LFN_ENTRY: u8 = 0x40;
+
+/// The punctuation allowed in short names, besides letters and digits.
+const SHORT_NAME_PUNCTUATION: &[u8] = b"!#$%&'()-@^_`{}~";
+
+const VOLUME_LABEL: &[u8; 11] = b"RUSTOS     ";
+
+/// How much of an image's file system is in use.
+#[derive(Debug, Copy, Clone)]
+pub struct Usage {
+    /// The bytes in clusters holding files and directories.
+    pub used: u64,
+    /// The bytes in all clusters.
+    pub capacity: u64,
+}
+
+/// The sizes of a FAT32 volume's parts, in sectors.
+struct Geometry {
+    sectors: u64,
+    sectors_per_cluster: u64,
+    sectors_per_fat: u64,
+    clusters: u64,
+}
+
+impl Geometry {
+    /// Returns the layout of a volume of `sectors` sectors, or an error of
+    /// `InvalidInput` if that is too small for FAT32.
+    fn new(sectors: u64) -> io::Result<Geometry> {
+        // The cluster sizes Microsoft's formatter picks for a volume's size.
+        let sectors_per_cluster = match (sectors * SECTOR_SIZE) >> 20 {
+            0..=260 => 1,
+            261..=8192 => 8,
+            8193..=16384 => 16,
+            16385..=32768 => 32,
+            _ => 64,
+        };
+
+        // The FATs take space from the clusters they cover, so grow them
+        // until they just cover what is left.
+        let too_small = || {
+            let message = "image too small for a FAT32 volume";
+            io::Error::new(io::ErrorKind::InvalidInput, message)
+        };
+        let mut sectors_per_fat = 1;
+        let clusters = loop {
+            let data_sectors = sectors
+                .checked_sub(RESERVED_SECTORS + NUM_FATS * sectors_per_fat)
+                .ok_or_else(too_small)?;
+            let clusters = data_sectors / sectors_per_cluster;
+            let needed = ((clusters + 2) * 4 + SECTOR_SIZE - 1) / SECTOR_SIZE;
+            if needed <= sectors_per_fat {
+                break clusters;
+            }
+            sectors_per_fat = needed;
+        };
+
+        if clusters < MIN_CLUSTERS {
+            return Err(too_small());
+        }
+
+        Ok(Geometry {
+            sectors,
+            sectors_per_cluster,
+            sectors_per_fat,
+            clusters,
+        })
+    }
+
+    /// The size of a cluster, in bytes.
+    fn cluster_size(&self) -> u64 {
+        self.sectors_per_cluster * SECTOR_SIZE
+    }
+
+    /// The sector where cluster `cluster` starts.
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        let data_start = RESERVED_SECTORS + NUM_FATS * self.sectors_per_fat;
+        data_start + (cluster as u64 - 2) * self.sectors_per_cluster
+    }
+}
+
+/// An image being written.
+struct Image {
+    file: File,
+    geometry: Geometry,
+    /// The FAT so far. Clusters are handed out in order, so the next free
+    /// one is always its length.
+    fat: Vec<u32>,
+}
+
+impl Image {
+    /// Writes `data` to the partition starting at its sector `sector`.
+    fn write_at(&mut self, sector: u64, data: &[u8]) -> io::Result<()> {
+        let offset = (PARTITION_START + sector) * SECTOR_SIZE;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)
+    }
+
+    /// Allocates a chain of clusters holding `len` bytes and returns its
+    /// first cluster, or 0 if `len` is 0.
+    fn allocate(&mut self, len: u64) -> io::Result<u32> {
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let cluster_size = self.geometry.cluster_size();
+        let count = (len + cluster_size - 1) / cluster_size;
+        let first = self.fat.len() as u64;
+        if first + count > self.geometry.clusters + 2 {
+            let message = "files do not fit in the image";
+            return Err(io::Error::new(io::ErrorKind::Other, message));
+        }
+
+        self.fat
+            .extend((first + 1..first + count).map(|next| next as u32));
+        self.fat.push(END_OF_CHAIN);
+        Ok(first as u32)
+    }
+
+    /// Writes the directory holding `children` to the chain starting at
+    /// `cluster`, and everything in it to chains of their own. `parent` is
+    /// the cluster of the directory's parent, or `None` for the root
+    /// directory.
+    fn write_dir(
+        &mut self,
+        children: &[Node],
+        cluster: u32,
+        parent: Option<u32>,
+        modified: SystemTime,
+    ) -> io::Result<()> {
+        let mut entries = Vec::with_capacity(dir_len(children, parent.is_some()) as usize);
+        if let Some(parent) = parent {
+            let timestamp = fat_timestamp(modified);
+            push_entry(
+                &mut entries,
+                b".          ",
+                DIRECTORY,
+                cluster,
+                0,
+                timestamp,
+            );
+            push_entry(
+                &mut entries,
+                b"..         ",
+                DIRECTORY,
+                parent,
+                0,
+                timestamp,
+            );
+        }
+
+        // Directories in the root name cluster 0 as their parent.
+        let this = if parent.is_some() { cluster } else { 0 };
+        let mut short_names = Vec::with_capacity(children.len());
+        for node in children {
+            let (first, attributes, size) = match &node.kind {
+                Kind::File(data) => {
+                    let first = self.allocate(data.len() as u64)?;
+                    if first != 0 {
+                        let sector = self.geometry.cluster_sector(first);
+                        self.write_at(sector, data)?;
+                    }
+                    (first, ARCHIVE, data.len() as u32)
+                }
+                Kind::Dir(grandchildren) => {
+                    let first = self.allocate(dir_len(grandchildren, true))?;
+                    self.write_dir(grandchildren, first, Some(this), node.modified)?;
+                    (first, DIRECTORY, 0)
+                }
+            };
+
+            let short_name = match exact_short_name(&node.name) {
+                Some(short_name) => short_name,
+                None => {
+                    let short_name = generated_short_name(&node.name, &short_names);
+                    push_lfn_entries(&mut entries, &node.name, &short_name);
+                    short_name
+                }
+            };
+            short_names.push(short_name);
+
+            let timestamp = fat_timestamp(node.modified);
+            push_entry(
+                &mut entries,
+                &short_name,
+                attributes,
+                first,
+                size,
+                timestamp,
+            );
+        }
+
+        // The rest of the chain is already zero, which ends the directory.
+        let sector = self.geometry.cluster_sector(cluster);
+        self.write_at(sector, &entries)
+    }
+
+    /// Writes the boot sector, its backup, and the FS information sectors
+    /// that follow them.
+    fn write_boot_sectors(&mut self, serial: u32) -> io::Result<()> {
+        let geometry = &self.geometry;
+        let mut boot = [0u8; SECTOR_SIZE as usize];
+        boot[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        boot[3..11].copy_from_slice(b"RUSTOS  ");
+        boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        boot[13] = geometry.sectors_per_cluster as u8;
+        boot[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+        boot[16] = NUM_FATS as u8;
+        boot[21] = 0xF8;
+        boot[24..26].copy_from_slice(&63u16.to_le_bytes());
+        boot[26..28].copy_from_slice(&255u16.to_le_bytes());
+        boot[28..32].copy_from_slice(&(PARTITION_START as u32).to_le_bytes());
+        boot[32..36].copy_from_slice(&(geometry.sectors as u32).to_le_bytes());
+        boot[36..40].copy_from_slice(&(geometry.sectors_per_fat as u32).to_le_bytes());
+        boot[44..48].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+        boot[48..50].copy_from_slice(&(FS_INFO_SECTOR as u16).to_le_bytes());
+        boot[50..52].copy_from_slice(&(BACKUP_BOOT_SECTOR as u16).to_le_bytes());
+        boot[64] = 0x80;
+        boot[66] = 0x29;
+        boot[67..71].copy_from_slice(&serial.to_le_bytes());
+        boot[71..82].copy_from_slice(VOLUME_LABEL);
+        boot[82..90].copy_from_slice(b"FAT32   ");
+        boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        let used = self.fat.len() as u64 - 2;
+        let mut fs_info = [0u8; SECTOR_SIZE as usize];
+        fs_info[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+        fs_info[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+        fs_info[488..492].copy_from_slice(&((geometry.clusters - used) as u32).to_le_bytes());
+        fs_info[492..496].copy_from_slice(&(self.fat.len() as u32).to_le_bytes());
+        fs_info[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+
+        for &start in &[0, BACKUP_BOOT_SECTOR] {
+            self.write_at(start, &boot)?;
+            self.write_at(start + FS_INFO_SECTOR, &fs_info)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every copy of the FAT.
+    fn write_fats(&mut self) -> io::Result<()> {
+        let bytes: Vec<u8> = self
+            .fat
+            .iter()
+            .flat_map(|entry| entry.to_le_bytes())
+            .collect();
+        for i in 0..NUM_FATS {
+            let sector = RESERVED_SECTORS + i * self.geometry.sectors_per_fat;
+            self.write_at(sector, &bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the MBR, with one partition covering the rest of the image.
+    fn write_mbr(&mut self, disk_id: u32) -> io::Result<()> {
+        let mut mbr = [0u8; SECTOR_SIZE as usize];
+        mbr[440..444].copy_from_slice(&disk_id.to_le_bytes());
+
+        let entry = &mut mbr[446..462];
+        // Addressed by LBA only, so both CHS addresses are the "too large"
+        // marker.
+        entry[1..4].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        entry[4] = FAT32_LBA_PART_TYPE;
+        entry[5..8].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+        entry[8..12].copy_from_slice(&(PARTITION_START as u32).to_le_bytes());
+        entry[12..16].copy_from_slice(&(self.geometry.sectors as u32).to_le_bytes());
+        mbr[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&mbr)
+    }
+}
+
+/// Writes an image of `size` bytes to `path`, holding the files and
+/// directories in `root`, and returns how much of it they take.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `size` is too small for a FAT32
+/// volume or too large for an MBR partition, or of `Other` if the files do not
+/// fit.
+pub fn write(path: &Path, size: u64, root: &[Node]) -> io::Result<Usage> {
+    let sectors = (size / SECTOR_SIZE).saturating_sub(PARTITION_START);
+    if sectors > u32::MAX as u64 {
+        let message = "image too large for an MBR partition";
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+
+    let geometry = Geometry::new(sectors)?;
+    let file = File::create(path)?;
+    file.set_len(size)?;
+
+    let mut image = Image {
+        file,
+        geometry,
+        fat: vec![MEDIA_ENTRY, END_OF_CHAIN],
+    };
+
+    // The root directory always has a cluster, even when it is empty.
+    let root_cluster = image.allocate(dir_len(root, false).max(1))?;
+    debug_assert_eq!(root_cluster, ROOT_CLUSTER);
+    image.write_dir(root, root_cluster, None, SystemTime::now())?;
+
+    let serial = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as u32);
+    image.write_fats()?;
+    image.write_boot_sectors(serial)?;
+    image.write_mbr(serial)?;
+    image.file.sync_all()?;
+
+    let cluster_size = image.geometry.cluster_size();
+    Ok(Usage {
+        used: (image.fat.len() as u64 - 2) * cluster_size,
+        capacity: image.geometry.clusters * cluster_size,
+    })
+}
+
+/// Returns the size, in bytes, of a directory holding `children`, and `.`
+/// and `..` if `dots` is `true`.
+fn dir_len(children: &[Node], dots: bool) -> u64 {
+    let entries: usize = children
+        .iter()
+        .map(|node| match exact_short_name(&node.name) {
+            Some(_) => 1,
+            None => 1 + lfn_entries(&node.name),
+        })
+        .sum();
+    let dots = if dots { 2 } else { 0 };
+    ((entries + dots) * ENTRY_SIZE) as u64
+}
+
+/// Returns the number of entries the long file name `name` takes.
+fn lfn_entries(name: &str) -> usize {
+    let len = name.encode_utf16().count();
+    (len + LFN_CHARS_PER_ENTRY - 1) / LFN_CHARS_PER_ENTRY
+}
+
+/// Returns `true` if `c` may appear in a short name.
+fn is_short_name_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || SHORT_NAME_PUNCTUATION.contains(&c)
+}
+
+/// Returns `name` as a short name, blank padded, if it is one exactly: up to
+/// eight characters, then optionally a dot and up to three more, all of them
+/// upper case letters, digits or allowed punctuation.
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, extension) = match name.find('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+
+    let valid = |part: &str, max: usize| part.len() <= max && part.bytes().all(is_short_name_char);
+    if base.is_empty() || !valid(base, 8) || !valid(extension, 3) {
+        return None;
+    }
+    if name.ends_with('.') {
+        return None;
+    }
+
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base.as_bytes());
+    short_name[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+    Some(short_name)
+}
+
+/// Returns a short name for `name`, which is not one itself, that is not in
+/// `taken`. That is `name` upper cased if it then is one, as for
+/// `kernel8.img`, since the firmware may only look at short names. Otherwise
+/// it is the name's first letters and extension, upper cased, and a `~` and
+/// a number to tell it apart, as Windows makes them.
+fn generated_short_name(name: &str, taken: &[[u8; 11]]) -> [u8; 11] {
+    if let Some(short_name) = exact_short_name(&name.to_ascii_uppercase()) {
+        if !taken.contains(&short_name) {
+            return short_name;
+        }
+    }
+
+    let to_short = |part: &str| -> Vec<u8> {
+        part.bytes()
+            .filter(|&c| c != b' ' && c != b'.')
+            .map(|c| c.to_ascii_uppercase())
+            .map(|c| if is_short_name_char(c) { c } else { b'_' })
+            .collect()
+    };
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, extension) = match trimmed.rfind('.') {
+        Some(dot) => (to_short(&trimmed[..dot]), to_short(&trimmed[dot + 1..])),
+        None => (to_short(trimmed), Vec::new()),
+    };
+    let base = if base.is_empty() { vec![b'_'] } else { base };
+
+    let mut short_name = [b' '; 11];
+    let extension = &extension[..extension.len().min(3)];
+    short_name[8..8 + extension.len()].copy_from_slice(extension);
+    for n in 1.. {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        short_name[..8].copy_from_slice(b"        ");
+        short_name[..keep].copy_from_slice(&base[..keep]);
+        short_name[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        if !taken.contains(&short_name) {
+            break;
+        }
+    }
+    short_name
+}
+
+/// Returns the checksum of a short name that its long file name entries
+/// carry.
+fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+/// Appends the long file name entries for `name`, whose short name is
+/// `short_name`, to `entries`. They go last part first.
+fn push_lfn_entries(entries: &mut Vec<u8>, name: &str, short_name: &[u8; 11]) {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    let count = lfn_entries(name);
+    // The name ends with a NUL if there is room, and then 0xFFFF padding.
+    if chars.len() < count * LFN_CHARS_PER_ENTRY {
+        chars.push(0x0000);
+    }
+    chars.resize(count * LFN_CHARS_PER_ENTRY, 0xFFFF);
+
+    let checksum = short_name_checksum(short_name);
+    for (i, part) in chars.chunks(LFN_CHARS_PER_ENTRY).enumerate().rev() {
+        let mut entry = [0u8; ENTRY_SIZE];
+        entry[0] = (i + 1) as u8;
+        if i + 1 == count {
+            entry[0] |= LAST_LFN_ENTRY;
+        }
+        entry[11] = LFN;
+        entry[13] = checksum;
+
+        let bytes: Vec<u8> = part.iter().flat_map(|c| c.to_le_bytes()).collect();
+        entry[1..11].copy_from_slice(&bytes[0..10]);
+        entry[14..26].copy_from_slice(&bytes[10..22]);
+        entry[28..32].copy_from_slice(&bytes[22..26]);
+        entries.extend_from_slice(&entry);
+    }
+}
+
+/// Appends a regular directory entry to `entries`.
+fn push_entry(
+    entries: &mut Vec<u8>,
+    short_name: &[u8; 11],
+    attributes: u8,
+    cluster: u32,
+    size: u32,
+    (date, time): (u16, u16),
+) {
+    let mut entry = [0u8; ENTRY_SIZE];
+    entry[0..11].copy_from_slice(short_name);
+    entry[11] = attributes;
+    entry[14..16].copy_from_slice(&time.to_le_bytes());
+    entry[16..18].copy_from_slice(&date.to_le_bytes());
+    entry[18..20].copy_from_slice(&date.to_le_bytes());
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[22..24].copy_from_slice(&time.to_le_bytes());
+    entry[24..26].copy_from_slice(&date.to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entries.extend_from_slice(&entry);
+}
+
+/// Returns `time`, in UTC, as a FAT date and time. Times before 1980, which
+/// FAT cannot represent, become its start.
+fn fat_timestamp(time: SystemTime) -> (u16, u16) {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts days since 1970-01-01 to a civil date, as in Howard Hinnant's
+    // `civil_from_days`.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    if year < 1980 {
+        return (1 << 5 | 1, 0);
+    }
+    let year = (year - 1980).min(127);
+    let date = year << 9 | month << 5 | day;
+    let time = (secs / 3600) << 11 | (secs / 60 % 60) << 5 | ((secs % 60) / 2);
+    (date as u16, time as u16)
+}
@@ -0,0 +1,141 @@
+mod image;
+mod tree;
+mod verify;
+
+use structopt;
+use structopt_derive::StructOpt;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use boot_protocol::{ImageHeader, KERNEL_LOAD_ADDR};
+use structopt::StructOpt;
+
+use tree::{Kind, Node};
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Build a bootable SD card image with a FAT32 file system.")]
+struct Opt {
+    #[structopt(help = "Path to write the image to", parse(from_os_str))]
+    output: PathBuf,
+
+    #[structopt(
+        short = "s",
+        long = "size",
+        parse(try_from_str),
+        help = "Set image size in MiB",
+        default_value = "64"
+    )]
+    size: u64,
+
+    #[structopt(
+        short = "f",
+        long = "firmware",
+        help = "Directory of firmware files to copy to the root (e.g. ext/firmware)",
+        parse(from_os_str)
+    )]
+    firmware: Option<PathBuf>,
+
+    #[structopt(
+        short = "c",
+        long = "config",
+        help = "Firmware configuration to install as /config.txt (generated for the kernel if not set)",
+        parse(from_os_str)
+    )]
+    config: Option<PathBuf>,
+
+    #[structopt(
+        short = "k",
+        long = "kernel",
+        help = "Kernel binary to install as /kernel8.img",
+        parse(from_os_str)
+    )]
+    kernel: Option<PathBuf>,
+
+    #[structopt(
+        short = "u",
+        long = "user",
+        help = "Directory of user programs to copy to /bin",
+        parse(from_os_str)
+    )]
+    user: Option<PathBuf>,
+
+    #[structopt(
+        short = "d",
+        long = "dir",
+        help = "Directory tree to copy to the root, over everything else",
+        parse(from_os_str)
+    )]
+    dir: Option<PathBuf>,
+}
+
+/// Reads the directory at `path` and adds its entries to `dir`.
+fn add_dir_contents(dir: &mut Node, path: &Path) -> io::Result<()> {
+    match Node::read(path, "")?.kind {
+        Kind::Dir(children) => {
+            for child in children {
+                dir.insert(child);
+            }
+            Ok(())
+        }
+        Kind::File(_) => {
+            let message = format!("{}: not a directory", path.display());
+            Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+        }
+    }
+}
+
+/// Returns a `config.txt` that has the firmware load `kernel` where its
+/// header says to, or where kernels are loaded if it has no header.
+fn config_for(kernel: &[u8]) -> Vec<u8> {
+    let load_addr =
+        ImageHeader::parse(kernel).map_or(KERNEL_LOAD_ADDR as u64, |header| header.load_addr);
+    format!("arm_control=0x200\nkernel_address={:#x}\n", load_addr).into_bytes()
+}
+
+fn run(opt: &Opt) -> io::Result<()> {
+    let mut root = Node::dir("");
+    if let Some(firmware) = &opt.firmware {
+        add_dir_contents(&mut root, firmware)?;
+    }
+    if let Some(kernel) = &opt.kernel {
+        let kernel = Node::read(kernel, "kernel8.img")?;
+        if let Kind::File(data) = &kernel.kind {
+            if opt.config.is_none() {
+                root.insert(Node::file("config.txt", config_for(data)));
+            }
+        }
+        root.insert(kernel);
+    }
+    if let Some(config) = &opt.config {
+        root.insert(Node::read(config, "config.txt")?);
+    }
+    if let Some(user) = &opt.user {
+        let mut bin = Node::dir("bin");
+        add_dir_contents(&mut bin, user)?;
+        root.insert(bin);
+    }
+    if let Some(dir) = &opt.dir {
+        add_dir_contents(&mut root, dir)?;
+    }
+
+    let root = root.children().expect("root is a directory");
+    let usage = image::write(&opt.output, opt.size << 20, root)?;
+    verify::verify(&opt.output, root)?;
+
+    println!(
+        "Wrote {}: {} KiB of {} KiB used",
+        opt.output.display(),
+        usage.used >> 10,
+        usage.capacity >> 10
+    );
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(&opt) {
+        eprintln!("mkimage: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,111 @@
+//! The tree of files and directories to copy into an image, gathered from
+//! the host's file system.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// A file or directory to copy into the image.
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub modified: SystemTime,
+    pub kind: Kind,
+}
+
+#[derive(Debug)]
+pub enum Kind {
+    File(Vec<u8>),
+    Dir(Vec<Node>),
+}
+
+impl Node {
+    /// Returns an empty directory named `name`.
+    pub fn dir(name: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            modified: SystemTime::now(),
+            kind: Kind::Dir(Vec::new()),
+        }
+    }
+
+    /// Returns a file named `name` holding `data`.
+    pub fn file(name: &str, data: Vec<u8>) -> Node {
+        Node {
+            name: name.to_string(),
+            modified: SystemTime::now(),
+            kind: Kind::File(data),
+        }
+    }
+
+    /// Reads the file or directory at `path` into a node named `name`.
+    /// Directories are read recursively, and their entries sorted by name.
+    pub fn read(path: &Path, name: &str) -> io::Result<Node> {
+        let metadata = fs::metadata(path)?;
+        let kind = if metadata.is_dir() {
+            let mut children = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(name) => {
+                        let message = format!("{:?}: name is not valid UTF-8", name);
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                    }
+                };
+                children.push(Node::read(&entry.path(), &name)?);
+            }
+            children.sort_by(|a, b| a.name.cmp(&b.name));
+            Kind::Dir(children)
+        } else {
+            Kind::File(fs::read(path)?)
+        };
+
+        Ok(Node {
+            name: name.to_string(),
+            modified: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+            kind,
+        })
+    }
+
+    /// Returns the node's entries if it is a directory, or `None` otherwise.
+    pub fn children(&self) -> Option<&[Node]> {
+        match &self.kind {
+            Kind::Dir(children) => Some(children),
+            Kind::File(_) => None,
+        }
+    }
+
+    /// Adds `node` to this directory, replacing any entry with the same name.
+    /// Names are compared case-insensitively, as FAT does. If both are
+    /// directories, their entries are merged instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` is not a directory.
+    pub fn insert(&mut self, node: Node) {
+        let children = match &mut self.kind {
+            Kind::Dir(children) => children,
+            Kind::File(_) => panic!("{}: not a directory", self.name),
+        };
+
+        match children
+            .iter_mut()
+            .position(|child| child.name.eq_ignore_ascii_case(&node.name))
+        {
+            Some(i) => {
+                let child = &mut children[i];
+                match node.kind {
+                    Kind::Dir(entries) if child.children().is_some() => {
+                        for entry in entries {
+                            child.insert(entry);
+                        }
+                    }
+                    kind => *child = Node { kind, ..node },
+                }
+            }
+            None => children.push(node),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Checks a written image by mounting it with the `fat32` crate, as the
+//! kernel does, and comparing what it reads back to the tree that went in.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use fat32::traits::{BlockDevice, Entry, FileSystem};
+use fat32::vfat::{VFat, VFatHandle};
+
+use crate::tree::{Kind, Node};
+
+#[derive(Clone)]
+struct StdVFatHandle(Arc<Mutex<VFat<Self>>>);
+
+impl fmt::Debug for StdVFatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StdVFatHandle")
+    }
+}
+
+impl VFatHandle for StdVFatHandle {
+    fn new(val: VFat<StdVFatHandle>) -> Self {
+        StdVFatHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut VFat<StdVFatHandle>) -> R) -> R {
+        f(&mut self.0.lock().expect("all okay"))
+    }
+}
+
+/// An image file, read a sector at a time rather than all at once.
+struct Image(File);
+
+impl BlockDevice for Image {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size();
+        let to_read = ::std::cmp::min(sector_size as usize, buf.len());
+        self.0.seek(SeekFrom::Start(n * sector_size))?;
+        self.0.read_exact(&mut buf[..to_read])?;
+        Ok(to_read)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size();
+        let to_write = ::std::cmp::min(sector_size as usize, buf.len());
+        self.0.seek(SeekFrom::Start(n * sector_size))?;
+        self.0.write_all(&buf[..to_write])?;
+        Ok(to_write)
+    }
+}
+
+/// Returns an error of `InvalidData` saying what is wrong with `path`.
+fn mismatch(path: &Path, what: &str) -> io::Error {
+    let message = format!("{}: {} in the image", path.display(), what);
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Mounts the image at `image` and checks that it holds exactly `root`.
+pub fn verify(image: &Path, root: &[Node]) -> io::Result<()> {
+    let device = Image(File::open(image)?);
+    let vfat = match VFat::<StdVFatHandle>::from(device) {
+        Ok(vfat) => vfat,
+        Err(e) => {
+            let message = format!("mounting the image failed: {:?}", e);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+    };
+
+    verify_dir(&vfat, Path::new("/"), root)
+}
+
+fn verify_dir(vfat: &StdVFatHandle, path: &Path, children: &[Node]) -> io::Result<()> {
+    use fat32::traits::Dir;
+
+    let dir = vfat.open_dir(path)?;
+    let entries = dir
+        .entries()?
+        .filter(|entry| entry.name() != "." && entry.name() != "..")
+        .count();
+    if entries != children.len() {
+        return Err(mismatch(path, "wrong number of entries"));
+    }
+
+    for node in children {
+        let path = path.join(&node.name);
+        let entry = dir
+            .find(&node.name)
+            .map_err(|_| mismatch(&path, "missing"))?;
+        if entry.name() != node.name {
+            return Err(mismatch(&path, "misnamed"));
+        }
+
+        match &node.kind {
+            Kind::File(data) => {
+                let mut file = entry
+                    .into_file()
+                    .ok_or_else(|| mismatch(&path, "not a file"))?;
+                let mut contents = Vec::with_capacity(data.len());
+                file.read_to_end(&mut contents)?;
+                if contents != *data {
+                    return Err(mismatch(&path, "different contents"));
+                }
+            }
+            Kind::Dir(children) => {
+                if !entry.is_dir() {
+                    return Err(mismatch(&path, "not a directory"));
+                }
+                verify_dir(vfat, &path, children)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,12 +1,14 @@
 use crate::atags::raw;
 
-pub use crate::atags::raw::{Core, Mem};
+pub use crate::atags::raw::{Core, Mem, Revision, Serial};
 
 /// An ATAG.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Atag {
     Core(raw::Core),
     Mem(raw::Mem),
+    Serial(raw::Serial),
+    Revision(raw::Revision),
     Cmd(&'static str),
     Unknown(u32),
     None,
@@ -31,6 +33,26 @@ impl Atag {
         }
     }
 
+    /// Returns `Some` with the board's serial number if this is a `Serial`
+    /// ATAG. Otherwise returns `None`.
+    pub fn serial(self) -> Option<u64> {
+        if let Atag::Serial(serial) = self {
+            Some((serial.high as u64) << 32 | serial.low as u64)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `Some` with the board's revision code if this is a `Revision`
+    /// ATAG. Otherwise returns `None`.
+    pub fn revision(self) -> Option<u32> {
+        if let Atag::Revision(revision) = self {
+            Some(revision.rev)
+        } else {
+            None
+        }
+    }
+
     /// Returns `Some` with the command line string if this is a `Cmd` ATAG.
     /// Otherwise returns `None`.
     pub fn cmd(self) -> Option<&'static str> {
@@ -48,6 +70,8 @@ impl From<&'static raw::Atag> for Atag {
             match (atag.tag, &atag.kind) {
                 (raw::Atag::CORE, &raw::Kind { core }) => Atag::Core(core),
                 (raw::Atag::MEM, &raw::Kind { mem }) => Atag::Mem(mem),
+                (raw::Atag::SERIAL, &raw::Kind { serial }) => Atag::Serial(serial),
+                (raw::Atag::REVISION, &raw::Kind { revision }) => Atag::Revision(revision),
                 (raw::Atag::CMDLINE, &raw::Kind { ref cmd }) => cmd.into(),
                 (raw::Atag::NONE, _) => Atag::None,
                 (id, _) => Atag::Unknown(id),
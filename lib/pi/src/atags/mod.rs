@@ -1,10 +1,9 @@
 mod atag;
 mod raw;
 
-pub use self::atag::*;
+use boot_protocol::ATAGS_ADDR;
 
-/// The address at which the firmware loads the ATAGS.
-const ATAG_BASE: usize = 0x100;
+pub use self::atag::*;
 
 /// An iterator over the ATAGS on this system.
 pub struct Atags {
@@ -15,7 +14,7 @@ impl Atags {
     /// Returns an instance of `Atags`, an iterator over ATAGS on this system.
     pub fn get() -> Atags {
         Atags {
-            ptr: Some(unsafe { &*(ATAG_BASE as *const raw::Atag) }),
+            ptr: Some(unsafe { &*(ATAGS_ADDR as *const raw::Atag) }),
         }
     }
 }
@@ -36,7 +35,7 @@ impl Iterator for Atags {
 mod test {
     use super::{raw, Atag, Atags};
 
-    const MEM: [u32; 23] = [
+    const MEM: [u32; 27] = [
         // CORE
         5,
         raw::Atag::CORE,
@@ -57,7 +56,12 @@ mod test {
         raw::Atag::CMDLINE,
         1819043176,
         111,
-        // UNKNOWN
+        // SERIAL
+        4,
+        raw::Atag::SERIAL,
+        0xdeadbeef,
+        0x1,
+        // REVISION
         5,
         raw::Atag::REVISION,
         123,
@@ -95,7 +99,19 @@ mod test {
 
         assert_eq!(atags.next(), Some(Atag::Cmd("hello")));
 
-        assert_eq!(atags.next(), Some(Atag::Unknown(raw::Atag::REVISION)));
+        let serial = atags.next();
+        assert_eq!(
+            serial,
+            Some(Atag::Serial(raw::Serial {
+                low: 0xdeadbeef,
+                high: 0x1,
+            }))
+        );
+        assert_eq!(serial.and_then(Atag::serial), Some(0x1_deadbeef));
+
+        let revision = atags.next();
+        assert_eq!(revision, Some(Atag::Revision(raw::Revision { rev: 123 })));
+        assert_eq!(revision.and_then(Atag::revision), Some(123));
 
         assert_eq!(atags.next(), Some(Atag::None));
 
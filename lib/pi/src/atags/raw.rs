@@ -40,6 +40,8 @@ impl Atag {
 pub union Kind {
     pub core: Core,
     pub mem: Mem,
+    pub serial: Serial,
+    pub revision: Revision,
     pub cmd: Cmd,
 }
 
@@ -60,6 +62,21 @@ pub struct Mem {
     pub start: u32,
 }
 
+/// A `SERIAL` ATAG.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Serial {
+    pub low: u32,
+    pub high: u32,
+}
+
+/// A `REVISION` ATAG.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Revision {
+    pub rev: u32,
+}
+
 /// A `CMDLINE` ATAG.
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -1,12 +1,155 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::atags::Atags;
+
+#[cfg(not(any(feature = "bcm2835", feature = "bcm2837", feature = "bcm2711")))]
+compile_error!("one of the features `bcm2835`, `bcm2837` or `bcm2711` must be enabled");
+
+#[cfg(any(
+    all(feature = "bcm2835", feature = "bcm2837"),
+    all(feature = "bcm2835", feature = "bcm2711"),
+    all(feature = "bcm2837", feature = "bcm2711")
+))]
+compile_error!("only one of the features `bcm2835`, `bcm2837` or `bcm2711` may be enabled");
+
+/// A system on a chip the drivers support, which decides where the
+/// peripherals are and how many cores there are.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Soc {
+    /// The Raspberry Pi Zero and 1. Its one core is 32-bit only.
+    Bcm2835,
+    /// The Raspberry Pi 2 and 3. The BCM2836 of the first Pi 2s lays its
+    /// peripherals out as the BCM2837 does.
+    Bcm2837,
+    /// The Raspberry Pi 4, in low peripheral mode, the firmware's default.
+    /// Its legacy interrupt controller is only used with `enable_gic=0` in
+    /// `config.txt`.
+    Bcm2711,
+}
+
+impl Soc {
+    /// Returns the physical address the SoC's peripherals start at.
+    pub const fn io_base(self) -> usize {
+        match self {
+            Soc::Bcm2835 => 0x2000_0000,
+            Soc::Bcm2837 => 0x3F00_0000,
+            Soc::Bcm2711 => 0xFE00_0000,
+        }
+    }
+
+    /// Returns the physical address the SoC's peripherals end at.
+    pub const fn io_base_end(self) -> usize {
+        self.io_base() + 0x0100_0000
+    }
+
+    /// Returns the physical address of the core-local peripherals, if the SoC
+    /// has them.
+    pub const fn local_base(self) -> Option<usize> {
+        match self {
+            Soc::Bcm2835 => None,
+            Soc::Bcm2837 => Some(0x4000_0000),
+            Soc::Bcm2711 => Some(0xFF80_0000),
+        }
+    }
+
+    /// Returns the number of cores.
+    pub const fn ncores(self) -> usize {
+        match self {
+            Soc::Bcm2835 => 1,
+            Soc::Bcm2837 | Soc::Bcm2711 => 4,
+        }
+    }
+
+    /// Returns the frequency, in Hz, of the core clock the mini UART divides
+    /// to make its baud rate, as the firmware sets it with `enable_uart=1`.
+    pub const fn core_clock_hz(self) -> u32 {
+        match self {
+            Soc::Bcm2835 | Soc::Bcm2837 => 250_000_000,
+            Soc::Bcm2711 => 500_000_000,
+        }
+    }
+
+    /// Returns the SoC of the board with the revision code `revision`, or
+    /// `None` if the code names a SoC the drivers do not support.
+    ///
+    /// Old-style codes, without bit 23 set, are all for BCM2835 boards.
+    /// New-style codes name the SoC in bits 12 to 15.
+    pub fn from_revision(revision: u32) -> Option<Soc> {
+        if revision & (1 << 23) == 0 {
+            return Some(Soc::Bcm2835);
+        }
+
+        match (revision >> 12) & 0xF {
+            0 => Some(Soc::Bcm2835),
+            1 | 2 => Some(Soc::Bcm2837),
+            3 => Some(Soc::Bcm2711),
+            _ => None,
+        }
+    }
+
+    /// Returns the SoC of the board, from the revision code the firmware
+    /// left in the ATAGs, or `None` if there is none or it names a SoC the
+    /// drivers do not support.
+    pub fn detect() -> Option<Soc> {
+        Atags::get()
+            .find_map(|atag| atag.revision())
+            .and_then(Soc::from_revision)
+    }
+}
+
+/// The SoC the drivers are built for, chosen with the `bcm2835`, `bcm2837`
+/// or `bcm2711` feature. `Soc::detect()` says whether it is the board's.
+#[cfg(feature = "bcm2835")]
+pub const SOC: Soc = Soc::Bcm2835;
+#[cfg(feature = "bcm2837")]
+pub const SOC: Soc = Soc::Bcm2837;
+#[cfg(feature = "bcm2711")]
+pub const SOC: Soc = Soc::Bcm2711;
+
 /// The address where I/O peripherals are mapped to.
-pub const IO_BASE: usize = 0x3F000000;
-pub const IO_BASE_END: usize = 0x40000000;
+pub const IO_BASE: usize = SOC.io_base();
+pub const IO_BASE_END: usize = SOC.io_base_end();
 
 /// The base address of the `GPIO` registers
 pub const GPIO_BASE: usize = IO_BASE + 0x200000;
 
-/// The number of cores in Rpi3
-pub const NCORES: usize = 4;
+/// The address the peripherals between `IO_BASE` and `IO_BASE_END` are
+/// accessed through.
+static IO_VIRT_BASE: AtomicUsize = AtomicUsize::new(IO_BASE);
+
+/// Returns the address the drivers access the peripheral register at the
+/// physical address `addr`, which lies between `IO_BASE` and `IO_BASE_END`,
+/// through. This is `addr` itself unless `set_io_base()` was called.
+pub fn io_addr(addr: usize) -> usize {
+    IO_VIRT_BASE.load(Ordering::Relaxed) + (addr - IO_BASE)
+}
+
+/// Makes drivers created from now on access the peripherals through `base`,
+/// the virtual address `IO_BASE` is mapped at once the MMU is on.
+///
+/// # Safety
+///
+/// All of `IO_BASE` to `IO_BASE_END` must be mapped as device memory at
+/// `base`, and stay mapped.
+pub unsafe fn set_io_base(base: usize) {
+    IO_VIRT_BASE.store(base, Ordering::Relaxed);
+}
+
+/// The base address of the core-local peripherals' registers: the per-core
+/// timer and mailbox interrupts. They lie outside `IO_BASE` to `IO_BASE_END`,
+/// and are accessed at their physical address.
+#[cfg(not(feature = "bcm2835"))]
+pub const LOCAL_BASE: usize = match SOC.local_base() {
+    Some(base) => base,
+    None => 0,
+};
+
+/// The address the core-local peripherals' registers end at.
+#[cfg(not(feature = "bcm2835"))]
+pub const LOCAL_END: usize = LOCAL_BASE + 0x4_0000;
+
+/// The number of cores.
+pub const NCORES: usize = SOC.ncores();
 
 /// The base of physical addresses that each core is spinning on
 pub const SPINNING_BASE: *mut usize = 0xd8 as *mut usize;
@@ -19,3 +162,20 @@ pub macro states($($name:ident),*) {
         pub enum $name {  }
     )*
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Soc;
+
+    #[test]
+    fn soc_from_revision() {
+        // Old-style codes: a Pi 1 model B.
+        assert_eq!(Soc::from_revision(0x000e), Some(Soc::Bcm2835));
+        // New-style codes: a Pi Zero W, 2 B, 3 B+ and 4 B.
+        assert_eq!(Soc::from_revision(0x9000c1), Some(Soc::Bcm2835));
+        assert_eq!(Soc::from_revision(0xa01041), Some(Soc::Bcm2837));
+        assert_eq!(Soc::from_revision(0xa020d3), Some(Soc::Bcm2837));
+        assert_eq!(Soc::from_revision(0xb03111), Some(Soc::Bcm2711));
+        assert_eq!(Soc::from_revision(0xc04170), None);
+    }
+}
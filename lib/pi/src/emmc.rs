@@ -0,0 +1,155 @@
+use core::time::Duration;
+
+use crate::common::{io_addr, IO_BASE};
+use crate::timer::Stopwatch;
+
+use volatile::prelude::*;
+use volatile::{register_block, ReadVolatile, Reserved, Volatile};
+
+/// The base address for the EMMC controller's registers, which the SD card
+/// is attached to.
+const EMMC_REG_BASE: usize = IO_BASE + 0x300000;
+
+/// The size, in bytes, of a block of the SD card.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The `CMDTM` value for `WRITE_SINGLE_BLOCK` (CMD24): a 48-bit response, and
+/// one block of data from the host to the card.
+const CMD_WRITE_SINGLE: u32 = 0x1822_0000;
+
+/// The `STATUS` bit set while the controller cannot issue a command.
+const SR_CMD_INHIBIT: u32 = 1 << 0;
+
+/// The `STATUS` bit set while the data lines are in use, including while
+/// the card is busy programming a block written to it.
+const SR_DAT_INHIBIT: u32 = 1 << 1;
+
+/// The `INTERRUPT` bit set once a command has been sent and answered.
+const INT_CMD_DONE: u32 = 1 << 0;
+
+/// The `INTERRUPT` bit set once a data transfer has finished.
+const INT_DATA_DONE: u32 = 1 << 1;
+
+/// The `INTERRUPT` bit set once the controller is ready for data to write.
+const INT_WRITE_RDY: u32 = 1 << 4;
+
+/// The `INTERRUPT` bits set on a timeout.
+const INT_TIMEOUT: u32 = (1 << 16) | (1 << 20);
+
+/// The `INTERRUPT` bits set on any error.
+const INT_ERROR_MASK: u32 = 0x017e_8000;
+
+/// How long to wait for each step of a write before giving up.
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+register_block! {
+    struct Registers {
+        0x00 => __r0: Reserved<u32>,
+        0x04 => BLKSIZECNT: Volatile<u32>,
+        0x08 => ARG1: Volatile<u32>,
+        0x0c => CMDTM: Volatile<u32>,
+        0x10 => __r1: [Reserved<u32>; 4],
+        0x20 => DATA: Volatile<u32>,
+        0x24 => STATUS: ReadVolatile<u32>,
+        0x28 => __r2: [Reserved<u32>; 2],
+        0x30 => INTERRUPT: Volatile<u32>,
+        0x34 => @end,
+    }
+}
+
+/// Why a write to the SD card failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The controller or card did not answer in time.
+    TimedOut,
+    /// The controller reported an error, with these `INTERRUPT` bits.
+    Failed(u32),
+}
+
+/// The EMMC controller, driving an SD card that has already been
+/// initialized, which `libsd`'s `sd_init()` does. `libsd` only reads; this
+/// writes.
+pub struct Emmc {
+    registers: &'static mut Registers,
+}
+
+impl Emmc {
+    /// Returns a new instance of `Emmc`.
+    pub fn new() -> Emmc {
+        Emmc {
+            registers: unsafe { &mut *(io_addr(EMMC_REG_BASE) as *mut Registers) },
+        }
+    }
+
+    /// Returns the argument of the last command sent. After a read, this is
+    /// the address the block was read from, which tells whether the card is
+    /// addressed in blocks or in bytes.
+    pub fn last_argument(&self) -> u32 {
+        self.registers.ARG1.read()
+    }
+
+    /// Writes `block` to the card at `address`, which is a block number if
+    /// the card is addressed in blocks, and a byte offset if it is addressed
+    /// in bytes. Returns once the card has the block.
+    pub fn write_block(&mut self, address: u32, block: &[u8; BLOCK_SIZE]) -> Result<(), Error> {
+        self.wait_status(SR_CMD_INHIBIT | SR_DAT_INHIBIT)?;
+
+        self.registers
+            .BLKSIZECNT
+            .write((1 << 16) | BLOCK_SIZE as u32);
+        let pending = self.registers.INTERRUPT.read();
+        self.registers.INTERRUPT.write(pending);
+        self.registers.ARG1.write(address);
+        self.registers.CMDTM.write(CMD_WRITE_SINGLE);
+        self.wait_interrupt(INT_CMD_DONE)?;
+
+        self.wait_interrupt(INT_WRITE_RDY)?;
+        for word in block.chunks(4) {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            self.registers.DATA.write(word);
+        }
+        self.wait_interrupt(INT_DATA_DONE)?;
+
+        // The card programs the block once it has it, holding the data
+        // lines until it is done.
+        self.wait_status(SR_DAT_INHIBIT)
+    }
+
+    /// Waits for the `STATUS` bits in `mask` to clear.
+    fn wait_status(&mut self, mask: u32) -> Result<(), Error> {
+        let stopwatch = Stopwatch::start();
+        while self.registers.STATUS.has_mask(mask) {
+            let interrupt = self.registers.INTERRUPT.read();
+            if interrupt & INT_ERROR_MASK != 0 {
+                return Err(Error::Failed(interrupt));
+            }
+            if stopwatch.elapsed() > TIMEOUT {
+                return Err(Error::TimedOut);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the `INTERRUPT` bits in `mask` to be set, then clears them.
+    fn wait_interrupt(&mut self, mask: u32) -> Result<(), Error> {
+        let stopwatch = Stopwatch::start();
+        loop {
+            let interrupt = self.registers.INTERRUPT.read();
+            if interrupt & INT_TIMEOUT != 0 {
+                self.registers.INTERRUPT.write(interrupt);
+                return Err(Error::TimedOut);
+            }
+            if interrupt & INT_ERROR_MASK != 0 {
+                self.registers.INTERRUPT.write(interrupt);
+                return Err(Error::Failed(interrupt));
+            }
+            if interrupt & mask == mask {
+                self.registers.INTERRUPT.write(mask);
+                return Ok(());
+            }
+            if stopwatch.elapsed() > TIMEOUT {
+                return Err(Error::TimedOut);
+            }
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use core::marker::PhantomData;
+use core::time::Duration;
 
-use crate::common::{states, GPIO_BASE};
+use crate::common::{io_addr, states, GPIO_BASE};
+use crate::timer;
 use volatile::prelude::*;
-use volatile::{ReadVolatile, Reserved, Volatile, WriteVolatile};
+use volatile::{register_block, Field, ReadVolatile, Reserved, Volatile, WriteVolatile};
 
 /// An alternative GPIO function.
 #[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Function {
     Input = 0b000,
     Output = 0b001,
@@ -17,33 +20,67 @@ pub enum Function {
     Alt5 = 0b010,
 }
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct Registers {
-    FSEL: [Volatile<u32>; 6],
-    __r0: Reserved<u32>,
-    SET: [WriteVolatile<u32>; 2],
-    __r1: Reserved<u32>,
-    CLR: [WriteVolatile<u32>; 2],
-    __r2: Reserved<u32>,
-    LEV: [ReadVolatile<u32>; 2],
-    __r3: Reserved<u32>,
-    EDS: [Volatile<u32>; 2],
-    __r4: Reserved<u32>,
-    REN: [Volatile<u32>; 2],
-    __r5: Reserved<u32>,
-    FEN: [Volatile<u32>; 2],
-    __r6: Reserved<u32>,
-    HEN: [Volatile<u32>; 2],
-    __r7: Reserved<u32>,
-    LEN: [Volatile<u32>; 2],
-    __r8: Reserved<u32>,
-    AREN: [Volatile<u32>; 2],
-    __r9: Reserved<u32>,
-    AFEN: [Volatile<u32>; 2],
-    __r10: Reserved<u32>,
-    PUD: Volatile<u32>,
-    PUDCLK: [Volatile<u32>; 2],
+impl FieldValue<u32> for Function {
+    fn from_bits(bits: u32) -> Function {
+        match bits {
+            0b000 => Function::Input,
+            0b001 => Function::Output,
+            0b100 => Function::Alt0,
+            0b101 => Function::Alt1,
+            0b110 => Function::Alt2,
+            0b111 => Function::Alt3,
+            0b011 => Function::Alt4,
+            _ => Function::Alt5,
+        }
+    }
+
+    fn into_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Returns the field of `FSEL[pin / 10]` that selects `pin`'s function.
+fn function_field(pin: u8) -> Field<u32, Function> {
+    Field::new(u32::from(pin % 10) * 3, 3)
+}
+
+/// The state of a GPIO pin's internal pull-up/down resistor.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pull {
+    Off = 0b00,
+    Down = 0b01,
+    Up = 0b10,
+}
+
+register_block! {
+    struct Registers {
+        0x00 => FSEL: [Volatile<u32>; 6],
+        0x18 => __r0: Reserved<u32>,
+        0x1c => SET: [WriteVolatile<u32>; 2],
+        0x24 => __r1: Reserved<u32>,
+        0x28 => CLR: [WriteVolatile<u32>; 2],
+        0x30 => __r2: Reserved<u32>,
+        0x34 => LEV: [ReadVolatile<u32>; 2],
+        0x3c => __r3: Reserved<u32>,
+        0x40 => EDS: [Volatile<u32>; 2],
+        0x48 => __r4: Reserved<u32>,
+        0x4c => REN: [Volatile<u32>; 2],
+        0x54 => __r5: Reserved<u32>,
+        0x58 => FEN: [Volatile<u32>; 2],
+        0x60 => __r6: Reserved<u32>,
+        0x64 => HEN: [Volatile<u32>; 2],
+        0x6c => __r7: Reserved<u32>,
+        0x70 => LEN: [Volatile<u32>; 2],
+        0x78 => __r8: Reserved<u32>,
+        0x7c => AREN: [Volatile<u32>; 2],
+        0x84 => __r9: Reserved<u32>,
+        0x88 => AFEN: [Volatile<u32>; 2],
+        0x90 => __r10: Reserved<u32>,
+        0x94 => PUD: Volatile<u32>,
+        0x98 => PUDCLK: [Volatile<u32>; 2],
+        0xa0 => @end,
+    }
 }
 
 /// Possible states for a GPIO pin.
@@ -77,6 +114,41 @@ impl<T> Gpio<T> {
             _state: PhantomData,
         }
     }
+
+    /// Returns the function `self` is currently configured for.
+    pub fn function(&self) -> Function {
+        let register = &self.registers.FSEL[(self.pin / 10) as usize];
+        register.read_field(function_field(self.pin))
+    }
+
+    /// Enables the pull-up or pull-down resistor of `self`, or disables both.
+    /// The setting is kept regardless of the pin's function.
+    pub fn set_pull(&mut self, pull: Pull) {
+        // The control signal must be set up for 150 cycles before it is
+        // clocked into the pin, and the clock held for 150 cycles after.
+        let hold = Duration::from_micros(1);
+        let reg_index = (self.pin / 32) as usize;
+        let mask_val = 1 << (self.pin % 32);
+
+        self.registers.PUD.write(pull as u32);
+        timer::spin_sleep(hold);
+        self.registers.PUDCLK[reg_index].write(mask_val);
+        timer::spin_sleep(hold);
+        self.registers.PUD.write(0);
+        self.registers.PUDCLK[reg_index].write(0);
+    }
+
+    /// Reads the pin's value regardless of its function. Returns `true` if
+    /// the level is high and `false` if the level is low.
+    fn read_level(&self) -> bool {
+        let reg_index = self.pin / 32;
+        let register = &self.registers.LEV[reg_index as usize];
+
+        let bit_num = self.pin % 32;
+        let mask_val = 1 << bit_num;
+
+        register.has_mask(mask_val)
+    }
 }
 
 impl Gpio<Uninitialized> {
@@ -91,7 +163,7 @@ impl Gpio<Uninitialized> {
         }
 
         Gpio {
-            registers: unsafe { &mut *(GPIO_BASE as *mut Registers) },
+            registers: unsafe { &mut *(io_addr(GPIO_BASE) as *mut Registers) },
             pin: pin,
             _state: PhantomData,
         }
@@ -100,12 +172,8 @@ impl Gpio<Uninitialized> {
     /// Enables the alternative function `function` for `self`. Consumes self
     /// and returns a `Gpio` structure in the `Alt` state.
     pub fn into_alt(self, function: Function) -> Gpio<Alt> {
-        let reg_num = self.pin / 10;
-        let register = &mut self.registers.FSEL[reg_num as usize];
-
-        let bit_num = self.pin % 10 * 3;
-        let mask_val = (function as u32) << bit_num;
-        register.or_mask(mask_val);
+        let register = &mut self.registers.FSEL[(self.pin / 10) as usize];
+        register.write_field(function_field(self.pin), function);
 
         self.transition()
     }
@@ -121,6 +189,12 @@ impl Gpio<Uninitialized> {
     pub fn into_input(self) -> Gpio<Input> {
         self.into_alt(Function::Input).transition()
     }
+
+    /// Reads the pin's value without changing its function. Returns `true` if
+    /// the level is high and `false` if the level is low.
+    pub fn level(&self) -> bool {
+        self.read_level()
+    }
 }
 
 impl Gpio<Output> {
@@ -151,12 +225,6 @@ impl Gpio<Input> {
     /// Reads the pin's value. Returns `true` if the level is high and `false`
     /// if the level is low.
     pub fn level(&mut self) -> bool {
-        let reg_index = self.pin / 32;
-        let register = &mut self.registers.LEV[reg_index as usize];
-
-        let bit_num = self.pin % 32;
-        let mask_val = 1 << bit_num;
-
-        register.has_mask(mask_val)
+        self.read_level()
     }
 }
@@ -0,0 +1,153 @@
+use core::time::Duration;
+
+use crate::common::{io_addr, IO_BASE};
+use crate::gpio::{Function, Gpio};
+use crate::timer::Stopwatch;
+
+use volatile::prelude::*;
+use volatile::{register_block, Volatile};
+
+/// The base address for the registers of BSC1, the I2C controller wired to
+/// GPIO 2 and 3 on the header.
+const BSC1_REG_BASE: usize = IO_BASE + 0x804000;
+
+/// The `C` bit that enables the controller.
+const C_I2CEN: u32 = 1 << 15;
+
+/// The `C` bit that starts a transfer.
+const C_ST: u32 = 1 << 7;
+
+/// The `C` bits that empty the FIFO.
+const C_CLEAR: u32 = 0b11 << 4;
+
+/// The `C` bit that makes a transfer a read.
+const C_READ: u32 = 1 << 0;
+
+/// The `S` bit set once a transfer has finished.
+const S_DONE: u32 = 1 << 1;
+
+/// The `S` bit set while the FIFO can take more data to write.
+const S_TXD: u32 = 1 << 4;
+
+/// The `S` bit set while the FIFO holds data read.
+const S_RXD: u32 = 1 << 5;
+
+/// The `S` bit set when the device did not acknowledge its address or data.
+const S_ERR: u32 = 1 << 8;
+
+/// The `S` bit set when the device held the clock low for too long.
+const S_CLKT: u32 = 1 << 9;
+
+/// How long to wait for each step of a transfer before giving up.
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+register_block! {
+    struct Registers {
+        0x00 => C: Volatile<u32>,
+        0x04 => S: Volatile<u32>,
+        0x08 => DLEN: Volatile<u32>,
+        0x0c => A: Volatile<u32>,
+        0x10 => FIFO: Volatile<u32>,
+        0x14 => DIV: Volatile<u32>,
+        0x18 => DEL: Volatile<u32>,
+        0x1c => CLKT: Volatile<u32>,
+        0x20 => @end,
+    }
+}
+
+/// Why an I2C transfer failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// No device acknowledged the address, or the device refused the data.
+    NoAck,
+    /// The transfer did not finish in time.
+    TimedOut,
+}
+
+/// The I2C controller on GPIO 2 (SDA) and 3 (SCL), as the bus master, at
+/// the controller's default clock rate.
+pub struct I2c {
+    registers: &'static mut Registers,
+}
+
+impl I2c {
+    /// Returns a new instance of `I2c`, switching GPIO 2 and 3 to the
+    /// controller.
+    pub fn new() -> I2c {
+        Gpio::new(2).into_alt(Function::Alt0);
+        Gpio::new(3).into_alt(Function::Alt0);
+        I2c {
+            registers: unsafe { &mut *(io_addr(BSC1_REG_BASE) as *mut Registers) },
+        }
+    }
+
+    /// Writes `bytes` to the device at the 7-bit address `address`.
+    pub fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.start(address, bytes.len(), 0);
+        for &byte in bytes {
+            self.wait(S_TXD)?;
+            self.registers.FIFO.write(byte as u32);
+        }
+        self.finish()
+    }
+
+    /// Fills `buf` with bytes read from the device at the 7-bit address
+    /// `address`.
+    pub fn read(&mut self, address: u8, buf: &mut [u8]) -> Result<(), Error> {
+        self.start(address, buf.len(), C_READ);
+        for byte in buf.iter_mut() {
+            self.wait(S_RXD)?;
+            *byte = self.registers.FIFO.read() as u8;
+        }
+        self.finish()
+    }
+
+    /// Fills `buf` with the device's registers from `register` on, the way
+    /// most devices are read: by writing the register number, then reading.
+    pub fn read_registers(
+        &mut self,
+        address: u8,
+        register: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Error> {
+        self.write(address, &[register])?;
+        self.read(address, buf)
+    }
+
+    /// Starts a transfer of `len` bytes with the device at `address`.
+    fn start(&mut self, address: u8, len: usize, read: u32) {
+        self.registers.A.write(address as u32);
+        self.registers.DLEN.write(len as u32);
+        self.registers.S.write(S_CLKT | S_ERR | S_DONE);
+        self.registers.C.write(C_I2CEN | C_ST | C_CLEAR | read);
+    }
+
+    /// Waits for the transfer to finish, then clears its status.
+    fn finish(&mut self) -> Result<(), Error> {
+        let result = self.wait(S_DONE);
+        self.registers.S.write(S_CLKT | S_ERR | S_DONE);
+        result
+    }
+
+    /// Waits for any of the `S` bits in `mask` to be set.
+    fn wait(&mut self, mask: u32) -> Result<(), Error> {
+        let stopwatch = Stopwatch::start();
+        loop {
+            let status = self.registers.S.read();
+            if status & S_ERR != 0 {
+                self.registers.S.write(S_CLKT | S_ERR | S_DONE);
+                return Err(Error::NoAck);
+            }
+            if status & S_CLKT != 0 {
+                self.registers.S.write(S_CLKT | S_ERR | S_DONE);
+                return Err(Error::TimedOut);
+            }
+            if status & mask != 0 {
+                return Ok(());
+            }
+            if stopwatch.elapsed() > TIMEOUT {
+                return Err(Error::TimedOut);
+            }
+        }
+    }
+}
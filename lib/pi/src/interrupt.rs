@@ -0,0 +1,148 @@
+use volatile::prelude::*;
+use volatile::{register_block, ReadVolatile, Volatile, WriteVolatile};
+
+use crate::common::{io_addr, IO_BASE};
+
+/// The base address of the interrupt controller's registers.
+const INT_BASE: usize = IO_BASE + 0xB000 + 0x200;
+
+/// The bit of `FIQ_CONTROL` that enables the FIQ.
+const FIQ_ENABLE: u32 = 1 << 7;
+/// The bits of `FIQ_CONTROL` that select the interrupt routed to the FIQ.
+const FIQ_SOURCE: u32 = 0x7F;
+
+/// An interrupt source routed through the interrupt controller. The value of
+/// each variant is its IRQ number in the BCM2837 documentation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    Timer1 = 1,
+    Timer3 = 3,
+    Usb = 9,
+    Aux = 29,
+    Gpio0 = 49,
+    Gpio1 = 50,
+    Gpio2 = 51,
+    Gpio3 = 52,
+    Uart = 57,
+    Emmc = 62,
+}
+
+impl Interrupt {
+    /// The number of interrupt sources.
+    pub const MAX: usize = 10;
+
+    /// Every interrupt source, in order of IRQ number.
+    pub const ALL: [Interrupt; Interrupt::MAX] = [
+        Interrupt::Timer1,
+        Interrupt::Timer3,
+        Interrupt::Usb,
+        Interrupt::Aux,
+        Interrupt::Gpio0,
+        Interrupt::Gpio1,
+        Interrupt::Gpio2,
+        Interrupt::Gpio3,
+        Interrupt::Uart,
+        Interrupt::Emmc,
+    ];
+
+    /// Returns an iterator over every interrupt source.
+    pub fn iter() -> impl Iterator<Item = Interrupt> {
+        Interrupt::ALL.iter().cloned()
+    }
+
+    /// Returns this interrupt's position in `Interrupt::ALL`, for indexing
+    /// tables with an entry per interrupt.
+    pub fn to_index(self) -> usize {
+        Interrupt::ALL.iter().position(|&int| int == self).unwrap()
+    }
+
+    /// Returns the interrupt at position `index` in `Interrupt::ALL`.
+    pub fn from_index(index: usize) -> Option<Interrupt> {
+        Interrupt::ALL.get(index).cloned()
+    }
+
+    /// Returns the register index and bit of this interrupt in the
+    /// controller's banks of 32 IRQs.
+    fn bank_and_bit(self) -> (usize, u32) {
+        let irq = self as usize;
+        (irq / 32, 1 << (irq % 32))
+    }
+}
+
+register_block! {
+    struct Registers {
+        0x00 => IRQ_BASIC_PENDING: ReadVolatile<u32>,
+        0x04 => IRQ_PENDING: [ReadVolatile<u32>; 2],
+        0x0c => FIQ_CONTROL: Volatile<u32>,
+        0x10 => ENABLE_IRQS: [Volatile<u32>; 2],
+        0x18 => ENABLE_BASIC_IRQS: Volatile<u32>,
+        0x1c => DISABLE_IRQS: [WriteVolatile<u32>; 2],
+        0x24 => DISABLE_BASIC_IRQS: WriteVolatile<u32>,
+        0x28 => @end,
+    }
+}
+
+/// The BCM2837 interrupt controller, which routes peripheral interrupts to the
+/// ARM cores.
+///
+/// The controller has no notion of acknowledging an interrupt: a source stays
+/// pending until the peripheral that raised it is serviced.
+pub struct Controller {
+    registers: &'static mut Registers,
+}
+
+impl Controller {
+    /// Returns a new handle to the interrupt controller.
+    pub fn new() -> Controller {
+        Controller {
+            registers: unsafe { &mut *(io_addr(INT_BASE) as *mut Registers) },
+        }
+    }
+
+    /// Enables the interrupt `int`.
+    pub fn enable(&mut self, int: Interrupt) {
+        let (bank, bit) = int.bank_and_bit();
+        self.registers.ENABLE_IRQS[bank].write(bit);
+    }
+
+    /// Disables the interrupt `int`.
+    pub fn disable(&mut self, int: Interrupt) {
+        let (bank, bit) = int.bank_and_bit();
+        self.registers.DISABLE_IRQS[bank].write(bit);
+    }
+
+    /// Returns `true` if `int` is enabled.
+    pub fn is_enabled(&self, int: Interrupt) -> bool {
+        let (bank, bit) = int.bank_and_bit();
+        self.registers.ENABLE_IRQS[bank].has_mask(bit)
+    }
+
+    /// Returns `true` if `int` is pending.
+    pub fn is_pending(&self, int: Interrupt) -> bool {
+        let (bank, bit) = int.bank_and_bit();
+        self.registers.IRQ_PENDING[bank].has_mask(bit)
+    }
+
+    /// Routes `int` to the FIQ instead of the IRQ, replacing any interrupt
+    /// routed there before. Only one interrupt can be routed to the FIQ.
+    ///
+    /// `int` should be disabled as an IRQ first; otherwise it raises both.
+    pub fn enable_fiq(&mut self, int: Interrupt) {
+        self.registers.FIQ_CONTROL.write(FIQ_ENABLE | int as u32);
+    }
+
+    /// Stops routing any interrupt to the FIQ.
+    pub fn disable_fiq(&mut self) {
+        self.registers.FIQ_CONTROL.write(0);
+    }
+
+    /// Returns the interrupt routed to the FIQ, if any.
+    pub fn fiq(&self) -> Option<Interrupt> {
+        let control = self.registers.FIQ_CONTROL.read();
+        if control & FIQ_ENABLE == 0 {
+            return None;
+        }
+        let source = control & FIQ_SOURCE;
+        Interrupt::iter().find(|&int| int as u32 == source)
+    }
+}
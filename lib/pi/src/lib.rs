@@ -5,6 +5,15 @@
 
 pub mod atags;
 pub mod common;
+pub mod emmc;
 pub mod gpio;
+pub mod i2c;
+pub mod interrupt;
+#[cfg(not(feature = "bcm2835"))]
+pub mod local_interrupt;
+pub mod mailbox;
+pub mod pm;
+pub mod rng;
 pub mod timer;
 pub mod uart;
+pub mod usb;
@@ -0,0 +1,106 @@
+use volatile::prelude::*;
+use volatile::{register_block, ReadVolatile, Reserved, Volatile, WriteVolatile};
+
+use crate::common::{LOCAL_BASE, NCORES};
+
+/// An interrupt source local to a core. The value of each variant is its bit
+/// in the core's interrupt source register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LocalInterrupt {
+    /// The secure EL1 physical timer.
+    CntPs = 0,
+    /// The non-secure EL1 physical timer: `CNTP_*_EL0`.
+    CntPns = 1,
+    /// The EL2 physical timer.
+    CntHp = 2,
+    /// The virtual timer.
+    CntV = 3,
+    Mailbox0 = 4,
+    Mailbox1 = 5,
+    Mailbox2 = 6,
+    Mailbox3 = 7,
+    /// Any interrupt from the interrupt controller routed to this core.
+    Gpu = 8,
+    Pmu = 9,
+}
+
+register_block! {
+    struct Registers {
+        0x00 => __r0: [Reserved<u32>; 4],
+        0x10 => PMU_INT_ROUTING_SET: WriteVolatile<u32>,
+        0x14 => PMU_INT_ROUTING_CLR: WriteVolatile<u32>,
+        0x18 => __r1: [Reserved<u32>; 10],
+        0x40 => CORE_TIMER_INT_CONTROL: [Volatile<u32>; NCORES],
+        0x50 => CORE_MAILBOX_INT_CONTROL: [Volatile<u32>; NCORES],
+        0x60 => CORE_IRQ_SOURCE: [ReadVolatile<u32>; NCORES],
+        0x70 => CORE_FIQ_SOURCE: [ReadVolatile<u32>; NCORES],
+        0x80 => CORE_MAILBOX_SET: [WriteVolatile<u32>; 16],
+        0xc0 => CORE_MAILBOX_CLR: [Volatile<u32>; 16],
+        0x100 => @end,
+    }
+}
+
+/// The interrupts local to one core: its generic timers' and performance
+/// monitors' interrupts, which only that core takes, its mailboxes, which any
+/// core can write to interrupt it, and where its IRQs came from.
+pub struct LocalController {
+    core: usize,
+    registers: &'static mut Registers,
+}
+
+impl LocalController {
+    /// Returns a handle to the local interrupts of the core `core`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such core.
+    pub fn new(core: usize) -> LocalController {
+        assert!(core < NCORES, "no core {}", core);
+        LocalController {
+            core,
+            registers: unsafe { &mut *(LOCAL_BASE as *mut Registers) },
+        }
+    }
+
+    /// Routes the interrupt of the generic timer `timer`, one of the first
+    /// four local interrupts, to the core's IRQ.
+    pub fn enable_timer(&mut self, timer: LocalInterrupt) {
+        assert!((timer as u32) < 4, "{:?} is not a timer", timer);
+        self.registers.CORE_TIMER_INT_CONTROL[self.core].or_mask(1 << timer as u32);
+    }
+
+    /// Routes the interrupt of the core's performance monitors to its IRQ.
+    pub fn enable_pmu(&mut self) {
+        self.registers.PMU_INT_ROUTING_SET.write(1 << self.core);
+    }
+
+    /// Stops routing the interrupt of the core's performance monitors.
+    pub fn disable_pmu(&mut self) {
+        self.registers.PMU_INT_ROUTING_CLR.write(1 << self.core);
+    }
+
+    /// Routes the interrupt of the core's mailbox `mailbox`, 0 to 3, to its
+    /// IRQ. The interrupt is pending while any bit of the mailbox is set.
+    pub fn enable_mailbox(&mut self, mailbox: usize) {
+        assert!(mailbox < 4, "no mailbox {}", mailbox);
+        self.registers.CORE_MAILBOX_INT_CONTROL[self.core].or_mask(1 << mailbox);
+    }
+
+    /// Sets the bits `bits` of the core's mailbox `mailbox`, interrupting the
+    /// core if it routed the mailbox's interrupt to its IRQ.
+    pub fn send(&mut self, mailbox: usize, bits: u32) {
+        assert!(mailbox < 4, "no mailbox {}", mailbox);
+        self.registers.CORE_MAILBOX_SET[4 * self.core + mailbox].write(bits);
+    }
+
+    /// Clears the bits `bits` of the core's mailbox `mailbox`.
+    pub fn clear_mailbox(&mut self, mailbox: usize, bits: u32) {
+        assert!(mailbox < 4, "no mailbox {}", mailbox);
+        self.registers.CORE_MAILBOX_CLR[4 * self.core + mailbox].write(bits);
+    }
+
+    /// Returns `true` if `int` is pending on the core.
+    pub fn is_pending(&self, int: LocalInterrupt) -> bool {
+        self.registers.CORE_IRQ_SOURCE[self.core].has_mask(1 << int as u32)
+    }
+}
@@ -0,0 +1,85 @@
+use volatile::prelude::*;
+use volatile::{register_block, ReadVolatile, Reserved, Volatile};
+
+use crate::common::{io_addr, IO_BASE};
+
+/// The base address for the mailboxes' registers.
+const MAILBOX_REG_BASE: usize = IO_BASE + 0xB880;
+
+/// The `STATUS` bit set while a mailbox cannot take another message.
+const MAILBOX_FULL: u32 = 1 << 31;
+
+/// The `STATUS` bit set while a mailbox holds no message.
+const MAILBOX_EMPTY: u32 = 1 << 30;
+
+/// The bits of a message that carry its channel. The rest carry its data.
+const CHANNEL_MASK: u32 = 0xF;
+
+/// The channel of the firmware's property interface: its messages carry the
+/// bus address of a buffer of tags, which the firmware answers in place.
+pub const PROPERTY_CHANNEL: u8 = 8;
+
+register_block! {
+    /// One of the two mailboxes: messages are read from the first, which the
+    /// VideoCore writes, and written to the second, which it reads.
+    struct MailboxRegisters {
+        0x00 => DATA: Volatile<u32>,
+        0x04 => __r0: [Reserved<u32>; 3],
+        0x10 => PEEK: ReadVolatile<u32>,
+        0x14 => SENDER: ReadVolatile<u32>,
+        0x18 => STATUS: ReadVolatile<u32>,
+        0x1c => CONFIG: Volatile<u32>,
+        0x20 => @end,
+    }
+}
+
+register_block! {
+    struct Registers {
+        0x00 => read: MailboxRegisters,
+        0x20 => write: MailboxRegisters,
+        0x40 => @end,
+    }
+}
+
+/// The mailboxes the ARM cores talk to the VideoCore's firmware through.
+pub struct Mailbox {
+    registers: &'static mut Registers,
+}
+
+impl Mailbox {
+    /// Returns a new instance of `Mailbox`.
+    pub fn new() -> Mailbox {
+        let registers = unsafe { &mut *(io_addr(MAILBOX_REG_BASE) as *mut Registers) };
+        Mailbox { registers }
+    }
+
+    /// Sends `data` on `channel`, then waits for the reply on the same
+    /// channel and returns it. Only the top 28 bits of `data` and of the
+    /// reply are carried, so a buffer they point to must be 16-byte aligned.
+    ///
+    /// # Safety
+    ///
+    /// What the firmware does with `data` depends on the channel. On
+    /// `PROPERTY_CHANNEL`, it reads and writes the buffer at the bus address
+    /// `data`, which must stay valid, and clean in the data cache, until the
+    /// reply arrives.
+    pub unsafe fn call(&mut self, channel: u8, data: u32) -> u32 {
+        let channel = u32::from(channel) & CHANNEL_MASK;
+        // The barriers order the firmware's accesses to the buffer after the
+        // caller's before the call and before the caller's after it.
+        while self.registers.write.STATUS.has_mask(MAILBOX_FULL) {}
+        self.registers
+            .write
+            .DATA
+            .write_release((data & !CHANNEL_MASK) | channel);
+
+        // Replies on other channels are not ours; they are dropped.
+        loop {
+            while self.registers.read.STATUS.has_mask(MAILBOX_EMPTY) {}
+            let reply = self.registers.read.DATA.read_acquire();
+            if reply & CHANNEL_MASK == channel {
+                return reply & !CHANNEL_MASK;
+            }
+        }
+    }
+}
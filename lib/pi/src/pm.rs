@@ -0,0 +1,87 @@
+use core::time::Duration;
+
+use crate::common::{io_addr, IO_BASE};
+
+use volatile::prelude::*;
+use volatile::{register_block, Reserved, Volatile};
+
+/// The base address for the power management registers.
+const PM_REG_BASE: usize = IO_BASE + 0x100000;
+
+/// Must be written in the top byte of every write to a PM register, or the
+/// write is ignored.
+const PM_PASSWORD: u32 = 0x5a00_0000;
+
+/// The `RSTC` bits selecting what the watchdog resets when it fires.
+const PM_RSTC_WRCFG_MASK: u32 = 0x0000_0030;
+
+/// The `RSTC` setting for a full reset of the chip when the watchdog fires.
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x0000_0020;
+
+/// The `WDOG` bits holding the number of ticks left before the watchdog
+/// fires.
+const PM_WDOG_TIME_MASK: u32 = 0x000f_ffff;
+
+/// The rate at which the watchdog counts down, in ticks a second.
+const WDOG_TICKS_PER_SEC: u64 = 65536;
+
+/// The number of watchdog ticks (each about 16µs) before a requested reset.
+const RESET_TICKS: u32 = 10;
+
+register_block! {
+    struct Registers {
+        0x00 => __r0: [Reserved<u32>; 7],
+        0x1c => RSTC: Volatile<u32>,
+        0x20 => RSTS: Volatile<u32>,
+        0x24 => WDOG: Volatile<u32>,
+        0x28 => @end,
+    }
+}
+
+/// The Raspberry Pi power management block, whose watchdog can reset the
+/// board.
+pub struct PowerManager {
+    registers: &'static mut Registers,
+}
+
+impl PowerManager {
+    /// Returns a new instance of `PowerManager`.
+    pub fn new() -> PowerManager {
+        PowerManager {
+            registers: unsafe { &mut *(io_addr(PM_REG_BASE) as *mut Registers) },
+        }
+    }
+
+    /// Resets the board by arming the watchdog to fire almost immediately.
+    /// The firmware then boots the board as if it had been power cycled.
+    pub fn reset(&mut self) -> ! {
+        self.arm(RESET_TICKS);
+
+        // The reset happens within a few microseconds.
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Arms the watchdog to reset the board once `timeout` has passed, as
+    /// `reset()` does. Arming it again restarts the countdown. The watchdog
+    /// counts 20 bits of ticks, so a `timeout` over 16 seconds is cut to 16
+    /// seconds.
+    pub fn start_watchdog(&mut self, timeout: Duration) {
+        let ticks = timeout.as_micros() as u64 * WDOG_TICKS_PER_SEC / 1_000_000;
+        self.arm(ticks.min(PM_WDOG_TIME_MASK as u64) as u32);
+    }
+
+    /// Arms the watchdog to reset the board in `ticks` ticks.
+    fn arm(&mut self, ticks: u32) {
+        self.registers.WDOG.write(PM_PASSWORD | ticks);
+        self.registers
+            .RSTC
+            .update(|rstc| PM_PASSWORD | (rstc & !PM_RSTC_WRCFG_MASK) | PM_RSTC_WRCFG_FULL_RESET);
+    }
+}
+
+/// Resets the board. See `PowerManager::reset()`.
+pub fn reset() -> ! {
+    PowerManager::new().reset()
+}
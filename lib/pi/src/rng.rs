@@ -0,0 +1,67 @@
+use crate::common::{io_addr, IO_BASE};
+
+use volatile::prelude::*;
+use volatile::{register_block, ReadVolatile, Reserved, Volatile};
+
+/// The base address for the hardware random number generator's registers.
+const RNG_REG_BASE: usize = IO_BASE + 0x104000;
+
+/// The `CTRL` bit that enables the generator.
+const RNG_CTRL_RBGEN: u32 = 1;
+
+/// The `INT_MASK` bit that stops the generator raising interrupts.
+const RNG_INT_OFF: u32 = 1;
+
+/// The number of numbers the generator throws away once enabled, before its
+/// output is random enough to use.
+const WARMUP_COUNT: u32 = 0x40000;
+
+register_block! {
+    struct Registers {
+        0x00 => CTRL: Volatile<u32>,
+        0x04 => STATUS: Volatile<u32>,
+        0x08 => DATA: ReadVolatile<u32>,
+        0x0c => __r0: Reserved<u32>,
+        0x10 => INT_MASK: Volatile<u32>,
+        0x14 => @end,
+    }
+}
+
+/// The Raspberry Pi hardware random number generator.
+pub struct Rng {
+    registers: &'static mut Registers,
+}
+
+impl Rng {
+    /// Returns a new instance of `Rng`, enabling the generator if it is not
+    /// already. Its first numbers are then ready once it has warmed up.
+    pub fn new() -> Rng {
+        let registers = unsafe { &mut *(io_addr(RNG_REG_BASE) as *mut Registers) };
+        if !registers.CTRL.has_mask(RNG_CTRL_RBGEN) {
+            registers.STATUS.write(WARMUP_COUNT);
+            registers.INT_MASK.or_mask(RNG_INT_OFF);
+            registers.CTRL.or_mask(RNG_CTRL_RBGEN);
+        }
+        Rng { registers }
+    }
+
+    /// Waits for the generator to have a number ready, and returns it.
+    pub fn next_u32(&mut self) -> u32 {
+        // The top byte of `STATUS` counts the words ready to read.
+        while self.registers.STATUS.read() >> 24 == 0 {}
+        self.registers.DATA.read()
+    }
+
+    /// Fills `buf` with random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let word = self.next_u32().to_ne_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+}
+
+/// Fills `buf` with random bytes. See `Rng::fill()`.
+pub fn fill(buf: &mut [u8]) {
+    Rng::new().fill(buf)
+}
@@ -1,21 +1,34 @@
-use crate::common::IO_BASE;
+use crate::common::{io_addr, IO_BASE};
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 
 use volatile::prelude::*;
-use volatile::{ReadVolatile, Volatile};
+use volatile::{register_block, ReadVolatile, Volatile};
 
 /// The base address for the ARM system timer registers.
 const TIMER_REG_BASE: usize = IO_BASE + 0x3000;
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct Registers {
-    CS: Volatile<u32>,
-    CLO: ReadVolatile<u32>,
-    CHI: ReadVolatile<u32>,
-    COMPARE: [Volatile<u32>; 4],
+/// The longest the generic timer's event stream may go between events for
+/// `spin_sleep()` to wait for them.
+pub const EVENT_STREAM_PERIOD: Duration = Duration::from_micros(100);
+
+/// Whether `spin_sleep()` waits for events between reads of the timer.
+static EVENT_STREAM: AtomicBool = AtomicBool::new(false);
+
+register_block! {
+    struct Registers {
+        0x00 => CS: Volatile<u32>,
+        0x04 => CLO: ReadVolatile<u32>,
+        0x08 => CHI: ReadVolatile<u32>,
+        0x0c => COMPARE: [Volatile<u32>; 4],
+        0x1c => @end,
+    }
 }
 
+/// The system timer compare channel used for ticks. The GPU uses channels 0
+/// and 2; a match on channel 1 raises `Interrupt::Timer1`.
+const TICK_CHANNEL: usize = 1;
+
 /// The Raspberry Pi ARM system timer.
 pub struct Timer {
     registers: &'static mut Registers,
@@ -25,7 +38,7 @@ impl Timer {
     /// Returns a new instance of `Timer`.
     pub fn new() -> Timer {
         Timer {
-            registers: unsafe { &mut *(TIMER_REG_BASE as *mut Registers) },
+            registers: unsafe { &mut *(io_addr(TIMER_REG_BASE) as *mut Registers) },
         }
     }
 
@@ -48,6 +61,36 @@ impl Timer {
         let time_in_micros = ((high_word as u64) << 32) | (low_word as u64);
         Duration::from_micros(time_in_micros)
     }
+
+    /// Sets up a match on the tick channel `t` from now, raising
+    /// `Interrupt::Timer1`. Also acknowledges any earlier match.
+    pub fn tick_in(&mut self, t: Duration) {
+        let now = self.registers.CLO.read();
+        self.arm(now.wrapping_add(t.as_micros() as u32));
+    }
+
+    /// Sets up a match on the tick channel `t` after its previous match, so
+    /// that periodic ticks do not drift, or `t` from now if that has already
+    /// passed. Also acknowledges the previous match.
+    pub fn tick_again(&mut self, t: Duration) {
+        let now = self.registers.CLO.read();
+        let micros = t.as_micros() as u32;
+        let next = self.registers.COMPARE[TICK_CHANNEL]
+            .read()
+            .wrapping_add(micros);
+        if (next.wrapping_sub(now) as i32) > 0 {
+            self.arm(next);
+        } else {
+            self.arm(now.wrapping_add(micros));
+        }
+    }
+
+    /// Sets the tick channel to match when the low word of the counter
+    /// reaches `at` and clears its current match.
+    fn arm(&mut self, at: u32) {
+        self.registers.COMPARE[TICK_CHANNEL].write(at);
+        self.registers.CS.write(1 << TICK_CHANNEL);
+    }
 }
 
 /// Returns current time.
@@ -55,7 +98,14 @@ pub fn current_time() -> Duration {
     Timer::new().read()
 }
 
-/// Spins until `t` duration have passed.
+/// Sets up a match on the tick channel `t` from now. See `Timer::tick_in()`.
+pub fn tick_in(t: Duration) {
+    Timer::new().tick_in(t)
+}
+
+/// Spins until `t` duration have passed. Once `enable_event_stream()` has
+/// been called, the core sleeps between reads of the timer, except for the
+/// last `EVENT_STREAM_PERIOD`.
 pub fn spin_sleep(t: Duration) {
     let timer = Timer::new();
     let start_time = timer.read();
@@ -66,6 +116,55 @@ pub fn spin_sleep(t: Duration) {
 
         if delta >= t {
             break;
+        } else if t - delta > EVENT_STREAM_PERIOD && EVENT_STREAM.load(Ordering::Relaxed) {
+            wait_for_event();
+        }
+    }
+}
+
+/// Lets `spin_sleep()` sleep until the next event rather than spin.
+///
+/// # Safety
+///
+/// Every core that calls `spin_sleep()` from now on must have its generic
+/// timer's event stream enabled, with at most `EVENT_STREAM_PERIOD` between
+/// events. Otherwise it may sleep until something else wakes it.
+pub unsafe fn enable_event_stream() {
+    EVENT_STREAM.store(true, Ordering::Relaxed);
+}
+
+/// Suspends the core until the next event.
+fn wait_for_event() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("wfe", options(nomem, nostack));
+    }
+}
+
+/// Measures the time elapsed since it was started, for timing code.
+#[derive(Debug, Copy, Clone)]
+pub struct Stopwatch {
+    start: Duration,
+}
+
+impl Stopwatch {
+    /// Returns a stopwatch started now.
+    pub fn start() -> Stopwatch {
+        Stopwatch {
+            start: current_time(),
         }
     }
+
+    /// Returns the time elapsed since the stopwatch was started.
+    pub fn elapsed(&self) -> Duration {
+        current_time() - self.start
+    }
+
+    /// Restarts the stopwatch, returning the time elapsed before the restart.
+    pub fn restart(&mut self) -> Duration {
+        let now = current_time();
+        let elapsed = now - self.start;
+        self.start = now;
+        elapsed
+    }
 }
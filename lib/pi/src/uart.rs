@@ -1,13 +1,12 @@
 use core::fmt;
 use core::time::Duration;
 
-use shim::const_assert_size;
 use shim::io;
 
 use volatile::prelude::*;
-use volatile::{ReadVolatile, Reserved, Volatile};
+use volatile::{register_block, Field, ReadVolatile, Volatile};
 
-use crate::common::IO_BASE;
+use crate::common::{io_addr, IO_BASE, SOC};
 use crate::gpio::{Function, Gpio};
 use crate::timer;
 
@@ -15,7 +14,10 @@ use crate::timer;
 const MU_REG_BASE: usize = IO_BASE + 0x215040;
 
 /// The `AUXENB` register from page 9 of the BCM2837 documentation.
-const AUX_ENABLES: *mut Volatile<u8> = (IO_BASE + 0x215004) as *mut Volatile<u8>;
+const AUX_ENABLES: usize = IO_BASE + 0x215004;
+
+/// The baud rate the mini UART runs at.
+const BAUD_RATE: u32 = 115200;
 
 /// Enum representing bit fields of the `AUX_MU_LSR_REG` register.
 #[repr(u8)]
@@ -24,23 +26,53 @@ enum LsrStatus {
     TxAvailable = 1 << 5,
 }
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct Registers {
-    IO: Volatile<u32>,
-    IER: Volatile<u32>,
-    IIR: Volatile<u32>,
-    LCR: Volatile<u32>,
-    MCR: Volatile<u32>,
-    LSR: ReadVolatile<u32>,
-    MSR: ReadVolatile<u32>,
-    SCRATCH: Volatile<u32>,
-    CNTL: Volatile<u32>,
-    STAT: ReadVolatile<u32>,
-    BAUD: Volatile<u32>,
+/// The data sizes the `AUX_MU_LCR_REG` register's `DATA_SIZE` field selects.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum DataSize {
+    SevenBit = 0b00,
+    EightBit = 0b11,
 }
 
-const_assert_size!(Registers, 0x7E21506C - 0x7E215040);
+impl FieldValue<u32> for DataSize {
+    fn from_bits(bits: u32) -> DataSize {
+        match bits {
+            0b11 => DataSize::EightBit,
+            _ => DataSize::SevenBit,
+        }
+    }
+
+    fn into_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+/// The mini UART's enable bit in `AUXENB`.
+const AUX_MINI_UART_ENABLE: Field<u8, bool> = Field::new(0, 1);
+
+/// Fields of the `AUX_MU_IER_REG`, `AUX_MU_LCR_REG` and `AUX_MU_CNTL_REG`
+/// registers. The BCM2837 documentation has the interrupt enable bits
+/// swapped: bit 0 enables the receive interrupt.
+const IER_RX_INTERRUPT: Field<u32, bool> = Field::new(0, 1);
+const LCR_DATA_SIZE: Field<u32, DataSize> = Field::new(0, 2);
+const CNTL_RX_ENABLE: Field<u32, bool> = Field::new(0, 1);
+const CNTL_TX_ENABLE: Field<u32, bool> = Field::new(1, 1);
+
+register_block! {
+    struct Registers {
+        0x00 => IO: Volatile<u32>,
+        0x04 => IER: Volatile<u32>,
+        0x08 => IIR: Volatile<u32>,
+        0x0c => LCR: Volatile<u32>,
+        0x10 => MCR: Volatile<u32>,
+        0x14 => LSR: ReadVolatile<u32>,
+        0x18 => MSR: ReadVolatile<u32>,
+        0x1c => SCRATCH: Volatile<u32>,
+        0x20 => CNTL: Volatile<u32>,
+        0x24 => STAT: ReadVolatile<u32>,
+        0x28 => BAUD: Volatile<u32>,
+        0x2c => @end,
+    }
+}
 
 /// The Raspberry Pi's "mini UART".
 pub struct MiniUart {
@@ -51,31 +83,34 @@ pub struct MiniUart {
 impl MiniUart {
     /// Initializes the mini UART by enabling it as an auxiliary peripheral,
     /// setting the data size to 8 bits, setting the BAUD rate to ~115200 (baud
-    /// divider of 270), setting GPIO pins 14 and 15 to alternative function 5
-    /// (TXD1/RDXD1), and finally enabling the UART transmitter and receiver.
+    /// divider of 270 on the Pi 3), setting GPIO pins 14 and 15 to alternative
+    /// function 5 (TXD1/RDXD1), and finally enabling the UART transmitter and
+    /// receiver.
     ///
     /// By default, reads will never time out. To set a read timeout, use
     /// `set_read_timeout()`.
     pub fn new() -> MiniUart {
         let registers = unsafe {
             // Enable the mini UART as an auxiliary device.
-            (*AUX_ENABLES).or_mask(1);
-            &mut *(MU_REG_BASE as *mut Registers)
+            (*(io_addr(AUX_ENABLES) as *mut Volatile<u8>)).write_field(AUX_MINI_UART_ENABLE, true);
+            &mut *(io_addr(MU_REG_BASE) as *mut Registers)
         };
 
         // Set data size to 8 bits
-        registers.LCR.or_mask(3);
+        registers.LCR.write_field(LCR_DATA_SIZE, DataSize::EightBit);
 
         // Set baud rate. Keep in mind that the baud rate is calculated
         // as sys_clock_freq / (8 * (register_value + 1))
-        registers.BAUD.write(270);
+        let divider = SOC.core_clock_hz() / (8 * BAUD_RATE) - 1;
+        registers.BAUD.write(divider);
 
         // turn on GPIO pins
         let tx_pin = Gpio::new(14).into_alt(Function::Alt5);
         let rx_pin = Gpio::new(15).into_alt(Function::Alt5);
 
         // enable the TX and RX
-        registers.CNTL.or_mask(3);
+        registers.CNTL.write_field(CNTL_RX_ENABLE, true);
+        registers.CNTL.write_field(CNTL_TX_ENABLE, true);
 
         MiniUart {
             registers,
@@ -88,6 +123,17 @@ impl MiniUart {
         self.timeout = Some(t);
     }
 
+    /// Removes the read timeout so that reads block until a byte arrives.
+    pub fn clear_read_timeout(&mut self) {
+        self.timeout = None;
+    }
+
+    /// Enables or disables the receive interrupt, raised through
+    /// `Interrupt::Aux` while there is data to read.
+    pub fn set_rx_interrupt(&mut self, enabled: bool) {
+        self.registers.IER.write_field(IER_RX_INTERRUPT, enabled);
+    }
+
     /// Write the byte `byte`. This method blocks until there is space available
     /// in the output FIFO.
     pub fn write_byte(&mut self, byte: u8) {
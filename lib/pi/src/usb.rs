@@ -0,0 +1,577 @@
+use core::cmp::min;
+use core::fmt;
+use core::time::Duration;
+
+use volatile::prelude::*;
+use volatile::{register_block, ReadVolatile, Reserved, Volatile, VolatileArray};
+
+use crate::common::{io_addr, IO_BASE};
+use crate::timer;
+
+/// The base address for the USB host controller's registers: a Synopsys
+/// DesignWare Hi-Speed USB 2.0 On-The-Go controller, or DWC2.
+const USB_REG_BASE: usize = IO_BASE + 0x980000;
+
+/// The number of host channels, each of which carries one transfer at a time.
+const CHANNELS: usize = 8;
+
+/// What `GSNPSID` holds in its top half: the ASCII for "OT", for On-The-Go.
+const CORE_ID: u32 = 0x4F54_0000;
+
+/// `GAHBCFG` bits.
+const AHB_DMA_ENABLE: u32 = 1 << 5;
+const AHB_WAIT_AXI_WRITES: u32 = 1 << 4;
+const AHB_MAX_AXI_BURST: u32 = 0b11 << 1;
+
+/// `GUSBCFG` bits.
+const USB_ULPI_EXT_VBUS_DRV: u32 = 1 << 20;
+const USB_TERM_SEL_DL_PULSE: u32 = 1 << 22;
+const USB_FORCE_HOST_MODE: u32 = 1 << 29;
+const USB_FORCE_DEV_MODE: u32 = 1 << 30;
+
+/// `GRSTCTL` bits.
+const RST_CORE_SOFT: u32 = 1 << 0;
+const RST_RX_FIFO_FLUSH: u32 = 1 << 4;
+const RST_TX_FIFO_FLUSH: u32 = 1 << 5;
+const RST_TX_FIFO_ALL: u32 = 0x10 << 6;
+const RST_AHB_IDLE: u32 = 1 << 31;
+
+/// The sizes of the receive, non-periodic transmit and periodic transmit
+/// FIFOs, in words.
+const RX_FIFO_SIZE: u32 = 1024;
+const NP_TX_FIFO_SIZE: u32 = 1024;
+const P_TX_FIFO_SIZE: u32 = 1024;
+
+/// `HPRT` bits. Writing 1 to the bits in `HPRT_WRITE_CLEAR` clears them; in
+/// particular, writing 1 to `HPRT_ENABLE` disables the port.
+const HPRT_CONNECT: u32 = 1 << 0;
+const HPRT_CONNECT_CHANGED: u32 = 1 << 1;
+const HPRT_ENABLE: u32 = 1 << 2;
+const HPRT_ENABLE_CHANGED: u32 = 1 << 3;
+const HPRT_OVERCURRENT_CHANGED: u32 = 1 << 5;
+const HPRT_RESET: u32 = 1 << 8;
+const HPRT_POWER: u32 = 1 << 12;
+const HPRT_SPEED_SHIFT: u32 = 17;
+const HPRT_WRITE_CLEAR: u32 =
+    HPRT_CONNECT_CHANGED | HPRT_ENABLE | HPRT_ENABLE_CHANGED | HPRT_OVERCURRENT_CHANGED;
+
+/// `HCCHAR` bits and fields.
+const HCCHAR_EP_SHIFT: u32 = 11;
+const HCCHAR_IN: u32 = 1 << 15;
+const HCCHAR_LOW_SPEED: u32 = 1 << 17;
+const HCCHAR_TYPE_SHIFT: u32 = 18;
+const HCCHAR_ONE_PER_FRAME: u32 = 1 << 20;
+const HCCHAR_ADDR_SHIFT: u32 = 22;
+const HCCHAR_ODD_FRAME: u32 = 1 << 29;
+const HCCHAR_DISABLE: u32 = 1 << 30;
+const HCCHAR_ENABLE: u32 = 1 << 31;
+
+/// `HCSPLT` bits and fields.
+const HCSPLT_HUB_SHIFT: u32 = 7;
+const HCSPLT_ALL: u32 = 3 << 14;
+const HCSPLT_COMPLETE: u32 = 1 << 16;
+const HCSPLT_ENABLE: u32 = 1 << 31;
+
+/// `HCINT` bits: why a channel halted.
+const HCINT_COMPLETE: u32 = 1 << 0;
+const HCINT_HALTED: u32 = 1 << 1;
+const HCINT_AHB_ERROR: u32 = 1 << 2;
+const HCINT_STALL: u32 = 1 << 3;
+const HCINT_NAK: u32 = 1 << 4;
+const HCINT_ACK: u32 = 1 << 5;
+const HCINT_NYET: u32 = 1 << 6;
+const HCINT_TRANSACTION_ERROR: u32 = 1 << 7;
+const HCINT_BABBLE: u32 = 1 << 8;
+const HCINT_DATA_TOGGLE_ERROR: u32 = 1 << 10;
+
+/// `HCTSIZ` fields.
+const HCTSIZ_SIZE_MASK: u32 = (1 << 19) - 1;
+const HCTSIZ_PACKETS_SHIFT: u32 = 19;
+const HCTSIZ_PID_SHIFT: u32 = 29;
+
+/// How long a channel may take to carry out a transaction.
+const CHANNEL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How many times a split transaction is started again after a transaction
+/// error, and its completion asked for again while the hub has no answer yet.
+const SPLIT_RETRIES: usize = 8;
+const COMPLETE_RETRIES: usize = 64;
+
+register_block! {
+    /// The registers of one host channel.
+    struct Channel {
+        0x00 => HCCHAR: Volatile<u32>,
+        0x04 => HCSPLT: Volatile<u32>,
+        0x08 => HCINT: Volatile<u32>,
+        0x0c => HCINTMSK: Volatile<u32>,
+        0x10 => HCTSIZ: Volatile<u32>,
+        0x14 => HCDMA: Volatile<u32>,
+        0x18 => @end,
+    }
+}
+
+register_block! {
+    struct Registers {
+        0x000 => GOTGCTL: Volatile<u32>,
+        0x004 => GOTGINT: Volatile<u32>,
+        0x008 => GAHBCFG: Volatile<u32>,
+        0x00c => GUSBCFG: Volatile<u32>,
+        0x010 => GRSTCTL: Volatile<u32>,
+        0x014 => GINTSTS: Volatile<u32>,
+        0x018 => GINTMSK: Volatile<u32>,
+        0x01c => GRXSTSR: ReadVolatile<u32>,
+        0x020 => GRXSTSP: ReadVolatile<u32>,
+        0x024 => GRXFSIZ: Volatile<u32>,
+        0x028 => GNPTXFSIZ: Volatile<u32>,
+        0x02c => GNPTXSTS: ReadVolatile<u32>,
+        0x030 => __r0: [Reserved<u32>; 4],
+        0x040 => GSNPSID: ReadVolatile<u32>,
+        0x044 => GHWCFG: [ReadVolatile<u32>; 4],
+        0x054 => __r1: [Reserved<u32>; 43],
+        0x100 => HPTXFSIZ: Volatile<u32>,
+        0x104 => __r2: [Reserved<u32>; 191],
+        0x400 => HCFG: Volatile<u32>,
+        0x404 => HFIR: Volatile<u32>,
+        0x408 => HFNUM: ReadVolatile<u32>,
+        0x40c => __r3: Reserved<u32>,
+        0x410 => HPTXSTS: ReadVolatile<u32>,
+        0x414 => HAINT: ReadVolatile<u32>,
+        0x418 => HAINTMSK: Volatile<u32>,
+        0x41c => __r4: [Reserved<u32>; 9],
+        0x440 => HPRT: Volatile<u32>,
+        0x444 => __r5: [Reserved<u32>; 47],
+        0x500 => HC: VolatileArray<Channel, CHANNELS, 0x20>,
+        0x600 => __r6: [Reserved<u32>; 512],
+        0xe00 => PCGCCTL: Volatile<u32>,
+        0xe04 => @end,
+    }
+}
+
+/// The speed of a USB device, as `HPRT` reports it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Speed {
+    High = 0,
+    Full = 1,
+    Low = 2,
+}
+
+/// The type of an endpoint, as its descriptor gives it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EndpointType {
+    Control = 0,
+    Isochronous = 1,
+    Bulk = 2,
+    Interrupt = 3,
+}
+
+/// The packet ID a transfer starts with, as `HCTSIZ` encodes it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pid {
+    Data0 = 0,
+    Data2 = 1,
+    Data1 = 2,
+    Setup = 3,
+}
+
+impl Pid {
+    fn from_bits(bits: u32) -> Pid {
+        match bits & 0b11 {
+            0 => Pid::Data0,
+            1 => Pid::Data2,
+            2 => Pid::Data1,
+            _ => Pid::Setup,
+        }
+    }
+}
+
+/// A transfer between the host and one endpoint of a device.
+#[derive(Debug, Copy, Clone)]
+pub struct Transfer {
+    /// The device's address.
+    pub device: u8,
+    /// The endpoint's number.
+    pub endpoint: u8,
+    pub kind: EndpointType,
+    pub speed: Speed,
+    /// The endpoint's maximum packet size.
+    pub max_packet: u16,
+    /// Whether data moves from the device to the host.
+    pub input: bool,
+    /// The packet ID of the transfer's first packet.
+    pub pid: Pid,
+    /// For a low- or full-speed device behind a high-speed hub, the hub's
+    /// address and the port the device is reached through, in split
+    /// transactions.
+    pub split: Option<(u8, u8)>,
+}
+
+impl Transfer {
+    fn is_periodic(&self) -> bool {
+        self.kind == EndpointType::Interrupt || self.kind == EndpointType::Isochronous
+    }
+}
+
+/// Why a transfer failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The endpoint had no data to send or no room for more: for interrupt
+    /// endpoints, nothing happened since the last transfer.
+    Nak,
+    /// The endpoint is halted, or does not support the request.
+    Stall,
+    /// A packet was corrupted or not answered.
+    Transaction,
+    /// The device sent more than was asked for.
+    Babble,
+    /// A packet had the wrong data toggle.
+    DataToggle,
+    /// The controller could not reach memory.
+    Ahb,
+    /// The channel did not halt in time, or halted for no known reason.
+    Timeout,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Error::Nak => "not acknowledged",
+            Error::Stall => "endpoint stalled",
+            Error::Transaction => "transaction error",
+            Error::Babble => "babble",
+            Error::DataToggle => "data toggle error",
+            Error::Ahb => "DMA error",
+            Error::Timeout => "timed out",
+        })
+    }
+}
+
+/// Returns the error `HCINT` reports, if any.
+fn error(hcint: u32) -> Option<Error> {
+    if hcint & HCINT_STALL != 0 {
+        Some(Error::Stall)
+    } else if hcint & HCINT_BABBLE != 0 {
+        Some(Error::Babble)
+    } else if hcint & HCINT_AHB_ERROR != 0 {
+        Some(Error::Ahb)
+    } else if hcint & HCINT_TRANSACTION_ERROR != 0 {
+        Some(Error::Transaction)
+    } else if hcint & HCINT_DATA_TOGGLE_ERROR != 0 {
+        Some(Error::DataToggle)
+    } else if hcint & HCINT_NAK != 0 {
+        Some(Error::Nak)
+    } else {
+        None
+    }
+}
+
+/// Which half of a split transaction a channel carries out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Split {
+    None,
+    Start(u8, u8),
+    Complete(u8, u8),
+}
+
+/// What a channel reported when it halted.
+struct Halt {
+    hcint: u32,
+    /// The bytes it did not transfer.
+    remaining: usize,
+    /// The packet ID the next transfer with the endpoint starts with.
+    pid: Pid,
+}
+
+/// The USB host controller, driven with one channel, in DMA mode, without
+/// interrupts: each transfer is carried out while the caller waits.
+pub struct Host {
+    registers: &'static mut Registers,
+}
+
+impl Host {
+    /// Returns a new instance of `Host`.
+    pub fn new() -> Host {
+        let registers = unsafe { &mut *(io_addr(USB_REG_BASE) as *mut Registers) };
+        Host { registers }
+    }
+
+    /// Returns `true` if the controller is there to drive. Emulators may not
+    /// have one.
+    pub fn is_present(&self) -> bool {
+        self.registers.GSNPSID.read() & 0xFFFF_0000 == CORE_ID
+    }
+
+    /// Resets the controller into host mode, with DMA on, and powers its
+    /// port. The controller's power must be on. Returns `false` if the
+    /// controller does not come out of reset.
+    pub fn initialize(&mut self) -> bool {
+        self.registers.GINTMSK.write(0);
+        self.registers.GAHBCFG.and_mask(!1);
+
+        self.registers.GUSBCFG.update(|usbcfg| {
+            let usbcfg = usbcfg & !(USB_ULPI_EXT_VBUS_DRV | USB_TERM_SEL_DL_PULSE);
+            (usbcfg | USB_FORCE_HOST_MODE) & !USB_FORCE_DEV_MODE
+        });
+        if !self.reset_core() {
+            return false;
+        }
+        // The core takes this long to switch modes.
+        timer::spin_sleep(Duration::from_millis(50));
+
+        self.registers
+            .GAHBCFG
+            .update(|ahbcfg| (ahbcfg & !AHB_MAX_AXI_BURST) | AHB_DMA_ENABLE | AHB_WAIT_AXI_WRITES);
+        self.registers.PCGCCTL.write(0);
+        self.registers.HCFG.and_mask(!0b11);
+
+        self.registers.GRXFSIZ.write(RX_FIFO_SIZE);
+        self.registers
+            .GNPTXFSIZ
+            .write(NP_TX_FIFO_SIZE << 16 | RX_FIFO_SIZE);
+        self.registers
+            .HPTXFSIZ
+            .write(P_TX_FIFO_SIZE << 16 | (RX_FIFO_SIZE + NP_TX_FIFO_SIZE));
+        self.flush_fifos();
+
+        for channel in self.registers.HC.iter_mut() {
+            channel.HCINTMSK.write(0);
+            channel.HCINT.write(!0);
+        }
+
+        let hprt = self.registers.HPRT.read() & !HPRT_WRITE_CLEAR;
+        if hprt & HPRT_POWER == 0 {
+            self.registers.HPRT.write(hprt | HPRT_POWER);
+        }
+        true
+    }
+
+    /// Resets the core. Returns `false` if it does not come out of reset.
+    fn reset_core(&mut self) -> bool {
+        if !wait(|| self.registers.GRSTCTL.has_mask(RST_AHB_IDLE)) {
+            return false;
+        }
+        self.registers.GRSTCTL.or_mask(RST_CORE_SOFT);
+        if !wait(|| !self.registers.GRSTCTL.has_mask(RST_CORE_SOFT)) {
+            return false;
+        }
+        timer::spin_sleep(Duration::from_millis(100));
+        true
+    }
+
+    /// Empties the FIFOs.
+    fn flush_fifos(&mut self) {
+        self.registers
+            .GRSTCTL
+            .write(RST_TX_FIFO_FLUSH | RST_TX_FIFO_ALL);
+        wait(|| !self.registers.GRSTCTL.has_mask(RST_TX_FIFO_FLUSH));
+
+        self.registers.GRSTCTL.write(RST_RX_FIFO_FLUSH);
+        wait(|| !self.registers.GRSTCTL.has_mask(RST_RX_FIFO_FLUSH));
+    }
+
+    /// Returns `true` if a device is attached to the controller's port.
+    pub fn is_connected(&self) -> bool {
+        self.registers.HPRT.has_mask(HPRT_CONNECT)
+    }
+
+    /// Resets the device on the controller's port, which enables the port.
+    /// Returns the device's speed, or `None` if the port is not enabled.
+    pub fn reset_port(&mut self) -> Option<Speed> {
+        let hprt = self.registers.HPRT.read() & !HPRT_WRITE_CLEAR;
+        self.registers.HPRT.write(hprt | HPRT_RESET);
+        timer::spin_sleep(Duration::from_millis(50));
+        self.registers.HPRT.write(hprt & !HPRT_RESET);
+        timer::spin_sleep(Duration::from_millis(20));
+
+        let hprt = self.registers.HPRT.read();
+        if hprt & HPRT_ENABLE == 0 {
+            return None;
+        }
+
+        match (hprt >> HPRT_SPEED_SHIFT) & 0b11 {
+            0 => Some(Speed::High),
+            1 => Some(Speed::Full),
+            2 => Some(Speed::Low),
+            _ => None,
+        }
+    }
+
+    /// Carries out `transfer` of `len` bytes to or from the buffer at the bus
+    /// address `dma`, which must be 4-byte aligned. Returns the number of
+    /// bytes transferred, which is short of `len` if the device sent a short
+    /// packet, and the packet ID the next transfer with the endpoint starts
+    /// with.
+    ///
+    /// # Safety
+    ///
+    /// The controller reads or writes up to `len` bytes at `dma`, rounded up
+    /// to a whole packet for input, which must not be cached in the meantime.
+    pub unsafe fn transfer(
+        &mut self,
+        transfer: &Transfer,
+        dma: u32,
+        len: usize,
+    ) -> Result<(usize, Pid), Error> {
+        let (hub, port) = match transfer.split {
+            Some(split) => split,
+            None => {
+                let halt = self.run(transfer, transfer.pid, dma, len, Split::None)?;
+                return match error(halt.hcint) {
+                    Some(error) => Err(error),
+                    None if halt.hcint & HCINT_COMPLETE == 0 => Err(Error::Timeout),
+                    None => Ok((len.saturating_sub(halt.remaining), halt.pid)),
+                };
+            }
+        };
+
+        // A split transaction carries a single packet.
+        let (mut done, mut pid) = (0, transfer.pid);
+        loop {
+            let packet = min(len - done, usize::from(transfer.max_packet));
+            let addr = dma + done as u32;
+            let (n, next) = self.split(transfer, pid, addr, packet, hub, port)?;
+            done += n;
+            pid = next;
+            if n < packet || done == len {
+                return Ok((done, pid));
+            }
+        }
+    }
+
+    /// Carries out one packet of `transfer` as a split transaction, through
+    /// `port` of the hub at address `hub`: the hub is told to start the
+    /// transaction, then asked for its outcome until it has it.
+    unsafe fn split(
+        &mut self,
+        transfer: &Transfer,
+        pid: Pid,
+        dma: u32,
+        len: usize,
+        hub: u8,
+        port: u8,
+    ) -> Result<(usize, Pid), Error> {
+        let mut last = Error::Transaction;
+        for _ in 0..SPLIT_RETRIES {
+            if transfer.is_periodic() {
+                // Start periodic splits early enough in a frame for their
+                // completions to fit in it.
+                wait(|| self.registers.HFNUM.read() & 0b111 < 5);
+            }
+
+            let start = self.run(transfer, pid, dma, len, Split::Start(hub, port))?;
+            match error(start.hcint) {
+                Some(Error::Transaction) => continue,
+                Some(error) => return Err(error),
+                None if start.hcint & HCINT_ACK == 0 => return Err(Error::Timeout),
+                None => {}
+            }
+
+            for _ in 0..COMPLETE_RETRIES {
+                let end = self.run(transfer, pid, dma, len, Split::Complete(hub, port))?;
+                if end.hcint & HCINT_NYET != 0 {
+                    // The hub has no outcome yet: try in the next microframe.
+                    timer::spin_sleep(Duration::from_micros(125));
+                    continue;
+                }
+
+                last = match error(end.hcint) {
+                    Some(Error::Transaction) => Error::Transaction,
+                    Some(error) => return Err(error),
+                    None if end.hcint & HCINT_COMPLETE == 0 => Error::Timeout,
+                    None => return Ok((len.saturating_sub(end.remaining), end.pid)),
+                };
+                break;
+            }
+        }
+        Err(last)
+    }
+
+    /// Has channel 0 carry out one transaction, or one half of a split one,
+    /// and waits for it to halt.
+    unsafe fn run(
+        &mut self,
+        transfer: &Transfer,
+        pid: Pid,
+        dma: u32,
+        len: usize,
+        split: Split,
+    ) -> Result<Halt, Error> {
+        let max_packet = usize::from(transfer.max_packet).max(1);
+        let packets = len.div_ceil(max_packet).max(1) as u32;
+        let mut hcchar = u32::from(transfer.max_packet)
+            | u32::from(transfer.endpoint) << HCCHAR_EP_SHIFT
+            | (transfer.kind as u32) << HCCHAR_TYPE_SHIFT
+            | HCCHAR_ONE_PER_FRAME
+            | u32::from(transfer.device) << HCCHAR_ADDR_SHIFT;
+        if transfer.input {
+            hcchar |= HCCHAR_IN;
+        }
+        if transfer.speed == Speed::Low {
+            hcchar |= HCCHAR_LOW_SPEED;
+        }
+        let hcsplt = match split {
+            Split::None => 0,
+            Split::Start(hub, port) => {
+                HCSPLT_ENABLE | HCSPLT_ALL | u32::from(hub) << HCSPLT_HUB_SHIFT | u32::from(port)
+            }
+            Split::Complete(hub, port) => {
+                HCSPLT_ENABLE
+                    | HCSPLT_ALL
+                    | HCSPLT_COMPLETE
+                    | u32::from(hub) << HCSPLT_HUB_SHIFT
+                    | u32::from(port)
+            }
+        };
+
+        let registers = &mut *self.registers;
+        let channel = &mut registers.HC[0];
+        channel.HCINT.write(!0);
+        channel.HCSPLT.write(hcsplt);
+        channel.HCTSIZ.write(
+            len as u32 & HCTSIZ_SIZE_MASK
+                | packets << HCTSIZ_PACKETS_SHIFT
+                | (pid as u32) << HCTSIZ_PID_SHIFT,
+        );
+        channel.HCDMA.write(dma);
+        if transfer.is_periodic() && registers.HFNUM.read() & 1 == 0 {
+            // Go out in the next frame, which is odd.
+            hcchar |= HCCHAR_ODD_FRAME;
+        }
+        // The barriers order the controller's DMA after the caller's accesses
+        // to the buffer before the transfer and before those after it.
+        channel.HCCHAR.write_release(hcchar | HCCHAR_ENABLE);
+
+        let channel = &registers.HC[0];
+        if !wait_for(CHANNEL_TIMEOUT, || channel.HCINT.has_mask(HCINT_HALTED)) {
+            let channel = &mut registers.HC[0];
+            channel.HCCHAR.or_mask(HCCHAR_DISABLE | HCCHAR_ENABLE);
+            let channel = &registers.HC[0];
+            wait(|| channel.HCINT.has_mask(HCINT_HALTED));
+            return Err(Error::Timeout);
+        }
+
+        let hctsiz = channel.HCTSIZ.read();
+        Ok(Halt {
+            hcint: channel.HCINT.read_acquire(),
+            remaining: (hctsiz & HCTSIZ_SIZE_MASK) as usize,
+            pid: Pid::from_bits(hctsiz >> HCTSIZ_PID_SHIFT),
+        })
+    }
+}
+
+/// Waits for `f` to return `true`, for at most `CHANNEL_TIMEOUT`. Returns
+/// `false` if it did not.
+fn wait<F: FnMut() -> bool>(f: F) -> bool {
+    wait_for(CHANNEL_TIMEOUT, f)
+}
+
+/// Waits for `f` to return `true`, for at most `timeout`. Returns `false` if
+/// it did not.
+fn wait_for<F: FnMut() -> bool>(timeout: Duration, mut f: F) -> bool {
+    let deadline = timer::current_time() + timeout;
+    while !f() {
+        if timer::current_time() >= deadline {
+            return false;
+        }
+    }
+    true
+}
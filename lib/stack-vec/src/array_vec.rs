@@ -0,0 +1,265 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut, Index, IndexMut};
+use core::slice::{self, SliceIndex};
+
+use crate::{CapacityError, StackVec, StackVecIter};
+
+/// A contiguous array type that holds up to `N` elements inline.
+///
+/// `ArrayVec` is a `StackVec` that owns its storage rather than borrowing a
+/// slice, so it can be kept in long-lived structures and in statics: `new()`
+/// is a `const fn`. Since it owns its elements, it drops those it truncates
+/// and those it still holds when it is dropped.
+pub struct ArrayVec<T, const N: usize> {
+    /// Slots `0..len` are initialized.
+    storage: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    /// Constructs a new, empty `ArrayVec<T, N>`, which will be able to hold
+    /// `N` values.
+    pub const fn new() -> ArrayVec<T, N> {
+        ArrayVec {
+            // An array of uninitialized slots needs no initialization.
+            storage: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            len: 0,
+        }
+    }
+
+    /// Calls `f` with a `StackVec` of this vector's elements, then keeps what
+    /// `f` left in it.
+    fn with_stack_vec<R>(&mut self, f: impl FnOnce(&mut StackVec<'_, T>) -> R) -> R {
+        // Should `f` panic, the guard still keeps what it left.
+        let len = self.len;
+        let mut guard = SetLenOnDrop {
+            vec: StackVec {
+                storage: &mut self.storage,
+                len,
+            },
+            len: &mut self.len,
+        };
+        f(&mut guard.vec)
+    }
+
+    /// Returns the number of elements this vector can hold: `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// rest. If `len` is greater than the vector's current length, this has no
+    /// effect.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.with_stack_vec(|vec| drop(vec.drain(len..)));
+        }
+    }
+
+    /// Drops every element.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Extracts a slice containing the entire vector.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.storage.as_ptr() as *const T, self.len) }
+    }
+
+    /// Extracts a mutable slice of the entire vector.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut T, self.len) }
+    }
+
+    /// Returns the number of elements in the vector.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the vector contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the vector is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Appends `value` to the back of this vector if the vector is not full.
+    /// See `StackVec::push()`.
+    pub fn push(&mut self, value: T) -> Result<(), CapacityError> {
+        self.with_stack_vec(|vec| vec.push(value))
+    }
+
+    /// Inserts `value` at position `index` if the vector is not full. See
+    /// `StackVec::insert()`.
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        self.with_stack_vec(|vec| vec.insert(index, value))
+    }
+
+    /// Removes the last element and returns it, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        self.with_stack_vec(|vec| vec.pop())
+    }
+
+    /// Removes the element at position `index` and returns it. See
+    /// `StackVec::remove()`.
+    pub fn remove(&mut self, index: usize) -> T {
+        self.with_stack_vec(|vec| vec.remove(index))
+    }
+
+    /// Removes the element at position `index` and returns it, replacing it
+    /// with the last element. See `StackVec::swap_remove()`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        self.with_stack_vec(|vec| vec.swap_remove(index))
+    }
+
+    /// Keeps only the elements for which `f` returns `true`, in order, and
+    /// drops the rest.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        self.with_stack_vec(|vec| vec.retain(f))
+    }
+}
+
+/// A `StackVec` over an `ArrayVec`'s elements, which gives the `ArrayVec` its
+/// length when dropped, even by a panic.
+struct SetLenOnDrop<'v, T> {
+    vec: StackVec<'v, T>,
+    len: &'v mut usize,
+}
+
+impl<'v, T> Drop for SetLenOnDrop<'v, T> {
+    fn drop(&mut self) {
+        *self.len = self.vec.len;
+    }
+}
+
+impl<T: Clone, const N: usize> ArrayVec<T, N> {
+    /// Appends clones of as many of the elements of `other` as fit. See
+    /// `StackVec::extend_from_slice()`.
+    pub fn extend_from_slice(&mut self, other: &[T]) -> Result<usize, CapacityError> {
+        self.with_stack_vec(|vec| vec.extend_from_slice(other))
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut vec = ArrayVec::new();
+        let _ = vec.extend_from_slice(self.as_slice());
+        vec
+    }
+}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> Self {
+        ArrayVec::new()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize, const M: usize> PartialEq<ArrayVec<U, M>>
+    for ArrayVec<T, N>
+{
+    fn eq(&self, other: &ArrayVec<U, M>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: PartialEq<U>, U, const N: usize> PartialEq<[U]> for ArrayVec<T, N> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<'b, T: PartialEq<U>, U, const N: usize> PartialEq<&'b [U]> for ArrayVec<T, N> {
+    fn eq(&self, other: &&'b [U]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for ArrayVec<T, N> {}
+
+impl<T: PartialOrd, const N: usize> PartialOrd for ArrayVec<T, N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.as_slice().partial_cmp(other.as_slice())
+    }
+}
+
+impl<T: Ord, const N: usize> Ord for ArrayVec<T, N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl<T: Hash, const N: usize> Hash for ArrayVec<T, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const N: usize> Index<I> for ArrayVec<T, N> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const N: usize> IndexMut<I> for ArrayVec<T, N> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+/// Appends elements until the vector is full. The rest are not taken from the
+/// iterator.
+impl<T, const N: usize> Extend<T> for ArrayVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.with_stack_vec(|vec| vec.extend(iter))
+    }
+}
+
+/// Appends copies of elements until the vector is full.
+impl<'b, T: Copy + 'b, const N: usize> Extend<&'b T> for ArrayVec<T, N> {
+    fn extend<I: IntoIterator<Item = &'b T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
+impl<'v, T, const N: usize> IntoIterator for &'v ArrayVec<T, N> {
+    type IntoIter = StackVecIter<'v, T>;
+    type Item = &'v T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        StackVecIter::new(self.as_slice())
+    }
+}
@@ -0,0 +1,161 @@
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut, RangeBounds};
+
+use crate::{CapacityError, Drain, StackVec, StackVecIter};
+
+/// A `StackVec` that drops its elements.
+///
+/// A `StackVec` leaves the elements it truncates, and those it still holds
+/// when it is dropped, in its slice. `DroppingStackVec` drops them instead, as
+/// `Vec` does, so that it can hold values with destructors, like locks'
+/// guards. It is a type of its own, rather than a mode of `StackVec`, so that
+/// only vectors that drop their elements are borrow checked as if they did.
+pub struct DroppingStackVec<'a, T> {
+    vec: StackVec<'a, T>,
+}
+
+impl<'a, T> DroppingStackVec<'a, T> {
+    /// Constructs a new, empty `DroppingStackVec<T>` using the uninitialized
+    /// slots of `storage` as the backing store. The returned vector will be
+    /// able to hold `storage.len()` values.
+    pub fn new(storage: &'a mut [MaybeUninit<T>]) -> DroppingStackVec<'a, T> {
+        DroppingStackVec {
+            vec: StackVec::uninit(storage),
+        }
+    }
+
+    /// Returns the number of elements this vector can hold.
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// rest. If `len` is greater than the vector's current length, this has no
+    /// effect.
+    pub fn truncate(&mut self, len: usize) {
+        self.vec.truncate_with_drop(len);
+    }
+
+    /// Drops every element.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Extracts a slice containing the entire vector.
+    pub fn as_slice(&self) -> &[T] {
+        self.vec.as_slice()
+    }
+
+    /// Extracts a mutable slice of the entire vector.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.vec.as_mut_slice()
+    }
+
+    /// Returns the number of elements in the vector.
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// Returns true if the vector contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Returns true if the vector is at capacity.
+    pub fn is_full(&self) -> bool {
+        self.vec.is_full()
+    }
+
+    /// Appends `value` to the back of this vector if the vector is not full.
+    /// See `StackVec::push()`.
+    pub fn push(&mut self, value: T) -> Result<(), CapacityError> {
+        self.vec.push(value)
+    }
+
+    /// Inserts `value` at position `index` if the vector is not full. See
+    /// `StackVec::insert()`.
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        self.vec.insert(index, value)
+    }
+
+    /// Removes the last element and returns it, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        self.vec.pop()
+    }
+
+    /// Removes the element at position `index` and returns it. See
+    /// `StackVec::remove()`.
+    pub fn remove(&mut self, index: usize) -> T {
+        self.vec.remove(index)
+    }
+
+    /// Removes the element at position `index` and returns it, replacing it
+    /// with the last element. See `StackVec::swap_remove()`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        self.vec.swap_remove(index)
+    }
+
+    /// Keeps only the elements for which `f` returns `true`, in order, and
+    /// drops the rest.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) {
+        self.vec.retain(f)
+    }
+
+    /// Removes the elements in `range` from the vector, returning them in an
+    /// iterator. See `StackVec::drain()`.
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<'_, 'a, T> {
+        self.vec.drain(range)
+    }
+}
+
+impl<'a, T: Clone> DroppingStackVec<'a, T> {
+    /// Appends clones of as many of the elements of `other` as fit. See
+    /// `StackVec::extend_from_slice()`.
+    pub fn extend_from_slice(&mut self, other: &[T]) -> Result<usize, CapacityError> {
+        self.vec.extend_from_slice(other)
+    }
+}
+
+impl<'a, T> Drop for DroppingStackVec<'a, T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for DroppingStackVec<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl<'a, T> Deref for DroppingStackVec<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<'a, T> DerefMut for DroppingStackVec<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+/// Appends elements until the vector is full. The rest are not taken from the
+/// iterator.
+impl<'a, T> Extend<T> for DroppingStackVec<'a, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.vec.extend(iter)
+    }
+}
+
+impl<'v, 'a, T> IntoIterator for &'v DroppingStackVec<'a, T> {
+    type IntoIter = StackVecIter<'v, T>;
+    type Item = &'v T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        StackVecIter::new(self.as_slice())
+    }
+}
@@ -3,30 +3,63 @@
 #[cfg(test)]
 mod tests;
 
+mod array_vec;
+mod dropping_stack_vec;
+mod stack_string;
+
+pub use self::array_vec::ArrayVec;
+pub use self::dropping_stack_vec::DroppingStackVec;
+pub use self::stack_string::{PushBytesError, StackString};
+
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use core::iter::IntoIterator;
-use core::ops::{Deref, DerefMut};
-use core::slice;
+use core::mem::MaybeUninit;
+use core::ops::{Bound, Deref, DerefMut, Index, IndexMut, RangeBounds};
+use core::ptr;
+use core::slice::{self, SliceIndex};
 
 /// A contiguous array type backed by a slice.
 ///
 /// `StackVec`'s functionality is similar to that of `std::Vec`. You can `push`
-/// and `pop` and iterate over the vector. Unlike `Vec`, however, `StackVec`
-/// requires no memory allocation as it is backed by a user-supplied slice. As a
-/// result, `StackVec`'s capacity is _bounded_ by the user-supplied slice. This
-/// results in `push` being fallible: if `push` is called when the vector is
-/// full, an `Err` is returned.
-#[derive(Debug)]
+/// and `pop`, `insert` and `remove`, and iterate over the vector. Unlike `Vec`,
+/// however, `StackVec` requires no memory allocation as it is backed by a
+/// user-supplied slice. As a result, `StackVec`'s capacity is _bounded_ by the
+/// user-supplied slice. This results in `push` being fallible: if `push` is
+/// called when the vector is full, an `Err` is returned.
+///
+/// The slice is either one of initialized `Copy` values, given to `new()`, or
+/// one of uninitialized slots for any type, given to `uninit()`. Either way,
+/// elements are moved in and out of it, so none need be `Clone`. Elements
+/// still in the vector when it is dropped or truncated are not dropped, but
+/// left in the slice: use `truncate_with_drop()` to drop them, or a
+/// `DroppingStackVec` to hold values with destructors, like locks' guards.
 pub struct StackVec<'a, T> {
-    storage: &'a mut [T],
+    /// Slots `0..len` are initialized. `StackVec` only ever writes
+    /// initialized values, so that a slice of initialized `Copy` values
+    /// stays initialized.
+    storage: &'a mut [MaybeUninit<T>],
     len: usize,
 }
 
-impl<'a, T> StackVec<'a, T> {
+/// The error returned when what is added to a `StackVec`, `ArrayVec` or
+/// `StackString` does not fit in it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CapacityError;
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("not enough capacity")
+    }
+}
+
+impl<'a, T: Copy> StackVec<'a, T> {
     /// Constructs a new, empty `StackVec<T>` using `storage` as the backing
     /// store. The returned `StackVec` will be able to hold `storage.len()`
     /// values.
     pub fn new(storage: &'a mut [T]) -> StackVec<'a, T> {
-        Self { storage, len: 0 }
+        StackVec::with_len(storage, 0)
     }
 
     /// Constructs a new `StackVec<T>` using `storage` as the backing store. The
@@ -42,8 +75,24 @@ impl<'a, T> StackVec<'a, T> {
             panic!("Attempted to create StackVec larger than storage allocatd");
         }
 
+        // `T` and `MaybeUninit<T>` have the same layout, and `StackVec` never
+        // writes an uninitialized value, so `storage` stays initialized. As
+        // `T` is `Copy`, moving an element out leaves it as it was.
+        let len_storage = storage.len();
+        let storage = unsafe {
+            slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut MaybeUninit<T>, len_storage)
+        };
         Self { storage, len }
     }
+}
+
+impl<'a, T> StackVec<'a, T> {
+    /// Constructs a new, empty `StackVec<T>` using the uninitialized slots of
+    /// `storage` as the backing store. The returned `StackVec` will be able to
+    /// hold `storage.len()` values.
+    pub fn uninit(storage: &'a mut [MaybeUninit<T>]) -> StackVec<'a, T> {
+        Self { storage, len: 0 }
+    }
 
     /// Returns the number of elements this vector can hold.
     pub fn capacity(&self) -> usize {
@@ -59,22 +108,32 @@ impl<'a, T> StackVec<'a, T> {
         }
     }
 
+    /// Shortens the vector, keeping the first `len` elements and dropping the
+    /// rest. If `len` is greater than the vector's current length, this has no
+    /// effect.
+    pub fn truncate_with_drop(&mut self, len: usize) {
+        if len < self.len {
+            drop(self.drain(len..));
+        }
+    }
+
     /// Extracts a slice containing the entire vector, consuming `self`.
     ///
     /// Note that the returned slice's length will be the length of this vector,
     /// _not_ the length of the original backing storage.
     pub fn into_slice(self) -> &'a mut [T] {
-        &mut self.storage[0..self.len]
+        let StackVec { storage, len } = self;
+        unsafe { slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut T, len) }
     }
 
     /// Extracts a slice containing the entire vector.
     pub fn as_slice(&self) -> &[T] {
-        &self.storage[0..self.len]
+        unsafe { slice::from_raw_parts(self.storage.as_ptr() as *const T, self.len) }
     }
 
     /// Extracts a mutable slice of the entire vector.
     pub fn as_mut_slice(&mut self) -> &mut [T] {
-        &mut self.storage[0..self.len]
+        unsafe { slice::from_raw_parts_mut(self.storage.as_mut_ptr() as *mut T, self.len) }
     }
 
     /// Returns the number of elements in the vector, also referred to as its
@@ -99,28 +158,261 @@ impl<'a, T> StackVec<'a, T> {
     ///
     /// If this vector is full, an `Err` is returned. Otherwise, `Ok` is
     /// returned.
-    pub fn push(&mut self, value: T) -> Result<(), ()> {
+    pub fn push(&mut self, value: T) -> Result<(), CapacityError> {
         if self.is_full() {
-            Err(())
+            Err(CapacityError)
         } else {
-            self.storage[self.len] = value;
+            self.storage[self.len] = MaybeUninit::new(value);
+            self.len += 1;
+            Ok(())
+        }
+    }
+
+    /// Inserts `value` at position `index` if the vector is not full,
+    /// shifting all elements after it to the right.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full, `value` is returned in an `Err`. Otherwise,
+    /// `Ok` is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), T> {
+        if index > self.len {
+            panic!("insertion index {} is past the end, {}", index, self.len);
+        }
+
+        if self.is_full() {
+            Err(value)
+        } else {
+            self.storage[self.len] = MaybeUninit::new(value);
+            self.storage[index..=self.len].rotate_right(1);
             self.len += 1;
             Ok(())
         }
     }
-}
 
-impl<'a, T: Clone> StackVec<'a, T> {
     /// If this vector is not empty, removes the last element from this vector
-    /// by cloning it and returns it. Otherwise returns `None`.
+    /// and returns it. Otherwise returns `None`.
     pub fn pop(&mut self) -> Option<T> {
         if self.is_empty() {
             None
         } else {
             self.len -= 1;
-            let val = self.storage[self.len].clone();
-            Some(val)
+            Some(unsafe { self.take(self.len) })
+        }
+    }
+
+    /// Removes the element at position `index` and returns it, shifting all
+    /// elements after it to the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> T {
+        if index >= self.len {
+            panic!("removal index {} is out of bounds, {}", index, self.len);
+        }
+
+        self.storage[index..self.len].rotate_left(1);
+        self.len -= 1;
+        unsafe { self.take(self.len) }
+    }
+
+    /// Removes the element at position `index` and returns it, replacing it
+    /// with the last element. This does not preserve ordering, but is O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        if index >= self.len {
+            panic!("swap_remove index {} is out of bounds, {}", index, self.len);
+        }
+
+        self.len -= 1;
+        self.storage.swap(index, self.len);
+        unsafe { self.take(self.len) }
+    }
+
+    /// Keeps only the elements for which `f` returns `true`, in order, and
+    /// drops the rest.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        // Should `f` or a destructor panic, the elements are leaked rather
+        // than dropped twice.
+        let len = self.len;
+        self.len = 0;
+
+        let mut kept = 0;
+        for i in 0..len {
+            if f(unsafe { &*self.storage[i].as_ptr() }) {
+                // Slot `kept` was moved out of, if it is not slot `i`.
+                self.storage.swap(kept, i);
+                kept += 1;
+            } else {
+                drop(unsafe { self.take(i) });
+            }
+        }
+        self.len = kept;
+    }
+
+    /// Removes the elements in `range` from the vector, returning them in an
+    /// iterator. The elements after them are shifted left once the iterator
+    /// is dropped, and any it did not yield are dropped then.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of `range` is after its end, or its end is after
+    /// the end of the vector.
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<'_, 'a, T> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len,
+        };
+        if start > end || end > self.len {
+            panic!(
+                "drain range {}..{} is out of bounds, {}",
+                start, end, self.len
+            );
+        }
+
+        // Until the iterator is dropped, the drained elements and those after
+        // them are not part of the vector, so that they are leaked rather than
+        // dropped twice should it be forgotten.
+        let len = self.len;
+        self.len = start;
+        Drain {
+            vec: self,
+            start,
+            next: start,
+            end,
+            len,
+        }
+    }
+
+    /// Moves the element out of slot `index`.
+    ///
+    /// # Safety
+    ///
+    /// The slot must be initialized, and must not be read again until it is
+    /// written.
+    unsafe fn take(&mut self, index: usize) -> T {
+        ptr::read(self.storage[index].as_ptr())
+    }
+}
+
+impl<'a, T: Clone> StackVec<'a, T> {
+    /// Appends clones of as many of the elements of `other` as fit, in order,
+    /// and returns the number appended.
+    ///
+    /// # Error
+    ///
+    /// If this vector is full and `other` is not empty, an `Err` is returned.
+    /// Otherwise, `Ok` is returned, even if not all of `other` fit.
+    pub fn extend_from_slice(&mut self, other: &[T]) -> Result<usize, CapacityError> {
+        if self.is_full() && !other.is_empty() {
+            return Err(CapacityError);
+        }
+
+        let n = other.len().min(self.capacity() - self.len);
+        for (slot, value) in self.storage[self.len..].iter_mut().zip(&other[..n]) {
+            *slot = MaybeUninit::new(value.clone());
+            self.len += 1;
+        }
+        Ok(n)
+    }
+
+    /// Clones this vector into `storage`, returning a `StackVec` backed by
+    /// it. `StackVec` cannot be `Clone`, since the clone needs storage of its
+    /// own.
+    ///
+    /// # Error
+    ///
+    /// If `storage` cannot hold every element of this vector, an `Err` is
+    /// returned.
+    pub fn clone_into<'b>(
+        &self,
+        storage: &'b mut [MaybeUninit<T>],
+    ) -> Result<StackVec<'b, T>, CapacityError> {
+        if storage.len() < self.len {
+            return Err(CapacityError);
         }
+
+        let mut vec = StackVec::uninit(storage);
+        vec.extend_from_slice(self.as_slice())?;
+        Ok(vec)
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for StackVec<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl<'a, 'b, T: PartialEq<U>, U> PartialEq<StackVec<'b, U>> for StackVec<'a, T> {
+    fn eq(&self, other: &StackVec<'b, U>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<'a, T: PartialEq<U>, U> PartialEq<[U]> for StackVec<'a, T> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<'a, 'b, T: PartialEq<U>, U> PartialEq<&'b [U]> for StackVec<'a, T> {
+    fn eq(&self, other: &&'b [U]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+impl<'a, T: PartialEq<U>, U, const N: usize> PartialEq<[U; N]> for StackVec<'a, T> {
+    fn eq(&self, other: &[U; N]) -> bool {
+        self.as_slice() == &other[..]
+    }
+}
+
+impl<'a, T: Eq> Eq for StackVec<'a, T> {}
+
+impl<'a, T: PartialOrd> PartialOrd for StackVec<'a, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.as_slice().partial_cmp(other.as_slice())
+    }
+}
+
+impl<'a, T: Ord> Ord for StackVec<'a, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl<'a, T: Hash> Hash for StackVec<'a, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl<'a, T, I: SliceIndex<[T]>> Index<I> for StackVec<'a, T> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<'a, T, I: SliceIndex<[T]>> IndexMut<I> for StackVec<'a, T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
     }
 }
 
@@ -138,6 +430,27 @@ impl<'a, T> DerefMut for StackVec<'a, T> {
     }
 }
 
+/// Appends elements until the vector is full. The rest are not taken from the
+/// iterator.
+impl<'a, T> Extend<T> for StackVec<'a, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+        while !self.is_full() {
+            match iter.next() {
+                Some(value) => self.push(value).expect("not full"),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Appends copies of elements until the vector is full.
+impl<'a, 'b, T: Copy + 'b> Extend<&'b T> for StackVec<'a, T> {
+    fn extend<I: IntoIterator<Item = &'b T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied())
+    }
+}
+
 /// Iterates over a StackVec in order. Note that this order is different than
 /// what you would get by pushing and then popping -- values are iterated in
 /// the order they were pushed.
@@ -166,12 +479,80 @@ impl<'a, T> Iterator for StackVecIter<'a, T> {
     }
 }
 
+/// Moves the values out of a StackVec in the order they were pushed. Values
+/// left when the iterator is dropped are not dropped.
+pub struct IntoIter<'a, T> {
+    vec: StackVec<'a, T>,
+    index: usize,
+}
+
+impl<'a, T> Iterator for IntoIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.vec.len {
+            None
+        } else {
+            // Each slot is read once: the vector is not used as one again.
+            let val = unsafe { self.vec.take(self.index) };
+            self.index += 1;
+            Some(val)
+        }
+    }
+}
+
 impl<'a, T> IntoIterator for StackVec<'a, T> {
-    type IntoIter = StackVecIter<'a, T>;
-    type Item = &'a T;
+    type IntoIter = IntoIter<'a, T>;
+    type Item = T;
 
     fn into_iter(self) -> Self::IntoIter {
-        StackVecIter::new(self.into_slice())
+        IntoIter {
+            vec: self,
+            index: 0,
+        }
+    }
+}
+
+/// Moves a range of elements out of a StackVec, in order. Returned by
+/// `StackVec::drain()`.
+pub struct Drain<'v, 'a, T> {
+    vec: &'v mut StackVec<'a, T>,
+    /// The start of the drained range.
+    start: usize,
+    /// The next element to yield.
+    next: usize,
+    /// The end of the drained range.
+    end: usize,
+    /// The length of the vector before it was drained.
+    len: usize,
+}
+
+impl<'v, 'a, T> Iterator for Drain<'v, 'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            None
+        } else {
+            let val = unsafe { self.vec.take(self.next) };
+            self.next += 1;
+            Some(val)
+        }
+    }
+}
+
+impl<'v, 'a, T> Drop for Drain<'v, 'a, T> {
+    fn drop(&mut self) {
+        for val in self.by_ref() {
+            drop(val);
+        }
+
+        let tail = self.len - self.end;
+        unsafe {
+            let storage = self.vec.storage.as_mut_ptr();
+            ptr::copy(storage.add(self.end), storage.add(self.start), tail);
+        }
+        self.vec.len = self.start + tail;
     }
 }
 
@@ -0,0 +1,200 @@
+use core::fmt;
+use core::ops::Deref;
+use core::str;
+
+use crate::{CapacityError, StackVec};
+
+/// A UTF-8 string backed by a slice of bytes.
+///
+/// `StackString` is to `String` what `StackVec` is to `Vec`: it never
+/// allocates, and pushing to it fails once the slice is full. Text can be
+/// pushed as `str`s and `char`s, or as bytes with `push_bytes()`, which checks
+/// them as they come, so that a character can arrive over several calls.
+/// `as_str()` never includes a character that is not yet complete.
+pub struct StackString<'a> {
+    /// Bytes `0..valid` of `buf` are UTF-8. Any after them begin a character
+    /// that `push_bytes()` has not yet been given the rest of.
+    buf: StackVec<'a, u8>,
+    valid: usize,
+}
+
+/// Why `StackString::push_bytes()` could not push bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PushBytesError {
+    /// The bytes do not fit.
+    Full,
+    /// The bytes are not UTF-8, even if more were to follow them.
+    InvalidUtf8,
+}
+
+impl<'a> StackString<'a> {
+    /// Constructs a new, empty `StackString` using `storage` as the backing
+    /// store. The returned `StackString` will be able to hold
+    /// `storage.len()` bytes.
+    pub fn new(storage: &'a mut [u8]) -> StackString<'a> {
+        StackString {
+            buf: StackVec::new(storage),
+            valid: 0,
+        }
+    }
+
+    /// Returns the number of bytes this string can hold.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Returns the length of this string in bytes, not counting an incomplete
+    /// character.
+    pub fn len(&self) -> usize {
+        self.valid
+    }
+
+    /// Returns true if this string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.valid == 0
+    }
+
+    /// Extracts a string slice of the entire string.
+    pub fn as_str(&self) -> &str {
+        // Bytes `0..valid` were checked, or pushed as a `str`.
+        unsafe { str::from_utf8_unchecked(&self.buf[..self.valid]) }
+    }
+
+    /// Extracts a string slice of the entire string, consuming `self`.
+    pub fn into_str(self) -> &'a str {
+        let valid = self.valid;
+        unsafe { str::from_utf8_unchecked(&self.buf.into_slice()[..valid]) }
+    }
+
+    /// Appends `c` to the end of this string if it fits. An incomplete
+    /// character left by `push_bytes()` is dropped first, since `c` cannot
+    /// complete it.
+    ///
+    /// # Error
+    ///
+    /// If `c` does not fit, an `Err` is returned and the string is unchanged.
+    pub fn push(&mut self, c: char) -> Result<(), CapacityError> {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Appends `s` to the end of this string if all of it fits. An incomplete
+    /// character left by `push_bytes()` is dropped first, since `s` cannot
+    /// complete it.
+    ///
+    /// # Error
+    ///
+    /// If `s` does not fit, an `Err` is returned and the string is unchanged.
+    pub fn push_str(&mut self, s: &str) -> Result<(), CapacityError> {
+        if s.len() > self.capacity() - self.valid {
+            return Err(CapacityError);
+        }
+
+        self.buf.truncate(self.valid);
+        self.buf.extend_from_slice(s.as_bytes())?;
+        self.valid = self.buf.len();
+        Ok(())
+    }
+
+    /// Appends `bytes` to the end of this string if all of them fit and they
+    /// are UTF-8. `bytes` may start by completing a character the last bytes
+    /// pushed began, and may end by beginning one; it is left out of the
+    /// string until the bytes that complete it are pushed.
+    ///
+    /// # Error
+    ///
+    /// If `bytes` do not fit, or are not UTF-8, an `Err` saying which is
+    /// returned and the string is unchanged.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), PushBytesError> {
+        let len = self.buf.len();
+        if bytes.len() > self.capacity() - len {
+            return Err(PushBytesError::Full);
+        }
+
+        let _ = self.buf.extend_from_slice(bytes);
+        match str::from_utf8(&self.buf[self.valid..]) {
+            Ok(_) => self.valid = self.buf.len(),
+            Err(e) if e.error_len().is_none() => self.valid += e.valid_up_to(),
+            Err(_) => {
+                self.buf.truncate(len);
+                return Err(PushBytesError::InvalidUtf8);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the last character from this string and returns it, or
+    /// returns `None` if it is empty. An incomplete character left by
+    /// `push_bytes()` is dropped.
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str().chars().next_back()?;
+        self.valid -= c.len_utf8();
+        self.buf.truncate(self.valid);
+        Some(c)
+    }
+
+    /// Shortens this string to `len` bytes. If `len` is greater than the
+    /// string's current length, this has no effect. An incomplete character
+    /// left by `push_bytes()` is dropped either way.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` does not lie on a character boundary.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.valid {
+            if !self.as_str().is_char_boundary(len) {
+                panic!("truncation length {} is not a character boundary", len);
+            }
+            self.valid = len;
+        }
+        self.buf.truncate(self.valid);
+    }
+
+    /// Empties this string.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl<'a> Deref for StackString<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl<'a> fmt::Debug for StackString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a> fmt::Display for StackString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a> PartialEq<str> for StackString<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for StackString<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Fails once a piece of the text written does not fit. The pieces before it
+/// are kept.
+impl<'a> fmt::Write for StackString<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push(c).map_err(|_| fmt::Error)
+    }
+}
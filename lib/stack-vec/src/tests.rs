@@ -1,4 +1,9 @@
-use crate::StackVec;
+extern crate std;
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{ArrayVec, CapacityError, DroppingStackVec, PushBytesError, StackString, StackVec};
 
 #[test]
 fn assignment_text_example() {
@@ -119,7 +124,7 @@ fn pop() {
         assert_eq!(stack_vec.len(), i + 1);
     }
 
-    for i in 1023..=0 {
+    for i in (0..1024).rev() {
         assert_eq!(stack_vec.len(), i + 1);
         assert_eq!(stack_vec.pop(), Some(i));
         assert_eq!(stack_vec.len(), i);
@@ -171,16 +176,12 @@ fn iterator() {
         assert_eq!(*val, i * i);
     }
 
-    let mut i = 0;
-    for val in &stack_vec {
+    for (i, val) in (&stack_vec).into_iter().enumerate() {
         assert_eq!(*val, i * i);
-        i += 1;
     }
 
-    let mut i = 0;
-    for val in stack_vec {
-        assert_eq!(*val, i * i);
-        i += 1;
+    for (i, val) in stack_vec.into_iter().enumerate() {
+        assert_eq!(val, i * i);
     }
 }
 
@@ -211,12 +212,448 @@ fn errors() {
         assert_eq!(vec.push(i), Ok(()));
     }
     for i in 0..1024 {
-        assert_eq!(vec.push(i), Err(()));
+        assert_eq!(vec.push(i), Err(CapacityError));
     }
-    for i in 1023..=0 {
+    for i in (0..1024).rev() {
         assert_eq!(vec.pop(), Some(i));
     }
-    for _ in 1023..=0 {
+    for _ in 0..1024 {
         assert_eq!(vec.pop(), None);
     }
 }
+
+#[test]
+fn insert_and_remove() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::new(&mut storage);
+    assert_eq!(vec.insert(0, 2), Ok(()));
+    assert_eq!(vec.insert(0, 0), Ok(()));
+    assert_eq!(vec.insert(2, 3), Ok(()));
+    assert_eq!(vec.insert(1, 1), Ok(()));
+    assert_eq!(vec.as_slice(), &[0, 1, 2, 3]);
+    assert_eq!(vec.insert(2, 10), Err(10));
+    assert_eq!(vec.as_slice(), &[0, 1, 2, 3]);
+
+    assert_eq!(vec.remove(1), 1);
+    assert_eq!(vec.as_slice(), &[0, 2, 3]);
+    assert_eq!(vec.remove(2), 3);
+    assert_eq!(vec.remove(0), 0);
+    assert_eq!(vec.as_slice(), &[2]);
+    assert_eq!(vec.insert(1, 4), Ok(()));
+    assert_eq!(vec.as_slice(), &[2, 4]);
+}
+
+#[test]
+#[should_panic]
+fn insert_oob() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::new(&mut storage);
+    vec.push(1).expect("cap = 4");
+    let _ = vec.insert(2, 2);
+}
+
+#[test]
+#[should_panic]
+fn remove_oob() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::with_len(&mut storage, 2);
+    vec.truncate(1);
+    vec.remove(1);
+}
+
+/// A value that is neither `Copy` nor `Clone`.
+#[derive(Debug, PartialEq)]
+struct Token(usize);
+
+const EMPTY: MaybeUninit<Token> = MaybeUninit::uninit();
+
+#[test]
+fn into_iter_moves_values() {
+    let mut storage = [EMPTY; 4];
+    let mut vec = StackVec::uninit(&mut storage);
+    for i in 1..4 {
+        vec.push(Token(i)).expect("cap = 4");
+    }
+
+    let mut iter = vec.into_iter();
+    assert_eq!(iter.next(), Some(Token(1)));
+    assert_eq!(iter.next(), Some(Token(2)));
+    assert_eq!(iter.next(), Some(Token(3)));
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn moves_values_out() {
+    let mut storage = [EMPTY; 4];
+    let mut vec = StackVec::uninit(&mut storage);
+    assert_eq!(vec.capacity(), 4);
+    assert_eq!(vec.pop(), None);
+
+    for i in 0..4 {
+        vec.push(Token(i)).expect("cap = 4");
+    }
+    assert_eq!(vec.push(Token(4)), Err(CapacityError));
+    assert_eq!(vec.remove(1), Token(1));
+    assert_eq!(vec.pop(), Some(Token(3)));
+    assert_eq!(vec.insert(0, Token(5)), Ok(()));
+    assert_eq!(vec.as_slice(), &[Token(5), Token(0), Token(2)]);
+    assert_eq!(vec.pop(), Some(Token(2)));
+    assert_eq!(vec.pop(), Some(Token(0)));
+    assert_eq!(vec.pop(), Some(Token(5)));
+    assert_eq!(vec.pop(), None);
+}
+
+#[test]
+fn extend_from_slice() {
+    let mut storage = [0u8; 6];
+    let mut vec = StackVec::new(&mut storage);
+    assert_eq!(vec.extend_from_slice(b""), Ok(0));
+    assert_eq!(vec.extend_from_slice(b"abcd"), Ok(4));
+    assert_eq!(vec.extend_from_slice(b"efgh"), Ok(2));
+    assert_eq!(vec.as_slice(), b"abcdef");
+    assert_eq!(vec.extend_from_slice(b"i"), Err(CapacityError));
+    assert_eq!(vec.extend_from_slice(b""), Ok(0));
+}
+
+#[test]
+fn extend_stops_when_full() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::new(&mut storage);
+    vec.extend(0..2);
+    vec.extend(&[2, 3, 4]);
+    assert_eq!(vec.as_slice(), &[0, 1, 2, 3]);
+
+    let mut rest = 10..20;
+    vec.truncate(3);
+    vec.extend(&mut rest);
+    assert_eq!(vec.as_slice(), &[0, 1, 2, 10]);
+    assert_eq!(rest.next(), Some(11));
+}
+
+#[test]
+fn swap_remove() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::new(&mut storage);
+    vec.extend(0..4);
+    assert_eq!(vec.swap_remove(1), 1);
+    assert_eq!(vec.as_slice(), &[0, 3, 2]);
+    assert_eq!(vec.swap_remove(2), 2);
+    assert_eq!(vec.as_slice(), &[0, 3]);
+}
+
+#[test]
+#[should_panic]
+fn swap_remove_oob() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::new(&mut storage);
+    vec.push(0).expect("cap = 4");
+    vec.swap_remove(1);
+}
+
+#[test]
+fn retain() {
+    let mut storage = [0usize; 8];
+    let mut vec = StackVec::new(&mut storage);
+    vec.extend(0..8);
+    vec.retain(|&i| i % 3 != 0);
+    assert_eq!(vec.as_slice(), &[1, 2, 4, 5, 7]);
+    vec.retain(|_| true);
+    assert_eq!(vec.as_slice(), &[1, 2, 4, 5, 7]);
+    vec.retain(|_| false);
+    assert!(vec.is_empty());
+}
+
+#[test]
+fn retain_moves_values() {
+    let mut storage = [EMPTY; 4];
+    let mut vec = StackVec::uninit(&mut storage);
+    for i in 0..4 {
+        vec.push(Token(i)).expect("cap = 4");
+    }
+    vec.retain(|token| token.0 % 2 == 1);
+    assert_eq!(vec.as_slice(), &[Token(1), Token(3)]);
+}
+
+#[test]
+fn drain() {
+    let mut storage = [0usize; 8];
+    let mut vec = StackVec::new(&mut storage);
+    vec.extend(0..8);
+
+    let mut drain = vec.drain(2..5);
+    assert_eq!(drain.next(), Some(2));
+    drop(drain);
+    assert_eq!(vec.as_slice(), &[0, 1, 5, 6, 7]);
+
+    let drained: [usize; 2] = {
+        let mut drain = vec.drain(..=1);
+        [drain.next().unwrap(), drain.next().unwrap()]
+    };
+    assert_eq!(drained, [0, 1]);
+    assert_eq!(vec.as_slice(), &[5, 6, 7]);
+
+    assert_eq!(vec.drain(1..).count(), 2);
+    assert_eq!(vec.as_slice(), &[5]);
+    assert_eq!(vec.drain(1..1).count(), 0);
+    assert_eq!(vec.drain(..).count(), 1);
+    assert!(vec.is_empty());
+}
+
+#[test]
+fn drain_moves_values() {
+    let mut storage = [EMPTY; 4];
+    let mut vec = StackVec::uninit(&mut storage);
+    for i in 0..4 {
+        vec.push(Token(i)).expect("cap = 4");
+    }
+
+    let mut drain = vec.drain(1..3);
+    assert_eq!(drain.next(), Some(Token(1)));
+    assert_eq!(drain.next(), Some(Token(2)));
+    assert_eq!(drain.next(), None);
+    drop(drain);
+    assert_eq!(vec.as_slice(), &[Token(0), Token(3)]);
+}
+
+#[test]
+#[should_panic]
+fn drain_oob() {
+    let mut storage = [0usize; 4];
+    let mut vec = StackVec::new(&mut storage);
+    vec.push(0).expect("cap = 4");
+    vec.drain(0..2);
+}
+
+#[test]
+fn array_vec() {
+    static EMPTY_STATIC: ArrayVec<u32, 4> = ArrayVec::new();
+    assert!(EMPTY_STATIC.is_empty());
+    assert_eq!(EMPTY_STATIC.capacity(), 4);
+
+    let mut vec = ArrayVec::<usize, 4>::new();
+    vec.extend(0..3);
+    assert_eq!(vec.insert(0, 10), Ok(()));
+    assert!(vec.is_full());
+    assert_eq!(vec.push(4), Err(CapacityError));
+    assert_eq!(vec.as_slice(), &[10, 0, 1, 2]);
+
+    assert_eq!(vec.remove(1), 0);
+    assert_eq!(vec.swap_remove(0), 10);
+    assert_eq!(vec.pop(), Some(1));
+    assert_eq!(vec.as_slice(), &[2]);
+    assert_eq!(vec.extend_from_slice(&[3, 4, 5, 6]), Ok(3));
+    vec.retain(|&i| i != 4);
+    vec[0] = 1;
+    assert_eq!(vec.iter().sum::<usize>(), 9);
+    assert_eq!(vec.as_slice(), &[1, 3, 5]);
+}
+
+#[test]
+fn array_vec_drops_elements() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted;
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut vec = ArrayVec::<Counted, 8>::new();
+    for _ in 0..6 {
+        vec.push(Counted).expect("cap = 8");
+    }
+    vec.truncate(4);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+    drop(vec.pop());
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 3);
+    drop(vec);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 6);
+
+    // elements pushed before a panic are still the vector's, and dropped
+    let mut vec = ArrayVec::<Counted, 8>::new();
+    let pushing = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        vec.extend((0..4).map(|i| if i < 3 { Counted } else { panic!("no more") }));
+    }));
+    assert!(pushing.is_err());
+    assert_eq!(vec.len(), 3);
+    drop(vec);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 9);
+}
+
+#[test]
+fn dropping_drops_elements() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted;
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    const SLOT: MaybeUninit<Counted> = MaybeUninit::uninit();
+    let mut storage = [SLOT; 8];
+
+    let mut vec = StackVec::uninit(&mut storage);
+    for _ in 0..4 {
+        vec.push(Counted).expect("cap = 8");
+    }
+    vec.truncate(3);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+    vec.truncate_with_drop(1);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+
+    let mut vec = DroppingStackVec::new(&mut storage);
+    for _ in 0..6 {
+        vec.push(Counted).expect("cap = 8");
+    }
+    vec.truncate(4);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 4);
+    drop(vec.drain(1..2));
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 5);
+    drop(vec);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 8);
+}
+
+#[test]
+fn comparisons() {
+    let mut a_storage = [0u8; 4];
+    let mut b_storage = [1u8; 8];
+    let mut a = StackVec::new(&mut a_storage);
+    let mut b = StackVec::new(&mut b_storage);
+    assert_eq!(a, b);
+
+    a.extend_from_slice(b"abc").expect("cap = 4");
+    b.extend_from_slice(b"abd").expect("cap = 8");
+    assert_ne!(a, b);
+    assert!(a < b);
+    assert_eq!(a, *b"abc");
+    assert_eq!(a, &b"abc"[..]);
+    assert_eq!(a[..2], b[..2]);
+    assert_eq!(&b[1..], b"bd");
+
+    b[2] = b'c';
+    assert_eq!(a, b);
+    assert_eq!(a.cmp(&b), core::cmp::Ordering::Equal);
+
+    let mut other = ArrayVec::<u8, 3>::new();
+    other.extend(b"abc");
+    assert_eq!(other, &b"abc"[..]);
+    assert_eq!(other.clone(), other);
+}
+
+#[test]
+fn hash_matches_slice() {
+    use core::hash::{Hash, Hasher};
+
+    /// An FNV-1a hasher, enough to compare hashes.
+    struct Fnv(u64);
+
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+    }
+
+    fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let mut storage = [0u16; 4];
+    let mut vec = StackVec::new(&mut storage);
+    vec.extend(&[1, 2, 3]);
+    assert_eq!(hash(&vec), hash(&[1u16, 2, 3][..]));
+
+    let mut array_vec = ArrayVec::<u16, 8>::new();
+    array_vec.extend(&[1, 2, 3]);
+    assert_eq!(hash(&array_vec), hash(&vec));
+}
+
+#[test]
+fn clone_into() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Named(&'static str);
+
+    const NO_NAME: MaybeUninit<Named> = MaybeUninit::uninit();
+
+    let mut storage = [NO_NAME; 3];
+    let mut vec = StackVec::uninit(&mut storage);
+    vec.push(Named("a")).expect("cap = 3");
+    vec.push(Named("b")).expect("cap = 3");
+
+    let mut small = [NO_NAME; 1];
+    assert!(vec.clone_into(&mut small).is_err());
+
+    let mut copy_storage = [NO_NAME; 2];
+    let copy = vec.clone_into(&mut copy_storage).expect("room for 2");
+    assert_eq!(copy, vec);
+    assert!(copy.is_full());
+}
+
+#[test]
+fn stack_string() {
+    use core::fmt::Write;
+
+    let mut storage = [0u8; 8];
+    let mut string = StackString::new(&mut storage);
+    assert!(string.is_empty());
+    string.push_str("ab").expect("cap = 8");
+    string.push('ツ').expect("cap = 8");
+    assert_eq!(string, "abツ");
+    assert_eq!(string.len(), 5);
+
+    assert!(string.push_str("xyzw").is_err());
+    assert!(write!(string, "{}", 123).is_ok());
+    assert!(write!(string, "{}", 4).is_err());
+    assert_eq!(string.as_str(), "abツ123");
+
+    assert_eq!(string.pop(), Some('3'));
+    assert_eq!(string.pop(), Some('2'));
+    assert_eq!(string.pop(), Some('1'));
+    assert_eq!(string.pop(), Some('ツ'));
+    assert_eq!(string.len(), 2);
+    string.clear();
+    assert_eq!(string.pop(), None);
+}
+
+#[test]
+fn stack_string_checks_bytes() {
+    let mut storage = [0u8; 8];
+    let mut string = StackString::new(&mut storage);
+    let bytes = "aツ".as_bytes();
+
+    string.push_bytes(&bytes[..2]).expect("UTF-8 so far");
+    assert_eq!(string, "a");
+    string.push_bytes(&bytes[2..]).expect("completes it");
+    assert_eq!(string, "aツ");
+
+    assert_eq!(string.push_bytes(b"\xff"), Err(PushBytesError::InvalidUtf8));
+    assert_eq!(string.push_bytes(b"123456"), Err(PushBytesError::Full));
+    assert_eq!(string, "aツ");
+
+    // What follows an incomplete character cannot complete it.
+    string.push_bytes(&bytes[1..3]).expect("UTF-8 so far");
+    assert_eq!(string.push_bytes(b"b"), Err(PushBytesError::InvalidUtf8));
+    string.push('b').expect("cap = 8");
+    assert_eq!(string.into_str(), "aツb");
+}
+
+#[test]
+#[should_panic]
+fn stack_string_truncate_mid_character() {
+    let mut storage = [0u8; 8];
+    let mut string = StackString::new(&mut storage);
+    string.push_str("aツ").expect("cap = 8");
+    string.truncate(2);
+}
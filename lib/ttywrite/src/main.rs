@@ -8,8 +8,9 @@ use structopt;
 use structopt_derive::StructOpt;
 use xmodem::{Progress, Xmodem};
 
+use std::io::{self, Read, Write};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use serial::core::{BaudRate, CharSize, FlowControl, SerialDevice, SerialPortSettings, StopBits};
 use structopt::StructOpt;
@@ -76,13 +77,85 @@ struct Opt {
 
     #[structopt(short = "r", long = "raw", help = "Disable XMODEM")]
     raw: bool,
+
+    #[structopt(
+        long = "no-1k",
+        help = "Send 128-byte packets even if the receiver can take 1K packets"
+    )]
+    no_1k: bool,
+
+    #[structopt(
+        short = "n",
+        long = "retries",
+        parse(try_from_str),
+        help = "Restart the transfer this many times if it fails, as when the board resets",
+        default_value = "3"
+    )]
+    retries: usize,
+}
+
+/// The width of the progress bar, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Wraps the data being sent, drawing a progress bar and the transfer rate
+/// as it is read.
+struct ProgressReader<'a> {
+    data: &'a [u8],
+    total: usize,
+    start: Option<Instant>,
+}
+
+impl<'a> ProgressReader<'a> {
+    fn new(data: &'a [u8]) -> ProgressReader<'a> {
+        ProgressReader {
+            data,
+            total: data.len(),
+            start: None,
+        }
+    }
+
+    fn draw(&self, start: Instant) {
+        let sent = self.total - self.data.len();
+        let fraction = if self.total == 0 {
+            1.0
+        } else {
+            sent as f64 / self.total as f64
+        };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let elapsed = start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            sent as f64 / elapsed / 1024.0
+        } else {
+            0.0
+        };
+
+        print!(
+            "\r[{}{}] {:3}% {:8.1} KiB/s",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            (fraction * 100.0) as u32,
+            rate
+        );
+        let _ = io::stdout().flush();
+    }
+}
+
+impl io::Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // The clock starts with the first read, so the rate leaves out the
+        // wait for the receiver.
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let n = self.data.read(buf)?;
+        self.draw(start);
+        Ok(n)
+    }
 }
 
 fn progress_tracker(progress: Progress) {
     match progress {
         Progress::Waiting => println!("Establishing connection..."),
         Progress::Started => println!("Connection established!"),
-        Progress::Packet(num) => println!("Sent packet number: {}", num),
+        Progress::Packet(_) => (),
         Progress::NAK => (),
         Progress::Unknown => (),
     }
@@ -90,7 +163,7 @@ fn progress_tracker(progress: Progress) {
 
 fn main() {
     use std::fs::File;
-    use std::io::{self, BufReader};
+    use std::io::BufReader;
 
     let opt = Opt::from_args();
     let mut port = serial::open(&opt.tty_path).expect("path points to invalid TTY");
@@ -112,11 +185,45 @@ fn main() {
         None => Box::new(BufReader::new(io::stdin())),
     };
 
+    // The whole input is read up front so that a failed transfer can be
+    // started over, even from stdin.
+    let mut data = Vec::new();
+    input.read_to_end(&mut data).expect("failed to read input");
+
+    // A kernel that the bootloader would refuse is not worth sending.
+    if let Err(e) = boot_protocol::check_kernel(&data) {
+        eprintln!("Not sending input: {}", e);
+        std::process::exit(1);
+    }
+
     let bytes_written = if opt.raw {
-        io::copy(&mut input, &mut port).unwrap()
+        io::copy(&mut ProgressReader::new(&data), &mut port).unwrap()
     } else {
-        Xmodem::transmit_with_progress(input, port, progress_tracker).unwrap() as u64
+        let mut retries = 0;
+        loop {
+            let result = Xmodem::new_with_progress(&mut port, progress_tracker)
+                .with_1k(!opt.no_1k)
+                .transmit_all(ProgressReader::new(&data));
+
+            match result {
+                Ok(written) => break written as u64,
+                Err(e) if retries < opt.retries => {
+                    retries += 1;
+                    println!();
+                    println!(
+                        "Transfer failed: {}; retrying ({}/{})",
+                        e, retries, opt.retries
+                    );
+                }
+                Err(e) => {
+                    println!();
+                    eprintln!("Transfer failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
     };
 
+    println!();
     println!("Wrote {} bytes", bytes_written);
 }
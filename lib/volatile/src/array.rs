@@ -0,0 +1,118 @@
+use core::fmt;
+use core::mem::size_of;
+use core::ops::{Index, IndexMut};
+
+/// A bank of `N` registers of type `T`, each `STRIDE` bytes after the last.
+///
+/// This is for banks whose registers are spaced further apart than they are
+/// wide, like a controller's per-channel register blocks, which a plain
+/// array cannot describe. `T` is usually a wrapper type, or a register block
+/// declared with `register_block!`, and `STRIDE` must be a multiple of its
+/// alignment no smaller than it. Indexing is bounds checked:
+///
+/// ```rust
+/// use volatile::prelude::*;
+/// use volatile::{Volatile, VolatileArray};
+///
+/// let mut words = [0u32; 8];
+/// let bank = unsafe {
+///     &mut *(words.as_mut_ptr() as *mut VolatileArray<Volatile<u32>, 4, 8>)
+/// };
+/// bank[3].write(7);
+/// assert!(bank.get(4).is_none());
+/// assert_eq!(words, [0, 0, 0, 0, 0, 0, 7, 0]);
+/// ```
+#[repr(C)]
+pub struct VolatileArray<T, const N: usize, const STRIDE: usize> {
+    _align: [T; 0],
+    bank: [[u8; STRIDE]; N],
+}
+
+impl<T, const N: usize, const STRIDE: usize> VolatileArray<T, N, STRIDE> {
+    /// Returns the number of registers in the bank.
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns `true` if the bank has no registers.
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Returns the register at `index`, or `None` if `index` is out of
+    /// bounds.
+    #[inline(always)]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        debug_assert!(size_of::<T>() <= STRIDE);
+        self.bank
+            .get(index)
+            .map(|register| unsafe { &*(register.as_ptr() as *const T) })
+    }
+
+    /// Returns the register at `index` mutably, or `None` if `index` is out
+    /// of bounds.
+    #[inline(always)]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        debug_assert!(size_of::<T>() <= STRIDE);
+        self.bank
+            .get_mut(index)
+            .map(|register| unsafe { &mut *(register.as_mut_ptr() as *mut T) })
+    }
+
+    /// Returns an iterator over the bank's registers.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.bank
+            .iter()
+            .map(|register| unsafe { &*(register.as_ptr() as *const T) })
+    }
+
+    /// Returns an iterator over the bank's registers that allows modifying
+    /// them.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.bank
+            .iter_mut()
+            .map(|register| unsafe { &mut *(register.as_mut_ptr() as *mut T) })
+    }
+}
+
+impl<T, const N: usize, const STRIDE: usize> Index<usize> for VolatileArray<T, N, STRIDE> {
+    type Output = T;
+
+    /// Returns the register at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(register) => register,
+            None => panic!("register index {} out of bounds for a bank of {}", index, N),
+        }
+    }
+}
+
+impl<T, const N: usize, const STRIDE: usize> IndexMut<usize> for VolatileArray<T, N, STRIDE> {
+    /// Returns the register at `index` mutably.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        match self.get_mut(index) {
+            Some(register) => register,
+            None => panic!("register index {} out of bounds for a bank of {}", index, N),
+        }
+    }
+}
+
+impl<T, const N: usize, const STRIDE: usize> fmt::Debug for VolatileArray<T, N, STRIDE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VolatileArray")
+            .field("address", &(self as *const Self))
+            .field("len", &N)
+            .field("stride", &STRIDE)
+            .finish()
+    }
+}
@@ -0,0 +1,44 @@
+#[cfg(not(target_arch = "aarch64"))]
+use core::sync::atomic::{compiler_fence, fence, Ordering};
+
+/// Issues a data memory barrier: memory accesses before it are observed
+/// before those after it, device accesses included.
+///
+/// The BCM2835 peripheral manual asks for one before the first write to a
+/// peripheral and after the last read from it, since accesses to different
+/// peripherals can otherwise be seen out of order. `read_acquire()` and
+/// `write_release()` pair one with a register access.
+#[inline(always)]
+pub fn dmb() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("dmb sy", options(nostack, preserves_flags));
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    fence(Ordering::SeqCst);
+}
+
+/// Issues a data synchronization barrier: no instruction after it runs until
+/// every memory access, cache and TLB maintenance before it has completed.
+#[inline(always)]
+pub fn dsb() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("dsb sy", options(nostack, preserves_flags));
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    fence(Ordering::SeqCst);
+}
+
+/// Issues an instruction synchronization barrier: instructions after it are
+/// fetched again, so they see the effects of system register writes and
+/// cache maintenance before it.
+#[inline(always)]
+pub fn isb() {
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("isb", options(nostack, preserves_flags));
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    compiler_fence(Ordering::SeqCst);
+}
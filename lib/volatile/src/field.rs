@@ -0,0 +1,175 @@
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitOr, Not, Shl, Shr};
+
+/// Trait implemented by the integer types registers hold.
+pub trait Bits:
+    Copy
+    + PartialEq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Not<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+{
+    /// The number of bits in the type.
+    const BITS: u32;
+
+    /// The value with every bit set.
+    const ONES: Self;
+}
+
+macro_rules! bits {
+    ($($t:ty),*) => ($(
+        impl Bits for $t {
+            const BITS: u32 = (::core::mem::size_of::<$t>() * 8) as u32;
+            const ONES: $t = !0;
+        }
+    )*)
+}
+
+bits!(u8, u16, u32, u64, usize);
+
+/// Trait implemented by the types a register field's bits are read as and
+/// written from.
+///
+/// Every pattern of a field's bits must map to a value: an enum that does not
+/// name them all should map the rest to a catch-all variant. Integers are
+/// their own bits, and `bool` is a one-bit flag.
+pub trait FieldValue<T: Bits> {
+    /// Returns the value held in the field's bits, `bits`, which are shifted
+    /// down to bit 0.
+    fn from_bits(bits: T) -> Self;
+
+    /// Returns the bits, unshifted, that hold `self`.
+    fn into_bits(self) -> T;
+}
+
+impl<T: Bits> FieldValue<T> for T {
+    #[inline(always)]
+    fn from_bits(bits: T) -> T {
+        bits
+    }
+
+    #[inline(always)]
+    fn into_bits(self) -> T {
+        self
+    }
+}
+
+macro_rules! flag {
+    ($($t:ty),*) => ($(
+        impl FieldValue<$t> for bool {
+            #[inline(always)]
+            fn from_bits(bits: $t) -> bool {
+                bits != 0
+            }
+
+            #[inline(always)]
+            fn into_bits(self) -> $t {
+                self as $t
+            }
+        }
+    )*)
+}
+
+flag!(u8, u16, u32, u64, usize);
+
+/// A named field of a register holding `T`: `width` bits starting at bit
+/// `offset`, read and written as `V`.
+///
+/// Fields are declared once next to a driver's register block and then read
+/// with `Readable::read_field()` and written with
+/// `ReadableWriteable::write_field()`, in place of open-coded shifts and
+/// masks:
+///
+/// ```rust
+/// use volatile::prelude::*;
+/// use volatile::{Field, FieldValue, Volatile};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum DataSize {
+///     Seven,
+///     Eight,
+/// }
+///
+/// impl FieldValue<u32> for DataSize {
+///     fn from_bits(bits: u32) -> DataSize {
+///         if bits == 0b11 { DataSize::Eight } else { DataSize::Seven }
+///     }
+///
+///     fn into_bits(self) -> u32 {
+///         match self {
+///             DataSize::Seven => 0b00,
+///             DataSize::Eight => 0b11,
+///         }
+///     }
+/// }
+///
+/// const DATA_SIZE: Field<u32, DataSize> = Field::new(0, 2);
+/// const BREAK: Field<u32, bool> = Field::new(6, 1);
+///
+/// let mut value = 0x40u32;
+/// let lcr = unsafe { &mut *(&mut value as *mut u32 as *mut Volatile<u32>) };
+/// lcr.write_field(DATA_SIZE, DataSize::Eight);
+/// assert_eq!(lcr.read_field(DATA_SIZE), DataSize::Eight);
+/// assert!(lcr.read_field(BREAK));
+/// assert_eq!(lcr.read(), 0x43);
+/// ```
+pub struct Field<T, V = T> {
+    offset: u32,
+    width: u32,
+    _value: PhantomData<fn(T) -> V>,
+}
+
+impl<T, V> Field<T, V> {
+    /// Returns the field `width` bits wide starting at bit `offset`. The
+    /// field must be at least one bit wide and fit in a `T`.
+    pub const fn new(offset: u32, width: u32) -> Field<T, V> {
+        Field {
+            offset,
+            width,
+            _value: PhantomData,
+        }
+    }
+
+    /// Returns the bit the field starts at.
+    pub const fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Returns the number of bits in the field.
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+}
+
+impl<T: Bits, V: FieldValue<T>> Field<T, V> {
+    /// Returns the mask selecting the field's bits in a register's value.
+    #[inline(always)]
+    pub fn mask(&self) -> T {
+        debug_assert!(self.width > 0 && self.offset + self.width <= T::BITS);
+        (T::ONES >> (T::BITS - self.width)) << self.offset
+    }
+
+    /// Returns the field's value in the register value `reg`.
+    #[inline(always)]
+    pub fn get(&self, reg: T) -> V {
+        V::from_bits((reg & self.mask()) >> self.offset)
+    }
+
+    /// Returns the register value `reg` with the field set to `value`. Bits
+    /// of `value` that do not fit in the field are dropped.
+    #[inline(always)]
+    pub fn set(&self, reg: T, value: V) -> T {
+        let mask = self.mask();
+        (reg & !mask) | ((value.into_bits() << self.offset) & mask)
+    }
+}
+
+impl<T, V> Clone for Field<T, V> {
+    fn clone(&self) -> Field<T, V> {
+        *self
+    }
+}
+
+impl<T, V> Copy for Field<T, V> {}
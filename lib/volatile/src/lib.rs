@@ -6,8 +6,15 @@
 
 mod traits;
 mod macros;
+mod field;
+mod array;
+mod barrier;
 
 pub use traits::*;
+pub use field::*;
+pub use array::VolatileArray;
+pub use barrier::{dmb, dsb, isb};
+pub use macros::register_block;
 use macros::*;
 
 /// Reexports all of the traits in this crate.
@@ -20,7 +27,7 @@ use macros::*;
 /// ```
 pub mod prelude {
 	#[doc(no_inline)]
-    pub use super::{Readable, Writeable, ReadableWriteable, Wrapper};
+    pub use super::{Readable, Writeable, ReadableWriteable, Wrapper, FieldValue};
 }
 
 /// A wrapper type that enforces **read-only** _volatile_ accesses to a raw
@@ -35,6 +42,30 @@ pub struct Volatile<T>(T);
 
 /// A wrapper type that enforces **write-only** _volatile_ accesses to a raw
 /// pointer.
+///
+/// This is for registers whose reads do not return what was written, like
+/// the write-one-to-set and write-one-to-clear registers of the GPIO and
+/// interrupt controllers. Read-modify-writing one of these with `or_mask()`
+/// would write back every bit read as set, so none of the methods that read
+/// are implemented: each access writes a whole value.
+///
+/// ```rust,compile_fail
+/// use volatile::prelude::*;
+/// use volatile::WriteVolatile;
+///
+/// fn clear(reg: &mut WriteVolatile<u32>, bit: u32) {
+///     reg.or_mask(1 << bit);
+/// }
+/// ```
+///
+/// ```rust,compile_fail
+/// use volatile::prelude::*;
+/// use volatile::WriteVolatile;
+///
+/// fn peek(reg: &WriteVolatile<u32>) -> u32 {
+///     reg.read()
+/// }
+/// ```
 #[repr(C)]
 pub struct WriteVolatile<T>(T);
 
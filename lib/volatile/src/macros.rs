@@ -42,3 +42,69 @@ pub(crate) macro readable_writeable($type:ident) {
     {
     }
 }
+
+/// Declares a `#[repr(C)]` block of memory-mapped registers from a table of
+/// each register's offset, name, and wrapper type, ended by the block's size.
+///
+/// ```rust
+/// use volatile::{register_block, ReadVolatile, Reserved, Volatile};
+///
+/// register_block! {
+///     /// The registers of one mailbox.
+///     struct Registers {
+///         0x00 => DATA: Volatile<u32>,
+///         0x04 => __r0: [Reserved<u32>; 3],
+///         0x10 => PEEK: ReadVolatile<u32>,
+///         0x14 => SENDER: ReadVolatile<u32>,
+///         0x18 => STATUS: ReadVolatile<u32>,
+///         0x1c => CONFIG: Volatile<u32>,
+///         0x20 => @end,
+///     }
+/// }
+/// ```
+///
+/// Registers are laid out back to back in the order they are listed, so gaps
+/// between them must be filled with `Reserved` registers. Every offset, and
+/// the size, is checked against that layout at compile time:
+///
+/// ```rust,compile_fail
+/// use volatile::{register_block, Volatile};
+///
+/// register_block! {
+///     struct Registers {
+///         0x00 => CS: Volatile<u32>,
+///         0x08 => CLO: Volatile<u32>,
+///         0x0c => @end,
+///     }
+/// }
+/// ```
+pub macro register_block {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($body:tt)*
+        }
+    ) => {
+        register_block!(@fields [$(#[$attr])* $vis struct $name] $name [] (0) $($body)*);
+    },
+    (@fields [$($head:tt)*] $name:ident [$($fields:tt)*] ($at:expr) $end:literal => @end $(,)?) => {
+        #[repr(C)]
+        #[allow(non_snake_case)]
+        $($head)* {
+            $($fields)*
+        }
+
+        const _: [(); $end] = [(); ::core::mem::size_of::<$name>()];
+    },
+    (
+        @fields $head:tt $name:ident [$($fields:tt)*] ($at:expr)
+        $(#[$field_attr:meta])* $offset:literal => $field:ident: $ty:ty,
+        $($rest:tt)*
+    ) => {
+        const _: [(); $offset] = [(); $at];
+        register_block!(
+            @fields $head $name [$($fields)* $(#[$field_attr])* $field: $ty,]
+            ($at + ::core::mem::size_of::<$ty>()) $($rest)*
+        );
+    },
+}
@@ -1,3 +1,6 @@
+use crate::barrier::dmb;
+use crate::field::{Bits, Field, FieldValue};
+
 /// Trait implemented by all of the wrapper types in this crate.
 ///
 /// The inner type of wrapper is specified as an associated constant `Inner`.
@@ -23,6 +26,17 @@ pub trait Readable<T> {
         unsafe { ::core::ptr::read_volatile(self.inner()) }
     }
 
+    /// Reads and returns the value pointed to by `self`, then issues a data
+    /// memory barrier so that the read is observed before any access after
+    /// it. Use this for the last read from a peripheral before moving on to
+    /// another.
+    #[inline(always)]
+    fn read_acquire(&self) -> T {
+        let val = self.read();
+        dmb();
+        val
+    }
+
     /// Returns `true` if the value pointed to by `self` has the mask `mask`.
     /// This is equivalent to `(self.read() & mask) == mask`.
     #[inline(always)]
@@ -32,6 +46,15 @@ pub trait Readable<T> {
     {
         (self.read() & mask) == mask
     }
+
+    /// Reads the value pointed to by `self` and returns its field `field`.
+    /// This is equivalent to `field.get(self.read())`.
+    #[inline(always)]
+    fn read_field<V: FieldValue<T>>(&self, field: Field<T, V>) -> V
+        where T: Bits
+    {
+        field.get(self.read())
+    }
 }
 
 /// Trait implemented by **writeable** volatile wrappers.
@@ -46,6 +69,16 @@ pub trait Writeable<T> {
     fn write(&mut self, val: T) {
         unsafe { ::core::ptr::write_volatile(self.inner(), val) }
     }
+
+    /// Issues a data memory barrier, then writes the value `val` to the inner
+    /// address of `self`, so that every access before it is observed first.
+    /// Use this for the first write to a peripheral after accessing another,
+    /// or memory the peripheral then reads.
+    #[inline(always)]
+    fn write_release(&mut self, val: T) {
+        dmb();
+        self.write(val);
+    }
 }
 
 /// Trait implemented by **readable _and_ writeable** volatile wrappers.
@@ -66,5 +99,41 @@ pub trait ReadableWriteable<T>: Readable<T> + Writeable<T>
         let init_val = self.read();
         self.write(init_val | mask);
     }
+
+    /// Reads the value referred to by `self` and writes back `f` applied to
+    /// it. This is equivalent to `self.write(f(self.read()))`.
+    fn update<F: FnOnce(T) -> T>(&mut self, f: F) {
+        let init_val = self.read();
+        self.write(f(init_val));
+    }
+
+    /// Replaces the bits of the value referred to by `self` selected by
+    /// `mask` with those of `f` applied to them, leaving the others alone.
+    /// `f` is passed the selected bits in place, and bits it returns outside
+    /// of `mask` are dropped.
+    ///
+    /// ```rust
+    /// use volatile::prelude::*;
+    /// use volatile::Volatile;
+    ///
+    /// let mut value = 0x1234u32;
+    /// let reg = unsafe { &mut *(&mut value as *mut u32 as *mut Volatile<u32>) };
+    /// reg.map_bits(0xff0, |bits| bits + 0x10);
+    /// assert_eq!(reg.read(), 0x1244);
+    /// ```
+    fn map_bits<F: FnOnce(T) -> T>(&mut self, mask: T, f: F)
+        where T: ::core::ops::Not<Output = T> + Copy
+    {
+        self.update(|val| (val & !mask) | (f(val & mask) & mask));
+    }
+
+    /// Sets the field `field` of the value referred to by `self` to `value`,
+    /// leaving its other bits alone. This is equivalent to
+    /// `self.write(field.set(self.read(), value))`.
+    fn write_field<V: FieldValue<T>>(&mut self, field: Field<T, V>, value: V)
+        where T: Bits
+    {
+        self.update(|val| field.set(val, value));
+    }
 }
 
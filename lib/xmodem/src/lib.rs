@@ -14,17 +14,28 @@ pub use progress::{Progress, ProgressFn};
 use read_ext::ReadExt;
 
 const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
 const EOT: u8 = 0x04;
 const ACK: u8 = 0x06;
 const NAK: u8 = 0x15;
 const CAN: u8 = 0x18;
+const CRC: u8 = b'C';
 
 const PACKET_LEN: usize = 128;
+const PACKET_LEN_1K: usize = 1024;
 
 /// Implementation of the XMODEM protocol.
+///
+/// Besides the original protocol's 128-byte packets with one-byte checksums,
+/// the XMODEM-CRC and XMODEM-1K extensions are supported: a receiver that
+/// starts the transfer with `C` instead of `NAK` gets packets checked with a
+/// CRC-16, and may then be sent 1024-byte packets. Both are opt-in, with
+/// [`Xmodem::with_crc()`] and [`Xmodem::with_1k()`].
 pub struct Xmodem<R> {
     packet: u8,
     started: bool,
+    crc: bool,
+    one_k: bool,
     inner: R,
     progress: ProgressFn,
 }
@@ -52,36 +63,12 @@ impl Xmodem<()> {
     /// the transmission. See the [`Progress`] enum for more information.
     ///
     /// Returns the number of bytes written to `to`, excluding padding zeroes.
-    pub fn transmit_with_progress<R, W>(mut data: R, to: W, f: ProgressFn) -> io::Result<usize>
+    pub fn transmit_with_progress<R, W>(data: R, to: W, f: ProgressFn) -> io::Result<usize>
     where
         W: io::Read + io::Write,
         R: io::Read,
     {
-        let mut transmitter = Xmodem::new_with_progress(to, f);
-        let mut packet = [0u8; 128];
-        let mut written = 0;
-        'next_packet: loop {
-            let n = data.read_max(&mut packet)?;
-            packet[n..].iter_mut().for_each(|b| *b = 0);
-
-            if n == 0 {
-                transmitter.write_packet(&[])?;
-                return Ok(written);
-            }
-
-            for _ in 0..10 {
-                match transmitter.write_packet(&packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(_) => {
-                        written += n;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return ioerr!(BrokenPipe, "bad transmit");
-        }
+        Xmodem::new_with_progress(to, f).transmit_all(data)
     }
 
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
@@ -100,32 +87,12 @@ impl Xmodem<()> {
     ///
     /// The function `f` is used as a callback to indicate progress throughout
     /// the reception. See the [`Progress`] enum for more information.
-    pub fn receive_with_progress<R, W>(from: R, mut into: W, f: ProgressFn) -> io::Result<usize>
+    pub fn receive_with_progress<R, W>(from: R, into: W, f: ProgressFn) -> io::Result<usize>
     where
         R: io::Read + io::Write,
         W: io::Write,
     {
-        let mut receiver = Xmodem::new_with_progress(from, f);
-        let mut packet = [0u8; PACKET_LEN];
-        let mut received = 0;
-        'next_packet: loop {
-            for _ in 0..10 {
-                match receiver.read_packet(&mut packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(0) => break 'next_packet,
-                    Ok(n) => {
-                        received += n;
-                        into.write_all(&packet)?;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return ioerr!(BrokenPipe, "bad receive");
-        }
-
-        Ok(received)
+        Xmodem::new_with_progress(from, f).receive_all(into)
     }
 }
 
@@ -133,17 +100,26 @@ fn get_checksum(buf: &[u8]) -> u8 {
     return buf.iter().fold(0, |a, b| a.wrapping_add(*b));
 }
 
+/// Returns the CRC-16 of `buf` that XMODEM-CRC uses: polynomial `0x1021`,
+/// starting from zero.
+fn get_crc(buf: &[u8]) -> u16 {
+    buf.iter().fold(0, |crc, &b| {
+        (0..8).fold(crc ^ ((b as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
 impl<T: io::Read + io::Write> Xmodem<T> {
     /// Returns a new `Xmodem` instance with the internal reader/writer set to
     /// `inner`. The returned instance can be used for both receiving
     /// (downloading) and sending (uploading).
     pub fn new(inner: T) -> Self {
-        Xmodem {
-            packet: 1,
-            started: false,
-            inner,
-            progress: progress::noop,
-        }
+        Xmodem::new_with_progress(inner, progress::noop)
     }
 
     /// Returns a new `Xmodem` instance with the internal reader/writer set to
@@ -155,11 +131,98 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         Xmodem {
             packet: 1,
             started: false,
+            crc: false,
+            one_k: false,
             inner,
             progress: f,
         }
     }
 
+    /// Sets whether a receiver asks for packets checked with a CRC-16 instead
+    /// of a checksum by starting with `C` instead of `NAK`. Senders that only
+    /// know the original protocol never answer a `C`, so a receiver that may
+    /// talk to one should fall back to checksums after a few tries.
+    ///
+    /// A transmitter always uses whichever the receiver asks for.
+    pub fn with_crc(mut self, crc: bool) -> Self {
+        self.crc = crc;
+        self
+    }
+
+    /// Sets whether a transmitter sends 1024-byte packets, which it only does
+    /// when the receiver asks for CRC-16 checked packets.
+    pub fn with_1k(mut self, one_k: bool) -> Self {
+        self.one_k = one_k;
+        self
+    }
+
+    /// Transmits `data` to the receiver. If the length of the total data
+    /// yielded by `data` is not a multiple of the packet size, the data is
+    /// padded with zeroes and sent to the receiver.
+    ///
+    /// Returns the number of bytes written, excluding padding zeroes.
+    pub fn transmit_all<R: io::Read>(&mut self, mut data: R) -> io::Result<usize> {
+        self.start_transmit()?;
+
+        let len = if self.crc && self.one_k {
+            PACKET_LEN_1K
+        } else {
+            PACKET_LEN
+        };
+        let mut packet = [0u8; PACKET_LEN_1K];
+        let mut written = 0;
+        'next_packet: loop {
+            let n = data.read_max(&mut packet[..len])?;
+            if n == 0 {
+                self.write_packet(&[])?;
+                return Ok(written);
+            }
+
+            // A short last packet goes out at the original size, to save
+            // sending most of a kilobyte of padding.
+            let size = if n <= PACKET_LEN { PACKET_LEN } else { len };
+            packet[n..size].iter_mut().for_each(|b| *b = 0);
+
+            for _ in 0..10 {
+                match self.write_packet(&packet[..size]) {
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                    Ok(_) => {
+                        written += n;
+                        continue 'next_packet;
+                    }
+                }
+            }
+
+            return ioerr!(BrokenPipe, "bad transmit");
+        }
+    }
+
+    /// Receives data from the sender and writes it into `into`. Returns the
+    /// number of bytes received, a multiple of 128.
+    pub fn receive_all<W: io::Write>(&mut self, mut into: W) -> io::Result<usize> {
+        let mut packet = [0u8; PACKET_LEN_1K];
+        let mut received = 0;
+        'next_packet: loop {
+            for _ in 0..10 {
+                match self.read_packet(&mut packet) {
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                    Ok(0) => break 'next_packet,
+                    Ok(n) => {
+                        received += n;
+                        into.write_all(&packet[..n])?;
+                        continue 'next_packet;
+                    }
+                }
+            }
+
+            return ioerr!(BrokenPipe, "bad receive");
+        }
+
+        Ok(received)
+    }
+
     /// Reads a single byte from the inner I/O stream. If `abort_on_can` is
     /// `true`, an error of `ConnectionAborted` is returned if the read byte is
     /// `CAN`.
@@ -239,7 +302,8 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     }
 
     /// Reads (downloads) a single packet from the inner stream using the XMODEM
-    /// protocol. On success, returns the number of bytes read (always 128).
+    /// protocol. On success, returns the number of bytes read: 128, or 1024 for
+    /// a 1K packet.
     ///
     /// The progress callback is called with `Progress::Started` when reception
     /// for the first packet has started and subsequently with
@@ -251,39 +315,57 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The sender's first byte for a packet isn't `EOT` or `SOH`.
+    ///   * The sender's first byte for a packet isn't `EOT`, `SOH` or `STX`.
     ///   * The sender doesn't send a second `EOT` after the first.
     ///   * The received packet numbers don't match the expected values.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is returned if a packet checksum or CRC
+    /// fails.
     ///
     /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
     /// received when not expected.
     ///
-    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`.
+    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`, or if
+    /// a 1K packet arrives and `buf.len() < 1024`.
     pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        // Must send a NAK byte before receiving the first packet
+        // Must send a NAK (or C, for CRC) byte before receiving the first packet
         if !self.started {
-            self.write_byte(NAK)?;
+            self.write_byte(if self.crc { CRC } else { NAK })?;
             (self.progress)(Progress::Started);
             self.started = true;
         }
 
-        if buf.len() != PACKET_LEN {
+        if buf.len() < PACKET_LEN {
             return ioerr!(UnexpectedEof, "Packet missing data");
         }
 
         let next_byte = self.read_byte(true)?;
-        if next_byte == SOH {
+        if next_byte == SOH || next_byte == STX {
             // start of a full packet
+            let len = if next_byte == SOH {
+                PACKET_LEN
+            } else {
+                PACKET_LEN_1K
+            };
+            if buf.len() < len {
+                self.write_byte(CAN)?;
+                return ioerr!(UnexpectedEof, "Packet missing data");
+            }
+
             self.expect_byte_or_cancel(self.packet, "Unexpected packet number")?;
             self.expect_byte_or_cancel(!self.packet, "Unexpected inverse packet number")?;
 
+            let buf = &mut buf[..len];
             self.inner.read_exact(buf)?;
 
-            let checksum = get_checksum(&buf);
-            let received_checksum = self.read_byte(false)?;
-            if received_checksum != checksum {
+            let valid = if self.crc {
+                let high = self.read_byte(false)?;
+                let low = self.read_byte(false)?;
+                u16::from_be_bytes([high, low]) == get_crc(buf)
+            } else {
+                self.read_byte(false)? == get_checksum(buf)
+            };
+            if !valid {
                 self.write_byte(NAK)?;
                 return ioerr!(Interrupted, "Packet checksum failed");
             }
@@ -293,16 +375,16 @@ impl<T: io::Read + io::Write> Xmodem<T> {
             (self.progress)(Progress::Packet(self.packet));
             self.packet = self.packet.wrapping_add(1);
 
-            return Ok(PACKET_LEN);
+            Ok(len)
         } else if next_byte == EOT {
             // end of transmission handshake
             self.write_byte(NAK)?;
             self.expect_byte_or_cancel(EOT, "Expected second EOT")?;
             self.write_byte(ACK)?;
 
-            return Ok(0);
+            Ok(0)
         } else {
-            return ioerr!(InvalidData, "Must receive EOT or SOH");
+            ioerr!(InvalidData, "Must receive EOT, SOH or STX")
         }
     }
 
@@ -312,10 +394,13 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// transmission is complete. On success, returns the number of bytes
     /// written.
     ///
+    /// A packet is 128 bytes, or 1024 bytes if the receiver asked for CRC-16
+    /// checked packets by starting with `C`.
+    ///
     /// The progress callback is called with `Progress::Waiting` before waiting
-    /// for the receiver's `NAK`, `Progress::Started` when transmission of the
-    /// first packet has started and subsequently with `Progress::Packet` when a
-    /// packet is sent successfully.
+    /// for the receiver's `NAK` or `C`, `Progress::Started` when transmission
+    /// of the first packet has started and subsequently with `Progress::Packet`
+    /// when a packet is sent successfully.
     ///
     /// # Errors
     ///
@@ -323,39 +408,43 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The receiver's first byte isn't a `NAK`.
     ///   * The receiver doesn't respond with a `NAK` to the first `EOT`.
     ///   * The receiver doesn't respond with an `ACK` to the second `EOT`.
     ///   * The receiver responds to a complete packet with something besides
     ///     `ACK` or `NAK`.
     ///
-    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128 &&
-    /// buf.len() != 0`.
+    /// An error of kind `UnexpectedEof` is returned if `buf.len()` isn't 0 or
+    /// a packet's size.
     ///
     /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
     /// received when not expected.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is returned if the receiver answers a
+    /// packet with `NAK` because its checksum or CRC failed.
     pub fn write_packet(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if !self.started {
-            (self.progress)(Progress::Waiting);
-            self.expect_byte_or_cancel(NAK, "First byte must be NAK")?;
-            (self.progress)(Progress::Started);
-
-            self.started = true;
-        }
+        self.start_transmit()?;
 
-        if buf.len() == PACKET_LEN {
-            let checksum_byte = get_checksum(buf);
-
-            self.write_byte(SOH)?;
+        if buf.len() == PACKET_LEN || (buf.len() == PACKET_LEN_1K && self.crc) {
+            self.write_byte(if buf.len() == PACKET_LEN { SOH } else { STX })?;
 
             self.write_byte(self.packet)?;
             self.write_byte(!self.packet)?;
 
             self.inner.write_all(buf)?;
-            self.write_byte(checksum_byte)?;
-            self.expect_byte(ACK, "expected ACK after packet")?;
+            if self.crc {
+                self.inner.write_all(&get_crc(buf).to_be_bytes())?;
+            } else {
+                self.write_byte(get_checksum(buf))?;
+            }
+
+            match self.read_byte(true)? {
+                ACK => {}
+                NAK => {
+                    (self.progress)(Progress::NAK);
+                    return ioerr!(Interrupted, "Packet checksum failed");
+                }
+                _ => return ioerr!(InvalidData, "expected ACK after packet"),
+            }
 
             (self.progress)(Progress::Packet(self.packet));
             self.packet = self.packet.wrapping_add(1);
@@ -371,6 +460,34 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         Ok(buf.len())
     }
 
+    /// Waits for the receiver to start the transfer, if it hasn't yet, with
+    /// `NAK` for checksummed packets or `C` for CRC-16 checked ones. Anything
+    /// else, like a board's boot messages as it resets, is skipped.
+    fn start_transmit(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+
+        (self.progress)(Progress::Waiting);
+        loop {
+            match self.read_byte(true)? {
+                NAK => {
+                    self.crc = false;
+                    break;
+                }
+                CRC => {
+                    self.crc = true;
+                    break;
+                }
+                _ => continue,
+            }
+        }
+        (self.progress)(Progress::Started);
+
+        self.started = true;
+        Ok(())
+    }
+
     /// Flush this output stream, ensuring that all intermediately buffered
     /// contents reach their destination.
     ///
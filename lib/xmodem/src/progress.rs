@@ -6,7 +6,7 @@
 /// is intended to be used by progress indicators or for debugging purposes.
 #[derive(Debug, Copy, Clone)]
 pub enum Progress {
-    /// Waiting for receiver to send NAK.
+    /// Waiting for receiver to send NAK or C.
     Waiting,
     /// Download/upload has started.
     Started,
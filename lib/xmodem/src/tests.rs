@@ -227,3 +227,76 @@ fn test_eot() {
 
     assert_eq!(&buffer[..], &[NAK, EOT, NAK, EOT, ACK]);
 }
+
+#[test]
+fn test_crc_1k_loop() {
+    let mut input = [0u8; 2200];
+    (0..2200usize).for_each(|i| input[i] = (i % 251) as u8);
+
+    let (mut tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let sent = Xmodem::new(&mut rx).with_1k(true).transmit_all(&input[..]);
+        (sent, rx.2)
+    });
+    let rx_thread = std::thread::spawn(move || {
+        let mut output = vec![];
+        let received = Xmodem::new(&mut tx).with_crc(true).receive_all(&mut output);
+        (received, output, tx.2)
+    });
+
+    let (sent, rx_buf) = tx_thread.join().expect("tx join okay");
+    let (received, output, tx_buf) = rx_thread.join().expect("rx join okay");
+    assert_eq!(sent.expect("tx okay"), 2200);
+    assert_eq!(received.expect("rx okay"), 3 * 1024);
+    assert_eq!(&output[..2200], &input[..]);
+    assert!(output[2200..].iter().all(|&b| b == 0));
+
+    // the 152 bytes left over still need a 1K packet
+    assert_eq!(&rx_buf[0..3], &[STX, 1, 255 - 1]);
+    assert_eq!(&rx_buf[1027..1029], &get_crc(&input[..1024]).to_be_bytes());
+    assert_eq!(&rx_buf[1029..1032], &[STX, 2, 255 - 2]);
+    assert_eq!(&rx_buf[2058..2061], &[STX, 3, 255 - 3]);
+    assert_eq!(&tx_buf, &[CRC, ACK, ACK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn test_crc_short_last_packet() {
+    let input = [7u8; 1100];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::new(rx).with_1k(true).transmit_all(&input[..]));
+    let mut output = vec![];
+    let received = Xmodem::new(tx).with_crc(true).receive_all(&mut output);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1100);
+    assert_eq!(received.expect("rx okay"), 1024 + 128);
+    assert_eq!(&output[..1100], &input[..]);
+}
+
+#[test]
+fn test_crc() {
+    assert_eq!(get_crc(b"123456789"), 0x31C3);
+    assert_eq!(get_crc(&[]), 0);
+}
+
+#[test]
+fn test_checksum_receiver_gets_small_packets() {
+    let input = [3u8; 300];
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::new(rx).with_1k(true).transmit_all(&input[..]));
+    let mut output = vec![];
+    let received = Xmodem::receive(tx, &mut output).expect("rx okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 300);
+    assert_eq!(received, 384);
+    assert_eq!(&output[..300], &input[..]);
+}
+
+#[test]
+fn test_transmit_skips_noise_before_start() {
+    let input = [9u8; 128];
+    let (mut tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], rx));
+    io::Write::write_all(&mut tx, b"booting\r\n").expect("write noise");
+    let mut output = [0u8; 128];
+    Xmodem::receive(tx, &mut output[..]).expect("rx okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 128);
+    assert_eq!(&output[..], &input[..]);
+}
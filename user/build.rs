@@ -0,0 +1,3 @@
+pub fn main() {
+    println!("cargo:rerun-if-changed=.cargo/layout.ld");
+}
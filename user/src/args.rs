@@ -0,0 +1,88 @@
+//! The arguments a program was started with.
+
+use core::slice;
+use core::str;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// The number of arguments, which `_start` records.
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+
+/// The arguments' pointers, which `_start` records.
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Records the `argc` arguments at `argv`, as the kernel passes them, for
+/// `args()`.
+///
+/// # Safety
+///
+/// `argv` must point to `argc` pointers to NUL-terminated UTF-8 strings,
+/// which are never changed.
+#[cfg(target_os = "none")]
+pub(crate) unsafe fn init(argc: usize, argv: *const *const u8) {
+    ARGV.store(argv as *mut _, Ordering::Relaxed);
+    ARGC.store(argc, Ordering::Release);
+}
+
+/// Returns the arguments the program was started with. The first is the
+/// program's name, as the one who started it gave it.
+pub fn args() -> Args {
+    let argc = ARGC.load(Ordering::Acquire);
+    let argv = ARGV.load(Ordering::Relaxed);
+    unsafe { Args::new(argc, argv) }
+}
+
+/// An iterator over a program's arguments. See `args()`.
+#[derive(Clone, Debug)]
+pub struct Args {
+    argv: &'static [*const u8],
+    next: usize,
+}
+
+impl Args {
+    /// Returns an iterator over the `argc` arguments at `argv`. See `init()`.
+    unsafe fn new(argc: usize, argv: *const *const u8) -> Args {
+        let argv = match argc {
+            0 => &[],
+            argc => slice::from_raw_parts(argv, argc),
+        };
+        Args { argv, next: 0 }
+    }
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        let arg = *self.argv.get(self.next)?;
+        self.next += 1;
+        unsafe {
+            let len = (0..).take_while(|&i| *arg.add(i) != 0).count();
+            let bytes = slice::from_raw_parts(arg, len);
+            Some(str::from_utf8(bytes).expect("argument is not UTF-8"))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.argv.len() - self.next;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+#[cfg(test)]
+mod tests {
+    use super::Args;
+
+    #[test]
+    fn arguments() {
+        let strings: &'static [&[u8]] = &[b"cat\0", b"\0", b"a b\0"];
+        let argv: Vec<*const u8> = strings.iter().map(|s| s.as_ptr()).collect();
+        let argv = Box::leak(argv.into_boxed_slice());
+
+        let args = unsafe { Args::new(argv.len(), argv.as_ptr()) };
+        assert_eq!(args.len(), 3);
+        assert_eq!(args.collect::<Vec<_>>(), ["cat", "", "a b"]);
+        assert_eq!(unsafe { Args::new(0, core::ptr::null()) }.next(), None);
+    }
+}
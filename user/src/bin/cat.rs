@@ -0,0 +1,37 @@
+//! Copies standard input to standard output until the end of the input.
+//!
+//! The kernel has no system call to open files by name yet, so unlike the
+//! shell's `cat`, this one takes no arguments: give it a file by redirecting
+//! its input.
+
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+use kernel_api::STDIN;
+use user::syscall::{exit, read};
+use user::{args, eprintln, io};
+
+user::entry!(main);
+
+fn main() {
+    if args().len() > 1 {
+        eprintln!("usage: cat < file");
+        exit(2);
+    }
+
+    let mut buf = [0; 512];
+    loop {
+        let result = read(STDIN, &mut buf).and_then(|n| match n {
+            0 => Ok(0),
+            n => io::stdout().write_all(&buf[..n]).map(|()| n),
+        });
+        match result {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("cat: {}", e);
+                exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! Greets whoever its first argument names, or the world.
+
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+use user::{args, println};
+
+user::entry!(main);
+
+fn main() {
+    let name = args().nth(1).unwrap_or("world");
+    println!("hello, {}!", name);
+}
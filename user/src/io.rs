@@ -0,0 +1,72 @@
+//! Writing to standard output and error.
+
+use core::fmt;
+
+use kernel_api::syscall::write;
+use kernel_api::{OsError, OsResult, STDERR, STDOUT};
+
+/// A file descriptor that formatted text can be written to.
+#[derive(Copy, Clone, Debug)]
+pub struct File(u64);
+
+/// The program's standard output.
+pub fn stdout() -> File {
+    File(STDOUT)
+}
+
+/// The program's standard error.
+pub fn stderr() -> File {
+    File(STDERR)
+}
+
+impl File {
+    /// Writes all of `bytes`, in as many calls to `SYS_WRITE` as that takes.
+    /// Fails with `Io` if the file takes none of them.
+    pub fn write_all(&mut self, mut bytes: &[u8]) -> OsResult<()> {
+        while !bytes.is_empty() {
+            match write(self.0, bytes)? {
+                0 => return Err(OsError::Io),
+                written => bytes = &bytes[written..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for File {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(mut file: File, args: fmt::Arguments) {
+    // Like the console's, output that cannot be written is lost.
+    let _ = fmt::Write::write_fmt(&mut file, args);
+}
+
+/// Like `std`'s `print!`: writes to standard output.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::stdout(), format_args!($($arg)*)));
+}
+
+/// Like `std`'s `println!`: writes a line to standard output.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Like `std`'s `eprint!`: writes to standard error.
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::stderr(), format_args!($($arg)*)));
+}
+
+/// Like `std`'s `eprintln!`: writes a line to standard error.
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::eprint!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,49 @@
+//! The runtime of user programs: what a program needs to run under the
+//! kernel besides its own code.
+//!
+//! A program is a `no_std`, `no_main` binary that names its `main` function
+//! with `entry!`. The kernel enters it at `_start`, which records its
+//! arguments, for `args()`, and calls `main`, then exits with status 0 once
+//! it returns. A program that panics reports where to standard error and
+//! exits with status `PANIC_STATUS`. Built for the host, as its tests are, a
+//! program is an ordinary `std` one instead.
+//!
+//! ```ignore
+//! #![cfg_attr(target_os = "none", no_std)]
+//! #![cfg_attr(target_os = "none", no_main)]
+//!
+//! user::entry!(main);
+//!
+//! fn main() {
+//!     user::println!("hello from {}", user::args().next().unwrap_or("?"));
+//! }
+//! ```
+//!
+//! The system calls are in `syscall`, and `print!`, `println!`, `eprint!`
+//! and `eprintln!` write to standard output and error.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod args;
+pub mod io;
+mod rt;
+
+pub use kernel_api::syscall;
+pub use kernel_api::{OsError, OsResult};
+
+pub use crate::args::{args, Args};
+pub use crate::rt::PANIC_STATUS;
+
+/// Makes the function `$main`, which takes no arguments and returns nothing,
+/// the program's `main`: see the crate documentation.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[doc(hidden)]
+        #[no_mangle]
+        pub fn __user_main() {
+            let main: fn() = $main;
+            main()
+        }
+    };
+}
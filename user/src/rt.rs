@@ -0,0 +1,35 @@
+//! Where a program starts, and what it does when it panics. Elsewhere than
+//! under the kernel, as in tests run on the host, `std` does both.
+
+#[cfg(target_os = "none")]
+use core::panic::PanicInfo;
+
+#[cfg(target_os = "none")]
+use crate::{args, eprintln, syscall};
+
+/// The exit status of a program that panicked.
+pub const PANIC_STATUS: i32 = 101;
+
+#[cfg(target_os = "none")]
+extern "Rust" {
+    /// The program's `main`, which `entry!` defines.
+    fn __user_main();
+}
+
+/// Where the kernel enters a program: with `argc` and `argv` in `x0` and
+/// `x1`, and the stack pointer, 16-byte aligned, at `argc` on the stack.
+/// Runs `main`, then exits with status 0.
+#[cfg(target_os = "none")]
+#[no_mangle]
+pub unsafe extern "C" fn _start(argc: usize, argv: *const *const u8) -> ! {
+    args::init(argc, argv);
+    __user_main();
+    syscall::exit(0)
+}
+
+#[cfg(target_os = "none")]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    eprintln!("{}", info);
+    syscall::exit(PANIC_STATUS)
+}